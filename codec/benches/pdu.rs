@@ -0,0 +1,118 @@
+use codec::{
+    CodecCapabilities, GetLinesResponse, GetPaneRenderChangesResponse, Pdu, SerializedLines,
+};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mux::renderable::{RenderableDimensions, StableCursorPosition};
+use termwiz::cell::CellAttributes;
+use termwiz::color::AnsiColor;
+use termwiz::surface::Line;
+use wezterm_term::StableRowIndex;
+
+const COLS: usize = 120;
+const WORDS: &[&str] = &[
+    "drwxr-xr-x",
+    "wezterm",
+    "src",
+    "Cargo.toml",
+    "grüße",
+    "グループ",
+    "12:34",
+    "->",
+];
+
+/// Produce lines that resemble the output of a colorized `ls -l`:
+/// words of varying widths, some of them colored
+fn make_lines(first_row: StableRowIndex, count: usize) -> Vec<(StableRowIndex, Line)> {
+    let plain = CellAttributes::default();
+    let mut colored = CellAttributes::default();
+    colored.set_foreground(AnsiColor::Blue);
+
+    (0..count)
+        .map(|idx| {
+            let mut line = Line::with_width(0, 1);
+            let mut word_idx = idx;
+            while line.len() < COLS {
+                let attrs = if word_idx % 3 == 0 { &colored } else { &plain };
+                let text = format!("{} ", WORDS[word_idx % WORDS.len()]);
+                line.append_line(Line::from_text(&text, attrs, 1, None), 1);
+                word_idx += 1;
+            }
+            line.resize(COLS, 1);
+            (first_row + idx as StableRowIndex, line)
+        })
+        .collect()
+}
+
+fn get_lines(count: usize) -> Pdu {
+    Pdu::GetLinesResponse(GetLinesResponse {
+        pane_id: 1,
+        lines: make_lines(0, count).into(),
+    })
+}
+
+fn render_changes(bonus_lines: usize) -> Pdu {
+    let bonus: SerializedLines = make_lines(40, bonus_lines).into();
+    Pdu::GetPaneRenderChangesResponse(GetPaneRenderChangesResponse {
+        pane_id: 1,
+        mouse_grabbed: false,
+        cursor_position: StableCursorPosition {
+            x: 3,
+            y: 63,
+            ..Default::default()
+        },
+        dimensions: RenderableDimensions {
+            cols: COLS,
+            viewport_rows: 40,
+            scrollback_rows: 3000,
+            physical_top: 40,
+            scrollback_top: 0,
+            dpi: 96,
+            pixel_width: 1200,
+            pixel_height: 800,
+            reverse_video: false,
+        },
+        dirty_lines: vec![40..80],
+        title: "wezterm".to_string(),
+        working_dir: None,
+        bonus_lines: bonus,
+        input_serial: None,
+        seqno: 1,
+    })
+}
+
+fn round_trip(pdu: &Pdu, capabilities: CodecCapabilities, encoded: &mut Vec<u8>) -> Pdu {
+    encoded.clear();
+    pdu.encode_with_capabilities(&mut *encoded, 1, capabilities)
+        .unwrap();
+    Pdu::decode(encoded.as_slice()).unwrap().pdu
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let cases = [
+        // Attaching to a pane fetches its viewport
+        ("attach", get_lines(40)),
+        // Scrolling back fetches large blocks of lines
+        ("scrollback", get_lines(2000)),
+        // Each render diff carries the changed lines along with it
+        ("render_changes", render_changes(24)),
+    ];
+
+    let mut group = c.benchmark_group("pdu");
+    let mut encoded = vec![];
+    for (name, pdu) in &cases {
+        for (encoding, capabilities) in [
+            ("serde", CodecCapabilities::NONE),
+            ("flat", CodecCapabilities::FLAT_LINES),
+        ] {
+            round_trip(pdu, capabilities, &mut encoded);
+            group.throughput(Throughput::Bytes(encoded.len() as u64));
+            group.bench_with_input(BenchmarkId::new(*name, encoding), pdu, |b, pdu| {
+                b.iter(|| black_box(round_trip(pdu, capabilities, &mut encoded)))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
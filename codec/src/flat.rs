@@ -0,0 +1,335 @@
+//! A flat encoding for the lines carried by `GetLinesResponse` and
+//! `GetPaneRenderChangesResponse`.
+//!
+//! Serializing a `Line` via serde walks every field of every cell,
+//! so the cost is proportional to the number of cells rather than to
+//! the amount of text.  The flat encoding instead stores the text of
+//! all of the lines in a single contiguous buffer, followed by runs of
+//! cells that reference a table of the distinct attributes, so that
+//! encoding and decoding are mostly a matter of copying bytes.
+//! Decoding borrows the text directly from the frame and appends it
+//! to lines that use clustered storage, without materializing an
+//! intermediate `Vec<Cell>`.
+//!
+//! The flat encoding uses PDU idents of its own, and is only sent to
+//! peers that have enabled `CodecCapabilities::FLAT_LINES` via
+//! `SetCodecCapabilities`.  Any peer that understands that PDU can
+//! decode the flat encoding.
+//!
+//! The layout of the encoded lines is:
+//! attribute count, then each `CellAttributes` (varbincode)
+//! text length, then the utf8 text of every cell
+//! line count, then for each line:
+//!   stable row index (signed), seqno, line flags, run count,
+//!   then for each run: attribute index, cell count,
+//!     then for each cell: text length, width
+//! the hyperlinks and image cells of the `SerializedLines` (varbincode)
+//! All of the integers are leb128 encoded.
+use crate::{
+    GetLinesResponse, GetPaneRenderChangesResponse, InputSerial, LineHyperlink, Pdu,
+    SerializedImageCell, SerializedLines, COMPRESS_THRESH,
+};
+use anyhow::Context;
+use mux::pane::PaneId;
+use mux::renderable::{RenderableDimensions, StableCursorPosition};
+use mux::tab::SerdeUrl;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::ops::Range;
+use termwiz::cell::CellAttributes;
+use termwiz::surface::{Line, SequenceNo};
+use wezterm_term::StableRowIndex;
+
+/// The longest line that will be decoded.  The widths of the cells
+/// come from the peer, so this prevents a malformed PDU from growing
+/// a line without limit.
+const MAX_LINE_LEN: usize = 65536;
+
+/// The ident of the flat encoding of `GetLinesResponse`
+pub(crate) const GET_LINES_RESPONSE_FLAT: u64 = 85;
+/// The ident of the flat encoding of `GetPaneRenderChangesResponse`
+pub(crate) const GET_PANE_RENDER_CHANGES_RESPONSE_FLAT: u64 = 86;
+
+/// A PDU that has been encoded in its flat form
+pub(crate) struct FlatFrame {
+    pub ident: u64,
+    pub data: Vec<u8>,
+    pub is_compressed: bool,
+}
+
+/// Everything from `GetPaneRenderChangesResponse` except for
+/// `bonus_lines`, which are flat encoded after this header
+#[derive(Serialize)]
+struct RenderChangesHeaderRef<'a> {
+    pane_id: PaneId,
+    mouse_grabbed: bool,
+    cursor_position: &'a StableCursorPosition,
+    dimensions: &'a RenderableDimensions,
+    dirty_lines: &'a [Range<StableRowIndex>],
+    title: &'a str,
+    working_dir: &'a Option<SerdeUrl>,
+    input_serial: Option<InputSerial>,
+    seqno: SequenceNo,
+}
+
+/// The owned counterpart of `RenderChangesHeaderRef`, which has the
+/// same serialized form
+#[derive(Deserialize)]
+struct RenderChangesHeader {
+    pane_id: PaneId,
+    mouse_grabbed: bool,
+    cursor_position: StableCursorPosition,
+    dimensions: RenderableDimensions,
+    dirty_lines: Vec<Range<StableRowIndex>>,
+    title: String,
+    working_dir: Option<SerdeUrl>,
+    input_serial: Option<InputSerial>,
+    seqno: SequenceNo,
+}
+
+/// If `pdu` has a flat encoding, returns that encoding
+pub(crate) fn encode(pdu: &Pdu) -> anyhow::Result<Option<FlatFrame>> {
+    let mut data = vec![];
+    let ident = match pdu {
+        Pdu::GetLinesResponse(resp) => {
+            write_unsigned(&mut data, resp.pane_id as u64)?;
+            encode_lines(&resp.lines, &mut data)?;
+            GET_LINES_RESPONSE_FLAT
+        }
+        Pdu::GetPaneRenderChangesResponse(resp) => {
+            let header = RenderChangesHeaderRef {
+                pane_id: resp.pane_id,
+                mouse_grabbed: resp.mouse_grabbed,
+                cursor_position: &resp.cursor_position,
+                dimensions: &resp.dimensions,
+                dirty_lines: &resp.dirty_lines,
+                title: &resp.title,
+                working_dir: &resp.working_dir,
+                input_serial: resp.input_serial,
+                seqno: resp.seqno,
+            };
+            header.serialize(&mut varbincode::Serializer::new(&mut data))?;
+            encode_lines(&resp.bonus_lines, &mut data)?;
+            GET_PANE_RENDER_CHANGES_RESPONSE_FLAT
+        }
+        _ => return Ok(None),
+    };
+
+    let (data, is_compressed) = compress_if_smaller(data)?;
+    Ok(Some(FlatFrame {
+        ident,
+        data,
+        is_compressed,
+    }))
+}
+
+/// If `ident` is that of a flat encoding, decodes it
+pub(crate) fn decode(ident: u64, data: &[u8], is_compressed: bool) -> Option<anyhow::Result<Pdu>> {
+    if ident != GET_LINES_RESPONSE_FLAT && ident != GET_PANE_RENDER_CHANGES_RESPONSE_FLAT {
+        return None;
+    }
+    if is_compressed {
+        match zstd::decode_all(data).context("decompressing flat PDU") {
+            Ok(data) => Some(decode_data(ident, &data)),
+            Err(err) => Some(Err(err)),
+        }
+    } else {
+        Some(decode_data(ident, data))
+    }
+}
+
+fn decode_data(ident: u64, mut data: &[u8]) -> anyhow::Result<Pdu> {
+    if ident == GET_LINES_RESPONSE_FLAT {
+        let pane_id = read_usize(&mut data)?;
+        let lines = decode_lines(&mut data)?;
+        return Ok(Pdu::GetLinesResponse(GetLinesResponse { pane_id, lines }));
+    }
+
+    let header: RenderChangesHeader =
+        Deserialize::deserialize(&mut varbincode::Deserializer::new(&mut data))?;
+    let bonus_lines = decode_lines(&mut data)?;
+    Ok(Pdu::GetPaneRenderChangesResponse(
+        GetPaneRenderChangesResponse {
+            pane_id: header.pane_id,
+            mouse_grabbed: header.mouse_grabbed,
+            cursor_position: header.cursor_position,
+            dimensions: header.dimensions,
+            dirty_lines: header.dirty_lines,
+            title: header.title,
+            working_dir: header.working_dir,
+            bonus_lines,
+            input_serial: header.input_serial,
+            seqno: header.seqno,
+        },
+    ))
+}
+
+fn compress_if_smaller(data: Vec<u8>) -> anyhow::Result<(Vec<u8>, bool)> {
+    if data.len() <= COMPRESS_THRESH {
+        return Ok((data, false));
+    }
+    let compressed = zstd::encode_all(data.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL)?;
+    if compressed.len() < data.len() {
+        Ok((compressed, true))
+    } else {
+        Ok((data, false))
+    }
+}
+
+fn write_unsigned(data: &mut Vec<u8>, value: u64) -> anyhow::Result<()> {
+    leb128::write::unsigned(data, value)?;
+    Ok(())
+}
+
+fn read_unsigned(data: &mut &[u8]) -> anyhow::Result<u64> {
+    leb128::read::unsigned(data).context("reading flat encoded value")
+}
+
+fn read_usize(data: &mut &[u8]) -> anyhow::Result<usize> {
+    Ok(read_unsigned(data)?.try_into()?)
+}
+
+/// Removes and returns the first `len` bytes of `data`
+fn take<'a>(data: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if len > data.len() {
+        anyhow::bail!(
+            "flat encoded data is truncated: wanted {len} bytes but only {} remain",
+            data.len()
+        );
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+/// A run of cells that share the same attributes
+struct Run {
+    attr_idx: usize,
+    /// (text length, width) of each cell in the run
+    cells: Vec<(usize, usize)>,
+}
+
+fn encode_lines(lines: &SerializedLines, data: &mut Vec<u8>) -> anyhow::Result<()> {
+    let mut attrs: Vec<&CellAttributes> = vec![];
+    let mut text = String::new();
+    let mut line_runs = Vec::with_capacity(lines.lines.len());
+
+    for (_, line) in &lines.lines {
+        let mut runs: Vec<Run> = vec![];
+        for cell in line.visible_cells() {
+            text.push_str(cell.str());
+            let cell_info = (cell.str().len(), cell.width());
+            if let Some(run) = runs.last_mut() {
+                if attrs[run.attr_idx] == cell.attrs() {
+                    run.cells.push(cell_info);
+                    continue;
+                }
+            }
+            // Runs of the same attributes tend to recur, such as the
+            // default attributes between colored words, so check the
+            // most recently added entries first
+            let attr_idx = match attrs.iter().rposition(|a| *a == cell.attrs()) {
+                Some(idx) => idx,
+                None => {
+                    attrs.push(cell.attrs());
+                    attrs.len() - 1
+                }
+            };
+            runs.push(Run {
+                attr_idx,
+                cells: vec![cell_info],
+            });
+        }
+        line_runs.push(runs);
+    }
+
+    write_unsigned(data, attrs.len() as u64)?;
+    for attr in &attrs {
+        attr.serialize(&mut varbincode::Serializer::new(&mut *data))?;
+    }
+
+    write_unsigned(data, text.len() as u64)?;
+    data.extend_from_slice(text.as_bytes());
+
+    write_unsigned(data, lines.lines.len() as u64)?;
+    for ((stable_row, line), runs) in lines.lines.iter().zip(line_runs) {
+        leb128::write::signed(data, *stable_row as i64)?;
+        write_unsigned(data, line.current_seqno() as u64)?;
+        write_unsigned(data, line.line_flags() as u64)?;
+        write_unsigned(data, runs.len() as u64)?;
+        for run in runs {
+            write_unsigned(data, run.attr_idx as u64)?;
+            write_unsigned(data, run.cells.len() as u64)?;
+            for (text_len, width) in run.cells {
+                write_unsigned(data, text_len as u64)?;
+                write_unsigned(data, width as u64)?;
+            }
+        }
+    }
+
+    (&lines.hyperlinks, &lines.images).serialize(&mut varbincode::Serializer::new(data))?;
+    Ok(())
+}
+
+fn decode_lines(data: &mut &[u8]) -> anyhow::Result<SerializedLines> {
+    let num_attrs = read_usize(data)?;
+    let mut attrs = Vec::with_capacity(num_attrs.min(data.len()));
+    for _ in 0..num_attrs {
+        let attr: CellAttributes =
+            Deserialize::deserialize(&mut varbincode::Deserializer::new(&mut *data))?;
+        attrs.push(attr);
+    }
+
+    let text_len = read_usize(data)?;
+    let mut text =
+        std::str::from_utf8(take(data, text_len)?).context("flat encoded text is not utf8")?;
+
+    let num_lines = read_usize(data)?;
+    let mut lines = Vec::with_capacity(num_lines.min(data.len()));
+    for _ in 0..num_lines {
+        let stable_row =
+            leb128::read::signed(data).context("reading stable row index")? as StableRowIndex;
+        let seqno = read_unsigned(data)? as SequenceNo;
+        let flags = read_unsigned(data)? as u16;
+        let mut line = Line::new(seqno);
+        let mut cell_idx = 0;
+
+        let num_runs = read_usize(data)?;
+        for _ in 0..num_runs {
+            let attr_idx = read_usize(data)?;
+            let attr = attrs
+                .get(attr_idx)
+                .with_context(|| format!("attribute index {attr_idx} is out of range"))?;
+            let num_cells = read_usize(data)?;
+            for _ in 0..num_cells {
+                let text_len = read_usize(data)?;
+                let width = read_usize(data)?;
+                let cell_text = text
+                    .get(..text_len)
+                    .context("flat encoded cell text is out of range")?;
+                text = &text[text_len..];
+                // No character is wider than 2 cells, so neither is
+                // the grapheme that they form
+                if width > 2 * cell_text.chars().count().max(1) {
+                    anyhow::bail!("flat encoded cell width {width} is out of range");
+                }
+                if cell_idx + width > MAX_LINE_LEN {
+                    anyhow::bail!("flat encoded line is longer than {MAX_LINE_LEN} cells");
+                }
+                line.set_cell_grapheme(cell_idx, cell_text, width, attr.clone(), seqno);
+                cell_idx += width.max(1);
+            }
+        }
+        line.set_line_flags(flags);
+        lines.push((stable_row, line));
+    }
+
+    let (hyperlinks, images): (Vec<LineHyperlink>, Vec<SerializedImageCell>) =
+        Deserialize::deserialize(&mut varbincode::Deserializer::new(&mut *data))?;
+
+    Ok(SerializedLines {
+        lines,
+        hyperlinks,
+        images,
+    })
+}
@@ -16,6 +16,7 @@ use config::keyassignment::{PaneDirection, ScrollbackEraseMode};
 use mux::client::{ClientId, ClientInfo};
 use mux::pane::PaneId;
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
+use mux::stats::StatsSnapshot;
 use mux::tab::{PaneNode, SerdeUrl, SplitRequest, TabId};
 use mux::window::WindowId;
 use portable_pty::CommandBuilder;
@@ -36,6 +37,8 @@ use thiserror::Error;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::{Alert, ClipboardSelection, StableRowIndex, TerminalSize};
 
+mod flat;
+
 #[derive(Error, Debug)]
 #[error("Corrupt Response: {0}")]
 pub struct CorruptResponse(String);
@@ -399,6 +402,12 @@ macro_rules! pdu {
                         }
                     ,)*
                     _ => {
+                        if let Some(pdu) = flat::decode(decoded.ident, &decoded.data, decoded.is_compressed) {
+                            return Ok(DecodedPdu {
+                                serial: decoded.serial,
+                                pdu: pdu.context("decoding a flat PDU")?,
+                            });
+                        }
                         metrics::histogram!("pdu.size", "pdu" => "??").record(decoded.data.len() as f64);
                         metrics::histogram!("pdu.size.rate", "pdu" => "??").record(decoded.data.len() as f64);
                         Ok(DecodedPdu {
@@ -426,6 +435,12 @@ macro_rules! pdu {
                         }
                     ,)*
                     _ => {
+                        if let Some(pdu) = flat::decode(decoded.ident, &decoded.data, decoded.is_compressed) {
+                            return Ok(DecodedPdu {
+                                serial: decoded.serial,
+                                pdu: pdu.context("decoding a flat PDU")?,
+                            });
+                        }
                         metrics::histogram!("pdu.size", "pdu" => "??").record(decoded.data.len() as f64);
                         Ok(DecodedPdu {
                             serial: decoded.serial,
@@ -441,7 +456,7 @@ macro_rules! pdu {
 /// The overall version of the codec.
 /// This must be bumped when backwards incompatible changes
 /// are made to the types and protocol.
-pub const CODEC_VERSION: usize = 43;
+pub const CODEC_VERSION: usize = 55;
 
 // Defines the Pdu enum.
 // Each struct has an explicit identifying number.
@@ -502,9 +517,91 @@ pdu! {
     GetPaneDirection: 60,
     GetPaneDirectionResponse: 61,
     AdjustPaneSize: 62,
+    SshForward: 63,
+    SshForwardResponse: 64,
+    SshTransferOpen: 65,
+    SshTransferOpenResponse: 66,
+    SshTransferWrite: 67,
+    SshTransferRead: 68,
+    SshTransferReadResponse: 69,
+    SshTransferClose: 70,
+    WaitForPaneExit: 71,
+    WaitForPaneExitResponse: 72,
+    SubscribeToEvents: 73,
+    MuxEvent: 74,
+    PaneTitleChanged: 75,
+    MoveTab: 76,
+    MoveTabResponse: 77,
+    SetActiveWorkspace: 78,
+    ExecCommand: 79,
+    ExecCommandResponse: 80,
+    ReadExecOutput: 81,
+    ReadExecOutputResponse: 82,
+    SetCodecCapabilities: 83,
+    SetCodecCapabilitiesResponse: 84,
+    // 85 and 86 are used for the flat encodings of GetLinesResponse
+    // and GetPaneRenderChangesResponse; see flat.rs
+    GetStats: 87,
+    GetStatsResponse: 88,
+    GetPaneScreenshot: 89,
+    GetPaneScreenshotResponse: 90,
 }
 
 impl Pdu {
+    /// Encode the PDU, using the alternative encodings that are
+    /// enabled by `capabilities` if any apply to this type of PDU
+    pub fn encode_with_capabilities<W: std::io::Write>(
+        &self,
+        w: W,
+        serial: u64,
+        capabilities: CodecCapabilities,
+    ) -> Result<(), Error> {
+        match self.encode_flat(capabilities)? {
+            Some(frame) => {
+                let encoded_size =
+                    encode_raw(frame.ident, serial, &frame.data, frame.is_compressed, w)?;
+                self.record_flat_size(encoded_size);
+                Ok(())
+            }
+            None => self.encode(w, serial),
+        }
+    }
+
+    pub async fn encode_async_with_capabilities<W: Unpin + AsyncWriteExt>(
+        &self,
+        w: &mut W,
+        serial: u64,
+        capabilities: CodecCapabilities,
+    ) -> Result<(), Error> {
+        match self.encode_flat(capabilities)? {
+            Some(frame) => {
+                let encoded_size =
+                    encode_raw_async(frame.ident, serial, &frame.data, frame.is_compressed, w)
+                        .await?;
+                self.record_flat_size(encoded_size);
+                Ok(())
+            }
+            None => self.encode_async(w, serial).await,
+        }
+    }
+
+    fn encode_flat(
+        &self,
+        capabilities: CodecCapabilities,
+    ) -> anyhow::Result<Option<flat::FlatFrame>> {
+        if capabilities.contains(CodecCapabilities::FLAT_LINES) {
+            flat::encode(self)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn record_flat_size(&self, encoded_size: usize) {
+        log::debug!("encode flat {} size={encoded_size}", self.pdu_name());
+        metrics::histogram!("pdu.size", "pdu" => self.pdu_name()).record(encoded_size as f64);
+        metrics::histogram!("pdu.size.rate", "pdu" => self.pdu_name()).record(encoded_size as f64);
+    }
+
     /// Returns true if this type of Pdu represents action taken
     /// directly by a user, rather than background traffic on
     /// a live connection
@@ -595,6 +692,7 @@ impl Pdu {
             | Pdu::NotifyAlert(NotifyAlert { pane_id, .. })
             | Pdu::SetClipboard(SetClipboard { pane_id, .. })
             | Pdu::PaneFocused(PaneFocused { pane_id })
+            | Pdu::PaneTitleChanged(PaneTitleChanged { pane_id, .. })
             | Pdu::PaneRemoved(PaneRemoved { pane_id }) => Some(*pane_id),
             _ => None,
         }
@@ -620,6 +718,73 @@ pub struct GetCodecVersionResponse {
     pub config_file_path: Option<PathBuf>,
 }
 
+/// Optional features of the protocol.  A feature is only used on a
+/// connection when both the client and the server support it, which
+/// is established via `SetCodecCapabilities`.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct CodecCapabilities {
+    bits: u64,
+}
+
+impl CodecCapabilities {
+    pub const NONE: Self = Self { bits: 0 };
+    /// Lines are sent using the flat encoding from flat.rs
+    pub const FLAT_LINES: Self = Self { bits: 1 };
+
+    /// The capabilities supported by this build
+    pub fn supported() -> Self {
+        Self::FLAT_LINES
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// Returns the capabilities that are present in both self and other
+    pub fn intersection(self, other: Self) -> Self {
+        Self {
+            bits: self.bits & other.bits,
+        }
+    }
+}
+
+/// Sent by the client after it has verified the codec version, to
+/// advertise the capabilities that it supports.  The server responds
+/// with the subset of those that it will use.  Servers that predate
+/// this PDU respond with an error, and use no optional capabilities.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetCodecCapabilities {
+    pub capabilities: CodecCapabilities,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetCodecCapabilitiesResponse {
+    pub capabilities: CodecCapabilities,
+}
+
+/// Retrieve the metrics that have been recorded by the server
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetStats {}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetStatsResponse {
+    pub stats: StatsSnapshot,
+}
+
+/// Render the visible content of a pane to a png image,
+/// using the fonts and colors of the server
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetPaneScreenshot {
+    pub pane_id: PaneId,
+    /// Overrides the `dpi` from the configuration of the server
+    pub dpi: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetPaneScreenshotResponse {
+    pub png: Vec<u8>,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct Ping {}
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
@@ -781,6 +946,28 @@ pub struct RenameWorkspace {
     pub new_workspace: String,
 }
 
+/// Make `workspace` the active workspace of the other clients
+/// that are attached to the mux, such as the gui
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetActiveWorkspace {
+    pub workspace: String,
+}
+
+/// Move a tab into another window.
+/// If `window_id` is None, a new window is created in `workspace`,
+/// which defaults to the workspace of the tab's current window.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct MoveTab {
+    pub tab_id: TabId,
+    pub window_id: Option<WindowId>,
+    pub workspace: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct MoveTabResponse {
+    pub window_id: WindowId,
+}
+
 /// This is used both as a notification from server->client
 /// and as a configuration request from client->server when
 /// the client's preferred configuration changes
@@ -813,6 +1000,14 @@ pub struct TabTitleChanged {
     pub title: String,
 }
 
+/// Sets, or when sent by the server, advises of a change to,
+/// the title override of a pane.  `None` removes the override.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct PaneTitleChanged {
+    pub pane_id: PaneId,
+    pub title: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct WindowTitleChanged {
     pub window_id: WindowId,
@@ -881,6 +1076,221 @@ pub struct GetPaneDirectionResponse {
     pub pane_id: Option<PaneId>,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshForward {
+    pub domain: String,
+    /// When set, establish this additional forward before
+    /// reporting on the forwards for the domain
+    pub spec: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshForwardResponse {
+    pub forwards: Vec<String>,
+}
+
+/// Open `path` on the host of the ssh domain `domain` for
+/// reading, or for writing if `write` is true, in which case
+/// the file is created or truncated.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshTransferOpen {
+    pub domain: String,
+    pub path: String,
+    pub write: bool,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshTransferOpenResponse {
+    pub transfer_id: u64,
+    /// The size of the file, if known
+    pub size: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshTransferWrite {
+    pub transfer_id: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshTransferRead {
+    pub transfer_id: u64,
+    pub max_len: usize,
+}
+
+/// An empty `data` indicates the end of the file
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshTransferReadResponse {
+    pub data: Vec<u8>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SshTransferClose {
+    pub transfer_id: u64,
+}
+
+/// Wait for the process running in a pane to terminate
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct WaitForPaneExit {
+    pub pane_id: PaneId,
+}
+
+/// `exit_code` is None if the exit status couldn't be determined
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct WaitForPaneExitResponse {
+    pub exit_code: Option<u32>,
+    /// The name of the signal that terminated the process, if any
+    pub signal: Option<String>,
+}
+
+/// Run a command in a pane that isn't part of any tab, buffering
+/// its output so that it can be retrieved via `ReadExecOutput`
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ExecCommand {
+    pub domain: config::keyassignment::SpawnTabDomain,
+    pub command: Option<CommandBuilder>,
+    pub command_dir: Option<String>,
+    pub size: TerminalSize,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ExecCommandResponse {
+    pub pane_id: PaneId,
+}
+
+/// Wait for output from a command started by `ExecCommand`
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ReadExecOutput {
+    pub pane_id: PaneId,
+    pub max_len: usize,
+}
+
+/// `finished` is set once all of the output has been read and the
+/// process has terminated, at which point `data` is empty and the
+/// exit status is reported
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ReadExecOutputResponse {
+    pub data: Vec<u8>,
+    pub finished: bool,
+    pub exit_code: Option<u32>,
+    /// The name of the signal that terminated the process, if any
+    pub signal: Option<String>,
+}
+
+/// Ask the server to send a `MuxEvent` to this client whenever
+/// one of the named events occurs.
+/// Replaces any prior subscription; an empty list unsubscribes.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SubscribeToEvents {
+    pub events: Vec<String>,
+}
+
+/// Sent unilaterally to clients that used `SubscribeToEvents`
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct MuxEvent {
+    pub event: MuxEventKind,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub enum MuxEventKind {
+    PaneOutput {
+        pane_id: PaneId,
+    },
+    PaneAdded {
+        pane_id: PaneId,
+    },
+    PaneRemoved {
+        pane_id: PaneId,
+    },
+    PaneFocused {
+        pane_id: PaneId,
+    },
+    PaneExited {
+        pane_id: PaneId,
+        exit_code: Option<u32>,
+        signal: Option<String>,
+    },
+    Bell {
+        pane_id: PaneId,
+    },
+    UserVar {
+        pane_id: PaneId,
+        name: String,
+        value: String,
+    },
+    PaneTitleChanged {
+        pane_id: PaneId,
+        title: Option<String>,
+    },
+    TabAddedToWindow {
+        tab_id: TabId,
+        window_id: WindowId,
+    },
+    TabTitleChanged {
+        tab_id: TabId,
+        title: String,
+    },
+    WindowCreated {
+        window_id: WindowId,
+    },
+    WindowRemoved {
+        window_id: WindowId,
+    },
+    WindowTitleChanged {
+        window_id: WindowId,
+        title: String,
+    },
+    WindowWorkspaceChanged {
+        window_id: WindowId,
+        workspace: String,
+    },
+    WorkspaceRenamed {
+        old_workspace: String,
+        new_workspace: String,
+    },
+}
+
+impl MuxEventKind {
+    /// The names that can be passed to `SubscribeToEvents`
+    pub const NAMES: &'static [&'static str] = &[
+        "pane-output",
+        "pane-added",
+        "pane-removed",
+        "pane-focused",
+        "pane-exited",
+        "bell",
+        "user-var",
+        "pane-title-changed",
+        "tab-added-to-window",
+        "tab-title-changed",
+        "window-created",
+        "window-removed",
+        "window-title-changed",
+        "window-workspace-changed",
+        "workspace-renamed",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PaneOutput { .. } => "pane-output",
+            Self::PaneAdded { .. } => "pane-added",
+            Self::PaneRemoved { .. } => "pane-removed",
+            Self::PaneFocused { .. } => "pane-focused",
+            Self::PaneExited { .. } => "pane-exited",
+            Self::Bell { .. } => "bell",
+            Self::UserVar { .. } => "user-var",
+            Self::PaneTitleChanged { .. } => "pane-title-changed",
+            Self::TabAddedToWindow { .. } => "tab-added-to-window",
+            Self::TabTitleChanged { .. } => "tab-title-changed",
+            Self::WindowCreated { .. } => "window-created",
+            Self::WindowRemoved { .. } => "window-removed",
+            Self::WindowTitleChanged { .. } => "window-title-changed",
+            Self::WindowWorkspaceChanged { .. } => "window-workspace-changed",
+            Self::WorkspaceRenamed { .. } => "workspace-renamed",
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct ActivatePaneDirection {
     pub pane_id: PaneId,
@@ -1250,6 +1660,98 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_flat_lines() {
+        use termwiz::cell::CellAttributes;
+        use termwiz::surface::SEQ_ZERO;
+
+        let mut bold = CellAttributes::default();
+        bold.set_intensity(termwiz::cell::Intensity::Bold);
+        let lines: Vec<(StableRowIndex, Line)> = vec![
+            (
+                -2,
+                Line::from_text("hello", &CellAttributes::default(), 3, None),
+            ),
+            (-1, Line::from_text("グループ", &bold, 4, None)),
+            (0, Line::with_width(0, SEQ_ZERO)),
+        ];
+        let pdu = Pdu::GetLinesResponse(GetLinesResponse {
+            pane_id: 7,
+            lines: lines.clone().into(),
+        });
+
+        // The flat encoding is only used when it has been negotiated
+        let mut encoded = Vec::new();
+        pdu.encode_with_capabilities(&mut encoded, 0x42, CodecCapabilities::NONE)
+            .unwrap();
+        assert_eq!(Pdu::decode(encoded.as_slice()).unwrap().pdu, pdu);
+
+        let mut flat = Vec::new();
+        pdu.encode_with_capabilities(&mut flat, 0x42, CodecCapabilities::FLAT_LINES)
+            .unwrap();
+        assert_eq!(
+            decode_raw(flat.as_slice()).unwrap().ident,
+            flat::GET_LINES_RESPONSE_FLAT
+        );
+        let decoded = Pdu::decode(flat.as_slice()).unwrap();
+        assert_eq!(decoded.serial, 0x42);
+        let resp = match decoded.pdu {
+            Pdu::GetLinesResponse(resp) => resp,
+            pdu => panic!("unexpected {:?}", pdu),
+        };
+        assert_eq!(resp.pane_id, 7);
+
+        // The storage of the decoded lines may differ from that of the
+        // originals, so compare their contents rather than the lines
+        let (decoded_lines, _images) = resp.lines.extract_data();
+        assert_eq!(decoded_lines.len(), lines.len());
+        for ((row, line), (expected_row, expected)) in decoded_lines.iter().zip(&lines) {
+            assert_eq!(row, expected_row);
+            assert_eq!(line.len(), expected.len());
+            assert_eq!(line.current_seqno(), expected.current_seqno());
+            assert!(line
+                .visible_cells()
+                .zip(expected.visible_cells())
+                .all(|(a, b)| a.same_contents(&b)));
+        }
+    }
+
+    #[test]
+    fn test_flat_lines_bogus_width() {
+        use termwiz::cell::CellAttributes;
+
+        fn push(data: &mut Vec<u8>, value: u64) {
+            leb128::write::unsigned(data, value).unwrap();
+        }
+
+        let mut data = vec![];
+        // pane id, then a single attribute
+        push(&mut data, 7);
+        push(&mut data, 1);
+        CellAttributes::default()
+            .serialize(&mut varbincode::Serializer::new(&mut data))
+            .unwrap();
+        // the text
+        push(&mut data, 1);
+        data.push(b'a');
+        // one line at stable row 0, with seqno 0, no flags and one run
+        push(&mut data, 1);
+        leb128::write::signed(&mut data, 0).unwrap();
+        push(&mut data, 0);
+        push(&mut data, 0);
+        push(&mut data, 1);
+        // a run holding a single cell with an absurd width
+        push(&mut data, 0);
+        push(&mut data, 1);
+        push(&mut data, 1);
+        push(&mut data, 1 << 40);
+
+        let err = flat::decode(flat::GET_LINES_RESPONSE_FLAT, &data, false)
+            .unwrap()
+            .unwrap_err();
+        assert!(err.to_string().contains("cell width"), "{:#}", err);
+    }
+
     #[test]
     fn test_bogus_pdu() {
         let mut encoded = Vec::new();
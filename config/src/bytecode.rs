@@ -0,0 +1,282 @@
+//! An optional cache of the compiled bytecode of the config file
+//! and of the lua modules that it requires.
+//!
+//! The cache is enabled by setting `config_bytecode_cache = true`.
+//! Whether it is used has to be decided before the config has been
+//! evaluated, so it is enabled by the presence of the cache directory,
+//! which is created or removed each time the config is loaded
+//! according to the value of that option.
+//!
+//! Lua doesn't verify the bytecode that it loads, so each cache entry
+//! is authenticated by an HMAC-SHA256 of the whole entry, keyed by a
+//! secret that is kept in the cache directory.  The HMAC also covers
+//! the wezterm version and the source that the bytecode was compiled
+//! from, so that an entry is only used in place of that same source.
+use crate::{wezterm_version, DATA_DIR};
+use hmac::{Hmac, Mac};
+use mlua::{ChunkMode, Function, Lua};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Identifies a cache entry; followed by the HMAC of the entry
+/// on the same line, and then by the bytecode.
+const MAGIC: &str = "wezterm-config-bytecode";
+
+/// The number of bytes in the key used to authenticate entries
+const KEY_LEN: usize = 32;
+
+fn cache_dir() -> PathBuf {
+    DATA_DIR.join("config-bytecode")
+}
+
+/// Returns true if the cache was enabled the last time the config
+/// was loaded
+pub(crate) fn is_enabled() -> bool {
+    cache_dir().is_dir()
+}
+
+/// Create or remove the cache, according to `config_bytecode_cache`
+pub(crate) fn set_enabled(enable: bool) {
+    let dir = cache_dir();
+    let result = match (enable, dir.is_dir()) {
+        (true, false) => std::fs::create_dir_all(&dir).and_then(|_| create_key(&dir).map(|_| ())),
+        (false, true) => std::fs::remove_dir_all(&dir),
+        _ => Ok(()),
+    };
+    if let Err(err) = result {
+        log::warn!(
+            "Failed to {} config bytecode cache {}: {:#}",
+            if enable { "create" } else { "remove" },
+            dir.display(),
+            err
+        );
+    }
+}
+
+fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn cache_file_name(dir: &Path, path: &Path) -> PathBuf {
+    dir.join(format!("{:016x}.luac", hash_of(path)))
+}
+
+fn key_file_name(dir: &Path) -> PathBuf {
+    dir.join("key")
+}
+
+fn read_key(dir: &Path) -> Option<Vec<u8>> {
+    std::fs::read(key_file_name(dir))
+        .ok()
+        .filter(|key| key.len() == KEY_LEN)
+}
+
+/// Generate a new key, replacing any existing key and so
+/// invalidating all of the existing cache entries
+fn create_key(dir: &Path) -> std::io::Result<Vec<u8>> {
+    let mut key = vec![0u8; KEY_LEN];
+    getrandom::getrandom(&mut key)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
+
+    let file_name = key_file_name(dir);
+    std::fs::remove_file(&file_name).ok();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&file_name)?.write_all(&key)?;
+    Ok(key)
+}
+
+/// Returns the HMAC of an entry holding `bytecode` compiled from `source`,
+/// ready to be finalized or verified
+fn entry_mac(key: &[u8], source: &str, bytecode: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(MAGIC.as_bytes());
+    mac.update(wezterm_version().as_bytes());
+    mac.update(&[0]);
+    mac.update(&Sha256::digest(source.as_bytes()));
+    mac.update(bytecode);
+    mac
+}
+
+/// Builds a cache entry holding `bytecode` compiled from `source`
+fn encode_entry(key: &[u8], source: &str, bytecode: &[u8]) -> Vec<u8> {
+    let mac = entry_mac(key, source, bytecode).finalize().into_bytes();
+    let mut data = format!("{MAGIC} {}\n", hex::encode(mac)).into_bytes();
+    data.extend_from_slice(bytecode);
+    data
+}
+
+/// Returns the bytecode from the cache entry `data`, if the entry
+/// is authentic and was made for `source`
+fn decode_entry<'a>(key: &[u8], source: &str, data: &'a [u8]) -> Option<&'a [u8]> {
+    let newline = data.iter().position(|&b| b == b'\n')?;
+    let (header, bytecode) = (&data[..newline], &data[newline + 1..]);
+    let mac = std::str::from_utf8(header)
+        .ok()?
+        .strip_prefix(MAGIC)?
+        .strip_prefix(' ')?;
+    let mac = hex::decode(mac).ok()?;
+    entry_mac(key, source, bytecode)
+        .verify_slice(&mac)
+        .ok()
+        .map(|_| bytecode)
+}
+
+/// Returns the cached bytecode for `source`, if it is up to date
+/// and the entry is authentic
+fn load_cached(dir: &Path, path: &Path, source: &str) -> Option<Vec<u8>> {
+    let key = read_key(dir)?;
+    let data = std::fs::read(cache_file_name(dir, path)).ok()?;
+    match decode_entry(&key, source, &data) {
+        Some(bytecode) => Some(bytecode.to_vec()),
+        None => {
+            log::debug!("Ignoring stale or invalid bytecode for {}", path.display());
+            None
+        }
+    }
+}
+
+fn save(dir: &Path, path: &Path, source: &str, bytecode: &[u8]) -> anyhow::Result<()> {
+    let key = match read_key(dir) {
+        Some(key) => key,
+        None => create_key(dir)?,
+    };
+    let file_name = cache_file_name(dir, path);
+    let data = encode_entry(&key, source, bytecode);
+    // Write to a temporary file and rename it into place so that
+    // another wezterm process never sees a partially written file
+    let temp_name = file_name.with_extension(format!("luac.{}", std::process::id()));
+    std::fs::write(&temp_name, data)?;
+    if let Err(err) = std::fs::rename(&temp_name, &file_name) {
+        std::fs::remove_file(&temp_name).ok();
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Compile `source`, which was read from `path`, into a function,
+/// using the cached bytecode for it if it hasn't changed since it
+/// was cached.
+pub(crate) fn load_chunk<'lua>(
+    lua: &'lua Lua,
+    path: &Path,
+    source: &str,
+) -> mlua::Result<Function<'lua>> {
+    load_chunk_in(lua, &cache_dir(), path, source)
+}
+
+fn load_chunk_in<'lua>(
+    lua: &'lua Lua,
+    dir: &Path,
+    path: &Path,
+    source: &str,
+) -> mlua::Result<Function<'lua>> {
+    if let Some(bytecode) = load_cached(dir, path, source) {
+        match lua
+            .load(bytecode)
+            .set_name(path.to_string_lossy())
+            .set_mode(ChunkMode::Binary)
+            .into_function()
+        {
+            Ok(func) => return Ok(func),
+            Err(err) => {
+                log::warn!("Ignoring cached bytecode for {}: {:#}", path.display(), err);
+            }
+        }
+    }
+
+    let func = lua
+        .load(source)
+        .set_name(path.to_string_lossy())
+        .set_mode(ChunkMode::Text)
+        .into_function()?;
+    if let Err(err) = save(dir, path, source, &func.dump(false)) {
+        log::warn!("Failed to cache bytecode for {}: {:#}", path.display(), err);
+    }
+    Ok(func)
+}
+
+/// Used by the `require` searcher to load modules via the cache.
+/// Returns nil if the module can't be loaded, so that the regular
+/// searcher will be used to report the problem.
+pub(crate) fn load_module<'lua>(
+    lua: &'lua Lua,
+    path: String,
+) -> mlua::Result<Option<Function<'lua>>> {
+    let path = PathBuf::from(path);
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(_) => return Ok(None),
+    };
+    if source.starts_with('#') || source.starts_with('\u{FEFF}') {
+        // The regular searcher knows how to skip these
+        return Ok(None);
+    }
+    Ok(load_chunk(lua, &path, &source).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tampered_entry_is_recompiled() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let path = Path::new("/config/wezterm.lua");
+        let source = "return 40 + 2";
+        let lua = Lua::new();
+
+        let func = load_chunk_in(&lua, dir, path, source).unwrap();
+        assert_eq!(func.call::<_, i64>(()).unwrap(), 42);
+        let bytecode = load_cached(dir, path, source).expect("entry was saved");
+
+        // An entry doesn't apply to different source
+        assert!(load_cached(dir, path, "return 1").is_none());
+
+        // Modified bytecode is rejected
+        let file_name = cache_file_name(dir, path);
+        let mut data = std::fs::read(&file_name).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        std::fs::write(&file_name, &data).unwrap();
+        assert!(load_cached(dir, path, source).is_none());
+
+        // A modified mac is rejected
+        let mut data = std::fs::read(&file_name).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        data[MAGIC.len() + 1] ^= 1;
+        std::fs::write(&file_name, &data).unwrap();
+        assert!(load_cached(dir, path, source).is_none());
+
+        // and the source is compiled again, replacing the entry
+        let func = load_chunk_in(&lua, dir, path, source).unwrap();
+        assert_eq!(func.call::<_, i64>(()).unwrap(), 42);
+        assert_eq!(load_cached(dir, path, source), Some(bytecode));
+    }
+
+    #[test]
+    fn safe_context_loads_bytecode() {
+        let lua = Lua::new();
+        let bytecode = lua
+            .load("return 40 + 2")
+            .into_function()
+            .unwrap()
+            .dump(false);
+        let func = lua
+            .load(&bytecode)
+            .set_mode(ChunkMode::Binary)
+            .into_function()
+            .unwrap();
+        assert_eq!(func.call::<_, i64>(()).unwrap(), 42);
+    }
+}
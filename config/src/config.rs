@@ -6,8 +6,9 @@ use crate::color::{
 use crate::daemon::DaemonOptions;
 use crate::exec_domain::ExecDomain;
 use crate::font::{
-    AllowSquareGlyphOverflow, DisplayPixelGeometry, FontLocatorSelection, FontRasterizerSelection,
-    FontShaperSelection, FreeTypeLoadFlags, FreeTypeLoadTarget, StyleRule, TextStyle,
+    AllowSquareGlyphOverflow, DisplayPixelGeometry, FontFallbackRule, FontLocatorSelection,
+    FontRasterizerSelection, FontShaperSelection, FreeTypeLoadFlags, FreeTypeLoadTarget,
+    IconSubstitution, StyleRule, TextStyle,
 };
 use crate::frontend::FrontEndSelection;
 use crate::keyassignment::{
@@ -75,6 +76,12 @@ pub struct Config {
     #[dynamic(try_from = "crate::units::OptPixelUnit", default)]
     pub strikethrough_position: Option<Dimension>,
 
+    #[dynamic(try_from = "crate::units::OptPixelUnit", default)]
+    pub curly_underline_amplitude: Option<Dimension>,
+
+    #[dynamic(try_from = "crate::units::OptPixelUnit", default)]
+    pub overline_position: Option<Dimension>,
+
     #[dynamic(default)]
     pub allow_square_glyphs_to_overflow_width: AllowSquareGlyphOverflow,
 
@@ -121,6 +128,15 @@ pub struct Config {
     #[dynamic(default)]
     pub font_rules: Vec<StyleRule>,
 
+    /// Fonts that are used for specific scripts or ranges of
+    /// codepoints, ahead of the regular font and its fallbacks
+    #[dynamic(default)]
+    pub font_fallback_rules: Vec<FontFallbackRule>,
+
+    /// Replacement glyphs, fonts and scales for specific icons
+    #[dynamic(default)]
+    pub icon_substitutions: Vec<IconSubstitution>,
+
     /// When true (the default), PaletteIndex 0-7 are shifted to
     /// bright when the font intensity is bold.  The brightening
     /// doesn't apply to text that is the default color.
@@ -188,6 +204,11 @@ pub struct Config {
     #[dynamic(default = "default_scrollback_lines")]
     pub scrollback_lines: usize,
 
+    /// If set, limits the scrollback by the number of bytes used
+    /// to hold it in compressed form, rather than by scrollback_lines
+    #[dynamic(default)]
+    pub scrollback_bytes: Option<usize>,
+
     /// If no `prog` is specified on the command line, use this
     /// instead of running the user's shell.
     /// For example, to have `wezterm` always run `top` by default,
@@ -245,6 +266,20 @@ pub struct Config {
     #[dynamic(default)]
     pub enable_title_reporting: bool,
 
+    /// The largest text, in bytes, that an application can place
+    /// on the clipboard with OSC 52
+    #[dynamic(default = "default_osc52_max_clipboard_size")]
+    pub osc52_max_clipboard_size: usize,
+
+    /// Whether an application can read the clipboard with OSC 52
+    #[dynamic(default)]
+    pub enable_osc52_clipboard_reading: bool,
+
+    /// Adjusts the description of the terminal that is reported in
+    /// reply to the device attributes requests
+    #[dynamic(default)]
+    pub terminal_features: TerminalFeaturesConfig,
+
     /// Specifies the width of a new window, expressed in character cells
     #[dynamic(default = "default_initial_cols", validate = "validate_row_or_col")]
     pub initial_cols: u16,
@@ -268,6 +303,15 @@ pub struct Config {
     #[dynamic(default)]
     pub display_pixel_geometry: DisplayPixelGeometry,
     #[dynamic(default)]
+    pub display_pixel_geometry_by_screen: HashMap<String, DisplayPixelGeometry>,
+    /// Adjusts the coverage of subpixel antialiased text to
+    /// compensate for blending it in sRGB rather than linear space
+    #[dynamic(
+        default = "default_subpixel_gamma",
+        validate = "validate_subpixel_gamma"
+    )]
+    pub subpixel_gamma: f32,
+    #[dynamic(default)]
     pub freetype_load_target: FreeTypeLoadTarget,
     #[dynamic(default)]
     pub freetype_render_target: Option<FreeTypeLoadTarget>,
@@ -319,6 +363,22 @@ pub struct Config {
     #[dynamic(default = "default_harfbuzz_features")]
     pub harfbuzz_features: Vec<String>,
 
+    /// The language to use when shaping text, as a BCP 47 language
+    /// tag such as `en`, `sr` or `zh-Hant`.  Fonts may provide
+    /// different glyphs that are appropriate for a given language.
+    #[dynamic(default = "default_harfbuzz_language")]
+    pub harfbuzz_language: String,
+
+    /// The ISO 15924 code of the script to use when shaping text.
+    /// When not set, the script is inferred from the text.
+    #[dynamic(default, validate = "crate::font::validate_harfbuzz_script")]
+    pub harfbuzz_script: Option<String>,
+
+    /// Additional harfbuzz features to enable when shaping text
+    /// in a given language, keyed by language tag
+    #[dynamic(default)]
+    pub harfbuzz_language_features: HashMap<String, Vec<String>>,
+
     #[dynamic(default)]
     pub front_end: FrontEndSelection,
 
@@ -390,6 +450,27 @@ pub struct Config {
     #[dynamic(default = "default_mux_output_parser_coalesce_delay_ms")]
     pub mux_output_parser_coalesce_delay_ms: u64,
 
+    /// When a focused pane is producing output continuously, its
+    /// output is accumulated for up to this many ms before it is
+    /// applied to the terminal, rather than applying every chunk.
+    #[dynamic(default = "default_mux_output_max_latency_ms")]
+    pub mux_output_max_latency_ms: u64,
+
+    /// Like mux_output_max_latency_ms, but for panes that are not
+    /// focused by any client
+    #[dynamic(default = "default_mux_output_background_max_latency_ms")]
+    pub mux_output_background_max_latency_ms: u64,
+
+    /// The maximum number of bytes of output to accumulate when
+    /// a pane is producing output continuously
+    #[dynamic(default = "default_mux_output_max_batch_size")]
+    pub mux_output_max_batch_size: usize,
+
+    /// The longest that the output of a synchronized update is held
+    /// back while waiting for the application to end the update
+    #[dynamic(default = "default_mux_synchronized_output_timeout_ms")]
+    pub mux_synchronized_output_timeout_ms: u64,
+
     #[dynamic(default = "default_mux_env_remove")]
     pub mux_env_remove: Vec<String>,
 
@@ -407,6 +488,9 @@ pub struct Config {
     #[dynamic(default)]
     pub normalize_output_to_unicode_nfc: bool,
 
+    #[dynamic(default = "default_true")]
+    pub grapheme_clustering: bool,
+
     #[dynamic(default)]
     pub disable_default_key_bindings: bool,
     pub leader: Option<LeaderKey>,
@@ -691,6 +775,12 @@ pub struct Config {
     #[dynamic(default = "default_true")]
     pub automatically_reload_config: bool,
 
+    /// When true, cache the compiled bytecode of the config file and
+    /// the modules that it requires, and use it when loading the config
+    /// if the source hasn't changed.
+    #[dynamic(default)]
+    pub config_bytecode_cache: bool,
+
     #[dynamic(default = "default_check_for_updates")]
     pub check_for_updates: bool,
     #[dynamic(
@@ -889,6 +979,34 @@ impl Config {
         }
     }
 
+    /// Returns the rules that pin fonts to particular text, each with a
+    /// description of where it came from: a rule for each of the
+    /// icon_substitutions that specifies a font or a scale, followed by
+    /// the font_fallback_rules.  The icon rules come first so that they
+    /// take precedence over any broader font_fallback_rules.
+    /// Icons that are only scaled use the fonts from `style`.
+    pub fn effective_font_fallback_rules(
+        &self,
+        style: &TextStyle,
+    ) -> Vec<(String, FontFallbackRule)> {
+        self.icon_substitutions
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, icon)| {
+                Some((
+                    format!("icon_substitutions[{}]", idx + 1),
+                    icon.fallback_rule(style)?,
+                ))
+            })
+            .chain(
+                self.font_fallback_rules
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, rule)| (format!("font_fallback_rules[{}]", idx + 1), rule.clone())),
+            )
+            .collect()
+    }
+
     pub fn wsl_domains(&self) -> Vec<WslDomain> {
         if let Some(domains) = &self.wsl_domains {
             domains.clone()
@@ -1067,14 +1185,23 @@ impl Config {
             wezterm_dynamic::Error::capture_warnings(|| -> anyhow::Result<Config> {
                 let cfg: Config;
 
-                let config: mlua::Value = smol::block_on(
-                    // Skip a potential BOM that Windows software may have placed in the
-                    // file. Note that we can't catch this happening for files that are
-                    // imported via the lua require function.
-                    lua.load(s.trim_start_matches('\u{FEFF}'))
-                        .set_name(p.to_string_lossy())
-                        .eval_async(),
-                )?;
+                // Skip a potential BOM that Windows software may have placed in the
+                // file. Note that we can't catch this happening for files that are
+                // imported via the lua require function.
+                let source = s.trim_start_matches('\u{FEFF}');
+                let cached = if crate::bytecode::is_enabled() {
+                    // If it doesn't compile as a chunk then let eval report
+                    // the problem, or evaluate it as an expression
+                    crate::bytecode::load_chunk(&lua, p, source).ok()
+                } else {
+                    None
+                };
+                let config: mlua::Value = match cached {
+                    Some(func) => smol::block_on(func.call_async(()))?,
+                    None => {
+                        smol::block_on(lua.load(source).set_name(p.to_string_lossy()).eval_async())?
+                    }
+                };
                 let config = Config::apply_overrides_to(&lua, config)?;
                 let config = Config::apply_overrides_obj_to(&lua, config, overrides)?;
                 cfg = Config::from_lua(config, &lua).with_context(|| {
@@ -1096,6 +1223,7 @@ impl Config {
                 Ok(cfg)
             });
         let cfg = config?;
+        crate::bytecode::set_enabled(cfg.config_bytecode_cache);
 
         Ok(Some(LoadedConfig {
             config: Ok(cfg.compute_extra_defaults(Some(p))),
@@ -1605,6 +1733,22 @@ fn default_mux_output_parser_coalesce_delay_ms() -> u64 {
     3
 }
 
+fn default_mux_output_max_latency_ms() -> u64 {
+    16
+}
+
+fn default_mux_output_background_max_latency_ms() -> u64 {
+    100
+}
+
+fn default_mux_output_max_batch_size() -> usize {
+    4 * 1024 * 1024
+}
+
+fn default_mux_synchronized_output_timeout_ms() -> u64 {
+    1000
+}
+
 fn default_mux_output_parser_buffer_size() -> usize {
     128 * 1024
 }
@@ -1658,6 +1802,10 @@ pub fn default_hyperlink_rules() -> Vec<hyperlink::Rule> {
     ]
 }
 
+fn default_harfbuzz_language() -> String {
+    "en".to_string()
+}
+
 fn default_harfbuzz_features() -> Vec<String> {
     ["kern", "liga", "clig"]
         .iter()
@@ -1730,6 +1878,10 @@ fn default_anim_fps() -> u8 {
     10
 }
 
+fn default_osc52_max_clipboard_size() -> usize {
+    16 * 1024 * 1024
+}
+
 fn default_max_fps() -> u8 {
     60
 }
@@ -2049,6 +2201,51 @@ pub enum NotificationHandling {
     SuppressFromFocusedWindow,
 }
 
+/// The description of the terminal that is reported in reply to
+/// the primary, secondary and tertiary device attributes requests
+#[derive(Debug, FromDynamic, ToDynamic, Clone, PartialEq, Eq)]
+pub struct TerminalFeaturesConfig {
+    /// The conformance level, such as 65 for a VT500
+    #[dynamic(default = "default_terminal_level")]
+    pub level: u16,
+    #[dynamic(default = "default_true")]
+    pub sixel: bool,
+    #[dynamic(default)]
+    pub clipboard: bool,
+    /// The model that is reported in the secondary device attributes
+    #[dynamic(default = "default_terminal_type")]
+    pub terminal_type: i64,
+    #[dynamic(default = "default_firmware_version")]
+    pub firmware_version: i64,
+    #[dynamic(default)]
+    pub unit_id: u32,
+}
+
+impl Default for TerminalFeaturesConfig {
+    fn default() -> Self {
+        Self {
+            level: default_terminal_level(),
+            sixel: true,
+            clipboard: false,
+            terminal_type: default_terminal_type(),
+            firmware_version: default_firmware_version(),
+            unit_id: 0,
+        }
+    }
+}
+
+fn default_terminal_level() -> u16 {
+    65
+}
+
+fn default_terminal_type() -> i64 {
+    1
+}
+
+fn default_firmware_version() -> i64 {
+    277
+}
+
 fn validate_row_or_col(value: &u16) -> Result<(), String> {
     if *value < 1 {
         Err("initial_cols and initial_rows must be non-zero".to_string())
@@ -2057,6 +2254,20 @@ fn validate_row_or_col(value: &u16) -> Result<(), String> {
     }
 }
 
+fn default_subpixel_gamma() -> f32 {
+    1.8
+}
+
+fn validate_subpixel_gamma(value: &f32) -> Result<(), String> {
+    if !(1.0..=3.0).contains(value) {
+        Err(format!(
+            "Illegal value {value} for subpixel_gamma; it must be between 1.0 and 3.0"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
 fn validate_line_height(value: &f64) -> Result<(), String> {
     if *value <= 0.0 {
         Err(format!(
@@ -3,6 +3,7 @@ use crate::*;
 use bitflags::*;
 use enum_display_derive::Display;
 use luahelper::impl_lua_conversion_dynamic;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt::Display;
 use wezterm_dynamic::{FromDynamic, FromDynamicOptions, ToDynamic, Value};
@@ -223,6 +224,12 @@ pub enum DisplayPixelGeometry {
     #[default]
     RGB,
     BGR,
+    /// Use the subpixel order reported by the display, falling
+    /// back to grayscale antialiasing if it is unknown or if
+    /// the display is rotated
+    Auto,
+    /// Use grayscale rather than subpixel antialiasing
+    None,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, FromDynamic, ToDynamic)]
@@ -352,6 +359,117 @@ impl TryFrom<String> for FreeTypeLoadFlags {
     }
 }
 
+/// Coordinates for the design axes of a variable font, such as `wght`
+/// (weight), `slnt` (slant), `opsz` (optical size) or any custom axis
+/// defined by the font, keyed by the OpenType axis tag.
+/// In lua this is a table such as `{wght=650, slnt=-10}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FontVariations(Vec<(String, NotNan<f64>)>);
+
+impl FontVariations {
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.0.iter().map(|(tag, value)| (tag.as_str(), **value))
+    }
+
+    pub fn get(&self, tag: &str) -> Option<f64> {
+        self.iter()
+            .find_map(|(t, value)| if t == tag { Some(value) } else { None })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the tag as the big-endian integer used by freetype
+    /// and harfbuzz, padding it with spaces to four characters
+    pub fn tag_to_u32(tag: &str) -> u32 {
+        let mut bytes = [b' '; 4];
+        for (dest, src) in bytes.iter_mut().zip(tag.bytes()) {
+            *dest = src;
+        }
+        u32::from_be_bytes(bytes)
+    }
+
+    /// The inverse of `tag_to_u32`
+    pub fn tag_from_u32(tag: u32) -> String {
+        String::from_utf8_lossy(&tag.to_be_bytes())
+            .trim_end()
+            .to_string()
+    }
+}
+
+impl std::fmt::Display for FontVariations {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(fmt, "{{")?;
+        for (idx, (tag, value)) in self.iter().enumerate() {
+            if idx > 0 {
+                write!(fmt, ", ")?;
+            }
+            write!(fmt, "{tag}={value}")?;
+        }
+        write!(fmt, "}}")
+    }
+}
+
+impl FromDynamic for FontVariations {
+    fn from_dynamic(
+        value: &Value,
+        options: FromDynamicOptions,
+    ) -> Result<Self, wezterm_dynamic::Error> {
+        let map = <HashMap<String, NotNan<f64>>>::from_dynamic(value, options)?;
+        let mut variations = vec![];
+        for (tag, value) in map {
+            if tag.is_empty() || tag.len() > 4 || !tag.is_ascii() {
+                return Err(format!(
+                    "invalid variation axis tag `{tag}`; \
+                     axis tags are between 1 and 4 ascii characters long"
+                )
+                .into());
+            }
+            variations.push((tag, value));
+        }
+        variations.sort();
+        Ok(Self(variations))
+    }
+}
+
+impl ToDynamic for FontVariations {
+    fn to_dynamic(&self) -> Value {
+        self.0
+            .iter()
+            .cloned()
+            .collect::<HashMap<String, NotNan<f64>>>()
+            .to_dynamic()
+    }
+}
+
+/// Overrides for the metrics that are used to draw underlines,
+/// strikethrough and overlines.  The values are fractions of the
+/// cell height, and positions are measured from the top of the cell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, FromDynamic, ToDynamic)]
+pub struct DecorationMetrics {
+    #[dynamic(default, validate = "validate_cell_fraction")]
+    pub underline_thickness: Option<NotNan<f64>>,
+    #[dynamic(default, validate = "validate_cell_fraction")]
+    pub underline_position: Option<NotNan<f64>>,
+    #[dynamic(default, validate = "validate_cell_fraction")]
+    pub curly_underline_amplitude: Option<NotNan<f64>>,
+    #[dynamic(default, validate = "validate_cell_fraction")]
+    pub strikethrough_position: Option<NotNan<f64>>,
+    #[dynamic(default, validate = "validate_cell_fraction")]
+    pub overline_position: Option<NotNan<f64>>,
+}
+
+fn validate_cell_fraction(value: &Option<NotNan<f64>>) -> Result<(), String> {
+    match value {
+        Some(v) if !(0.0..=1.0).contains(&**v) => Err(format!(
+            "Illegal value {v}; decoration_metrics are fractions of the \
+             cell height and must be between 0 and 1"
+        )),
+        _ => Ok(()),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, FromDynamic, ToDynamic)]
 pub struct FontAttributes {
     /// The font family name
@@ -379,9 +497,51 @@ pub struct FontAttributes {
     pub scale: Option<NotNan<f64>>,
     #[dynamic(default)]
     pub assume_emoji_presentation: Option<bool>,
+    #[dynamic(default)]
+    pub variations: Option<FontVariations>,
+    /// How much to widen the strokes when synthesizing bold for a font
+    /// that has no bold variant, as a fraction of the em size.
+    /// 0 disables synthetic bold.
+    #[dynamic(default, validate = "validate_synthetic_bold_strength")]
+    pub synthetic_bold_strength: Option<NotNan<f64>>,
+    /// The angle, in degrees, by which to slant the glyphs when
+    /// synthesizing italics for a font that has no italic variant.
+    /// 0 disables synthetic italics.
+    #[dynamic(default, validate = "validate_synthetic_oblique_angle")]
+    pub synthetic_oblique_angle: Option<NotNan<f64>>,
+    /// Overrides the BCP 47 language tag used when shaping with this font
+    #[dynamic(default)]
+    pub harfbuzz_language: Option<String>,
+    /// Overrides the ISO 15924 script code used when shaping with this font
+    #[dynamic(default, validate = "validate_harfbuzz_script")]
+    pub harfbuzz_script: Option<String>,
+    /// Overrides the underline, strikethrough and overline metrics
+    /// that are reported by this font
+    #[dynamic(default)]
+    pub decoration_metrics: Option<DecorationMetrics>,
 }
 impl_lua_conversion_dynamic!(FontAttributes);
 
+pub(crate) fn validate_synthetic_bold_strength(value: &Option<NotNan<f64>>) -> Result<(), String> {
+    match value {
+        Some(strength) if !(0.0..=0.25).contains(&**strength) => Err(format!(
+            "Illegal value {strength} for synthetic_bold_strength; \
+             it must be between 0 and 0.25"
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn validate_synthetic_oblique_angle(value: &Option<NotNan<f64>>) -> Result<(), String> {
+    match value {
+        Some(angle) if !(-45.0..=45.0).contains(&**angle) => Err(format!(
+            "Illegal value {angle} for synthetic_oblique_angle; \
+             it must be between -45 and 45 degrees"
+        )),
+        _ => Ok(()),
+    }
+}
+
 impl std::fmt::Display for FontAttributes {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         write!(
@@ -407,6 +567,12 @@ impl FontAttributes {
             freetype_load_flags: None,
             scale: None,
             assume_emoji_presentation: None,
+            variations: None,
+            synthetic_bold_strength: None,
+            synthetic_oblique_angle: None,
+            harfbuzz_language: None,
+            harfbuzz_script: None,
+            decoration_metrics: None,
         }
     }
 
@@ -424,6 +590,12 @@ impl FontAttributes {
             freetype_load_flags: None,
             scale: None,
             assume_emoji_presentation: None,
+            variations: None,
+            synthetic_bold_strength: None,
+            synthetic_oblique_angle: None,
+            harfbuzz_language: None,
+            harfbuzz_script: None,
+            decoration_metrics: None,
         }
     }
 }
@@ -443,6 +615,12 @@ impl Default for FontAttributes {
             freetype_load_flags: None,
             scale: None,
             assume_emoji_presentation: None,
+            variations: None,
+            synthetic_bold_strength: None,
+            synthetic_oblique_angle: None,
+            harfbuzz_language: None,
+            harfbuzz_script: None,
+            decoration_metrics: None,
         }
     }
 }
@@ -649,6 +827,239 @@ pub struct StyleRule {
     pub font: TextStyle,
 }
 
+/// An inclusive range of unicode codepoints.
+/// In the configuration this is a string such as `"U+E000-U+F8FF"`,
+/// or `"U+E0B0"` for a single codepoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FromDynamic, ToDynamic)]
+#[dynamic(try_from = "String", into = "String")]
+pub struct UnicodeRange {
+    pub first: u32,
+    pub last: u32,
+}
+
+impl UnicodeRange {
+    pub fn contains(&self, c: char) -> bool {
+        (self.first..=self.last).contains(&(c as u32))
+    }
+}
+
+impl TryFrom<String> for UnicodeRange {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, String> {
+        fn parse_codepoint(s: &str, range: &str) -> Result<u32, String> {
+            let hex = s
+                .trim()
+                .strip_prefix("U+")
+                .or_else(|| s.trim().strip_prefix("u+"))
+                .unwrap_or(s.trim());
+            let value = u32::from_str_radix(hex, 16).map_err(|_| {
+                format!("invalid codepoint `{s}` in unicode range `{range}`; expected eg: U+E000")
+            })?;
+            if value > 0x10ffff {
+                return Err(format!(
+                    "codepoint `{s}` in unicode range `{range}` is larger than U+10FFFF"
+                ));
+            }
+            Ok(value)
+        }
+
+        let (first, last) = match s.split_once('-') {
+            Some((first, last)) => (parse_codepoint(first, &s)?, parse_codepoint(last, &s)?),
+            None => {
+                let value = parse_codepoint(&s, &s)?;
+                (value, value)
+            }
+        };
+        if last < first {
+            return Err(format!(
+                "unicode range `{s}` ends before it starts; the lower codepoint must come first"
+            ));
+        }
+        Ok(Self { first, last })
+    }
+}
+
+impl From<&UnicodeRange> for String {
+    fn from(range: &UnicodeRange) -> String {
+        range.to_string()
+    }
+}
+
+impl From<UnicodeRange> for String {
+    fn from(range: UnicodeRange) -> String {
+        range.to_string()
+    }
+}
+
+impl std::fmt::Display for UnicodeRange {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        if self.first == self.last {
+            write!(fmt, "U+{:04X}", self.first)
+        } else {
+            write!(fmt, "U+{:04X}-U+{:04X}", self.first, self.last)
+        }
+    }
+}
+
+/// Pins a font to a set of scripts and/or ranges of codepoints.
+/// Text that matches the rule is shaped with `font` before trying
+/// the regular font and its fallbacks, and `font` is not used for
+/// any other text.
+#[derive(Debug, Clone, PartialEq, FromDynamic, ToDynamic)]
+pub struct FontFallbackRule {
+    /// ISO 15924 script codes, such as `Hani` or `Arab`
+    #[dynamic(default, validate = "validate_script_codes")]
+    pub scripts: Vec<String>,
+    #[dynamic(default)]
+    pub unicode_ranges: Vec<UnicodeRange>,
+    pub font: TextStyle,
+}
+
+impl FontFallbackRule {
+    /// Returns a human readable description of the text matched by this rule
+    pub fn describe(&self) -> String {
+        let mut conditions = vec![];
+        if !self.scripts.is_empty() {
+            conditions.push(format!("scripts={}", self.scripts.join(",")));
+        }
+        if !self.unicode_ranges.is_empty() {
+            let ranges: Vec<String> = self.unicode_ranges.iter().map(|r| r.to_string()).collect();
+            conditions.push(format!("unicode_ranges={}", ranges.join(",")));
+        }
+        if conditions.is_empty() {
+            "nothing".to_string()
+        } else {
+            conditions.join(" ")
+        }
+    }
+}
+
+/// A character that identifies an icon.
+/// In the configuration this is a string holding either the character
+/// itself, its codepoint such as `"U+F07B"`, or the name of a Nerd Font
+/// symbol such as `"md_folder"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IconCodepoint(pub char);
+
+impl FromDynamic for IconCodepoint {
+    fn from_dynamic(
+        value: &Value,
+        options: FromDynamicOptions,
+    ) -> Result<Self, wezterm_dynamic::Error> {
+        let s = String::from_dynamic(value, options)?;
+        Ok(Self::try_from(s)?)
+    }
+}
+
+impl ToDynamic for IconCodepoint {
+    fn to_dynamic(&self) -> Value {
+        Value::String(String::from(*self))
+    }
+}
+
+impl TryFrom<String> for IconCodepoint {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, String> {
+        let mut chars = s.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(Self(c));
+        }
+        if let Some(hex) = s.strip_prefix("U+").or_else(|| s.strip_prefix("u+")) {
+            return u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .map(Self)
+                .ok_or_else(|| format!("invalid codepoint `{s}`; expected eg: U+F07B"));
+        }
+        termwiz::nerdfonts::NERD_FONTS
+            .get(s.as_str())
+            .map(|&c| Self(c))
+            .ok_or_else(|| {
+                format!(
+                    "invalid icon `{s}`; expected a single character, a codepoint \
+                     such as U+F07B or the name of a Nerd Font symbol such as md_folder"
+                )
+            })
+    }
+}
+
+impl From<IconCodepoint> for String {
+    fn from(icon: IconCodepoint) -> String {
+        format!("U+{:04X}", icon.0 as u32)
+    }
+}
+
+/// Adjusts the way that an icon, such as a symbol from a Nerd Font
+/// patched font, is rendered, without having to modify the fonts
+#[derive(Debug, Clone, PartialEq, FromDynamic, ToDynamic)]
+pub struct IconSubstitution {
+    pub icon: IconCodepoint,
+    /// Render this glyph in place of the icon
+    #[dynamic(default)]
+    pub replacement: Option<IconCodepoint>,
+    /// Render the icon using this font, ahead of the regular font
+    /// and its fallbacks
+    #[dynamic(default)]
+    pub font: Option<TextStyle>,
+    /// Scale the icon by this factor
+    #[dynamic(default, validate = "validate_icon_scale")]
+    pub scale: Option<NotNan<f64>>,
+}
+
+impl IconSubstitution {
+    /// Returns a rule that pins the font and/or scale of this icon,
+    /// or None if neither is specified.  Icons that are only scaled
+    /// are rendered using the fonts from `style`.
+    pub fn fallback_rule(&self, style: &TextStyle) -> Option<FontFallbackRule> {
+        if self.font.is_none() && self.scale.is_none() {
+            return None;
+        }
+        let mut font = self.font.clone().unwrap_or_else(|| style.clone());
+        if let Some(scale) = self.scale {
+            for attr in &mut font.font {
+                attr.scale = Some(attr.scale.map_or(scale, |s| s * scale));
+            }
+        }
+        let codepoint = self.icon.0 as u32;
+        Some(FontFallbackRule {
+            scripts: vec![],
+            unicode_ranges: vec![UnicodeRange {
+                first: codepoint,
+                last: codepoint,
+            }],
+            font,
+        })
+    }
+}
+
+fn validate_icon_scale(scale: &Option<NotNan<f64>>) -> Result<(), String> {
+    match scale {
+        Some(scale) if !scale.is_finite() || **scale <= 0. => Err(format!(
+            "invalid scale {scale}; the scale of an icon must be greater than zero"
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn validate_script_codes(scripts: &[String]) -> Result<(), String> {
+    for script in scripts {
+        if script.len() != 4 || !script.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(format!(
+                "invalid script `{script}`; scripts are specified using \
+                 their four letter ISO 15924 code, such as `Hani` or `Arab`"
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_harfbuzz_script(script: &Option<String>) -> Result<(), String> {
+    match script {
+        Some(script) => validate_script_codes(std::slice::from_ref(script)),
+        None => Ok(()),
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
 pub enum AllowSquareGlyphOverflow {
     Never,
@@ -720,4 +1131,61 @@ mod test {
             assert_eq!(style.font[0].family, "Inconsolata");
         }
     }
+
+    #[test]
+    fn test_unicode_range() {
+        let range = UnicodeRange::try_from("U+E000-U+F8FF".to_string()).unwrap();
+        assert_eq!(range.first, 0xe000);
+        assert_eq!(range.last, 0xf8ff);
+        assert!(range.contains('\u{e0b0}'));
+        assert!(!range.contains('a'));
+        assert_eq!(range.to_string(), "U+E000-U+F8FF");
+
+        let single = UnicodeRange::try_from("e0a0".to_string()).unwrap();
+        assert_eq!(
+            single,
+            UnicodeRange {
+                first: 0xe0a0,
+                last: 0xe0a0
+            }
+        );
+        assert_eq!(single.to_string(), "U+E0A0");
+
+        assert!(UnicodeRange::try_from("U+F8FF-U+E000".to_string()).is_err());
+        assert!(UnicodeRange::try_from("U+110000".to_string()).is_err());
+        assert!(UnicodeRange::try_from("nope".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_icon_codepoint() {
+        let icon = |s: &str| IconCodepoint::try_from(s.to_string()).map(|i| i.0);
+        assert_eq!(icon("\u{f07b}"), Ok('\u{f07b}'));
+        assert_eq!(icon("U+F07B"), Ok('\u{f07b}'));
+        assert_eq!(icon("u+f07b"), Ok('\u{f07b}'));
+        assert_eq!(icon("md_folder"), Ok('\u{f024b}'));
+        assert!(icon("U+D800").is_err());
+        assert!(icon("not_an_icon").is_err());
+        assert_eq!(String::from(IconCodepoint('\u{e0b0}')), "U+E0B0");
+    }
+
+    #[test]
+    fn test_icon_fallback_rule() {
+        let style = TextStyle {
+            font: vec![FontAttributes::new("Primary")],
+            foreground: None,
+        };
+        let mut icon = IconSubstitution {
+            icon: IconCodepoint('\u{f07b}'),
+            replacement: Some(IconCodepoint('\u{f024b}')),
+            font: None,
+            scale: None,
+        };
+        assert_eq!(icon.fallback_rule(&style), None);
+
+        icon.scale = Some(NotNan::new(0.5).unwrap());
+        let rule = icon.fallback_rule(&style).unwrap();
+        assert_eq!(rule.describe(), "unicode_ranges=U+F07B");
+        assert_eq!(rule.font.font[0].family, "Primary");
+        assert_eq!(rule.font.font[0].scale, Some(NotNan::new(0.5).unwrap()));
+    }
 }
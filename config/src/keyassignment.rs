@@ -515,6 +515,7 @@ pub enum KeyAssignment {
         destination: ClipboardCopyDestination,
     },
     PasteFrom(ClipboardPasteSource),
+    CancelPaste,
     ActivateTabRelative(isize),
     ActivateTabRelativeNoWrap(isize),
     IncreaseFontSize,
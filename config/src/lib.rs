@@ -21,6 +21,7 @@ use wezterm_dynamic::{FromDynamic, FromDynamicOptions, ToDynamic, UnknownFieldAc
 
 mod background;
 mod bell;
+mod bytecode;
 mod color;
 mod config;
 mod daemon;
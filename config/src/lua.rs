@@ -1,8 +1,8 @@
 use crate::exec_domain::{ExecDomain, ValueOrFunc};
 use crate::keyassignment::KeyAssignment;
 use crate::{
-    Config, FontAttributes, FontStretch, FontStyle, FontWeight, FreeTypeLoadTarget, RgbaColor,
-    TextStyle,
+    Config, DecorationMetrics, FontAttributes, FontStretch, FontStyle, FontVariations, FontWeight,
+    FreeTypeLoadTarget, RgbaColor, TextStyle,
 };
 use anyhow::{anyhow, Context};
 use luahelper::{from_lua_value_dynamic, lua_value_to_dynamic, to_lua};
@@ -209,6 +209,7 @@ fn config_builder_new_index<'lua>(
 /// In addition to this, the lua standard library, except for
 /// the `debug` module, is also available to the script.
 pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
+    let use_bytecode_cache = crate::bytecode::is_enabled();
     let lua = Lua::new();
 
     let config_dir = config_file.parent().unwrap_or_else(|| Path::new("/"));
@@ -263,20 +264,34 @@ pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
         // add the name of the file that it would find (as returned from
         // package.searchpath) to the watch list, then we just call the
         // original implementation.
+        // When the bytecode cache is enabled, we are passed a function
+        // that loads the module via the cache.
+        let load_cached = if use_bytecode_cache {
+            Some(lua.create_function(crate::bytecode::load_module)?)
+        } else {
+            None
+        };
         lua.load(
             r#"
+local load_cached = ...
 local orig = package.searchers[2]
 package.searchers[2] = function(module)
   local name, err = package.searchpath(module, package.path)
   if name then
     package.loaded.wezterm.add_to_config_reload_watch_list(name)
+    if load_cached then
+      local loader = load_cached(name)
+      if loader then
+        return loader, name
+      end
+    end
   end
   return orig(module)
 end
         "#,
         )
         .set_name("=searcher")
-        .eval()
+        .call::<_, ()>(load_cached)
         .context("replace package.searchers")?;
 
         wezterm_mod.set(
@@ -498,6 +513,18 @@ struct LuaFontAttributes {
     pub scale: Option<NotNan<f64>>,
     #[dynamic(default)]
     pub assume_emoji_presentation: Option<bool>,
+    #[dynamic(default)]
+    pub variations: Option<FontVariations>,
+    #[dynamic(default, validate = "crate::font::validate_synthetic_bold_strength")]
+    pub synthetic_bold_strength: Option<NotNan<f64>>,
+    #[dynamic(default, validate = "crate::font::validate_synthetic_oblique_angle")]
+    pub synthetic_oblique_angle: Option<NotNan<f64>>,
+    #[dynamic(default)]
+    pub harfbuzz_language: Option<String>,
+    #[dynamic(default, validate = "crate::font::validate_harfbuzz_script")]
+    pub harfbuzz_script: Option<String>,
+    #[dynamic(default)]
+    pub decoration_metrics: Option<DecorationMetrics>,
 }
 impl<'lua> FromLua<'lua> for LuaFontAttributes {
     fn from_lua(value: Value<'lua>, _lua: &'lua Lua) -> Result<Self, mlua::Error> {
@@ -585,6 +612,12 @@ fn font<'lua>(
             },
             scale: attrs.scale,
             assume_emoji_presentation: attrs.assume_emoji_presentation,
+            variations: attrs.variations,
+            synthetic_bold_strength: attrs.synthetic_bold_strength,
+            synthetic_oblique_angle: attrs.synthetic_oblique_angle,
+            harfbuzz_language: attrs.harfbuzz_language,
+            harfbuzz_script: attrs.harfbuzz_script,
+            decoration_metrics: attrs.decoration_metrics,
         }));
 
     Ok(text_style)
@@ -634,6 +667,12 @@ fn font_with_fallback<'lua>(
                 },
                 scale: attrs.scale,
                 assume_emoji_presentation: attrs.assume_emoji_presentation,
+                variations: attrs.variations,
+                synthetic_bold_strength: attrs.synthetic_bold_strength,
+                synthetic_oblique_angle: attrs.synthetic_oblique_angle,
+                harfbuzz_language: attrs.harfbuzz_language,
+                harfbuzz_script: attrs.harfbuzz_script,
+                decoration_metrics: attrs.decoration_metrics,
             }));
     }
 
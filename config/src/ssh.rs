@@ -100,6 +100,12 @@ pub struct SshDomain {
     #[dynamic(default)]
     pub ssh_option: HashMap<String, String>,
 
+    /// Port forwards to establish once connected, using the same
+    /// syntax as the `-L`, `-R` and `-D` options of `ssh`, eg:
+    /// `"L8080:localhost:80"`
+    #[dynamic(default)]
+    pub port_forwards: Vec<String>,
+
     pub default_prog: Option<Vec<String>>,
 
     #[dynamic(default)]
@@ -3,6 +3,7 @@
 use crate::{configuration, ConfigHandle, NewlineCanon};
 use std::sync::Mutex;
 use termwiz::cell::UnicodeVersion;
+use termwiz::escape::device_attributes::TerminalFeatures;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::config::BidiMode;
 
@@ -52,6 +53,10 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
         self.configuration().scrollback_lines
     }
 
+    fn scrollback_bytes(&self) -> Option<usize> {
+        self.configuration().scrollback_bytes
+    }
+
     fn enable_csi_u_key_encoding(&self) -> bool {
         self.configuration().enable_csi_u_key_encoding
     }
@@ -82,6 +87,14 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
         self.configuration().enable_title_reporting
     }
 
+    fn osc52_max_clipboard_size(&self) -> usize {
+        self.configuration().osc52_max_clipboard_size
+    }
+
+    fn enable_osc52_clipboard_reading(&self) -> bool {
+        self.configuration().enable_osc52_clipboard_reading
+    }
+
     fn enable_kitty_keyboard(&self) -> bool {
         self.configuration().enable_kitty_keyboard
     }
@@ -120,6 +133,24 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
         self.configuration().normalize_output_to_unicode_nfc
     }
 
+    fn grapheme_clustering(&self) -> bool {
+        self.configuration().grapheme_clustering
+    }
+
+    fn terminal_features(&self) -> TerminalFeatures {
+        let config = self.configuration();
+        let features = &config.terminal_features;
+        TerminalFeatures {
+            level: features.level,
+            ..TerminalFeatures::wezterm()
+        }
+        .with_sixel(features.sixel)
+        .with_clipboard(features.clipboard)
+        .with_terminal_type(features.terminal_type)
+        .with_firmware_version(features.firmware_version)
+        .with_unit_id(features.unit_id)
+    }
+
     fn bidi_mode(&self) -> BidiMode {
         let config = self.configuration();
         BidiMode {
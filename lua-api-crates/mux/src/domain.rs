@@ -1,6 +1,7 @@
 use super::*;
 use mlua::UserDataRef;
 use mux::domain::{Domain, DomainId, DomainState};
+use mux::ssh::RemoteSshDomain;
 use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug)]
@@ -83,5 +84,62 @@ impl UserData for MuxDomain {
                 .any(|p| p.domain_id() == domain.domain_id());
             Ok(have_panes_in_domain)
         });
+
+        methods.add_async_method("add_port_forward", |_, this, spec: String| async move {
+            let mux = get_mux()?;
+            let domain = this.resolve(&mux)?;
+            let ssh_domain = domain.downcast_ref::<RemoteSshDomain>().ok_or_else(|| {
+                mlua::Error::external(format!(
+                    "domain {} does not support port forwarding",
+                    domain.domain_name()
+                ))
+            })?;
+            ssh_domain.add_port_forward(&spec).await.map_err(|err| {
+                mlua::Error::external(format!(
+                    "failed to add port forward {spec} to domain {}: {err:#}",
+                    domain.domain_name()
+                ))
+            })
+        });
+
+        methods.add_method("ssh_stats", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let domain = this.resolve(&mux)?;
+            Ok(domain.ssh_session().map(|session| {
+                let stats = session.stats();
+                SshStats {
+                    bytes_sent: stats.bytes_sent,
+                    bytes_received: stats.bytes_received,
+                    send_rate: stats.send_rate,
+                    receive_rate: stats.receive_rate,
+                    open_channels: stats.open_channels,
+                    total_channels: stats.total_channels,
+                    connect_time_ms: stats.connect_time.map(|d| d.as_secs_f64() * 1000.),
+                    rtt_ms: stats.rtt.map(|d| d.as_secs_f64() * 1000.),
+                }
+            }))
+        });
+
+        methods.add_method("port_forwards", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let domain = this.resolve(&mux)?;
+            Ok(domain
+                .downcast_ref::<RemoteSshDomain>()
+                .map(|ssh_domain| ssh_domain.port_forward_status())
+                .unwrap_or_default())
+        });
     }
 }
+
+#[derive(Debug, FromDynamic, ToDynamic)]
+struct SshStats {
+    bytes_sent: u64,
+    bytes_received: u64,
+    send_rate: u64,
+    receive_rate: u64,
+    open_channels: usize,
+    total_channels: usize,
+    connect_time_ms: Option<f64>,
+    rtt_ms: Option<f64>,
+}
+impl_lua_conversion_dynamic!(SshStats);
@@ -4,12 +4,16 @@ use finl_unicode::grapheme_clusters::Graphemes;
 use luahelper::impl_lua_conversion_dynamic;
 use std::str::FromStr;
 use termwiz::caps::{Capabilities, ColorLevel, ProbeHints};
-use termwiz::cell::{grapheme_column_width, unicode_column_width, AttributeChange, CellAttributes};
+use termwiz::cell::{
+    grapheme_column_width, unicode_column_width, AttributeChange, CellAttributes, Intensity,
+    Underline,
+};
 use termwiz::color::{AnsiColor, ColorAttribute, ColorSpec, SrgbaTuple};
 use termwiz::render::terminfo::TerminfoRenderer;
 use termwiz::surface::change::Change;
 use termwiz::surface::Line;
 use wezterm_dynamic::{FromDynamic, ToDynamic};
+use wezterm_term::color::ColorPalette;
 
 pub fn register(lua: &Lua) -> anyhow::Result<()> {
     let wezterm_mod = get_or_create_module(lua, "wezterm")?;
@@ -301,3 +305,228 @@ pub fn lines_to_escapes(lines: Vec<Line>) -> anyhow::Result<String> {
     renderer.render_to(&changes, &mut target)?;
     Ok(String::from_utf8(target.target)?)
 }
+
+fn html_escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Compute the inline css for a cell with the specified attributes.
+/// Returns an empty string for cells that look like the default.
+fn cell_attrs_to_css(attrs: &CellAttributes, palette: &ColorPalette) -> String {
+    let mut css = vec![];
+
+    let (fg, bg) = if attrs.reverse() {
+        (
+            palette.resolve_bg(attrs.background()),
+            palette.resolve_fg(attrs.foreground()),
+        )
+    } else {
+        (
+            palette.resolve_fg(attrs.foreground()),
+            palette.resolve_bg(attrs.background()),
+        )
+    };
+    if fg != palette.foreground {
+        css.push(format!("color:{}", fg.to_rgb_string()));
+    }
+    if bg != palette.background {
+        css.push(format!("background-color:{}", bg.to_rgb_string()));
+    }
+
+    match attrs.intensity() {
+        Intensity::Normal => {}
+        Intensity::Bold => css.push("font-weight:bold".to_string()),
+        Intensity::Half => css.push("opacity:0.5".to_string()),
+    }
+    if attrs.italic() {
+        css.push("font-style:italic".to_string());
+    }
+
+    let mut lines = vec![];
+    let underline_style = match attrs.underline() {
+        Underline::None => None,
+        Underline::Single => Some("solid"),
+        Underline::Double => Some("double"),
+        Underline::Curly => Some("wavy"),
+        Underline::Dotted => Some("dotted"),
+        Underline::Dashed => Some("dashed"),
+    };
+    if underline_style.is_some() {
+        lines.push("underline");
+    }
+    if attrs.strikethrough() {
+        lines.push("line-through");
+    }
+    if attrs.overline() {
+        lines.push("overline");
+    }
+    if !lines.is_empty() {
+        css.push(format!("text-decoration-line:{}", lines.join(" ")));
+        if let Some(style @ ("double" | "wavy" | "dotted" | "dashed")) = underline_style {
+            css.push(format!("text-decoration-style:{style}"));
+        }
+    }
+
+    if attrs.invisible() {
+        css.push("visibility:hidden".to_string());
+    }
+
+    css.join(";")
+}
+
+/// Returns true if a hyperlink to `uri` can be rendered as an `<a>`
+/// element.  Only a few schemes are allowed, so that opening the html
+/// can't run a `javascript:` uri, for example.
+fn is_safe_href(uri: &str) -> bool {
+    match uri.split_once(':') {
+        Some((scheme, _)) => ["http", "https", "file", "mailto"]
+            .iter()
+            .any(|safe| scheme.eq_ignore_ascii_case(safe)),
+        None => false,
+    }
+}
+
+/// Render lines as an html `<pre>` element, styling the text with
+/// inline css using the colors from `palette`.
+/// Hyperlinks with an http, https, file or mailto uri are rendered
+/// as `<a>` elements; the text of other hyperlinks is rendered as-is.
+pub fn lines_to_html(lines: &[Line], palette: &ColorPalette) -> String {
+    let mut html = format!(
+        "<pre style=\"color:{};background-color:{}\">",
+        palette.foreground.to_rgb_string(),
+        palette.background.to_rgb_string()
+    );
+
+    for line in lines {
+        // Group the cells into runs that share the same presentation
+        let mut runs: Vec<(String, Option<String>, String)> = vec![];
+        for cell in line.visible_cells() {
+            let css = cell_attrs_to_css(cell.attrs(), palette);
+            let link = cell
+                .attrs()
+                .hyperlink()
+                .map(|link| link.uri())
+                .filter(|uri| is_safe_href(uri))
+                .map(str::to_string);
+            match runs.last_mut() {
+                Some((run_css, run_link, text)) if *run_css == css && *run_link == link => {
+                    text.push_str(cell.str());
+                }
+                _ => runs.push((css, link, cell.str().to_string())),
+            }
+        }
+
+        // Trailing blank space is not interesting
+        if let Some((css, link, text)) = runs.last_mut() {
+            if css.is_empty() && link.is_none() {
+                text.truncate(text.trim_end().len());
+            }
+        }
+
+        for (css, link, text) in runs {
+            if let Some(link) = &link {
+                html.push_str("<a href=\"");
+                html_escape(link, &mut html);
+                html.push_str("\">");
+            }
+            if css.is_empty() {
+                html_escape(&text, &mut html);
+            } else {
+                html.push_str("<span style=\"");
+                html.push_str(&css);
+                html.push_str("\">");
+                html_escape(&text, &mut html);
+                html.push_str("</span>");
+            }
+            if link.is_some() {
+                html.push_str("</a>");
+            }
+        }
+        html.push('\n');
+    }
+
+    html.push_str("</pre>");
+    html
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use termwiz::hyperlink::Hyperlink;
+    use termwiz::surface::SEQ_ZERO;
+
+    fn line(runs: &[(&str, Option<&str>)]) -> Line {
+        let mut line = Line::new(SEQ_ZERO);
+        for (text, uri) in runs {
+            let mut attrs = CellAttributes::default();
+            attrs.set_hyperlink(uri.map(|uri| Arc::new(Hyperlink::new(uri))));
+            line.append_line(Line::from_text(text, &attrs, SEQ_ZERO, None), SEQ_ZERO);
+        }
+        line
+    }
+
+    fn html(runs: &[(&str, Option<&str>)]) -> String {
+        let palette = ColorPalette::default();
+        let html = lines_to_html(&[line(runs)], &palette);
+        let prefix = format!(
+            "<pre style=\"color:{};background-color:{}\">",
+            palette.foreground.to_rgb_string(),
+            palette.background.to_rgb_string()
+        );
+        html.strip_prefix(&prefix)
+            .and_then(|html| html.strip_suffix("\n</pre>"))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            html(&[("<b>\"Tom\" & Jerry</b>", None)]),
+            "&lt;b&gt;&quot;Tom&quot; &amp; Jerry&lt;/b&gt;"
+        );
+        assert_eq!(
+            html(&[("x", Some("https://example.com/?a=1&b=\"><script>"))]),
+            "<a href=\"https://example.com/?a=1&amp;b=&quot;&gt;&lt;script&gt;\">x</a>"
+        );
+    }
+
+    #[test]
+    fn safe_links() {
+        for uri in [
+            "http://example.com/",
+            "https://example.com/",
+            "HTTPS://example.com/",
+            "file:///tmp/a.txt",
+            "mailto:someone@example.com",
+        ] {
+            assert_eq!(
+                html(&[("see ", None), ("here", Some(uri))]),
+                format!("see <a href=\"{uri}\">here</a>")
+            );
+        }
+    }
+
+    #[test]
+    fn unsafe_links() {
+        for uri in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            " javascript:alert(1)",
+            "data:text/html,<script>alert(1)</script>",
+            "vbscript:msgbox",
+            "no-scheme",
+        ] {
+            assert_eq!(html(&[("<here>", Some(uri))]), "&lt;here&gt;");
+        }
+    }
+}
@@ -22,6 +22,8 @@ pub struct ScreenInfo {
     pub scale: f64,
     pub max_fps: Option<usize>,
     pub effective_dpi: Option<f64>,
+    pub subpixel_order: String,
+    pub rotated: bool,
 }
 impl_lua_conversion_dynamic!(ScreenInfo);
 
@@ -48,6 +50,8 @@ impl From<window::screen::ScreenInfo> for ScreenInfo {
             scale: info.scale,
             max_fps: info.max_fps,
             effective_dpi: info.effective_dpi,
+            subpixel_order: format!("{:?}", info.subpixel_order),
+            rotated: info.rotated,
         }
     }
 }
@@ -0,0 +1,273 @@
+//! Decides when the output that has been parsed for a pane is
+//! applied to it.
+//!
+//! Applying a batch of output to a pane, and then rendering the pane,
+//! has a cost that is largely independent of the size of the batch.
+//! When a pane is producing output faster than it can be displayed,
+//! most of the intermediate states would never be seen, so rather than
+//! applying its output in many small batches, the output is accumulated
+//! for up to a frame's worth of time.  Output from a pane that isn't
+//! focused is accumulated for longer, so that it competes less with
+//! the focused pane.
+//!
+//! Output that arrives in isolated bursts, such as the echo of typed
+//! characters, is applied as soon as there is a short pause in it.
+use config::ConfigHandle;
+use metrics::histogram;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoalesceParams {
+    /// How long to wait for more output before applying
+    /// what has been accumulated
+    pub idle_delay: Duration,
+    /// The longest that output from a focused pane is held while
+    /// the pane is producing output continuously
+    pub max_latency: Duration,
+    /// The longest that output from other panes is held while
+    /// they are producing output continuously
+    pub background_max_latency: Duration,
+    /// How much output is accumulated before it is applied, when
+    /// the pane is not producing output continuously
+    pub batch_size: usize,
+    /// How much output is accumulated before it is applied, when
+    /// the pane is producing output continuously
+    pub max_batch_size: usize,
+}
+
+impl CoalesceParams {
+    pub fn from_config(config: &ConfigHandle) -> Self {
+        let idle_delay = Duration::from_millis(config.mux_output_parser_coalesce_delay_ms);
+        let batch_size = config.mux_output_parser_buffer_size;
+        Self {
+            idle_delay,
+            max_latency: Duration::from_millis(config.mux_output_max_latency_ms).max(idle_delay),
+            background_max_latency: Duration::from_millis(
+                config.mux_output_background_max_latency_ms,
+            )
+            .max(idle_delay),
+            batch_size,
+            max_batch_size: config.mux_output_max_batch_size.max(batch_size),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Apply the accumulated output now
+    Flush,
+    /// Wait up to `delay` for more output to arrive, then apply
+    /// the accumulated output if none did.
+    /// `idle` is true if that would be because the pane has paused
+    /// its output, rather than because a limit was reached.
+    Wait { delay: Duration, idle: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushReason {
+    /// No more output arrived within the idle delay
+    Idle,
+    /// The latency or size limit was reached
+    Limit,
+    /// The output was applied for some other reason, such as the
+    /// end of a synchronized update
+    Explicit,
+}
+
+pub struct OutputCoalescer {
+    params: CoalesceParams,
+    /// When the first of the output that is yet to be applied arrived
+    pending_since: Option<Instant>,
+    pending_bytes: usize,
+    /// Set while the pane is producing output continuously; that is,
+    /// the last batch was applied because it reached a limit rather
+    /// than because the output paused
+    saturated: bool,
+    /// Whether the pane had priority when the current batch began
+    prioritized: bool,
+}
+
+impl OutputCoalescer {
+    pub fn new(params: CoalesceParams) -> Self {
+        Self {
+            params,
+            pending_since: None,
+            pending_bytes: 0,
+            saturated: false,
+            prioritized: true,
+        }
+    }
+
+    pub fn set_params(&mut self, params: CoalesceParams) {
+        self.params = params;
+    }
+
+    pub fn is_saturated(&self) -> bool {
+        self.saturated
+    }
+
+    /// Record that `size` bytes of output have been parsed.
+    /// `prioritized` is called when this starts a new batch, to
+    /// determine whether the pane currently has priority.
+    pub fn add<F: FnOnce() -> bool>(&mut self, size: usize, now: Instant, prioritized: F) {
+        if self.pending_since.is_none() {
+            self.pending_since = Some(now);
+            self.prioritized = prioritized();
+        }
+        self.pending_bytes += size;
+    }
+
+    pub fn decide(&self, now: Instant) -> Decision {
+        let since = match self.pending_since {
+            Some(since) => since,
+            None => return Decision::Flush,
+        };
+        let (max_bytes, max_latency) = if !self.saturated {
+            (self.params.batch_size, self.params.idle_delay)
+        } else if self.prioritized {
+            (self.params.max_batch_size, self.params.max_latency)
+        } else {
+            (
+                self.params.max_batch_size,
+                self.params.background_max_latency,
+            )
+        };
+        if self.pending_bytes >= max_bytes {
+            return Decision::Flush;
+        }
+        let remaining = match max_latency.checked_sub(now.saturating_duration_since(since)) {
+            Some(remaining) if !remaining.is_zero() => remaining,
+            _ => return Decision::Flush,
+        };
+        if remaining > self.params.idle_delay {
+            Decision::Wait {
+                delay: self.params.idle_delay,
+                idle: true,
+            }
+        } else {
+            Decision::Wait {
+                delay: remaining,
+                idle: !self.saturated,
+            }
+        }
+    }
+
+    /// Record that the accumulated output has been applied
+    pub fn flushed(&mut self, reason: FlushReason, now: Instant) {
+        if let Some(since) = self.pending_since.take() {
+            histogram!("parse_buffered_data.batch.size").record(self.pending_bytes as f64);
+            histogram!("parse_buffered_data.batch.latency")
+                .record(now.saturating_duration_since(since));
+        }
+        self.pending_bytes = 0;
+        match reason {
+            FlushReason::Idle => self.saturated = false,
+            FlushReason::Limit => self.saturated = true,
+            FlushReason::Explicit => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn params() -> CoalesceParams {
+        CoalesceParams {
+            idle_delay: Duration::from_millis(3),
+            max_latency: Duration::from_millis(16),
+            background_max_latency: Duration::from_millis(100),
+            batch_size: 1000,
+            max_batch_size: 100_000,
+        }
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn bursts_are_applied_after_a_pause() {
+        let start = Instant::now();
+        let mut coalescer = OutputCoalescer::new(params());
+        coalescer.add(10, start, || true);
+        assert_eq!(
+            coalescer.decide(start),
+            Decision::Wait {
+                delay: ms(3),
+                idle: true
+            }
+        );
+        coalescer.add(10, start + ms(1), || unreachable!());
+        assert_eq!(
+            coalescer.decide(start + ms(1)),
+            Decision::Wait {
+                delay: ms(2),
+                idle: true
+            }
+        );
+        assert_eq!(coalescer.decide(start + ms(3)), Decision::Flush);
+
+        coalescer.flushed(FlushReason::Idle, start + ms(3));
+        assert!(!coalescer.is_saturated());
+    }
+
+    #[test]
+    fn continuous_output_is_held_for_a_frame() {
+        let start = Instant::now();
+        let mut coalescer = OutputCoalescer::new(params());
+        coalescer.add(1000, start, || true);
+        assert_eq!(coalescer.decide(start), Decision::Flush);
+        coalescer.flushed(FlushReason::Limit, start);
+        assert!(coalescer.is_saturated());
+
+        coalescer.add(1000, start, || true);
+        assert_eq!(
+            coalescer.decide(start + ms(2)),
+            Decision::Wait {
+                delay: ms(3),
+                idle: true
+            }
+        );
+        assert_eq!(
+            coalescer.decide(start + ms(14)),
+            Decision::Wait {
+                delay: ms(2),
+                idle: false
+            }
+        );
+        assert_eq!(coalescer.decide(start + ms(16)), Decision::Flush);
+
+        // The size of a batch is also limited
+        coalescer.flushed(FlushReason::Limit, start + ms(16));
+        coalescer.add(100_000, start + ms(16), || true);
+        assert_eq!(coalescer.decide(start + ms(16)), Decision::Flush);
+
+        // A pause ends the continuous output
+        coalescer.flushed(FlushReason::Idle, start + ms(20));
+        assert!(!coalescer.is_saturated());
+    }
+
+    #[test]
+    fn background_panes_are_held_longer() {
+        let start = Instant::now();
+        let mut coalescer = OutputCoalescer::new(params());
+        coalescer.add(1000, start, || false);
+        coalescer.flushed(FlushReason::Limit, start);
+
+        coalescer.add(1000, start, || false);
+        assert_eq!(
+            coalescer.decide(start + ms(50)),
+            Decision::Wait {
+                delay: ms(3),
+                idle: true
+            }
+        );
+        assert_eq!(coalescer.decide(start + ms(100)), Decision::Flush);
+
+        // An explicit flush doesn't change whether the output is
+        // continuous
+        coalescer.flushed(FlushReason::Explicit, start + ms(100));
+        assert!(coalescer.is_saturated());
+    }
+}
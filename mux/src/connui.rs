@@ -1,16 +1,18 @@
+use crate::pane::PaneId;
 use crate::termwiztermtab;
 use anyhow::{anyhow, bail, Context as _};
 use crossbeam::channel::{unbounded, Receiver, Sender};
 use finl_unicode::grapheme_clusters::Graphemes;
 use promise::spawn::block_on;
 use promise::Promise;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use termwiz::cell::{unicode_column_width, CellAttributes};
 use termwiz::lineedit::*;
 use termwiz::surface::{Change, Position};
 use termwiz::terminal::*;
-use wezterm_term::TerminalSize;
+use wezterm_term::{Alert, TerminalSize};
 
 #[derive(Default)]
 struct PasswordPromptHost {
@@ -52,6 +54,9 @@ pub enum UIRequest {
         duration: Duration,
         respond: Promise<()>,
     },
+    /// Raise an alert, such as a notification, for the pane
+    /// showing the UI
+    Alert(Alert),
     Close,
 }
 
@@ -72,6 +77,12 @@ impl ConnectionUIImpl {
             match self.rx.recv_timeout(Duration::from_millis(200)) {
                 Ok(UIRequest::Close) => return Ok(CloseStatus::Explicit),
                 Ok(UIRequest::Output(changes)) => self.term.render(&changes)?,
+                Ok(UIRequest::Alert(alert)) => {
+                    crate::Mux::notify_from_any_thread(crate::MuxNotification::Alert {
+                        pane_id: self.term.pane_id(),
+                        alert,
+                    });
+                }
                 Ok(UIRequest::Input {
                     prompt,
                     echo: true,
@@ -214,6 +225,9 @@ impl HeadlessImpl {
                 Ok(UIRequest::Output(changes)) => {
                     log::trace!("Output: {:?}", changes);
                 }
+                Ok(UIRequest::Alert(alert)) => {
+                    log::trace!("Alert: {:?}", alert);
+                }
                 Ok(UIRequest::Input { mut respond, .. }) => {
                     respond.result(Err(anyhow!("Input requested from headless context")));
                 }
@@ -332,6 +346,10 @@ impl ConnectionUI {
         self.tx.send(UIRequest::Output(changes)).ok();
     }
 
+    pub fn alert(&self, alert: Alert) {
+        self.tx.send(UIRequest::Alert(alert)).ok();
+    }
+
     pub fn output_str(&self, s: &str) {
         let s = s.replace("\n", "\r\n");
         self.output(vec![Change::Text(s)]);
@@ -452,3 +470,191 @@ pub fn show_configuration_error_message(err: &str) {
     wrapped.push_str("\n");
     ui.output_str(&wrapped);
 }
+
+/// A single input field of a `PanePrompt`
+#[derive(Debug, Clone)]
+pub struct PanePromptField {
+    pub label: String,
+    /// When false, the input should be masked
+    pub echo: bool,
+}
+
+/// A request for the frontend to collect input on behalf of a pane that
+/// is still connecting, such as an ssh host key confirmation.
+/// Prompts that are not claimed by a frontend in a timely fashion are
+/// handled inline in the pane by the requester instead.
+#[derive(Debug)]
+pub struct PanePrompt {
+    pub pane_id: PaneId,
+    pub title: String,
+    pub message: String,
+    /// When empty, this is a yes/no confirmation
+    pub fields: Vec<PanePromptField>,
+    claimed: AtomicBool,
+    reply: Mutex<Option<Sender<Vec<String>>>>,
+}
+
+impl PanePrompt {
+    /// Take responsibility for answering the prompt.
+    /// Returns false if it was already claimed.
+    pub fn claim(&self) -> bool {
+        !self.claimed.swap(true, Ordering::SeqCst)
+    }
+
+    /// Supply the answers to the fields, or an empty list
+    /// to accept a confirmation.  Dropping the prompt without
+    /// responding is equivalent to cancelling it.
+    pub fn respond(&self, answers: Vec<String>) {
+        if let Some(reply) = self.reply.lock().unwrap().take() {
+            reply.send(answers).ok();
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.reply.lock().unwrap().take();
+    }
+}
+
+/// How long to wait for a frontend to claim a `PanePrompt`
+const PANE_PROMPT_CLAIM_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Ask the frontend to show a prompt over `pane_id`, blocking until it
+/// has been answered.
+/// Returns `None` if no frontend took on the prompt, in which case the
+/// caller should prompt by some other means, otherwise returns the
+/// answers, which are `None` if the user cancelled.
+pub fn prompt_via_frontend(
+    pane_id: PaneId,
+    title: &str,
+    message: &str,
+    fields: Vec<PanePromptField>,
+) -> Option<Option<Vec<String>>> {
+    let mux = crate::Mux::try_get()?;
+    run_pane_prompt(
+        pane_id,
+        title,
+        message,
+        fields,
+        PANE_PROMPT_CLAIM_TIMEOUT,
+        |prompt| mux.notify(crate::MuxNotification::PanePrompt(prompt)),
+    )
+}
+
+/// Publishes a `PanePrompt` via `notify` and waits for it to be
+/// answered, giving up if it isn't claimed within `claim_timeout`.
+fn run_pane_prompt(
+    pane_id: PaneId,
+    title: &str,
+    message: &str,
+    fields: Vec<PanePromptField>,
+    claim_timeout: Duration,
+    notify: impl FnOnce(Arc<PanePrompt>),
+) -> Option<Option<Vec<String>>> {
+    let (tx, rx) = unbounded();
+    let prompt = Arc::new(PanePrompt {
+        pane_id,
+        title: title.to_string(),
+        message: message.to_string(),
+        fields,
+        claimed: AtomicBool::new(false),
+        reply: Mutex::new(Some(tx)),
+    });
+    notify(Arc::clone(&prompt));
+
+    let deadline = Instant::now() + claim_timeout;
+    while !prompt.claimed.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            if prompt.claim() {
+                // Nobody picked it up
+                return None;
+            }
+            break;
+        }
+        std::thread::sleep((deadline - now).min(Duration::from_millis(50)));
+    }
+
+    // Release our reference so that the reply channel closes if
+    // the frontend goes away without responding
+    drop(prompt);
+    Some(rx.recv().ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn prompt(
+        claim_timeout: Duration,
+        notify: impl FnOnce(Arc<PanePrompt>),
+    ) -> Option<Option<Vec<String>>> {
+        let fields = vec![PanePromptField {
+            label: "Password: ".to_string(),
+            echo: false,
+        }];
+        run_pane_prompt(1, "Title", "Message", fields, claim_timeout, notify)
+    }
+
+    #[test]
+    fn unclaimed_prompt_times_out() {
+        let (tx, rx) = unbounded();
+        let start = Instant::now();
+        let result = prompt(Duration::from_millis(100), |prompt| {
+            tx.send(prompt).unwrap();
+        });
+        assert_eq!(result, None);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // A frontend that gets to it late must not show it too
+        let prompt = rx.recv().unwrap();
+        assert_eq!(prompt.pane_id, 1);
+        assert!(!prompt.claim());
+    }
+
+    #[test]
+    fn claimed_prompt_returns_answers() {
+        let result = prompt(Duration::from_secs(10), |prompt| {
+            std::thread::spawn(move || {
+                assert!(prompt.claim());
+                assert!(!prompt.claim());
+                // Answering takes longer than the claim timeout
+                // would allow, had it not been claimed
+                std::thread::sleep(Duration::from_millis(100));
+                prompt.respond(vec!["hunter2".to_string()]);
+            });
+        });
+        assert_eq!(result, Some(Some(vec!["hunter2".to_string()])));
+    }
+
+    #[test]
+    fn claimed_prompt_waits_beyond_timeout() {
+        let result = prompt(Duration::from_millis(50), |prompt| {
+            assert!(prompt.claim());
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                prompt.respond(vec![]);
+            });
+        });
+        assert_eq!(result, Some(Some(vec![])));
+    }
+
+    #[test]
+    fn cancelled_prompt() {
+        let result = prompt(Duration::from_secs(10), |prompt| {
+            std::thread::spawn(move || {
+                assert!(prompt.claim());
+                prompt.cancel();
+            });
+        });
+        assert_eq!(result, Some(None));
+
+        // Dropping a claimed prompt without responding also cancels it
+        let result = prompt(Duration::from_secs(10), |prompt| {
+            std::thread::spawn(move || {
+                assert!(prompt.claim());
+                drop(prompt);
+            });
+        });
+        assert_eq!(result, Some(None));
+    }
+}
@@ -7,6 +7,7 @@
 
 use crate::localpane::LocalPane;
 use crate::pane::{alloc_pane_id, Pane, PaneId};
+use crate::paste::{PasteProgress, PasteQueue, SharedWriter};
 use crate::tab::{SplitRequest, Tab, TabId};
 use crate::window::WindowId;
 use crate::Mux;
@@ -33,6 +34,22 @@ pub enum DomainState {
     Attached,
 }
 
+/// Returns the argument vector of `command` in the form used
+/// by `Pane::get_spawn_command`
+pub(crate) fn spawn_command_argv(command: Option<&CommandBuilder>) -> Option<Vec<String>> {
+    let command = command?;
+    if command.is_default_prog() {
+        return None;
+    }
+    Some(
+        command
+            .get_argv()
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect(),
+    )
+}
+
 pub fn alloc_domain_id() -> DomainId {
     DOMAIN_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed)
 }
@@ -107,6 +124,14 @@ pub trait Domain: Downcast + Send + Sync {
                     .await?
             }
             SplitSource::MovePane(src_pane_id) => {
+                if src_pane_id == pane_id {
+                    anyhow::bail!("cannot move pane {} into a split of itself", pane_id);
+                }
+                // Check this before removing the pane from its tab,
+                // so that a failed split doesn't orphan it
+                if tab.get_zoomed_pane().is_some() {
+                    anyhow::bail!("cannot split while zoomed");
+                }
                 let (_domain, _window, src_tab) = mux
                     .resolve_pane_id(src_pane_id)
                     .ok_or_else(|| anyhow::anyhow!("pane {} not found", src_pane_id))?;
@@ -127,6 +152,17 @@ pub trait Domain: Downcast + Send + Sync {
             }
         };
 
+        // Removing a pane that was moved from this same tab
+        // shifts the index of the panes that follow it
+        let pane_index = match tab
+            .iter_panes_ignoring_zoom()
+            .iter()
+            .find(|p| p.pane.pane_id() == pane_id)
+        {
+            Some(p) => p.index,
+            None => anyhow::bail!("invalid pane id {}", pane_id),
+        };
+
         tab.split_and_insert(pane_index, split_request, Arc::clone(&pane))?;
         Ok(pane)
     }
@@ -186,6 +222,12 @@ pub trait Domain: Downcast + Send + Sync {
 
     /// Indicates the state of the domain
     fn state(&self) -> DomainState;
+
+    /// Returns the ssh session that is used to communicate with
+    /// the remote host, if the domain is currently connected via ssh
+    fn ssh_session(&self) -> Option<wezterm_ssh::Session> {
+        None
+    }
 }
 impl_downcast!(Domain);
 
@@ -481,26 +523,43 @@ impl LocalDomain {
 /// teach the Pane impl to reference the writer in the Termninal,
 /// but the Pane trait returns a RefMut and that makes it a bit
 /// awkward at the moment.
+/// Pastes are written via the wrapper in chunks; see `crate::paste`.
 #[derive(Clone)]
-pub(crate) struct WriterWrapper {
-    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+pub struct WriterWrapper {
+    writer: SharedWriter,
+    paste: PasteQueue,
 }
 
 impl WriterWrapper {
     pub fn new(writer: Box<dyn Write + Send>) -> Self {
         Self {
             writer: Arc::new(Mutex::new(writer)),
+            paste: PasteQueue::default(),
         }
     }
+
+    /// Write a paste that was encoded by `Terminal::encode_paste`
+    pub fn paste(&self, pane_id: PaneId, text: String, end: &'static str) {
+        self.paste
+            .paste(&self.writer, pane_id, text.into_bytes(), end.as_bytes());
+    }
+
+    pub fn paste_progress(&self) -> Option<PasteProgress> {
+        self.paste.progress()
+    }
+
+    pub fn cancel_paste(&self) -> bool {
+        self.paste.cancel()
+    }
 }
 
 impl std::io::Write for WriterWrapper {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.writer.lock().write(buf)
+        self.paste.write(&self.writer, buf)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        self.writer.lock().flush()
+        self.paste.flush(&self.writer)
     }
 }
 
@@ -582,6 +641,7 @@ impl Domain for LocalDomain {
         command_dir: Option<String>,
     ) -> anyhow::Result<Arc<dyn Pane>> {
         let pane_id = alloc_pane_id();
+        let spawn_command = spawn_command_argv(command.as_ref());
         let cmd = self
             .build_command(command, command_dir, pane_id)
             .await
@@ -623,9 +683,10 @@ impl Domain for LocalDomain {
                 terminal,
                 child,
                 pair.master,
-                Box::new(writer),
+                writer,
                 self.id,
                 command_description,
+                spawn_command,
             )),
             Err(err) => {
                 // Show the error to the user in the new pane
@@ -639,9 +700,10 @@ impl Domain for LocalDomain {
                     Box::new(FailedSpawnPty {
                         inner: Mutex::new(pair.master),
                     }),
-                    Box::new(writer),
+                    writer,
                     self.id,
                     command_description,
+                    spawn_command,
                 ))
             }
         };
@@ -0,0 +1,302 @@
+//! Commands spawned on behalf of `wezterm cli exec`.
+//! The command runs in a pane that is not part of any tab, so it
+//! isn't shown by the gui; the raw output read from its pty is
+//! buffered here until the client requests it, and the pane is
+//! removed once the client has collected its output and exit status.
+use crate::domain::DomainState;
+use crate::localpane::LocalPane;
+use crate::pane::PaneId;
+use crate::{Mux, MuxNotification};
+use anyhow::{anyhow, Context};
+use config::keyassignment::SpawnTabDomain;
+use portable_pty::{CommandBuilder, ExitStatus};
+use smol::channel::{bounded, Receiver, Sender};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use wezterm_term::TerminalSize;
+
+/// The largest chunk that will be returned by a single read
+pub const MAX_CHUNK_SIZE: usize = 256 * 1024;
+
+/// How much output to retain for a pane that is still being
+/// spawned by `spawn`, before it is known to be an exec pane
+const MAX_EARLY_OUTPUT: usize = 1024 * 1024;
+
+/// How much output to retain until the client collects it.
+/// Once that much is held, the pty of the pane isn't read until the
+/// client catches up, so that the command is held up rather than
+/// its output accumulating without limit.
+const MAX_OUTPUT: usize = 4 * MAX_CHUNK_SIZE;
+
+struct Session {
+    output: Vec<u8>,
+    eof: bool,
+    status: Option<ExitStatus>,
+    wake_tx: Sender<()>,
+    wake_rx: Receiver<()>,
+    /// Signals the pty reader that is waiting for the output to be
+    /// collected; the channel is closed when the session is removed
+    resume_tx: Sender<()>,
+    resume_rx: Receiver<()>,
+}
+
+impl Session {
+    fn new() -> Self {
+        let (wake_tx, wake_rx) = bounded(1);
+        let (resume_tx, resume_rx) = bounded(1);
+        Self {
+            output: vec![],
+            eof: false,
+            status: None,
+            wake_tx,
+            wake_rx,
+            resume_tx,
+            resume_rx,
+        }
+    }
+
+    fn wake(&self) {
+        self.wake_tx.try_send(()).ok();
+    }
+
+    fn resume(&self) {
+        self.resume_tx.try_send(()).ok();
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    sessions: HashMap<PaneId, Session>,
+    /// The number of calls to `spawn` that are in progress.
+    /// While that is non-zero, output from panes that are not
+    /// (yet) known to be exec panes is retained in `early`, because
+    /// the command may produce output before `spawn` is able to
+    /// register its session.
+    spawning: usize,
+    early: HashMap<PaneId, (Vec<u8>, bool)>,
+}
+
+impl Registry {
+    fn start_spawning(&mut self) {
+        self.spawning += 1;
+        ACTIVE.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn finish_spawning(&mut self) {
+        self.spawning -= 1;
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+        if self.spawning == 0 {
+            self.early.clear();
+        }
+    }
+
+    fn insert_session(&mut self, pane_id: PaneId, session: Session) {
+        if self.sessions.insert(pane_id, session).is_none() {
+            ACTIVE.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn remove_session(&mut self, pane_id: PaneId) -> Option<Session> {
+        let session = self.sessions.remove(&pane_id);
+        if session.is_some() {
+            ACTIVE.fetch_sub(1, Ordering::SeqCst);
+        }
+        session
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry::default());
+}
+
+/// The number of sessions plus the number of calls to `spawn` that
+/// are in progress; it is only changed while `REGISTRY` is locked.
+/// The pty reader of every pane consults this first so that it
+/// only takes the lock while there might be an exec pane.
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// The result of a call to `read`
+#[derive(Debug, Default)]
+pub struct ExecOutput {
+    pub data: Vec<u8>,
+    /// Set once all of the output has been read and the process
+    /// has terminated; no further reads are possible
+    pub status: Option<ExitStatus>,
+}
+
+/// Called by the pty reader with the data that it read from a pane.
+/// While the output of an exec pane that has yet to be collected
+/// exceeds `MAX_OUTPUT`, this blocks the reader.
+pub(crate) fn relay_output(pane_id: PaneId, data: &[u8]) {
+    if ACTIVE.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+    loop {
+        let resume_rx = {
+            let mut registry = REGISTRY.lock().unwrap();
+            if let Some(session) = registry.sessions.get_mut(&pane_id) {
+                if session.output.len() < MAX_OUTPUT {
+                    session.output.extend_from_slice(data);
+                    session.wake();
+                    return;
+                }
+                session.resume_rx.clone()
+            } else {
+                if registry.spawning > 0 {
+                    let (early, _eof) = registry.early.entry(pane_id).or_default();
+                    if early.len() < MAX_EARLY_OUTPUT {
+                        early.extend_from_slice(data);
+                    }
+                }
+                return;
+            }
+        };
+        // Fails once the session has been removed
+        smol::block_on(resume_rx.recv()).ok();
+    }
+}
+
+/// Called by the pty reader when there is no more output from a pane
+pub(crate) fn output_finished(pane_id: PaneId) {
+    if ACTIVE.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+    let mut registry = REGISTRY.lock().unwrap();
+    if let Some(session) = registry.sessions.get_mut(&pane_id) {
+        session.eof = true;
+        session.wake();
+    } else if registry.spawning > 0 {
+        registry.early.entry(pane_id).or_default().1 = true;
+    }
+}
+
+fn finish_spawning() {
+    REGISTRY.lock().unwrap().finish_spawning();
+}
+
+/// Spawn `command` in a pane that is not added to any tab.
+/// Only domains whose panes are read locally are supported; panes that
+/// are proxied from another mux don't provide access to their output.
+pub async fn spawn(
+    domain: &SpawnTabDomain,
+    command: Option<CommandBuilder>,
+    command_dir: Option<String>,
+    size: TerminalSize,
+) -> anyhow::Result<PaneId> {
+    let mux = Mux::get();
+    let domain = mux.resolve_spawn_tab_domain(None, domain)?;
+    if domain.state() == DomainState::Detached {
+        domain.attach(None).await?;
+    }
+
+    REGISTRY.lock().unwrap().start_spawning();
+    let pane = domain.spawn_pane(size, command, command_dir).await;
+    let pane = match pane {
+        Ok(pane) => pane,
+        Err(err) => {
+            finish_spawning();
+            return Err(err).context("spawn");
+        }
+    };
+    let pane_id = pane.pane_id();
+
+    if pane.downcast_ref::<LocalPane>().is_none() {
+        finish_spawning();
+        mux.remove_pane(pane_id);
+        anyhow::bail!(
+            "cannot exec in domain {}: its panes are proxied from another \
+             multiplexer, so their output isn't available",
+            domain.domain_name()
+        );
+    }
+
+    let status = mux.pane_exit_status(pane_id);
+    {
+        let mut registry = REGISTRY.lock().unwrap();
+        let mut session = Session::new();
+        if let Some((output, eof)) = registry.early.remove(&pane_id) {
+            session.output = output;
+            session.eof = eof;
+        }
+        session.status = status.clone();
+        registry.insert_session(pane_id, session);
+    }
+    finish_spawning();
+
+    if status.is_none() {
+        mux.subscribe(move |n| match n {
+            MuxNotification::PaneExited {
+                pane_id: id,
+                status,
+            } if id == pane_id => {
+                if let Some(session) = REGISTRY.lock().unwrap().sessions.get_mut(&pane_id) {
+                    session.status.replace(status);
+                    session.wake();
+                }
+                false
+            }
+            _ => REGISTRY.lock().unwrap().sessions.contains_key(&pane_id),
+        });
+        // The process may have terminated before we subscribed
+        if let Some(status) = mux.pane_exit_status(pane_id) {
+            if let Some(session) = REGISTRY.lock().unwrap().sessions.get_mut(&pane_id) {
+                session.status.replace(status);
+            }
+        }
+    }
+
+    Ok(pane_id)
+}
+
+/// Wait for output from the pane, returning up to `max_len` bytes
+/// of it. Once the output is exhausted and the process has terminated,
+/// its exit status is returned and the pane is removed.
+pub async fn read(pane_id: PaneId, max_len: usize) -> anyhow::Result<ExecOutput> {
+    loop {
+        let wake_rx = {
+            let mut registry = REGISTRY.lock().unwrap();
+            let session = registry
+                .sessions
+                .get_mut(&pane_id)
+                .ok_or_else(|| anyhow!("pane {pane_id} is not running a command via exec"))?;
+
+            if !session.output.is_empty() {
+                let len = session.output.len().min(max_len.min(MAX_CHUNK_SIZE)).max(1);
+                let data = session.output.drain(..len).collect();
+                session.resume();
+                return Ok(ExecOutput { data, status: None });
+            }
+
+            if session.eof && session.status.is_some() {
+                let status = session.status.take();
+                registry.remove_session(pane_id);
+                drop(registry);
+                remove_pane(pane_id);
+                return Ok(ExecOutput {
+                    data: vec![],
+                    status,
+                });
+            }
+
+            session.wake_rx.clone()
+        };
+        wake_rx.recv().await.ok();
+    }
+}
+
+/// Kill the command and forget about it, for example because
+/// the client that spawned it went away
+pub fn discard(pane_id: PaneId) {
+    if REGISTRY.lock().unwrap().remove_session(pane_id).is_some() {
+        remove_pane(pane_id);
+    }
+}
+
+fn remove_pane(pane_id: PaneId) {
+    promise::spawn::spawn_into_main_thread(async move {
+        let mux = Mux::get();
+        mux.remove_pane(pane_id);
+    })
+    .detach();
+}
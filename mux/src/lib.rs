@@ -1,4 +1,6 @@
 use crate::client::{ClientId, ClientInfo};
+use crate::coalesce::{CoalesceParams, Decision, FlushReason, OutputCoalescer};
+use crate::localpane::LocalPane;
 use crate::pane::{CachePolicy, Pane, PaneId};
 use crate::ssh_agent::AgentProxy;
 use crate::tab::{SplitRequest, Tab, TabId};
@@ -17,29 +19,34 @@ use parking_lot::{
 };
 use percent_encoding::percent_decode_str;
 use portable_pty::{CommandBuilder, ExitStatus, PtySize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryInto;
 use std::io::{Read, Write};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
 use std::thread;
 use std::time::{Duration, Instant};
-use termwiz::escape::csi::{DecPrivateMode, DecPrivateModeCode, Device, Mode};
-use termwiz::escape::{Action, CSI};
+use termwiz::escape::csi::Device;
+use termwiz::escape::{Action, ActionBatch, CSI};
 use thiserror::*;
-use wezterm_term::{Clipboard, ClipboardSelection, DownloadHandler, TerminalSize};
+use wezterm_term::{Clipboard, ClipboardQuery, ClipboardSelection, DownloadHandler, TerminalSize};
 #[cfg(windows)]
 use winapi::um::winsock2::{SOL_SOCKET, SO_RCVBUF, SO_SNDBUF};
 
 pub mod activity;
 pub mod client;
+pub mod coalesce;
 pub mod connui;
 pub mod domain;
+pub mod exec;
 pub mod localpane;
 pub mod pane;
+pub mod paste;
 pub mod renderable;
 pub mod ssh;
 pub mod ssh_agent;
+pub mod ssh_transfer;
+pub mod stats;
 pub mod tab;
 pub mod termwiztermtab;
 pub mod tmux;
@@ -71,6 +78,12 @@ pub enum MuxNotification {
         selection: ClipboardSelection,
         clipboard: Option<String>,
     },
+    /// An application in the pane queried the clipboard with OSC 52;
+    /// the frontend writes the reply to the pane
+    QueryClipboard {
+        pane_id: PaneId,
+        query: ClipboardQuery,
+    },
     SaveToDownloads {
         name: Option<String>,
         data: Arc<Vec<u8>>,
@@ -89,14 +102,32 @@ pub enum MuxNotification {
         window_id: WindowId,
         title: String,
     },
+    /// The title override of a pane was set or removed
+    PaneTitleChanged {
+        pane_id: PaneId,
+        title: Option<String>,
+    },
     WorkspaceRenamed {
         old_workspace: String,
         new_workspace: String,
     },
+    /// Requests that the frontend collect input for a pane
+    PanePrompt(Arc<connui::PanePrompt>),
+    /// The process running in a pane terminated
+    PaneExited {
+        pane_id: PaneId,
+        status: ExitStatus,
+    },
+    /// The progress of a large paste into a pane changed
+    PasteProgress(PaneId),
 }
 
 static SUB_ID: AtomicUsize = AtomicUsize::new(0);
 
+/// How many exit statuses to remember for panes whose
+/// process has terminated
+const MAX_EXIT_STATUSES: usize = 128;
+
 pub struct Mux {
     tabs: RwLock<HashMap<TabId, Arc<Tab>>>,
     panes: RwLock<HashMap<PaneId, Arc<dyn Pane>>>,
@@ -109,6 +140,7 @@ pub struct Mux {
     clients: RwLock<HashMap<ClientId, ClientInfo>>,
     identity: RwLock<Option<Arc<ClientId>>>,
     num_panes_by_workspace: RwLock<HashMap<String, usize>>,
+    exit_statuses: Mutex<VecDeque<(PaneId, ExitStatus)>>,
     main_thread_id: std::thread::ThreadId,
     agent: Option<AgentProxy>,
 }
@@ -117,11 +149,11 @@ const BUFSIZE: usize = 1024 * 1024;
 
 /// This function applies parsed actions to the pane and notifies any
 /// mux subscribers about the output event
-fn send_actions_to_mux(pane: &Weak<dyn Pane>, dead: &Arc<AtomicBool>, actions: Vec<Action>) {
+fn send_actions_to_mux(pane: &Weak<dyn Pane>, dead: &Arc<AtomicBool>, actions: &mut ActionBatch) {
     let start = Instant::now();
     match pane.upgrade() {
         Some(pane) => {
-            pane.perform_actions(actions);
+            pane.perform_action_batch(actions);
             histogram!("send_actions_to_mux.perform_actions.latency").record(start.elapsed());
             Mux::notify_from_any_thread(MuxNotification::PaneOutput(pane.pane_id()));
         }
@@ -130,21 +162,52 @@ fn send_actions_to_mux(pane: &Weak<dyn Pane>, dead: &Arc<AtomicBool>, actions: V
             // the mux, so signal that we should stop
             // trying to process it in read_from_pane_pty.
             dead.store(true, Ordering::Relaxed);
+            actions.clear();
         }
     }
     histogram!("send_actions_to_mux.rate").record(1.);
 }
 
 fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: FileDescriptor) {
-    let mut buf = vec![0; configuration().mux_output_parser_buffer_size];
+    let pane_id = match pane.upgrade() {
+        Some(pane) => pane.pane_id(),
+        None => return,
+    };
+    let config = configuration();
+    let mut buf = vec![0; config.mux_output_parser_buffer_size];
     let mut parser = termwiz::escape::parser::Parser::new();
-    let mut actions = vec![];
-    let mut hold = false;
-    let mut action_size = 0;
-    let mut delay = Duration::from_millis(configuration().mux_output_parser_coalesce_delay_ms);
-    let mut deadline = None;
+    parser.set_diagnostics(config.log_unknown_escape_sequences);
+    // The batch is reused for every read so that its buffers are
+    // only allocated once, rather than once per chunk of output
+    let mut actions = ActionBatch::new();
+    // Set to when the current synchronized update began, while its
+    // output is being held back
+    let mut hold: Option<Instant> = None;
+    let mut sync_timeout = Duration::from_millis(config.mux_synchronized_output_timeout_ms);
+    let mut coalescer = OutputCoalescer::new(CoalesceParams::from_config(&config));
+    let is_prioritized = || Mux::try_get().map_or(true, |mux| mux.is_pane_prioritized(pane_id));
 
     loop {
+        if let Some(since) = hold {
+            // Don't hold the output of an update that doesn't end in
+            // time, for example because the application was killed
+            // part way through it
+            let remaining = sync_timeout.saturating_sub(since.elapsed());
+            let mut pfd = [pollfd {
+                fd: rx.as_socket_descriptor(),
+                events: POLLIN,
+                revents: 0,
+            }];
+            if remaining.is_zero() || !matches!(poll(&mut pfd, Some(remaining)), Ok(1)) {
+                log::debug!("synchronized update of pane {} timed out", pane_id);
+                hold = None;
+                if !actions.is_empty() {
+                    send_actions_to_mux(&pane, &dead, &mut actions);
+                    coalescer.flushed(FlushReason::Explicit, Instant::now());
+                }
+            }
+        }
+
         match rx.read(&mut buf) {
             Ok(size) if size == 0 => {
                 dead.store(true, Ordering::Relaxed);
@@ -155,60 +218,65 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
                 break;
             }
             Ok(size) => {
-                parser.parse(&buf[0..size], |action| {
+                histogram!("parse_buffered_data.bytes.rate").record(size as f64);
+                if parser.is_tmux_control_mode() && parser.tmux_protocol_version().is_none() {
+                    // Once the tmux domain has detected the version, the
+                    // control mode parser can adapt to it
+                    if let Some(version) = Mux::try_get()
+                        .and_then(|mux| mux.get_pane(pane_id))
+                        .and_then(|pane| pane.downcast_ref::<LocalPane>()?.tmux_protocol_version())
+                    {
+                        parser.set_tmux_protocol_version(version);
+                    }
+                }
+                parser.parse_into_with(&buf[0..size], &mut actions, |action, actions| {
                     let mut flush = false;
-                    match &action {
-                        Action::CSI(CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
-                            DecPrivateModeCode::SynchronizedOutput,
-                        )))) => {
-                            hold = true;
+                    match (&action, action.synchronized_update()) {
+                        (_, Some(true)) => {
+                            hold = Some(Instant::now());
 
                             // Flush prior actions
                             if !actions.is_empty() {
-                                send_actions_to_mux(&pane, &dead, std::mem::take(&mut actions));
-                                action_size = 0;
+                                send_actions_to_mux(&pane, &dead, actions);
+                                coalescer.flushed(FlushReason::Explicit, Instant::now());
                             }
                         }
-                        Action::CSI(CSI::Mode(Mode::ResetDecPrivateMode(
-                            DecPrivateMode::Code(DecPrivateModeCode::SynchronizedOutput),
-                        ))) => {
-                            hold = false;
+                        (_, Some(false)) => {
+                            hold = None;
                             flush = true;
                         }
-                        Action::CSI(CSI::Device(dev)) if matches!(**dev, Device::SoftReset) => {
-                            hold = false;
+                        (Action::CSI(CSI::Device(dev)), _)
+                            if matches!(**dev, Device::SoftReset) =>
+                        {
+                            hold = None;
                             flush = true;
                         }
                         _ => {}
                     };
-                    action.append_to(&mut actions);
+                    actions.push(action);
 
                     if flush && !actions.is_empty() {
-                        send_actions_to_mux(&pane, &dead, std::mem::take(&mut actions));
-                        action_size = 0;
+                        send_actions_to_mux(&pane, &dead, actions);
+                        coalescer.flushed(FlushReason::Explicit, Instant::now());
                     }
                 });
-                action_size += size;
-                if !actions.is_empty() && !hold {
-                    // If we haven't accumulated too much data,
-                    // pause for a short while to increase the chances
-                    // that we coalesce a full "frame" from an unoptimized
-                    // TUI program
-                    if action_size < buf.len() {
-                        let poll_delay = match deadline {
-                            None => {
-                                deadline.replace(Instant::now() + delay);
-                                Some(delay)
-                            }
-                            Some(target) => target.checked_duration_since(Instant::now()),
-                        };
-                        if poll_delay.is_some() {
+                if !actions.is_empty() {
+                    coalescer.add(size, Instant::now(), is_prioritized);
+                }
+                if !actions.is_empty() && hold.is_none() {
+                    // Accumulate more output while the coalescer allows,
+                    // so that we don't apply states that would never be
+                    // seen, and so that we are more likely to apply a full
+                    // "frame" from an unoptimized TUI program at once
+                    let reason = match coalescer.decide(Instant::now()) {
+                        Decision::Flush => FlushReason::Limit,
+                        Decision::Wait { delay, idle } => {
                             let mut pfd = [pollfd {
                                 fd: rx.as_socket_descriptor(),
                                 events: POLLIN,
                                 revents: 0,
                             }];
-                            if let Ok(1) = poll(&mut pfd, poll_delay) {
+                            if let Ok(1) = poll(&mut pfd, Some(delay)) {
                                 // We can read now without blocking, so accumulate
                                 // more data into actions
                                 continue;
@@ -216,17 +284,22 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
 
                             // Not readable in time: let the data we have flow into
                             // the terminal model
+                            if idle {
+                                FlushReason::Idle
+                            } else {
+                                FlushReason::Limit
+                            }
                         }
-                    }
+                    };
 
-                    send_actions_to_mux(&pane, &dead, std::mem::take(&mut actions));
-                    deadline = None;
-                    action_size = 0;
+                    send_actions_to_mux(&pane, &dead, &mut actions);
+                    coalescer.flushed(reason, Instant::now());
                 }
 
                 let config = configuration();
                 buf.resize(config.mux_output_parser_buffer_size, 0);
-                delay = Duration::from_millis(config.mux_output_parser_coalesce_delay_ms);
+                coalescer.set_params(CoalesceParams::from_config(&config));
+                sync_timeout = Duration::from_millis(config.mux_synchronized_output_timeout_ms);
             }
         }
     }
@@ -236,7 +309,7 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
     // for very short lived commands so that we don't forget to
     // display what they displayed.
     if !actions.is_empty() {
-        send_actions_to_mux(&pane, &dead, std::mem::take(&mut actions));
+        send_actions_to_mux(&pane, &dead, &mut actions);
     }
 }
 
@@ -322,6 +395,7 @@ fn read_from_pane_pty(
             Ok(size) => {
                 histogram!("read_from_pane_pty.bytes.rate").record(size as f64);
                 log::trace!("read_pty pane {pane_id} read {size} bytes");
+                exec::relay_output(pane_id, &buf[..size]);
                 if let Err(err) = tx.write_all(&buf[..size]) {
                     error!(
                         "read_pty failed to write to parser: pane {} {:?}",
@@ -333,6 +407,8 @@ fn read_from_pane_pty(
         }
     }
 
+    exec::output_finished(pane_id);
+
     match exit_behavior.unwrap_or_else(|| configuration().exit_behavior) {
         ExitBehavior::Hold | ExitBehavior::CloseOnCleanExit => {
             // We don't know if we can unilaterally close
@@ -439,6 +515,7 @@ impl Mux {
             clients: RwLock::new(HashMap::new()),
             identity: RwLock::new(None),
             num_panes_by_workspace: RwLock::new(HashMap::new()),
+            exit_statuses: Mutex::new(VecDeque::new()),
             main_thread_id: std::thread::current().id(),
             agent,
         }
@@ -495,6 +572,22 @@ impl Mux {
         }
     }
 
+    /// Returns true if output from the pane should be given priority,
+    /// which is the case if it is the focused pane of any client, or if
+    /// no client has focused a pane
+    pub fn is_pane_prioritized(&self, pane_id: PaneId) -> bool {
+        let mut any_focused = false;
+        for info in self.clients.read().values() {
+            if let Some(focused) = info.focused_pane_id {
+                if focused == pane_id {
+                    return true;
+                }
+                any_focused = true;
+            }
+        }
+        !any_focused
+    }
+
     pub fn resolve_focused_pane(
         &self,
         client_id: &ClientId,
@@ -760,6 +853,82 @@ impl Mux {
         self.panes.read().get(&pane_id).map(Arc::clone)
     }
 
+    /// Set or remove the title override of the specified pane,
+    /// and notify any subscribers.
+    /// An empty title removes the override.
+    pub fn set_pane_title(&self, pane_id: PaneId, title: Option<String>) -> anyhow::Result<()> {
+        let pane = self
+            .get_pane(pane_id)
+            .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+        let title = title.filter(|title| !title.is_empty());
+        pane.set_title_override(title.clone());
+        self.notify(MuxNotification::PaneTitleChanged { pane_id, title });
+        Ok(())
+    }
+
+    /// Record the exit status of the process that was running in
+    /// the specified pane, and notify any subscribers.
+    /// May be called from any thread.
+    pub fn record_pane_exit(&self, pane_id: PaneId, status: ExitStatus) {
+        {
+            let mut statuses = self.exit_statuses.lock();
+            if statuses.len() >= MAX_EXIT_STATUSES {
+                statuses.pop_front();
+            }
+            statuses.push_back((pane_id, status.clone()));
+        }
+        Self::notify_from_any_thread(MuxNotification::PaneExited { pane_id, status });
+    }
+
+    /// Returns the exit status of the process that was running in
+    /// the specified pane, if it has terminated recently
+    pub fn pane_exit_status(&self, pane_id: PaneId) -> Option<ExitStatus> {
+        self.exit_statuses
+            .lock()
+            .iter()
+            .find(|(id, _)| *id == pane_id)
+            .map(|(_, status)| status.clone())
+    }
+
+    /// Wait for the process running in the specified pane to terminate,
+    /// and return its exit status.
+    /// Resolves to `None` if the status cannot be determined, which
+    /// is the case for panes that are proxied from another mux.
+    pub async fn wait_for_pane_exit(&self, pane_id: PaneId) -> anyhow::Result<Option<ExitStatus>> {
+        if let Some(status) = self.pane_exit_status(pane_id) {
+            return Ok(Some(status));
+        }
+        let is_local = self
+            .get_pane(pane_id)
+            .ok_or_else(|| anyhow!("pane {pane_id} not found"))?
+            .downcast_ref::<LocalPane>()
+            .is_some();
+
+        let (tx, rx) = smol::channel::bounded(1);
+        self.subscribe(move |n| match n {
+            MuxNotification::PaneExited {
+                pane_id: id,
+                status,
+            } if id == pane_id => {
+                tx.try_send(Some(status)).ok();
+                false
+            }
+            // We won't hear about the exit status of a remote process,
+            // so its pane going away is as good as it gets
+            MuxNotification::PaneRemoved(id) if id == pane_id && !is_local => {
+                tx.try_send(None).ok();
+                false
+            }
+            _ => !tx.is_closed(),
+        });
+
+        // The process may have terminated before we subscribed
+        if let Some(status) = self.pane_exit_status(pane_id) {
+            return Ok(Some(status));
+        }
+        Ok(rx.recv().await?)
+    }
+
     pub fn get_tab(&self, tab_id: TabId) -> Option<Arc<Tab>> {
         self.tabs.read().get(&tab_id).map(Arc::clone)
     }
@@ -1136,7 +1305,7 @@ impl Mux {
         Ok(domain)
     }
 
-    fn resolve_cwd(
+    pub(crate) fn resolve_cwd(
         &self,
         command_dir: Option<String>,
         pane: Option<Arc<dyn Pane>>,
@@ -1289,6 +1458,67 @@ impl Mux {
         Ok((tab, window_id))
     }
 
+    /// Move a tab out of its current window and into `window_id`,
+    /// or, if `window_id` is None, into a new window in `workspace`.
+    /// The new window defaults to the workspace of the current window.
+    /// Returns the id of the window that now contains the tab.
+    pub fn move_tab_to_window(
+        &self,
+        tab_id: TabId,
+        window_id: Option<WindowId>,
+        workspace: Option<String>,
+    ) -> anyhow::Result<WindowId> {
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("tab {} not found", tab_id))?;
+        let src_window_id = self
+            .window_containing_tab(tab_id)
+            .ok_or_else(|| anyhow!("tab {} is not in a window", tab_id))?;
+        if window_id == Some(src_window_id) {
+            anyhow::bail!("tab {} is already in window {}", tab_id, src_window_id);
+        }
+
+        let window_builder;
+        let window_id = match window_id {
+            Some(window_id) => {
+                let size = self
+                    .get_window(window_id)
+                    .ok_or_else(|| anyhow!("window {} not found", window_id))?
+                    .get_active()
+                    .map(|active| active.get_size());
+                if let Some(size) = size {
+                    tab.resize(size);
+                }
+                window_id
+            }
+            None => {
+                let workspace = match workspace {
+                    Some(workspace) => workspace,
+                    None => self
+                        .get_window(src_window_id)
+                        .map(|window| window.get_workspace().to_string())
+                        .unwrap_or_else(|| self.active_workspace()),
+                };
+                window_builder = self.new_empty_window(Some(workspace), None);
+                *window_builder
+            }
+        };
+
+        let src_is_empty = match self.get_window_mut(src_window_id) {
+            Some(mut window) => {
+                window.remove_by_id(tab_id);
+                window.is_empty()
+            }
+            None => false,
+        };
+        self.add_tab_to_window(&tab, window_id)?;
+        if src_is_empty {
+            self.kill_window(src_window_id);
+        }
+
+        Ok(window_id)
+    }
+
     pub async fn spawn_tab_or_window(
         &self,
         window_id: Option<WindowId>,
@@ -1435,6 +1665,17 @@ impl Clipboard for MuxClipboard {
         });
         Ok(())
     }
+
+    fn query_contents(&self, query: ClipboardQuery) -> anyhow::Result<Option<String>> {
+        // The frontend can only read the clipboard asynchronously
+        let mux = Mux::try_get()
+            .ok_or_else(|| anyhow::anyhow!("MuxClipboard::query_contents: no Mux?"))?;
+        mux.notify(MuxNotification::QueryClipboard {
+            pane_id: self.pane_id,
+            query,
+        });
+        Ok(None)
+    }
 }
 
 struct MuxDownloader {}
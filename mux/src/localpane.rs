@@ -1,8 +1,9 @@
-use crate::domain::DomainId;
+use crate::domain::{DomainId, WriterWrapper};
 use crate::pane::{
     CachePolicy, CloseReason, ForEachPaneLogicalLine, LogicalLine, Pane, PaneId, Pattern,
     SearchResult, WithPaneLines,
 };
+use crate::paste::PasteProgress;
 use crate::renderable::*;
 use crate::tmux::{TmuxDomain, TmuxDomainState};
 use crate::{Domain, Mux, MuxNotification};
@@ -19,7 +20,7 @@ use smol::channel::{bounded, Receiver, TryRecvError};
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
-use std::io::{Result as IoResult, Write};
+use std::io::Result as IoResult;
 use std::ops::Range;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -27,6 +28,7 @@ use termwiz::escape::csi::{Sgr, CSI};
 use termwiz::escape::{Action, DeviceControlMode};
 use termwiz::input::KeyboardEncoding;
 use termwiz::surface::{Line, SequenceNo};
+use termwiz::tmux_cc::ProtocolVersion;
 use url::Url;
 use wezterm_dynamic::Value;
 use wezterm_term::color::ColorPalette;
@@ -126,13 +128,15 @@ pub struct LocalPane {
     terminal: Mutex<Terminal>,
     process: Mutex<ProcessState>,
     pty: Mutex<Box<dyn MasterPty>>,
-    writer: Mutex<Box<dyn Write + Send>>,
+    writer: Mutex<WriterWrapper>,
     domain_id: DomainId,
     tmux_domain: Mutex<Option<Arc<TmuxDomainState>>>,
     proc_list: Mutex<Option<CachedProcInfo>>,
     #[cfg(unix)]
     leader: Arc<Mutex<Option<CachedLeaderInfo>>>,
     command_description: String,
+    spawn_command: Option<Vec<String>>,
+    title_override: Mutex<Option<String>>,
 }
 
 #[async_trait(?Send)]
@@ -230,8 +234,24 @@ impl Pane for LocalPane {
             .unwrap_or(false);
         let is_failed_spawn = pty.is::<crate::domain::FailedSpawnPty>();
 
+        // If we lost the connection to an ssh host, hold the pane
+        // so that the ssh domain can offer to respawn it
+        let is_ssh_connection_lost = || {
+            Mux::try_get()
+                .and_then(|mux| mux.get_domain(self.domain_id))
+                .map(|domain| {
+                    domain
+                        .downcast_ref::<crate::ssh::RemoteSshDomain>()
+                        .map(|ssh| ssh.connection_was_lost())
+                        .unwrap_or(false)
+                })
+                .unwrap_or(false)
+        };
+
         if is_ssh_connecting || is_failed_spawn {
             Some(ExitBehavior::CloseOnCleanExit)
+        } else if is_ssh_connection_lost() {
+            Some(ExitBehavior::Hold)
         } else {
             None
         }
@@ -387,6 +407,10 @@ impl Pane for LocalPane {
         self.terminal.lock().perform_actions(actions)
     }
 
+    fn perform_action_batch(&self, batch: &mut termwiz::escape::ActionBatch) {
+        self.terminal.lock().perform_action_batch(batch)
+    }
+
     fn mouse_event(&self, event: MouseEvent) -> Result<(), Error> {
         Mux::get().record_input_for_current_identity();
         self.terminal.lock().mouse_event(event)
@@ -435,14 +459,26 @@ impl Pane for LocalPane {
 
     fn send_paste(&self, text: &str) -> Result<(), Error> {
         Mux::get().record_input_for_current_identity();
-        if self.tmux_domain.lock().is_some() {
-            Ok(())
-        } else {
-            self.terminal.lock().send_paste(text)
+        if self.tmux_domain.lock().is_none() {
+            let (text, end) = self.terminal.lock().encode_paste(text);
+            self.writer.lock().paste(self.pane_id, text, end);
         }
+        Ok(())
+    }
+
+    fn paste_progress(&self) -> Option<PasteProgress> {
+        self.writer.lock().paste_progress()
+    }
+
+    fn cancel_paste(&self) -> bool {
+        self.writer.lock().cancel_paste()
     }
 
     fn get_title(&self) -> String {
+        if let Some(title) = self.title_override.lock().as_ref() {
+            return title.to_string();
+        }
+
         let title = self.terminal.lock().get_title().to_string();
         // If the title is the default pane title, then try to spice
         // things up a bit by returning the process basename instead
@@ -458,6 +494,10 @@ impl Pane for LocalPane {
         title
     }
 
+    fn set_title_override(&self, title: Option<String>) {
+        *self.title_override.lock() = title;
+    }
+
     fn palette(&self) -> ColorPalette {
         self.terminal.lock().palette()
     }
@@ -509,6 +549,10 @@ impl Pane for LocalPane {
             .or_else(|| self.divine_current_working_dir(policy))
     }
 
+    fn get_spawn_command(&self) -> Option<Vec<String>> {
+        self.spawn_command.clone()
+    }
+
     fn tty_name(&self) -> Option<String> {
         #[cfg(unix)]
         {
@@ -522,6 +566,10 @@ impl Pane for LocalPane {
         }
     }
 
+    fn estimated_memory_bytes(&self) -> Option<usize> {
+        Some(self.terminal.lock().estimated_screen_bytes())
+    }
+
     fn get_foreground_process_info(&self, policy: CachePolicy) -> Option<LocalProcessInfo> {
         #[cfg(unix)]
         if let Some(pid) = self.pty.lock().process_group_leader() {
@@ -951,7 +999,10 @@ impl AlertHandler for LocalPaneNotifHandler {
 /// to terminate, and then nudge the muxer to check for dead processes.
 /// Without this, typing `exit` in `cmd.exe` would keep the pane around
 /// until something else triggered the mux to prune dead processes.
+/// The exit status is also recorded with the mux, so that it can be
+/// reported by `wezterm cli wait-for-exit` even if the pane is closed.
 fn split_child(
+    pane_id: PaneId,
     mut process: Box<dyn Child>,
 ) -> (
     Receiver<IoResult<ExitStatus>>,
@@ -965,6 +1016,14 @@ fn split_child(
 
     std::thread::spawn(move || {
         let status = process.wait();
+        // Record this before the pane can be pruned
+        if let Some(mux) = Mux::try_get() {
+            let exit_status = match &status {
+                Ok(status) => status.clone(),
+                Err(_) => ExitStatus::with_exit_code(1),
+            };
+            mux.record_pane_exit(pane_id, exit_status);
+        }
         tx.try_send(status).ok();
         promise::spawn::spawn_into_main_thread(async move {
             let mux = Mux::get();
@@ -982,11 +1041,12 @@ impl LocalPane {
         mut terminal: Terminal,
         process: Box<dyn Child + Send>,
         pty: Box<dyn MasterPty>,
-        writer: Box<dyn Write + Send>,
+        writer: WriterWrapper,
         domain_id: DomainId,
         command_description: String,
+        spawn_command: Option<Vec<String>>,
     ) -> Self {
-        let (process, signaller, pid) = split_child(process);
+        let (process, signaller, pid) = split_child(pane_id, process);
 
         terminal.set_device_control_handler(Box::new(LocalPaneDCSHandler {
             pane_id,
@@ -1011,9 +1071,20 @@ impl LocalPane {
             #[cfg(unix)]
             leader: Arc::new(Mutex::new(None)),
             command_description,
+            spawn_command,
+            title_override: Mutex::new(None),
         }
     }
 
+    /// The version of tmux that was detected by the tmux domain
+    /// attached to this pane, if any
+    pub(crate) fn tmux_protocol_version(&self) -> Option<ProtocolVersion> {
+        self.tmux_domain
+            .lock()
+            .as_ref()
+            .and_then(|tmux| *tmux.version.lock())
+    }
+
     #[cfg(unix)]
     fn get_leader(&self, policy: CachePolicy) -> CachedLeaderInfo {
         let mut leader = self.leader.lock();
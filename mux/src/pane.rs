@@ -1,4 +1,5 @@
 use crate::domain::DomainId;
+use crate::paste::PasteProgress;
 use crate::renderable::*;
 use crate::ExitBehavior;
 use async_trait::async_trait;
@@ -10,7 +11,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ops::Range;
 use std::sync::Arc;
-use termwiz::hyperlink::Rule;
+use termwiz::hyperlink::{Rule, RuleSet};
 use termwiz::input::KeyboardEncoding;
 use termwiz::surface::{Line, SequenceNo};
 use url::Url;
@@ -210,21 +211,28 @@ pub trait Pane: Downcast + Send + Sync {
     fn get_logical_lines(&self, lines: Range<StableRowIndex>) -> Vec<LogicalLine>;
 
     fn apply_hyperlinks(&self, lines: Range<StableRowIndex>, rules: &[Rule]) {
-        struct ApplyHyperLinks<'a> {
-            rules: &'a [Rule],
+        if rules.is_empty() {
+            return;
         }
-        impl<'a> ForEachPaneLogicalLine for ApplyHyperLinks<'a> {
+
+        struct ApplyHyperLinks {
+            rules: Arc<RuleSet>,
+        }
+        impl ForEachPaneLogicalLine for ApplyHyperLinks {
             fn with_logical_line_mut(
                 &mut self,
                 _: Range<StableRowIndex>,
                 lines: &mut [&mut Line],
             ) -> bool {
-                Line::apply_hyperlink_rules(self.rules, lines);
+                Line::apply_hyperlink_rule_set(&self.rules, lines);
 
                 true
             }
         }
 
+        // Lines that were already scanned with these same rules are
+        // skipped, so only newly output or modified lines get matched
+        let rules = RuleSet::cached(rules);
         self.for_each_logical_line_in_stable_range_mut(lines, &mut ApplyHyperLinks { rules });
     }
 
@@ -232,7 +240,22 @@ pub trait Pane: Downcast + Send + Sync {
     fn get_dimensions(&self) -> RenderableDimensions;
 
     fn get_title(&self) -> String;
+    /// Set a title that takes precedence over the title that the
+    /// program running in the pane sets via escape sequences.
+    /// Passing `None` removes the override.
+    /// Use `Mux::set_pane_title` so that clients are notified.
+    fn set_title_override(&self, _title: Option<String>) {}
     fn send_paste(&self, text: &str) -> anyhow::Result<()>;
+    /// Returns the progress of a large paste that is still being
+    /// written to the pane
+    fn paste_progress(&self) -> Option<PasteProgress> {
+        None
+    }
+    /// Abandon any pastes that are still being written to the pane.
+    /// Returns true if there were any.
+    fn cancel_paste(&self) -> bool {
+        false
+    }
     fn reader(&self) -> anyhow::Result<Option<Box<dyn std::io::Read + Send>>>;
     fn writer(&self) -> MappedMutexGuard<dyn std::io::Write>;
     fn resize(&self, size: TerminalSize) -> anyhow::Result<()>;
@@ -246,6 +269,12 @@ pub trait Pane: Downcast + Send + Sync {
     }
     fn mouse_event(&self, event: MouseEvent) -> anyhow::Result<()>;
     fn perform_actions(&self, _actions: Vec<termwiz::escape::Action>) {}
+    /// Perform the actions held by `batch`, leaving it empty.
+    /// Panes that can apply the borrowed text directly should
+    /// override this to avoid converting it to owned `Action`s.
+    fn perform_action_batch(&self, batch: &mut termwiz::escape::ActionBatch) {
+        self.perform_actions(batch.take_actions())
+    }
     fn is_dead(&self) -> bool;
     fn kill(&self) {}
     fn palette(&self) -> ColorPalette;
@@ -330,6 +359,18 @@ pub trait Pane: Downcast + Send + Sync {
         None
     }
 
+    /// Returns an estimate of the memory used by the screen and
+    /// scrollback of the pane, if it holds them locally
+    fn estimated_memory_bytes(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the argument vector that was requested when the pane
+    /// was spawned, or None if it is running the default program
+    fn get_spawn_command(&self) -> Option<Vec<String>> {
+        None
+    }
+
     fn exit_behavior(&self) -> Option<ExitBehavior> {
         None
     }
@@ -0,0 +1,298 @@
+//! Writes pastes to a pty in chunks.
+//!
+//! A large paste can take a long time to write: the pty only buffers
+//! a few KiB, so once that fills up each write waits until the program
+//! in the pane has read some of its input.  Rather than blocking the
+//! caller for the duration, the paste is queued up and written one
+//! chunk at a time by a background thread, and its pace is set by how
+//! quickly the pty accepts each chunk.
+//!
+//! Any other input that is written while a paste is in flight, such as
+//! keys that are pressed or responses to queries from the program, is
+//! queued up behind it so that it cannot land in the middle of the
+//! pasted text, and in particular inside of a bracketed paste.
+use crate::pane::PaneId;
+use crate::{Mux, MuxNotification};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Arc;
+
+/// The most that is written to the pty at once
+const PASTE_CHUNK_SIZE: usize = 4096;
+
+/// Pastes that are at least this large report their progress
+const PROGRESS_THRESHOLD: usize = 256 * 1024;
+
+pub(crate) type SharedWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// How much of a large paste has been written to the pty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasteProgress {
+    /// The number of bytes written so far
+    pub written: usize,
+    /// The size of the paste in bytes
+    pub total: usize,
+}
+
+impl PasteProgress {
+    pub fn percent(&self) -> u8 {
+        if self.total == 0 {
+            100
+        } else {
+            (self.written.min(self.total) * 100 / self.total) as u8
+        }
+    }
+}
+
+enum Pending {
+    Paste {
+        pane_id: PaneId,
+        data: Vec<u8>,
+        written: usize,
+        /// Written once the data has been written, even if the paste
+        /// is cancelled, so that a bracketed paste is always closed
+        end: &'static [u8],
+    },
+    Input(Vec<u8>),
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<Pending>,
+    /// True while a thread is working through `pending`
+    busy: bool,
+}
+
+/// Input that is waiting to be written to the pty
+#[derive(Clone, Default)]
+pub(crate) struct PasteQueue {
+    state: Arc<Mutex<QueueState>>,
+}
+
+impl PasteQueue {
+    /// Write `buf` to `writer`, unless a paste is in flight, in which
+    /// case it is queued up to be written after the paste
+    pub fn write(&self, writer: &SharedWriter, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = self.state.lock();
+        if !state.busy {
+            return writer.lock().write(buf);
+        }
+        match state.pending.back_mut() {
+            Some(Pending::Input(input)) => input.extend_from_slice(buf),
+            _ => state.pending.push_back(Pending::Input(buf.to_vec())),
+        }
+        Ok(buf.len())
+    }
+
+    pub fn flush(&self, writer: &SharedWriter) -> std::io::Result<()> {
+        let state = self.state.lock();
+        if state.busy {
+            // The paste thread will flush once it has written everything
+            Ok(())
+        } else {
+            writer.lock().flush()
+        }
+    }
+
+    /// Write `data` followed by `end` to `writer`.
+    /// Small pastes are written immediately; larger ones are written
+    /// by a background thread.
+    pub fn paste(&self, writer: &SharedWriter, pane_id: PaneId, data: Vec<u8>, end: &'static [u8]) {
+        let mut state = self.state.lock();
+        if !state.busy && data.len() + end.len() <= PASTE_CHUNK_SIZE {
+            let mut writer = writer.lock();
+            if let Err(err) = writer
+                .write_all(&data)
+                .and_then(|_| writer.write_all(end))
+                .and_then(|_| writer.flush())
+            {
+                log::error!("error writing paste to pane {pane_id}: {err:#}");
+            }
+            return;
+        }
+
+        state.pending.push_back(Pending::Paste {
+            pane_id,
+            data,
+            written: 0,
+            end,
+        });
+        if state.busy {
+            return;
+        }
+        state.busy = true;
+        drop(state);
+
+        let queue = self.clone();
+        let writer = Arc::clone(writer);
+        if let Err(err) = std::thread::Builder::new()
+            .name(format!("paste to pane {pane_id}"))
+            .spawn(move || queue.run(&writer))
+        {
+            log::error!("failed to spawn paste thread, abandoning paste: {err:#}");
+            let mut state = self.state.lock();
+            state.pending.clear();
+            state.busy = false;
+        }
+    }
+
+    /// Returns the progress of the paste that is being written,
+    /// if it is large enough to be worth reporting
+    pub fn progress(&self) -> Option<PasteProgress> {
+        match self.state.lock().pending.front() {
+            Some(Pending::Paste { data, written, .. }) if data.len() >= PROGRESS_THRESHOLD => {
+                Some(PasteProgress {
+                    written: *written,
+                    total: data.len(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Abandon the pastes that have not been written yet.
+    /// A paste that is part of the way through is cut short, but
+    /// still has its `end` written.
+    /// Returns true if there were any pastes to cancel.
+    pub fn cancel(&self) -> bool {
+        let mut state = self.state.lock();
+        let mut cancelled = false;
+        state.pending.retain_mut(|pending| match pending {
+            Pending::Paste { data, written, .. } => {
+                cancelled = true;
+                data.truncate(*written);
+                *written > 0
+            }
+            Pending::Input(_) => true,
+        });
+        cancelled
+    }
+
+    /// Remove the next chunk of data to write from the queue.
+    /// Returns the chunk along with the pane whose paste progress
+    /// should be reported once it has been written.
+    fn next_chunk(&self) -> Option<(Vec<u8>, Option<PaneId>)> {
+        let mut state = self.state.lock();
+        match state.pending.pop_front()? {
+            Pending::Input(input) => Some((input, None)),
+            Pending::Paste {
+                pane_id,
+                data,
+                written,
+                end,
+            } => {
+                let report = (data.len() >= PROGRESS_THRESHOLD).then_some(pane_id);
+                if written == data.len() {
+                    return Some((end.to_vec(), report));
+                }
+                let len = (data.len() - written).min(PASTE_CHUNK_SIZE);
+                let chunk = data[written..written + len].to_vec();
+                state.pending.push_front(Pending::Paste {
+                    pane_id,
+                    data,
+                    written: written + len,
+                    end,
+                });
+                Some((chunk, report))
+            }
+        }
+    }
+
+    fn run(&self, writer: &SharedWriter) {
+        let mut last_percent = None;
+        loop {
+            let (chunk, report) = match self.next_chunk() {
+                Some(next) => next,
+                None => {
+                    let mut state = self.state.lock();
+                    // More may have been queued since next_chunk returned
+                    if state.pending.is_empty() {
+                        if let Err(err) = writer.lock().flush() {
+                            log::error!("error flushing paste: {err:#}");
+                        }
+                        state.busy = false;
+                        return;
+                    }
+                    continue;
+                }
+            };
+
+            if let Err(err) = writer.lock().write_all(&chunk) {
+                log::error!("error writing paste, abandoning it: {err:#}");
+                let mut state = self.state.lock();
+                state.pending.clear();
+                state.busy = false;
+                return;
+            }
+
+            if let Some(pane_id) = report {
+                let percent = self.progress().map(|p| p.percent());
+                if percent != last_percent {
+                    last_percent = percent;
+                    Mux::notify_from_any_thread(MuxNotification::PasteProgress(pane_id));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn drain(queue: &PasteQueue) -> Vec<u8> {
+        let mut written = vec![];
+        while let Some((chunk, _)) = queue.next_chunk() {
+            written.extend_from_slice(&chunk);
+        }
+        written
+    }
+
+    #[test]
+    fn input_is_queued_behind_paste() {
+        let writer: SharedWriter = Arc::new(Mutex::new(Box::new(Vec::new())));
+        let queue = PasteQueue::default();
+        queue.state.lock().busy = true;
+
+        let data = vec![b'x'; PASTE_CHUNK_SIZE * 2 + 1];
+        queue.paste(&writer, 1, data.clone(), b"]");
+        queue.write(&writer, b"key").unwrap();
+        queue.paste(&writer, 1, b"more".to_vec(), b"]");
+
+        let mut expected = data;
+        expected.extend_from_slice(b"]keymore]");
+        assert_eq!(drain(&queue), expected);
+    }
+
+    #[test]
+    fn cancel_closes_paste() {
+        let writer: SharedWriter = Arc::new(Mutex::new(Box::new(Vec::new())));
+        let queue = PasteQueue::default();
+        queue.state.lock().busy = true;
+
+        let data = vec![b'x'; PROGRESS_THRESHOLD];
+        queue.paste(&writer, 1, data, b"]");
+        queue.write(&writer, b"key").unwrap();
+        queue.paste(&writer, 1, b"never sent".to_vec(), b"]");
+
+        let (chunk, report) = queue.next_chunk().unwrap();
+        assert_eq!(report, Some(1));
+        assert_eq!(
+            queue.progress(),
+            Some(PasteProgress {
+                written: PASTE_CHUNK_SIZE,
+                total: PROGRESS_THRESHOLD,
+            })
+        );
+
+        assert!(queue.cancel());
+        let mut written = chunk;
+        written.extend_from_slice(&drain(&queue));
+        let mut expected = vec![b'x'; PASTE_CHUNK_SIZE];
+        expected.extend_from_slice(b"]key");
+        assert_eq!(written, expected);
+        assert_eq!(queue.progress(), None);
+        assert!(!queue.cancel());
+    }
+}
@@ -1,7 +1,7 @@
-use crate::connui::ConnectionUI;
+use crate::connui::{prompt_via_frontend, ConnectionUI, PanePromptField};
 use crate::domain::{alloc_domain_id, Domain, DomainId, DomainState, WriterWrapper};
 use crate::localpane::LocalPane;
-use crate::pane::{alloc_pane_id, Pane, PaneId};
+use crate::pane::{alloc_pane_id, CachePolicy, Pane, PaneId};
 use crate::Mux;
 use anyhow::{anyhow, bail, Context};
 use async_trait::async_trait;
@@ -23,9 +23,10 @@ use termwiz::render::terminfo::TerminfoRenderer;
 use termwiz::surface::{Change, LineAttribute};
 use termwiz::terminal::{ScreenSize, Terminal, TerminalWaker};
 use wezterm_ssh::{
-    ConfigMap, HostVerificationFailed, Session, SessionEvent, SshChildProcess, SshPty,
+    AuthenticationEvent, ConfigMap, ForwardSpec, HostVerificationFailed, PortForward,
+    SecurityKeyTouchEvent, Session, SessionEvent, SshChildProcess, SshPty,
 };
-use wezterm_term::TerminalSize;
+use wezterm_term::{Alert, TerminalSize};
 
 #[derive(Default)]
 struct PasswordPromptHost {
@@ -116,9 +117,24 @@ pub fn ssh_connect_with_ui(
                     smol::block_on(auth.answer(answers))?;
                 }
                 SessionEvent::HostVerificationFailed(failed) => {
-                    let message = format_host_verification_for_terminal(failed);
+                    let message = format_host_verification_for_terminal(&failed);
                     ui.output(message);
-                    anyhow::bail!("Host key verification failed");
+                    if !failed.can_update_known_hosts() {
+                        anyhow::bail!("Host key verification failed");
+                    }
+                    ui.output_str(&format!("{}\n", failed.update_prompt()));
+                    let update = match ui.input("Enter [y/n]> ") {
+                        Ok(line) => matches!(line.as_ref(), "y" | "Y" | "yes" | "YES"),
+                        Err(_) => false,
+                    };
+                    smol::block_on(failed.answer(update)).context("send update response")?;
+                    if !update {
+                        anyhow::bail!("Host key verification failed");
+                    }
+                }
+                SessionEvent::SecurityKeyTouch(touch) => {
+                    ui.output_str(&format!("{}\n", touch.message()));
+                    ui.alert(security_key_touch_alert(&touch));
                 }
                 SessionEvent::Error(err) => {
                     anyhow::bail!("Error: {}", err);
@@ -130,7 +146,17 @@ pub fn ssh_connect_with_ui(
     })
 }
 
-fn format_host_verification_for_terminal(failed: HostVerificationFailed) -> Vec<Change> {
+/// The security key may be waiting for a touch while the window
+/// showing the prompt is not visible, so we also raise a toast.
+fn security_key_touch_alert(touch: &SecurityKeyTouchEvent) -> Alert {
+    Alert::ToastNotification {
+        title: Some("Security Key".to_string()),
+        body: touch.message(),
+        focus: true,
+    }
+}
+
+fn format_host_verification_for_terminal(failed: &HostVerificationFailed) -> Vec<Change> {
     vec![
         AttributeChange::Intensity(Intensity::Bold).into(),
         LineAttribute::DoubleHeightTopHalfLine.into(),
@@ -151,7 +177,7 @@ fn format_host_verification_for_terminal(failed: HostVerificationFailed) -> Vec<
         ),
         AttributeChange::Intensity(Intensity::Normal).into(),
         Change::Text("\r\n".to_string()),
-        match failed.file {
+        match &failed.file {
             Some(file) => Change::Text(format!(
                 "The host is {}, and its fingerprint is\r\n{}\r\n\
                 If the administrator confirms that the key has changed, you can\r\n\
@@ -169,6 +195,95 @@ fn format_host_verification_for_terminal(failed: HostVerificationFailed) -> Vec<
     ]
 }
 
+/// A port forward that was requested for an ssh session
+#[derive(Debug)]
+pub enum PortForwardEntry {
+    Active(PortForward),
+    Failed { spec: ForwardSpec, error: String },
+}
+
+impl std::fmt::Display for PortForwardEntry {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Active(forward) => write!(fmt, "{}", forward),
+            Self::Failed { spec, error } => write!(fmt, "{} (failed: {})", spec, error),
+        }
+    }
+}
+
+/// Returns the port forwards to establish for `ssh_dom`; these
+/// come from its `port_forwards` option as well as any `LocalForward`,
+/// `RemoteForward` and `DynamicForward` entries in `ssh_config`.
+pub fn ssh_domain_port_forwards(ssh_dom: &SshDomain, ssh_config: &ConfigMap) -> Vec<ForwardSpec> {
+    let mut specs = ForwardSpec::from_config_map(ssh_config);
+    for spec in &ssh_dom.port_forwards {
+        match spec.parse::<ForwardSpec>() {
+            Ok(spec) => specs.push(spec),
+            Err(err) => log::error!(
+                "ssh domain {}: ignoring port_forwards entry {}: {:#}",
+                ssh_dom.name,
+                spec,
+                err
+            ),
+        }
+    }
+    specs
+}
+
+/// Establish the requested port forwards over an authenticated
+/// session; failures are recorded rather than aborting the connection.
+pub fn establish_port_forwards(
+    session: &Session,
+    specs: Vec<ForwardSpec>,
+) -> Vec<PortForwardEntry> {
+    specs
+        .into_iter()
+        .map(|spec| match smol::block_on(session.forward(spec.clone())) {
+            Ok(forward) => PortForwardEntry::Active(forward),
+            Err(err) => {
+                log::error!("{}: {:#}", spec, err);
+                PortForwardEntry::Failed {
+                    spec,
+                    error: format!("{:#}", err),
+                }
+            }
+        })
+        .collect()
+}
+
+/// Build the message and per-prompt fields used to present `auth`
+/// in a gui prompt.
+/// Multi-line prompts, such as those used to show a one-time
+/// code challenge, contribute all but their last line to the message.
+fn authentication_prompt(
+    banner: Option<String>,
+    auth: &AuthenticationEvent,
+) -> (String, Vec<PanePromptField>) {
+    let mut message = vec![];
+    if let Some(banner) = banner {
+        message.push(banner);
+    }
+    if !auth.username.is_empty() {
+        message.push(format!("Authentication for {}", auth.username));
+    }
+    if !auth.instructions.is_empty() {
+        message.push(auth.instructions.clone());
+    }
+
+    let mut fields = vec![];
+    for prompt in &auth.prompts {
+        let mut prompt_lines = prompt.prompt.split('\n').collect::<Vec<_>>();
+        let label = prompt_lines.pop().unwrap_or("");
+        message.extend(prompt_lines.into_iter().map(|line| line.to_string()));
+        fields.push(PanePromptField {
+            label: label.to_string(),
+            echo: prompt.echo,
+        });
+    }
+
+    (message.join("\n"), fields)
+}
+
 /// Represents a connection to remote host via ssh.
 /// The domain is created with the ssh config prior to making the
 /// connection.  The connection is established by the first spawn()
@@ -179,6 +294,11 @@ fn format_host_verification_for_terminal(failed: HostVerificationFailed) -> Vec<
 /// interactive setup.  The bulk of that is driven by `connect_ssh_session`.
 pub struct RemoteSshDomain {
     session: Mutex<Option<Session>>,
+    port_forwards: Arc<Mutex<Vec<PortForwardEntry>>>,
+    /// The command that was used to spawn each pane, so that
+    /// it can be respawned if the connection is lost.
+    /// An entry is removed when its pane is removed from the mux.
+    pane_commands: Arc<Mutex<HashMap<PaneId, Option<CommandBuilder>>>>,
     dom: SshDomain,
     id: DomainId,
     name: String,
@@ -187,6 +307,13 @@ pub struct RemoteSshDomain {
 pub fn ssh_domain_to_ssh_config(ssh_dom: &SshDomain) -> anyhow::Result<ConfigMap> {
     let mut ssh_config = wezterm_ssh::Config::new();
     ssh_config.add_default_config_files();
+    if let Some(username) = &ssh_dom.username {
+        // Make the username visible to `Match user` evaluation
+        ssh_config.set_option("user", username);
+    }
+    if let Some(timeout) = ssh_dom.ssh_option.get("wezterm_ssh_match_exec_timeout") {
+        ssh_config.set_option("wezterm_ssh_match_exec_timeout", timeout);
+    }
 
     let (remote_host_name, port) = {
         let parts: Vec<&str> = ssh_dom.remote_address.split(':').collect();
@@ -236,6 +363,8 @@ impl RemoteSshDomain {
             id,
             name: dom.name.clone(),
             session: Mutex::new(None),
+            port_forwards: Arc::new(Mutex::new(vec![])),
+            pane_commands: Arc::new(Mutex::new(HashMap::new())),
             dom: dom.clone(),
         })
     }
@@ -244,6 +373,106 @@ impl RemoteSshDomain {
         ssh_domain_to_ssh_config(&self.dom)
     }
 
+    /// Establish an additional port forward over the current session
+    pub async fn add_port_forward(&self, spec: &str) -> anyhow::Result<String> {
+        let spec: ForwardSpec = spec.parse()?;
+        let session = self
+            .session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .cloned()
+            .ok_or_else(|| anyhow!("ssh domain {} is not connected", self.name))?;
+        let forward = session.forward(spec).await?;
+        let status = forward.to_string();
+        self.port_forwards
+            .lock()
+            .unwrap()
+            .push(PortForwardEntry::Active(forward));
+        Ok(status)
+    }
+
+    /// Returns a description of each of the port forwards for
+    /// the current session
+    pub fn port_forward_status(&self) -> Vec<String> {
+        self.port_forwards
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect()
+    }
+
+    /// Returns true if the current session terminated because
+    /// of an error after it was established, such as the network
+    /// connection being lost.
+    pub fn connection_was_lost(&self) -> bool {
+        self.session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|session| session.error().is_some())
+            .unwrap_or(false)
+    }
+
+    /// Called when the established session fails.
+    /// The panes that were using it are held open by
+    /// `LocalPane::exit_behavior`; we explain what happened in
+    /// each of them and then wait for the host to become
+    /// reachable again, at which point we offer to reconnect and
+    /// respawn their commands in their prior working directories.
+    fn connection_lost(&self, err: String) {
+        if !self.connection_was_lost() {
+            // Stale notification for a session that was already replaced
+            return;
+        }
+
+        for entry in self.port_forwards.lock().unwrap().iter_mut() {
+            if let PortForwardEntry::Active(forward) = entry {
+                *entry = PortForwardEntry::Failed {
+                    spec: forward.spec().clone(),
+                    error: "connection lost".to_string(),
+                };
+            }
+        }
+
+        let mux = Mux::get();
+        let mut pane_commands = self.pane_commands.lock().unwrap();
+        pane_commands.retain(|pane_id, _| mux.get_pane(*pane_id).is_some());
+
+        let mut lost = vec![];
+        for (pane_id, command) in pane_commands.iter() {
+            if let Some(pane) = mux.get_pane(*pane_id) {
+                let cwd = mux.resolve_cwd(None, Some(pane), self.id, CachePolicy::AllowStale);
+                crate::localpane::emit_output_for_pane(
+                    *pane_id,
+                    &format!("\r\nConnection to {} lost: {}\r\n", self.name, err),
+                );
+                lost.push(LostPane {
+                    pane_id: *pane_id,
+                    command: command.clone(),
+                    cwd,
+                });
+            }
+        }
+        if lost.is_empty() {
+            return;
+        }
+        lost.sort_by_key(|p| p.pane_id);
+
+        let domain_id = self.id;
+        let name = self.name.clone();
+        let ssh_config = self.ssh_config();
+        std::thread::spawn(move || {
+            if let Ok(ssh_config) = ssh_config {
+                if !wait_for_host(&ssh_config, &lost) {
+                    return;
+                }
+            }
+            offer_to_respawn(domain_id, name, lost);
+        });
+    }
+
     fn build_command(
         &self,
         pane_id: PaneId,
@@ -326,13 +555,18 @@ impl RemoteSshDomain {
 
     async fn start_new_session(
         &self,
+        pane_id: PaneId,
         command_line: Option<String>,
         env: HashMap<String, String>,
         size: TerminalSize,
     ) -> anyhow::Result<StartNewSessionResult> {
-        let (session, events) = Session::connect(self.ssh_config().context("obtain ssh config")?)
-            .context("connect to ssh server")?;
+        let ssh_config = self.ssh_config().context("obtain ssh config")?;
+        let forwards = ssh_domain_port_forwards(&self.dom, &ssh_config);
+        let (session, events) = Session::connect(ssh_config).context("connect to ssh server")?;
         self.session.lock().unwrap().replace(session.clone());
+        // Forwards from a prior session are no longer valid
+        self.port_forwards.lock().unwrap().clear();
+        let port_forwards = Arc::clone(&self.port_forwards);
 
         // We get to establish the session!
         //
@@ -387,8 +621,11 @@ impl RemoteSshDomain {
         // to perform the blocking (from its perspective) terminal
         // UI to carry out any authentication.
         let mut stdout_write = BufWriter::new(stdout_write);
+        let domain_id = self.id;
         std::thread::spawn(move || {
             if let Err(err) = connect_ssh_session(
+                domain_id,
+                pane_id,
                 session,
                 events,
                 stdin_read,
@@ -400,6 +637,8 @@ impl RemoteSshDomain {
                 size,
                 command_line,
                 env,
+                forwards,
+                port_forwards,
             ) {
                 let _ = write!(stdout_write, "{:#}", err);
                 log::error!("Failed to connect ssh: {:#}", err);
@@ -419,6 +658,8 @@ struct StartNewSessionResult {
 
 /// Carry out the authentication process and create the initial pty.
 fn connect_ssh_session(
+    domain_id: DomainId,
+    pane_id: PaneId,
     session: Session,
     events: smol::channel::Receiver<SessionEvent>,
     mut stdin_read: FileDescriptor,
@@ -430,6 +671,8 @@ fn connect_ssh_session(
     size: Arc<Mutex<TerminalSize>>,
     command_line: Option<String>,
     env: HashMap<String, String>,
+    forwards: Vec<ForwardSpec>,
+    port_forwards: Arc<Mutex<Vec<PortForwardEntry>>>,
 ) -> anyhow::Result<()> {
     struct StdoutShim<'a> {
         size: Arc<Mutex<TerminalSize>>,
@@ -587,30 +830,67 @@ fn connect_ssh_session(
             s.push_str("\r\n");
             self.render(&[Change::Text(s)])
         }
+
+        /// Prompt for the answers to `auth` inline in the pane
+        fn inline_auth_prompts(
+            &mut self,
+            auth: &AuthenticationEvent,
+        ) -> anyhow::Result<Vec<String>> {
+            let mut answers = vec![];
+            for prompt in &auth.prompts {
+                let mut prompt_lines = prompt.prompt.split('\n').collect::<Vec<_>>();
+                let editor_prompt = prompt_lines.pop().unwrap();
+                for line in &prompt_lines {
+                    self.output_line(line)?;
+                }
+                let mut editor = LineEditor::new(self);
+                let mut host = PasswordPromptHost::default();
+                editor.set_prompt(editor_prompt);
+                host.echo = prompt.echo;
+                if let Some(line) = editor.read_line(&mut host)? {
+                    answers.push(line);
+                } else {
+                    anyhow::bail!("Authentication was cancelled");
+                }
+            }
+            Ok(answers)
+        }
+
+        /// Ask a yes/no question, preferring a prompt in the gui
+        /// and falling back to asking inline in the pane, in which
+        /// case `message` is expected to have been output already
+        fn confirm(&mut self, pane_id: PaneId, title: &str, message: &str) -> anyhow::Result<bool> {
+            if let Some(answer) = prompt_via_frontend(pane_id, title, message, vec![]) {
+                return Ok(answer.is_some());
+            }
+
+            let mut editor = LineEditor::new(self);
+            let mut host = PasswordPromptHost::default();
+            host.echo = true;
+            editor.set_prompt("Enter [y/n]> ");
+            Ok(match editor.read_line(&mut host)?.as_deref() {
+                Some("y" | "Y" | "yes" | "YES") => true,
+                _ => false,
+            })
+        }
     }
 
+    // The most recent banner, which is repeated in the
+    // authentication prompt so that it isn't missed
+    let mut banner = None;
+
     // Process authentication related events
     while let Ok(event) = smol::block_on(events.recv()) {
         match event {
-            SessionEvent::Banner(banner) => {
-                if let Some(banner) = banner {
-                    shim.output_line(&banner)?;
+            SessionEvent::Banner(text) => {
+                if let Some(text) = text {
+                    shim.output_line(&text)?;
+                    banner.replace(text);
                 }
             }
             SessionEvent::HostVerify(verify) => {
                 shim.output_line(&verify.message)?;
-                let mut editor = LineEditor::new(&mut shim);
-                let mut host = PasswordPromptHost::default();
-                host.echo = true;
-                editor.set_prompt("Enter [y/n]> ");
-                let ok = if let Some(line) = editor.read_line(&mut host)? {
-                    match line.as_ref() {
-                        "y" | "Y" | "yes" | "YES" => true,
-                        "n" | "N" | "no" | "NO" | _ => false,
-                    }
-                } else {
-                    false
-                };
+                let ok = shim.confirm(pane_id, "SSH Host Verification", &verify.message)?;
                 smol::block_on(verify.answer(ok)).context("send verify response")?;
             }
             SessionEvent::Authenticate(auth) => {
@@ -620,31 +900,37 @@ fn connect_ssh_session(
                 if !auth.instructions.is_empty() {
                     shim.output_line(&auth.instructions)?;
                 }
-                let mut answers = vec![];
-                for prompt in &auth.prompts {
-                    let mut prompt_lines = prompt.prompt.split('\n').collect::<Vec<_>>();
-                    let editor_prompt = prompt_lines.pop().unwrap();
-                    for line in &prompt_lines {
-                        shim.output_line(line)?;
-                    }
-                    let mut editor = LineEditor::new(&mut shim);
-                    let mut host = PasswordPromptHost::default();
-                    editor.set_prompt(editor_prompt);
-                    host.echo = prompt.echo;
-                    if let Some(line) = editor.read_line(&mut host)? {
-                        answers.push(line);
-                    } else {
-                        anyhow::bail!("Authentication was cancelled");
+                let answers = if auth.prompts.is_empty() {
+                    vec![]
+                } else {
+                    let (message, fields) = authentication_prompt(banner.take(), &auth);
+                    match prompt_via_frontend(pane_id, "SSH Authentication", &message, fields) {
+                        Some(Some(answers)) => answers,
+                        Some(None) => anyhow::bail!("Authentication was cancelled"),
+                        None => shim.inline_auth_prompts(&auth)?,
                     }
-                }
+                };
                 smol::block_on(auth.answer(answers))?;
             }
             SessionEvent::Error(err) => {
                 shim.output_line(&format!("Error: {}", err))?;
             }
             SessionEvent::HostVerificationFailed(failed) => {
-                let message = format_host_verification_for_terminal(failed);
+                let message = format_host_verification_for_terminal(&failed);
                 shim.render(&message)?;
+                if failed.can_update_known_hosts() {
+                    shim.output_line(&failed.update_prompt())?;
+                    let update =
+                        shim.confirm(pane_id, "SSH Host Key Changed", &failed.update_prompt())?;
+                    smol::block_on(failed.answer(update)).context("send update response")?;
+                }
+            }
+            SessionEvent::SecurityKeyTouch(touch) => {
+                shim.output_line(&touch.message())?;
+                Mux::notify_from_any_thread(crate::MuxNotification::Alert {
+                    pane_id,
+                    alert: security_key_touch_alert(&touch),
+                });
             }
             SessionEvent::Authenticated => {
                 // Our session has been authenticated: we can now
@@ -660,6 +946,10 @@ fn connect_ssh_session(
                         break;
                     }
                     Ok((pty, child)) => {
+                        for entry in establish_port_forwards(&session, forwards) {
+                            shim.output_line(&format!("Forwarding {}", entry))?;
+                            port_forwards.lock().unwrap().push(entry);
+                        }
                         drop(shim);
 
                         // Obtain the real stdin/stdout for the pty
@@ -687,6 +977,7 @@ fn connect_ssh_session(
                         // The pty and child will be picked up when
                         // they are next polled or resized.
 
+                        std::thread::spawn(move || monitor_ssh_session(domain_id, events));
                         return Ok(());
                     }
                 }
@@ -697,6 +988,177 @@ fn connect_ssh_session(
     Ok(())
 }
 
+/// Once a session has been established, wait for it to terminate
+/// and let the domain know if that was because it failed.
+fn monitor_ssh_session(domain_id: DomainId, events: smol::channel::Receiver<SessionEvent>) {
+    while let Ok(event) = smol::block_on(events.recv()) {
+        if let SessionEvent::Error(err) = event {
+            log::error!("ssh session for domain {domain_id} failed: {err}");
+            promise::spawn::spawn_into_main_thread(async move {
+                let mux = Mux::get();
+                if let Some(domain) = mux.get_domain(domain_id) {
+                    if let Some(ssh) = domain.downcast_ref::<RemoteSshDomain>() {
+                        ssh.connection_lost(err);
+                    }
+                }
+            })
+            .detach();
+        }
+    }
+}
+
+/// A pane whose session was lost, along with what we need
+/// in order to respawn it
+struct LostPane {
+    pane_id: PaneId,
+    command: Option<CommandBuilder>,
+    cwd: Option<String>,
+}
+
+fn any_lost_pane_alive(lost: &[LostPane]) -> bool {
+    match Mux::try_get() {
+        Some(mux) => lost.iter().any(|p| mux.get_pane(p.pane_id).is_some()),
+        None => false,
+    }
+}
+
+/// Wait, with backoff, until the host accepts tcp connections again.
+/// Hosts that are reached via a ProxyCommand are assumed to be
+/// reachable, as we can't tell without running the command.
+/// Returns false if all of the lost panes were closed while waiting.
+fn wait_for_host(ssh_config: &ConfigMap, lost: &[LostPane]) -> bool {
+    const BASE_INTERVAL: Duration = Duration::from_secs(1);
+    const MAX_INTERVAL: Duration = Duration::from_secs(30);
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    match ssh_config.get("proxycommand").map(|s| s.as_str()) {
+        Some("none") | None => {}
+        Some(_) => return true,
+    }
+    let hostname = match ssh_config.get("hostname") {
+        Some(hostname) => hostname.to_string(),
+        None => return true,
+    };
+    let port = ssh_config
+        .get("port")
+        .and_then(|p| p.parse::<u16>().ok())
+        .unwrap_or(22);
+
+    let mut backoff = BASE_INTERVAL;
+    loop {
+        if !any_lost_pane_alive(lost) {
+            return false;
+        }
+
+        let reachable = std::net::ToSocketAddrs::to_socket_addrs(&(hostname.as_str(), port))
+            .map(|addrs| {
+                addrs.into_iter().any(|addr| {
+                    std::net::TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).is_ok()
+                })
+            })
+            .unwrap_or(false);
+        if reachable {
+            return true;
+        }
+
+        log::info!("{hostname}:{port} is not reachable; will retry in {backoff:?}");
+        std::thread::sleep(backoff);
+        backoff = (backoff + backoff).min(MAX_INTERVAL);
+    }
+}
+
+/// Ask whether to respawn the lost panes, using the first of
+/// them that is still present to host the prompt.
+fn offer_to_respawn(domain_id: DomainId, name: String, lost: Vec<LostPane>) {
+    let mux = match Mux::try_get() {
+        Some(mux) => mux,
+        None => return,
+    };
+    let prompt_pane = match lost.iter().find(|p| mux.get_pane(p.pane_id).is_some()) {
+        Some(p) => p.pane_id,
+        None => return,
+    };
+    drop(mux);
+
+    let message = format!(
+        "The connection to {name} was lost and is now available again.\n\
+         Reconnect and restart {} pane(s) with their prior command \
+         and working directory?",
+        lost.len()
+    );
+    match prompt_via_frontend(prompt_pane, "SSH Connection Lost", &message, vec![]) {
+        Some(Some(_)) => {
+            promise::spawn::spawn_into_main_thread(async move {
+                promise::spawn::spawn(async move {
+                    if let Err(err) = respawn_lost_panes(domain_id, lost).await {
+                        log::error!("respawning ssh panes: {err:#}");
+                    }
+                })
+                .detach();
+            })
+            .detach();
+        }
+        Some(None) => {}
+        None => {
+            for pane in &lost {
+                crate::localpane::emit_output_for_pane(
+                    pane.pane_id,
+                    &format!("{name} is reachable again; open a new tab to reconnect\r\n"),
+                );
+            }
+        }
+    }
+}
+
+/// Spawn a replacement for each of the lost panes, putting each
+/// one in place of its predecessor.  The first of these carries
+/// out authentication for the new session, and the others wait
+/// for it to complete.
+async fn respawn_lost_panes(domain_id: DomainId, lost: Vec<LostPane>) -> anyhow::Result<()> {
+    let mux = Mux::get();
+    let domain = mux
+        .get_domain(domain_id)
+        .ok_or_else(|| anyhow!("domain {domain_id} not found"))?;
+
+    for LostPane {
+        pane_id,
+        command,
+        cwd,
+    } in lost
+    {
+        let old_pane = match mux.get_pane(pane_id) {
+            Some(pane) => pane,
+            None => continue,
+        };
+        let tab = match mux
+            .resolve_pane_id(pane_id)
+            .and_then(|(_, _, tab_id)| mux.get_tab(tab_id))
+        {
+            Some(tab) => tab,
+            None => continue,
+        };
+
+        let dims = old_pane.get_dimensions();
+        let size = TerminalSize {
+            rows: dims.viewport_rows,
+            cols: dims.cols,
+            pixel_width: dims.pixel_width,
+            pixel_height: dims.pixel_height,
+            dpi: dims.dpi,
+        };
+
+        let pane = domain.spawn_pane(size, command, cwd).await?;
+        if tab.replace_pane(pane_id, &pane).is_some() {
+            mux.remove_pane(pane_id);
+            mux.notify(crate::MuxNotification::TabResized(tab.tab_id()));
+        } else {
+            // The old pane moved or went away while we were spawning
+            mux.remove_pane(pane.pane_id());
+        }
+    }
+    Ok(())
+}
+
 #[async_trait(?Send)]
 impl Domain for RemoteSshDomain {
     async fn spawn_pane(
@@ -706,6 +1168,7 @@ impl Domain for RemoteSshDomain {
         command_dir: Option<String>,
     ) -> anyhow::Result<Arc<dyn Pane>> {
         let pane_id = alloc_pane_id();
+        let original_command = command.clone();
 
         let (command_line, env) = self
             .build_command(pane_id, command, command_dir)
@@ -742,7 +1205,8 @@ impl Domain for RemoteSshDomain {
                     {
                         // Session died (perhaps they closed the initial tab?)
                         // So we'll try making a new one
-                        self.start_new_session(command_line, env, size).await?
+                        self.start_new_session(pane_id, command_line, env, size)
+                            .await?
                     } else {
                         log::error!("{err:#?}");
                         return Err(err);
@@ -750,7 +1214,8 @@ impl Domain for RemoteSshDomain {
                 }
             }
         } else {
-            self.start_new_session(command_line, env, size).await?
+            self.start_new_session(pane_id, command_line, env, size)
+                .await?
         };
 
         // Wrap up the pty etc. in a LocalPane.  That allows for
@@ -772,12 +1237,26 @@ impl Domain for RemoteSshDomain {
             terminal,
             child,
             pty,
-            Box::new(writer),
+            writer,
             self.id,
             "RemoteSshDomain".to_string(),
+            crate::domain::spawn_command_argv(original_command.as_ref()),
         ));
         let mux = Mux::get();
         mux.add_pane(&pane)?;
+        self.pane_commands
+            .lock()
+            .unwrap()
+            .insert(pane_id, original_command);
+
+        let pane_commands = Arc::clone(&self.pane_commands);
+        mux.subscribe(move |n| match n {
+            crate::MuxNotification::PaneRemoved(id) if id == pane_id => {
+                pane_commands.lock().unwrap().remove(&pane_id);
+                false
+            }
+            _ => true,
+        });
 
         Ok(pane)
     }
@@ -808,6 +1287,15 @@ impl Domain for RemoteSshDomain {
         // a spawn.
         DomainState::Attached
     }
+
+    fn ssh_session(&self) -> Option<Session> {
+        self.session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|session| session.is_connected())
+            .cloned()
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,93 @@
+//! Files opened via sftp on the host of an ssh session on behalf of
+//! `wezterm cli cp`, which streams their contents over the mux protocol
+//! in chunks.  Open files are tracked by a transfer id so that the
+//! chunks can be processed by independent requests.
+use anyhow::{anyhow, Context};
+use smol::io::{AsyncReadExt, AsyncWriteExt};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use wezterm_ssh::{File, Session};
+
+/// The largest chunk that will be returned by a single read
+pub const MAX_CHUNK_SIZE: usize = 256 * 1024;
+
+lazy_static::lazy_static! {
+    static ref TRANSFERS: Mutex<HashMap<u64, File>> = Mutex::new(HashMap::new());
+}
+static NEXT_TRANSFER_ID: AtomicU64 = AtomicU64::new(1);
+
+pub fn alloc_transfer_id() -> u64 {
+    NEXT_TRANSFER_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Opens `path` for reading, or creates/truncates it for writing.
+/// Returns the size of the file when opening it for reading.
+pub async fn open(
+    transfer_id: u64,
+    session: &Session,
+    path: &str,
+    write: bool,
+) -> anyhow::Result<Option<u64>> {
+    let sftp = session.sftp();
+    let (file, size) = if write {
+        let file = sftp
+            .create(path)
+            .await
+            .with_context(|| format!("creating {path}"))?;
+        (file, None)
+    } else {
+        let file = sftp
+            .open(path)
+            .await
+            .with_context(|| format!("opening {path}"))?;
+        let size = file.metadata().await.ok().and_then(|meta| meta.size);
+        (file, size)
+    };
+    TRANSFERS.lock().unwrap().insert(transfer_id, file);
+    Ok(size)
+}
+
+fn take(transfer_id: u64) -> anyhow::Result<File> {
+    TRANSFERS
+        .lock()
+        .unwrap()
+        .remove(&transfer_id)
+        .ok_or_else(|| anyhow!("no such transfer {transfer_id}"))
+}
+
+fn put_back(transfer_id: u64, file: File) {
+    TRANSFERS.lock().unwrap().insert(transfer_id, file);
+}
+
+pub async fn write(transfer_id: u64, data: &[u8]) -> anyhow::Result<()> {
+    let mut file = take(transfer_id)?;
+    let result = file.write_all(data).await;
+    put_back(transfer_id, file);
+    Ok(result?)
+}
+
+/// Reads up to `max_len` bytes; an empty result indicates
+/// the end of the file
+pub async fn read(transfer_id: u64, max_len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut file = take(transfer_id)?;
+    let mut buf = vec![0u8; max_len.min(MAX_CHUNK_SIZE)];
+    let result = file.read(&mut buf).await;
+    put_back(transfer_id, file);
+    let len = result?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+/// Flushes and closes the file
+pub async fn close(transfer_id: u64) -> anyhow::Result<()> {
+    let mut file = take(transfer_id)?;
+    file.close().await?;
+    Ok(())
+}
+
+/// Forget about a transfer without waiting to close it,
+/// for example because the client that started it went away
+pub fn discard(transfer_id: u64) {
+    TRANSFERS.lock().unwrap().remove(&transfer_id);
+}
@@ -1,11 +1,25 @@
+//! A lightweight registry for the metrics that are recorded via
+//! the `metrics` crate throughout wezterm.
+//!
+//! Histograms whose names end in `.rate` track throughput per second,
+//! those that end in `.size` track sizes, and all others are assumed
+//! to be latencies recorded in seconds.
+//!
+//! The current values can be periodically logged, by setting
+//! `periodic_stat_logging`, and can be retrieved as a `StatsSnapshot`
+//! for display in the debug overlay or via `wezterm cli stats`.
+use crate::pane::PaneId;
+use crate::Mux;
 use config::configuration;
 use config::lua::get_or_create_sub_module;
 use config::lua::mlua::Lua;
 use hdrhistogram::Histogram;
 use metrics::{Counter, Gauge, Key, KeyName, Metadata, Recorder, SharedString, Unit};
 use parking_lot::Mutex;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tabout::{tabulate_output, Alignment, Column};
@@ -136,66 +150,246 @@ impl metrics::CounterFn for MyCounter {
     }
 }
 
+/// Holds the bits of an f64
+struct MyGauge {
+    value: AtomicU64,
+}
+
+impl MyGauge {
+    fn get(&self) -> f64 {
+        f64::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    fn update<F: Fn(f64) -> f64>(&self, f: F) {
+        self.value
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some(f(f64::from_bits(bits)).to_bits())
+            })
+            .ok();
+    }
+}
+
+impl metrics::GaugeFn for MyGauge {
+    fn increment(&self, value: f64) {
+        self.update(|current| current + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update(|current| current - value);
+    }
+
+    fn set(&self, value: f64) {
+        self.value.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Formats the key, including its labels if any
+fn key_name(key: &Key) -> String {
+    let labels: Vec<String> = key
+        .labels()
+        .map(|label| format!("{}={}", label.key(), label.value()))
+        .collect();
+    if labels.is_empty() {
+        key.name().to_string()
+    } else {
+        format!("{}{{{}}}", key.name(), labels.join(","))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p75: u64,
+    pub p95: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThroughputStats {
+    /// The count accumulated in the current one second window
+    pub current: u64,
+    /// Percentiles of the counts of prior one second windows
+    pub per_second: Percentiles,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PaneStats {
+    pub pane_id: PaneId,
+    /// The number of lines held by the pane, including scrollback
+    pub lines: usize,
+    /// An estimate of the memory used by those lines
+    pub memory_bytes: usize,
+}
+
+/// The values of all of the metrics that have been recorded by
+/// this process, along with some information about its panes
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StatsSnapshot {
+    pub counters: BTreeMap<String, u64>,
+    pub gauges: BTreeMap<String, f64>,
+    pub throughput: BTreeMap<String, ThroughputStats>,
+    pub sizes: BTreeMap<String, Percentiles>,
+    /// Latencies, in nanoseconds
+    pub latency: BTreeMap<String, Percentiles>,
+    /// Only panes whose content is held by this process are included
+    pub panes: Vec<PaneStats>,
+}
+
+fn stat_columns(names: &[&str]) -> Vec<Column> {
+    names
+        .iter()
+        .map(|name| Column {
+            name: name.to_string(),
+            alignment: Alignment::Left,
+        })
+        .collect()
+}
+
+impl StatsSnapshot {
+    /// Write the snapshot as a series of tables
+    pub fn render<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        let data: Vec<Vec<String>> = self
+            .throughput
+            .iter()
+            .map(|(key, tput)| {
+                vec![
+                    key.to_string(),
+                    tput.current.to_string(),
+                    tput.per_second.p50.to_string(),
+                    tput.per_second.p75.to_string(),
+                    tput.per_second.p95.to_string(),
+                ]
+            })
+            .collect();
+        tabulate_output(
+            &stat_columns(&["STAT", "current", "p50", "p75", "p95"]),
+            &data,
+            out,
+        )?;
+
+        let latency = |nanos| format!("{:.2?}", Duration::from_nanos(nanos));
+        let mut data: Vec<Vec<String>> = self
+            .sizes
+            .iter()
+            .map(|(key, p)| {
+                vec![
+                    key.to_string(),
+                    p.p50.to_string(),
+                    p.p75.to_string(),
+                    p.p95.to_string(),
+                ]
+            })
+            .chain(self.latency.iter().map(|(key, p)| {
+                vec![
+                    key.to_string(),
+                    latency(p.p50),
+                    latency(p.p75),
+                    latency(p.p95),
+                ]
+            }))
+            .collect();
+        data.sort_by(|a, b| a[0].cmp(&b[0]));
+        writeln!(out)?;
+        tabulate_output(&stat_columns(&["STAT", "p50", "p75", "p95"]), &data, out)?;
+
+        let data: Vec<Vec<String>> = self
+            .counters
+            .iter()
+            .map(|(key, count)| vec![key.to_string(), count.to_string()])
+            .chain(
+                self.gauges
+                    .iter()
+                    .map(|(key, value)| vec![key.to_string(), value.to_string()]),
+            )
+            .collect();
+        writeln!(out)?;
+        tabulate_output(&stat_columns(&["STAT", "VALUE"]), &data, out)?;
+
+        if !self.panes.is_empty() {
+            let data: Vec<Vec<String>> = self
+                .panes
+                .iter()
+                .map(|pane| {
+                    vec![
+                        pane.pane_id.to_string(),
+                        pane.lines.to_string(),
+                        format!("{:.1}", pane.memory_bytes as f64 / 1024.),
+                    ]
+                })
+                .collect();
+            writeln!(out)?;
+            tabulate_output(&stat_columns(&["PANE", "LINES", "KiB"]), &data, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the current values of the metrics recorded by this process.
+/// The pane information is only available in a process that has
+/// a mux.
+pub fn snapshot() -> StatsSnapshot {
+    let mut snapshot = INNER.lock().snapshot();
+    if let Some(mux) = Mux::try_get() {
+        snapshot.panes = mux
+            .iter_panes()
+            .into_iter()
+            .filter_map(|pane| {
+                Some(PaneStats {
+                    pane_id: pane.pane_id(),
+                    memory_bytes: pane.estimated_memory_bytes()?,
+                    lines: pane.get_dimensions().scrollback_rows,
+                })
+            })
+            .collect();
+        snapshot.panes.sort_by_key(|pane| pane.pane_id);
+    }
+    snapshot
+}
+
 struct Inner {
     histograms: HashMap<Key, Arc<ScaledHistogram>>,
     throughput: HashMap<Key, Arc<Throughput>>,
     counters: HashMap<Key, Arc<MyCounter>>,
+    gauges: HashMap<Key, Arc<MyGauge>>,
 }
 
 impl Inner {
-    fn run(inner: Arc<Mutex<Inner>>) {
-        let mut last_print = Instant::now();
+    fn snapshot(&self) -> StatsSnapshot {
+        let mut snapshot = StatsSnapshot::default();
+        for (key, tput) in &self.throughput {
+            let current = tput.current();
+            let (p50, p75, p95) = tput.percentiles();
+            snapshot.throughput.insert(
+                key_name(key),
+                ThroughputStats {
+                    current,
+                    per_second: Percentiles { p50, p75, p95 },
+                },
+            );
+        }
+        for (key, histogram) in &self.histograms {
+            // The latencies are recorded in nanoseconds, so these
+            // are the raw values in both cases
+            let (p50, p75, p95) = histogram.percentiles();
+            let percentiles = Percentiles { p50, p75, p95 };
+            if key.name().ends_with(".size") {
+                snapshot.sizes.insert(key_name(key), percentiles);
+            } else {
+                snapshot.latency.insert(key_name(key), percentiles);
+            }
+        }
+        for (key, count) in &self.counters {
+            snapshot
+                .counters
+                .insert(key_name(key), count.value.load(Ordering::Relaxed) as u64);
+        }
+        for (key, gauge) in &self.gauges {
+            snapshot.gauges.insert(key_name(key), gauge.get());
+        }
+        snapshot
+    }
 
-        let rate_cols = vec![
-            Column {
-                name: "STAT".to_string(),
-                alignment: Alignment::Left,
-            },
-            Column {
-                name: "current".to_string(),
-                alignment: Alignment::Left,
-            },
-            Column {
-                name: "p50".to_string(),
-                alignment: Alignment::Left,
-            },
-            Column {
-                name: "p75".to_string(),
-                alignment: Alignment::Left,
-            },
-            Column {
-                name: "p95".to_string(),
-                alignment: Alignment::Left,
-            },
-        ];
-        let cols = vec![
-            Column {
-                name: "STAT".to_string(),
-                alignment: Alignment::Left,
-            },
-            Column {
-                name: "p50".to_string(),
-                alignment: Alignment::Left,
-            },
-            Column {
-                name: "p75".to_string(),
-                alignment: Alignment::Left,
-            },
-            Column {
-                name: "p95".to_string(),
-                alignment: Alignment::Left,
-            },
-        ];
-        let count_cols = vec![
-            Column {
-                name: "STAT".to_string(),
-                alignment: Alignment::Left,
-            },
-            Column {
-                name: "COUNT".to_string(),
-                alignment: Alignment::Left,
-            },
-        ];
+    fn run() {
+        let mut last_print = Instant::now();
 
         loop {
             std::thread::sleep(Duration::from_secs(1));
@@ -209,59 +403,10 @@ impl Inner {
                 continue;
             }
             if last_print.elapsed() >= Duration::from_secs(seconds) {
-                let mut data = vec![];
-
-                let mut inner = inner.lock();
-                for (key, tput) in &mut inner.throughput {
-                    let current = tput.current();
-                    let (p50, p75, p95) = tput.percentiles();
-                    data.push(vec![
-                        key.to_string(),
-                        format!("{:.2?}", current),
-                        format!("{:.2?}", p50),
-                        format!("{:.2?}", p75),
-                        format!("{:.2?}", p95),
-                    ]);
-                }
-                data.sort_by(|a, b| a[0].cmp(&b[0]));
-                eprintln!();
-                tabulate_output(&rate_cols, &data, &mut std::io::stderr().lock()).ok();
-
-                data.clear();
-                for (key, histogram) in &inner.histograms {
-                    if key.name().ends_with(".size") {
-                        let (p50, p75, p95) = histogram.percentiles();
-                        data.push(vec![
-                            key.to_string(),
-                            format!("{:.2?}", p50),
-                            format!("{:.2?}", p75),
-                            format!("{:.2?}", p95),
-                        ]);
-                    } else {
-                        let (p50, p75, p95) = histogram.latency_percentiles();
-                        data.push(vec![
-                            key.to_string(),
-                            format!("{:.2?}", p50),
-                            format!("{:.2?}", p75),
-                            format!("{:.2?}", p95),
-                        ]);
-                    }
-                }
-                data.sort_by(|a, b| a[0].cmp(&b[0]));
-                eprintln!();
-                tabulate_output(&cols, &data, &mut std::io::stderr().lock()).ok();
-
-                data.clear();
-                for (key, count) in &inner.counters {
-                    data.push(vec![
-                        key.to_string(),
-                        count.value.load(Ordering::Relaxed).to_string(),
-                    ]);
-                }
-                data.sort_by(|a, b| a[0].cmp(&b[0]));
-                eprintln!();
-                tabulate_output(&count_cols, &data, &mut std::io::stderr().lock()).ok();
-
+                let snapshot = snapshot();
+                let mut stderr = std::io::stderr().lock();
+                writeln!(stderr).ok();
+                snapshot.render(&mut stderr).ok();
                 last_print = Instant::now();
             }
         }
@@ -273,6 +418,7 @@ fn make_inner() -> Arc<Mutex<Inner>> {
         histograms: HashMap::new(),
         throughput: HashMap::new(),
         counters: HashMap::new(),
+        gauges: HashMap::new(),
     }))
 }
 
@@ -289,8 +435,7 @@ impl Stats {
 
     pub fn init() -> anyhow::Result<()> {
         let stats = Self::new();
-        let inner = Arc::clone(&stats.inner);
-        std::thread::spawn(move || Inner::run(inner));
+        std::thread::spawn(Inner::run);
         metrics::set_global_recorder(stats)
             .map_err(|e| anyhow::anyhow!("Failed to set metrics recorder:{}", e))
     }
@@ -317,8 +462,18 @@ impl Recorder for Stats {
         }
     }
 
-    fn register_gauge(&self, _key: &Key, _metadata: &Metadata) -> Gauge {
-        Gauge::noop()
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata) -> Gauge {
+        let mut inner = self.inner.lock();
+        match inner.gauges.get(key) {
+            Some(existing) => Gauge::from_arc(existing.clone()),
+            None => {
+                let gauge = Arc::new(MyGauge {
+                    value: AtomicU64::new(0f64.to_bits()),
+                });
+                inner.gauges.insert(key.clone(), gauge.clone());
+                Gauge::from_arc(gauge)
+            }
+        }
     }
 
     fn register_histogram(&self, key: &Key, _metadata: &Metadata) -> metrics::Histogram {
@@ -368,6 +523,18 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
             Ok(counters)
         })?,
     )?;
+    metrics_mod.set(
+        "get_gauges",
+        lua.create_function(|_, _: ()| {
+            let inner = INNER.lock();
+            let gauges: HashMap<String, f64> = inner
+                .gauges
+                .iter()
+                .map(|(k, v)| (k.name().to_string(), v.get()))
+                .collect();
+            Ok(gauges)
+        })?,
+    )?;
     metrics_mod.set(
         "get_throughput",
         lua.create_function(|_, _: ()| {
@@ -436,3 +603,32 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
     )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use metrics::{GaugeFn, Label};
+
+    #[test]
+    fn gauge() {
+        let gauge = MyGauge {
+            value: AtomicU64::new(0f64.to_bits()),
+        };
+        gauge.set(2.5);
+        gauge.increment(1.);
+        gauge.decrement(0.5);
+        assert_eq!(gauge.get(), 3.);
+    }
+
+    #[test]
+    fn key_names() {
+        assert_eq!(key_name(&Key::from_name("pdu.size")), "pdu.size");
+        assert_eq!(
+            key_name(&Key::from_parts(
+                "pdu.size",
+                vec![Label::new("pdu", "Ping")]
+            )),
+            "pdu.size{pdu=Ping}"
+        );
+    }
+}
@@ -278,6 +278,8 @@ fn pane_tree(
                 left_col,
                 top_row,
                 tty_name: pane.tty_name(),
+                spawn_command: pane.get_spawn_command(),
+                foreground_process_name: pane.get_foreground_process_name(CachePolicy::AllowStale),
             })
         }
     }
@@ -720,6 +722,14 @@ impl Tab {
             .swap_active_with_index(pane_index, keep_focus)
     }
 
+    /// Replace the pane identified by `pane_id` with `new_pane`,
+    /// keeping its position in the tab.
+    /// Returns the pane that was replaced, or None if `pane_id`
+    /// is not part of this tab.
+    pub fn replace_pane(&self, pane_id: PaneId, new_pane: &Arc<dyn Pane>) -> Option<Arc<dyn Pane>> {
+        self.inner.lock().replace_pane(pane_id, new_pane)
+    }
+
     /// Computes the size of the pane that would result if the specified
     /// pane was split in a particular direction.
     /// The intent is to call this prior to spawning the new pane so that
@@ -1861,6 +1871,32 @@ impl TabInner {
         None
     }
 
+    fn replace_pane(&mut self, pane_id: PaneId, new_pane: &Arc<dyn Pane>) -> Option<Arc<dyn Pane>> {
+        let pane_index = self
+            .iter_panes_ignoring_zoom()
+            .into_iter()
+            .find(|p| p.pane.pane_id() == pane_id)
+            .map(|p| p.index)?;
+
+        let mut pane = Arc::clone(new_pane);
+        let mut cursor = self.pane.take().unwrap().cursor();
+        match cursor.go_to_nth_leaf(pane_index) {
+            Ok(c) => cursor = c,
+            Err(c) => {
+                self.pane.replace(c.tree());
+                return None;
+            }
+        };
+        std::mem::swap(&mut pane, cursor.leaf_mut().unwrap());
+        self.pane.replace(cursor.tree());
+
+        if self.zoomed.as_ref().map(|p| p.pane_id()) == Some(pane_id) {
+            self.zoomed.replace(Arc::clone(new_pane));
+        }
+
+        Some(pane)
+    }
+
     fn compute_split_size(
         &mut self,
         pane_index: usize,
@@ -2156,6 +2192,8 @@ pub struct PaneEntry {
     pub top_row: usize,
     pub left_col: usize,
     pub tty_name: Option<String>,
+    pub spawn_command: Option<Vec<String>>,
+    pub foreground_process_name: Option<String>,
 }
 
 #[derive(Deserialize, Clone, Serialize, PartialEq, Debug)]
@@ -2516,6 +2554,53 @@ mod test {
         true
     }
 
+    #[test]
+    fn replace_pane() {
+        let size = TerminalSize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+            dpi: 96,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let split_size = tab
+            .compute_split_size(
+                0,
+                SplitRequest {
+                    direction: SplitDirection::Horizontal,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitRequest {
+                direction: SplitDirection::Horizontal,
+                ..Default::default()
+            },
+            FakePane::new(2, split_size.second),
+        )
+        .unwrap();
+
+        assert!(tab
+            .replace_pane(3, &FakePane::new(4, split_size.second))
+            .is_none());
+
+        let replaced = tab
+            .replace_pane(1, &FakePane::new(3, split_size.first))
+            .unwrap();
+        assert_eq!(replaced.pane_id(), 1);
+
+        let panes = tab.iter_panes();
+        assert_eq!(2, panes.len());
+        assert_eq!(3, panes[0].pane.pane_id());
+        assert_eq!(2, panes[1].pane.pane_id());
+        assert_eq!(39, panes[0].width);
+    }
+
     #[test]
     fn tab_is_send_and_sync() {
         assert!(is_send_and_sync::<Tab>());
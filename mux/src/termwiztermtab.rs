@@ -266,6 +266,10 @@ impl Pane for TermWizTerminalPane {
         self.terminal.lock().perform_actions(actions)
     }
 
+    fn perform_action_batch(&self, batch: &mut termwiz::escape::ActionBatch) {
+        self.terminal.lock().perform_action_batch(batch)
+    }
+
     fn kill(&self) {
         *self.dead.lock() = true;
     }
@@ -311,12 +315,18 @@ pub struct TermWizTerminal {
     input_rx: Receiver<InputEvent>,
     renderer: TerminfoRenderer,
     grab_mouse: bool,
+    pane_id: PaneId,
 }
 
 impl TermWizTerminal {
     pub fn no_grab_mouse_in_raw_mode(&mut self) {
         self.grab_mouse = false;
     }
+
+    /// Returns the id of the pane that displays this terminal
+    pub fn pane_id(&self) -> PaneId {
+        self.pane_id
+    }
 }
 
 struct TermWizTerminalRenderTty {
@@ -454,6 +464,10 @@ pub fn allocate(
 
     let renderer = termwiz_funcs::new_wezterm_terminfo_renderer();
 
+    let domain_id = 0;
+    let pane = TermWizTerminalPane::new(domain_id, size, input_tx, render_pipe.read, Some(config));
+    let pane_id = pane.pane_id();
+
     let tw_term = TermWizTerminal {
         render_tx: TermWizTerminalRenderTty {
             render_tx: BufWriter::new(render_pipe.write),
@@ -467,11 +481,9 @@ pub fn allocate(
         input_rx,
         renderer,
         grab_mouse: true,
+        pane_id,
     };
 
-    let domain_id = 0;
-    let pane = TermWizTerminalPane::new(domain_id, size, input_tx, render_pipe.read, Some(config));
-
     // Add the tab to the mux so that the output is processed
     let pane: Arc<dyn Pane> = Arc::new(pane);
 
@@ -503,21 +515,6 @@ pub async fn run<
 
     let renderer = termwiz_funcs::new_wezterm_terminfo_renderer();
 
-    let tw_term = TermWizTerminal {
-        render_tx: TermWizTerminalRenderTty {
-            render_tx: BufWriter::new(render_pipe.write),
-            screen_size: ScreenSize {
-                cols: size.cols as usize,
-                rows: size.rows as usize,
-                xpixel: (size.pixel_width / size.cols) as usize,
-                ypixel: (size.pixel_height / size.rows) as usize,
-            },
-        },
-        input_rx,
-        renderer,
-        grab_mouse: true,
-    };
-
     async fn register_tab(
         input_tx: Sender<InputEvent>,
         render_rx: FileDescriptor,
@@ -564,6 +561,22 @@ pub async fn run<
     })
     .await?;
 
+    let tw_term = TermWizTerminal {
+        render_tx: TermWizTerminalRenderTty {
+            render_tx: BufWriter::new(render_pipe.write),
+            screen_size: ScreenSize {
+                cols: size.cols as usize,
+                rows: size.rows as usize,
+                xpixel: (size.pixel_width / size.cols) as usize,
+                ypixel: (size.pixel_height / size.rows) as usize,
+            },
+        },
+        input_rx,
+        renderer,
+        grab_mouse: true,
+        pane_id,
+    };
+
     let result = promise::spawn::spawn_into_new_thread(move || f(tw_term)).await;
 
     // Since we're typically called with an outstanding Activity token active,
@@ -1,7 +1,7 @@
 use crate::domain::{alloc_domain_id, Domain, DomainId, DomainState};
 use crate::pane::{Pane, PaneId};
 use crate::tab::TabId;
-use crate::tmux_commands::{ListAllPanes, TmuxCommand};
+use crate::tmux_commands::{DetectVersion, ListAllPanes, TmuxCommand};
 use crate::{Mux, MuxWindowBuilder};
 use async_trait::async_trait;
 use filedescriptor::FileDescriptor;
@@ -60,6 +60,8 @@ pub(crate) struct TmuxDomainState {
     pub gui_tabs: Mutex<Vec<TmuxTab>>,
     pub remote_panes: Mutex<HashMap<TmuxPaneId, RefTmuxRemotePane>>,
     pub tmux_session: Mutex<Option<TmuxSessionId>>,
+    /// The version of tmux, once it has been detected
+    pub version: Mutex<Option<ProtocolVersion>>,
 }
 
 pub struct TmuxDomain {
@@ -91,11 +93,11 @@ impl TmuxDomainState {
                     }
                     State::Idle => {}
                 },
-                Event::Output { pane, text } => {
+                Event::Output { pane, text } | Event::ExtendedOutput { pane, text, .. } => {
                     let pane_map = self.remote_panes.lock();
                     if let Some(ref_pane) = pane_map.get(pane) {
                         let mut tmux_pane = ref_pane.lock();
-                        if let Err(err) = tmux_pane.output_write.write_all(text.as_bytes()) {
+                        if let Err(err) = tmux_pane.output_write.write_all(text) {
                             log::error!("Failed to write tmux data to output: {:#}", err);
                         }
                     } else {
@@ -183,6 +185,7 @@ impl TmuxDomain {
     pub fn new(pane_id: PaneId) -> Self {
         let domain_id = alloc_domain_id();
         let mut cmd_queue = VecDeque::<Box<dyn TmuxCommand>>::new();
+        cmd_queue.push_back(Box::new(DetectVersion));
         cmd_queue.push_back(Box::new(ListAllPanes));
         let inner = Arc::new(TmuxDomainState {
             domain_id,
@@ -194,6 +197,7 @@ impl TmuxDomain {
             gui_tabs: Mutex::new(Vec::default()),
             remote_panes: Mutex::new(HashMap::default()),
             tmux_session: Mutex::new(None),
+            version: Mutex::new(None),
         });
 
         Self { inner }
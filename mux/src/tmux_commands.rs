@@ -5,11 +5,11 @@ use crate::tab::{Tab, TabId};
 use crate::tmux::{TmuxDomain, TmuxDomainState, TmuxRemotePane, TmuxTab};
 use crate::tmux_pty::{TmuxChild, TmuxPty};
 use crate::{Mux, Pane};
-use anyhow::{anyhow, Context};
+use anyhow::Context;
 use parking_lot::{Condvar, Mutex};
 use portable_pty::{MasterPty, PtySize};
 use std::collections::HashSet;
-use std::fmt::{Debug, Write};
+use std::fmt::Debug;
 use std::io::Write as _;
 use std::sync::Arc;
 use termwiz::tmux_cc::*;
@@ -20,23 +20,9 @@ pub(crate) trait TmuxCommand: Send + Debug {
     fn process_result(&self, domain_id: DomainId, result: &Guarded) -> anyhow::Result<()>;
 }
 
-#[derive(Debug)]
-pub(crate) struct PaneItem {
-    session_id: TmuxSessionId,
-    window_id: TmuxWindowId,
-    pane_id: TmuxPaneId,
-    _pane_index: u64,
-    cursor_x: u64,
-    cursor_y: u64,
-    pane_width: u64,
-    pane_height: u64,
-    pane_left: u64,
-    pane_top: u64,
-}
-
 impl TmuxDomainState {
-    /// check if a PaneItem received from ListAllPanes has been attached
-    fn check_pane_attached(&self, target: &PaneItem) -> bool {
+    /// check if a PaneInfo received from ListAllPanes has been attached
+    fn check_pane_attached(&self, target: &PaneInfo) -> bool {
         let pane_list = self.gui_tabs.lock();
         let local_tab = match pane_list
             .iter()
@@ -59,7 +45,7 @@ impl TmuxDomainState {
 
     /// after we create a tab for a remote pane, save its ID into the
     /// TmuxPane-TmuxPane tree, so we can ref it later.
-    fn add_attached_pane(&self, target: &PaneItem, tab_id: &TabId) -> anyhow::Result<()> {
+    fn add_attached_pane(&self, target: &PaneInfo, tab_id: &TabId) -> anyhow::Result<()> {
         let mut pane_list = self.gui_tabs.lock();
         let local_tab = match pane_list
             .iter_mut()
@@ -86,13 +72,13 @@ impl TmuxDomainState {
         }
     }
 
-    fn sync_pane_state(&self, panes: &[PaneItem]) -> anyhow::Result<()> {
+    fn sync_pane_state(&self, panes: &[PaneInfo]) -> anyhow::Result<()> {
         // TODO:
         // 1) iter over current session panes
         // 2) create pane if not exist
         // 3) fetch scroll buffer if new created
         // 4) update pane state if exist
-        let current_session = self.tmux_session.lock().unwrap_or(0);
+        let current_session = self.tmux_session.lock().unwrap_or_default();
         for pane in panes.iter() {
             if pane.session_id != current_session || self.check_pane_attached(&pane) {
                 continue;
@@ -155,9 +141,10 @@ impl TmuxDomainState {
                 terminal,
                 Box::new(child),
                 Box::new(pane_pty),
-                Box::new(writer),
+                writer,
                 self.domain_id,
                 "tmux pane".to_string(),
+                None,
             ));
 
             let tab = Arc::new(Tab::new(&size));
@@ -189,74 +176,35 @@ impl TmuxDomainState {
 }
 
 #[derive(Debug)]
-pub(crate) struct ListAllPanes;
-impl TmuxCommand for ListAllPanes {
+pub(crate) struct DetectVersion;
+impl TmuxCommand for DetectVersion {
     fn get_command(&self) -> String {
-        "list-panes -aF '#{session_id} #{window_id} #{pane_id} \
-            #{pane_index} #{cursor_x} #{cursor_y} #{pane_width} #{pane_height} \
-            #{pane_left} #{pane_top}'\n"
-            .to_owned()
+        CommandBuilder::display_message(ProtocolVersion::FORMAT).build()
     }
 
     fn process_result(&self, domain_id: DomainId, result: &Guarded) -> anyhow::Result<()> {
-        let mut items = vec![];
-
-        for line in result.output.split('\n') {
-            if line.is_empty() {
-                continue;
+        let version = ProtocolVersion::from_reply(result);
+        log::info!("tmux version in domain_id {}: {:?}", domain_id, version);
+        let mux = Mux::get();
+        if let Some(domain) = mux.get_domain(domain_id) {
+            if let Some(tmux_domain) = domain.downcast_ref::<TmuxDomain>() {
+                *tmux_domain.inner.version.lock() = version;
+                return Ok(());
             }
-            let mut fields = line.split(' ');
-            let session_id = fields.next().ok_or_else(|| anyhow!("missing session_id"))?;
-            let window_id = fields.next().ok_or_else(|| anyhow!("missing window_id"))?;
-            let pane_id = fields.next().ok_or_else(|| anyhow!("missing pane_id"))?;
-            let _pane_index = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_index"))?
-                .parse()?;
-            let cursor_x = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing cursor_x"))?
-                .parse()?;
-            let cursor_y = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing cursor_y"))?
-                .parse()?;
-            let pane_width = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_width"))?
-                .parse()?;
-            let pane_height = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_height"))?
-                .parse()?;
-            let pane_left = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_left"))?
-                .parse()?;
-            let pane_top = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_top"))?
-                .parse()?;
+        }
+        anyhow::bail!("Tmux domain lost");
+    }
+}
 
-            // These ids all have various sigils such as `$`, `%`, `@`,
-            // so skip those prior to parsing them
-            let session_id = session_id[1..].parse()?;
-            let window_id = window_id[1..].parse()?;
-            let pane_id = pane_id[1..].parse()?;
+#[derive(Debug)]
+pub(crate) struct ListAllPanes;
+impl TmuxCommand for ListAllPanes {
+    fn get_command(&self) -> String {
+        CommandBuilder::list_all_panes(PaneInfo::FORMAT).build()
+    }
 
-            items.push(PaneItem {
-                session_id,
-                window_id,
-                pane_id,
-                _pane_index,
-                cursor_x,
-                cursor_y,
-                pane_width,
-                pane_height,
-                pane_left,
-                pane_top,
-            });
-        }
+    fn process_result(&self, domain_id: DomainId, result: &Guarded) -> anyhow::Result<()> {
+        let items = parse_list_panes(&result.output)?;
 
         log::info!("panes in domain_id {}: {:?}", domain_id, items);
         let mux = Mux::get();
@@ -276,7 +224,7 @@ pub(crate) struct Resize {
 
 impl TmuxCommand for Resize {
     fn get_command(&self) -> String {
-        format!("refresh-client -C {}x{}\n", self.size.cols, self.size.rows)
+        CommandBuilder::refresh_client_size(self.size.cols.into(), self.size.rows.into()).build()
     }
 
     fn process_result(&self, domain_id: DomainId, result: &Guarded) -> anyhow::Result<()> {
@@ -295,7 +243,7 @@ impl TmuxCommand for Resize {
 pub(crate) struct CapturePane(TmuxPaneId);
 impl TmuxCommand for CapturePane {
     fn get_command(&self) -> String {
-        format!("capturep -p -t %{} -e -C\n", self.0)
+        CommandBuilder::capture_pane(self.0).build()
     }
 
     fn process_result(&self, domain_id: DomainId, result: &Guarded) -> anyhow::Result<()> {
@@ -332,11 +280,7 @@ pub(crate) struct SendKeys {
 }
 impl TmuxCommand for SendKeys {
     fn get_command(&self) -> String {
-        let mut s = String::new();
-        for &byte in self.keys.iter() {
-            write!(&mut s, "0x{:X} ", byte).expect("unable to write key");
-        }
-        format!("send-keys -t %{} {}\r", self.pane, s)
+        CommandBuilder::send_keys(self.pane, &self.keys).build()
     }
 
     fn process_result(&self, _domain_id: DomainId, _result: &Guarded) -> anyhow::Result<()> {
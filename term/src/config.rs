@@ -1,6 +1,8 @@
 use crate::color::ColorPalette;
 use downcast_rs::{impl_downcast, Downcast};
 use termwiz::cell::UnicodeVersion;
+use termwiz::escape::csi::Window;
+use termwiz::escape::device_attributes::TerminalFeatures;
 use termwiz::surface::{Line, SequenceNo};
 use wezterm_bidi::ParagraphDirectionHint;
 
@@ -147,6 +149,13 @@ pub trait TerminalConfiguration: Downcast + std::fmt::Debug + Send + Sync {
         3500
     }
 
+    /// Returns the maximum size of the compressed portion of the
+    /// scrollback in bytes.  When this returns Some, the scrollback
+    /// is limited by its size rather than by `scrollback_size`.
+    fn scrollback_bytes(&self) -> Option<usize> {
+        None
+    }
+
     /// Return true if the embedding application wants to use CSI-u encoding
     /// for keys that would otherwise be ambiguous.
     /// <http://www.leonerd.org.uk/hacks/fixterms/>
@@ -194,6 +203,14 @@ pub trait TerminalConfiguration: Downcast + std::fmt::Debug + Send + Sync {
         }
     }
 
+    /// Whether the terminal starts with grapheme clustering
+    /// (DECSET 2027) enabled.  When it is disabled, the width of
+    /// a sequence such as an emoji ZWJ sequence is the sum of the
+    /// widths of its codepoints, as wcwidth would compute it.
+    fn grapheme_clustering(&self) -> bool {
+        true
+    }
+
     /// Whether to normalize incoming text runs to
     /// canonical NFC unicode representation
     fn normalize_output_to_unicode_nfc(&self) -> bool {
@@ -219,9 +236,37 @@ pub trait TerminalConfiguration: Downcast + std::fmt::Debug + Send + Sync {
         false
     }
 
+    /// Whether an application may use the XTWINOPS (`CSI t`)
+    /// operation `op`.  The title reports are further subject to
+    /// `enable_title_reporting`, and the operations that would change
+    /// the window are ignored even when they are permitted.
+    fn allow_window_op(&self, _op: &Window) -> bool {
+        true
+    }
+
+    /// The largest text, in bytes, that an application can place
+    /// on the clipboard with OSC 52; larger text is ignored
+    fn osc52_max_clipboard_size(&self) -> usize {
+        16 * 1024 * 1024
+    }
+
+    /// Whether an application can read the clipboard with OSC 52.
+    /// Disabled by default, as it allows any program that can write
+    /// to the terminal, including one on a remote host, to read
+    /// whatever was copied.
+    fn enable_osc52_clipboard_reading(&self) -> bool {
+        false
+    }
+
     fn log_unknown_escape_sequences(&self) -> bool {
         false
     }
+
+    /// The description of the terminal that is reported in reply
+    /// to the device attributes requests
+    fn terminal_features(&self) -> TerminalFeatures {
+        TerminalFeatures::wezterm()
+    }
 }
 impl_downcast!(TerminalConfiguration);
 
@@ -36,6 +36,8 @@ pub use termwiz::surface::line::*;
 pub mod screen;
 pub use crate::screen::*;
 
+mod scrollback;
+
 pub mod terminal;
 pub use crate::terminal::*;
 
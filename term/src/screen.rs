@@ -1,8 +1,8 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
 use super::*;
 use crate::config::BidiMode;
+use crate::scrollback::LineStore;
 use log::debug;
-use std::collections::VecDeque;
 use std::sync::Arc;
 use termwiz::input::KeyboardEncoding;
 use termwiz::surface::SequenceNo;
@@ -14,14 +14,14 @@ use termwiz::surface::SequenceNo;
 #[derive(Debug, Clone)]
 pub struct Screen {
     /// Holds the line data that comprises the screen contents.
-    /// This is allocated with capacity for the entire scrollback.
+    /// Older lines of scrollback are held in compressed form.
     /// The last N lines are the visible lines, with those prior being
     /// the lines that have scrolled off the top of the screen.
     /// Index 0 is the topmost line of the screen/scrollback (depending
     /// on the current window size) and will be the first line to be
     /// popped off the front of the screen when a new line is added that
     /// would otherwise have exceeded the line capacity
-    lines: VecDeque<Line>,
+    lines: LineStore,
 
     /// Whenever we scroll a line off the top of the scrollback, we
     /// increment this.  We use this offset to translate between
@@ -45,10 +45,14 @@ pub struct Screen {
 }
 
 fn scrollback_size(config: &Arc<dyn TerminalConfiguration>, allow_scrollback: bool) -> usize {
-    if allow_scrollback {
-        config.scrollback_size()
-    } else {
+    if !allow_scrollback {
         0
+    } else if config.scrollback_bytes().is_some() {
+        // The scrollback is limited by its size in bytes,
+        // rather than by its number of lines
+        usize::MAX / 2
+    } else {
+        config.scrollback_size()
     }
 }
 
@@ -66,8 +70,7 @@ impl Screen {
         let physical_rows = size.rows.max(1);
         let physical_cols = size.cols.max(1);
 
-        let mut lines =
-            VecDeque::with_capacity(physical_rows + scrollback_size(config, allow_scrollback));
+        let mut lines = LineStore::new(physical_rows);
         for _ in 0..physical_rows {
             let mut line = Line::new(seqno);
             bidi_mode.apply_to_line(&mut line, seqno);
@@ -94,6 +97,17 @@ impl Screen {
         scrollback_size(&self.config, self.allow_scrollback)
     }
 
+    /// Discard the oldest lines of scrollback until the compressed
+    /// portion fits within the configured `scrollback_bytes`
+    fn trim_scrollback_to_budget(&mut self) {
+        if !self.allow_scrollback {
+            return;
+        }
+        if let Some(budget) = self.config.scrollback_bytes() {
+            self.stable_row_index_offset += self.lines.trim_to_budget(budget);
+        }
+    }
+
     fn rewrap_lines(
         &mut self,
         physical_cols: usize,
@@ -102,12 +116,13 @@ impl Screen {
         cursor_y: PhysRowIndex,
         seqno: SequenceNo,
     ) -> (usize, PhysRowIndex) {
-        let mut rewrapped = VecDeque::new();
+        let mut rewrapped = LineStore::new(physical_rows);
         let mut logical_line: Option<Line> = None;
         let mut logical_cursor_x: Option<usize> = None;
         let mut adjusted_cursor = (cursor_x, cursor_y);
 
-        for (phys_idx, mut line) in self.lines.drain(..).enumerate() {
+        let lines = std::mem::replace(&mut self.lines, LineStore::new(physical_rows));
+        for (phys_idx, mut line) in lines.into_lines().enumerate() {
             line.update_last_change_seqno(seqno);
             let was_wrapped = line.last_cell_was_wrapped();
 
@@ -176,7 +191,7 @@ impl Screen {
         // if the bottom line(s) are whitespace, we'll prune those
         // out first in the rewrap case so that we don't lose any
         // real information off the top of the scrollback
-        let capacity = physical_rows + self.scrollback_size();
+        let capacity = physical_rows.saturating_add(self.scrollback_size());
         while self.lines.len() > capacity
             && self.lines.back().map(Line::is_whitespace).unwrap_or(false)
         {
@@ -230,7 +245,7 @@ impl Screen {
             if self.allow_scrollback {
                 self.rewrap_lines(physical_cols, physical_rows, cursor.x, cursor_phys, seqno)
             } else {
-                for line in &mut self.lines {
+                for line in self.lines.iter_mut() {
                     if physical_cols < self.physical_cols {
                         // Do a simple prune of the lines instead
                         line.resize(physical_cols, seqno);
@@ -245,11 +260,7 @@ impl Screen {
             (cursor.x, cursor_phys)
         };
 
-        let capacity = physical_rows + self.scrollback_size();
-        let current_capacity = self.lines.capacity();
-        if capacity > current_capacity {
-            self.lines.reserve(capacity - current_capacity);
-        }
+        self.lines.set_hot_lines(physical_rows);
 
         // If we resized wider and the rewrap resulted in fewer
         // lines than the viewport size, or we resized taller,
@@ -313,6 +324,7 @@ impl Screen {
 
         self.physical_rows = physical_rows;
         self.physical_cols = physical_cols;
+        self.trim_scrollback_to_budget();
         CursorPosition {
             x: cursor_x,
             y: new_cursor_y,
@@ -333,6 +345,12 @@ impl Screen {
         self.lines.len()
     }
 
+    /// Returns an estimate of the memory used by the lines of
+    /// this screen, including its scrollback
+    pub fn estimated_bytes(&self) -> usize {
+        self.lines.estimated_bytes()
+    }
+
     /// Sets a line dirty.  The line is relative to the visible origin.
     #[inline]
     pub fn dirty_line(&mut self, idx: VisibleRowIndex, seqno: SequenceNo) {
@@ -346,20 +364,19 @@ impl Screen {
     #[cfg(test)]
     pub fn visible_lines(&self) -> Vec<Line> {
         let line_idx = self.lines.len() - self.physical_rows;
-        let mut lines = Vec::new();
-        for line in self.lines.iter().skip(line_idx) {
-            if lines.len() >= self.physical_rows {
-                break;
-            }
-            lines.push(line.clone());
-        }
-        lines
+        self.lines
+            .iter_range(line_idx..line_idx + self.physical_rows)
+            .cloned()
+            .collect()
     }
 
     /// Returns a copy of the lines in the screen (including scrollback)
     #[cfg(test)]
     pub fn all_lines(&self) -> Vec<Line> {
-        self.lines.iter().map(|l| l.clone()).collect()
+        self.lines
+            .iter_range(0..self.lines.len())
+            .cloned()
+            .collect()
     }
 
     pub fn insert_cell(
@@ -673,7 +690,7 @@ impl Screen {
             // Remove the scrolled lines
             num_rows
         } else {
-            let max_allowed = self.physical_rows + self.scrollback_size();
+            let max_allowed = self.physical_rows.saturating_add(self.scrollback_size());
             if self.lines.len() + num_rows >= max_allowed {
                 (self.lines.len() + num_rows) - max_allowed
             } else {
@@ -747,15 +764,17 @@ impl Screen {
                 self.lines.insert(phys_scroll.end, line);
             }
         }
+
+        if scroll_region.start == 0 {
+            self.trim_scrollback_to_budget();
+        }
     }
 
     pub fn erase_scrollback(&mut self) {
         let len = self.lines.len();
         let to_clear = len - self.physical_rows;
-        for _ in 0..to_clear {
-            self.lines.pop_front();
-            self.stable_row_index_offset += 1;
-        }
+        self.lines.drop_front(to_clear);
+        self.stable_row_index_offset += to_clear;
     }
 
     /// ```text
@@ -886,12 +905,7 @@ impl Screen {
     }
 
     pub fn lines_in_phys_range(&self, phys_range: Range<PhysRowIndex>) -> Vec<Line> {
-        self.lines
-            .iter()
-            .skip(phys_range.start)
-            .take(phys_range.end - phys_range.start)
-            .cloned()
-            .collect()
+        self.lines.iter_range(phys_range).cloned().collect()
     }
 
     pub fn get_changed_stable_rows(
@@ -901,13 +915,7 @@ impl Screen {
     ) -> Vec<StableRowIndex> {
         let phys = self.stable_range(&stable_lines);
         let mut set = vec![];
-        for (idx, line) in self
-            .lines
-            .iter()
-            .enumerate()
-            .skip(phys.start)
-            .take(phys.end - phys.start)
-        {
+        for (idx, line) in phys.clone().zip(self.lines.iter_range(phys)) {
             if line.changed_since(seqno) {
                 set.push(self.phys_to_stable_row_index(idx))
             }
@@ -919,19 +927,7 @@ impl Screen {
     where
         F: FnMut(&[&Line]),
     {
-        let (first, second) = self.lines.as_slices();
-        let first_range = 0..first.len();
-        let second_range = first.len()..first.len() + second.len();
-        let first_range = phys_intersection(&first_range, &phys_range);
-        let second_range = phys_intersection(&second_range, &phys_range);
-
-        let mut lines: Vec<&Line> = Vec::with_capacity(phys_range.end - phys_range.start);
-        for line in &first[first_range] {
-            lines.push(line);
-        }
-        for line in &second[second_range] {
-            lines.push(line);
-        }
+        let lines: Vec<&Line> = self.lines.iter_range(phys_range).collect();
         func(&lines)
     }
 
@@ -939,22 +935,7 @@ impl Screen {
     where
         F: FnMut(&mut [&mut Line]),
     {
-        let (first, second) = self.lines.as_mut_slices();
-        let first_len = first.len();
-        let first_range = 0..first.len();
-        let second_range = first.len()..first.len() + second.len();
-        let first_range = phys_intersection(&first_range, &phys_range);
-        let second_range = phys_intersection(&second_range, &phys_range);
-
-        let mut lines: Vec<&mut Line> = Vec::with_capacity(phys_range.end - phys_range.start);
-        for line in &mut first[first_range] {
-            lines.push(line);
-        }
-        for line in &mut second[second_range.start.saturating_sub(first_len)
-            ..second_range.end.saturating_sub(first_len)]
-        {
-            lines.push(line);
-        }
+        let mut lines: Vec<&mut Line> = self.lines.iter_range_mut(phys_range).collect();
         func(&mut lines)
     }
 
@@ -962,18 +943,22 @@ impl Screen {
     where
         F: FnMut(usize, &Line),
     {
-        for (idx, line) in self.lines.iter().enumerate() {
+        let mut idx = 0;
+        self.lines.for_each(|line| {
             f(idx, line);
-        }
+            idx += 1;
+        });
     }
 
     pub fn for_each_phys_line_mut<F>(&mut self, mut f: F)
     where
         F: FnMut(usize, &mut Line),
     {
-        for (idx, line) in self.lines.iter_mut().enumerate() {
+        let mut idx = 0;
+        self.lines.for_each_mut(|line| {
             f(idx, line);
-        }
+            idx += 1;
+        });
     }
 
     pub fn for_each_logical_line_in_stable_range_mut<F>(
@@ -1131,13 +1116,3 @@ impl Screen {
         }
     }
 }
-
-fn phys_intersection(r1: &Range<PhysRowIndex>, r2: &Range<PhysRowIndex>) -> Range<PhysRowIndex> {
-    let start = r1.start.max(r2.start);
-    let end = r1.end.min(r2.end);
-    if end > start {
-        start..end
-    } else {
-        0..0
-    }
-}
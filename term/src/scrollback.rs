@@ -0,0 +1,779 @@
+//! Storage for the lines of a screen, including its scrollback.
+//!
+//! The most recent lines, which include the visible portion of the
+//! screen, are held in a plain `VecDeque`.  As lines scroll further
+//! back they are grouped into fixed size blocks that are serialized
+//! and compressed, which reduces the memory required for a large
+//! scrollback by an order of magnitude or more.  Blocks are
+//! transparently decompressed when they are accessed, and a small
+//! number of them are kept decompressed so that scrolling through
+//! the history doesn't repeatedly decompress the same block.
+use crate::PhysRowIndex;
+use once_cell::sync::OnceCell;
+use std::collections::VecDeque;
+use std::ops::{Index, IndexMut, Range};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use termwiz::surface::line::Line;
+
+/// The number of lines held in each compressed block
+const BLOCK_LINES: usize = 256;
+
+/// When more than this many blocks are held in decompressed form,
+/// the least recently accessed of them are compressed again
+const MAX_RESIDENT_BLOCKS: usize = 16;
+
+struct Block {
+    /// The compressed form of the lines.  This is None if the block
+    /// is pinned, or if its lines have been modified since they
+    /// were compressed.
+    frozen: Option<Vec<u8>>,
+    /// The decompressed lines.  For a frozen block this is only
+    /// populated while the block is resident.
+    lines: OnceCell<Vec<Line>>,
+    /// Pinned blocks are never compressed.  That is the case for
+    /// blocks that have images attached to their cells, as well as
+    /// for all blocks when serialization support is not compiled in.
+    pinned: bool,
+    /// The estimated number of bytes used by the block when it
+    /// is not resident
+    bytes: usize,
+    last_access: AtomicUsize,
+}
+
+impl Block {
+    fn freeze(mut lines: Vec<Line>) -> Self {
+        let mut block = Self {
+            frozen: None,
+            lines: OnceCell::new(),
+            pinned: false,
+            bytes: 0,
+            last_access: AtomicUsize::new(0),
+        };
+        for line in &mut lines {
+            // Compute the zones now, so that they are retained in the
+            // compressed form and don't require modifying the line later
+            line.semantic_zone_ranges();
+        }
+        block.compress(lines);
+        block
+    }
+
+    /// Replace the decompressed lines with their compressed form.
+    /// If that isn't possible, the block becomes pinned.
+    fn compress(&mut self, lines: Vec<Line>) {
+        // The image data is shared with the cells and is not something
+        // that we want to duplicate via serialization
+        let compressed = if lines.iter().any(line_has_images) {
+            None
+        } else {
+            compress_lines(&lines)
+        };
+        match compressed {
+            Some(data) => {
+                self.bytes = data.len();
+                self.frozen.replace(data);
+                self.lines = OnceCell::new();
+            }
+            None => {
+                self.bytes = lines.iter().map(estimated_line_bytes).sum();
+                self.pinned = true;
+                self.lines = OnceCell::with_value(lines);
+            }
+        }
+    }
+
+    /// Returns true if the block holds decompressed lines that can
+    /// be released by calling `evict`
+    fn is_resident(&self) -> bool {
+        !self.pinned && self.lines.get().is_some()
+    }
+
+    /// Release the decompressed lines, compressing them first if
+    /// they have been modified
+    fn evict(&mut self) {
+        if self.pinned {
+            return;
+        }
+        if let Some(lines) = self.lines.take() {
+            if self.frozen.is_none() {
+                self.compress(lines);
+            }
+        }
+    }
+
+    fn decompress(&self) -> Vec<Line> {
+        let data = self.frozen.as_deref().unwrap_or(&[]);
+        decompress_lines(data).unwrap_or_else(|err| {
+            log::error!("failed to decompress scrollback: {:#}", err);
+            vec![Line::new(0); BLOCK_LINES]
+        })
+    }
+
+    fn into_lines(mut self) -> Vec<Line> {
+        match self.lines.take() {
+            Some(lines) => lines,
+            None => self.decompress(),
+        }
+    }
+}
+
+impl Clone for Block {
+    fn clone(&self) -> Self {
+        // There is no need to duplicate the decompressed lines
+        // if we have their compressed form
+        let lines = match &self.frozen {
+            Some(_) => OnceCell::new(),
+            None => self.lines.clone(),
+        };
+        Self {
+            frozen: self.frozen.clone(),
+            lines,
+            pinned: self.pinned,
+            bytes: self.bytes,
+            last_access: AtomicUsize::new(self.last_access.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// A rough estimate of the heap used by a line that is not compressed
+fn estimated_line_bytes(line: &Line) -> usize {
+    std::mem::size_of::<Line>() + line.len() * std::mem::size_of::<termwiz::cell::Cell>()
+}
+
+fn line_has_images(line: &Line) -> bool {
+    line.visible_cells()
+        .any(|cell| cell.attrs().images().is_some())
+}
+
+#[cfg(feature = "use_serde")]
+fn compress_lines(lines: &[Line]) -> Option<Vec<u8>> {
+    use serde::Serialize;
+
+    let compress = || -> anyhow::Result<Vec<u8>> {
+        let mut compressed = vec![];
+        let mut encoder = zstd::Encoder::new(&mut compressed, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+        let mut encode = varbincode::Serializer::new(&mut encoder);
+        lines.serialize(&mut encode)?;
+        drop(encode);
+        encoder.finish()?;
+        Ok(compressed)
+    };
+
+    match compress() {
+        Ok(compressed) => Some(compressed),
+        Err(err) => {
+            log::error!("failed to compress scrollback: {:#}", err);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "use_serde")]
+fn decompress_lines(data: &[u8]) -> anyhow::Result<Vec<Line>> {
+    let mut decoder = zstd::Decoder::new(data)?;
+    let mut decode = varbincode::Deserializer::new(&mut decoder);
+    Ok(serde::Deserialize::deserialize(&mut decode)?)
+}
+
+#[cfg(not(feature = "use_serde"))]
+fn compress_lines(_lines: &[Line]) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(not(feature = "use_serde"))]
+fn decompress_lines(_data: &[u8]) -> anyhow::Result<Vec<Line>> {
+    anyhow::bail!("scrollback compression requires the use_serde feature")
+}
+
+/// Holds the lines of a Screen, indexed by PhysRowIndex.
+/// This offers a subset of the `VecDeque` interface; the lines
+/// towards the end are stored in `hot`, while those that precede
+/// them are held in compressed blocks.
+pub struct LineStore {
+    blocks: VecDeque<Block>,
+    /// The number of lines that have been removed from the
+    /// start of the first block
+    front_skip: usize,
+    hot: VecDeque<Line>,
+    /// The minimum number of lines to keep in `hot`
+    hot_lines: usize,
+    /// The sum of `Block::bytes` for all blocks
+    cold_bytes: usize,
+    /// The number of blocks for which `Block::is_resident` is true
+    resident: AtomicUsize,
+    clock: AtomicUsize,
+}
+
+impl Clone for LineStore {
+    fn clone(&self) -> Self {
+        let blocks: VecDeque<Block> = self.blocks.iter().cloned().collect();
+        let resident = blocks.iter().filter(|b| b.is_resident()).count();
+        Self {
+            blocks,
+            front_skip: self.front_skip,
+            hot: self.hot.clone(),
+            hot_lines: self.hot_lines,
+            cold_bytes: self.cold_bytes,
+            resident: AtomicUsize::new(resident),
+            clock: AtomicUsize::new(self.clock.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl std::fmt::Debug for LineStore {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("LineStore")
+            .field("len", &self.len())
+            .field("blocks", &self.blocks.len())
+            .field("cold_bytes", &self.cold_bytes)
+            .field("hot", &self.hot)
+            .finish()
+    }
+}
+
+impl LineStore {
+    /// Create an empty store that will keep at least `hot_lines`
+    /// of its most recent lines uncompressed
+    pub fn new(hot_lines: usize) -> Self {
+        Self {
+            blocks: VecDeque::new(),
+            front_skip: 0,
+            hot: VecDeque::new(),
+            hot_lines,
+            cold_bytes: 0,
+            resident: AtomicUsize::new(0),
+            clock: AtomicUsize::new(0),
+        }
+    }
+
+    /// Change the minimum number of lines to keep uncompressed.
+    /// This is typically the height of the screen, so that the
+    /// lines that are modified by the terminal are directly accessible.
+    pub fn set_hot_lines(&mut self, hot_lines: usize) {
+        self.hot_lines = hot_lines;
+        while self.hot.len() < self.hot_lines && !self.blocks.is_empty() {
+            self.thaw_blocks_from(self.blocks.len() - 1);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cold_len() + self.hot.len()
+    }
+
+    fn cold_len(&self) -> usize {
+        self.blocks.len() * BLOCK_LINES - self.front_skip
+    }
+
+    /// Translate an index in the cold portion into a block index
+    /// and the offset of the line within that block
+    fn locate(&self, idx: PhysRowIndex) -> (usize, usize) {
+        let idx = idx + self.front_skip;
+        (idx / BLOCK_LINES, idx % BLOCK_LINES)
+    }
+
+    fn touch(&self, block: &Block) {
+        block.last_access.store(
+            self.clock.fetch_add(1, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+    }
+
+    fn block_lines(&self, block_idx: usize) -> &[Line] {
+        let block = &self.blocks[block_idx];
+        self.touch(block);
+        block.lines.get_or_init(|| {
+            self.resident.fetch_add(1, Ordering::Relaxed);
+            block.decompress()
+        })
+    }
+
+    /// Returns the lines of a block for modification; the block
+    /// will need to be compressed again when it is evicted
+    fn block_lines_mut(&mut self, block_idx: usize) -> &mut Vec<Line> {
+        let block = &mut self.blocks[block_idx];
+        block.last_access.store(
+            self.clock.fetch_add(1, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        if block.lines.get().is_none() {
+            let lines = block.decompress();
+            block.lines = OnceCell::with_value(lines);
+            *self.resident.get_mut() += 1;
+        }
+        if !block.pinned {
+            block.frozen.take();
+        }
+        block.lines.get_mut().expect("lines were populated above")
+    }
+
+    fn forget_block(&mut self, block: &Block) {
+        self.cold_bytes -= block.bytes;
+        if block.is_resident() {
+            *self.resident.get_mut() -= 1;
+        }
+    }
+
+    /// Move the lines from the block at `block_idx` onwards
+    /// back into the hot portion
+    fn thaw_blocks_from(&mut self, block_idx: usize) {
+        while self.blocks.len() > block_idx {
+            let block = self.blocks.pop_back().expect("blocks is not empty");
+            self.forget_block(&block);
+            let skip = if self.blocks.is_empty() {
+                std::mem::take(&mut self.front_skip)
+            } else {
+                0
+            };
+            for line in block.into_lines().into_iter().skip(skip).rev() {
+                self.hot.push_front(line);
+            }
+        }
+    }
+
+    /// Compress lines from the hot portion, and release any excess
+    /// decompressed blocks
+    fn compact(&mut self) {
+        while self.hot.len() > self.hot_lines + BLOCK_LINES {
+            let lines: Vec<Line> = self.hot.drain(..BLOCK_LINES).collect();
+            let block = Block::freeze(lines);
+            self.cold_bytes += block.bytes;
+            self.blocks.push_back(block);
+        }
+
+        if *self.resident.get_mut() > MAX_RESIDENT_BLOCKS {
+            let mut resident: Vec<(usize, usize)> = self
+                .blocks
+                .iter()
+                .enumerate()
+                .filter(|(_, block)| block.is_resident())
+                .map(|(idx, block)| (block.last_access.load(Ordering::Relaxed), idx))
+                .collect();
+            resident.sort_unstable_by(|a, b| b.cmp(a));
+            for &(_, idx) in resident.iter().skip(MAX_RESIDENT_BLOCKS / 2) {
+                let block = &mut self.blocks[idx];
+                self.cold_bytes -= block.bytes;
+                block.evict();
+                self.cold_bytes += block.bytes;
+                if !block.is_resident() {
+                    *self.resident.get_mut() -= 1;
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, idx: PhysRowIndex) -> Option<&Line> {
+        let cold_len = self.cold_len();
+        if idx < cold_len {
+            let (block_idx, offset) = self.locate(idx);
+            Some(&self.block_lines(block_idx)[offset])
+        } else {
+            self.hot.get(idx - cold_len)
+        }
+    }
+
+    pub fn get_mut(&mut self, idx: PhysRowIndex) -> Option<&mut Line> {
+        let cold_len = self.cold_len();
+        if idx < cold_len {
+            let (block_idx, offset) = self.locate(idx);
+            Some(&mut self.block_lines_mut(block_idx)[offset])
+        } else {
+            self.hot.get_mut(idx - cold_len)
+        }
+    }
+
+    pub fn back(&self) -> Option<&Line> {
+        match self.hot.back() {
+            Some(line) => Some(line),
+            None => self.len().checked_sub(1).and_then(|idx| self.get(idx)),
+        }
+    }
+
+    pub fn push_back(&mut self, line: Line) {
+        self.hot.push_back(line);
+        self.compact();
+    }
+
+    pub fn pop_back(&mut self) -> Option<Line> {
+        if self.hot.is_empty() && !self.blocks.is_empty() {
+            self.thaw_blocks_from(self.blocks.len() - 1);
+        }
+        self.hot.pop_back()
+    }
+
+    pub fn pop_front(&mut self) -> Option<Line> {
+        if self.blocks.is_empty() {
+            return self.hot.pop_front();
+        }
+        let line = self.get(0).cloned();
+        self.drop_front(1);
+        line
+    }
+
+    /// Remove up to `count` lines from the front, without needing
+    /// to decompress them
+    pub fn drop_front(&mut self, count: usize) {
+        let mut count = count.min(self.len());
+        while count > 0 && !self.blocks.is_empty() {
+            let remaining = BLOCK_LINES - self.front_skip;
+            if count < remaining {
+                self.front_skip += count;
+                return;
+            }
+            let block = self.blocks.pop_front().expect("blocks is not empty");
+            self.forget_block(&block);
+            self.front_skip = 0;
+            count -= remaining;
+        }
+        self.hot.drain(..count);
+    }
+
+    /// Remove blocks from the front until the compressed portion
+    /// fits within `budget` bytes.  The hot portion is retained.
+    /// Returns the number of lines that were removed.
+    pub fn trim_to_budget(&mut self, budget: usize) -> usize {
+        let mut removed = 0;
+        while self.cold_bytes > budget && !self.blocks.is_empty() {
+            let count = BLOCK_LINES - self.front_skip;
+            self.drop_front(count);
+            removed += count;
+        }
+        removed
+    }
+
+    /// A rough estimate of the memory used by the lines: the
+    /// compressed blocks, the lines of any blocks that are currently
+    /// decompressed, and the hot lines
+    pub fn estimated_bytes(&self) -> usize {
+        let resident: usize = self
+            .blocks
+            .iter()
+            .filter(|block| block.is_resident())
+            .filter_map(|block| block.lines.get())
+            .flat_map(|lines| lines.iter())
+            .map(estimated_line_bytes)
+            .sum();
+        let hot: usize = self.hot.iter().map(estimated_line_bytes).sum();
+        self.cold_bytes + resident + hot
+    }
+
+    pub fn insert(&mut self, idx: PhysRowIndex, line: Line) {
+        let cold_len = self.cold_len();
+        if idx < cold_len {
+            let (block_idx, _) = self.locate(idx);
+            self.thaw_blocks_from(block_idx);
+        }
+        let cold_len = self.cold_len();
+        self.hot.insert(idx - cold_len, line);
+    }
+
+    pub fn remove(&mut self, idx: PhysRowIndex) -> Option<Line> {
+        if idx == 0 {
+            return self.pop_front();
+        }
+        let cold_len = self.cold_len();
+        if idx < cold_len {
+            let (block_idx, _) = self.locate(idx);
+            self.thaw_blocks_from(block_idx);
+        }
+        let cold_len = self.cold_len();
+        self.hot.remove(idx - cold_len)
+    }
+
+    /// Iterate the lines in `range`, which is clamped to the
+    /// bounds of the store
+    pub fn iter_range(&self, range: Range<PhysRowIndex>) -> impl Iterator<Item = &Line> + '_ {
+        let len = self.len();
+        let end = range.end.min(len);
+        let start = range.start.min(end);
+        let cold_len = self.cold_len();
+
+        let cold_start = start.min(cold_len);
+        let cold_end = end.min(cold_len);
+        let cold = if cold_start < cold_end {
+            let (first_block, first_offset) = self.locate(cold_start);
+            let (last_block, last_offset) = self.locate(cold_end - 1);
+            Some((first_block..=last_block).flat_map(move |block_idx| {
+                let lines = self.block_lines(block_idx);
+                let start = if block_idx == first_block {
+                    first_offset
+                } else {
+                    0
+                };
+                let end = if block_idx == last_block {
+                    last_offset + 1
+                } else {
+                    BLOCK_LINES
+                };
+                lines[start..end].iter()
+            }))
+        } else {
+            None
+        };
+
+        let hot = start.max(cold_len) - cold_len..end.max(cold_len) - cold_len;
+        cold.into_iter().flatten().chain(self.hot.range(hot))
+    }
+
+    /// Iterate the lines in `range` for modification
+    pub fn iter_range_mut(
+        &mut self,
+        range: Range<PhysRowIndex>,
+    ) -> impl Iterator<Item = &mut Line> + '_ {
+        let len = self.len();
+        let end = range.end.min(len);
+        let start = range.start.min(end);
+        let cold_len = self.cold_len();
+
+        let cold_start = start.min(cold_len);
+        let cold_end = end.min(cold_len);
+        let (first_block, first_offset) = self.locate(cold_start);
+        let (last_block, last_offset) = self.locate(cold_end.max(1) - 1);
+        let blocks = if cold_start < cold_end {
+            for block_idx in first_block..=last_block {
+                self.block_lines_mut(block_idx);
+            }
+            first_block..last_block + 1
+        } else {
+            0..0
+        };
+
+        let hot = start.max(cold_len) - cold_len..end.max(cold_len) - cold_len;
+        let block_indices = blocks.clone();
+        self.blocks
+            .range_mut(blocks)
+            .zip(block_indices)
+            .flat_map(move |(block, block_idx)| {
+                let lines = block.lines.get_mut().expect("block was thawed above");
+                let start = if block_idx == first_block {
+                    first_offset
+                } else {
+                    0
+                };
+                let end = if block_idx == last_block {
+                    last_offset + 1
+                } else {
+                    BLOCK_LINES
+                };
+                lines[start..end].iter_mut()
+            })
+            .chain(self.hot.range_mut(hot))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Line> + '_ {
+        let len = self.len();
+        self.iter_range_mut(0..len)
+    }
+
+    /// Call `f` for each line.  Unlike `iter`, blocks that are
+    /// not already resident are decompressed only for the duration
+    /// of the call, so visiting the entire scrollback doesn't
+    /// inflate the memory usage.
+    pub fn for_each<F: FnMut(&Line)>(&self, mut f: F) {
+        for (block_idx, block) in self.blocks.iter().enumerate() {
+            let skip = if block_idx == 0 { self.front_skip } else { 0 };
+            match block.lines.get() {
+                Some(lines) => lines[skip..].iter().for_each(&mut f),
+                None => block.decompress()[skip..].iter().for_each(&mut f),
+            }
+        }
+        self.hot.iter().for_each(f);
+    }
+
+    /// Call `f` for each line, allowing it to be modified.
+    /// Blocks that are not already resident are decompressed
+    /// and then compressed again after `f` has been called for
+    /// their lines.
+    pub fn for_each_mut<F: FnMut(&mut Line)>(&mut self, mut f: F) {
+        for block_idx in 0..self.blocks.len() {
+            let skip = if block_idx == 0 { self.front_skip } else { 0 };
+            let block = &mut self.blocks[block_idx];
+            if block.lines.get().is_some() {
+                self.block_lines_mut(block_idx)[skip..]
+                    .iter_mut()
+                    .for_each(&mut f);
+            } else {
+                let mut lines = block.decompress();
+                lines[skip..].iter_mut().for_each(&mut f);
+                self.cold_bytes -= block.bytes;
+                block.compress(lines);
+                self.cold_bytes += block.bytes;
+            }
+        }
+        self.hot.iter_mut().for_each(f);
+    }
+
+    /// Consume the store, yielding each of its lines
+    pub fn into_lines(self) -> impl Iterator<Item = Line> {
+        let front_skip = self.front_skip;
+        self.blocks
+            .into_iter()
+            .enumerate()
+            .flat_map(move |(block_idx, block)| {
+                let skip = if block_idx == 0 { front_skip } else { 0 };
+                block.into_lines().into_iter().skip(skip)
+            })
+            .chain(self.hot)
+    }
+}
+
+impl Index<PhysRowIndex> for LineStore {
+    type Output = Line;
+
+    fn index(&self, idx: PhysRowIndex) -> &Line {
+        self.get(idx).expect("line index out of bounds")
+    }
+}
+
+impl IndexMut<PhysRowIndex> for LineStore {
+    fn index_mut(&mut self, idx: PhysRowIndex) -> &mut Line {
+        self.get_mut(idx).expect("line index out of bounds")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::cell::CellAttributes;
+
+    fn line(n: usize) -> Line {
+        Line::from_text(&n.to_string(), &CellAttributes::default(), 0, None)
+    }
+
+    fn store(count: usize) -> LineStore {
+        let mut store = LineStore::new(24);
+        for n in 0..count {
+            store.push_back(line(n));
+        }
+        store
+    }
+
+    fn text(store: &LineStore) -> Vec<String> {
+        store
+            .iter_range(0..store.len())
+            .map(|line| line.as_str().into_owned())
+            .collect()
+    }
+
+    fn expected(range: Range<usize>) -> Vec<String> {
+        range.map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn compacts_into_blocks() {
+        let store = store(10_000);
+        assert_eq!(store.len(), 10_000);
+        assert!(!store.blocks.is_empty());
+        assert!(store.hot.len() <= 24 + BLOCK_LINES);
+        assert!(store.hot.len() >= 24);
+
+        assert_eq!(text(&store), expected(0..10_000));
+        assert_eq!(store[0].as_str(), "0");
+        assert_eq!(store[4321].as_str(), "4321");
+        assert_eq!(store.back().unwrap().as_str(), "9999");
+
+        let mut visited = vec![];
+        store.for_each(|line| visited.push(line.as_str().into_owned()));
+        assert_eq!(visited, expected(0..10_000));
+
+        let range: Vec<String> = store
+            .iter_range(250..1030)
+            .map(|line| line.as_str().into_owned())
+            .collect();
+        assert_eq!(range, expected(250..1030));
+    }
+
+    #[test]
+    fn remove_from_front() {
+        let mut store = store(2000);
+        assert_eq!(store.pop_front().unwrap().as_str(), "0");
+        store.drop_front(300);
+        assert_eq!(store.len(), 1699);
+        assert_eq!(text(&store), expected(301..2000));
+
+        assert_eq!(store.remove(0).unwrap().as_str(), "301");
+        store.drop_front(2000);
+        assert_eq!(store.len(), 0);
+    }
+
+    #[test]
+    fn modify_cold_lines() {
+        let mut store = store(5000);
+        store.insert(10, line(10));
+        assert_eq!(store.remove(11).unwrap().as_str(), "10");
+        assert_eq!(text(&store), expected(0..5000));
+
+        store[1000] = line(42);
+        for line in store.iter_range_mut(2000..2002) {
+            *line = Line::from_text("x", &CellAttributes::default(), 0, None);
+        }
+        // Touch enough blocks to cause the modified ones to be
+        // compressed again
+        for idx in (0..store.len()).step_by(BLOCK_LINES) {
+            store.get(idx);
+        }
+        store.push_back(line(5000));
+        assert!(store.resident.load(Ordering::Relaxed) <= MAX_RESIDENT_BLOCKS);
+
+        assert_eq!(store[1000].as_str(), "42");
+        assert_eq!(store[2000].as_str(), "x");
+        assert_eq!(store[2001].as_str(), "x");
+        assert_eq!(store[2002].as_str(), "2002");
+
+        store.for_each_mut(|line| {
+            if line.as_str() == "x" {
+                *line = Line::from_text("y", &CellAttributes::default(), 0, None);
+            }
+        });
+        assert_eq!(store[2001].as_str(), "y");
+
+        let lines: Vec<String> = store
+            .clone()
+            .into_lines()
+            .map(|line| line.as_str().into_owned())
+            .collect();
+        assert_eq!(lines, text(&store));
+    }
+
+    #[test]
+    fn trim_to_budget() {
+        let mut store = store(5000);
+        let total = store.cold_bytes;
+        let removed = store.trim_to_budget(total / 2);
+        assert!(removed > 0);
+        assert_eq!(removed % BLOCK_LINES, 0);
+        assert!(store.cold_bytes <= total / 2);
+        assert_eq!(text(&store), expected(removed..5000));
+
+        let len = store.len();
+        let removed = store.trim_to_budget(0);
+        assert_eq!(store.cold_bytes, 0);
+        assert_eq!(store.len(), len - removed);
+        assert_eq!(text(&store), expected(5000 - store.len()..5000));
+    }
+
+    #[test]
+    fn estimated_bytes() {
+        let mut store = store(5000);
+        let hot: usize = store.hot.iter().map(estimated_line_bytes).sum();
+        assert_eq!(store.estimated_bytes(), store.cold_bytes + hot);
+
+        // Decompressing a block for access is accounted for
+        store.get(0);
+        let resident = store.resident.load(Ordering::Relaxed);
+        assert_eq!(
+            store.estimated_bytes() > store.cold_bytes + hot,
+            resident > 0
+        );
+
+        store.drop_front(store.len());
+        assert_eq!(store.estimated_bytes(), 0);
+    }
+
+    #[test]
+    fn grow_hot_lines() {
+        let mut store = store(1000);
+        store.set_hot_lines(900);
+        assert!(store.hot.len() >= 900);
+        assert_eq!(text(&store), expected(0..1000));
+    }
+}
@@ -1,7 +1,9 @@
 use super::*;
 use crate::terminalstate::performer::Performer;
 use std::sync::Arc;
+use termwiz::escape::osc::Selection;
 use termwiz::escape::parser::Parser;
+use termwiz::escape::{ActionBatch, OperatingSystemCommand};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
@@ -16,6 +18,55 @@ pub trait Clipboard: Send + Sync {
         selection: ClipboardSelection,
         data: Option<String>,
     ) -> anyhow::Result<()>;
+
+    /// Returns the contents of `selection`, for an application that
+    /// queries it with OSC 52.  The default implementation has none.
+    fn get_contents(&self, _selection: ClipboardSelection) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Answers an application that queried the clipboard with OSC 52.
+    /// Returns the reply to send to the application, if there is one.
+    /// An implementation that can only read the clipboard asynchronously
+    /// can return `None` and send `query.reply()` to the application
+    /// itself once it has the contents.
+    /// The default implementation uses `get_contents`.
+    fn query_contents(&self, query: ClipboardQuery) -> anyhow::Result<Option<String>> {
+        for target in query.targets() {
+            if let Some(text) = self.get_contents(*target)? {
+                return Ok(Some(query.reply(text)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// An OSC 52 query of the clipboard, which is answered with the
+/// contents of the first of its targets that has any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardQuery {
+    selection: Selection,
+    targets: Vec<ClipboardSelection>,
+}
+
+impl ClipboardQuery {
+    pub fn new(selection: Selection) -> Self {
+        Self {
+            selection,
+            targets: crate::terminalstate::performer::selection_targets(selection),
+        }
+    }
+
+    /// The selections to read, in order of preference
+    pub fn targets(&self) -> &[ClipboardSelection] {
+        &self.targets
+    }
+
+    /// Returns the reply that tells the application that the
+    /// clipboard holds `text`
+    pub fn reply(&self, text: String) -> String {
+        OperatingSystemCommand::SetSelection(self.selection, text).to_string()
+    }
 }
 
 impl Clipboard for Box<dyn Clipboard> {
@@ -26,6 +77,14 @@ impl Clipboard for Box<dyn Clipboard> {
     ) -> anyhow::Result<()> {
         self.as_ref().set_contents(selection, data)
     }
+
+    fn get_contents(&self, selection: ClipboardSelection) -> anyhow::Result<Option<String>> {
+        self.as_ref().get_contents(selection)
+    }
+
+    fn query_contents(&self, query: ClipboardQuery) -> anyhow::Result<Option<String>> {
+        self.as_ref().query_contents(query)
+    }
 }
 
 pub trait DeviceControlHandler: Send + Sync {
@@ -138,9 +197,12 @@ impl Terminal {
         // writing to the writer sends data to input of the pty
         writer: Box<dyn std::io::Write + Send>,
     ) -> Terminal {
+        let mut parser = Parser::new();
+        parser.set_diagnostics(config.log_unknown_escape_sequences());
+        parser.set_sixel_streaming(true);
         Terminal {
             state: TerminalState::new(size, config, term_program, term_version, writer),
-            parser: Parser::new(),
+            parser,
         }
     }
 
@@ -171,4 +233,17 @@ impl Terminal {
         }
         self.trigger_unseen_output_notif();
     }
+
+    /// Perform the actions held by `batch`, leaving it empty so that
+    /// its buffers can be reused for the next batch
+    pub fn perform_action_batch(&mut self, batch: &mut ActionBatch) {
+        self.state.increment_seqno();
+        {
+            let mut performer = Performer::new(&mut self.state);
+            for action in batch.drain() {
+                performer.perform_batched(action);
+            }
+        }
+        self.trigger_unseen_output_notif();
+    }
 }
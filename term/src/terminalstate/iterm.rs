@@ -7,6 +7,46 @@ use termwiz::escape::osc::ITermFileData;
 use termwiz::image::ImageDataType;
 
 impl TerminalState {
+    pub(crate) fn begin_multipart_file(&mut self, file: ITermFileData) {
+        if let Some(prior) = self.iterm_multipart_file.replace(file) {
+            error!(
+                "Discarding incomplete multipart file name={:?} size={}",
+                prior.name,
+                prior.data.len()
+            );
+        }
+    }
+
+    pub(crate) fn add_file_part(&mut self, data: Vec<u8>) {
+        match self.iterm_multipart_file.as_mut() {
+            Some(file) => {
+                let received = file.data.len() + data.len();
+                match file.size {
+                    Some(size) if received > size => {
+                        error!(
+                            "Discarding multipart file name={:?} that is larger \
+                             than its size {}",
+                            file.name, size
+                        );
+                        self.iterm_multipart_file.take();
+                    }
+                    _ => file.data.extend_from_slice(&data),
+                }
+            }
+            None => error!(
+                "Ignoring FilePart of {} bytes without MultipartFile",
+                data.len()
+            ),
+        }
+    }
+
+    pub(crate) fn end_multipart_file(&mut self) {
+        match self.iterm_multipart_file.take() {
+            Some(file) => self.set_image(file),
+            None => error!("Ignoring FileEnd without MultipartFile"),
+        }
+    }
+
     pub(crate) fn set_image(&mut self, image: ITermFileData) {
         if !image.inline {
             if let Some(handler) = &self.download_handler {
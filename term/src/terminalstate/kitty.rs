@@ -10,9 +10,9 @@ use std::io::Write;
 use std::sync::Arc;
 use std::time::Duration;
 use termwiz::escape::apc::{
-    KittyFrameCompositionMode, KittyImage, KittyImageCompression, KittyImageData, KittyImageDelete,
-    KittyImageFormat, KittyImageFrame, KittyImageFrameCompose, KittyImagePlacement,
-    KittyImageTransmit, KittyImageVerbosity,
+    KittyFrameCompositionMode, KittyImage, KittyImageAnimation, KittyImageCompression,
+    KittyImageData, KittyImageDelete, KittyImageFormat, KittyImageFrame, KittyImageFrameCompose,
+    KittyImagePlacement, KittyImageTransmit, KittyImageVerbosity,
 };
 use termwiz::image::ImageDataType;
 use termwiz::surface::change::ImageData;
@@ -111,6 +111,29 @@ impl TerminalState {
             )
         })?);
 
+        if placement.virtual_placement {
+            // The image is displayed by the placeholder cells that
+            // refer to it rather than being attached to cells here;
+            // record the placement so that its data is retained.
+            self.kitty_img.placements.insert(
+                (image_id, placement.placement_id),
+                PlacementInfo {
+                    first_row: 0,
+                    rows: 0,
+                    cols: 0,
+                },
+            );
+            return Ok(());
+        }
+        if placement.parent_image_id.is_some() {
+            log::warn!(
+                "kitty image {} placed relative to image {:?} \
+                 will be placed at the cursor instead",
+                image_id,
+                placement.parent_image_id
+            );
+        }
+
         let (image_width, image_height) = img.data().dimensions()?;
 
         let info = self.assign_image_to_cells(ImageAttachParams {
@@ -261,6 +284,32 @@ impl TerminalState {
                     self.kitty_img.remove_data_for_id(image_id);
                 }
             }
+            KittyImage::Delete {
+                what:
+                    KittyImageDelete::ByImageIdRange {
+                        first,
+                        last,
+                        delete,
+                    },
+                verbosity: _,
+            } => {
+                let mut image_ids: Vec<u32> = self
+                    .kitty_img
+                    .placements
+                    .keys()
+                    .map(|(image_id, _)| *image_id)
+                    .chain(self.kitty_img.id_to_data.keys().copied())
+                    .filter(|image_id| (first..=last).contains(image_id))
+                    .collect();
+                image_ids.sort_unstable();
+                image_ids.dedup();
+                for image_id in image_ids {
+                    self.kitty_remove_placement(image_id, None);
+                    if delete {
+                        self.kitty_img.remove_data_for_id(image_id);
+                    }
+                }
+            }
             KittyImage::Delete {
                 what: KittyImageDelete::All { delete },
                 verbosity: _,
@@ -284,6 +333,17 @@ impl TerminalState {
                     log::error!("Error {:#} while handling KittyImage::ComposeFrame", err);
                 }
             }
+            KittyImage::AnimationControl {
+                animation,
+                verbosity,
+            } => {
+                if let Err(err) = self.kitty_animation_control(animation, verbosity) {
+                    log::error!(
+                        "Error {:#} while handling KittyImage::AnimationControl",
+                        err
+                    );
+                }
+            }
         };
 
         Ok(())
@@ -387,6 +447,58 @@ impl TerminalState {
         self.writer.flush().ok();
     }
 
+    /// Animations always loop while they are displayed, so the only
+    /// part of animation control that we honor is adjusting the gap
+    /// between frames.
+    fn kitty_animation_control(
+        &mut self,
+        animation: KittyImageAnimation,
+        verbosity: KittyImageVerbosity,
+    ) -> anyhow::Result<()> {
+        let image_id = match animation.image_number {
+            Some(no) => self.kitty_img.number_to_id.get(&no).copied(),
+            None => animation.image_id,
+        };
+        let anim = match image_id.and_then(|id| self.kitty_img.id_to_data.get(&id)) {
+            Some(anim) => anim,
+            None => {
+                self.kitty_send_response(
+                    verbosity,
+                    false,
+                    animation.image_id,
+                    animation.image_number,
+                    "ENOENT".to_string(),
+                );
+                anyhow::bail!(
+                    "no matching image id {:?} for image_number {:?}",
+                    animation.image_id,
+                    animation.image_number
+                );
+            }
+        };
+
+        log::trace!("animation control {:?}", animation);
+
+        if let (Some(frame_no), Some(gap)) = (animation.frame_number, animation.duration_ms) {
+            let mut anim = anim.data();
+            if let ImageDataType::AnimRgba8 { durations, .. } = &mut *anim {
+                let frame_no = frame_no as usize;
+                anyhow::ensure!(
+                    frame_no > 0 && frame_no <= durations.len(),
+                    "attempted to change the gap of frame {} which is outside range 1-{}",
+                    frame_no,
+                    durations.len()
+                );
+                durations[frame_no - 1] = Duration::from_millis(match gap {
+                    0 => 40,
+                    n => n.into(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     fn kitty_frame_compose(
         &mut self,
         frame: KittyImageFrameCompose,
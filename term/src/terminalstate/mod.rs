@@ -5,20 +5,21 @@ use super::*;
 use crate::color::{ColorPalette, RgbColor};
 use crate::config::{BidiMode, NewlineCanon};
 use log::debug;
-use num_traits::ToPrimitive;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::io::{BufWriter, Write};
 use std::num::NonZeroUsize;
 use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
-use terminfo::{Database, Value};
 use termwiz::cell::UnicodeVersion;
 use termwiz::escape::csi::{
     Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay,
-    EraseInLine, Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode, Window, XtSmGraphics,
-    XtSmGraphicsAction, XtSmGraphicsItem, XtSmGraphicsStatus, XtermKeyModifierResource,
+    EraseInLine, Mode, ModeSetting, Rectangle, RectangularArea, Sgr, TabulationClear, TerminalMode,
+    TerminalModeCode, Window, WindowState, XtSmGraphics, XtSmGraphicsAction, XtSmGraphicsItem,
+    XtSmGraphicsStatus, XtermKeyModifierResource,
 };
-use termwiz::escape::{OneBased, OperatingSystemCommand, CSI};
+use termwiz::escape::osc::Notification;
+use termwiz::escape::xtgettcap::{CapabilityReport, CapabilityValue};
+use termwiz::escape::{OneBased, CSI};
 use termwiz::image::ImageData;
 use termwiz::input::KeyboardEncoding;
 use termwiz::surface::{CursorShape, CursorVisibility, SequenceNo};
@@ -35,13 +36,6 @@ mod sixel;
 use crate::terminalstate::image::*;
 use crate::terminalstate::kitty::*;
 
-lazy_static::lazy_static! {
-    static ref DB: Database = {
-        let data = include_bytes!("../../../termwiz/data/wezterm");
-        Database::from_buffer(&data[..]).unwrap()
-    };
-}
-
 pub(crate) struct TabStop {
     tabs: Vec<bool>,
     tab_width: usize,
@@ -141,6 +135,14 @@ struct SavedCursor {
     // TODO: selective_erase when supported
 }
 
+/// An entry of the title stack.  A title is `None` when it wasn't
+/// saved, and so isn't restored.
+#[derive(Debug, Clone)]
+struct SavedTitles {
+    icon_title: Option<Option<String>>,
+    title: Option<String>,
+}
+
 struct ScreenOrAlt {
     /// The primary screen + scrollback
     screen: Screen,
@@ -247,6 +249,19 @@ impl ScreenOrAlt {
     }
 }
 
+/// How many of the hyperlinks that have an id can be continued
+const MAX_RECENT_HYPERLINKS: usize = 16;
+
+/// How many entries the title stack can hold; as with xterm,
+/// the oldest is discarded to make room for another
+const MAX_TITLE_STACK: usize = 10;
+
+/// How many chunks a kitty notification may be sent in, and how
+/// many bytes its title and body may hold in total; a notification
+/// that exceeds either is discarded
+const MAX_NOTIFICATION_CHUNKS: usize = 64;
+const MAX_NOTIFICATION_SIZE: usize = 16 * 1024;
+
 /// Manages the state for the terminal
 pub struct TerminalState {
     config: Arc<dyn TerminalConfiguration>,
@@ -301,9 +316,18 @@ pub struct TerminalState {
     sixel_display_mode: bool,
     use_private_color_registers_for_each_graphic: bool,
 
+    /// Set while the application is in the middle of a synchronized
+    /// update, begun by `CSI ? 2026 h` or BSU.  The output of the
+    /// update is held back by the mux until it is complete, so that
+    /// it is applied to the terminal at once.
+    synchronized_output: bool,
+
     /// Graphics mode color register map.
     color_map: HashMap<u16, RgbColor>,
 
+    /// The sixel image that is being received
+    pending_sixel: Option<sixel::PendingSixel>,
+
     /// When set, modifies the sequence of bytes sent for keys
     /// in the numeric keypad portion of the keyboard.
     application_keypad: bool,
@@ -339,6 +363,8 @@ pub struct TerminalState {
     title: String,
     /// The icon title string (OSC 1)
     icon_title: Option<String>,
+    /// The titles saved by XTWINOPS 22, most recent last
+    title_stack: Vec<SavedTitles>,
 
     palette: Option<ColorPalette>,
 
@@ -363,12 +389,24 @@ pub struct TerminalState {
 
     user_vars: HashMap<String, String>,
 
+    /// The file being received through the iTerm2 MultipartFile
+    /// and FilePart sequences
+    iterm_multipart_file: Option<termwiz::escape::osc::ITermFileData>,
+
+    /// The most recently used hyperlinks that have an id, most recent
+    /// first, so that a link that is continued by a later OSC 8 sequence
+    /// is shared with the cells that were written before
+    recent_hyperlinks: VecDeque<Arc<Hyperlink>>,
+
     kitty_img: KittyImageState,
     seqno: SequenceNo,
 
     /// The unicode version that is in effect
     unicode_version: UnicodeVersion,
     unicode_version_stack: Vec<UnicodeVersionStackEntry>,
+    /// DECSET 2027; when reset, each codepoint that has a width
+    /// occupies its own cells, as it would for wcwidth
+    grapheme_clustering: bool,
 
     enable_conpty_quirks: bool,
     /// On Windows, the ConPTY layer emits an OSC sequence to
@@ -379,6 +417,12 @@ pub struct TerminalState {
 
     accumulating_title: Option<String>,
 
+    /// A kitty notification whose title or body is continued
+    /// by a later chunk
+    /// A kitty notification that is still being sent, along with
+    /// the number of chunks it was sent in so far
+    pending_notification: Option<(Notification, usize)>,
+
     /// seqno when we last lost focus
     lost_focus_seqno: SequenceNo,
     /// seqno when we last emitted Alert::OutputSinceFocusLost
@@ -514,6 +558,7 @@ impl TerminalState {
         let color_map = default_color_map();
 
         let unicode_version = config.unicode_version();
+        let grapheme_clustering = config.grapheme_clustering();
 
         TerminalState {
             config,
@@ -537,7 +582,9 @@ impl TerminalState {
             dec_ansi_mode: false,
             sixel_display_mode: false,
             use_private_color_registers_for_each_graphic: false,
+            synchronized_output: false,
             color_map,
+            pending_sixel: None,
             application_keypad: false,
             bracketed_paste: false,
             focus_tracking: false,
@@ -557,6 +604,7 @@ impl TerminalState {
             tabs: TabStop::new(size.cols, 8),
             title: "wezterm".to_string(),
             icon_title: None,
+            title_stack: vec![],
             palette: None,
             pixel_height: size.pixel_height,
             pixel_width: size.pixel_width,
@@ -571,13 +619,17 @@ impl TerminalState {
             writer,
             image_cache: lru::LruCache::new(NonZeroUsize::new(16).unwrap()),
             user_vars: HashMap::new(),
+            iterm_multipart_file: None,
+            recent_hyperlinks: VecDeque::new(),
             kitty_img: Default::default(),
             seqno,
             unicode_version,
             unicode_version_stack: vec![],
+            grapheme_clustering,
             suppress_initial_title_change: false,
             enable_conpty_quirks: false,
             accumulating_title: None,
+            pending_notification: None,
             lost_focus_seqno: seqno,
             lost_focus_alerted_seqno: seqno,
             focused: true,
@@ -702,6 +754,12 @@ impl TerminalState {
         &mut self.screen
     }
 
+    /// Returns an estimate of the memory used by the lines of both
+    /// the primary and the alternate screen
+    pub fn estimated_screen_bytes(&self) -> usize {
+        self.screen.screen.estimated_bytes() + self.screen.alt_screen.estimated_bytes()
+    }
+
     fn set_clipboard_contents(
         &self,
         selection: ClipboardSelection,
@@ -754,6 +812,12 @@ impl TerminalState {
         self.screen.is_alt_screen_active()
     }
 
+    /// Returns true if the associated application is in the middle
+    /// of a synchronized update
+    pub fn is_synchronized_output(&self) -> bool {
+        self.synchronized_output
+    }
+
     /// Returns true if the associated application has enabled
     /// bracketed paste mode, which can be helpful to the hosting
     /// GUI application to decide about fragmenting a large paste.
@@ -819,6 +883,20 @@ impl TerminalState {
     /// De-fang the text by removing any embedded bracketed paste
     /// sequence that may be present.
     pub fn send_paste(&mut self, text: &str) -> Result<(), Error> {
+        let (mut buf, end) = self.encode_paste(text);
+        buf.push_str(end);
+        self.writer.write_all(buf.as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Returns the data that `send_paste` would write for `text`,
+    /// split in two: the de-fanged text, preceded by the start of
+    /// the bracketing if bracketed paste mode is enabled, and the
+    /// sequence that ends the bracketing, which is empty if it isn't.
+    /// The end of the bracketing must be sent even if the paste is
+    /// abandoned part of the way through the text.
+    pub fn encode_paste(&self, text: &str) -> (String, &'static str) {
         let mut buf = String::new();
         if self.bracketed_paste {
             buf.push_str("\x1b[200~");
@@ -834,13 +912,12 @@ impl TerminalState {
         let de_fanged = canon.replace("\x1b[200~", "").replace("\x1b[201~", "");
         buf.push_str(&de_fanged);
 
-        if self.bracketed_paste {
-            buf.push_str("\x1b[201~");
-        }
-
-        self.writer.write_all(buf.as_bytes())?;
-        self.writer.flush()?;
-        Ok(())
+        let end = if self.bracketed_paste {
+            "\x1b[201~"
+        } else {
+            ""
+        };
+        (buf, end)
     }
 
     /// Informs the terminal that the viewport of the window has resized to the
@@ -1174,10 +1251,30 @@ impl TerminalState {
     }
 
     fn set_hyperlink(&mut self, link: Option<Hyperlink>) {
-        self.pen.set_hyperlink(match link {
-            Some(hyperlink) => Some(Arc::new(hyperlink)),
-            None => None,
-        });
+        let link = link.map(|link| self.continue_hyperlink(link));
+        self.pen.set_hyperlink(link);
+    }
+
+    /// Returns the shared instance of `link`.  A link with an id that
+    /// matches one of the recent links, including its uri, continues
+    /// that link, so that all of its cells are highlighted together
+    /// on hover even when they were written by separate sequences.
+    /// Each link without an id is distinct.
+    fn continue_hyperlink(&mut self, link: Hyperlink) -> Arc<Hyperlink> {
+        if link.id().is_none() {
+            return Arc::new(link);
+        }
+        let link = match self
+            .recent_hyperlinks
+            .iter()
+            .position(|recent| **recent == link)
+        {
+            Some(idx) => self.recent_hyperlinks.remove(idx).unwrap(),
+            None => Arc::new(link),
+        };
+        self.recent_hyperlinks.push_front(Arc::clone(&link));
+        self.recent_hyperlinks.truncate(MAX_RECENT_HYPERLINKS);
+        link
     }
 
     /// <https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h4-Device-Control-functions:DCS-plus-q-Pt-ST.F95>
@@ -1186,54 +1283,22 @@ impl TerminalState {
         let mut res = String::new();
 
         for name in &names {
-            res.push_str("\x1bP");
-
-            let encoded_name = hex::encode_upper(&name);
-            match name.as_str() {
-                "TN" | "name" => {
-                    res.push_str("1+r");
-                    res.push_str(&encoded_name);
-                    res.push('=');
-
-                    let encoded_val = hex::encode_upper(&self.term_program);
-                    res.push_str(&encoded_val);
-                }
-
-                "Co" | "colors" => {
-                    res.push_str("1+r");
-                    res.push_str(&encoded_name);
-                    res.push('=');
-                    let encoded_val = hex::encode_upper("256");
-                    res.push_str(&encoded_val);
-                }
-
+            let report = match name.as_str() {
+                "TN" | "name" => CapabilityReport::new(
+                    name,
+                    Some(CapabilityValue::String(
+                        self.term_program.as_bytes().to_vec(),
+                    )),
+                ),
                 "RGB" => {
-                    res.push_str("1+r");
-                    res.push_str(&encoded_name);
-                    res.push('=');
-                    let encoded_val = hex::encode_upper("8/8/8");
-                    res.push_str(&encoded_val);
-                }
-
-                _ => {
-                    if let Some(value) = DB.raw(name) {
-                        res.push_str("1+r");
-                        res.push_str(&encoded_name);
-                        res.push('=');
-                        let value = match value {
-                            Value::True => hex::encode_upper("1"),
-                            Value::Number(n) => hex::encode_upper(&n.to_string()),
-                            Value::String(s) => hex::encode_upper(s),
-                        };
-                        res.push_str(&value);
-                    } else {
-                        log::trace!("xt_get_tcap: unknown name {}", name);
-                        res.push_str("0+r");
-                        res.push_str(&encoded_name);
-                    }
+                    CapabilityReport::new(name, Some(CapabilityValue::String(b"8/8/8".to_vec())))
                 }
+                _ => CapabilityReport::wezterm(name),
+            };
+            if report.value.is_none() {
+                log::trace!("xt_get_tcap: unknown name {}", name);
             }
-            res.push_str("\x1b\\");
+            res.push_str(&report.to_string());
         }
 
         log::trace!(
@@ -1257,6 +1322,7 @@ impl TerminalState {
                 self.pen = CellAttributes::default();
                 self.insert = false;
                 self.dec_origin_mode = false;
+                self.synchronized_output = false;
                 // Note that xterm deviates from the documented DECSTR
                 // setting for dec_auto_wrap, so we do too
                 self.dec_auto_wrap = true;
@@ -1281,37 +1347,30 @@ impl TerminalState {
                 self.g1_charset = CharSet::Ascii;
             }
             Device::RequestPrimaryDeviceAttributes => {
-                let mut ident = "\x1b[?65".to_string(); // Vt500
-                ident.push_str(";4"); // Sixel graphics
-                ident.push_str(";6"); // Selective erase
-                ident.push_str(";18"); // windowing extensions
-                ident.push_str(";22"); // ANSI color, vt525
-                ident.push('c');
-
-                self.writer.write(ident.as_bytes()).ok();
+                let features = self.config.terminal_features();
+                write!(self.writer, "{}", CSI::Device(Box::new(features.primary()))).ok();
                 self.writer.flush().ok();
             }
             Device::RequestSecondaryDeviceAttributes => {
-                // Response is: Pp ; Pv ; Pc
-                // Where Pp=1 means vt220
-                // and Pv is the firmware version.
-                // Pc is always 0.
-                // Because our default TERM is xterm, the firmware
-                // version will be considered to be equialent to xterm's
-                // patch levels, with the following effects:
-                // pv < 95 -> ttymouse=xterm
-                // pv >= 95 < 277 -> ttymouse=xterm2
-                // pv >= 277 -> ttymouse=sgr
-                // pv >= 279 - xterm will probe for additional device settings.
-                self.writer.write(b"\x1b[>1;277;0c").ok();
+                let features = self.config.terminal_features();
+                write!(
+                    self.writer,
+                    "{}",
+                    CSI::Device(Box::new(features.secondary()))
+                )
+                .ok();
                 self.writer.flush().ok();
             }
             Device::RequestTertiaryDeviceAttributes => {
-                self.writer
-                    .write(format!("\x1bP!|00000000{}", ST).as_bytes())
-                    .ok();
+                let features = self.config.terminal_features();
+                write!(self.writer, "{}", features.tertiary()).ok();
                 self.writer.flush().ok();
             }
+            Device::SecondaryDeviceAttributes(a) => {
+                if self.config.log_unknown_escape_sequences() {
+                    log::warn!("unhandled: {:?}", a);
+                }
+            }
             Device::RequestTerminalNameAndVersion => {
                 self.writer.write(DCS.as_bytes()).ok();
                 self.writer
@@ -1376,51 +1435,74 @@ impl TerminalState {
         }
     }
 
-    /// Indicates that mode is permanently enabled
-    fn decqrm_response_permanent(&mut self, mode: Mode) {
-        let (is_dec, number) = match &mode {
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(code)) => (true, code.to_u16().unwrap()),
-            Mode::QueryDecPrivateMode(DecPrivateMode::Unspecified(code)) => (true, *code),
-            Mode::QueryMode(TerminalMode::Code(code)) => (false, code.to_u16().unwrap()),
-            Mode::QueryMode(TerminalMode::Unspecified(code)) => (false, *code),
-            _ => unreachable!(),
-        };
-
-        let prefix = if is_dec { "?" } else { "" };
-
-        write!(self.writer, "\x1b[{prefix}{number};3$y").ok();
-        self.writer.flush().ok();
+    /// Returns the state of a DEC private mode, for DECRQM
+    fn dec_private_mode_setting(&self, code: &DecPrivateModeCode) -> ModeSetting {
+        match code {
+            DecPrivateModeCode::ApplicationCursorKeys => self.application_cursor_keys.into(),
+            DecPrivateModeCode::DecAnsiMode => self.dec_ansi_mode.into(),
+            DecPrivateModeCode::Select132Columns => ModeSetting::Reset,
+            DecPrivateModeCode::SmoothScroll => ModeSetting::PermanentlyReset,
+            DecPrivateModeCode::ReverseVideo => self.reverse_video_mode.into(),
+            DecPrivateModeCode::OriginMode => self.dec_origin_mode.into(),
+            DecPrivateModeCode::AutoWrap => self.dec_auto_wrap.into(),
+            // Key repeat is left to the GUI layer
+            DecPrivateModeCode::AutoRepeat => ModeSetting::PermanentlySet,
+            DecPrivateModeCode::StartBlinkingCursor => ModeSetting::Reset,
+            DecPrivateModeCode::ShowCursor => self.cursor_visible.into(),
+            DecPrivateModeCode::ReverseWraparound => self.reverse_wraparound_mode.into(),
+            DecPrivateModeCode::LeftRightMarginMode => self.left_and_right_margin_mode.into(),
+            DecPrivateModeCode::SixelDisplayMode => self.sixel_display_mode.into(),
+            DecPrivateModeCode::MouseTracking => self.mouse_tracking.into(),
+            DecPrivateModeCode::HighlightMouseTracking => ModeSetting::PermanentlyReset,
+            DecPrivateModeCode::ButtonEventMouse => self.button_event_mouse.into(),
+            DecPrivateModeCode::AnyEventMouse => self.any_event_mouse.into(),
+            DecPrivateModeCode::FocusTracking => self.focus_tracking.into(),
+            DecPrivateModeCode::Utf8Mouse => (self.mouse_encoding == MouseEncoding::Utf8).into(),
+            DecPrivateModeCode::SGRMouse => (self.mouse_encoding == MouseEncoding::SGR).into(),
+            DecPrivateModeCode::SGRPixelsMouse => {
+                (self.mouse_encoding == MouseEncoding::SgrPixels).into()
+            }
+            DecPrivateModeCode::EnableAlternateScreen
+            | DecPrivateModeCode::OptEnableAlternateScreen
+            | DecPrivateModeCode::ClearAndEnableAlternateScreen => {
+                self.screen.is_alt_screen_active().into()
+            }
+            DecPrivateModeCode::BracketedPaste => self.bracketed_paste.into(),
+            DecPrivateModeCode::GraphemeClustering => self.grapheme_clustering.into(),
+            DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic => {
+                self.use_private_color_registers_for_each_graphic.into()
+            }
+            DecPrivateModeCode::SynchronizedOutput => self.synchronized_output.into(),
+            DecPrivateModeCode::SixelScrollsRight => self.sixel_scrolls_right.into(),
+            DecPrivateModeCode::Win32InputMode => {
+                (self.keyboard_encoding == KeyboardEncoding::Win32).into()
+            }
+            DecPrivateModeCode::SaveCursor
+            | DecPrivateModeCode::XTermMetaSendsEscape
+            | DecPrivateModeCode::XTermAltSendsEscape
+            | DecPrivateModeCode::MinTTYApplicationEscapeKeyMode => ModeSetting::NotRecognized,
+        }
     }
 
-    fn decqrm_response(&mut self, mode: Mode, mut recognized: bool, enabled: bool) {
-        let (is_dec, number) = match &mode {
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(code)) => (true, code.to_u16().unwrap()),
-            Mode::QueryDecPrivateMode(DecPrivateMode::Unspecified(code)) => {
-                recognized = false;
-                (true, *code)
-            }
-            Mode::QueryMode(TerminalMode::Code(code)) => (false, code.to_u16().unwrap()),
-            Mode::QueryMode(TerminalMode::Unspecified(code)) => {
-                recognized = false;
-                (false, *code)
-            }
-            _ => unreachable!(),
-        };
-
-        let prefix = if is_dec { "?" } else { "" };
-
-        let status = if recognized {
-            if enabled {
-                1 // set
-            } else {
-                2 // reset
-            }
-        } else {
-            0
-        };
+    /// Returns the state of an ANSI mode, for DECRQM
+    fn terminal_mode_setting(&self, code: &TerminalModeCode) -> ModeSetting {
+        match code {
+            TerminalModeCode::KeyboardAction => ModeSetting::PermanentlyReset,
+            TerminalModeCode::Insert => self.insert.into(),
+            TerminalModeCode::BiDirectionalSupportMode => self
+                .bidi_enabled
+                .unwrap_or_else(|| self.config.bidi_mode().enabled)
+                .into(),
+            // We never echo locally
+            TerminalModeCode::SendReceive => ModeSetting::PermanentlySet,
+            TerminalModeCode::AutomaticNewline => self.newline_mode.into(),
+            TerminalModeCode::ShowCursor => self.cursor_visible.into(),
+        }
+    }
 
-        log::trace!("{:?} -> recognized={} status={}", mode, recognized, status);
-        write!(self.writer, "\x1b[{}{};{}$y", prefix, number, status).ok();
+    fn report_mode(&mut self, report: Mode) {
+        log::trace!("DECRQM responding with {:?}", report);
+        write!(self.writer, "{}", CSI::Mode(report)).ok();
         self.writer.flush().ok();
     }
 
@@ -1432,11 +1514,6 @@ impl TerminalState {
             | Mode::ResetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::StartBlinkingCursor,
             )) => {}
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::StartBlinkingCursor,
-            )) => {
-                self.decqrm_response(mode, true, false);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoRepeat))
             | Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoRepeat)) => {
@@ -1451,14 +1528,6 @@ impl TerminalState {
                 self.keyboard_encoding = KeyboardEncoding::Xterm;
             }
 
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Win32InputMode)) => {
-                self.decqrm_response(
-                    mode,
-                    true,
-                    self.keyboard_encoding == KeyboardEncoding::Win32,
-                );
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::ReverseWraparound,
             )) => {
@@ -1471,12 +1540,6 @@ impl TerminalState {
                 self.reverse_wraparound_mode = false;
             }
 
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ReverseWraparound,
-            )) => {
-                self.decqrm_response(mode, true, self.reverse_wraparound_mode);
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::LeftRightMarginMode,
             )) => {
@@ -1490,25 +1553,15 @@ impl TerminalState {
                 self.left_and_right_margins = 0..self.screen().physical_cols;
             }
 
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::LeftRightMarginMode,
-            )) => {
-                self.decqrm_response(mode, true, self.left_and_right_margin_mode);
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::GraphemeClustering,
-            ))
-            | Mode::ResetDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::GraphemeClustering,
             )) => {
-                // Permanently enabled
+                self.grapheme_clustering = true;
             }
-
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::GraphemeClustering,
             )) => {
-                self.decqrm_response_permanent(mode);
+                self.grapheme_clustering = false;
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SaveCursor)) => {
@@ -1527,10 +1580,6 @@ impl TerminalState {
                 self.dec_auto_wrap = false;
             }
 
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoWrap)) => {
-                self.decqrm_response(mode, true, self.dec_auto_wrap);
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::OriginMode)) => {
                 self.dec_origin_mode = true;
                 self.set_cursor_pos(&Position::Absolute(0), &Position::Absolute(0));
@@ -1541,10 +1590,6 @@ impl TerminalState {
                 self.set_cursor_pos(&Position::Absolute(0), &Position::Absolute(0));
             }
 
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::OriginMode)) => {
-                self.decqrm_response(mode, true, self.dec_origin_mode);
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic,
             )) => {
@@ -1555,32 +1600,17 @@ impl TerminalState {
             )) => {
                 self.use_private_color_registers_for_each_graphic = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic,
-            )) => {
-                self.decqrm_response(
-                    mode,
-                    true,
-                    self.use_private_color_registers_for_each_graphic,
-                );
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::SynchronizedOutput,
             )) => {
-                // This is handled in wezterm's mux
+                // The output is held back by wezterm's mux
+                self.synchronized_output = true;
             }
             Mode::ResetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::SynchronizedOutput,
             )) => {
-                // This is handled in wezterm's mux
-            }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::SynchronizedOutput,
-            )) => {
-                // This is handled in wezterm's mux; if we get here, then it isn't enabled,
-                // so we always report false
-                self.decqrm_response(mode, true, false);
+                self.synchronized_output = false;
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SmoothScroll))
@@ -1614,11 +1644,6 @@ impl TerminalState {
                 self.set_cursor_pos(&Position::Absolute(0), &Position::Absolute(0));
                 self.erase_in_display(EraseInDisplay::EraseDisplay);
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::Select132Columns,
-            )) => {
-                self.decqrm_response(mode, true, false);
-            }
 
             Mode::SetMode(TerminalMode::Code(TerminalModeCode::BiDirectionalSupportMode)) => {
                 self.bidi_enabled.replace(true);
@@ -1626,14 +1651,6 @@ impl TerminalState {
             Mode::ResetMode(TerminalMode::Code(TerminalModeCode::BiDirectionalSupportMode)) => {
                 self.bidi_enabled.replace(false);
             }
-            Mode::QueryMode(TerminalMode::Code(TerminalModeCode::BiDirectionalSupportMode)) => {
-                self.decqrm_response(
-                    mode,
-                    true,
-                    self.bidi_enabled
-                        .unwrap_or_else(|| self.config.bidi_mode().enabled),
-                );
-            }
 
             Mode::SetMode(TerminalMode::Code(TerminalModeCode::Insert)) => {
                 self.insert = true;
@@ -1641,9 +1658,6 @@ impl TerminalState {
             Mode::ResetMode(TerminalMode::Code(TerminalModeCode::Insert)) => {
                 self.insert = false;
             }
-            Mode::QueryMode(TerminalMode::Code(TerminalModeCode::Insert)) => {
-                self.decqrm_response(mode, true, self.insert);
-            }
 
             Mode::SetMode(TerminalMode::Code(TerminalModeCode::AutomaticNewline)) => {
                 self.newline_mode = true;
@@ -1651,9 +1665,6 @@ impl TerminalState {
             Mode::ResetMode(TerminalMode::Code(TerminalModeCode::AutomaticNewline)) => {
                 self.newline_mode = false;
             }
-            Mode::QueryMode(TerminalMode::Code(TerminalModeCode::AutomaticNewline)) => {
-                self.decqrm_response(mode, true, self.newline_mode);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::BracketedPaste)) => {
                 self.bracketed_paste = true;
@@ -1661,9 +1672,6 @@ impl TerminalState {
             Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::BracketedPaste)) => {
                 self.bracketed_paste = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::BracketedPaste)) => {
-                self.decqrm_response(mode, true, self.bracketed_paste);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::OptEnableAlternateScreen,
@@ -1705,11 +1713,6 @@ impl TerminalState {
             )) => {
                 self.application_cursor_keys = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ApplicationCursorKeys,
-            )) => {
-                self.decqrm_response(mode, true, self.application_cursor_keys);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SixelDisplayMode)) => {
                 self.sixel_display_mode = true;
@@ -1719,11 +1722,6 @@ impl TerminalState {
             )) => {
                 self.sixel_display_mode = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::SixelDisplayMode,
-            )) => {
-                self.decqrm_response(mode, true, self.sixel_display_mode);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::DecAnsiMode)) => {
                 self.dec_ansi_mode = true;
@@ -1731,9 +1729,6 @@ impl TerminalState {
             Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::DecAnsiMode)) => {
                 self.dec_ansi_mode = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::DecAnsiMode)) => {
-                self.decqrm_response(mode, true, self.dec_ansi_mode);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
                 self.cursor_visible = true;
@@ -1741,9 +1736,6 @@ impl TerminalState {
             Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
                 self.cursor_visible = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
-                self.decqrm_response(mode, true, self.cursor_visible);
-            }
             Mode::SetMode(TerminalMode::Code(TerminalModeCode::ShowCursor)) => {
                 self.cursor_visible = true;
             }
@@ -1759,9 +1751,6 @@ impl TerminalState {
                 self.mouse_tracking = false;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::MouseTracking)) => {
-                self.decqrm_response(mode, true, self.mouse_tracking);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::HighlightMouseTracking,
@@ -1780,11 +1769,6 @@ impl TerminalState {
                 self.button_event_mouse = false;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ButtonEventMouse,
-            )) => {
-                self.decqrm_response(mode, true, self.button_event_mouse);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
                 self.any_event_mouse = true;
@@ -1794,9 +1778,6 @@ impl TerminalState {
                 self.any_event_mouse = false;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
-                self.decqrm_response(mode, true, self.any_event_mouse);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
                 self.focus_tracking = true;
@@ -1806,9 +1787,6 @@ impl TerminalState {
                 self.focus_tracking = false;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
-                self.decqrm_response(mode, true, self.focus_tracking);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRMouse)) => {
                 self.mouse_encoding = MouseEncoding::SGR;
@@ -1818,16 +1796,6 @@ impl TerminalState {
                 self.mouse_encoding = MouseEncoding::X10;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRMouse)) => {
-                self.decqrm_response(
-                    mode,
-                    true,
-                    match self.mouse_encoding {
-                        MouseEncoding::SGR => true,
-                        _ => false,
-                    },
-                );
-            }
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRPixelsMouse)) => {
                 self.mouse_encoding = MouseEncoding::SgrPixels;
                 self.last_mouse_move.take();
@@ -1836,16 +1804,6 @@ impl TerminalState {
                 self.mouse_encoding = MouseEncoding::X10;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRPixelsMouse)) => {
-                self.decqrm_response(
-                    mode,
-                    true,
-                    match self.mouse_encoding {
-                        MouseEncoding::SgrPixels => true,
-                        _ => false,
-                    },
-                );
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Utf8Mouse)) => {
                 self.mouse_encoding = MouseEncoding::Utf8;
@@ -1855,16 +1813,6 @@ impl TerminalState {
                 self.mouse_encoding = MouseEncoding::X10;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Utf8Mouse)) => {
-                self.decqrm_response(
-                    mode,
-                    true,
-                    match self.mouse_encoding {
-                        MouseEncoding::Utf8 => true,
-                        _ => false,
-                    },
-                );
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::SixelScrollsRight,
@@ -1876,11 +1824,6 @@ impl TerminalState {
             )) => {
                 self.sixel_scrolls_right = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::SixelScrollsRight,
-            )) => {
-                self.decqrm_response(mode, true, self.sixel_scrolls_right);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::ClearAndEnableAlternateScreen,
@@ -1959,8 +1902,47 @@ impl TerminalState {
                 }
             }
 
-            Mode::QueryDecPrivateMode(_) | Mode::QueryMode(_) => {
-                self.decqrm_response(mode, false, false);
+            Mode::DisableXtermKeyMode(XtermKeyModifierResource::OtherKeys) => {
+                self.modify_other_keys = None;
+                log::debug!("XtermKeyMode OtherKeys disabled");
+            }
+
+            Mode::QueryXtermKeyMode(XtermKeyModifierResource::OtherKeys) => {
+                let response = CSI::Mode(Mode::XtermKeyMode {
+                    resource: XtermKeyModifierResource::OtherKeys,
+                    value: Some(self.modify_other_keys.unwrap_or(0)),
+                });
+                log::debug!("XTQMODKEYS responding with {:?}", response);
+                write!(self.writer, "{}", response).ok();
+                self.writer.flush().ok();
+            }
+
+            Mode::DisableXtermKeyMode(_) | Mode::QueryXtermKeyMode(_) => {
+                if self.config.log_unknown_escape_sequences() {
+                    log::warn!("unhandled {:?}", mode);
+                }
+            }
+
+            Mode::QueryDecPrivateMode(mode) => {
+                let setting = match &mode {
+                    DecPrivateMode::Code(code) => self.dec_private_mode_setting(code),
+                    DecPrivateMode::Unspecified(_) => ModeSetting::NotRecognized,
+                };
+                self.report_mode(Mode::ReportDecPrivateMode(mode, setting));
+            }
+
+            Mode::QueryMode(mode) => {
+                let setting = match &mode {
+                    TerminalMode::Code(code) => self.terminal_mode_setting(code),
+                    TerminalMode::Unspecified(_) => ModeSetting::NotRecognized,
+                };
+                self.report_mode(Mode::ReportMode(mode, setting));
+            }
+
+            Mode::ReportDecPrivateMode(..) | Mode::ReportMode(..) => {
+                if self.config.log_unknown_escape_sequences() {
+                    log::warn!("unhandled {:?}", mode);
+                }
             }
         }
     }
@@ -2015,51 +1997,83 @@ impl TerminalState {
         }
     }
 
-    fn perform_csi_window(&mut self, window: Window) {
-        match window {
-            Window::ReportTextAreaSizeCells => {
-                let screen = self.screen();
-                let height = Some(screen.physical_rows as i64);
-                let width = Some(screen.physical_cols as i64);
+    /// Describes the window for the XTWINOPS reports.  We don't know
+    /// where the window is on the screen, nor how large the screen is,
+    /// so the text area stands in for both the window and the screen.
+    fn window_state(&self) -> WindowState {
+        let screen = self.screen();
+        let cells = (screen.physical_cols as i64, screen.physical_rows as i64);
+        let pixels = (self.pixel_width as i64, self.pixel_height as i64);
+        WindowState {
+            iconified: false,
+            window_position: (0, 0),
+            text_area_position: (0, 0),
+            window_size: pixels,
+            text_area_size: pixels,
+            screen_size: pixels,
+            cell_size: (pixels.0 / cells.0, pixels.1 / cells.1),
+            text_area_cells: cells,
+            screen_cells: cells,
+            icon_label: self.get_title().to_string(),
+            title: self.title.clone(),
+        }
+    }
 
-                let response = Box::new(Window::ResizeWindowCells { width, height });
-                write!(self.writer, "{}", CSI::Window(response)).ok();
-                self.writer.flush().ok();
-            }
+    fn push_titles(&mut self, icon: bool, window: bool) {
+        if self.title_stack.len() >= MAX_TITLE_STACK {
+            self.title_stack.remove(0);
+        }
+        self.title_stack.push(SavedTitles {
+            icon_title: if icon {
+                Some(self.icon_title.clone())
+            } else {
+                None
+            },
+            title: if window {
+                Some(self.title.clone())
+            } else {
+                None
+            },
+        });
+    }
 
-            Window::ReportCellSizePixels => {
-                let screen = self.screen();
-                let height = screen.physical_rows;
-                let width = screen.physical_cols;
-                let response = Box::new(Window::ReportCellSizePixelsResponse {
-                    width: Some((self.pixel_width / width) as i64),
-                    height: Some((self.pixel_height / height) as i64),
-                });
-                write!(self.writer, "{}", CSI::Window(response)).ok();
-                self.writer.flush().ok();
+    fn pop_titles(&mut self, icon: bool, window: bool) {
+        let saved = match self.title_stack.pop() {
+            Some(saved) => saved,
+            None => return,
+        };
+        if let Some(icon_title) = saved.icon_title.filter(|_| icon) {
+            self.icon_title = icon_title.clone();
+            if let Some(handler) = self.alert_handler.as_mut() {
+                handler.alert(Alert::IconTitleChanged(icon_title));
             }
-
-            Window::ReportTextAreaSizePixels => {
-                let response = Box::new(Window::ResizeWindowPixels {
-                    width: Some(self.pixel_width as i64),
-                    height: Some(self.pixel_height as i64),
-                });
-                write!(self.writer, "{}", CSI::Window(response)).ok();
-                self.writer.flush().ok();
+        }
+        if let Some(title) = saved.title.filter(|_| window) {
+            self.title = title.clone();
+            if let Some(handler) = self.alert_handler.as_mut() {
+                handler.alert(Alert::WindowTitleChanged(title));
             }
+        }
+    }
 
-            Window::ReportWindowTitle => {
-                if self.config.enable_title_reporting() {
-                    write!(
-                        self.writer,
-                        "{}",
-                        OperatingSystemCommand::SetWindowTitleSun(self.title.clone())
-                    )
-                    .ok();
-                    self.writer.flush().ok();
-                }
+    fn perform_csi_window(&mut self, window: Window) {
+        if !self.config.allow_window_op(&window) {
+            log::debug!("XTWINOPS {:?} is not permitted", window);
+            return;
+        }
+
+        if let Some(response) = window.report(&self.window_state()) {
+            if matches!(window, Window::ReportIconLabel | Window::ReportWindowTitle)
+                && !self.config.enable_title_reporting()
+            {
+                return;
             }
+            write!(self.writer, "{}", response).ok();
+            self.writer.flush().ok();
+            return;
+        }
 
+        match window {
             Window::ChecksumRectangularArea {
                 request_id,
                 top,
@@ -2077,17 +2091,16 @@ impl TerminalState {
                 write!(self.writer, "\x1bP{}!~{:04x}\x1b\\", request_id, checksum).ok();
                 self.writer.flush().ok();
             }
-            Window::ResizeWindowCells { .. } => {
-                // We don't allow the application to change the window size; that's
-                // up to the user!
+            Window::PushIconAndWindowTitle => self.push_titles(true, true),
+            Window::PushIconTitle => self.push_titles(true, false),
+            Window::PushWindowTitle => self.push_titles(false, true),
+            Window::PopIconAndWindowTitle => self.pop_titles(true, true),
+            Window::PopIconTitle => self.pop_titles(true, false),
+            Window::PopWindowTitle => self.pop_titles(false, true),
+            window if window.manipulates_window() => {
+                // We don't allow the application to change the window;
+                // that's up to the user!
             }
-            Window::Iconify | Window::DeIconify => {}
-            Window::PopIconAndWindowTitle
-            | Window::PopWindowTitle
-            | Window::PopIconTitle
-            | Window::PushIconAndWindowTitle
-            | Window::PushIconTitle
-            | Window::PushWindowTitle => {}
 
             _ => {
                 if self.config.log_unknown_escape_sequences() {
@@ -2097,6 +2110,111 @@ impl TerminalState {
         }
     }
 
+    /// Returns the visible rows and the columns that are covered by
+    /// `area`, which is relative to the origin and clipped to the
+    /// margins when DECOM is set, or None if it is empty
+    fn rectangle_bounds(&self, area: &Rectangle) -> Option<(Range<VisibleRowIndex>, Range<usize>)> {
+        let (rows, cols) = if self.dec_origin_mode {
+            (
+                self.top_and_bottom_margins.clone(),
+                self.left_and_right_margins.clone(),
+            )
+        } else {
+            (
+                0..self.screen().physical_rows as VisibleRowIndex,
+                0..self.screen().physical_cols,
+            )
+        };
+        let top = rows.start + VisibleRowIndex::from(area.top.as_zero_based());
+        let bottom =
+            (rows.start + VisibleRowIndex::from(area.bottom.as_zero_based())).min(rows.end - 1);
+        let left = cols
+            .start
+            .saturating_add(area.left.as_zero_based() as usize);
+        let right = cols
+            .start
+            .saturating_add(area.right.as_zero_based() as usize)
+            .min(cols.end - 1);
+        if top > bottom || left > right {
+            return None;
+        }
+        Some((top..bottom + 1, left..right + 1))
+    }
+
+    fn perform_csi_rectangular_area(&mut self, op: RectangularArea) {
+        let seqno = self.seqno;
+        match op {
+            RectangularArea::Copy {
+                area, top, left, ..
+            } => {
+                // There is only a single page, so the page numbers
+                // are ignored.  The destination is clipped in the
+                // same way as the source.
+                let destination = Rectangle {
+                    top,
+                    left,
+                    bottom: OneBased::new(u32::MAX),
+                    right: OneBased::new(u32::MAX),
+                };
+                let ((rows, cols), (dest_rows, dest_cols)) = match (
+                    self.rectangle_bounds(&area),
+                    self.rectangle_bounds(&destination),
+                ) {
+                    (Some(source), Some(destination)) => (source, destination),
+                    _ => return,
+                };
+                let screen = self.screen_mut();
+                // The source is copied first, as the areas may overlap
+                let mut cells = vec![];
+                for y in rows {
+                    let row: Vec<Cell> = cols
+                        .clone()
+                        .map(|x| screen.get_cell(x, y).cloned().unwrap_or_else(Cell::blank))
+                        .collect();
+                    cells.push(row);
+                }
+                for (y, row) in dest_rows.zip(cells) {
+                    for (x, cell) in dest_cols.clone().zip(row) {
+                        screen.set_cell(x, y, &cell, seqno);
+                    }
+                }
+            }
+            RectangularArea::Fill { character, area } => {
+                let cell = Cell::new(character, self.pen.clone_sgr_only());
+                self.fill_rectangle(&area, &cell);
+            }
+            RectangularArea::Erase(area) => {
+                let cell = Cell::blank_with_attrs(self.pen.clone_sgr_only());
+                self.fill_rectangle(&area, &cell);
+            }
+            RectangularArea::SelectiveErase(area) => {
+                if let Some((rows, cols)) = self.rectangle_bounds(&area) {
+                    let screen = self.screen_mut();
+                    for y in rows {
+                        for x in cols.clone() {
+                            if let Some(attrs) = screen.get_cell(x, y).map(|c| c.attrs().clone()) {
+                                screen.set_cell(x, y, &Cell::new(' ', attrs), seqno);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn fill_rectangle(&mut self, area: &Rectangle, cell: &Cell) {
+        let seqno = self.seqno;
+        if let Some((rows, cols)) = self.rectangle_bounds(area) {
+            let screen = self.screen_mut();
+            for y in rows {
+                let line_idx = screen.phys_row(y);
+                screen
+                    .line_mut(line_idx)
+                    .fill_range(cols.clone(), cell, seqno);
+            }
+        }
+    }
+
     fn erase_in_display(&mut self, erase: EraseInDisplay) {
         let seqno = self.seqno;
         let cy = self.cursor.y;
@@ -1,8 +1,9 @@
 use crate::terminal::Alert;
 use crate::terminalstate::{
     default_color_map, CharSet, MouseEncoding, TabStop, UnicodeVersionStackEntry,
+    MAX_NOTIFICATION_CHUNKS, MAX_NOTIFICATION_SIZE,
 };
-use crate::{ClipboardSelection, Position, TerminalState, VisibleRowIndex, DCS, ST};
+use crate::{ClipboardQuery, ClipboardSelection, Position, TerminalState, VisibleRowIndex};
 use finl_unicode::grapheme_clusters::Graphemes;
 use log::{debug, error};
 use num_traits::FromPrimitive;
@@ -12,16 +13,20 @@ use std::io::Write as _;
 use std::ops::{Deref, DerefMut};
 use termwiz::cell::{grapheme_column_width, Cell, CellAttributes, SemanticType};
 use termwiz::escape::csi::{
-    CharacterPath, EraseInDisplay, Keyboard, KittyKeyboardFlags, KittyKeyboardMode,
+    CharacterPath, CursorStyle, EraseInDisplay, Keyboard, KittyKeyboardFlags, KittyKeyboardMode,
 };
+use termwiz::escape::decrqss::{StatusReport, StatusString};
+use termwiz::escape::notification::{NotificationKind, NotificationOccasion, NotificationProtocol};
 use termwiz::escape::osc::{
-    ChangeColorPair, ColorOrQuery, FinalTermSemanticPrompt, ITermProprietary,
-    ITermUnicodeVersionOp, Selection,
+    ColorOrQuery, FinalTermSemanticPrompt, ITermProprietary, ITermUnicodeVersionOp, Notification,
+    Selection,
 };
 use termwiz::escape::{
-    Action, ControlCode, DeviceControlMode, Esc, EscCode, OperatingSystemCommand, CSI,
+    Action, BatchedAction, ControlCode, DeviceControlMode, Esc, EscCode, OperatingSystemCommand,
+    CSI,
 };
 use termwiz::input::KeyboardEncoding;
+use termwiz::surface::{CursorShape, SequenceNo};
 use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
 use url::Url;
 use wezterm_bidi::ParagraphDirectionHint;
@@ -130,90 +135,111 @@ impl<'a> Performer<'a> {
         };
 
         for g in Graphemes::new(text) {
-            let g = self.remap_grapheme(g);
-
-            let print_width = grapheme_column_width(g, Some(self.unicode_version));
-            if print_width == 0 {
-                // We got a zero-width grapheme.
-                // We used to force them into a cell to guarantee that we
-                // preserved them in the model, but it introduces presentation
-                // problems, such as <https://github.com/wez/wezterm/issues/1422>
-                log::trace!("Eliding zero-width grapheme {:?}", g);
-                continue;
-            }
-
-            if self.wrap_next {
-                // Since we're implicitly moving the cursor to the next
-                // line, we need to tag the current position as wrapped
-                // so that we can correctly reflow it if the window is
-                // resized.
-                {
-                    let y = self.cursor.y;
-                    let is_conpty = self.state.enable_conpty_quirks;
-                    let screen = self.screen_mut();
-                    let y = screen.phys_row(y);
-
-                    fn makes_sense_to_wrap(s: &str) -> bool {
-                        let len = s.len();
-                        match (len, s.chars().next()) {
-                            (1, Some(c)) => c.is_alphanumeric() || c.is_ascii_punctuation(),
-                            _ => true,
-                        }
-                    }
-
-                    let should_mark_wrapped = !is_conpty
-                        || screen
-                            .line_mut(y)
-                            .visible_cells()
-                            .last()
-                            .map(|cell| makes_sense_to_wrap(cell.str()))
-                            .unwrap_or(false);
-                    if should_mark_wrapped {
-                        screen.line_mut(y).set_last_cell_was_wrapped(true, seqno);
+            if self.grapheme_clustering {
+                self.print_grapheme(g, seqno);
+            } else {
+                // Without grapheme clustering, each codepoint that has
+                // a width starts a new cell, taking the zero-width
+                // codepoints that follow it along with it
+                let mut start = 0;
+                for (idx, c) in g.char_indices().skip(1) {
+                    let mut buf = [0u8; 4];
+                    if grapheme_column_width(c.encode_utf8(&mut buf), Some(self.unicode_version))
+                        > 0
+                    {
+                        self.print_grapheme(&g[start..idx], seqno);
+                        start = idx;
                     }
                 }
-                self.new_line(true);
+                self.print_grapheme(&g[start..], seqno);
             }
+        }
 
-            let x = self.cursor.x;
-            let y = self.cursor.y;
-            let width = self.left_and_right_margins.end;
+        std::mem::swap(&mut self.print, &mut p);
+        self.print.clear();
+    }
 
-            let pen = self.pen.clone();
+    fn print_grapheme(&mut self, g: &str, seqno: SequenceNo) {
+        let g = self.remap_grapheme(g);
 
-            let wrappable = x + print_width >= width;
+        let print_width = grapheme_column_width(g, Some(self.unicode_version));
+        if print_width == 0 {
+            // We got a zero-width grapheme.
+            // We used to force them into a cell to guarantee that we
+            // preserved them in the model, but it introduces presentation
+            // problems, such as <https://github.com/wez/wezterm/issues/1422>
+            log::trace!("Eliding zero-width grapheme {:?}", g);
+            return;
+        }
 
-            if self.insert {
-                let margin = self.left_and_right_margins.end;
+        if self.wrap_next {
+            // Since we're implicitly moving the cursor to the next
+            // line, we need to tag the current position as wrapped
+            // so that we can correctly reflow it if the window is
+            // resized.
+            {
+                let y = self.cursor.y;
+                let is_conpty = self.state.enable_conpty_quirks;
                 let screen = self.screen_mut();
-                for _ in x..x + print_width as usize {
-                    screen.insert_cell(x, y, margin, seqno);
+                let y = screen.phys_row(y);
+
+                fn makes_sense_to_wrap(s: &str) -> bool {
+                    let len = s.len();
+                    match (len, s.chars().next()) {
+                        (1, Some(c)) => c.is_alphanumeric() || c.is_ascii_punctuation(),
+                        _ => true,
+                    }
+                }
+
+                let should_mark_wrapped = !is_conpty
+                    || screen
+                        .line_mut(y)
+                        .visible_cells()
+                        .last()
+                        .map(|cell| makes_sense_to_wrap(cell.str()))
+                        .unwrap_or(false);
+                if should_mark_wrapped {
+                    screen.line_mut(y).set_last_cell_was_wrapped(true, seqno);
                 }
             }
+            self.new_line(true);
+        }
 
-            // Assign the cell
-            log::trace!(
-                "print x={} y={} print_width={} width={} cell={} {:?}",
-                x,
-                y,
-                print_width,
-                width,
-                g,
-                self.pen
-            );
-            self.screen_mut()
-                .set_cell_grapheme(x, y, g, print_width, pen, seqno);
+        let x = self.cursor.x;
+        let y = self.cursor.y;
+        let width = self.left_and_right_margins.end;
 
-            if !wrappable {
-                self.cursor.x += print_width;
-                self.wrap_next = false;
-            } else {
-                self.wrap_next = self.dec_auto_wrap;
+        let pen = self.pen.clone();
+
+        let wrappable = x + print_width >= width;
+
+        if self.insert {
+            let margin = self.left_and_right_margins.end;
+            let screen = self.screen_mut();
+            for _ in x..x + print_width as usize {
+                screen.insert_cell(x, y, margin, seqno);
             }
         }
 
-        std::mem::swap(&mut self.print, &mut p);
-        self.print.clear();
+        // Assign the cell
+        log::trace!(
+            "print x={} y={} print_width={} width={} cell={} {:?}",
+            x,
+            y,
+            print_width,
+            width,
+            g,
+            self.pen
+        );
+        self.screen_mut()
+            .set_cell_grapheme(x, y, g, print_width, pen, seqno);
+
+        if !wrappable {
+            self.cursor.x += print_width;
+            self.wrap_next = false;
+        } else {
+            self.wrap_next = self.dec_auto_wrap;
+        }
     }
 
     /// ConPTY, at the time of writing, does something horrible to rewrite
@@ -247,17 +273,14 @@ impl<'a> Performer<'a> {
         }
         match action {
             Action::Print(c) => self.print(c),
-            Action::PrintString(s) => {
-                for c in s.chars() {
-                    self.print(c)
-                }
-            }
+            Action::PrintString(s) => self.print_str(&s),
             Action::Control(code) => self.control(code),
             Action::DeviceControl(ctrl) => self.device_control(ctrl),
             Action::OperatingSystemCommand(osc) => self.osc_dispatch(*osc),
             Action::Esc(esc) => self.esc_dispatch(esc),
             Action::CSI(csi) => self.csi_dispatch(csi),
             Action::Sixel(sixel) => self.sixel(sixel),
+            Action::SixelStream(stream) => self.sixel_stream(stream),
             Action::XtGetTcap(names) => self.xt_get_tcap(names),
             Action::KittyImage(img) => {
                 self.flush_print();
@@ -265,6 +288,15 @@ impl<'a> Performer<'a> {
                     log::error!("kitty_img: {:#}", err);
                 }
             }
+            Action::ParseError { bytes, reason } => {
+                if self.config.log_unknown_escape_sequences() {
+                    log::warn!(
+                        "malformed sequence ({:?}): {}",
+                        reason,
+                        bytes.escape_ascii()
+                    );
+                }
+            }
         }
     }
 
@@ -273,57 +305,10 @@ impl<'a> Performer<'a> {
         match &ctrl {
             DeviceControlMode::ShortDeviceControl(s) => {
                 match (s.byte, s.intermediates.as_slice()) {
-                    (b'q', &[b'$']) => {
-                        // DECRQSS - Request Status String
-                        // https://vt100.net/docs/vt510-rm/DECRQSS.html
-                        // The response is described here:
-                        // https://vt100.net/docs/vt510-rm/DECRPSS.html
-                        // but note that *that* text has the validity value
-                        // inverted; there's a note about this in the xterm
-                        // ctlseqs docs.
-                        match s.data.as_slice() {
-                            &[b'"', b'p'] => {
-                                // DECSCL - select conformance level
-                                write!(self.writer, "{}1$r65;1\"p{}", DCS, ST).ok();
-                                self.writer.flush().ok();
-                            }
-                            &[b'r'] => {
-                                // DECSTBM - top and bottom margins
-                                let margins = self.top_and_bottom_margins.clone();
-                                write!(
-                                    self.writer,
-                                    "{}1$r{};{}r{}",
-                                    DCS,
-                                    margins.start + 1,
-                                    margins.end,
-                                    ST
-                                )
-                                .ok();
-                                self.writer.flush().ok();
-                            }
-                            &[b's'] => {
-                                // DECSLRM - left and right margins
-                                let margins = self.left_and_right_margins.clone();
-                                write!(
-                                    self.writer,
-                                    "{}1$r{};{}s{}",
-                                    DCS,
-                                    margins.start + 1,
-                                    margins.end,
-                                    ST
-                                )
-                                .ok();
-                                self.writer.flush().ok();
-                            }
-                            _ => {
-                                if self.config.log_unknown_escape_sequences() {
-                                    log::warn!("unhandled DECRQSS {:?}", s);
-                                }
-                                // Reply that the request is invalid
-                                write!(self.writer, "{}0$r{}", DCS, ST).ok();
-                                self.writer.flush().ok();
-                            }
-                        }
+                    (b'q', &[b'$']) => self.report_status_string(&s.data),
+                    (b's', &[b'=']) if s.synchronized_update().is_some() => {
+                        // BSU/ESU; the output is held back by wezterm's mux
+                        self.synchronized_output = s.synchronized_update() == Some(true);
                     }
                     _ => {
                         if self.config.log_unknown_escape_sequences() {
@@ -332,6 +317,11 @@ impl<'a> Performer<'a> {
                     }
                 }
             }
+            DeviceControlMode::TmuxPassthrough(passthrough) => {
+                for action in passthrough.actions() {
+                    self.perform(action);
+                }
+            }
             _ => match self.device_control_handler.as_mut() {
                 Some(handler) => handler.handle_device_control(ctrl),
                 None => {
@@ -353,6 +343,27 @@ impl<'a> Performer<'a> {
         }
     }
 
+    /// Draw a run of text to the screen
+    fn print_str(&mut self, text: &str) {
+        if let Some(title) = self.accumulating_title.as_mut() {
+            title.push_str(text);
+        } else {
+            self.print.push_str(text);
+        }
+    }
+
+    /// Perform an action taken from an `ActionBatch`, drawing its
+    /// text directly from the batch
+    pub fn perform_batched(&mut self, action: BatchedAction) {
+        match action {
+            BatchedAction::Print(text) => {
+                debug!("perform PrintString({:?})", text);
+                self.print_str(text)
+            }
+            BatchedAction::Action(action) => self.perform(action),
+        }
+    }
+
     fn control(&mut self, control: ControlCode) {
         let seqno = self.seqno;
         self.pop_tmux_title_state();
@@ -487,7 +498,9 @@ impl<'a> Performer<'a> {
             CSI::Mode(mode) => self.state.perform_csi_mode(mode),
             CSI::Device(dev) => self.state.perform_device(*dev),
             CSI::Mouse(mouse) => error!("mouse report sent by app? {:?}", mouse),
+            CSI::KeyReport(report) => error!("key report sent by app? {:?}", report),
             CSI::Window(window) => self.state.perform_csi_window(*window),
+            CSI::RectangularArea(op) => self.state.perform_csi_rectangular_area(*op),
             CSI::SelectCharacterPath(CharacterPath::ImplementationDefault, _) => {
                 self.state.bidi_hint.take();
             }
@@ -660,6 +673,7 @@ impl<'a> Performer<'a> {
             Esc::Code(EscCode::FullReset) => {
                 let seqno = self.seqno;
                 self.pen = Default::default();
+                self.recent_hyperlinks.clear();
                 self.cursor = Default::default();
                 self.wrap_next = false;
                 self.clear_semantic_attribute_on_newline = false;
@@ -669,6 +683,7 @@ impl<'a> Performer<'a> {
                 self.reverse_video_mode = false;
                 self.dec_origin_mode = false;
                 self.use_private_color_registers_for_each_graphic = false;
+                self.synchronized_output = false;
                 self.color_map = default_color_map();
                 self.application_cursor_keys = false;
                 self.sixel_display_mode = false;
@@ -694,8 +709,10 @@ impl<'a> Performer<'a> {
                 self.left_and_right_margins = 0..self.screen().physical_cols;
                 self.unicode_version = self.config.unicode_version();
                 self.unicode_version_stack.clear();
+                self.grapheme_clustering = self.config.grapheme_clustering();
                 self.suppress_initial_title_change = false;
                 self.accumulating_title.take();
+                self.pending_notification.take();
 
                 self.screen.full_reset();
                 self.screen.activate_alt_screen(seqno);
@@ -714,6 +731,149 @@ impl<'a> Performer<'a> {
         }
     }
 
+    /// DECRQSS - Request Status String
+    /// <https://vt100.net/docs/vt510-rm/DECRQSS.html>
+    fn report_status_string(&mut self, data: &[u8]) {
+        let report = match StatusString::parse(data) {
+            Some(StatusString::GraphicRendition) => StatusReport::graphic_rendition(&self.pen),
+            Some(StatusString::CursorStyle) => StatusReport::CursorStyle(match self.cursor.shape {
+                CursorShape::Default => CursorStyle::Default,
+                CursorShape::BlinkingBlock => CursorStyle::BlinkingBlock,
+                CursorShape::SteadyBlock => CursorStyle::SteadyBlock,
+                CursorShape::BlinkingUnderline => CursorStyle::BlinkingUnderline,
+                CursorShape::SteadyUnderline => CursorStyle::SteadyUnderline,
+                CursorShape::BlinkingBar => CursorStyle::BlinkingBar,
+                CursorShape::SteadyBar => CursorStyle::SteadyBar,
+            }),
+            Some(StatusString::TopAndBottomMargins) => StatusReport::TopAndBottomMargins {
+                top: self.top_and_bottom_margins.start as usize + 1,
+                bottom: self.top_and_bottom_margins.end as usize,
+            },
+            Some(StatusString::LeftAndRightMargins) => StatusReport::LeftAndRightMargins {
+                left: self.left_and_right_margins.start + 1,
+                right: self.left_and_right_margins.end,
+            },
+            Some(StatusString::ConformanceLevel) => StatusReport::ConformanceLevel {
+                level: self.config.terminal_features().level,
+                eight_bit_controls: false,
+            },
+            None => {
+                if self.config.log_unknown_escape_sequences() {
+                    log::warn!("unhandled DECRQSS {:?}", String::from_utf8_lossy(data));
+                }
+                StatusReport::Invalid
+            }
+        };
+        write!(self.writer, "{}", report).ok();
+        self.writer.flush().ok();
+    }
+
+    /// Responds to an OSC 52 query with the contents of the first of
+    /// the targets of `selection` that has any, if the application is
+    /// allowed to read the clipboard
+    fn query_clipboard_contents(&mut self, selection: Selection) {
+        if !self.config.enable_osc52_clipboard_reading() {
+            log::debug!("ignoring OSC 52 query; reading the clipboard is not enabled");
+            return;
+        }
+        let clip = match self.clipboard.clone() {
+            Some(clip) => clip,
+            None => return,
+        };
+        match clip.query_contents(ClipboardQuery::new(selection)) {
+            Ok(Some(reply)) => {
+                self.writer.write_all(reply.as_bytes()).ok();
+                self.writer.flush().ok();
+            }
+            Ok(None) => {}
+            Err(err) => log::error!("failed to read clipboard for OSC 52: {:#}", err),
+        }
+    }
+
+    fn notify(&mut self, notification: Notification) {
+        match &notification.kind {
+            NotificationKind::Show => {}
+            NotificationKind::Query(_) => {
+                let mut response = Notification::new(
+                    NotificationProtocol::Kitty,
+                    // Only what is honored is reported: the notification
+                    // can't be focused, closed or given an urgency
+                    NotificationKind::Query("o=always,unfocused:p=title,body,?".into()),
+                );
+                response.id = notification.id;
+                write!(
+                    self.writer,
+                    "{}",
+                    OperatingSystemCommand::Notification(response)
+                )
+                .ok();
+                self.writer.flush().ok();
+                return;
+            }
+            NotificationKind::Close => {
+                // Only a notification that is still being sent can be closed
+                if matches!(&self.pending_notification, Some((pending, _)) if pending.id == notification.id)
+                {
+                    self.pending_notification.take();
+                }
+                return;
+            }
+            NotificationKind::Alive(_) => return,
+        }
+
+        // A kitty notification may be sent in several chunks
+        let (mut notification, mut chunks) = match self.pending_notification.take() {
+            Some((mut pending, chunks)) if pending.id == notification.id => {
+                for (text, chunk) in [
+                    (&mut pending.title, notification.title),
+                    (&mut pending.body, notification.body),
+                ] {
+                    if let Some(chunk) = chunk {
+                        text.get_or_insert_with(String::new).push_str(&chunk);
+                    }
+                }
+                pending.done = notification.done;
+                (pending, chunks.saturating_add(1))
+            }
+            _ => (notification, 1),
+        };
+        let size = notification.title.as_ref().map_or(0, String::len)
+            + notification.body.as_ref().map_or(0, String::len);
+        if size > MAX_NOTIFICATION_SIZE {
+            // Keep track of the rest of its chunks, so that they
+            // aren't taken to be a new notification, but not its text
+            notification.title.take();
+            notification.body.take();
+            chunks = chunks.max(MAX_NOTIFICATION_CHUNKS + 1);
+        }
+        if !notification.done {
+            self.pending_notification.replace((notification, chunks));
+            return;
+        }
+        if chunks > MAX_NOTIFICATION_CHUNKS {
+            log::warn!("Discarding notification that was too large or sent in too many chunks");
+            return;
+        }
+
+        if notification.occasion != NotificationOccasion::Always && self.focused {
+            return;
+        }
+        let (title, body) = match (notification.title, notification.body) {
+            (Some(title), Some(body)) => (Some(title), body),
+            (Some(text), None) | (None, Some(text)) => (None, text),
+            (None, None) => return,
+        };
+        if let Some(handler) = self.alert_handler.as_mut() {
+            handler.alert(Alert::ToastNotification {
+                title,
+                body,
+                focus: notification.focus,
+            });
+        } else {
+            log::info!("Application sends notification: {}", body);
+        }
+    }
+
     fn osc_dispatch(&mut self, osc: OperatingSystemCommand) {
         self.pop_tmux_title_state();
         self.flush_print();
@@ -762,15 +922,30 @@ impl<'a> Performer<'a> {
             }
 
             OperatingSystemCommand::ClearSelection(selection) => {
-                let selection = selection_to_selection(selection);
-                self.set_clipboard_contents(selection, None).ok();
+                for target in selection_targets(selection) {
+                    self.set_clipboard_contents(target, None).ok();
+                }
+            }
+            OperatingSystemCommand::QuerySelection(selection) => {
+                self.query_clipboard_contents(selection)
             }
-            OperatingSystemCommand::QuerySelection(_) => {}
             OperatingSystemCommand::SetSelection(selection, selection_data) => {
-                let selection = selection_to_selection(selection);
-                match self.set_clipboard_contents(selection, Some(selection_data)) {
-                    Ok(_) => (),
-                    Err(err) => error!("failed to set clipboard in response to OSC 52: {:#?}", err),
+                let max_size = self.config.osc52_max_clipboard_size();
+                if selection_data.len() > max_size {
+                    log::warn!(
+                        "ignoring OSC 52 of {} bytes, which exceeds \
+                         osc52_max_clipboard_size of {} bytes",
+                        selection_data.len(),
+                        max_size
+                    );
+                } else {
+                    for target in selection_targets(selection) {
+                        if let Err(err) =
+                            self.set_clipboard_contents(target, Some(selection_data.clone()))
+                        {
+                            error!("failed to set clipboard in response to OSC 52: {:#?}", err);
+                        }
+                    }
                 }
             }
             OperatingSystemCommand::ITermProprietary(iterm) => match iterm {
@@ -806,12 +981,32 @@ impl<'a> Performer<'a> {
                     self.writer.flush().ok();
                 }
                 ITermProprietary::File(image) => self.set_image(*image),
+                ITermProprietary::MultipartFile(file) => self.begin_multipart_file(*file),
+                ITermProprietary::FilePart(data) => self.add_file_part(data),
+                ITermProprietary::FileEnd => self.end_multipart_file(),
                 ITermProprietary::SetUserVar { name, value } => {
                     self.user_vars.insert(name.clone(), value.clone());
                     if let Some(handler) = self.alert_handler.as_mut() {
                         handler.alert(Alert::SetUserVar { name, value });
                     }
                 }
+                ITermProprietary::ReportVariable(name) => {
+                    // Only the user variables, which were set by the
+                    // application, are reported
+                    let value = name
+                        .strip_prefix("user.")
+                        .and_then(|name| self.user_vars.get(name))
+                        .cloned()
+                        .unwrap_or_default();
+                    let response = OperatingSystemCommand::ITermProprietary(
+                        ITermProprietary::ReportVariable(value),
+                    );
+                    write!(self.writer, "{}", response).ok();
+                    self.writer.flush().ok();
+                }
+                ITermProprietary::ClearScrollback => {
+                    self.erase_in_display(EraseInDisplay::EraseScrollback);
+                }
                 ITermProprietary::UnicodeVersion(ITermUnicodeVersionOp::Set(n)) => {
                     self.unicode_version.version = n;
                 }
@@ -881,56 +1076,25 @@ impl<'a> Performer<'a> {
                 FinalTermSemanticPrompt::CommandStatus { .. },
             ) => {}
 
-            OperatingSystemCommand::SystemNotification(message) => {
-                if let Some(handler) = self.alert_handler.as_mut() {
-                    handler.alert(Alert::ToastNotification {
-                        title: None,
-                        body: message,
-                        focus: true,
-                    });
-                } else {
-                    log::info!("Application sends SystemNotification: {}", message);
-                }
-            }
-            OperatingSystemCommand::RxvtExtension(params) => {
-                if let Some("notify") = params.get(0).map(String::as_str) {
-                    let title = params.get(1);
-                    let body = params.get(2);
-                    let (title, body) = match (title.cloned(), body.cloned()) {
-                        (Some(title), None) => (None, title),
-                        (Some(title), Some(body)) => (Some(title), body),
-                        _ => {
-                            log::warn!("malformed rxvt notify escape: {:?}", params);
-                            return;
-                        }
-                    };
-                    if let Some(handler) = self.alert_handler.as_mut() {
-                        handler.alert(Alert::ToastNotification {
-                            title,
-                            body,
-                            focus: true,
-                        });
-                    }
-                }
-            }
-            OperatingSystemCommand::CurrentWorkingDirectory(url) => {
-                self.current_dir = Url::parse(&url).ok();
+            OperatingSystemCommand::Notification(notification) => self.notify(notification),
+            OperatingSystemCommand::RxvtExtension(_) => {}
+            OperatingSystemCommand::CurrentWorkingDirectory(dir) => {
+                self.current_dir = Url::parse(&dir.url()).ok();
                 if let Some(handler) = self.alert_handler.as_mut() {
                     handler.alert(Alert::CurrentWorkingDirectoryChanged);
                 }
             }
             OperatingSystemCommand::ChangeColorNumber(specs) => {
                 log::trace!("ChangeColorNumber: {:?}", specs);
+                // Answering a query doesn't change the palette
+                let changed = specs.iter().any(|pair| pair.color != ColorOrQuery::Query);
                 for pair in specs {
                     match pair.color {
                         ColorOrQuery::Query => {
-                            let response =
-                                OperatingSystemCommand::ChangeColorNumber(vec![ChangeColorPair {
-                                    palette_index: pair.palette_index,
-                                    color: ColorOrQuery::Color(
-                                        self.palette().colors.0[pair.palette_index as usize],
-                                    ),
-                                }]);
+                            let response = OperatingSystemCommand::palette_color_response(
+                                pair.palette_index,
+                                self.palette().colors.0[pair.palette_index as usize],
+                            );
                             write!(self.writer, "{}", response).ok();
                             self.writer.flush().ok();
                         }
@@ -939,8 +1103,10 @@ impl<'a> Performer<'a> {
                         }
                     }
                 }
-                self.implicit_palette_reset_if_same_as_configured();
-                self.palette_did_change();
+                if changed {
+                    self.implicit_palette_reset_if_same_as_configured();
+                    self.palette_did_change();
+                }
             }
 
             OperatingSystemCommand::ResetColors(colors) => {
@@ -967,6 +1133,7 @@ impl<'a> Performer<'a> {
             OperatingSystemCommand::ChangeDynamicColors(first_color, colors) => {
                 log::trace!("ChangeDynamicColors: {:?} {:?}", first_color, colors);
                 use termwiz::escape::osc::DynamicColorNumber;
+                let changed = colors.iter().any(|color| *color != ColorOrQuery::Query);
                 let mut idx: u8 = first_color as u8;
                 for color in colors {
                     let which_color: Option<DynamicColorNumber> = FromPrimitive::from_u8(idx);
@@ -976,10 +1143,11 @@ impl<'a> Performer<'a> {
                             ($name:ident) => {
                                 match color {
                                     ColorOrQuery::Query => {
-                                        let response = OperatingSystemCommand::ChangeDynamicColors(
-                                            which_color,
-                                            vec![ColorOrQuery::Color(self.palette().$name.into())],
-                                        );
+                                        let response =
+                                            OperatingSystemCommand::dynamic_color_response(
+                                                which_color,
+                                                self.palette().$name.into(),
+                                            );
                                         log::trace!("Color Query response {:?}", response);
                                         write!(self.writer, "{}", response).ok();
                                         self.writer.flush().ok();
@@ -1015,8 +1183,10 @@ impl<'a> Performer<'a> {
                     }
                     idx += 1;
                 }
-                self.implicit_palette_reset_if_same_as_configured();
-                self.palette_did_change();
+                if changed {
+                    self.implicit_palette_reset_if_same_as_configured();
+                    self.palette_did_change();
+                }
             }
 
             OperatingSystemCommand::ResetDynamicColor(color) => {
@@ -1059,15 +1229,20 @@ impl<'a> Performer<'a> {
     }
 }
 
-fn selection_to_selection(sel: Selection) -> ClipboardSelection {
-    match sel {
-        Selection::CLIPBOARD => ClipboardSelection::Clipboard,
-        Selection::PRIMARY => ClipboardSelection::PrimarySelection,
-        // xterm will use a configurable selection in the NONE case
-        Selection::NONE => ClipboardSelection::Clipboard,
-        // otherwise we just use clipboard.  Could potentially
-        // also use the same fallback configuration as NONE,
-        // if/when we add it
-        _ => ClipboardSelection::Clipboard,
+/// Returns the clipboards that correspond to the targets of OSC 52.
+/// We don't have the select target or cut buffers, so when neither
+/// the clipboard nor the primary selection is targeted, which
+/// includes the default of `s0`, the clipboard is used.
+pub(crate) fn selection_targets(sel: Selection) -> Vec<ClipboardSelection> {
+    let mut targets = vec![];
+    if sel.contains(Selection::CLIPBOARD) {
+        targets.push(ClipboardSelection::Clipboard);
+    }
+    if sel.contains(Selection::PRIMARY) {
+        targets.push(ClipboardSelection::PrimarySelection);
+    }
+    if targets.is_empty() {
+        targets.push(ClipboardSelection::Clipboard);
     }
+    targets
 }
@@ -3,110 +3,207 @@ use crate::terminalstate::{default_color_map, ImageAttachParams};
 use crate::TerminalState;
 use ::image::RgbaImage;
 use termwiz::color::RgbColor;
-use termwiz::escape::{Sixel, SixelData};
+use termwiz::escape::parser::{SixelBand, SixelDecoder, SixelLimits};
+use termwiz::escape::{Sixel, SixelStream};
 use termwiz::image::ImageDataType;
 
+/// A sixel image that is being received.  Its data is decoded as it
+/// arrives, and each row of cells that the image covers is shown as
+/// soon as its pixels are complete, so that only the bands of pixels
+/// of that row are held in memory.
+pub(crate) struct PendingSixel {
+    decoder: SixelDecoder,
+    /// Whether the color registers are private to this image
+    private_color_map: bool,
+    background_is_transparent: bool,
+    /// The size of the image, if it was given by its raster attributes
+    dimensions: Option<(u32, u32)>,
+    /// The width of the image so far
+    width: u32,
+    /// The decoded bands that are not yet entirely shown
+    bands: Vec<SixelBand>,
+    /// The height of a row of cells, in pixels
+    row_height: u32,
+    /// The number of rows of cells of the image that were shown
+    rows_shown: u32,
+    /// The row of the image that the cursor is on
+    cursor_row: u32,
+    /// The column at which the image is placed
+    cursor_x: usize,
+    /// Set when the image grew too large; the rest of it is discarded
+    overflow: bool,
+}
+
 impl TerminalState {
-    pub(crate) fn sixel(&mut self, sixel: Box<Sixel>) {
-        let (width, height) = sixel.dimensions();
+    pub(crate) fn sixel(&mut self, mut sixel: Box<Sixel>) {
+        let data = std::mem::take(&mut sixel.data);
+        self.sixel_stream(SixelStream::Enter(sixel));
+        for d in data {
+            self.sixel_stream(SixelStream::Data(d));
+        }
+        self.sixel_stream(SixelStream::Exit);
+    }
 
-        if let Err(err) = check_image_dimensions(width, height) {
-            log::error!("{}", err);
-            return;
+    pub(crate) fn sixel_stream(&mut self, stream: SixelStream) {
+        match stream {
+            SixelStream::Enter(sixel) => {
+                if let Some(pending) = self.pending_sixel.take() {
+                    self.finish_sixel(pending);
+                }
+                self.pending_sixel = self.start_sixel(&sixel);
+            }
+            SixelStream::Data(data) => {
+                if let Some(mut pending) = self.pending_sixel.take() {
+                    if let Some(band) = pending.decoder.push_data(&data) {
+                        self.add_sixel_band(&mut pending, band);
+                    }
+                    self.pending_sixel.replace(pending);
+                }
+            }
+            SixelStream::Exit => {
+                if let Some(pending) = self.pending_sixel.take() {
+                    self.finish_sixel(pending);
+                }
+            }
         }
+    }
 
-        let mut private_color_map;
-        let color_map = if self.use_private_color_registers_for_each_graphic {
-            private_color_map = default_color_map();
-            &mut private_color_map
-        } else {
-            &mut self.color_map
+    fn start_sixel(&mut self, sixel: &Sixel) -> Option<PendingSixel> {
+        let dimensions = match (sixel.pixel_width, sixel.pixel_height) {
+            (Some(_), Some(_)) => {
+                let (width, height) = sixel.dimensions();
+                if let Err(err) = check_image_dimensions(width, height) {
+                    log::error!("{}", err);
+                    return None;
+                }
+                Some((width, height))
+            }
+            // The size is learned as the data is decoded
+            _ => None,
+        };
+        let limits = match dimensions {
+            Some((max_width, max_height)) => SixelLimits {
+                max_width,
+                max_height,
+            },
+            None => SixelLimits::default(),
         };
 
-        let mut image = if sixel.background_is_transparent {
-            RgbaImage::new(width, height)
+        let private_color_map = self.use_private_color_registers_for_each_graphic;
+        let color_map = if private_color_map {
+            default_color_map()
         } else {
-            let background_color = color_map
-                .get(&0)
-                .cloned()
-                .unwrap_or(RgbColor::new_8bpc(0, 0, 0));
-            let (red, green, blue) = background_color.to_tuple_rgb8();
-            RgbaImage::from_pixel(width, height, [red, green, blue, 0xffu8].into())
+            std::mem::take(&mut self.color_map)
         };
 
-        let mut x = 0;
-        let mut y = 0;
-        let mut foreground_color = RgbColor::new_8bpc(0, 0xff, 0);
+        let row_height = self.pixel_height / self.screen().physical_rows;
+        Some(PendingSixel {
+            decoder: SixelDecoder::for_sixel(sixel, limits).with_color_map(color_map),
+            private_color_map,
+            background_is_transparent: sixel.background_is_transparent,
+            dimensions,
+            width: dimensions.map_or(0, |(width, _)| width),
+            bands: vec![],
+            row_height: row_height.max(1) as u32,
+            rows_shown: 0,
+            cursor_row: 0,
+            cursor_x: self.cursor.x,
+            overflow: false,
+        })
+    }
 
-        let mut emit_sixel = |d: &u8, foreground_color: &RgbColor, x: u32, y: u32| {
-            if x >= width {
-                return;
-            }
-            let (red, green, blue) = foreground_color.to_tuple_rgb8();
-            for bitno in 0..6 {
-                if y + bitno >= height {
-                    break;
-                }
-                let on = (d & (1 << bitno)) != 0;
-                if on {
-                    image.get_pixel_mut(x, y + bitno).0 = [red, green, blue, 0xffu8];
-                }
-            }
-        };
+    fn add_sixel_band(&mut self, pending: &mut PendingSixel, band: SixelBand) {
+        if pending.overflow {
+            return;
+        }
+        pending.width = pending.width.max(band.width);
+        let bottom = band.y + band.height;
+        pending.bands.push(band);
+        // The rows above the bottom of the band are complete
+        while (pending.rows_shown + 1) * pending.row_height <= bottom {
+            self.show_sixel_row(pending, pending.row_height);
+        }
+    }
 
-        for d in &sixel.data {
-            match d {
-                SixelData::Data(d) => {
-                    emit_sixel(d, &foreground_color, x, y);
-                    x += 1;
-                }
+    fn finish_sixel(&mut self, mut pending: PendingSixel) {
+        for band in pending.decoder.finish() {
+            self.add_sixel_band(&mut pending, band);
+        }
 
-                SixelData::Repeat { repeat_count, data } => {
-                    for _ in 0..*repeat_count {
-                        emit_sixel(data, &foreground_color, x, y);
-                        x += 1;
-                    }
-                }
+        let height = match pending.dimensions {
+            Some((_, height)) => height,
+            None => pending
+                .bands
+                .iter()
+                .map(|band| band.y + band.height)
+                .max()
+                .unwrap_or(0),
+        };
+        loop {
+            let top = pending.rows_shown * pending.row_height;
+            if top >= height {
+                break;
+            }
+            let row_height = pending.row_height.min(height - top);
+            self.show_sixel_row(&mut pending, row_height);
+        }
 
-                SixelData::CarriageReturn => x = 0,
-                SixelData::NewLine => {
-                    x = 0;
-                    y += 6;
-                }
+        if !pending.private_color_map {
+            self.color_map = pending.decoder.into_color_map();
+        }
+    }
 
-                SixelData::DefineColorMapRGB { color_number, rgb } => {
-                    color_map.insert(*color_number, *rgb);
-                }
+    /// Shows the next row of cells of the image, which is `height`
+    /// pixels tall
+    fn show_sixel_row(&mut self, pending: &mut PendingSixel, height: u32) {
+        let row = pending.rows_shown;
+        let top = row * pending.row_height;
+        pending.rows_shown += 1;
+        if pending.overflow {
+            return;
+        }
 
-                SixelData::DefineColorMapHSL {
-                    color_number,
-                    hue_angle,
-                    saturation,
-                    lightness,
-                } => {
-                    // Sixel's hue angles are: blue=0, red=120, green=240,
-                    // whereas Hsl has red=0, green=120, blue=240.
-                    // Looking at red, we need to rotate left by 120 to
-                    // go from sixel red to standard hsl red.
-                    // Negative values wrap around the circle.
-                    // https://github.com/wez/wezterm/issues/775
-                    let angle = (*hue_angle as f64) - 120.0;
-                    let angle = if angle < 0. { 360.0 + angle } else { angle };
-                    let c = csscolorparser::Color::from_hsla(
-                        angle,
-                        *saturation as f64 / 100.,
-                        *lightness as f64 / 100.,
-                        1.,
-                    );
-                    let [r, g, b, _] = c.to_rgba8();
-                    color_map.insert(*color_number, RgbColor::new_8bpc(r, g, b));
+        let width = pending.width;
+        if width == 0 {
+            // Nothing has been drawn yet
+            return;
+        }
+        if let Err(err) = check_image_dimensions(width, top + height) {
+            log::error!("{}", err);
+            pending.overflow = true;
+            pending.bands.clear();
+            return;
+        }
+
+        let mut image = RgbaImage::new(width, height);
+        for band in &pending.bands {
+            for y in band.y.max(top)..(band.y + band.height).min(top + height) {
+                for x in 0..band.width.min(width) {
+                    let pixel = band.pixel(x, y - band.y);
+                    if pixel[3] != 0 {
+                        image.get_pixel_mut(x, y - top).0 = pixel;
+                    }
                 }
+            }
+        }
+        pending
+            .bands
+            .retain(|band| band.y + band.height > top + height);
 
-                SixelData::SelectColorMapEntry(n) => {
-                    foreground_color = color_map.get(n).cloned().unwrap_or_else(|| {
-                        log::error!("sixel selected noexistent colormap entry {}", n);
-                        RgbColor::new_8bpc(255, 255, 255)
-                    });
+        if !pending.background_is_transparent {
+            // The background is color register 0 as it was left by
+            // the sixel data so far, which commonly redefines it
+            // before drawing anything
+            let background_color = pending
+                .decoder
+                .color_map()
+                .get(&0)
+                .cloned()
+                .unwrap_or(RgbColor::new_8bpc(0, 0, 0));
+            let (red, green, blue) = background_color.to_tuple_rgb8();
+            for pixel in image.pixels_mut() {
+                if pixel.0[3] == 0 {
+                    pixel.0 = [red, green, blue, 0xff];
                 }
             }
         }
@@ -126,8 +223,18 @@ impl TerminalState {
             // Sixel Display Mode (DECSDM) requires placing the image
             // at the top-left corner, but not moving the text cursor
             // position.
+            if row as usize >= self.screen().physical_rows {
+                return;
+            }
             self.cursor.x = 0;
-            self.cursor.y = 0;
+            self.cursor.y = row as i64;
+        } else {
+            // The cursor was left on the last row that was shown
+            while pending.cursor_row < row {
+                self.new_line(false);
+                pending.cursor_row += 1;
+            }
+            self.cursor.x = pending.cursor_x;
         }
         if let Err(err) = self.assign_image_to_cells(ImageAttachParams {
             image_width: width,
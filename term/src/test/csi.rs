@@ -1,4 +1,5 @@
 use super::*;
+use k9::assert_equal as assert_eq;
 
 /// In this issue, the `CSI 2 P` sequence incorrectly removed two
 /// cells from the line, leaving them effectively blank, when those
@@ -201,6 +202,7 @@ fn test_789() {
         bits: LineBits(
             0x0,
         ),
+        hyperlink_rules: 0,
         appdata: Mutex {
             data: None,
             poisoned: false,
@@ -401,3 +403,62 @@ fn test_ed_erase_scrollback() {
     term.print("b");
     assert_all_contents(&term, file!(), line!(), &["111", "222", "ab"]);
 }
+
+#[test]
+fn test_deccra() {
+    let mut term = TestTerm::new(4, 5, 0);
+    term.print("abcde\r\nfghij\r\nklmno\r\npqrst");
+    term.print("\x1b[1;1;2;2;1;3;4;1$v");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["abcde", "fghij", "klmab", "pqrfg"],
+    );
+
+    // Overlapping areas are copied as they were before the copy,
+    // and the destination is clipped to the screen
+    term.print("\x1b[1;1;1;;1;1;3$v");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["ababc", "fghij", "klmab", "pqrfg"],
+    );
+}
+
+#[test]
+fn test_decfra_decera() {
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("abcd\r\nefgh\r\nijkl");
+    term.print("\x1b[1m\x1b[42;2;2;3;3$x");
+    assert_visible_contents(&term, file!(), line!(), &["abcd", "e**h", "i**l"]);
+    let bold = CellAttributes::default()
+        .set_intensity(Intensity::Bold)
+        .clone();
+    assert_eq!(term.screen_mut().get_cell(1, 1).unwrap().attrs(), &bold);
+
+    // Erasing uses the current background color
+    term.print("\x1b[0;44m\x1b[1;3$z\x1b[0m");
+    assert_visible_contents(&term, file!(), line!(), &["ab  ", "e*  ", "i*  "]);
+    let navy = CellAttributes::default()
+        .set_background(color::AnsiColor::Navy)
+        .clone();
+    assert_eq!(term.screen_mut().get_cell(2, 1).unwrap().attrs(), &navy);
+
+    // The origin and extent follow DECOM
+    term.print("\x1b[2;3r\x1b[?6h\x1b[43;;;1$x\x1b[?6l");
+    assert_visible_contents(&term, file!(), line!(), &["ab  ", "++++", "i*  "]);
+}
+
+#[test]
+fn test_decsera() {
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("\x1b[1mabcd\r\nefgh");
+    term.print("\x1b[0m\x1b[1;2;2;3${");
+    assert_visible_contents(&term, file!(), line!(), &["a  d", "e  h"]);
+    let bold = CellAttributes::default()
+        .set_intensity(Intensity::Bold)
+        .clone();
+    assert_eq!(term.screen_mut().get_cell(1, 0).unwrap().attrs(), &bold);
+}
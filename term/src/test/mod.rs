@@ -9,8 +9,11 @@ mod csi;
 // mod selection; FIXME: port to render layer
 use crate::color::ColorPalette;
 use k9::assert_equal as assert_eq;
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use termwiz::escape::csi::{Edit, EraseInDisplay, EraseInLine};
+use termwiz::escape::device_attributes::TerminalFeatures;
 use termwiz::escape::{OneBased, OperatingSystemCommand, CSI};
 use termwiz::surface::{CursorShape, CursorVisibility, SequenceNo, SEQ_ZERO};
 
@@ -36,10 +39,30 @@ impl Clipboard for LocalClip {
         *self.clip.lock().unwrap() = clip;
         Ok(())
     }
+
+    fn get_contents(&self, _selection: ClipboardSelection) -> anyhow::Result<Option<String>> {
+        Ok(self.clip.lock().unwrap().clone())
+    }
 }
 
 struct TestTerm {
     term: Terminal,
+    clip: Arc<LocalClip>,
+    replies: Receiver<Vec<u8>>,
+}
+
+/// Passes what the terminal writes back to the application to the
+/// `TestTerm`, from the thread that the terminal writes it on
+struct ReplyWriter(Sender<Vec<u8>>);
+impl std::io::Write for ReplyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.send(buf.to_vec()).ok();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -58,12 +81,7 @@ impl TerminalConfiguration for TestTermConfig {
 
 impl TestTerm {
     fn new(height: usize, width: usize, scrollback: usize) -> Self {
-        let _ = env_logger::Builder::new()
-            .is_test(true)
-            .filter_level(log::LevelFilter::Trace)
-            .try_init();
-
-        let mut term = Terminal::new(
+        Self::with_config(
             TerminalSize {
                 rows: height,
                 cols: width,
@@ -72,20 +90,63 @@ impl TestTerm {
                 dpi: 0,
             },
             Arc::new(TestTermConfig { scrollback }),
+        )
+    }
+
+    fn with_config(size: TerminalSize, config: Arc<dyn TerminalConfiguration>) -> Self {
+        let _ = env_logger::Builder::new()
+            .is_test(true)
+            .filter_level(log::LevelFilter::Trace)
+            .try_init();
+
+        let (sender, replies) = channel();
+        let mut term = Terminal::new(
+            size,
+            config,
             "WezTerm",
             "O_o",
-            Box::new(Vec::new()),
+            Box::new(ReplyWriter(sender)),
         );
-        let clip: Arc<dyn Clipboard> = Arc::new(LocalClip::new());
-        term.set_clipboard(&clip);
-
-        let mut term = Self { term };
+        let clip = Arc::new(LocalClip::new());
+        let dyn_clip: Arc<dyn Clipboard> = clip.clone();
+        term.set_clipboard(&dyn_clip);
+
+        let mut term = Self {
+            term,
+            clip,
+            replies,
+        };
 
         term.set_auto_wrap(true);
 
         term
     }
 
+    /// Feeds `bytes` to the terminal, and returns whatever it has
+    /// written back to the application since the previous call.
+    /// The replies are written in order by another thread, so a
+    /// status report is requested after `bytes`; its reply marks
+    /// the end of the replies to `bytes`.
+    fn replies<B: AsRef<[u8]>>(&mut self, bytes: B) -> String {
+        const STATUS_REPORT: &[u8] = b"\x1b[0n";
+        self.print(bytes);
+        self.print("\x1b[5n");
+        let mut replies = vec![];
+        while !replies.ends_with(STATUS_REPORT) {
+            let data = self
+                .replies
+                .recv_timeout(Duration::from_secs(10))
+                .expect("the terminal didn't reply");
+            replies.extend(data);
+        }
+        replies.truncate(replies.len() - STATUS_REPORT.len());
+        String::from_utf8(replies).unwrap()
+    }
+
+    fn clipboard(&self) -> Option<String> {
+        self.clip.clip.lock().unwrap().clone()
+    }
+
     fn print<B: AsRef<[u8]>>(&mut self, bytes: B) {
         self.term.advance_bytes(bytes);
     }
@@ -1265,3 +1326,382 @@ fn test_hyperlinks() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+#[test]
+fn test_hyperlink_continuation() {
+    let mut term = TestTerm::new(3, 10, 0);
+    let link_at = |term: &TestTerm, x: usize, y: usize| {
+        term.screen().visible_lines()[y]
+            .get_cell(x)
+            .and_then(|cell| cell.attrs().hyperlink().cloned())
+    };
+
+    // A link with an id that is continued on another line, with
+    // another link opened in between, is the same link
+    term.print("\x1b]8;id=a;http://example.com\x1b\\hel\x1b]8;;\x1b\\");
+    term.print("\x1b]8;;http://other.com\x1b\\x");
+    term.print("\r\n\x1b]8;id=a;http://example.com\x1b\\lo\x1b]8;;\x1b\\");
+    let first = link_at(&term, 0, 0).unwrap();
+    assert_eq!(first.id(), Some("a"));
+    assert!(Arc::ptr_eq(&first, &link_at(&term, 1, 1).unwrap()));
+    assert_eq!(link_at(&term, 3, 0).unwrap().uri(), "http://other.com");
+
+    // The same id with a different uri is a different link
+    term.print("\x1b]8;id=a;http://example.com/b\x1b\\x\x1b]8;;\x1b\\");
+    assert!(!Arc::ptr_eq(&first, &link_at(&term, 2, 1).unwrap()));
+
+    // Links without an id are never continued
+    term.print("\r\n\x1b]8;;http://other.com\x1b\\y\x1b]8;;\x1b\\");
+    assert!(!Arc::ptr_eq(
+        &link_at(&term, 3, 0).unwrap(),
+        &link_at(&term, 0, 2).unwrap()
+    ));
+
+    // An unterminated link remains active, and closing it with
+    // params closes it too
+    term.print("\x1b]8;id=c;http://c.com\x1b\\z\x1b]8;id=c;\x1b\\z");
+    assert_eq!(link_at(&term, 1, 2).unwrap().uri(), "http://c.com");
+    assert_eq!(link_at(&term, 2, 2), None);
+}
+
+#[test]
+fn test_synchronized_output() {
+    let mut term = TestTerm::new(3, 10, 0);
+    assert!(!term.is_synchronized_output());
+    term.print("\x1b[?2026h");
+    assert!(term.is_synchronized_output());
+    term.print("\x1b[?2026l");
+    assert!(!term.is_synchronized_output());
+
+    // BSU and ESU
+    term.print("\x1bP=1s\x1b\\");
+    assert!(term.is_synchronized_output());
+    term.print("\x1bP=2s\x1b\\");
+    assert!(!term.is_synchronized_output());
+
+    term.print("\x1b[?2026h");
+    term.soft_reset();
+    assert!(!term.is_synchronized_output());
+}
+
+#[test]
+fn test_osc52() {
+    #[derive(Debug)]
+    struct Osc52Config;
+    impl TerminalConfiguration for Osc52Config {
+        fn color_palette(&self) -> ColorPalette {
+            ColorPalette::default()
+        }
+
+        fn osc52_max_clipboard_size(&self) -> usize {
+            5
+        }
+
+        fn enable_osc52_clipboard_reading(&self) -> bool {
+            true
+        }
+    }
+
+    let mut term = TestTerm::with_config(TerminalSize::default(), Arc::new(Osc52Config));
+
+    term.advance_bytes("\x1b]52;c;aGVsbG8=\x1b\\");
+    assert_eq!(term.clipboard().as_deref(), Some("hello"));
+
+    // Larger than osc52_max_clipboard_size
+    term.advance_bytes("\x1b]52;c;aGVsbG8hIQ==\x1b\\");
+    assert_eq!(term.clipboard().as_deref(), Some("hello"));
+
+    assert_eq!(
+        term.replies("\x1b]52;c;?\x1b\\"),
+        "\x1b]52;c;aGVsbG8=\x1b\\"
+    );
+
+    term.advance_bytes("\x1b]52;c;!\x1b\\");
+    assert_eq!(term.clipboard().as_deref(), None);
+}
+
+#[test]
+fn test_encode_paste() {
+    let mut term = TestTerm::new(3, 10, 0);
+    assert_eq!(
+        term.encode_paste("hello\x1b[201~world"),
+        ("helloworld".to_string(), "")
+    );
+
+    term.set_mode("?2004", true);
+    assert_eq!(
+        term.encode_paste("hello\x1b[201~world"),
+        ("\x1b[200~helloworld".to_string(), "\x1b[201~")
+    );
+}
+
+#[test]
+fn test_tmux_passthrough() {
+    let mut term = TestTerm::new(3, 10, 0);
+    // printf '\ePtmux;\e\e]1337;SetUserVar=foo=YmFy\a\e\\'
+    term.advance_bytes("\x1bPtmux;\x1b\x1b]1337;SetUserVar=foo=YmFy\x07\x1b\\");
+    assert_eq!(term.user_vars().get("foo").map(String::as_str), Some("bar"));
+}
+
+#[test]
+fn test_title_stack() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("\x1b]2;first\x07");
+    // Save both titles, then just the window title
+    term.print("\x1b[22;0t\x1b]1;icon\x07\x1b[22;2t");
+    term.print("\x1b]2;second\x07");
+    assert_eq!(term.get_title(), "icon");
+
+    term.print("\x1b[23;2t");
+    term.print("\x1b]1;\x07");
+    assert_eq!(term.get_title(), "first");
+
+    term.print("\x1b]1;other\x07\x1b]2;third\x07");
+    term.print("\x1b[23;0t");
+    assert_eq!(term.get_title(), "first");
+
+    // Popping the empty stack changes nothing
+    term.print("\x1b[23;0t");
+    assert_eq!(term.get_title(), "first");
+}
+
+#[test]
+fn test_color_queries() {
+    let mut term = TestTerm::new(24, 80, 0);
+
+    term.advance_bytes("\x1b]4;1;#ff0000\x07\x1b]11;rgb:10/20/30\x07");
+    assert_eq!(
+        term.replies("\x1b]4;1;?\x07\x1b]11;?\x07"),
+        "\x1b]4;1;rgb:ffff/0000/0000\x1b\\\x1b]11;rgb:1010/2020/3030\x1b\\"
+    );
+
+    term.advance_bytes("\x1b]104;1\x07\x1b]111\x07");
+    assert!(term.palette() == ColorPalette::default());
+}
+
+#[test]
+fn test_decrqm() {
+    let mut term = TestTerm::new(24, 80, 0);
+
+    term.advance_bytes("\x1b[?2004h\x1b[?1049h\x1b[4h");
+    assert_eq!(
+        term.replies("\x1b[?2004$p\x1b[?1049$p\x1b[?1$p\x1b[?2027$p\x1b[?9999$p\x1b[4$p"),
+        "\x1b[?2004;1$y\x1b[?1049;1$y\x1b[?1;2$y\x1b[?2027;1$y\x1b[?9999;0$y\x1b[4;1$y"
+    );
+}
+
+#[test]
+fn test_grapheme_clustering_mode() {
+    let family = "\u{1f469}\u{200d}\u{1f469}\u{200d}\u{1f466}";
+
+    let mut term = TestTerm::new(2, 10, 0);
+    term.print(family);
+    term.print("x");
+    assert_eq!((term.cursor_pos().x, term.cursor_pos().y), (3, 0));
+
+    term.print("\r\n\x1b[?2027l");
+    term.print(family);
+    term.print("x");
+    assert_eq!((term.cursor_pos().x, term.cursor_pos().y), (7, 1));
+
+    let lines = term.screen().visible_lines();
+    let cells: Vec<String> = lines[1]
+        .visible_cells()
+        .map(|cell| cell.str().to_string())
+        .collect();
+    assert_eq!(
+        cells,
+        vec![
+            "\u{1f469}\u{200d}".to_string(),
+            "\u{1f469}\u{200d}".to_string(),
+            "\u{1f466}".to_string(),
+            "x".to_string(),
+        ]
+    );
+
+    term.print("\x1bc");
+    term.print(family);
+    assert_eq!((term.cursor_pos().x, term.cursor_pos().y), (2, 0));
+}
+
+#[test]
+fn test_notifications() {
+    #[derive(Clone, Default)]
+    struct Alerts(Arc<Mutex<Vec<Alert>>>);
+    impl AlertHandler for Alerts {
+        fn alert(&mut self, alert: Alert) {
+            self.0.lock().unwrap().push(alert);
+        }
+    }
+
+    let mut term = TestTerm::new(24, 80, 0);
+    let alerts = Alerts::default();
+    term.set_notification_handler(Box::new(alerts.clone()));
+
+    term.advance_bytes("\x1b]9;the tea is ready\x07");
+    term.advance_bytes("\x1b]777;notify;alert user;the tea is ready\x07");
+    // The title and body are sent in chunks
+    term.advance_bytes("\x1b]99;i=tea:d=0:a=-focus;Te\x1b\\\x1b]99;i=tea:d=0;a\x1b\\");
+    term.advance_bytes("\x1b]99;i=tea:p=body;is ready\x1b\\");
+    // Closed before it was complete
+    term.advance_bytes("\x1b]99;i=cake:d=0;Cake\x1b\\\x1b]99;i=cake:p=close;\x1b\\");
+    // Sent in too many chunks
+    for _ in 0..100 {
+        term.advance_bytes("\x1b]99;i=many:d=0;x\x1b\\");
+    }
+    term.advance_bytes("\x1b]99;i=many;x\x1b\\");
+    // Too large
+    let chunk = "x".repeat(4096);
+    for _ in 0..5 {
+        term.advance_bytes(format!("\x1b]99;i=large:d=0;{}\x1b\\", chunk));
+    }
+    term.advance_bytes("\x1b]99;i=large;x\x1b\\");
+    term.advance_bytes("\x1b]99;o=unfocused;hidden\x1b\\");
+    term.focus_changed(false);
+    term.advance_bytes("\x1b]99;o=unfocused;shown\x1b\\");
+
+    let toast = |title: Option<&str>, body: &str, focus: bool| Alert::ToastNotification {
+        title: title.map(str::to_string),
+        body: body.to_string(),
+        focus,
+    };
+    assert_eq!(
+        alerts
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|alert| matches!(alert, Alert::ToastNotification { .. }))
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![
+            toast(None, "the tea is ready", true),
+            toast(Some("alert user"), "the tea is ready", true),
+            toast(Some("Tea"), "is ready", false),
+            toast(None, "shown", true),
+        ]
+    );
+
+    assert_eq!(
+        term.replies("\x1b]99;i=q:p=?;\x1b\\"),
+        "\x1b]99;i=q:p=?;o=always,unfocused:p=title,body,?\x1b\\"
+    );
+}
+
+#[test]
+fn test_iterm_proprietary() {
+    let mut term = TestTerm::new(2, 80, 10);
+
+    term.advance_bytes("1\r\n2\r\n3\r\n4");
+    assert_eq!(term.screen().scrollback_rows(), 4);
+    term.advance_bytes("\x1b]1337;ClearScrollback\x07");
+    assert_eq!(term.screen().scrollback_rows(), 2);
+
+    // printf '\e]1337;SetUserVar=foo=YmFy\a\e]1337;ReportVariable=%s\a' $(printf user.foo | base64)
+    term.advance_bytes("\x1b]1337;SetUserVar=foo=YmFy\x07");
+    assert_eq!(
+        term.replies(
+            "\x1b]1337;ReportVariable=dXNlci5mb28=\x07\x1b]1337;ReportVariable=dXNlci5iYXo=\x07"
+        ),
+        "\x1b]1337;ReportVariable=YmFy\x1b\\\x1b]1337;ReportVariable=\x1b\\"
+    );
+}
+
+#[test]
+fn test_sixel_background_and_aspect_ratio() {
+    let mut term = TestTerm::new(4, 10, 0);
+
+    let mut decode = |sixel: &str| {
+        term.advance_bytes(format!("\x1b[H{}\x1b\\", sixel));
+        let images = term
+            .screen_mut()
+            .get_cell(0, 0)
+            .unwrap()
+            .attrs()
+            .images()
+            .unwrap();
+        let data = images[0].image_data().data();
+        match &*data {
+            termwiz::image::ImageDataType::Rgba8 {
+                data,
+                width,
+                height,
+                ..
+            } => (*width, *height, data.clone()),
+            other => panic!("unexpected {:?}", other),
+        }
+    };
+
+    let red = [255, 0, 0, 255];
+    let blue = [0, 0, 255, 255];
+
+    // The background is register 0 as redefined by the image
+    let (width, height, data) = decode("\x1bPq#0;2;100;0;0#1;2;0;0;100#1@");
+    assert_eq!((width, height), (1, 6));
+    assert_eq!(data[0..4], blue);
+    assert_eq!(data[4..8], red);
+
+    // P2=1 leaves the pixels that weren't drawn transparent
+    let (_, _, data) = decode("\x1bP0;1q#0;2;100;0;0#1;2;0;0;100#1@");
+    assert_eq!(data[0..4], blue);
+    assert_eq!(data[4..8], [0; 4]);
+
+    // A 2:1 aspect ratio from the raster attributes doubles the height
+    let (width, height, data) = decode("\x1bP0;1q\"2;1;1;1#1;2;0;0;100#1@");
+    assert_eq!((width, height), (1, 2));
+    assert_eq!(data[0..4], blue);
+    assert_eq!(data[4..8], blue);
+}
+
+#[test]
+fn test_sixel_progressive() {
+    // Cells are 8x16 pixels
+    let mut term = TestTerm::new(4, 10, 0);
+    let image_size = |term: &mut TestTerm, y: usize| {
+        let cell = term.screen_mut().get_cell(0, y as i64).cloned()?;
+        cell.attrs().images().map(|images| {
+            let data = images[0].image_data().data();
+            match &*data {
+                termwiz::image::ImageDataType::Rgba8 { width, height, .. } => (*width, *height),
+                other => panic!("unexpected {:?}", other),
+            }
+        })
+    };
+
+    // Each row of cells is shown once its pixels are decoded
+    term.advance_bytes("\x1bPq\"1;1;2;20#1;2;100;0;0#1~~-~~");
+    assert_eq!(image_size(&mut term, 0), None);
+    term.advance_bytes("-~~-");
+    assert_eq!(image_size(&mut term, 0), Some((2, 16)));
+    assert_eq!(image_size(&mut term, 1), None);
+    assert_eq!(term.cursor_pos().y, 0);
+
+    // The rest of the image is shown once it ends
+    term.advance_bytes("\x1b\\");
+    assert_eq!(image_size(&mut term, 1), Some((2, 4)));
+    assert_eq!(term.cursor_pos().y, 1);
+}
+
+#[test]
+fn test_device_attributes() {
+    #[derive(Debug)]
+    struct FeaturesConfig;
+    impl TerminalConfiguration for FeaturesConfig {
+        fn color_palette(&self) -> ColorPalette {
+            ColorPalette::default()
+        }
+
+        fn terminal_features(&self) -> TerminalFeatures {
+            TerminalFeatures::wezterm()
+                .with_clipboard(true)
+                .with_unit_id(0x1234)
+        }
+    }
+
+    let mut term = TestTerm::with_config(TerminalSize::default(), Arc::new(FeaturesConfig));
+
+    assert_eq!(
+        term.replies("\x1b[c\x1b[>c\x1b[=c\x1bP$q\"p\x1b\\"),
+        "\x1b[?65;4;6;18;22;52c\x1b[>1;277;0c\x1bP!|00001234\x1b\\\x1bP1$r65;1\"p\x1b\\"
+    );
+}
@@ -0,0 +1,102 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use termwiz::hyperlink::{Rule, RuleSet};
+use termwiz::surface::Line;
+
+/// The rules that wezterm uses by default, followed by a rule per
+/// project for linking issue references, which is the sort of thing
+/// that users tend to accumulate in their configs
+fn rules(num_projects: usize) -> Vec<Rule> {
+    let mut rules = vec![
+        Rule::with_highlight(r"\((\w+://\S+)\)", "$1", 1).unwrap(),
+        Rule::with_highlight(r"\[(\w+://\S+)\]", "$1", 1).unwrap(),
+        Rule::with_highlight(r"<(\w+://\S+)>", "$1", 1).unwrap(),
+        Rule::new(r"\b\w+://\S+[)/a-zA-Z0-9-]+", "$0").unwrap(),
+        Rule::new(r"\b\w+@[\w-]+(\.[\w-]+)+\b", "mailto:$0").unwrap(),
+    ];
+    for n in 0..num_projects {
+        rules.push(
+            Rule::with_highlight(
+                &format!(r"\bproject{n}#(\d+)\b"),
+                &format!("https://example.com/project{n}/issues/$1"),
+                1,
+            )
+            .unwrap(),
+        );
+    }
+    rules
+}
+
+/// Produce the scrollback from `cat`ing some source files, most lines
+/// of which contain nothing that looks like a link
+fn scrollback() -> Vec<Line> {
+    let sources = [
+        include_str!("../src/surface/mod.rs"),
+        include_str!("../src/escape/csi.rs"),
+        include_str!("../src/hyperlink.rs"),
+    ];
+    let mut lines = vec![];
+    while lines.len() < 10_000 {
+        for source in &sources {
+            lines.extend(source.lines().map(Line::from));
+        }
+    }
+    lines
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let lines = scrollback();
+    let mut group = c.benchmark_group("hyperlink scrollback");
+    group.throughput(Throughput::Elements(lines.len() as u64));
+
+    for num_projects in [0, 20] {
+        let rules = rules(num_projects);
+        let set = RuleSet::new(&rules);
+        let text: Vec<String> = lines
+            .iter()
+            .map(|line| line.as_str().into_owned())
+            .collect();
+
+        group.bench_function(
+            BenchmarkId::new("Rule::match_hyperlinks", rules.len()),
+            |b| {
+                b.iter(|| {
+                    for line in &text {
+                        black_box(Rule::match_hyperlinks(line, &rules));
+                    }
+                })
+            },
+        );
+        group.bench_function(
+            BenchmarkId::new("RuleSet::match_hyperlinks", rules.len()),
+            |b| {
+                b.iter(|| {
+                    for line in &text {
+                        black_box(set.match_hyperlinks(line));
+                    }
+                })
+            },
+        );
+
+        // Rendering rescans the visible lines each frame; only lines
+        // that have changed since they were last scanned do any work
+        group.bench_function(
+            BenchmarkId::new("rescan unchanged lines", rules.len()),
+            |b| {
+                let mut lines = lines.clone();
+                for line in &mut lines {
+                    line.scan_and_create_hyperlinks(&rules);
+                }
+                b.iter(|| {
+                    for line in &mut lines {
+                        line.scan_and_create_hyperlinks(&rules);
+                    }
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
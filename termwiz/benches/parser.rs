@@ -0,0 +1,146 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use termwiz::escape::parser::Parser;
+use termwiz::escape::{Action, ActionBatch};
+use vtparse::{CsiParam, VTActor, VTParser};
+
+/// The size of the reads made from the pty by the mux
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Produce the output of `cat`ing a large source file to the terminal
+fn cat_output() -> Vec<u8> {
+    let sources = [
+        include_str!("../src/surface/mod.rs"),
+        include_str!("../src/escape/csi.rs"),
+        include_str!("../src/cell.rs"),
+    ];
+    let mut output = String::new();
+    while output.len() < 4 * 1024 * 1024 {
+        for source in &sources {
+            output.push_str(&source.replace('\n', "\r\n"));
+        }
+    }
+    output.into_bytes()
+}
+
+/// Produce the output of `cat`ing a large file of mostly non-ASCII text
+fn cat_utf8_output() -> Vec<u8> {
+    let lines = [
+        "Все счастливые семьи похожи друг на друга, каждая несчастливая семья несчастлива по-своему.",
+        "吾輩は猫である。名前はまだ無い。どこで生れたかとんと見当がつかぬ。",
+        "Ἐν ἀρχῇ ἦν ὁ λόγος, καὶ ὁ λόγος ἦν πρὸς τὸν θεόν 🙂👍",
+    ];
+    let mut output = String::new();
+    while output.len() < 4 * 1024 * 1024 {
+        for line in &lines {
+            output.push_str(line);
+            output.push_str("\r\n");
+        }
+    }
+    output.into_bytes()
+}
+
+/// Counts the bytes of the text that is printed, so that the benchmarks
+/// of `VTParser` measure the parser rather than its actor
+#[derive(Default)]
+struct CountingActor {
+    printed: usize,
+}
+
+impl VTActor for CountingActor {
+    fn print(&mut self, b: char) {
+        self.printed += b.len_utf8();
+    }
+    fn print_str(&mut self, s: &str) {
+        self.printed += s.len();
+    }
+    fn execute_c0_or_c1(&mut self, _control: u8) {}
+    fn dcs_hook(
+        &mut self,
+        _mode: u8,
+        _params: &[i64],
+        _intermediates: &[u8],
+        _ignored_excess_intermediates: bool,
+    ) {
+    }
+    fn dcs_put(&mut self, _byte: u8) {}
+    fn dcs_unhook(&mut self) {}
+    fn esc_dispatch(
+        &mut self,
+        _params: &[i64],
+        _intermediates: &[u8],
+        _ignored_excess_intermediates: bool,
+        _byte: u8,
+    ) {
+    }
+    fn csi_dispatch(&mut self, _params: &[CsiParam], _parameters_truncated: bool, _byte: u8) {}
+    fn osc_dispatch(&mut self, _params: &[&[u8]]) {}
+    fn apc_dispatch(&mut self, _data: Vec<u8>) {}
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let output = cat_output();
+    let mut group = c.benchmark_group("parse cat");
+    group.throughput(Throughput::Bytes(output.len() as u64));
+
+    group.bench_function(BenchmarkId::new("append_to", "Vec<Action>"), |b| {
+        let mut parser = Parser::new();
+        b.iter(|| {
+            for chunk in output.chunks(CHUNK_SIZE) {
+                let mut actions: Vec<Action> = vec![];
+                parser.parse(chunk, |action| action.append_to(&mut actions));
+                black_box(&actions);
+            }
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("parse_into", "ActionBatch"), |b| {
+        let mut parser = Parser::new();
+        let mut batch = ActionBatch::new();
+        b.iter(|| {
+            for chunk in output.chunks(CHUNK_SIZE) {
+                parser.parse_into(chunk, &mut batch);
+                for action in batch.drain() {
+                    black_box(action);
+                }
+            }
+        })
+    });
+
+    group.finish();
+
+    // Compares the fast path for runs of text in `VTParser::parse`
+    // with feeding the state machine a byte at a time
+    for (name, output) in [("ascii", &output), ("utf8", &cat_utf8_output())] {
+        let mut group = c.benchmark_group(format!("vtparse cat {}", name));
+        group.throughput(Throughput::Bytes(output.len() as u64));
+
+        group.bench_function("parse", |b| {
+            let mut parser = VTParser::new();
+            b.iter(|| {
+                let mut actor = CountingActor::default();
+                for chunk in output.chunks(CHUNK_SIZE) {
+                    parser.parse(chunk, &mut actor);
+                }
+                black_box(actor.printed);
+            })
+        });
+
+        group.bench_function("parse_byte", |b| {
+            let mut parser = VTParser::new();
+            b.iter(|| {
+                let mut actor = CountingActor::default();
+                for chunk in output.chunks(CHUNK_SIZE) {
+                    for &byte in chunk {
+                        parser.parse_byte(byte, &mut actor);
+                    }
+                }
+                black_box(actor.printed);
+            })
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
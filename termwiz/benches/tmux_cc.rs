@@ -0,0 +1,119 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use termwiz::tmux_cc::{unvis_bytes, vis_bytes, Parser, WindowLayout};
+
+/// The size of the reads made from the pty by the mux
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The output of `tmux -CC`, captured from tmux 3.3a, while running
+/// `cat` and `ls --color` in split panes, zooming a pane, and running
+/// `capture-pane` and `list-windows`
+const SESSION: &[u8] = include_bytes!("fixtures/tmux-3.3a-session.txt");
+
+/// Produce the text of a large source file, as the terminal sees it
+fn cat_output() -> Vec<u8> {
+    let sources = [
+        include_str!("../src/surface/mod.rs"),
+        include_str!("../src/escape/csi.rs"),
+        include_str!("../src/cell.rs"),
+    ];
+    let mut output = String::new();
+    while output.len() < 4 * 1024 * 1024 {
+        for source in &sources {
+            output.push_str(&source.replace('\n', "\r\n"));
+        }
+    }
+    output.into_bytes()
+}
+
+/// Produce the `%output` notifications for `cat`ing the source,
+/// which tmux sends in pieces of no more than a few KB
+fn output_notifications() -> Vec<u8> {
+    let mut stream = String::new();
+    for piece in cat_output().chunks(4096) {
+        stream.push_str("%output %1 ");
+        stream.push_str(&vis_bytes(piece));
+        stream.push('\n');
+    }
+    stream.into_bytes()
+}
+
+/// Produce the reply to a `capture-pane` of the source
+fn guarded_reply() -> Vec<u8> {
+    let mut stream = String::from("%begin 1578920019 258 1\n");
+    stream.push_str(&String::from_utf8(cat_output()).unwrap().replace('\r', ""));
+    stream.push_str("%end 1578920019 258 1\n");
+    stream.into_bytes()
+}
+
+fn parse(group_name: &str, c: &mut Criterion, stream: &[u8]) {
+    let mut group = c.benchmark_group(group_name);
+    group.throughput(Throughput::Bytes(stream.len() as u64));
+    group.bench_function(BenchmarkId::new("advance_bytes", "Vec<Event>"), |b| {
+        let mut parser = Parser::new();
+        b.iter(|| {
+            for chunk in stream.chunks(CHUNK_SIZE) {
+                black_box(parser.advance_bytes(chunk).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Returns the fields that follow `prefix` in the lines of `SESSION`
+/// that start with it
+fn session_fields(prefix: &str) -> impl Iterator<Item = &str> {
+    std::str::from_utf8(SESSION)
+        .unwrap()
+        .lines()
+        .filter_map(move |line| line.strip_prefix(prefix))
+        .flat_map(|rest| rest.split(' '))
+}
+
+fn layouts(c: &mut Criterion) {
+    let layouts: Vec<&str> = session_fields("%layout-change ")
+        .filter(|field| field.contains(','))
+        .collect();
+    let mut group = c.benchmark_group("tmux layout");
+    group.throughput(Throughput::Elements(layouts.len() as u64));
+    group.bench_function("WindowLayout::parse", |b| {
+        b.iter(|| {
+            for layout in &layouts {
+                black_box(WindowLayout::parse(layout).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn unvis(c: &mut Criterion) {
+    let encoded: Vec<&[u8]> = std::str::from_utf8(SESSION)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.strip_prefix("%output "))
+        .filter_map(|rest| rest.split_once(' '))
+        .map(|(_pane, text)| text.as_bytes())
+        .collect();
+    let mut group = c.benchmark_group("tmux unvis");
+    group.throughput(Throughput::Bytes(
+        encoded.iter().map(|text| text.len() as u64).sum(),
+    ));
+    group.bench_function("unvis_bytes", |b| {
+        b.iter(|| {
+            for text in &encoded {
+                black_box(unvis_bytes(text).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    parse("tmux %output", c, &output_notifications());
+    parse("tmux guarded reply", c, &guarded_reply());
+    parse("tmux captured session", c, SESSION);
+    layouts(c);
+    unvis(c);
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
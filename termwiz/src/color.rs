@@ -296,6 +296,19 @@ impl From<ColorSpec> for ColorAttribute {
     }
 }
 
+/// Drops the fallback of a true color, which is only meaningful
+/// when rendering to a terminal that doesn't support true color
+impl From<ColorAttribute> for ColorSpec {
+    fn from(attr: ColorAttribute) -> Self {
+        match attr {
+            ColorAttribute::Default => ColorSpec::Default,
+            ColorAttribute::PaletteIndex(idx) => ColorSpec::PaletteIndex(idx),
+            ColorAttribute::TrueColorWithPaletteFallback(color, _)
+            | ColorAttribute::TrueColorWithDefaultFallback(color) => ColorSpec::TrueColor(color),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -149,7 +149,7 @@ impl KittyImageData {
                 keys.insert("t", "f".to_string());
                 keys.insert("payload", base64_encode(&path));
                 set(keys, "S", data_size);
-                set(keys, "S", data_offset);
+                set(keys, "O", data_offset);
             }
             Self::TemporaryFile {
                 path,
@@ -159,7 +159,7 @@ impl KittyImageData {
                 keys.insert("t", "t".to_string());
                 keys.insert("payload", base64_encode(&path));
                 set(keys, "S", data_size);
-                set(keys, "S", data_offset);
+                set(keys, "O", data_offset);
             }
             Self::SharedMem {
                 name,
@@ -169,7 +169,7 @@ impl KittyImageData {
                 keys.insert("t", "s".to_string());
                 keys.insert("payload", base64_encode(&name));
                 set(keys, "S", data_size);
-                set(keys, "S", data_offset);
+                set(keys, "O", data_offset);
             }
         }
     }
@@ -611,6 +611,22 @@ pub struct KittyImagePlacement {
     pub placement_id: Option<u32>,
     /// z=...
     pub z_index: Option<i32>,
+    /// Create a virtual placement, which isn't displayed by itself,
+    /// but through cells holding `KITTY_PLACEHOLDER`, whose colors
+    /// and diacritics identify the image and the part of it to show.
+    /// U=0, U=1
+    pub virtual_placement: bool,
+    /// Place the image relative to a placement of another image.
+    /// P=...
+    pub parent_image_id: Option<u32>,
+    /// The placement of the parent image.
+    /// Q=...
+    pub parent_placement_id: Option<u32>,
+    /// The offset in cells from the parent placement.
+    /// H=...
+    pub parent_x_offset: Option<i32>,
+    /// V=...
+    pub parent_y_offset: Option<i32>,
 }
 
 impl KittyImagePlacement {
@@ -631,6 +647,15 @@ impl KittyImagePlacement {
                 _ => return None,
             },
             z_index: geti(keys, "z"),
+            virtual_placement: match get(keys, "U") {
+                None | Some("0") => false,
+                Some("1") => true,
+                _ => return None,
+            },
+            parent_image_id: geti(keys, "P"),
+            parent_placement_id: geti(keys, "Q"),
+            parent_x_offset: geti(keys, "H"),
+            parent_y_offset: geti(keys, "V"),
         })
     }
 
@@ -650,9 +675,23 @@ impl KittyImagePlacement {
         }
 
         set(keys, "z", &self.z_index);
+
+        if self.virtual_placement {
+            keys.insert("U", "1".to_string());
+        }
+        set(keys, "P", &self.parent_image_id);
+        set(keys, "Q", &self.parent_placement_id);
+        set(keys, "H", &self.parent_x_offset);
+        set(keys, "V", &self.parent_y_offset);
     }
 }
 
+/// The character that is written to the cells in which a virtual
+/// placement is displayed.  The foreground color of the cell holds
+/// the image id, the underline color the placement id, and the
+/// combining diacritics the row and column of the image to show.
+pub const KITTY_PLACEHOLDER: char = '\u{10EEEE}';
+
 /// When the uppercase form is used, the delete: field is set to true
 /// which means that the underlying data is also released.  Otherwise,
 /// the data is available to be placed again.
@@ -704,6 +743,11 @@ pub enum KittyImageDelete {
         delete: bool,
     },
 
+    /// d='r' or d='R'
+    /// Delete all images whose image_id is in the range
+    /// first..=last
+    ByImageIdRange { first: u32, last: u32, delete: bool },
+
     /// d='x' or d='X'
     /// Delete all placements that intersect the specified column.
     DeleteColumn { x: u32, delete: bool },
@@ -750,6 +794,11 @@ impl KittyImageDelete {
                 z: geti(keys, "z")?,
                 delete,
             }),
+            'r' | 'R' => Some(Self::ByImageIdRange {
+                first: geti(keys, "x")?,
+                last: geti(keys, "y")?,
+                delete,
+            }),
             'x' | 'X' => Some(Self::DeleteColumn {
                 x: geti(keys, "x")?,
                 delete,
@@ -809,11 +858,20 @@ impl KittyImageDelete {
                 keys.insert("y", y.to_string());
             }
             Self::DeleteAtZ { x, y, z, delete } => {
-                keys.insert("d", d('p', delete));
+                keys.insert("d", d('q', delete));
                 keys.insert("x", x.to_string());
                 keys.insert("y", y.to_string());
                 keys.insert("z", z.to_string());
             }
+            Self::ByImageIdRange {
+                first,
+                last,
+                delete,
+            } => {
+                keys.insert("d", d('r', delete));
+                keys.insert("x", first.to_string());
+                keys.insert("y", last.to_string());
+            }
             Self::DeleteColumn { x, delete } => {
                 keys.insert("d", d('x', delete));
                 keys.insert("x", x.to_string());
@@ -978,7 +1036,8 @@ impl KittyImageFrame {
                 None | Some(0) => None,
                 n => n,
             },
-            duration_ms: match geti(keys, "Z") {
+            // Older versions of termwiz used Z= for the gap
+            duration_ms: match geti(keys, "z").or_else(|| geti(keys, "Z")) {
                 None | Some(0) => None,
                 n => n,
             },
@@ -996,7 +1055,7 @@ impl KittyImageFrame {
         set(keys, "y", &self.y);
         set(keys, "c", &self.base_frame);
         set(keys, "r", &self.frame_number);
-        set(keys, "Z", &self.duration_ms);
+        set(keys, "z", &self.duration_ms);
         match &self.composition_mode {
             KittyFrameCompositionMode::AlphaBlending => {}
             KittyFrameCompositionMode::Overwrite => {
@@ -1007,6 +1066,96 @@ impl KittyImageFrame {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyAnimationState {
+    /// s=1
+    Stopped,
+    /// Run the animation, but wait for more frames
+    /// when the last frame is reached.
+    /// s=2
+    Loading,
+    /// s=3
+    Running,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KittyImageAnimation {
+    /// i=...
+    pub image_id: Option<u32>,
+    /// I=...
+    pub image_number: Option<u32>,
+
+    /// s=...
+    pub state: Option<KittyAnimationState>,
+
+    /// 1-based number of the frame whose gap should be changed.
+    /// r=...
+    pub frame_number: Option<u32>,
+
+    /// The new gap in milliseconds for frame_number.
+    /// z=...
+    pub duration_ms: Option<u32>,
+
+    /// 1-based number of the frame that should be made current.
+    /// c=...
+    pub current_frame: Option<u32>,
+
+    /// The number of loops to play.
+    /// 0 is ignored, 1 loops forever, otherwise loop n-1 times.
+    /// v=...
+    pub loops: Option<u32>,
+}
+
+impl KittyImageAnimation {
+    fn from_keys(keys: &BTreeMap<&str, &str>) -> Option<Self> {
+        Some(Self {
+            image_id: geti(keys, "i"),
+            image_number: geti(keys, "I"),
+            state: match geti(keys, "s") {
+                None | Some(0) => None,
+                Some(1) => Some(KittyAnimationState::Stopped),
+                Some(2) => Some(KittyAnimationState::Loading),
+                Some(3) => Some(KittyAnimationState::Running),
+                _ => return None,
+            },
+            frame_number: match geti(keys, "r") {
+                None | Some(0) => None,
+                n => n,
+            },
+            duration_ms: geti(keys, "z"),
+            current_frame: match geti(keys, "c") {
+                None | Some(0) => None,
+                n => n,
+            },
+            loops: match geti(keys, "v") {
+                None | Some(0) => None,
+                n => n,
+            },
+        })
+    }
+
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) {
+        set(keys, "i", &self.image_id);
+        set(keys, "I", &self.image_number);
+        match &self.state {
+            None => {}
+            Some(KittyAnimationState::Stopped) => {
+                keys.insert("s", "1".to_string());
+            }
+            Some(KittyAnimationState::Loading) => {
+                keys.insert("s", "2".to_string());
+            }
+            Some(KittyAnimationState::Running) => {
+                keys.insert("s", "3".to_string());
+            }
+        }
+        set(keys, "r", &self.frame_number);
+        set(keys, "z", &self.duration_ms);
+        set(keys, "c", &self.current_frame);
+        set(keys, "v", &self.loops);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum KittyImage {
     /// a='t'
@@ -1045,6 +1194,11 @@ pub enum KittyImage {
         frame: KittyImageFrameCompose,
         verbosity: KittyImageVerbosity,
     },
+    /// a='a'
+    AnimationControl {
+        animation: KittyImageAnimation,
+        verbosity: KittyImageVerbosity,
+    },
 }
 
 impl KittyImage {
@@ -1057,6 +1211,7 @@ impl KittyImage {
             Self::Delete { verbosity, .. } => *verbosity,
             Self::TransmitFrame { verbosity, .. } => *verbosity,
             Self::ComposeFrame { verbosity, .. } => *verbosity,
+            Self::AnimationControl { verbosity, .. } => *verbosity,
         }
     }
 
@@ -1110,6 +1265,10 @@ impl KittyImage {
                 frame: KittyImageFrameCompose::from_keys(&keys)?,
                 verbosity,
             }),
+            "a" => Some(Self::AnimationControl {
+                animation: KittyImageAnimation::from_keys(&keys)?,
+                verbosity,
+            }),
             _ => None,
         }
     }
@@ -1133,7 +1292,7 @@ impl KittyImage {
                 verbosity,
                 placement,
             } => {
-                keys.insert("a", "Q".to_string());
+                keys.insert("a", "T".to_string());
                 verbosity.to_keys(keys);
                 placement.to_keys(keys);
                 transmit.to_keys(keys);
@@ -1174,6 +1333,14 @@ impl KittyImage {
                 frame.to_keys(keys);
                 verbosity.to_keys(keys);
             }
+            Self::AnimationControl {
+                animation,
+                verbosity,
+            } => {
+                keys.insert("a", "a".to_string());
+                animation.to_keys(keys);
+                verbosity.to_keys(keys);
+            }
         }
     }
 }
@@ -1268,4 +1435,181 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn kitty_round_trip() {
+        let frame = KittyImage::TransmitFrame {
+            transmit: KittyImageTransmit {
+                format: Some(KittyImageFormat::Rgba),
+                data: KittyImageData::Direct("AAAA".to_string()),
+                width: Some(1),
+                height: Some(1),
+                image_id: Some(42),
+                image_number: None,
+                compression: KittyImageCompression::None,
+                more_data_follows: false,
+            },
+            frame: KittyImageFrame {
+                x: None,
+                y: None,
+                base_frame: None,
+                frame_number: None,
+                duration_ms: Some(100),
+                composition_mode: KittyFrameCompositionMode::Overwrite,
+                background_pixel: None,
+            },
+            verbosity: KittyImageVerbosity::Quiet,
+        };
+        let encoded = frame.to_string();
+        assert_eq!(encoded, "\x1b_GX=1,a=f,f=32,i=42,q=2,s=1,v=1,z=100;AAAA");
+        assert_eq!(
+            KittyImage::parse_apc(&encoded.as_bytes()[2..]).unwrap(),
+            frame
+        );
+
+        let display = KittyImage::TransmitDataAndDisplay {
+            transmit: KittyImageTransmit {
+                format: Some(KittyImageFormat::Rgba),
+                data: KittyImageData::Direct("AAAA".to_string()),
+                width: Some(1),
+                height: Some(1),
+                image_id: Some(42),
+                image_number: None,
+                compression: KittyImageCompression::None,
+                more_data_follows: false,
+            },
+            placement: KittyImagePlacement {
+                x: None,
+                y: None,
+                w: None,
+                h: None,
+                x_offset: None,
+                y_offset: None,
+                columns: Some(4),
+                rows: Some(2),
+                do_not_move_cursor: false,
+                placement_id: None,
+                z_index: Some(-1),
+                virtual_placement: false,
+                parent_image_id: None,
+                parent_placement_id: None,
+                parent_x_offset: None,
+                parent_y_offset: None,
+            },
+            verbosity: KittyImageVerbosity::Quiet,
+        };
+        let encoded = display.to_string();
+        assert_eq!(encoded, "\x1b_Ga=T,c=4,f=32,i=42,q=2,r=2,s=1,v=1,z=-1;AAAA");
+        assert_eq!(
+            KittyImage::parse_apc(&encoded.as_bytes()[2..]).unwrap(),
+            display
+        );
+
+        let animation = KittyImage::AnimationControl {
+            animation: KittyImageAnimation {
+                image_id: Some(42),
+                image_number: None,
+                state: Some(KittyAnimationState::Running),
+                frame_number: None,
+                duration_ms: None,
+                current_frame: None,
+                loops: Some(1),
+            },
+            verbosity: KittyImageVerbosity::Quiet,
+        };
+        let encoded = animation.to_string();
+        assert_eq!(encoded, "\x1b_Ga=a,i=42,q=2,s=3,v=1");
+        assert_eq!(
+            KittyImage::parse_apc(&encoded.as_bytes()[2..]).unwrap(),
+            animation
+        );
+    }
+
+    #[test]
+    fn kitty_placements_and_deletes() {
+        let virtual_placement = KittyImage::Display {
+            image_id: Some(42),
+            image_number: None,
+            placement: KittyImagePlacement {
+                x: None,
+                y: None,
+                w: None,
+                h: None,
+                x_offset: None,
+                y_offset: None,
+                columns: Some(10),
+                rows: Some(3),
+                do_not_move_cursor: false,
+                placement_id: Some(7),
+                z_index: None,
+                virtual_placement: true,
+                parent_image_id: Some(41),
+                parent_placement_id: Some(1),
+                parent_x_offset: Some(-2),
+                parent_y_offset: Some(1),
+            },
+            verbosity: KittyImageVerbosity::Quiet,
+        };
+        let encoded = virtual_placement.to_string();
+        assert_eq!(
+            encoded,
+            "\x1b_GH=-2,P=41,Q=1,U=1,V=1,a=p,c=10,i=42,p=7,q=2,r=3"
+        );
+        assert_eq!(
+            KittyImage::parse_apc(&encoded.as_bytes()[2..]).unwrap(),
+            virtual_placement
+        );
+        assert_eq!(KittyImage::parse_apc(b"Ga=p,i=1,U=2"), None);
+
+        for what in [
+            KittyImageDelete::DeleteAtZ {
+                x: 1,
+                y: 2,
+                z: -3,
+                delete: false,
+            },
+            KittyImageDelete::ByImageIdRange {
+                first: 10,
+                last: 20,
+                delete: true,
+            },
+        ]
+        .iter()
+        {
+            let delete = KittyImage::Delete {
+                what: what.clone(),
+                verbosity: KittyImageVerbosity::Verbose,
+            };
+            let encoded = delete.to_string();
+            assert_eq!(
+                KittyImage::parse_apc(&encoded.as_bytes()[2..]).unwrap(),
+                delete,
+                "{}",
+                encoded
+            );
+        }
+
+        let shm = KittyImage::TransmitData {
+            transmit: KittyImageTransmit {
+                format: Some(KittyImageFormat::Rgba),
+                data: KittyImageData::SharedMem {
+                    name: "/kitty-shm".to_string(),
+                    data_size: Some(16),
+                    data_offset: Some(4),
+                },
+                width: Some(2),
+                height: Some(2),
+                image_id: Some(1),
+                image_number: None,
+                compression: KittyImageCompression::None,
+                more_data_follows: false,
+            },
+            verbosity: KittyImageVerbosity::Verbose,
+        };
+        let encoded = shm.to_string();
+        assert_eq!(
+            KittyImage::parse_apc(&encoded.as_bytes()[2..]).unwrap(),
+            shm
+        );
+    }
 }
@@ -0,0 +1,176 @@
+//! A batch of parsed actions whose printable text is held in a single
+//! buffer that is shared by all of the actions in the batch.
+//! Collecting actions into a `Vec<Action>` allocates a `String` for
+//! each run of printable text; an `ActionBatch` reuses its buffer from
+//! one batch to the next, so that the steady state of parsing output
+//! doesn't need to allocate for text at all.
+use crate::escape::Action;
+use std::ops::Range;
+
+/// An action that was taken from an `ActionBatch`
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchedAction<'a> {
+    /// A run of printable text, borrowed from the batch
+    Print(&'a str),
+    /// Any other action
+    Action(Action),
+}
+
+impl<'a> BatchedAction<'a> {
+    /// Convert into an owned `Action`
+    pub fn into_action(self) -> Action {
+        match self {
+            Self::Print(text) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Action::Print(c),
+                    _ => Action::PrintString(text.to_string()),
+                }
+            }
+            Self::Action(action) => action,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Item {
+    Print(Range<usize>),
+    Action(Action),
+}
+
+/// Accumulates actions, combining consecutive printable characters
+/// into runs of text in a buffer that is shared by the whole batch.
+/// Use `Parser::parse_into` to parse directly into a batch.
+#[derive(Debug, Default)]
+pub struct ActionBatch {
+    text: String,
+    items: Vec<Item>,
+}
+
+impl ActionBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of actions in the batch
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the number of bytes of printable text in the batch
+    pub fn text_len(&self) -> usize {
+        self.text.len()
+    }
+
+    /// Discard the contents of the batch, retaining its allocations
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.text.clear();
+    }
+
+    /// Append an action to the batch
+    pub fn push(&mut self, action: Action) {
+        match action {
+            Action::Print(c) => self.push_char(c),
+            Action::PrintString(s) => self.push_str(&s),
+            action => {
+                self.reset_text_if_drained();
+                self.items.push(Item::Action(action));
+            }
+        }
+    }
+
+    /// Append a printable character to the batch
+    pub fn push_char(&mut self, c: char) {
+        self.reset_text_if_drained();
+        let start = self.text.len();
+        self.text.push(c);
+        self.extend_print(start);
+    }
+
+    /// Append a run of printable text to the batch
+    pub fn push_str(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        self.reset_text_if_drained();
+        let start = self.text.len();
+        self.text.push_str(s);
+        self.extend_print(start);
+    }
+
+    fn extend_print(&mut self, start: usize) {
+        let end = self.text.len();
+        match self.items.last_mut() {
+            Some(Item::Print(range)) if range.end == start => range.end = end,
+            _ => self.items.push(Item::Print(start..end)),
+        }
+    }
+
+    /// The text is retained by `drain` so that the iterator can borrow
+    /// from it; it is released here once all of the actions are gone
+    fn reset_text_if_drained(&mut self) {
+        if self.items.is_empty() {
+            self.text.clear();
+        }
+    }
+
+    /// Remove all of the actions from the batch, in the order that
+    /// they were added.  Any actions that are not consumed by the
+    /// caller are discarded when the iterator is dropped.
+    pub fn drain(&mut self) -> impl Iterator<Item = BatchedAction<'_>> {
+        let text = &self.text;
+        self.items.drain(..).map(move |item| match item {
+            Item::Print(range) => BatchedAction::Print(&text[range]),
+            Item::Action(action) => BatchedAction::Action(action),
+        })
+    }
+
+    /// Remove all of the actions from the batch, converting them into
+    /// owned `Action`s
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        let actions = self.drain().map(BatchedAction::into_action).collect();
+        self.text.clear();
+        actions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::escape::ControlCode;
+
+    #[test]
+    fn combines_printable_text() {
+        let mut batch = ActionBatch::new();
+        batch.push(Action::Print('h'));
+        batch.push(Action::PrintString("ell".to_string()));
+        batch.push(Action::Print('o'));
+        batch.push(Action::Control(ControlCode::LineFeed));
+        batch.push(Action::Print('!'));
+        assert_eq!(batch.len(), 3);
+
+        let actions: Vec<_> = batch.drain().collect();
+        assert_eq!(
+            actions,
+            vec![
+                BatchedAction::Print("hello"),
+                BatchedAction::Action(Action::Control(ControlCode::LineFeed)),
+                BatchedAction::Print("!"),
+            ]
+        );
+        assert!(batch.is_empty());
+
+        // The buffer is reused for the next batch
+        batch.push_str("again");
+        assert_eq!(batch.text_len(), 5);
+        assert_eq!(
+            batch.take_actions(),
+            vec![Action::PrintString("again".to_string())]
+        );
+    }
+}
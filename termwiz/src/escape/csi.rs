@@ -1,7 +1,8 @@
-use super::OneBased;
+use super::{Action, OneBased, OperatingSystemCommand};
 use crate::cell::{Blink, Intensity, Underline, VerticalAlign};
 use crate::color::{AnsiColor, ColorSpec, RgbColor, SrgbaTuple};
 use crate::input::{Modifiers, MouseButtons};
+use bitflags::bitflags;
 use num_derive::*;
 use num_traits::{FromPrimitive, ToPrimitive};
 use std::convert::TryInto;
@@ -28,8 +29,13 @@ pub enum CSI {
 
     Window(Box<Window>),
 
+    RectangularArea(Box<RectangularArea>),
+
     Keyboard(Keyboard),
 
+    /// A key event reported using the kitty keyboard protocol
+    KeyReport(Box<KittyKeyReport>),
+
     /// ECMA-48 SCP
     SelectCharacterPath(CharacterPath, i64),
 
@@ -77,6 +83,226 @@ pub enum Keyboard {
     ReportKittyState(KittyKeyboardFlags),
 }
 
+bitflags! {
+    /// The modifiers of a kitty keyboard protocol key event.
+    /// These are sent as one more than their bits.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct KittyKeyModifiers: u8 {
+        const NONE = 0;
+        const SHIFT = 1;
+        const ALT = 2;
+        const CTRL = 4;
+        const SUPER = 8;
+        const HYPER = 16;
+        const META = 32;
+        const CAPS_LOCK = 64;
+        const NUM_LOCK = 128;
+    }
+}
+
+impl KittyKeyModifiers {
+    /// Converts from the modifiers used by the rest of termwiz.
+    /// The positional modifiers are treated as their unpositioned
+    /// equivalents.
+    pub fn from_modifiers(mods: Modifiers) -> Self {
+        let mut result = Self::NONE;
+        if mods.intersects(Modifiers::SHIFT | Modifiers::LEFT_SHIFT | Modifiers::RIGHT_SHIFT) {
+            result |= Self::SHIFT;
+        }
+        if mods.intersects(Modifiers::ALT | Modifiers::LEFT_ALT | Modifiers::RIGHT_ALT) {
+            result |= Self::ALT;
+        }
+        if mods.intersects(Modifiers::CTRL | Modifiers::LEFT_CTRL | Modifiers::RIGHT_CTRL) {
+            result |= Self::CTRL;
+        }
+        if mods.contains(Modifiers::SUPER) {
+            result |= Self::SUPER;
+        }
+        result
+    }
+
+    /// Converts to the modifiers used by the rest of termwiz.
+    /// HYPER has no equivalent, META is treated as ALT, in the same
+    /// way as by the keymap, and the lock states are dropped.
+    pub fn to_modifiers(self) -> Modifiers {
+        let mut mods = Modifiers::NONE;
+        if self.contains(Self::SHIFT) {
+            mods |= Modifiers::SHIFT;
+        }
+        if self.intersects(Self::ALT | Self::META) {
+            mods |= Modifiers::ALT;
+        }
+        if self.contains(Self::CTRL) {
+            mods |= Modifiers::CTRL;
+        }
+        if self.contains(Self::SUPER) {
+            mods |= Modifiers::SUPER;
+        }
+        mods
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KittyKeyEventType {
+    Press = 1,
+    Repeat = 2,
+    Release = 3,
+}
+
+/// A key event reported using the kitty keyboard protocol, such as
+/// `CSI 97;5u` for CTRL-a, or `CSI 1;2:3A` for the release of
+/// SHIFT-UpArrow.
+/// <https://sw.kovidgoyal.net/kitty/keyboard-protocol/>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KittyKeyReport {
+    /// The unicode codepoint of the key without any modifiers applied,
+    /// or the number of a functional key.  This is 1 for the keys
+    /// whose final character identifies them, such as the arrows.
+    pub key_code: u32,
+    /// The codepoint produced by the key when SHIFT is held
+    pub shifted_key: Option<u32>,
+    /// The codepoint of the key in the standard PC-101 layout
+    pub base_layout_key: Option<u32>,
+    pub modifiers: KittyKeyModifiers,
+    pub event_type: KittyKeyEventType,
+    /// The text generated by the key event, if it was requested
+    pub text: String,
+    /// The final character of the sequence; `u` for most keys
+    pub control: char,
+}
+
+impl KittyKeyReport {
+    /// Returns true if `control` is a final character that may end
+    /// a key event.  `R` is excluded as it would be ambiguous with a
+    /// cursor position report, which is why F3 is sent as `13~`.
+    pub fn is_key_control(control: char) -> bool {
+        matches!(
+            control,
+            'u' | '~' | 'A' | 'B' | 'C' | 'D' | 'E' | 'F' | 'H' | 'P' | 'Q' | 'S'
+        )
+    }
+
+    /// Parses the parameters of a key event, which are fields separated
+    /// by `;`, each of which has subfields separated by `:`
+    pub fn parse(params: &[CsiParam], control: char) -> Option<Self> {
+        if !Self::is_key_control(control) {
+            return None;
+        }
+
+        let mut fields: Vec<Vec<Option<u32>>> = vec![vec![None]];
+        for p in params {
+            match p {
+                CsiParam::P(b';') => fields.push(vec![None]),
+                CsiParam::P(b':') => fields.last_mut()?.push(None),
+                CsiParam::Integer(n) => {
+                    let value = fields.last_mut().and_then(|field| field.last_mut())?;
+                    if value.is_some() {
+                        return None;
+                    }
+                    *value = Some((*n).try_into().ok()?);
+                }
+                _ => return None,
+            }
+        }
+
+        if fields.len() > 3 || fields[0].len() > 3 || fields.get(1).map_or(0, Vec::len) > 2 {
+            return None;
+        }
+        let get = |field: usize, sub: usize| {
+            fields
+                .get(field)
+                .and_then(|f| f.get(sub).copied().flatten())
+        };
+
+        let key_code = match get(0, 0) {
+            Some(code) => code,
+            None if control == 'u' || control == '~' => return None,
+            None => 1,
+        };
+
+        let modifiers = match get(1, 0).unwrap_or(1) {
+            m @ 1..=256 => KittyKeyModifiers::from_bits_truncate((m - 1) as u8),
+            _ => return None,
+        };
+
+        let event_type = match get(1, 1) {
+            None | Some(1) => KittyKeyEventType::Press,
+            Some(2) => KittyKeyEventType::Repeat,
+            Some(3) => KittyKeyEventType::Release,
+            _ => return None,
+        };
+
+        let mut text = String::new();
+        if let Some(codepoints) = fields.get(2) {
+            for c in codepoints {
+                text.push(c.and_then(char::from_u32)?);
+            }
+        }
+
+        Some(Self {
+            key_code,
+            shifted_key: get(0, 1),
+            base_layout_key: get(0, 2),
+            modifiers,
+            event_type,
+            text,
+            control,
+        })
+    }
+}
+
+impl Display for KittyKeyReport {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let has_modifiers =
+            !self.modifiers.is_empty() || self.event_type != KittyKeyEventType::Press;
+        let has_text = !self.text.is_empty();
+        let has_alternates = self.shifted_key.is_some() || self.base_layout_key.is_some();
+
+        // The arrows and similar keys are sent without a key code
+        // when there is nothing else to report
+        let omit_key_code = self.key_code == 1
+            && self.control.is_ascii_uppercase()
+            && !has_alternates
+            && !has_modifiers
+            && !has_text;
+        if !omit_key_code {
+            write!(f, "{}", self.key_code)?;
+            if has_alternates {
+                write!(f, ":")?;
+                if let Some(shifted) = self.shifted_key {
+                    write!(f, "{}", shifted)?;
+                }
+                if let Some(base) = self.base_layout_key {
+                    write!(f, ":{}", base)?;
+                }
+            }
+        }
+
+        if has_modifiers || has_text {
+            write!(f, ";")?;
+            if has_modifiers {
+                write!(f, "{}", 1 + self.modifiers.bits() as u16)?;
+                if self.event_type != KittyKeyEventType::Press {
+                    write!(f, ":{}", self.event_type as u8)?;
+                }
+            }
+        }
+
+        if has_text {
+            write!(f, ";")?;
+            for (idx, c) in self.text.chars().enumerate() {
+                if idx > 0 {
+                    write!(f, ":")?;
+                }
+                write!(f, "{}", c as u32)?;
+            }
+        }
+
+        write!(f, "{}", self.control)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CharacterPath {
     /// 0
@@ -123,6 +349,7 @@ impl Display for CSI {
             CSI::Mouse(mouse) => mouse.fmt(f)?,
             CSI::Device(dev) => dev.fmt(f)?,
             CSI::Window(window) => window.fmt(f)?,
+            CSI::RectangularArea(area) => area.fmt(f)?,
             CSI::Keyboard(Keyboard::SetKittyState { flags, mode }) => {
                 write!(f, "={};{}u", flags.bits(), *mode as u16)?
             }
@@ -132,6 +359,7 @@ impl Display for CSI {
             CSI::Keyboard(Keyboard::PopKittyState(n)) => write!(f, "<{}u", *n)?,
             CSI::Keyboard(Keyboard::QueryKittySupport) => write!(f, "?u")?,
             CSI::Keyboard(Keyboard::ReportKittyState(flags)) => write!(f, "?{}u", flags.bits())?,
+            CSI::KeyReport(report) => report.fmt(f)?,
             CSI::SelectCharacterPath(path, n) => {
                 let a = match path {
                     CharacterPath::ImplementationDefault => 0,
@@ -180,6 +408,8 @@ pub enum DeviceAttributeCodes {
     HorizontalScrolling = 21,
     AnsiColor = 22,
     AnsiTextLocator = 29,
+    /// Access to the clipboard with OSC 52, as reported by xterm
+    Clipboard = 52,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -234,6 +464,17 @@ pub enum DeviceAttributes {
     Vt220(DeviceAttributeFlags),
     Vt320(DeviceAttributeFlags),
     Vt420(DeviceAttributeFlags),
+    Vt500(DeviceAttributeFlags),
+}
+
+/// The reply to a request for the secondary device attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecondaryDeviceAttributes {
+    /// The model of the terminal; 1 is a VT220
+    pub terminal_type: i64,
+    pub firmware_version: i64,
+    /// The ROM cartridge registration number, which is always 0
+    pub rom_cartridge: i64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -349,6 +590,7 @@ impl XtSmGraphics {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Device {
     DeviceAttributes(DeviceAttributes),
+    SecondaryDeviceAttributes(SecondaryDeviceAttributes),
     /// DECSTR - https://vt100.net/docs/vt510-rm/DECSTR.html
     SoftReset,
     RequestPrimaryDeviceAttributes,
@@ -373,6 +615,12 @@ impl Display for Device {
             Device::DeviceAttributes(DeviceAttributes::Vt220(attr)) => attr.emit(f, "?62")?,
             Device::DeviceAttributes(DeviceAttributes::Vt320(attr)) => attr.emit(f, "?63")?,
             Device::DeviceAttributes(DeviceAttributes::Vt420(attr)) => attr.emit(f, "?64")?,
+            Device::DeviceAttributes(DeviceAttributes::Vt500(attr)) => attr.emit(f, "?65")?,
+            Device::SecondaryDeviceAttributes(attr) => write!(
+                f,
+                ">{};{};{}c",
+                attr.terminal_type, attr.firmware_version, attr.rom_cartridge
+            )?,
             Device::SoftReset => write!(f, "!p")?,
             Device::RequestPrimaryDeviceAttributes => write!(f, "c")?,
             Device::RequestSecondaryDeviceAttributes => write!(f, ">c")?,
@@ -461,6 +709,10 @@ pub enum Window {
     ReportTextAreaSizePixels,
     ReportWindowSizePixels,
     ReportScreenSizePixels,
+    ReportScreenSizePixelsResponse {
+        width: Option<i64>,
+        height: Option<i64>,
+    },
     ReportCellSizePixels,
     ReportCellSizePixelsResponse {
         width: Option<i64>,
@@ -468,6 +720,10 @@ pub enum Window {
     },
     ReportTextAreaSizeCells,
     ReportScreenSizeCells,
+    ReportScreenSizeCellsResponse {
+        width: Option<i64>,
+        height: Option<i64>,
+    },
     ReportIconLabel,
     ReportWindowTitle,
     PushIconAndWindowTitle,
@@ -476,6 +732,9 @@ pub enum Window {
     PopIconAndWindowTitle,
     PopIconTitle,
     PopWindowTitle,
+    /// DECSLPP; `CSI Ps t` with `Ps` of 24 or more resizes
+    /// the window to that many lines
+    SetLinesPerPage(i64),
     /// DECRQCRA; used by esctest
     ChecksumRectangularArea {
         request_id: i64,
@@ -528,6 +787,12 @@ impl Display for Window {
             Window::ReportTextAreaSizePixels => write!(f, "14t"),
             Window::ReportWindowSizePixels => write!(f, "14;2t"),
             Window::ReportScreenSizePixels => write!(f, "15t"),
+            Window::ReportScreenSizePixelsResponse { width, height } => write!(
+                f,
+                "5;{};{}t",
+                numstr_or_empty(height),
+                numstr_or_empty(width),
+            ),
             Window::ReportCellSizePixels => write!(f, "16t"),
             Window::ReportCellSizePixelsResponse { width, height } => write!(
                 f,
@@ -537,6 +802,12 @@ impl Display for Window {
             ),
             Window::ReportTextAreaSizeCells => write!(f, "18t"),
             Window::ReportScreenSizeCells => write!(f, "19t"),
+            Window::ReportScreenSizeCellsResponse { width, height } => write!(
+                f,
+                "9;{};{}t",
+                numstr_or_empty(height),
+                numstr_or_empty(width),
+            ),
             Window::ReportIconLabel => write!(f, "20t"),
             Window::ReportWindowTitle => write!(f, "21t"),
             Window::PushIconAndWindowTitle => write!(f, "22;0t"),
@@ -545,6 +816,7 @@ impl Display for Window {
             Window::PopIconAndWindowTitle => write!(f, "23;0t"),
             Window::PopIconTitle => write!(f, "23;1t"),
             Window::PopWindowTitle => write!(f, "23;2t"),
+            Window::SetLinesPerPage(lines) => write!(f, "{}t", lines),
             Window::ChecksumRectangularArea {
                 request_id,
                 page_number,
@@ -561,6 +833,193 @@ impl Display for Window {
     }
 }
 
+impl Window {
+    /// Returns true for the operations that change the window,
+    /// rather than report on it or save and restore its title
+    pub fn manipulates_window(&self) -> bool {
+        matches!(
+            self,
+            Window::DeIconify
+                | Window::Iconify
+                | Window::MoveWindow { .. }
+                | Window::ResizeWindowPixels { .. }
+                | Window::RaiseWindow
+                | Window::LowerWindow
+                | Window::RefreshWindow
+                | Window::ResizeWindowCells { .. }
+                | Window::RestoreMaximizedWindow
+                | Window::MaximizeWindow
+                | Window::MaximizeWindowVertically
+                | Window::MaximizeWindowHorizontally
+                | Window::UndoFullScreenMode
+                | Window::ChangeToFullScreenMode
+                | Window::ToggleFullScreen
+                | Window::SetLinesPerPage(_)
+        )
+    }
+
+    /// If this is one of the XTWINOPS reports, returns the
+    /// response to it, built from `state`
+    pub fn report(&self, state: &WindowState) -> Option<Action> {
+        let window = |window| Some(Action::CSI(CSI::Window(Box::new(window))));
+        let size = |(width, height): (i64, i64)| (Some(width), Some(height));
+        match self {
+            Window::ReportWindowState if state.iconified => window(Window::Iconify),
+            Window::ReportWindowState => window(Window::DeIconify),
+            Window::ReportWindowPosition => window(Window::MoveWindow {
+                x: state.window_position.0,
+                y: state.window_position.1,
+            }),
+            Window::ReportTextAreaPosition => window(Window::MoveWindow {
+                x: state.text_area_position.0,
+                y: state.text_area_position.1,
+            }),
+            Window::ReportTextAreaSizePixels => {
+                let (width, height) = size(state.text_area_size);
+                window(Window::ResizeWindowPixels { width, height })
+            }
+            Window::ReportWindowSizePixels => {
+                let (width, height) = size(state.window_size);
+                window(Window::ResizeWindowPixels { width, height })
+            }
+            Window::ReportScreenSizePixels => {
+                let (width, height) = size(state.screen_size);
+                window(Window::ReportScreenSizePixelsResponse { width, height })
+            }
+            Window::ReportCellSizePixels => {
+                let (width, height) = size(state.cell_size);
+                window(Window::ReportCellSizePixelsResponse { width, height })
+            }
+            Window::ReportTextAreaSizeCells => {
+                let (width, height) = size(state.text_area_cells);
+                window(Window::ResizeWindowCells { width, height })
+            }
+            Window::ReportScreenSizeCells => {
+                let (width, height) = size(state.screen_cells);
+                window(Window::ReportScreenSizeCellsResponse { width, height })
+            }
+            Window::ReportIconLabel => Some(Action::OperatingSystemCommand(Box::new(
+                OperatingSystemCommand::SetIconNameSun(state.icon_label.clone()),
+            ))),
+            Window::ReportWindowTitle => Some(Action::OperatingSystemCommand(Box::new(
+                OperatingSystemCommand::SetWindowTitleSun(state.title.clone()),
+            ))),
+            _ => None,
+        }
+    }
+}
+
+/// What the terminal knows about its window, from which
+/// `Window::report` builds the responses to the XTWINOPS reports.
+/// Positions are `(x, y)` and sizes are `(width, height)`, both
+/// measured in pixels unless noted otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowState {
+    pub iconified: bool,
+    pub window_position: (i64, i64),
+    /// The position of the text area relative to the window
+    pub text_area_position: (i64, i64),
+    pub window_size: (i64, i64),
+    pub text_area_size: (i64, i64),
+    pub screen_size: (i64, i64),
+    pub cell_size: (i64, i64),
+    /// The size of the text area, in cells
+    pub text_area_cells: (i64, i64),
+    /// The size of the screen, in cells
+    pub screen_cells: (i64, i64),
+    pub icon_label: String,
+    pub title: String,
+}
+
+/// The area of the page that is affected by one of the DEC
+/// rectangular area operations.  The corners are inclusive, and are
+/// relative to the origin, so they are affected by DECOM.  `bottom`
+/// and `right` default to the last line and column of the page,
+/// which is represented as `u32::MAX`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rectangle {
+    pub top: OneBased,
+    pub left: OneBased,
+    pub bottom: OneBased,
+    pub right: OneBased,
+}
+
+impl Rectangle {
+    /// Parses the four parameters that start at `idx`
+    fn parse(params: &Cracked, idx: usize) -> Result<Self, ()> {
+        let big = |idx| match params.get(idx) {
+            Some(p) => OneBased::from_esc_param_with_big_default(p),
+            None => Ok(OneBased::new(u32::MAX)),
+        };
+        Ok(Self {
+            top: OneBased::from_optional_esc_param(params.get(idx))?,
+            left: OneBased::from_optional_esc_param(params.get(idx + 1))?,
+            bottom: big(idx + 2)?,
+            right: big(idx + 3)?,
+        })
+    }
+}
+
+impl Display for Rectangle {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{};{};", self.top, self.left)?;
+        if self.bottom.as_one_based() != u32::MAX {
+            write!(f, "{}", self.bottom)?;
+        }
+        write!(f, ";")?;
+        if self.right.as_one_based() != u32::MAX {
+            write!(f, "{}", self.right)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RectangularArea {
+    /// DECCRA - Copy Rectangular Area.
+    /// Copies `area` of `source_page`, placing its top left corner
+    /// at `top`, `left` of `destination_page`.
+    Copy {
+        area: Rectangle,
+        source_page: OneBased,
+        top: OneBased,
+        left: OneBased,
+        destination_page: OneBased,
+    },
+    /// DECFRA - Fill Rectangular Area.
+    /// Fills `area` with `character`, using the current graphic rendition.
+    Fill { character: char, area: Rectangle },
+    /// DECERA - Erase Rectangular Area
+    Erase(Rectangle),
+    /// DECSERA - Selective Erase Rectangular Area.
+    /// Erases the characters in the area, leaving their attributes
+    /// unchanged.
+    SelectiveErase(Rectangle),
+}
+
+impl Display for RectangularArea {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            RectangularArea::Copy {
+                area,
+                source_page,
+                top,
+                left,
+                destination_page,
+            } => write!(
+                f,
+                "{};{};{};{};{}$v",
+                area, source_page, top, left, destination_page
+            ),
+            RectangularArea::Fill { character, area } => {
+                write!(f, "{};{}$x", *character as u32, area)
+            }
+            RectangularArea::Erase(area) => write!(f, "{}$z", area),
+            RectangularArea::SelectiveErase(area) => write!(f, "{}${{", area),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MouseReport {
     SGR1006 {
@@ -688,6 +1147,15 @@ impl XtermKeyModifierResource {
             _ => return None,
         })
     }
+
+    pub fn to_i64(&self) -> i64 {
+        match self {
+            XtermKeyModifierResource::Keyboard => 0,
+            XtermKeyModifierResource::CursorKeys => 1,
+            XtermKeyModifierResource::FunctionKeys => 2,
+            XtermKeyModifierResource::OtherKeys => 4,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -696,14 +1164,27 @@ pub enum Mode {
     ResetDecPrivateMode(DecPrivateMode),
     SaveDecPrivateMode(DecPrivateMode),
     RestoreDecPrivateMode(DecPrivateMode),
+    /// DECRQM; `CSI ? Ps $ p`
     QueryDecPrivateMode(DecPrivateMode),
+    /// DECRPM; `CSI ? Ps ; Pm $ y`, the response to `QueryDecPrivateMode`
+    ReportDecPrivateMode(DecPrivateMode, ModeSetting),
     SetMode(TerminalMode),
     ResetMode(TerminalMode),
+    /// DECRQM; `CSI Ps $ p`
     QueryMode(TerminalMode),
+    /// DECRPM; `CSI Ps ; Pm $ y`, the response to `QueryMode`
+    ReportMode(TerminalMode, ModeSetting),
+    /// XTMODKEYS; `CSI > Pp ; Pv m`.  A `value` of `None`
+    /// resets the resource to its initial value.
     XtermKeyMode {
         resource: XtermKeyModifierResource,
         value: Option<i64>,
     },
+    /// Disables the key modifier options of a resource; `CSI > Pp n`
+    DisableXtermKeyMode(XtermKeyModifierResource),
+    /// XTQMODKEYS; `CSI ? Pp m`.  The terminal responds with
+    /// the XTMODKEYS sequence that sets the current value.
+    QueryXtermKeyMode(XtermKeyModifierResource),
 }
 
 impl Display for Mode {
@@ -731,29 +1212,18 @@ impl Display for Mode {
             Mode::ResetDecPrivateMode(mode) => emit!("l", mode),
             Mode::SaveDecPrivateMode(mode) => emit!("s", mode),
             Mode::RestoreDecPrivateMode(mode) => emit!("r", mode),
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(mode)) => {
-                write!(f, "?{}$p", mode.to_u16().ok_or_else(|| FmtError)?)
-            }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Unspecified(mode)) => {
-                write!(f, "?{}$p", mode)
+            Mode::QueryDecPrivateMode(mode) => emit!("$p", mode),
+            Mode::ReportDecPrivateMode(mode, setting) => {
+                emit!(format!(";{}$y", *setting as u8), mode)
             }
             Mode::SetMode(mode) => emit_mode!("h", mode),
             Mode::ResetMode(mode) => emit_mode!("l", mode),
-            Mode::QueryMode(TerminalMode::Code(mode)) => {
-                write!(f, "?{}$p", mode.to_u16().ok_or_else(|| FmtError)?)
+            Mode::QueryMode(mode) => emit_mode!("$p", mode),
+            Mode::ReportMode(mode, setting) => {
+                emit_mode!(format!(";{}$y", *setting as u8), mode)
             }
-            Mode::QueryMode(TerminalMode::Unspecified(mode)) => write!(f, "?{}$p", mode),
             Mode::XtermKeyMode { resource, value } => {
-                write!(
-                    f,
-                    ">{}",
-                    match resource {
-                        XtermKeyModifierResource::Keyboard => 0,
-                        XtermKeyModifierResource::CursorKeys => 1,
-                        XtermKeyModifierResource::FunctionKeys => 2,
-                        XtermKeyModifierResource::OtherKeys => 4,
-                    }
-                )?;
+                write!(f, ">{}", resource.to_i64())?;
                 if let Some(value) = value {
                     write!(f, ";{}", value)?;
                 } else {
@@ -761,6 +1231,28 @@ impl Display for Mode {
                 }
                 write!(f, "m")
             }
+            Mode::DisableXtermKeyMode(resource) => write!(f, ">{}n", resource.to_i64()),
+            Mode::QueryXtermKeyMode(resource) => write!(f, "?{}m", resource.to_i64()),
+        }
+    }
+}
+
+/// The state of a mode, as reported by DECRPM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum ModeSetting {
+    NotRecognized = 0,
+    Set = 1,
+    Reset = 2,
+    PermanentlySet = 3,
+    PermanentlyReset = 4,
+}
+
+impl From<bool> for ModeSetting {
+    fn from(enabled: bool) -> Self {
+        if enabled {
+            ModeSetting::Set
+        } else {
+            ModeSetting::Reset
         }
     }
 }
@@ -1730,6 +2222,12 @@ impl<'a> CSIParser<'a> {
             ('k', [.., CsiParam::P(b' ')]) => self.select_character_path(params),
             ('q', [.., CsiParam::P(b' ')]) => self.cursor_style(params),
             ('y', [.., CsiParam::P(b'*')]) => self.checksum_area(params),
+            ('v', [.., CsiParam::P(b'$')])
+            | ('x', [.., CsiParam::P(b'$')])
+            | ('z', [.., CsiParam::P(b'$')])
+            | ('{', [.., CsiParam::P(b'$')]) => self
+                .rectangular_area(params)
+                .map(|area| CSI::RectangularArea(Box::new(area))),
 
             ('c', [CsiParam::P(b'='), ..]) => self
                 .req_tertiary_device_attributes(params)
@@ -1743,7 +2241,7 @@ impl<'a> CSIParser<'a> {
             }
 
             ('c', [CsiParam::P(b'?'), ..]) => self
-                .secondary_device_attributes(params)
+                .primary_device_attributes(params)
                 .map(|dev| CSI::Device(Box::new(dev))),
 
             ('S', [CsiParam::P(b'?'), ..]) => XtSmGraphics::parse(params),
@@ -1751,6 +2249,7 @@ impl<'a> CSIParser<'a> {
             | ('p', [CsiParam::P(b'?'), CsiParam::Integer(_), CsiParam::P(b'$')]) => {
                 self.decrqm(params)
             }
+            ('y', [.., CsiParam::P(b'$')]) => self.decrpm(params),
             ('h', [CsiParam::P(b'?'), ..]) => self
                 .dec(self.focus(params, 1, 0))
                 .map(|mode| CSI::Mode(Mode::SetDecPrivateMode(mode))),
@@ -1767,6 +2266,12 @@ impl<'a> CSIParser<'a> {
                 .dec(self.focus(params, 1, 0))
                 .map(|mode| CSI::Mode(Mode::SaveDecPrivateMode(mode))),
             ('m', [CsiParam::P(b'>'), ..]) => self.xterm_key_modifier(params),
+            ('n', [CsiParam::P(b'>'), CsiParam::Integer(_)]) => self
+                .xterm_key_modifier_resource(params)
+                .map(|resource| CSI::Mode(Mode::DisableXtermKeyMode(resource))),
+            ('m', [CsiParam::P(b'?'), CsiParam::Integer(_)]) => self
+                .xterm_key_modifier_resource(params)
+                .map(|resource| CSI::Mode(Mode::QueryXtermKeyMode(resource))),
 
             ('p', [CsiParam::P(b'!')]) => Ok(CSI::Device(Box::new(Device::SoftReset))),
             ('u', [CsiParam::P(b'='), CsiParam::Integer(flags)]) => {
@@ -1823,6 +2328,9 @@ impl<'a> CSIParser<'a> {
                 Keyboard::PopKittyState((*how_many).try_into().map_err(|_| ())?),
             )),
             ('u', [CsiParam::P(b'<')]) => Ok(CSI::Keyboard(Keyboard::PopKittyState(1))),
+            ('u', [CsiParam::Integer(_), ..]) => KittyKeyReport::parse(params, 'u')
+                .map(|report| CSI::KeyReport(Box::new(report)))
+                .ok_or(()),
 
             _ => match self.control {
                 'c' => self
@@ -1967,6 +2475,48 @@ impl<'a> CSIParser<'a> {
         })))
     }
 
+    fn rectangular_area(&mut self, params: &'a [CsiParam]) -> Result<RectangularArea, ()> {
+        let params = Cracked::parse(&params[..params.len() - 1])?;
+        match self.control {
+            'v' => {
+                if params.len() > 8 {
+                    return Err(());
+                }
+                Ok(RectangularArea::Copy {
+                    area: Rectangle::parse(&params, 0)?,
+                    source_page: OneBased::from_optional_esc_param(params.get(4))?,
+                    top: OneBased::from_optional_esc_param(params.get(5))?,
+                    left: OneBased::from_optional_esc_param(params.get(6))?,
+                    destination_page: OneBased::from_optional_esc_param(params.get(7))?,
+                })
+            }
+            'x' => {
+                if params.len() > 5 {
+                    return Err(());
+                }
+                let character = std::char::from_u32(params.int(0)?.try_into().map_err(|_| ())?)
+                    .filter(|c| !c.is_control())
+                    .ok_or(())?;
+                Ok(RectangularArea::Fill {
+                    character,
+                    area: Rectangle::parse(&params, 1)?,
+                })
+            }
+            'z' | '{' => {
+                if params.len() > 4 {
+                    return Err(());
+                }
+                let area = Rectangle::parse(&params, 0)?;
+                Ok(if self.control == 'z' {
+                    RectangularArea::Erase(area)
+                } else {
+                    RectangularArea::SelectiveErase(area)
+                })
+            }
+            _ => Err(()),
+        }
+    }
+
     fn dsr(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
         match params {
             [CsiParam::Integer(5)] => {
@@ -2056,6 +2606,20 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    /// Parses the `Pp` of `CSI > Pp n` and `CSI ? Pp m`
+    fn xterm_key_modifier_resource(
+        &mut self,
+        params: &'a [CsiParam],
+    ) -> Result<XtermKeyModifierResource, ()> {
+        match params {
+            [_, CsiParam::Integer(p)] => {
+                let resource = XtermKeyModifierResource::parse(*p).ok_or(())?;
+                Ok(self.advance_by(2, params, resource))
+            }
+            _ => Err(()),
+        }
+    }
+
     fn decslrm(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
         match params {
             [] => {
@@ -2121,6 +2685,17 @@ impl<'a> CSIParser<'a> {
             [CsiParam::P(b'>'), CsiParam::Integer(0)] => {
                 Ok(self.advance_by(2, params, Device::RequestSecondaryDeviceAttributes))
             }
+            [CsiParam::P(b'>'), CsiParam::Integer(terminal_type), CsiParam::P(b';'), CsiParam::Integer(firmware_version), CsiParam::P(b';'), CsiParam::Integer(rom_cartridge)] => {
+                Ok(self.advance_by(
+                    6,
+                    params,
+                    Device::SecondaryDeviceAttributes(SecondaryDeviceAttributes {
+                        terminal_type: *terminal_type,
+                        firmware_version: *firmware_version,
+                        rom_cartridge: *rom_cartridge,
+                    }),
+                ))
+            }
             _ => Err(()),
         }
     }
@@ -2135,7 +2710,7 @@ impl<'a> CSIParser<'a> {
         }
     }
 
-    fn secondary_device_attributes(&mut self, params: &'a [CsiParam]) -> Result<Device, ()> {
+    fn primary_device_attributes(&mut self, params: &'a [CsiParam]) -> Result<Device, ()> {
         match params {
             [_, CsiParam::Integer(1), CsiParam::P(b';'), CsiParam::Integer(0)] => Ok(self
                 .advance_by(
@@ -2173,6 +2748,13 @@ impl<'a> CSIParser<'a> {
                     DeviceAttributeFlags::from_params(&params[2..]),
                 )),
             )),
+            [_, CsiParam::Integer(65), ..] => Ok(self.advance_by(
+                params.len(),
+                params,
+                Device::DeviceAttributes(DeviceAttributes::Vt500(
+                    DeviceAttributeFlags::from_params(&params[2..]),
+                )),
+            )),
             _ => Err(()),
         }
     }
@@ -2268,6 +2850,31 @@ impl<'a> CSIParser<'a> {
         }))
     }
 
+    fn decrpm(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        let setting = |p: &i64| FromPrimitive::from_i64(*p).ok_or(());
+        Ok(CSI::Mode(match params {
+            [CsiParam::Integer(p), CsiParam::P(b';'), CsiParam::Integer(s), CsiParam::P(b'$')] => {
+                Mode::ReportMode(
+                    match FromPrimitive::from_i64(*p) {
+                        None => TerminalMode::Unspecified(p.to_u16().ok_or(())?),
+                        Some(mode) => TerminalMode::Code(mode),
+                    },
+                    setting(s)?,
+                )
+            }
+            [CsiParam::P(b'?'), CsiParam::Integer(p), CsiParam::P(b';'), CsiParam::Integer(s), CsiParam::P(b'$')] => {
+                Mode::ReportDecPrivateMode(
+                    match FromPrimitive::from_i64(*p) {
+                        None => DecPrivateMode::Unspecified(p.to_u16().ok_or(())?),
+                        Some(mode) => DecPrivateMode::Code(mode),
+                    },
+                    setting(s)?,
+                )
+            }
+            _ => return Err(()),
+        }))
+    }
+
     fn dec(&mut self, params: &'a [CsiParam]) -> Result<DecPrivateMode, ()> {
         match params {
             [CsiParam::Integer(p0), ..] => match FromPrimitive::from_i64(*p0) {
@@ -2364,7 +2971,13 @@ impl<'a> CSIParser<'a> {
                 height: arg1,
                 width: arg2,
             }),
-            5 => Ok(Window::RaiseWindow),
+            5 => match params.len() {
+                1 => Ok(Window::RaiseWindow),
+                _ => Ok(Window::ReportScreenSizePixelsResponse {
+                    height: arg1,
+                    width: arg2,
+                }),
+            },
             6 => match params.len() {
                 1 => Ok(Window::LowerWindow),
                 _ => Ok(Window::ReportCellSizePixelsResponse {
@@ -2377,6 +2990,10 @@ impl<'a> CSIParser<'a> {
                 height: arg1,
                 width: arg2,
             }),
+            9 if params.len() > 2 => Ok(Window::ReportScreenSizeCellsResponse {
+                height: arg1,
+                width: arg2,
+            }),
             9 => match arg1 {
                 Some(0) => Ok(Window::RestoreMaximizedWindow),
                 Some(1) => Ok(Window::MaximizeWindow),
@@ -2408,17 +3025,18 @@ impl<'a> CSIParser<'a> {
             20 => Ok(Window::ReportIconLabel),
             21 => Ok(Window::ReportWindowTitle),
             22 => match arg1 {
-                Some(0) => Ok(Window::PushIconAndWindowTitle),
+                None | Some(0) => Ok(Window::PushIconAndWindowTitle),
                 Some(1) => Ok(Window::PushIconTitle),
                 Some(2) => Ok(Window::PushWindowTitle),
                 _ => Err(()),
             },
             23 => match arg1 {
-                Some(0) => Ok(Window::PopIconAndWindowTitle),
+                None | Some(0) => Ok(Window::PopIconAndWindowTitle),
                 Some(1) => Ok(Window::PopIconTitle),
                 Some(2) => Ok(Window::PopWindowTitle),
                 _ => Err(()),
             },
+            p if p >= 24 => Ok(Window::SetLinesPerPage(p)),
             _ => Err(()),
         }
     }
@@ -2987,6 +3605,86 @@ mod test {
                 }
             ))]
         );
+        assert_eq!(
+            parse('t', &[5, 1080, 1920], "\x1b[5;1080;1920t"),
+            vec![CSI::Window(Box::new(
+                Window::ReportScreenSizePixelsResponse {
+                    width: Some(1920),
+                    height: Some(1080)
+                }
+            ))]
+        );
+        assert_eq!(
+            parse('t', &[9, 1], "\x1b[9;1t"),
+            vec![CSI::Window(Box::new(Window::MaximizeWindow))]
+        );
+        assert_eq!(
+            parse('t', &[9, 2, 80], "\x1b[9;2;80t"),
+            vec![CSI::Window(Box::new(
+                Window::ReportScreenSizeCellsResponse {
+                    width: Some(80),
+                    height: Some(2)
+                }
+            ))]
+        );
+        assert_eq!(
+            parse('t', &[22], "\x1b[22;0t"),
+            vec![CSI::Window(Box::new(Window::PushIconAndWindowTitle))]
+        );
+        assert_eq!(
+            parse('t', &[23, 2], "\x1b[23;2t"),
+            vec![CSI::Window(Box::new(Window::PopWindowTitle))]
+        );
+        assert_eq!(
+            parse('t', &[24], "\x1b[24t"),
+            vec![CSI::Window(Box::new(Window::SetLinesPerPage(24)))]
+        );
+    }
+
+    #[test]
+    fn window_report() {
+        let state = WindowState {
+            window_position: (10, 20),
+            text_area_size: (800, 600),
+            cell_size: (8, 16),
+            text_area_cells: (100, 37),
+            screen_cells: (240, 67),
+            title: "hello".to_string(),
+            ..WindowState::default()
+        };
+        let report = |window: Window| window.report(&state).map(|action| action.to_string());
+
+        assert_eq!(
+            report(Window::ReportWindowState),
+            Some("\x1b[1t".to_string())
+        );
+        assert_eq!(
+            report(Window::ReportWindowPosition),
+            Some("\x1b[3;10;20t".to_string())
+        );
+        assert_eq!(
+            report(Window::ReportTextAreaSizePixels),
+            Some("\x1b[4;600;800t".to_string())
+        );
+        assert_eq!(
+            report(Window::ReportCellSizePixels),
+            Some("\x1b[6;16;8t".to_string())
+        );
+        assert_eq!(
+            report(Window::ReportTextAreaSizeCells),
+            Some("\x1b[8;37;100t".to_string())
+        );
+        assert_eq!(
+            report(Window::ReportScreenSizeCells),
+            Some("\x1b[9;67;240t".to_string())
+        );
+        assert_eq!(
+            report(Window::ReportWindowTitle),
+            Some("\x1b]lhello\x1b\\".to_string())
+        );
+        assert_eq!(report(Window::Iconify), None);
+        assert!(Window::Iconify.manipulates_window());
+        assert!(!Window::ReportWindowTitle.manipulates_window());
     }
 
     #[test]
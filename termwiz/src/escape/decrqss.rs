@@ -0,0 +1,259 @@
+//! DECRQSS, with which an application requests the value of one of
+//! the settings of the terminal, and DECRPSS, the reply to it.
+//!
+//! The request, `DCS $ q Pt ST`, identifies the setting by `Pt`, the
+//! intermediate and final bytes of the control function that changes
+//! it.  The reply, `DCS 1 $ r Pt ST`, holds that control function
+//! with the current value of the setting as its parameters.  Note that
+//! the text of <https://vt100.net/docs/vt510-rm/DECRPSS.html> has the
+//! validity value inverted; there's a note about this in the xterm
+//! ctlseqs docs.
+use super::csi::{CursorStyle, Sgr};
+use super::ShortDeviceControl;
+use crate::cell::{Blink, CellAttributes, Intensity, Underline, VerticalAlign};
+use crate::color::ColorSpec;
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// A setting whose value can be requested with DECRQSS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusString {
+    /// SGR - the graphic rendition
+    GraphicRendition,
+    /// DECSCUSR - the cursor style
+    CursorStyle,
+    /// DECSTBM - the top and bottom margins
+    TopAndBottomMargins,
+    /// DECSLRM - the left and right margins
+    LeftAndRightMargins,
+    /// DECSCL - the conformance level
+    ConformanceLevel,
+}
+
+impl StatusString {
+    /// Parses the `Pt` of a DECRQSS request
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        match data {
+            b"m" => Some(Self::GraphicRendition),
+            b" q" => Some(Self::CursorStyle),
+            b"r" => Some(Self::TopAndBottomMargins),
+            b"s" => Some(Self::LeftAndRightMargins),
+            b"\"p" => Some(Self::ConformanceLevel),
+            _ => None,
+        }
+    }
+
+    /// Returns the intermediate and final bytes of the control
+    /// function that changes the setting
+    pub fn control(self) -> &'static str {
+        match self {
+            Self::GraphicRendition => "m",
+            Self::CursorStyle => " q",
+            Self::TopAndBottomMargins => "r",
+            Self::LeftAndRightMargins => "s",
+            Self::ConformanceLevel => "\"p",
+        }
+    }
+}
+
+impl ShortDeviceControl {
+    /// Returns the `Pt` of a DECRQSS request, `DCS $ q Pt ST`
+    pub fn request_status_string(&self) -> Option<&[u8]> {
+        if self.byte == b'q' && self.intermediates == [b'$'] && self.params.is_empty() {
+            Some(&self.data)
+        } else {
+            None
+        }
+    }
+}
+
+/// DECRPSS - the reply to a DECRQSS request
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatusReport {
+    /// The attributes that differ from the defaults
+    GraphicRendition(Vec<Sgr>),
+    CursorStyle(CursorStyle),
+    /// The one-based lines of the margins, inclusive
+    TopAndBottomMargins {
+        top: usize,
+        bottom: usize,
+    },
+    /// The one-based columns of the margins, inclusive
+    LeftAndRightMargins {
+        left: usize,
+        right: usize,
+    },
+    /// `level` is the terminal model, such as 65 for a VT500,
+    /// and `eight_bit_controls` is whether C1 controls are sent
+    /// as single bytes
+    ConformanceLevel {
+        level: u16,
+        eight_bit_controls: bool,
+    },
+    /// The requested setting isn't supported
+    Invalid,
+}
+
+impl StatusReport {
+    /// Returns the report of the graphic rendition of `attrs`
+    pub fn graphic_rendition(attrs: &CellAttributes) -> Self {
+        let mut sgr = vec![];
+        if attrs.intensity() != Intensity::Normal {
+            sgr.push(Sgr::Intensity(attrs.intensity()));
+        }
+        if attrs.underline() != Underline::None {
+            sgr.push(Sgr::Underline(attrs.underline()));
+        }
+        if attrs.blink() != Blink::None {
+            sgr.push(Sgr::Blink(attrs.blink()));
+        }
+        if attrs.italic() {
+            sgr.push(Sgr::Italic(true));
+        }
+        if attrs.reverse() {
+            sgr.push(Sgr::Inverse(true));
+        }
+        if attrs.invisible() {
+            sgr.push(Sgr::Invisible(true));
+        }
+        if attrs.strikethrough() {
+            sgr.push(Sgr::StrikeThrough(true));
+        }
+        if attrs.overline() {
+            sgr.push(Sgr::Overline(true));
+        }
+        if attrs.vertical_align() != VerticalAlign::BaseLine {
+            sgr.push(Sgr::VerticalAlign(attrs.vertical_align()));
+        }
+        let foreground: ColorSpec = attrs.foreground().into();
+        if foreground != ColorSpec::Default {
+            sgr.push(Sgr::Foreground(foreground));
+        }
+        let background: ColorSpec = attrs.background().into();
+        if background != ColorSpec::Default {
+            sgr.push(Sgr::Background(background));
+        }
+        let underline_color: ColorSpec = attrs.underline_color().into();
+        if underline_color != ColorSpec::Default {
+            sgr.push(Sgr::UnderlineColor(underline_color));
+        }
+        Self::GraphicRendition(sgr)
+    }
+
+    fn setting(&self) -> Option<StatusString> {
+        match self {
+            Self::GraphicRendition(_) => Some(StatusString::GraphicRendition),
+            Self::CursorStyle(_) => Some(StatusString::CursorStyle),
+            Self::TopAndBottomMargins { .. } => Some(StatusString::TopAndBottomMargins),
+            Self::LeftAndRightMargins { .. } => Some(StatusString::LeftAndRightMargins),
+            Self::ConformanceLevel { .. } => Some(StatusString::ConformanceLevel),
+            Self::Invalid => None,
+        }
+    }
+}
+
+impl Display for StatusReport {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let setting = match self.setting() {
+            Some(setting) => setting,
+            None => return write!(f, "\x1bP0$r\x1b\\"),
+        };
+        write!(f, "\x1bP1$r")?;
+        match self {
+            Self::GraphicRendition(sgr) => {
+                // The defaults are reported as a reset, so that the
+                // reply can be used to restore the rendition
+                write!(f, "0")?;
+                for sgr in sgr {
+                    let mut code = sgr.to_string();
+                    // Sgr is displayed as it appears in a CSI sequence
+                    code.pop();
+                    write!(f, ";{}", code)?;
+                }
+            }
+            Self::CursorStyle(style) => write!(f, "{}", *style as u8)?,
+            Self::TopAndBottomMargins { top, bottom } => write!(f, "{};{}", top, bottom)?,
+            Self::LeftAndRightMargins { left, right } => write!(f, "{};{}", left, right)?,
+            Self::ConformanceLevel {
+                level,
+                eight_bit_controls,
+            } => write!(f, "{};{}", level, if *eight_bit_controls { 0 } else { 1 })?,
+            Self::Invalid => {}
+        }
+        f.write_str(setting.control())?;
+        write!(f, "\x1b\\")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::AnsiColor;
+    use crate::escape::parser::Parser;
+    use crate::escape::{Action, DeviceControlMode};
+    use k9::assert_equal as assert_eq;
+
+    fn request(s: &str) -> Option<StatusString> {
+        let mut p = Parser::new();
+        match p.parse_as_vec(s.as_bytes()).into_iter().next() {
+            Some(Action::DeviceControl(DeviceControlMode::ShortDeviceControl(dcs))) => {
+                StatusString::parse(dcs.request_status_string()?)
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parse_requests() {
+        assert_eq!(
+            request("\x1bP$qm\x1b\\"),
+            Some(StatusString::GraphicRendition)
+        );
+        assert_eq!(request("\x1bP$q q\x1b\\"), Some(StatusString::CursorStyle));
+        assert_eq!(
+            request("\x1bP$qr\x1b\\"),
+            Some(StatusString::TopAndBottomMargins)
+        );
+        assert_eq!(
+            request("\x1bP$q\"p\x1b\\"),
+            Some(StatusString::ConformanceLevel)
+        );
+        assert_eq!(request("\x1bP$qx\x1b\\"), None);
+        assert_eq!(request("\x1bP=1s\x1b\\"), None);
+    }
+
+    #[test]
+    fn reports() {
+        let mut attrs = CellAttributes::default();
+        assert_eq!(
+            StatusReport::graphic_rendition(&attrs).to_string(),
+            "\x1bP1$r0m\x1b\\"
+        );
+        attrs
+            .set_intensity(Intensity::Bold)
+            .set_underline(Underline::Curly)
+            .set_foreground(AnsiColor::Maroon)
+            .set_background(ColorSpec::PaletteIndex(100));
+        assert_eq!(
+            StatusReport::graphic_rendition(&attrs).to_string(),
+            "\x1bP1$r0;1;4:3;31;48:5:100m\x1b\\"
+        );
+
+        assert_eq!(
+            StatusReport::CursorStyle(CursorStyle::SteadyBar).to_string(),
+            "\x1bP1$r6 q\x1b\\"
+        );
+        assert_eq!(
+            StatusReport::TopAndBottomMargins { top: 1, bottom: 24 }.to_string(),
+            "\x1bP1$r1;24r\x1b\\"
+        );
+        assert_eq!(
+            StatusReport::ConformanceLevel {
+                level: 65,
+                eight_bit_controls: false
+            }
+            .to_string(),
+            "\x1bP1$r65;1\"p\x1b\\"
+        );
+        assert_eq!(StatusReport::Invalid.to_string(), "\x1bP0$r\x1b\\");
+    }
+}
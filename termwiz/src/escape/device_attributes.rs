@@ -0,0 +1,247 @@
+//! Device attributes (DA), with which an application asks the terminal
+//! to identify itself, and the replies to them.
+//!
+//! Primary DA, `CSI c`, is answered with `CSI ? Pl ; Ps... c`, where
+//! `Pl` is the conformance level, such as 65 for a VT500, and each `Ps`
+//! is a feature of the terminal, such as 4 for sixel graphics.
+//! Secondary DA, `CSI > c`, is answered with `CSI > Pp ; Pv ; Pc c`:
+//! the terminal type, the firmware version and the ROM cartridge, which
+//! is always 0.  Tertiary DA, `CSI = c`, is answered with DECRPTUI,
+//! `DCS ! | D...D ST`, which holds the unit ID as eight hex digits.
+//!
+//! The parser decodes the requests into `Device::RequestPrimaryDeviceAttributes`,
+//! `Device::RequestSecondaryDeviceAttributes` and
+//! `Device::RequestTertiaryDeviceAttributes`, and the replies into
+//! `Device::DeviceAttributes`, `Device::SecondaryDeviceAttributes` and
+//! a `ShortDeviceControl` from which `tertiary_device_attributes`
+//! extracts the unit ID.
+use super::csi::{
+    Device, DeviceAttribute, DeviceAttributeCodes, DeviceAttributeFlags, DeviceAttributes,
+    SecondaryDeviceAttributes,
+};
+use super::ShortDeviceControl;
+use num_traits::ToPrimitive;
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// Describes the terminal, and produces the replies to the device
+/// attributes requests from that description
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminalFeatures {
+    /// The conformance level, such as 65 for a VT500
+    pub level: u16,
+    /// The features that are reported in the primary device
+    /// attributes, in ascending order
+    pub attributes: Vec<DeviceAttributeCodes>,
+    /// The model that is reported in the secondary device attributes;
+    /// 1 is a VT220
+    pub terminal_type: i64,
+    pub firmware_version: i64,
+    pub unit_id: u32,
+}
+
+impl TerminalFeatures {
+    /// Describes a terminal of conformance level `level` with no
+    /// optional features
+    pub fn new(level: u16) -> Self {
+        Self {
+            level,
+            attributes: vec![],
+            terminal_type: 1,
+            firmware_version: 0,
+            unit_id: 0,
+        }
+    }
+
+    /// Describes wezterm.
+    /// Because the default TERM is xterm, the firmware version is
+    /// treated as if it were the patch level of xterm, with these
+    /// effects on vim:
+    /// version < 95 -> ttymouse=xterm
+    /// version >= 95 < 277 -> ttymouse=xterm2
+    /// version >= 277 -> ttymouse=sgr
+    /// version >= 279 - xterm will probe for additional device settings.
+    pub fn wezterm() -> Self {
+        Self::new(65)
+            .with_sixel(true)
+            .with_attribute(DeviceAttributeCodes::SelectiveErase, true)
+            .with_attribute(DeviceAttributeCodes::UserWindows, true)
+            .with_ansi_color(true)
+            .with_firmware_version(277)
+    }
+
+    /// Adds or removes `code` from the reported features
+    pub fn with_attribute(mut self, code: DeviceAttributeCodes, enabled: bool) -> Self {
+        self.attributes.retain(|c| *c != code);
+        if enabled {
+            self.attributes.push(code);
+            self.attributes.sort_by_key(|c| c.to_u16());
+        }
+        self
+    }
+
+    pub fn with_sixel(self, enabled: bool) -> Self {
+        self.with_attribute(DeviceAttributeCodes::SixelGraphics, enabled)
+    }
+
+    pub fn with_ansi_color(self, enabled: bool) -> Self {
+        self.with_attribute(DeviceAttributeCodes::AnsiColor, enabled)
+    }
+
+    /// Whether applications can access the clipboard with OSC 52
+    pub fn with_clipboard(self, enabled: bool) -> Self {
+        self.with_attribute(DeviceAttributeCodes::Clipboard, enabled)
+    }
+
+    pub fn with_terminal_type(mut self, terminal_type: i64) -> Self {
+        self.terminal_type = terminal_type;
+        self
+    }
+
+    pub fn with_firmware_version(mut self, firmware_version: i64) -> Self {
+        self.firmware_version = firmware_version;
+        self
+    }
+
+    pub fn with_unit_id(mut self, unit_id: u32) -> Self {
+        self.unit_id = unit_id;
+        self
+    }
+
+    pub fn has_attribute(&self, code: DeviceAttributeCodes) -> bool {
+        self.attributes.contains(&code)
+    }
+
+    /// Returns the reply to a request for the primary device attributes
+    pub fn primary(&self) -> Device {
+        let flags = DeviceAttributeFlags::new(
+            self.attributes
+                .iter()
+                .cloned()
+                .map(DeviceAttribute::Code)
+                .collect(),
+        );
+        Device::DeviceAttributes(match self.level {
+            0..=61 => DeviceAttributes::Vt100WithAdvancedVideoOption,
+            62 => DeviceAttributes::Vt220(flags),
+            63 => DeviceAttributes::Vt320(flags),
+            64 => DeviceAttributes::Vt420(flags),
+            _ => DeviceAttributes::Vt500(flags),
+        })
+    }
+
+    /// Returns the reply to a request for the secondary device attributes
+    pub fn secondary(&self) -> Device {
+        Device::SecondaryDeviceAttributes(SecondaryDeviceAttributes {
+            terminal_type: self.terminal_type,
+            firmware_version: self.firmware_version,
+            rom_cartridge: 0,
+        })
+    }
+
+    /// Returns the reply to a request for the tertiary device attributes
+    pub fn tertiary(&self) -> TertiaryDeviceAttributes {
+        TertiaryDeviceAttributes {
+            unit_id: self.unit_id,
+        }
+    }
+}
+
+impl Default for TerminalFeatures {
+    fn default() -> Self {
+        Self::wezterm()
+    }
+}
+
+/// DECRPTUI - the reply to a request for the tertiary device attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TertiaryDeviceAttributes {
+    pub unit_id: u32,
+}
+
+impl Display for TertiaryDeviceAttributes {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "\x1bP!|{:08X}\x1b\\", self.unit_id)
+    }
+}
+
+impl ShortDeviceControl {
+    /// Returns the reply to a request for the tertiary device
+    /// attributes, `DCS ! | D...D ST`
+    pub fn tertiary_device_attributes(&self) -> Option<TertiaryDeviceAttributes> {
+        if self.byte != b'|' || self.intermediates != [b'!'] || !self.params.is_empty() {
+            return None;
+        }
+        let unit_id = std::str::from_utf8(&self.data).ok()?;
+        Some(TertiaryDeviceAttributes {
+            unit_id: u32::from_str_radix(unit_id, 16).ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escape::csi::CSI;
+    use crate::escape::parser::Parser;
+    use crate::escape::{Action, DeviceControlMode};
+    use k9::assert_equal as assert_eq;
+
+    fn parse(s: &str) -> Action {
+        Parser::new().parse_as_vec(s.as_bytes()).remove(0)
+    }
+
+    #[test]
+    fn wezterm() {
+        let features = TerminalFeatures::wezterm();
+        assert_eq!(
+            CSI::Device(Box::new(features.primary())).to_string(),
+            "\x1b[?65;4;6;18;22c"
+        );
+        assert_eq!(
+            CSI::Device(Box::new(features.secondary())).to_string(),
+            "\x1b[>1;277;0c"
+        );
+        assert_eq!(features.tertiary().to_string(), "\x1bP!|00000000\x1b\\");
+    }
+
+    #[test]
+    fn builder() {
+        let features = TerminalFeatures::new(64)
+            .with_clipboard(true)
+            .with_ansi_color(true)
+            .with_sixel(true)
+            .with_sixel(false)
+            .with_terminal_type(41)
+            .with_firmware_version(10)
+            .with_unit_id(0x7e57);
+        assert!(!features.has_attribute(DeviceAttributeCodes::SixelGraphics));
+        assert_eq!(
+            CSI::Device(Box::new(features.primary())).to_string(),
+            "\x1b[?64;22;52c"
+        );
+        assert_eq!(
+            CSI::Device(Box::new(features.secondary())).to_string(),
+            "\x1b[>41;10;0c"
+        );
+        assert_eq!(features.tertiary().to_string(), "\x1bP!|00007E57\x1b\\");
+    }
+
+    #[test]
+    fn parse_replies() {
+        let features = TerminalFeatures::wezterm().with_unit_id(0xabcdef);
+        assert_eq!(
+            parse("\x1b[?65;4;6;18;22c"),
+            Action::CSI(CSI::Device(Box::new(features.primary())))
+        );
+        assert_eq!(
+            parse("\x1b[>1;277;0c"),
+            Action::CSI(CSI::Device(Box::new(features.secondary())))
+        );
+        match parse(&features.tertiary().to_string()) {
+            Action::DeviceControl(DeviceControlMode::ShortDeviceControl(dcs)) => {
+                assert_eq!(dcs.tertiary_device_attributes(), Some(features.tertiary()));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Encodes parsed actions back into bytes, so that a stream that was
+//! parsed with `Parser` can be passed on, perhaps after some of its
+//! actions have been filtered or changed.
+//!
+//! Each action is encoded in a canonical form, which is not always
+//! the same as the bytes that were parsed: a C1 `CSI` is encoded as
+//! `ESC [`, for example, and the parameters of an SGR sequence are
+//! encoded as a sequence for each attribute.  Parsing the encoded
+//! bytes produces the same actions again.
+//!
+//! An OSC is terminated by either BEL or ST, and the parser reports
+//! ST as a separate action.  The `Encoder` holds back the terminator
+//! of an OSC until it has seen the next action, so that the same
+//! terminator is used, whereas `encode` always uses ST.
+//!
+//! `Action::ParseError` isn't encoded: the sequence that it describes
+//! was either discarded, or is also reported as some other action.
+//!
+//! `DeviceControlMode::TmuxEvents` isn't encoded either: the events
+//! were decoded from the output of tmux, whose bytes aren't retained
+//! and can't be rebuilt from them.  The `DCS 1000 p` that entered
+//! tmux control mode is still encoded.
+use crate::escape::{Action, BatchedAction, DeviceControlMode, Esc, EscCode};
+use std::io::Write;
+
+/// Encodes a single action
+pub fn encode(action: &Action) -> Vec<u8> {
+    match action {
+        Action::OperatingSystemCommand(osc) => osc.to_string().into_bytes(),
+        action => {
+            let mut encoder = Encoder::new(vec![]);
+            // Writing to a Vec can't fail
+            encoder.encode(action).ok();
+            encoder.writer
+        }
+    }
+}
+
+/// Encodes a stream of actions to a writer
+pub struct Encoder<W: Write> {
+    writer: W,
+    /// The most recent action was an OSC, whose terminator
+    /// hasn't been written yet
+    unterminated_osc: bool,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            unterminated_osc: false,
+        }
+    }
+
+    /// Writes the terminator of a pending OSC; returns true if
+    /// `action` is the ST that terminated it
+    fn terminate_osc(&mut self, action: Option<&Action>) -> std::io::Result<bool> {
+        if !self.unterminated_osc {
+            return Ok(false);
+        }
+        self.unterminated_osc = false;
+        if let Some(Action::Esc(Esc::Code(EscCode::StringTerminator))) = action {
+            self.writer.write_all(b"\x1b\\")?;
+            Ok(true)
+        } else {
+            self.writer.write_all(b"\x07")?;
+            Ok(false)
+        }
+    }
+
+    pub fn encode(&mut self, action: &Action) -> std::io::Result<()> {
+        if self.terminate_osc(Some(action))? {
+            return Ok(());
+        }
+        match action {
+            Action::OperatingSystemCommand(osc) => {
+                let osc = osc.to_string();
+                let osc = osc.strip_suffix("\x1b\\").unwrap_or(&osc);
+                self.writer.write_all(osc.as_bytes())?;
+                self.unterminated_osc = true;
+                Ok(())
+            }
+            Action::DeviceControl(DeviceControlMode::Data(b)) => self.writer.write_all(&[*b]),
+            // The events were decoded from the output of tmux, which
+            // isn't retained
+            Action::DeviceControl(DeviceControlMode::TmuxEvents(_)) => Ok(()),
+            Action::DeviceControl(DeviceControlMode::TmuxPassthrough(passthrough)) => {
+                self.writer.write_all(b"\x1bPtmux;")?;
+                // Each ESC is doubled; the ST follows as a separate action
+                for &b in passthrough.data() {
+                    if b == 0x1b {
+                        self.writer.write_all(&[b])?;
+                    }
+                    self.writer.write_all(&[b])?;
+                }
+                Ok(())
+            }
+            action => write!(self.writer, "{}", action),
+        }
+    }
+
+    /// Encodes an action that was taken from an `ActionBatch`
+    pub fn encode_batched(&mut self, action: &BatchedAction) -> std::io::Result<()> {
+        match action {
+            BatchedAction::Print(text) => {
+                self.terminate_osc(None)?;
+                self.writer.write_all(text.as_bytes())
+            }
+            BatchedAction::Action(action) => self.encode(action),
+        }
+    }
+
+    /// Terminates a pending OSC and flushes the writer.
+    /// If the OSC was terminated by ST, the ST will still be
+    /// written when it is encoded, so the OSC is followed by
+    /// both BEL and ST; that parses to the same actions.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.terminate_osc(None)?;
+        self.writer.flush()
+    }
+
+    /// Terminates a pending OSC and returns the writer
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::escape::parser::Parser;
+    use crate::escape::ActionBatch;
+    use k9::assert_equal as assert_eq;
+
+    fn encode_all(actions: &[Action]) -> Vec<u8> {
+        let mut encoder = Encoder::new(vec![]);
+        for action in actions {
+            encoder.encode(action).unwrap();
+        }
+        encoder.finish().unwrap()
+    }
+
+    fn round_trip(input: &[u8]) -> Vec<u8> {
+        let actions = Parser::new().parse_as_vec(input);
+        let encoded = encode_all(&actions);
+        assert_eq!(Parser::new().parse_as_vec(&encoded), actions);
+        encoded
+    }
+
+    #[test]
+    fn canonical() {
+        for input in [
+            &b"hello\r\n\x1b[1m\x1b[31mworld\x1b[0m"[..],
+            b"\x1b[?1049h\x1b[5 q\x1b(0\x1bM\x07",
+            b"\x1b]0;title\x07\x1b]2;title\x1b\\",
+            b"\x1b]8;;http://example.com\x1b\\link\x1b]8;;\x07",
+            b"\x1bP1;2|ab\x1b\\",
+            b"\x1bP1$r0m\x1b\\",
+            b"\x1bP+q544e;636f\x1b\\",
+            b"\x1bPtmux;\x1b\x1b]0;x\x07\x1b\\",
+            b"\x1bPtmux;\x1b\x1b[1m\x1b\\",
+        ] {
+            assert_eq!(
+                String::from_utf8(round_trip(input)).unwrap(),
+                String::from_utf8(input.to_vec()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for (input, expected) in [
+            (&b"\x9b1m"[..], &b"\x1b[1m"[..]),
+            (b"\x1b[1;31m", b"\x1b[1m\x1b[31m"),
+            (
+                b"\x1bPq#0;2;0;0;0#1!6~-\x1b\\",
+                b"\x1bP0;0q#0;2;0;0;0#1!6~-\x1b\\",
+            ),
+            (b"\x1b_Ga=q,i=1;AAAA\x1b\\", b"\x1b_Ga=q,i=1;AAAA\x1b\\"),
+        ] {
+            assert_eq!(
+                String::from_utf8(round_trip(input)).unwrap(),
+                String::from_utf8(expected.to_vec()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn single_actions() {
+        let actions = Parser::new().parse_as_vec(b"\x1b]0;title\x07\x1bPx\x1b\\");
+        assert_eq!(encode(&actions[0]), b"\x1b]0;title\x1b\\".to_vec());
+        assert_eq!(encode(&actions[1]), b"\x1bPx".to_vec());
+    }
+
+    #[test]
+    fn osc_split_by_flush() {
+        let mut encoder = Encoder::new(vec![]);
+        let actions = Parser::new().parse_as_vec(b"\x1b]0;title\x1b\\");
+        encoder.encode(&actions[0]).unwrap();
+        encoder.flush().unwrap();
+        encoder.encode(&actions[1]).unwrap();
+        let encoded = encoder.finish().unwrap();
+        assert_eq!(encoded, b"\x1b]0;title\x07\x1b\\".to_vec());
+        assert_eq!(Parser::new().parse_as_vec(&encoded), actions);
+    }
+
+    #[test]
+    fn tmux_events_are_not_encoded() {
+        let actions = Parser::new().parse_as_vec(b"\x1bP1000p%begin 1 1 1\n%end 1 1 1\n");
+        assert!(actions
+            .iter()
+            .any(|a| matches!(a, Action::DeviceControl(DeviceControlMode::TmuxEvents(_)))));
+        assert_eq!(encode_all(&actions), b"\x1bP1000p".to_vec());
+    }
+
+    #[test]
+    fn batched() {
+        let mut batch = ActionBatch::new();
+        Parser::new().parse_into(b"\x1b]0;x\x07hi\x1b[1mthere", &mut batch);
+        let mut encoder = Encoder::new(vec![]);
+        for action in batch.drain() {
+            encoder.encode_batched(&action).unwrap();
+        }
+        assert_eq!(
+            encoder.finish().unwrap(),
+            b"\x1b]0;x\x07hi\x1b[1mthere".to_vec()
+        );
+    }
+}
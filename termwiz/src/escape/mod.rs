@@ -12,17 +12,27 @@ use std::fmt::{Display, Error as FmtError, Formatter, Write as FmtWrite};
 use wezterm_color_types::LinearRgba;
 
 pub mod apc;
+pub mod batch;
 pub mod csi;
+pub mod decrqss;
+pub mod device_attributes;
+pub mod encode;
 pub mod esc;
+pub mod notification;
 pub mod osc;
 pub mod parser;
+pub mod xtgettcap;
 
 pub use self::apc::KittyImage;
+pub use self::batch::{ActionBatch, BatchedAction};
 pub use self::csi::CSI;
+pub use self::encode::{encode, Encoder};
 pub use self::esc::{Esc, EscCode};
 pub use self::osc::OperatingSystemCommand;
 
+use self::csi::{DecPrivateMode, DecPrivateModeCode, Mode};
 use vtparse::CsiParam;
+pub use vtparse::ParseErrorReason;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Action {
@@ -41,10 +51,21 @@ pub enum Action {
     CSI(CSI),
     Esc(Esc),
     Sixel(Box<Sixel>),
+    /// A part of a sixel image, passed on as it is parsed rather than
+    /// as a whole `Sixel`; see `Parser::set_sixel_streaming`
+    SixelStream(SixelStream),
     /// A list of termcap, terminfo names for which the application
     /// wants information
     XtGetTcap(Vec<String>),
     KittyImage(Box<KittyImage>),
+    /// A malformed sequence; these are only reported once
+    /// diagnostics have been enabled with `Parser::set_diagnostics`.
+    /// `bytes` holds the bytes of the sequence, or the first
+    /// `vtparse::MAX_DIAGNOSTIC_BYTES` of them.
+    ParseError {
+        bytes: Box<[u8]>,
+        reason: ParseErrorReason,
+    },
 }
 
 impl Action {
@@ -71,6 +92,25 @@ impl Action {
         }
         dest.push(self);
     }
+
+    /// Returns `Some(true)` if this action begins a synchronized update,
+    /// `Some(false)` if it ends one, or `None` for any other action.
+    /// An update is begun by either `CSI ? 2026 h` or the `DCS = 1 s ST`
+    /// (BSU) sequence, and ended by `CSI ? 2026 l` or `DCS = 2 s ST` (ESU).
+    pub fn synchronized_update(&self) -> Option<bool> {
+        match self {
+            Action::CSI(CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::SynchronizedOutput,
+            )))) => Some(true),
+            Action::CSI(CSI::Mode(Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::SynchronizedOutput,
+            )))) => Some(false),
+            Action::DeviceControl(DeviceControlMode::ShortDeviceControl(dcs)) => {
+                dcs.synchronized_update()
+            }
+            _ => None,
+        }
+    }
 }
 
 #[cfg(all(test, target_pointer_width = "64"))]
@@ -96,6 +136,7 @@ impl Display for Action {
             Action::CSI(csi) => csi.fmt(f),
             Action::Esc(esc) => esc.fmt(f),
             Action::Sixel(sixel) => sixel.fmt(f),
+            Action::SixelStream(stream) => stream.fmt(f),
             Action::XtGetTcap(names) => {
                 write!(f, "\x1bP+q")?;
                 for (i, name) in names.iter().enumerate() {
@@ -110,6 +151,9 @@ impl Display for Action {
                 Ok(())
             }
             Action::KittyImage(img) => img.fmt(f),
+            // The sequence was discarded, or was also reported
+            // as some other action
+            Action::ParseError { .. } => Ok(()),
         }
     }
 }
@@ -131,6 +175,49 @@ pub struct ShortDeviceControl {
     pub data: Vec<u8>,
 }
 
+impl ShortDeviceControl {
+    /// Returns `Some(true)` for BSU, `DCS = 1 s ST`, which begins a
+    /// synchronized update, and `Some(false)` for ESU, `DCS = 2 s ST`,
+    /// which ends it
+    pub fn synchronized_update(&self) -> Option<bool> {
+        if self.byte != b's' || self.intermediates != [b'='] || !self.data.is_empty() {
+            return None;
+        }
+        match self.params.as_slice() {
+            [1] => Some(true),
+            [2] => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the params and intermediates of a DCS sequence.  A private
+/// marker is held as the first of the intermediates, but precedes
+/// the params.
+fn write_dcs_params(
+    f: &mut Formatter,
+    params: &[i64],
+    intermediates: &[u8],
+) -> Result<(), FmtError> {
+    let (marker, intermediates) = match intermediates.split_first() {
+        Some((&marker, rest)) if (0x3c..=0x3f).contains(&marker) => (Some(marker), rest),
+        _ => (None, intermediates),
+    };
+    if let Some(marker) = marker {
+        f.write_char(marker as char)?;
+    }
+    for (idx, p) in params.iter().enumerate() {
+        if idx > 0 {
+            write!(f, ";")?;
+        }
+        write!(f, "{}", p)?;
+    }
+    for b in intermediates {
+        f.write_char(*b as char)?;
+    }
+    Ok(())
+}
+
 impl std::fmt::Debug for ShortDeviceControl {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         write!(
@@ -158,15 +245,7 @@ impl std::fmt::Debug for ShortDeviceControl {
 impl Display for ShortDeviceControl {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
         write!(f, "\x1bP")?;
-        for (idx, p) in self.params.iter().enumerate() {
-            if idx > 0 {
-                write!(f, ";")?;
-            }
-            write!(f, "{}", p)?;
-        }
-        for b in &self.intermediates {
-            f.write_char(*b as char)?;
-        }
+        write_dcs_params(f, &self.params, &self.intermediates)?;
         f.write_char(self.byte as char)?;
         for b in &self.data {
             f.write_char(*b as char)?;
@@ -220,6 +299,57 @@ pub enum DeviceControlMode {
     ShortDeviceControl(Box<ShortDeviceControl>),
     /// Tmux parsed events
     TmuxEvents(Box<Vec<Event>>),
+    /// The sequences that were wrapped in `DCS tmux; ... ST` so
+    /// that tmux would pass them through to the terminal
+    TmuxPassthrough(Box<TmuxPassthrough>),
+}
+
+/// The contents of `DCS tmux; ... ST`, in which each ESC is doubled.
+/// The unwrapped bytes are held rather than the actions that they
+/// parse to, so that they are passed on exactly as they were written.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TmuxPassthrough {
+    data: Vec<u8>,
+}
+
+impl TmuxPassthrough {
+    /// `data` is the unwrapped sequences, whose ESCs are not doubled
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Parses the unwrapped sequences
+    pub fn actions(&self) -> Vec<Action> {
+        parser::Parser::new().parse_as_vec(&self.data)
+    }
+}
+
+impl Display for TmuxPassthrough {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "\x1bPtmux;")?;
+        // The ST follows as a separate action
+        for c in String::from_utf8_lossy(&self.data).chars() {
+            if c == '\x1b' {
+                f.write_char(c)?;
+            }
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for TmuxPassthrough {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            fmt,
+            "TmuxPassthrough({:?})",
+            String::from_utf8_lossy(&self.data)
+        )
+    }
 }
 
 impl Display for DeviceControlMode {
@@ -227,15 +357,7 @@ impl Display for DeviceControlMode {
         match self {
             Self::Enter(mode) => {
                 write!(f, "\x1bP")?;
-                for (idx, p) in mode.params.iter().enumerate() {
-                    if idx > 0 {
-                        write!(f, ";")?;
-                    }
-                    write!(f, "{}", p)?;
-                }
-                for b in &mode.intermediates {
-                    f.write_char(*b as char)?;
-                }
+                write_dcs_params(f, &mode.params, &mode.intermediates)?;
                 f.write_char(mode.byte as char)
             }
             // We don't need to emit a sequence for the Exit, as we're
@@ -244,6 +366,7 @@ impl Display for DeviceControlMode {
             Self::Data(c) => f.write_char(*c as char),
             Self::ShortDeviceControl(s) => s.fmt(f),
             Self::TmuxEvents(_) => write!(f, "tmux event"),
+            Self::TmuxPassthrough(passthrough) => passthrough.fmt(f),
         }
     }
 }
@@ -256,6 +379,7 @@ impl std::fmt::Debug for DeviceControlMode {
             Self::Data(b) => write!(fmt, "Data({:?} 0x{:x})", *b as char, *b),
             Self::ShortDeviceControl(s) => write!(fmt, "ShortDeviceControl({:?})", s),
             Self::TmuxEvents(_) => write!(fmt, "tmux event"),
+            Self::TmuxPassthrough(passthrough) => write!(fmt, "{:?}", passthrough),
         }
     }
 }
@@ -269,6 +393,13 @@ pub struct Sixel {
     /// Specifies the denominator for the pixel aspect ratio
     pub pad: i64,
 
+    /// True if the aspect ratio was set by the raster attributes
+    /// (DECGRA) rather than by the first parameter of the DCS.
+    /// Only an aspect ratio that was set by the raster attributes
+    /// is applied to the image, as most applications expect square
+    /// pixels even though the default of the DCS is 2:1.
+    pub raster_aspect_ratio: bool,
+
     /// How wide the image is, in pixels
     pub pixel_width: Option<u32>,
 
@@ -288,8 +419,32 @@ pub struct Sixel {
 }
 
 impl Sixel {
+    /// Returns the number of pixels that each pixel of the sixel
+    /// data occupies horizontally and vertically, according to the
+    /// aspect ratio
+    pub fn aspect_scale(&self) -> (u32, u32) {
+        if !self.raster_aspect_ratio {
+            return (1, 1);
+        }
+        let pan = self.pan.clamp(1, u32::MAX as i64) as u32;
+        let pad = self.pad.clamp(1, u32::MAX as i64) as u32;
+        (
+            (pad.saturating_add(pan / 2) / pan).max(1),
+            (pan.saturating_add(pad / 2) / pad).max(1),
+        )
+    }
+
     /// Returns the width, height of the image
     pub fn dimensions(&self) -> (u32, u32) {
+        let (width, height) = self.unscaled_dimensions();
+        let (x_scale, y_scale) = self.aspect_scale();
+        (
+            width.saturating_mul(x_scale),
+            height.saturating_mul(y_scale),
+        )
+    }
+
+    fn unscaled_dimensions(&self) -> (u32, u32) {
         if let (Some(w), Some(h)) = (self.pixel_width, self.pixel_height) {
             return (w, h);
         }
@@ -347,6 +502,18 @@ impl Display for Sixel {
                 self.pixel_width.unwrap_or(0),
                 self.pixel_height.unwrap_or(0)
             )?;
+        } else if self.raster_aspect_ratio {
+            write!(
+                f,
+                "\x1bP;{}{}q\"{};{}",
+                if self.background_is_transparent { 1 } else { 0 },
+                match self.horizontal_grid_size {
+                    Some(h) => format!(";{}", h),
+                    None => "".to_string(),
+                },
+                self.pan,
+                self.pad,
+            )?;
         } else {
             write!(
                 f,
@@ -377,6 +544,28 @@ impl Display for Sixel {
     }
 }
 
+/// A sixel image in the parts in which it is parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SixelStream {
+    /// Begins the image.  Holds its attributes, including those set
+    /// by its raster attributes, which precede its data; the data of
+    /// the `Sixel` is empty.
+    Enter(Box<Sixel>),
+    Data(SixelData),
+    /// Ends the image.  As with `Sixel`, the ST is a separate action.
+    Exit,
+}
+
+impl Display for SixelStream {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            Self::Enter(sixel) => sixel.fmt(f),
+            Self::Data(data) => data.fmt(f),
+            Self::Exit => Ok(()),
+        }
+    }
+}
+
 /// A decoded 6-bit sixel value.
 /// Each sixel represents a six-pixel tall bitmap where
 /// the least significant bit is the topmost bit.
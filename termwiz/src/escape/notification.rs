@@ -0,0 +1,474 @@
+//! Desktop notifications, which an application may request with any of:
+//!
+//! * `OSC 9 ; body ST`, from iTerm2
+//! * `OSC 9 ; 2 ; body ST`, the message box of ConEmu.  The other
+//!   ConEmu commands that share OSC 9 aren't notifications.
+//! * `OSC 777 ; notify ; title ; body ST`, from urxvt
+//! * `OSC 99 ; metadata ; payload ST`, from kitty; see
+//!   <https://sw.kovidgoyal.net/kitty/desktop-notifications/>
+//!
+//! Each of them is parsed into a `Notification`.  The kitty protocol
+//! is the most capable of them: the metadata is a `:` separated list
+//! of `key=value` pairs that identify the notification, split its
+//! title and body across several sequences, and say what should
+//! happen when it is activated or closed.  The terminal can respond
+//! with a sequence of its own, which is also a `Notification`.
+use crate::escape::osc::{base64_decode, base64_encode};
+use crate::{bail, ensure, Result};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::time::Duration;
+
+/// The escape sequence that described a notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationProtocol {
+    /// `OSC 9`
+    ITerm,
+    /// `OSC 9 ; 2`
+    ConEmu,
+    /// `OSC 777 ; notify`
+    Rxvt,
+    /// `OSC 99`
+    Kitty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationKind {
+    /// Show the title and body.  A kitty notification whose `done`
+    /// is false is continued by the next notification that has the
+    /// same id.
+    Show,
+    /// Close the notification that has the same id
+    Close,
+    /// Asks which of the notifications are still being shown.
+    /// In the response, lists their ids.
+    Alive(Vec<String>),
+    /// Asks what the terminal supports.  In the response, describes
+    /// it as a `:` separated list of `key=value` pairs.
+    Query(String),
+}
+
+/// When to show a notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationOccasion {
+    Always,
+    /// Only if the window isn't focused
+    Unfocused,
+    /// Only if the window isn't visible
+    Invisible,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationUrgency {
+    Low = 0,
+    Normal = 1,
+    Critical = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub protocol: NotificationProtocol,
+    pub kind: NotificationKind,
+    /// Identifies the notification, so that the application can
+    /// continue, close or be told about it later
+    pub id: Option<String>,
+    /// A kitty notification holds either a title or a body;
+    /// if both are set, only the title is encoded
+    pub title: Option<String>,
+    pub body: Option<String>,
+    /// False if the title or body is continued by a later notification
+    pub done: bool,
+    /// Whether activating the notification should focus the
+    /// window/tab/pane that requested it
+    pub focus: bool,
+    /// Whether the terminal should tell the application when the
+    /// notification is activated
+    pub report_activation: bool,
+    /// Whether the terminal should tell the application when the
+    /// notification is closed
+    pub report_close: bool,
+    pub occasion: NotificationOccasion,
+    pub urgency: NotificationUrgency,
+    /// How long to show the notification; `None` for the default
+    /// of the system.  A zero duration means that the notification
+    /// doesn't expire.
+    pub timeout: Option<Duration>,
+}
+
+impl Notification {
+    pub fn new(protocol: NotificationProtocol, kind: NotificationKind) -> Self {
+        Self {
+            protocol,
+            kind,
+            id: None,
+            title: None,
+            body: None,
+            done: true,
+            focus: true,
+            report_activation: false,
+            report_close: false,
+            occasion: NotificationOccasion::Always,
+            urgency: NotificationUrgency::Normal,
+            timeout: None,
+        }
+    }
+
+    /// Parses an `OSC 9`
+    pub fn parse_system_notification(osc: &[&[u8]]) -> Result<Self> {
+        ensure!(osc.len() >= 2, "wrong param count");
+        let conemu_command = std::str::from_utf8(osc[1])
+            .ok()
+            .and_then(|s| s.parse::<u8>().ok())
+            .filter(|n| (1..=12).contains(n));
+        let (protocol, body) = match conemu_command {
+            None => (NotificationProtocol::ITerm, &osc[1..]),
+            Some(2) if osc.len() > 2 => (NotificationProtocol::ConEmu, &osc[2..]),
+            Some(n) => bail!("ConEmu OSC 9;{} is not a notification", n),
+        };
+        // The body may contain semicolons
+        let body = String::from_utf8(body.join(&b';'))?;
+        Ok(Self {
+            body: Some(body),
+            ..Self::new(protocol, NotificationKind::Show)
+        })
+    }
+
+    /// Parses an `OSC 777 ; notify`
+    pub fn parse_rxvt(osc: &[&[u8]]) -> Result<Self> {
+        ensure!(osc.get(1) == Some(&&b"notify"[..]), "not an rxvt notify");
+        let (title, body) = match osc.len() {
+            3 => (None, osc[2].to_vec()),
+            n if n > 3 => (
+                Some(String::from_utf8(osc[2].to_vec())?),
+                osc[3..].join(&b';'),
+            ),
+            _ => bail!("malformed rxvt notify: {:?}", osc),
+        };
+        Ok(Self {
+            title,
+            body: Some(String::from_utf8(body)?),
+            ..Self::new(NotificationProtocol::Rxvt, NotificationKind::Show)
+        })
+    }
+
+    /// Parses an `OSC 99`
+    pub fn parse_kitty(osc: &[&[u8]]) -> Result<Self> {
+        ensure!(osc.len() >= 2, "wrong param count");
+        let mut notification = Self::new(NotificationProtocol::Kitty, NotificationKind::Show);
+        let mut payload_type = "title";
+        let mut base64 = false;
+
+        for item in std::str::from_utf8(osc[1])?.split(':') {
+            if item.is_empty() {
+                continue;
+            }
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("invalid kitty notification metadata {:?}", item))?;
+            match key {
+                "a" => {
+                    for action in value.split(',') {
+                        let (action, enable) = match action.strip_prefix('-') {
+                            Some(action) => (action, false),
+                            None => (action, true),
+                        };
+                        match action {
+                            "focus" => notification.focus = enable,
+                            "report" => notification.report_activation = enable,
+                            _ => {}
+                        }
+                    }
+                }
+                "c" => notification.report_close = value == "1",
+                "d" => notification.done = value != "0",
+                "e" => base64 = value == "1",
+                "i" => {
+                    ensure!(
+                        !value.is_empty()
+                            && value
+                                .chars()
+                                .all(|c| c.is_ascii_alphanumeric()
+                                    || matches!(c, '-' | '_' | '+' | '.')),
+                        "invalid kitty notification id {:?}",
+                        value
+                    );
+                    notification.id = Some(value.to_string());
+                }
+                "o" => {
+                    notification.occasion = match value {
+                        "always" => NotificationOccasion::Always,
+                        "unfocused" => NotificationOccasion::Unfocused,
+                        "invisible" => NotificationOccasion::Invisible,
+                        _ => bail!("invalid kitty notification occasion {:?}", value),
+                    }
+                }
+                "p" => payload_type = value,
+                "u" => {
+                    notification.urgency = match value {
+                        "0" => NotificationUrgency::Low,
+                        "1" => NotificationUrgency::Normal,
+                        "2" => NotificationUrgency::Critical,
+                        _ => bail!("invalid kitty notification urgency {:?}", value),
+                    }
+                }
+                "w" => {
+                    let millis: i64 = value.parse()?;
+                    // -1 means the default of the system
+                    notification.timeout = if millis < 0 {
+                        None
+                    } else {
+                        Some(Duration::from_millis(millis as u64))
+                    };
+                }
+                // The application name, icon, sound and type aren't used
+                _ => {}
+            }
+        }
+
+        // The payload may contain semicolons
+        let payload = osc[2..].join(&b';');
+        let payload = if base64 {
+            base64_decode(&payload)?
+        } else {
+            payload
+        };
+        let payload = String::from_utf8(payload)?;
+
+        match payload_type {
+            "title" => notification.title = Some(payload),
+            "body" => notification.body = Some(payload),
+            "close" => notification.kind = NotificationKind::Close,
+            "alive" => {
+                notification.kind = NotificationKind::Alive(
+                    payload
+                        .split(',')
+                        .filter(|id| !id.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                )
+            }
+            "?" => notification.kind = NotificationKind::Query(payload),
+            _ => bail!("unsupported kitty notification payload {:?}", payload_type),
+        }
+
+        Ok(notification)
+    }
+
+    fn fmt_kitty(&self, f: &mut Formatter) -> std::result::Result<(), FmtError> {
+        let mut metadata = vec![];
+        if let Some(id) = &self.id {
+            metadata.push(format!("i={}", id));
+        }
+        let payload = match &self.kind {
+            NotificationKind::Show => match (&self.title, &self.body) {
+                (None, Some(body)) => {
+                    metadata.push("p=body".to_string());
+                    body.clone()
+                }
+                (title, _) => title.clone().unwrap_or_default(),
+            },
+            NotificationKind::Close => {
+                metadata.push("p=close".to_string());
+                String::new()
+            }
+            NotificationKind::Alive(ids) => {
+                metadata.push("p=alive".to_string());
+                ids.join(",")
+            }
+            NotificationKind::Query(capabilities) => {
+                metadata.push("p=?".to_string());
+                capabilities.clone()
+            }
+        };
+        if !self.done {
+            metadata.push("d=0".to_string());
+        }
+        match (self.focus, self.report_activation) {
+            (true, false) => {}
+            (true, true) => metadata.push("a=report".to_string()),
+            (false, false) => metadata.push("a=-focus".to_string()),
+            (false, true) => metadata.push("a=-focus,report".to_string()),
+        }
+        if self.report_close {
+            metadata.push("c=1".to_string());
+        }
+        match self.occasion {
+            NotificationOccasion::Always => {}
+            NotificationOccasion::Unfocused => metadata.push("o=unfocused".to_string()),
+            NotificationOccasion::Invisible => metadata.push("o=invisible".to_string()),
+        }
+        if self.urgency != NotificationUrgency::Normal {
+            metadata.push(format!("u={}", self.urgency as u8));
+        }
+        if let Some(timeout) = self.timeout {
+            metadata.push(format!("w={}", timeout.as_millis()));
+        }
+
+        // Control characters would end the sequence
+        if payload.chars().any(char::is_control) {
+            metadata.push("e=1".to_string());
+            write!(f, "99;{};{}", metadata.join(":"), base64_encode(payload))
+        } else {
+            write!(f, "99;{};{}", metadata.join(":"), payload)
+        }
+    }
+}
+
+impl Display for Notification {
+    fn fmt(&self, f: &mut Formatter) -> std::result::Result<(), FmtError> {
+        let body = self.body.as_deref().unwrap_or("");
+        match self.protocol {
+            NotificationProtocol::ITerm => write!(f, "9;{}", body),
+            NotificationProtocol::ConEmu => write!(f, "9;2;{}", body),
+            NotificationProtocol::Rxvt => match &self.title {
+                Some(title) => write!(f, "777;notify;{};{}", title, body),
+                None => write!(f, "777;notify;{}", body),
+            },
+            NotificationProtocol::Kitty => self.fmt_kitty(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escape::parser::Parser;
+    use crate::escape::{Action, OperatingSystemCommand};
+    use k9::assert_equal as assert_eq;
+
+    fn parse(s: &str) -> Notification {
+        let mut p = Parser::new();
+        let actions = p.parse_as_vec(s.as_bytes());
+        match actions.into_iter().next() {
+            Some(Action::OperatingSystemCommand(osc)) => match *osc {
+                OperatingSystemCommand::Notification(notification) => {
+                    // The encoded form parses to the same notification
+                    assert_eq!(
+                        p.parse_as_vec(osc_string(&notification).as_bytes())[0],
+                        Action::OperatingSystemCommand(Box::new(
+                            OperatingSystemCommand::Notification(notification.clone())
+                        ))
+                    );
+                    notification
+                }
+                other => panic!("unexpected {:?}", other),
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn osc_string(notification: &Notification) -> String {
+        OperatingSystemCommand::Notification(notification.clone()).to_string()
+    }
+
+    #[test]
+    fn iterm() {
+        let n = parse("\x1b]9;the tea; it is ready\x07");
+        assert_eq!(n.protocol, NotificationProtocol::ITerm);
+        assert_eq!(n.title, None);
+        assert_eq!(n.body.as_deref(), Some("the tea; it is ready"));
+        assert_eq!(osc_string(&n), "\x1b]9;the tea; it is ready\x1b\\");
+    }
+
+    #[test]
+    fn conemu() {
+        let n = parse("\x1b]9;2;the tea is ready\x07");
+        assert_eq!(n.protocol, NotificationProtocol::ConEmu);
+        assert_eq!(n.body.as_deref(), Some("the tea is ready"));
+
+        // Progress reports are not notifications
+        assert_eq!(
+            Parser::new().parse_as_vec(b"\x1b]9;4;1;50\x07"),
+            vec![Action::OperatingSystemCommand(Box::new(
+                OperatingSystemCommand::Unspecified(vec![
+                    b"9".to_vec(),
+                    b"4".to_vec(),
+                    b"1".to_vec(),
+                    b"50".to_vec()
+                ])
+            ))]
+        );
+    }
+
+    #[test]
+    fn rxvt() {
+        let n = parse("\x1b]777;notify;alert user;the tea is ready\x07");
+        assert_eq!(n.protocol, NotificationProtocol::Rxvt);
+        assert_eq!(n.title.as_deref(), Some("alert user"));
+        assert_eq!(n.body.as_deref(), Some("the tea is ready"));
+
+        let n = parse("\x1b]777;notify;the tea is ready\x07");
+        assert_eq!(n.title, None);
+        assert_eq!(n.body.as_deref(), Some("the tea is ready"));
+
+        // Other rxvt extensions are left alone
+        assert_eq!(
+            Parser::new().parse_as_vec(b"\x1b]777;other;x\x07"),
+            vec![Action::OperatingSystemCommand(Box::new(
+                OperatingSystemCommand::RxvtExtension(vec!["other".to_string(), "x".to_string()])
+            ))]
+        );
+    }
+
+    #[test]
+    fn kitty() {
+        let n = parse("\x1b]99;i=tea:d=0:u=2:a=-focus,report:c=1:o=unfocused;Tea\x1b\\");
+        assert_eq!(
+            n,
+            Notification {
+                id: Some("tea".to_string()),
+                title: Some("Tea".to_string()),
+                done: false,
+                focus: false,
+                report_activation: true,
+                report_close: true,
+                occasion: NotificationOccasion::Unfocused,
+                urgency: NotificationUrgency::Critical,
+                ..Notification::new(NotificationProtocol::Kitty, NotificationKind::Show)
+            }
+        );
+
+        let n = parse("\x1b]99;i=tea:p=body:e=1:w=5000;aXMgcmVhZHk=\x1b\\");
+        assert_eq!(n.title, None);
+        assert_eq!(n.body.as_deref(), Some("is ready"));
+        assert_eq!(n.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(osc_string(&n), "\x1b]99;i=tea:p=body:w=5000;is ready\x1b\\");
+
+        let mut n = Notification::new(NotificationProtocol::Kitty, NotificationKind::Show);
+        n.title = Some("a\x07b".to_string());
+        assert_eq!(osc_string(&n), "\x1b]99;e=1;YQdi\x1b\\");
+
+        assert_eq!(
+            parse("\x1b]99;i=tea:p=close;\x1b\\").kind,
+            NotificationKind::Close
+        );
+        assert_eq!(
+            parse("\x1b]99;i=x:p=alive;tea,cake\x1b\\").kind,
+            NotificationKind::Alive(vec!["tea".to_string(), "cake".to_string()])
+        );
+        assert_eq!(
+            parse("\x1b]99;i=x:p=?;\x1b\\").kind,
+            NotificationKind::Query(String::new())
+        );
+    }
+
+    #[test]
+    fn kitty_invalid() {
+        for s in [
+            "\x1b]99;i=a b;x\x1b\\",
+            "\x1b]99;u=3;x\x1b\\",
+            "\x1b]99;p=icon;x\x1b\\",
+            "\x1b]99;e=1;!!\x1b\\",
+        ] {
+            match Parser::new().parse_as_vec(s.as_bytes()).into_iter().next() {
+                Some(Action::OperatingSystemCommand(osc)) => {
+                    assert!(
+                        matches!(*osc, OperatingSystemCommand::Unspecified(_)),
+                        "{:?}",
+                        s
+                    );
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+}
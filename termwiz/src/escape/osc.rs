@@ -1,6 +1,8 @@
 use crate::color::SrgbaTuple;
+pub use crate::escape::notification::Notification;
 pub use crate::hyperlink::Hyperlink;
 use crate::{bail, ensure, Result};
+use base64::engine::DecodePaddingMode;
 use base64::Engine;
 use bitflags::bitflags;
 use num_derive::*;
@@ -37,13 +39,13 @@ pub enum OperatingSystemCommand {
     ClearSelection(Selection),
     QuerySelection(Selection),
     SetSelection(Selection, String),
-    SystemNotification(String),
+    Notification(Notification),
     ITermProprietary(ITermProprietary),
     FinalTermSemanticPrompt(FinalTermSemanticPrompt),
     ChangeColorNumber(Vec<ChangeColorPair>),
     ChangeDynamicColors(DynamicColorNumber, Vec<ColorOrQuery>),
     ResetDynamicColor(DynamicColorNumber),
-    CurrentWorkingDirectory(String),
+    CurrentWorkingDirectory(WorkingDirectory),
     ResetColors(Vec<u8>),
     RxvtExtension(Vec<String>),
 
@@ -163,6 +165,21 @@ impl OperatingSystemCommand {
         })
     }
 
+    /// Returns the response to the OSC 4 query of the palette entry
+    /// `palette_index`, whose value is `color`
+    pub fn palette_color_response(palette_index: u8, color: SrgbaTuple) -> Self {
+        OperatingSystemCommand::ChangeColorNumber(vec![ChangeColorPair {
+            palette_index,
+            color: ColorOrQuery::Color(color),
+        }])
+    }
+
+    /// Returns the response to the OSC 10-19 query of the dynamic
+    /// color `which`, whose value is `color`
+    pub fn dynamic_color_response(which: DynamicColorNumber, color: SrgbaTuple) -> Self {
+        OperatingSystemCommand::ChangeDynamicColors(which, vec![ColorOrQuery::Color(color)])
+    }
+
     fn parse_selection(osc: &[&[u8]]) -> Result<Self> {
         if osc.len() == 2 {
             Selection::try_parse(osc[1]).map(OperatingSystemCommand::ClearSelection)
@@ -170,9 +187,16 @@ impl OperatingSystemCommand {
             Selection::try_parse(osc[1]).map(OperatingSystemCommand::QuerySelection)
         } else if osc.len() == 3 {
             let sel = Selection::try_parse(osc[1])?;
-            let bytes = base64_decode(osc[2])?;
-            let s = String::from_utf8(bytes)?;
-            Ok(OperatingSystemCommand::SetSelection(sel, s))
+            // Some applications omit the padding
+            match base64_decode_with_padding(osc[2], DecodePaddingMode::Indifferent) {
+                Ok(bytes) => Ok(OperatingSystemCommand::SetSelection(
+                    sel,
+                    String::from_utf8_lossy(&bytes).into_owned(),
+                )),
+                // As in xterm, data that isn't valid base64 clears
+                // the selection
+                Err(_) => Ok(OperatingSystemCommand::ClearSelection(sel)),
+            }
         } else {
             bail!("unhandled OSC 52: {:?}", osc);
         }
@@ -273,16 +297,6 @@ impl OperatingSystemCommand {
         }
         .ok_or_else(|| format!("unknown code"))?;
 
-        macro_rules! single_string {
-            ($variant:ident) => {{
-                if osc.len() != 2 {
-                    bail!("wrong param count");
-                }
-                let s = String::from_utf8(osc[1].to_vec())?;
-                Ok(OperatingSystemCommand::$variant(s))
-            }};
-        }
-
         macro_rules! single_title_string {
             ($variant:ident) => {{
                 if osc.len() < 2 {
@@ -311,11 +325,24 @@ impl OperatingSystemCommand {
             )),
             SetHyperlink => Ok(OperatingSystemCommand::SetHyperlink(Hyperlink::parse(osc)?)),
             ManipulateSelectionData => Self::parse_selection(osc),
-            SystemNotification => single_string!(SystemNotification),
-            SetCurrentWorkingDirectory => single_string!(CurrentWorkingDirectory),
+            SystemNotification => Notification::parse_system_notification(osc)
+                .map(OperatingSystemCommand::Notification),
+            KittyNotification => {
+                Notification::parse_kitty(osc).map(OperatingSystemCommand::Notification)
+            }
+            SetCurrentWorkingDirectory => {
+                // The path may contain semicolons
+                let url = osc[1..].join(&b';');
+                Ok(OperatingSystemCommand::CurrentWorkingDirectory(
+                    str::from_utf8(&url)?.parse()?,
+                ))
+            }
             ITermProprietary => {
                 self::ITermProprietary::parse(osc).map(OperatingSystemCommand::ITermProprietary)
             }
+            RxvtProprietary if osc.get(1) == Some(&&b"notify"[..]) => {
+                Notification::parse_rxvt(osc).map(OperatingSystemCommand::Notification)
+            }
             RxvtProprietary => {
                 let mut vec = vec![];
                 for slice in osc.iter().skip(1) {
@@ -407,7 +434,7 @@ osc_entries!(
     SetCurrentWorkingDirectory = "7",
     /// See https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda
     SetHyperlink = "8",
-    /// iTerm2
+    /// iTerm2 and ConEmu
     SystemNotification = "9",
     SetTextForegroundColor = "10",
     SetTextBackgroundColor = "11",
@@ -419,6 +446,8 @@ osc_entries!(
     SetHighlightBackgroundColor = "17",
     SetTektronixCursorColor = "18",
     SetHighlightForegroundColor = "19",
+    /// kitty
+    KittyNotification = "99",
     SetLogFileName = "46",
     SetFont = "50",
     EmacsShell = "51",
@@ -508,7 +537,7 @@ impl Display for OperatingSystemCommand {
             ClearSelection(s) => write!(f, "52;{}", s)?,
             QuerySelection(s) => write!(f, "52;{};?", s)?,
             SetSelection(s, val) => write!(f, "52;{};{}", s, base64_encode(val))?,
-            SystemNotification(s) => write!(f, "9;{}", s)?,
+            Notification(n) => n.fmt(f)?,
             ITermProprietary(i) => i.fmt(f)?,
             FinalTermSemanticPrompt(i) => i.fmt(f)?,
             ResetColors(colors) => {
@@ -518,9 +547,9 @@ impl Display for OperatingSystemCommand {
                 }
             }
             ChangeColorNumber(specs) => {
-                write!(f, "4;")?;
+                write!(f, "4")?;
                 for pair in specs {
-                    write!(f, "{};{}", pair.palette_index, pair.color)?
+                    write!(f, ";{};{}", pair.palette_index, pair.color)?
                 }
             }
             ChangeDynamicColors(first_color, colors) => {
@@ -532,7 +561,7 @@ impl Display for OperatingSystemCommand {
             ResetDynamicColor(color) => {
                 write!(f, "{}", 100 + *color as u8)?;
             }
-            CurrentWorkingDirectory(s) => write!(f, "7;{}", s)?,
+            CurrentWorkingDirectory(dir) => write!(f, "7;{}", dir)?,
         };
         // Use the longer form ST as neovim doesn't like the BEL version
         write!(f, "\x1b\\")?;
@@ -660,9 +689,13 @@ pub enum FinalTermSemanticPrompt {
         aid: Option<String>,
     },
 
-    /// Indicates the result of the command
+    /// Indicates the result of the command.
+    /// `status` is the exit code, which is omitted when the command
+    /// didn't run, for example because the input was cancelled.
+    /// `err` is a description of the failure, if any.
     CommandStatus {
-        status: i32,
+        status: Option<i32>,
+        err: Option<String>,
         aid: Option<String>,
     },
 }
@@ -672,22 +705,17 @@ impl FinalTermSemanticPrompt {
         ensure!(osc.len() > 1, "not enough args");
         let param = String::from_utf8_lossy(osc[1]);
 
-        macro_rules! single {
-            ($variant:ident, $text:expr) => {
-                if osc.len() == 2 && param == $text {
-                    return Ok(FinalTermSemanticPrompt::$variant);
-                }
-            };
-        }
-
-        single!(FreshLine, "L");
-        single!(MarkEndOfPromptAndStartOfInputUntilNextMarker, "B");
-        single!(MarkEndOfPromptAndStartOfInputUntilEndOfLine, "I");
+        // The exit code of D is the only positional parameter;
+        // it is omitted if the command didn't run
+        let status = match osc.get(2) {
+            Some(s) if param == "D" && !s.contains(&b'=') => Some(*s),
+            _ => None,
+        };
 
         let mut params = HashMap::new();
         use std::convert::TryInto;
 
-        for s in osc.iter().skip(if param == "D" { 3 } else { 2 }) {
+        for s in osc.iter().skip(if status.is_some() { 3 } else { 2 }) {
             if let Some(equal) = s.iter().position(|c| *c == b'=') {
                 let key = &s[..equal];
                 let value = &s[equal + 1..];
@@ -697,59 +725,50 @@ impl FinalTermSemanticPrompt {
             }
         }
 
-        if param == "A" {
-            return Ok(Self::FreshLineAndStartPrompt {
-                aid: params.get("aid").map(|&s| s.to_owned()),
-                cl: match params.get("cl") {
-                    Some(&cl) => Some(cl.try_into()?),
-                    None => None,
-                },
-            });
-        }
-
-        if param == "C" {
-            return Ok(Self::MarkEndOfInputAndStartOfOutput {
-                aid: params.get("aid").map(|&s| s.to_owned()),
-            });
-        }
-
-        if param == "D" {
-            let status = match osc.get(2).map(|&p| p) {
-                Some(s) => match str::from_utf8(s) {
-                    Ok(s) => s.parse().unwrap_or(0),
-                    _ => 0,
-                },
-                _ => 0,
-            };
-
-            return Ok(Self::CommandStatus {
-                status,
-                aid: params.get("aid").map(|&s| s.to_owned()),
-            });
-        }
+        let aid = params.get("aid").map(|&s| s.to_owned());
+        let cl = match params.get("cl") {
+            Some(&cl) => Some(cl.try_into()?),
+            None => None,
+        };
 
-        if param == "N" {
-            return Ok(Self::MarkEndOfCommandWithFreshLine {
-                aid: params.get("aid").map(|&s| s.to_owned()),
-                cl: match params.get("cl") {
-                    Some(&cl) => Some(cl.try_into()?),
-                    None => None,
+        // Options that don't apply to a marker are ignored
+        match param.as_ref() {
+            "L" => Ok(Self::FreshLine),
+            "A" => Ok(Self::FreshLineAndStartPrompt { aid, cl }),
+            "N" => Ok(Self::MarkEndOfCommandWithFreshLine { aid, cl }),
+            "P" => Ok(Self::StartPrompt(match params.get("k") {
+                Some(&k) => k.try_into()?,
+                None => FinalTermPromptKind::default(),
+            })),
+            "B" => Ok(Self::MarkEndOfPromptAndStartOfInputUntilNextMarker),
+            "I" => Ok(Self::MarkEndOfPromptAndStartOfInputUntilEndOfLine),
+            "C" => Ok(Self::MarkEndOfInputAndStartOfOutput { aid }),
+            "D" => Ok(Self::CommandStatus {
+                status: match status {
+                    Some(s) if !s.is_empty() => Some(str::from_utf8(s)?.parse()?),
+                    _ => None,
                 },
-            });
+                err: params.get("err").map(|&s| s.to_owned()),
+                aid,
+            }),
+            _ => bail!(
+                "invalid FinalTermSemanticPrompt p1:{:?}, params:{:?}",
+                param,
+                params
+            ),
         }
+    }
 
-        if param == "P" {
-            return Ok(Self::StartPrompt(match params.get("k") {
-                Some(&cl) => cl.try_into()?,
-                None => FinalTermPromptKind::default(),
-            }));
+    /// Returns the application id, which associates the markers
+    /// of a command with each other
+    pub fn aid(&self) -> Option<&str> {
+        match self {
+            Self::FreshLineAndStartPrompt { aid, .. }
+            | Self::MarkEndOfCommandWithFreshLine { aid, .. }
+            | Self::MarkEndOfInputAndStartOfOutput { aid }
+            | Self::CommandStatus { aid, .. } => aid.as_deref(),
+            _ => None,
         }
-
-        bail!(
-            "invalid FinalTermSemanticPrompt p1:{:?}, params:{:?}",
-            param,
-            params
-        );
     }
 }
 
@@ -787,20 +806,142 @@ impl Display for FinalTermSemanticPrompt {
                     write!(f, ";aid={}", aid)?;
                 }
             }
-            Self::CommandStatus {
-                status,
-                aid: Some(aid),
-            } => {
-                write!(f, "D;{};err={};aid={}", status, status, aid)?;
-            }
-            Self::CommandStatus { status, aid: None } => {
-                write!(f, "D;{}", status)?;
+            Self::CommandStatus { status, err, aid } => {
+                write!(f, "D")?;
+                if let Some(status) = status {
+                    write!(f, ";{}", status)?;
+                } else if err.is_some() || aid.is_some() {
+                    // Keep the place of the omitted exit code
+                    write!(f, ";")?;
+                }
+                if let Some(err) = err {
+                    write!(f, ";err={}", err)?;
+                }
+                if let Some(aid) = aid {
+                    write!(f, ";aid={}", aid)?;
+                }
             }
         }
         Ok(())
     }
 }
 
+/// The working directory that is reported by OSC 7 as a `file://` URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkingDirectory {
+    /// The host that the directory is on.  This is empty if the URL
+    /// has no host, which means the local host.
+    pub hostname: String,
+    /// The decoded path.  A Windows path, which appears in the URL
+    /// as `/C:/Users`, doesn't have the leading slash.
+    pub path: String,
+}
+
+impl WorkingDirectory {
+    /// Returns true if `hostname` is a valid host name, IPv4 address
+    /// or bracketed IPv6 address, or is empty
+    fn is_valid_hostname(hostname: &str) -> bool {
+        if let Some(addr) = hostname.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+            return addr.parse::<std::net::Ipv6Addr>().is_ok();
+        }
+        hostname.len() <= 253
+            && (hostname.is_empty()
+                || hostname.split('.').all(|label| {
+                    !label.is_empty()
+                        && label.len() <= 63
+                        && !label.starts_with('-')
+                        && !label.ends_with('-')
+                        && label
+                            .bytes()
+                            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+                }))
+    }
+
+    /// Returns true if `path` starts with a drive letter, such as `C:`
+    fn has_drive_letter(path: &str) -> bool {
+        let bytes = path.as_bytes();
+        bytes.len() >= 2
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && matches!(bytes.get(2), None | Some(b'/') | Some(b'\\'))
+    }
+
+    fn percent_decode(s: &str) -> Result<String> {
+        let mut bytes = Vec::with_capacity(s.len());
+        let mut iter = s.bytes();
+        while let Some(b) = iter.next() {
+            if b == b'%' {
+                let mut digit = || {
+                    iter.next()
+                        .and_then(|d| char::from(d).to_digit(16))
+                        .map(|d| d as u8)
+                };
+                match (digit(), digit()) {
+                    (Some(hi), Some(lo)) => bytes.push(hi << 4 | lo),
+                    _ => bail!("invalid percent encoding in {:?}", s),
+                }
+            } else {
+                bytes.push(b);
+            }
+        }
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    /// Returns the `file://` URL of the directory
+    pub fn url(&self) -> String {
+        let mut url = format!("file://{}", self.hostname);
+        if !self.path.starts_with('/') {
+            url.push('/');
+        }
+        for &b in self.path.as_bytes() {
+            if b.is_ascii_alphanumeric() || b"/-._~!$&'()*+,=:@".contains(&b) {
+                url.push(b as char);
+            } else {
+                url.push_str(&format!("%{:02X}", b));
+            }
+        }
+        url
+    }
+}
+
+impl FromStr for WorkingDirectory {
+    type Err = crate::Error;
+
+    /// Parses a `file://` URL
+    fn from_str(url: &str) -> Result<Self> {
+        let rest = match url.get(..7) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("file://") => &url[7..],
+            _ => bail!("working directory {:?} is not a file:// URL", url),
+        };
+        let (hostname, path) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, "/"),
+        };
+        ensure!(
+            Self::is_valid_hostname(hostname),
+            "invalid hostname {:?} in working directory",
+            hostname
+        );
+
+        let path = Self::percent_decode(path)?;
+        let path = match path.strip_prefix('/') {
+            Some(windows) if Self::has_drive_letter(windows) => windows.to_string(),
+            _ => path,
+        };
+
+        Ok(Self {
+            hostname: hostname.to_string(),
+            path,
+        })
+    }
+}
+
+impl Display for WorkingDirectory {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str(&self.url())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ITermProprietary {
     /// The "Set Mark" command allows you to record a location and then jump back to it later
@@ -835,8 +976,9 @@ pub enum ITermProprietary {
     /// Place a string in the systems pasteboard
     Copy(String),
     /// Each iTerm2 session has internal variables (as described in
-    /// <https://www.iterm2.com/documentation-badges.html>). This escape sequence reports
-    /// a variable's value.  The response is another ReportVariable.
+    /// <https://www.iterm2.com/documentation-badges.html>). This escape sequence requests
+    /// the value of the named variable.  The response is another ReportVariable,
+    /// which holds the value.
     ReportVariable(String),
     /// User-defined variables may be set with the following escape sequence
     SetUserVar {
@@ -846,6 +988,17 @@ pub enum ITermProprietary {
     SetBadgeFormat(String),
     /// Download file data from the application.
     File(Box<ITermFileData>),
+    /// Begin downloading a file whose data is sent by the subsequent
+    /// FilePart sequences, so that large files need not be sent in a
+    /// single sequence.  The data of the ITermFileData is empty.
+    MultipartFile(Box<ITermFileData>),
+    /// The next piece of the data of the file begun by MultipartFile
+    FilePart(Vec<u8>),
+    /// The file begun by MultipartFile is complete
+    FileEnd,
+    /// Ask the user to choose files to upload to the application,
+    /// which are sent as input in the specified format, such as `tgz`
+    RequestUpload(String),
 
     /// Configure unicode version
     UnicodeVersion(ITermUnicodeVersionOp),
@@ -882,6 +1035,16 @@ pub struct ITermFileData {
 
 impl ITermFileData {
     fn parse(osc: &[&[u8]]) -> Result<Self> {
+        Self::parse_args(osc, "File=", true)
+    }
+
+    /// Parses the arguments of MultipartFile, which are the same as
+    /// those of File, but are not followed by any data
+    fn parse_multipart(osc: &[&[u8]]) -> Result<Self> {
+        Self::parse_args(osc, "MultipartFile=", false)
+    }
+
+    fn parse_args(osc: &[&[u8]], prefix: &str, has_data: bool) -> Result<Self> {
         let mut params = HashMap::new();
 
         // Unfortunately, the encoding for the file download data is
@@ -896,17 +1059,17 @@ impl ITermFileData {
         let last = osc.len() - 1;
         for (idx, s) in osc.iter().enumerate().skip(1) {
             let param = if idx == 1 {
-                if s.len() >= 5 {
-                    // skip over File=
-                    &s[5..]
+                if s.len() >= prefix.len() {
+                    // skip over File= or MultipartFile=
+                    &s[prefix.len()..]
                 } else {
-                    bail!("failed to parse file data; File= not found");
+                    bail!("failed to parse file data; {} not found", prefix);
                 }
             } else {
                 s
             };
 
-            let param = if idx == last {
+            let param = if idx == last && has_data {
                 // The final argument contains `:base64`, so look for that
                 if let Some(colon) = param.iter().position(|c| *c == b':') {
                     data = Some(base64_decode(&param[colon + 1..])?);
@@ -930,7 +1093,7 @@ impl ITermFileData {
                 let key = &param[..equal];
                 let value = &param[equal + 1..];
                 params.insert(str::from_utf8(key)?, str::from_utf8(value)?);
-            } else if idx != last {
+            } else if idx != last || !has_data {
                 bail!("failed to parse file data; no equals found");
             }
         }
@@ -957,7 +1120,11 @@ impl ITermFileData {
             .get("doNotMoveCursor")
             .map(|s| *s != "0")
             .unwrap_or(false);
-        let data = data.ok_or_else(|| format!("didn't set data"))?;
+        let data = if has_data {
+            data.ok_or_else(|| "didn't set data".to_string())?
+        } else {
+            vec![]
+        };
         Ok(Self {
             name,
             size,
@@ -974,6 +1141,15 @@ impl ITermFileData {
 impl Display for ITermFileData {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         write!(f, "File")?;
+        self.fmt_args(f)?;
+        write!(f, ":{}", base64_encode(&self.data))?;
+        Ok(())
+    }
+}
+
+impl ITermFileData {
+    /// Writes the arguments, preceded by `=`
+    fn fmt_args(&self, f: &mut Formatter) -> FmtResult {
         let mut sep = "=";
         let emit_sep = |sep, f: &mut Formatter| -> std::result::Result<&str, FmtError> {
             write!(f, "{}", sep)?;
@@ -1012,7 +1188,6 @@ impl Display for ITermFileData {
         if sep == "=" {
             write!(f, "=")?;
         }
-        write!(f, ":{}", base64_encode(&self.data))?;
         Ok(())
     }
 }
@@ -1191,6 +1366,14 @@ impl ITermProprietary {
             }
         }
 
+        if osc.len() == 2 && keyword == "ReportVariable" {
+            if let Some(p1) = p1 {
+                return Ok(ITermProprietary::ReportVariable(String::from_utf8(
+                    base64_decode(p1)?,
+                )?));
+            }
+        }
+
         if osc.len() == 2 && keyword == "UnicodeVersion" {
             if let Some(p1) = p1 {
                 let mut iter = p1.splitn(2, ' ');
@@ -1219,6 +1402,22 @@ impl ITermProprietary {
         if keyword == "File" {
             return Ok(ITermProprietary::File(Box::new(ITermFileData::parse(osc)?)));
         }
+        if keyword == "MultipartFile" {
+            return Ok(ITermProprietary::MultipartFile(Box::new(
+                ITermFileData::parse_multipart(osc)?,
+            )));
+        }
+        if osc.len() == 2 && keyword == "FilePart" {
+            if let Some(p1) = p1 {
+                return Ok(ITermProprietary::FilePart(base64_decode(p1)?));
+            }
+        }
+        single!(FileEnd, "FileEnd");
+        if osc.len() == 2 && keyword == "RequestUpload" {
+            if let Some(format) = p1.and_then(|p1| p1.strip_prefix("format=")) {
+                return Ok(ITermProprietary::RequestUpload(format.to_string()));
+            }
+        }
 
         bail!("ITermProprietary {:?}", osc);
     }
@@ -1232,11 +1431,20 @@ pub(crate) fn base64_encode<T: AsRef<[u8]>>(s: T) -> String {
 /// base64::decode is deprecated, so make a less frustrating helper
 pub(crate) fn base64_decode<T: AsRef<[u8]>>(
     s: T,
+) -> std::result::Result<Vec<u8>, base64::DecodeError> {
+    base64_decode_with_padding(s, DecodePaddingMode::RequireCanonical)
+}
+
+fn base64_decode_with_padding<T: AsRef<[u8]>>(
+    s: T,
+    padding: DecodePaddingMode,
 ) -> std::result::Result<Vec<u8>, base64::DecodeError> {
     use base64::engine::{GeneralPurpose, GeneralPurposeConfig};
     GeneralPurpose::new(
         &base64::alphabet::STANDARD,
-        GeneralPurposeConfig::new().with_decode_allow_trailing_bits(true),
+        GeneralPurposeConfig::new()
+            .with_decode_allow_trailing_bits(true)
+            .with_decode_padding_mode(padding),
     )
     .decode(s)
 }
@@ -1277,6 +1485,13 @@ impl Display for ITermProprietary {
             }
             SetBadgeFormat(s) => write!(f, "SetBadgeFormat={}", base64_encode(s))?,
             File(file) => file.fmt(f)?,
+            MultipartFile(file) => {
+                write!(f, "MultipartFile")?;
+                file.fmt_args(f)?;
+            }
+            FilePart(data) => write!(f, "FilePart={}", base64_encode(data))?,
+            FileEnd => write!(f, "FileEnd")?,
+            RequestUpload(format) => write!(f, "RequestUpload=format={}", format)?,
             UnicodeVersion(ITermUnicodeVersionOp::Set(n)) => write!(f, "UnicodeVersion={}", n)?,
             UnicodeVersion(ITermUnicodeVersionOp::Push(Some(label))) => {
                 write!(f, "UnicodeVersion=push {}", label)?
@@ -1294,6 +1509,7 @@ impl Display for ITermProprietary {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::escape::notification::{NotificationKind, NotificationProtocol};
 
     fn encode(osc: &OperatingSystemCommand) -> String {
         format!("{}", osc)
@@ -1329,6 +1545,69 @@ mod test {
             parse(&["112"], "\x1b]112\x1b\\"),
             OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextCursorColor)
         );
+        assert_eq!(
+            parse(&["110"], "\x1b]110\x1b\\"),
+            OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextForegroundColor)
+        );
+        assert_eq!(
+            parse(&["111"], "\x1b]111\x1b\\"),
+            OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextBackgroundColor)
+        );
+    }
+
+    #[test]
+    fn change_colors() {
+        assert_eq!(
+            parse(
+                &["4", "1", "?", "2", "#ff8000"],
+                "\x1b]4;1;?;2;rgb:ffff/8080/0000\x1b\\"
+            ),
+            OperatingSystemCommand::ChangeColorNumber(vec![
+                ChangeColorPair {
+                    palette_index: 1,
+                    color: ColorOrQuery::Query,
+                },
+                ChangeColorPair {
+                    palette_index: 2,
+                    color: ColorOrQuery::Color((0xff, 0x80, 0).into()),
+                },
+            ])
+        );
+        assert_eq!(
+            parse(&["11", "?"], "\x1b]11;?\x1b\\"),
+            OperatingSystemCommand::ChangeDynamicColors(
+                DynamicColorNumber::TextBackgroundColor,
+                vec![ColorOrQuery::Query]
+            )
+        );
+        assert_eq!(
+            parse(
+                &["10", "?", "rgb:00/00/00"],
+                "\x1b]10;?;rgb:0000/0000/0000\x1b\\"
+            ),
+            OperatingSystemCommand::ChangeDynamicColors(
+                DynamicColorNumber::TextForegroundColor,
+                vec![ColorOrQuery::Query, ColorOrQuery::Color((0, 0, 0).into())]
+            )
+        );
+    }
+
+    #[test]
+    fn color_responses() {
+        assert_eq!(
+            encode(&OperatingSystemCommand::palette_color_response(
+                1,
+                (0xcc, 0x55, 0x55).into()
+            )),
+            "\x1b]4;1;rgb:cccc/5555/5555\x1b\\"
+        );
+        assert_eq!(
+            encode(&OperatingSystemCommand::dynamic_color_response(
+                DynamicColorNumber::TextBackgroundColor,
+                (0x32, 0x32, 0x32).into()
+            )),
+            "\x1b]11;rgb:3232/3232/3232\x1b\\"
+        );
     }
 
     #[test]
@@ -1383,7 +1662,7 @@ mod test {
             OperatingSystemCommand::SetHyperlink(None)
         );
 
-        // too many params
+        // params must be key=value pairs
         assert_eq!(
             parse(&["8", "1", "2"], "\x1b]8;1;2\x1b\\"),
             OperatingSystemCommand::Unspecified(vec![b"8".to_vec(), b"1".to_vec(), b"2".to_vec()])
@@ -1393,6 +1672,67 @@ mod test {
             Hyperlink::parse(&[b"8", b"", b"x"]).unwrap(),
             Some(Hyperlink::new("x"))
         );
+
+        // An empty uri closes the link, even with params
+        assert_eq!(Hyperlink::parse(&[b"8", b"id=foo", b""]).unwrap(), None);
+
+        // The uri may contain semicolons
+        assert_eq!(
+            parse(
+                &["8", "id=foo", "http://example.com/a", "b"],
+                "\x1b]8;id=foo;http://example.com/a;b\x1b\\"
+            ),
+            OperatingSystemCommand::SetHyperlink(Some(Hyperlink::new_with_id(
+                "http://example.com/a;b",
+                "foo"
+            )))
+        );
+
+        // Empty params are ignored, and an empty id is no id
+        let link = Hyperlink::parse(&[b"8", b"id=:", b"x"]).unwrap().unwrap();
+        assert_eq!(link.id(), None);
+        assert_eq!(Hyperlink::new_with_id("x", "foo").id(), Some("foo"));
+        assert_eq!(Hyperlink::new("x").id(), None);
+    }
+
+    #[test]
+    fn selection() {
+        assert_eq!(
+            parse(&["52", "c", "aGVsbG8="], "\x1b]52;c;aGVsbG8=\x1b\\"),
+            OperatingSystemCommand::SetSelection(Selection::CLIPBOARD, "hello".to_owned())
+        );
+        // Padding is optional, but only for OSC 52
+        assert_eq!(
+            parse(&["52", "pc", "aGVsbG8"], "\x1b]52;cp;aGVsbG8=\x1b\\"),
+            OperatingSystemCommand::SetSelection(
+                Selection::CLIPBOARD | Selection::PRIMARY,
+                "hello".to_owned()
+            )
+        );
+        assert!(base64_decode("aGVsbG8").is_err());
+        assert_eq!(base64_decode("aGVsbG8=").unwrap(), b"hello".to_vec());
+        // The default is the selection and the first cut buffer
+        assert_eq!(
+            parse(&["52", "", "?"], "\x1b]52;s0;?\x1b\\"),
+            OperatingSystemCommand::QuerySelection(Selection::SELECT | Selection::CUT0)
+        );
+        assert_eq!(
+            parse(&["52", "c"], "\x1b]52;c\x1b\\"),
+            OperatingSystemCommand::ClearSelection(Selection::CLIPBOARD)
+        );
+        assert_eq!(
+            parse(&["52", "c", "!"], "\x1b]52;c\x1b\\"),
+            OperatingSystemCommand::ClearSelection(Selection::CLIPBOARD)
+        );
+        // Text that isn't UTF-8 is decoded lossily
+        assert_eq!(
+            parse(&["52", "7", "/2E="], "\x1b]52;7;77+9YQ==\x1b\\"),
+            OperatingSystemCommand::SetSelection(Selection::CUT7, "\u{fffd}a".to_owned())
+        );
+        assert_eq!(
+            parse(&["52", "x", "?"], "\x1b]52;x;?\x1b\\"),
+            OperatingSystemCommand::Unspecified(vec![b"52".to_vec(), b"x".to_vec(), b"?".to_vec()])
+        );
     }
 
     #[test]
@@ -1421,7 +1761,8 @@ mod test {
             parse(&["133", "D", "1"], "\x1b]133;D;1\x1b\\"),
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::CommandStatus {
-                    status: 1,
+                    status: Some(1),
+                    err: None,
                     aid: None
                 }
             )
@@ -1431,33 +1772,30 @@ mod test {
             parse(&["133", "D", "0"], "\x1b]133;D;0\x1b\\"),
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::CommandStatus {
-                    status: 0,
+                    status: Some(0),
+                    err: None,
                     aid: None
                 }
             )
         );
 
         assert_eq!(
-            parse(
-                &["133", "D", "0", "aid=23"],
-                "\x1b]133;D;0;err=0;aid=23\x1b\\"
-            ),
+            parse(&["133", "D", "0", "aid=23"], "\x1b]133;D;0;aid=23\x1b\\"),
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::CommandStatus {
-                    status: 0,
+                    status: Some(0),
+                    err: None,
                     aid: Some("23".to_owned())
                 }
             )
         );
 
         assert_eq!(
-            parse(
-                &["133", "D", "1", "aid=23"],
-                "\x1b]133;D;1;err=1;aid=23\x1b\\"
-            ),
+            parse(&["133", "D", "1", "aid=23"], "\x1b]133;D;1;aid=23\x1b\\"),
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::CommandStatus {
-                    status: 1,
+                    status: Some(1),
+                    err: None,
                     aid: Some("23".to_owned())
                 }
             )
@@ -1596,6 +1934,165 @@ mod test {
         );
     }
 
+    #[test]
+    fn finalterm_command_status() {
+        let status = |status, err: Option<&str>, aid: Option<&str>| {
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::CommandStatus {
+                    status,
+                    err: err.map(str::to_owned),
+                    aid: aid.map(str::to_owned),
+                },
+            )
+        };
+
+        // The exit code is omitted when the command didn't run
+        assert_eq!(
+            parse(&["133", "D"], "\x1b]133;D\x1b\\"),
+            status(None, None, None)
+        );
+        assert_eq!(
+            parse(&["133", "D", "aid=5"], "\x1b]133;D;;aid=5\x1b\\"),
+            status(None, None, Some("5"))
+        );
+        assert_eq!(
+            parse(&["133", "D", "", "aid=5"], "\x1b]133;D;;aid=5\x1b\\"),
+            status(None, None, Some("5"))
+        );
+        assert_eq!(
+            parse(
+                &["133", "D", "127", "err=not found", "aid=5"],
+                "\x1b]133;D;127;err=not found;aid=5\x1b\\"
+            ),
+            status(Some(127), Some("not found"), Some("5"))
+        );
+        assert_eq!(
+            parse(&["133", "D", "-1"], "\x1b]133;D;-1\x1b\\"),
+            status(Some(-1), None, None)
+        );
+        assert_eq!(
+            parse(&["133", "D", "x"], "\x1b]133;D;x\x1b\\"),
+            OperatingSystemCommand::Unspecified(vec![
+                b"133".to_vec(),
+                b"D".to_vec(),
+                b"x".to_vec()
+            ])
+        );
+    }
+
+    #[test]
+    fn finalterm_options() {
+        // Options that don't apply to a marker are ignored
+        assert_eq!(
+            parse(&["133", "B", "aid=1"], "\x1b]133;B\x1b\\"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker
+            ),
+        );
+        assert_eq!(
+            parse(&["133", "C", "aid=1", "k=s"], "\x1b]133;C;aid=1\x1b\\"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput {
+                    aid: Some("1".to_owned())
+                }
+            ),
+        );
+        assert_eq!(
+            parse(&["133", "A", "cl=x"], "\x1b]133;A;cl=x\x1b\\"),
+            OperatingSystemCommand::Unspecified(vec![
+                b"133".to_vec(),
+                b"A".to_vec(),
+                b"cl=x".to_vec()
+            ])
+        );
+
+        let prompt = FinalTermSemanticPrompt::FreshLineAndStartPrompt {
+            aid: Some("12".to_owned()),
+            cl: None,
+        };
+        assert_eq!(prompt.aid(), Some("12"));
+        assert_eq!(FinalTermSemanticPrompt::FreshLine.aid(), None);
+    }
+
+    #[test]
+    fn working_directory() {
+        let cwd = |hostname: &str, path: &str| {
+            OperatingSystemCommand::CurrentWorkingDirectory(WorkingDirectory {
+                hostname: hostname.to_owned(),
+                path: path.to_owned(),
+            })
+        };
+
+        assert_eq!(
+            parse(
+                &["7", "file://foo/home/wez"],
+                "\x1b]7;file://foo/home/wez\x1b\\"
+            ),
+            cwd("foo", "/home/wez")
+        );
+        assert_eq!(
+            parse(&["7", "file:///tmp"], "\x1b]7;file:///tmp\x1b\\"),
+            cwd("", "/tmp")
+        );
+        assert_eq!(
+            parse(&["7", "FILE://foo"], "\x1b]7;file://foo/\x1b\\"),
+            cwd("foo", "/")
+        );
+        assert_eq!(
+            parse(
+                &["7", "file://my-host.example.com/a%20b/%E2%9C%93", "c"],
+                "\x1b]7;file://my-host.example.com/a%20b/%E2%9C%93%3Bc\x1b\\"
+            ),
+            cwd("my-host.example.com", "/a b/\u{2713};c")
+        );
+        assert_eq!(
+            parse(&["7", "file://[::1]/tmp"], "\x1b]7;file://[::1]/tmp\x1b\\"),
+            cwd("[::1]", "/tmp")
+        );
+
+        // Windows paths don't have the leading slash
+        assert_eq!(
+            parse(
+                &["7", "file://pc/C:/Users/wez"],
+                "\x1b]7;file://pc/C:/Users/wez\x1b\\"
+            ),
+            cwd("pc", "C:/Users/wez")
+        );
+        assert_eq!(
+            parse(
+                &["7", "file:///C:\\Users"],
+                "\x1b]7;file:///C:%5CUsers\x1b\\"
+            ),
+            cwd("", "C:\\Users")
+        );
+        assert_eq!(
+            parse(&["7", "file:///C:"], "\x1b]7;file:///C:\x1b\\"),
+            cwd("", "C:")
+        );
+        assert_eq!(
+            parse(&["7", "file:///Cat:"], "\x1b]7;file:///Cat:\x1b\\"),
+            cwd("", "/Cat:")
+        );
+
+        for bad in [
+            "/home/wez",
+            "http://foo/home",
+            "file://bad host/tmp",
+            "file://-foo/tmp",
+            "file://a..b/tmp",
+            "file://[zz]/tmp",
+            "file:///%zz",
+            "file:///%ff",
+        ]
+        .iter()
+        {
+            assert_eq!(
+                parse(&["7", bad], &format!("\x1b]7;{}\x1b\\", bad)),
+                OperatingSystemCommand::Unspecified(vec![b"7".to_vec(), bad.as_bytes().to_vec()])
+            );
+        }
+    }
+
     #[test]
     fn rxvt() {
         assert_eq!(
@@ -1603,12 +2100,16 @@ mod test {
                 &["777", "notify", "alert user", "the tea is ready"],
                 "\x1b]777;notify;alert user;the tea is ready\x1b\\"
             ),
-            OperatingSystemCommand::RxvtExtension(vec![
-                "notify".into(),
-                "alert user".into(),
-                "the tea is ready".into()
-            ]),
-        )
+            OperatingSystemCommand::Notification(Notification {
+                title: Some("alert user".into()),
+                body: Some("the tea is ready".into()),
+                ..Notification::new(NotificationProtocol::Rxvt, NotificationKind::Show)
+            }),
+        );
+        assert_eq!(
+            parse(&["777", "preedit", "x"], "\x1b]777;preedit;x\x1b\\"),
+            OperatingSystemCommand::RxvtExtension(vec!["preedit".into(), "x".into()]),
+        );
     }
 
     #[test]
@@ -1653,6 +2154,29 @@ mod test {
             })
         );
 
+        assert_eq!(
+            parse(
+                &["1337", "ReportVariable=dXNlci5mb28="],
+                "\x1b]1337;ReportVariable=dXNlci5mb28=\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::ReportVariable(
+                "user.foo".into()
+            ))
+        );
+
+        assert_eq!(
+            parse(&["1337", "StealFocus"], "\x1b]1337;StealFocus\x1b\\"),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::StealFocus)
+        );
+
+        assert_eq!(
+            parse(
+                &["1337", "ClearScrollback"],
+                "\x1b]1337;ClearScrollback\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::ClearScrollback)
+        );
+
         assert_eq!(
             parse(
                 &["1337", "SetBadgeFormat=", "aGVsbG8="],
@@ -1869,4 +2393,44 @@ mod test {
             )))
         );
     }
+
+    #[test]
+    fn iterm_multipart_file() {
+        assert_eq!(
+            parse(
+                &["1337", "MultipartFile=name=bXluYW1l", "size=10"],
+                "\x1b]1337;MultipartFile=size=10;name=bXluYW1l\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::MultipartFile(Box::new(
+                ITermFileData {
+                    name: Some("myname".into()),
+                    size: Some(10),
+                    width: ITermDimension::Automatic,
+                    height: ITermDimension::Automatic,
+                    preserve_aspect_ratio: true,
+                    inline: false,
+                    do_not_move_cursor: false,
+                    data: vec![],
+                }
+            )))
+        );
+        assert_eq!(
+            parse(
+                &["1337", "FilePart=aGVsbG8="],
+                "\x1b]1337;FilePart=aGVsbG8=\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::FilePart(b"hello".to_vec()))
+        );
+        assert_eq!(
+            parse(&["1337", "FileEnd"], "\x1b]1337;FileEnd\x1b\\"),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::FileEnd)
+        );
+        assert_eq!(
+            parse(
+                &["1337", "RequestUpload=format=tgz"],
+                "\x1b]1337;RequestUpload=format=tgz\x1b\\"
+            ),
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::RequestUpload("tgz".into()))
+        );
+    }
 }
@@ -1,9 +1,9 @@
 #![allow(clippy::many_single_char_names)]
 use crate::escape::{
-    Action, DeviceControlMode, EnterDeviceControlMode, Esc, OperatingSystemCommand,
-    ShortDeviceControl, CSI,
+    Action, ActionBatch, DeviceControlMode, EnterDeviceControlMode, Esc, OperatingSystemCommand,
+    ShortDeviceControl, TmuxPassthrough, CSI,
 };
-use crate::tmux_cc::Event;
+use crate::tmux_cc::{Event, ProtocolVersion};
 use log::error;
 use num_traits::FromPrimitive;
 use std::borrow::BorrowMut;
@@ -11,7 +11,12 @@ use std::cell::RefCell;
 use vtparse::{CsiParam, VTActor, VTParser};
 
 mod sixel;
-use sixel::SixelBuilder;
+
+/// The largest amount of data that is held for `DCS tmux; ... ST`,
+/// which is well beyond what tmux itself will buffer for a sequence
+const MAX_TMUX_PASSTHROUGH_SIZE: usize = 16 * 1024 * 1024;
+pub use sixel::{SixelBand, SixelDecoder, SixelLimits};
+use sixel::{SixelBuilder, SixelStreamer};
 
 #[derive(Default)]
 struct GetTcapBuilder {
@@ -45,8 +50,15 @@ impl GetTcapBuilder {
 #[derive(Default)]
 struct ParseState {
     sixel: Option<SixelBuilder>,
+    sixel_stream: Option<SixelStreamer>,
+    /// Whether sixel images are passed on as `Action::SixelStream`
+    sixel_streaming: bool,
     dcs: Option<ShortDeviceControl>,
     get_tcap: Option<GetTcapBuilder>,
+    tmux_passthrough: Option<Vec<u8>>,
+    /// Set when the data of `tmux_passthrough` exceeded
+    /// `MAX_TMUX_PASSTHROUGH_SIZE`, so that it is discarded
+    tmux_passthrough_overflow: bool,
     tmux_state: Option<RefCell<crate::tmux_cc::Parser>>,
 }
 
@@ -75,6 +87,47 @@ impl Parser {
         }
     }
 
+    /// Enables or disables the reporting of malformed sequences as
+    /// `Action::ParseError`, which is otherwise silent.  This slows
+    /// down parsing, so it is intended for debugging.
+    pub fn set_diagnostics(&mut self, enable: bool) {
+        self.state_machine.set_diagnostics(enable);
+    }
+
+    /// Enables or disables passing on sixel images in parts, as
+    /// `Action::SixelStream`, as they are parsed.  Otherwise each
+    /// image is collected into a single `Action::Sixel`, which holds
+    /// the whole of its data.
+    pub fn set_sixel_streaming(&mut self, enable: bool) {
+        self.state.borrow_mut().sixel_streaming = enable;
+    }
+
+    /// Returns true while the output is being parsed as tmux
+    /// control mode
+    pub fn is_tmux_control_mode(&self) -> bool {
+        self.state.borrow().tmux_state.is_some()
+    }
+
+    /// Returns the version of tmux that was given to the control
+    /// mode parser by `set_tmux_protocol_version`
+    pub fn tmux_protocol_version(&self) -> Option<ProtocolVersion> {
+        self.state
+            .borrow()
+            .tmux_state
+            .as_ref()
+            .and_then(|tmux| tmux.borrow().protocol_version())
+    }
+
+    /// Sets the version of tmux whose control mode output is being
+    /// parsed, once the application that started control mode has
+    /// detected it.  This has no effect outside of control mode, and
+    /// the version is forgotten when control mode ends.
+    pub fn set_tmux_protocol_version(&mut self, version: ProtocolVersion) {
+        if let Some(tmux) = self.state.borrow().tmux_state.as_ref() {
+            tmux.borrow_mut().set_protocol_version(version);
+        }
+    }
+
     /// advance with tmux parser, bypass VTParse
     fn advance_tmux_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<Vec<Event>> {
         let parser_state = self.state.borrow();
@@ -84,11 +137,36 @@ impl Parser {
     }
 
     pub fn parse<F: FnMut(Action)>(&mut self, bytes: &[u8], mut callback: F) {
+        self.parse_with_sink(bytes, &mut callback);
+    }
+
+    /// Parse the bytes, appending the actions to `batch`.
+    /// Unlike `parse_as_vec`, runs of printable text are accumulated
+    /// into the buffer held by the batch rather than allocating a
+    /// `String` for each of them.
+    pub fn parse_into(&mut self, bytes: &[u8], batch: &mut ActionBatch) {
+        self.parse_into_with(bytes, batch, |action, batch| batch.push(action));
+    }
+
+    /// Like `parse_into`, but each action other than printable text is
+    /// passed to `callback` along with the batch, rather than being
+    /// appended to it.  This allows the caller to act on some actions
+    /// as they are recognized while still accumulating text in the batch.
+    pub fn parse_into_with<F: FnMut(Action, &mut ActionBatch)>(
+        &mut self,
+        bytes: &[u8],
+        batch: &mut ActionBatch,
+        callback: F,
+    ) {
+        self.parse_with_sink(bytes, &mut BatchSink { batch, callback });
+    }
+
+    fn parse_with_sink<S: ActionSink>(&mut self, bytes: &[u8], sink: &mut S) {
         let is_tmux_mode: bool = self.state.borrow().tmux_state.is_some();
         if is_tmux_mode {
             match self.advance_tmux_bytes(bytes) {
                 Ok(tmux_events) => {
-                    callback(Action::DeviceControl(DeviceControlMode::TmuxEvents(
+                    sink.perform(Action::DeviceControl(DeviceControlMode::TmuxEvents(
                         Box::new(tmux_events),
                     )));
                 }
@@ -98,7 +176,7 @@ impl Parser {
                     let mut parser_state = self.state.borrow_mut();
                     parser_state.tmux_state = None;
                     let mut perform = Performer {
-                        callback: &mut callback,
+                        sink,
                         state: &mut parser_state,
                     };
                     self.state_machine
@@ -107,7 +185,7 @@ impl Parser {
             }
         } else {
             let mut perform = Performer {
-                callback: &mut callback,
+                sink,
                 state: &mut self.state.borrow_mut(),
             };
             self.state_machine.parse(bytes, &mut perform);
@@ -126,7 +204,7 @@ impl Parser {
         let mut first_idx = None;
         {
             let mut perform = Performer {
-                callback: &mut |action| {
+                sink: &mut |action| {
                     // capture the action, but only if it is the first one
                     // we've seen.  Preserve an existing one if any.
                     if first.borrow().is_some() {
@@ -170,7 +248,7 @@ impl Parser {
             self.state_machine.parse_byte(
                 *b,
                 &mut Performer {
-                    callback: &mut |action| actions.push(action),
+                    sink: &mut |action| actions.push(action),
                     state: &mut self.state.borrow_mut(),
                 },
             );
@@ -184,28 +262,80 @@ impl Parser {
     }
 }
 
-struct Performer<'a, F: FnMut(Action) + 'a> {
-    callback: &'a mut F,
+/// Receives the actions recognized by the `Performer`
+trait ActionSink {
+    fn perform(&mut self, action: Action);
+
+    fn print(&mut self, c: char) {
+        self.perform(Action::Print(c));
+    }
+
+    fn print_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.print(c);
+        }
+    }
+}
+
+impl<F: FnMut(Action)> ActionSink for F {
+    fn perform(&mut self, action: Action) {
+        self(action)
+    }
+}
+
+/// Appends printable characters directly to the batch,
+/// without first wrapping them in an `Action`
+struct BatchSink<'a, F: FnMut(Action, &mut ActionBatch)> {
+    batch: &'a mut ActionBatch,
+    callback: F,
+}
+
+impl<'a, F: FnMut(Action, &mut ActionBatch)> ActionSink for BatchSink<'a, F> {
+    fn perform(&mut self, action: Action) {
+        (self.callback)(action, self.batch)
+    }
+
+    fn print(&mut self, c: char) {
+        self.batch.push_char(c)
+    }
+
+    fn print_str(&mut self, s: &str) {
+        self.batch.push_str(s)
+    }
+}
+
+struct Performer<'a, S: ActionSink + 'a> {
+    sink: &'a mut S,
     state: &'a mut ParseState,
 }
 
 fn is_short_dcs(intermediates: &[u8], byte: u8) -> bool {
-    if intermediates == &[b'$'] && byte == b'q' {
+    if intermediates == [b'$'] && byte == b'q' {
         // DECRQSS
         true
+    } else if intermediates == [b'='] && byte == b's' {
+        // BSU/ESU synchronized update
+        true
+    } else if intermediates == [b'!'] && byte == b'|' {
+        // DECRPTUI, the tertiary device attributes
+        true
     } else {
         false
     }
 }
 
-impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
+impl<'a, S: ActionSink> VTActor for Performer<'a, S> {
     fn print(&mut self, c: char) {
-        (self.callback)(Action::Print(c));
+        self.sink.print(c);
+    }
+
+    fn print_str(&mut self, s: &str) {
+        self.sink.print_str(s);
     }
 
     fn execute_c0_or_c1(&mut self, byte: u8) {
         match FromPrimitive::from_u8(byte) {
-            Some(code) => (self.callback)(Action::Control(code)),
+            Some(code) => self.sink.perform(Action::Control(code)),
             None => error!(
                 "impossible C0/C1 control code {:?} 0x{:x} was dropped",
                 byte as char, byte
@@ -213,9 +343,16 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
         }
     }
 
+    fn parse_error(&mut self, bytes: &[u8], reason: vtparse::ParseErrorReason) {
+        self.sink.perform(Action::ParseError {
+            bytes: bytes.into(),
+            reason,
+        });
+    }
+
     fn apc_dispatch(&mut self, data: Vec<u8>) {
         if let Some(img) = super::KittyImage::parse_apc(&data) {
-            (self.callback)(Action::KittyImage(Box::new(img)))
+            self.sink.perform(Action::KittyImage(Box::new(img)))
         } else {
             log::trace!("Ignoring APC data: {:?}", String::from_utf8_lossy(&data));
         }
@@ -229,12 +366,26 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
         ignored_extra_intermediates: bool,
     ) {
         self.state.sixel.take();
+        self.state.sixel_stream.take();
         self.state.get_tcap.take();
         self.state.dcs.take();
+        self.state.tmux_passthrough.take();
+        self.state.tmux_passthrough_overflow = false;
         if byte == b'q' && intermediates.is_empty() && !ignored_extra_intermediates {
-            self.state.sixel.replace(SixelBuilder::new(params));
+            if self.state.sixel_streaming {
+                self.state.sixel_stream.replace(SixelStreamer::new(params));
+            } else {
+                self.state.sixel.replace(SixelBuilder::new(params));
+            }
         } else if byte == b'q' && intermediates == [b'+'] {
             self.state.get_tcap.replace(GetTcapBuilder::default());
+        } else if byte == b't'
+            && params.is_empty()
+            && intermediates.is_empty()
+            && !ignored_extra_intermediates
+        {
+            // Possibly `DCS tmux;`; we'll know once the data has arrived
+            self.state.tmux_passthrough.replace(vec![]);
         } else if !ignored_extra_intermediates && is_short_dcs(intermediates, byte) {
             self.state.dcs.replace(ShortDeviceControl {
                 params: params.to_vec(),
@@ -244,18 +395,22 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
             });
         } else {
             if byte == b'p' && params == [1000] {
-                // into tmux_cc mode
-                self.state.borrow_mut().tmux_state =
-                    Some(RefCell::new(crate::tmux_cc::Parser::new()));
+                // into tmux_cc mode.  The parser is lenient so that a
+                // line that it doesn't recognize, such as a notification
+                // from a newer tmux, doesn't end control mode
+                let mut tmux_parser = crate::tmux_cc::Parser::new();
+                tmux_parser.set_lenient(true);
+                self.state.borrow_mut().tmux_state = Some(RefCell::new(tmux_parser));
             }
-            (self.callback)(Action::DeviceControl(DeviceControlMode::Enter(Box::new(
-                EnterDeviceControlMode {
-                    byte,
-                    params: params.to_vec(),
-                    intermediates: intermediates.to_vec(),
-                    ignored_extra_intermediates,
-                },
-            ))));
+            self.sink
+                .perform(Action::DeviceControl(DeviceControlMode::Enter(Box::new(
+                    EnterDeviceControlMode {
+                        byte,
+                        params: params.to_vec(),
+                        intermediates: intermediates.to_vec(),
+                        ignored_extra_intermediates,
+                    },
+                ))));
         }
     }
 
@@ -264,17 +419,34 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
             dcs.data.push(data);
         } else if let Some(sixel) = self.state.sixel.as_mut() {
             sixel.push(data);
+        } else if let Some(stream) = self.state.sixel_stream.as_mut() {
+            for part in stream.push(data) {
+                self.sink.perform(Action::SixelStream(part));
+            }
         } else if let Some(tcap) = self.state.get_tcap.as_mut() {
             tcap.push(data);
+        } else if let Some(passthrough) = self.state.tmux_passthrough.as_mut() {
+            if self.state.tmux_passthrough_overflow {
+                // Discarded
+            } else if passthrough.len() < MAX_TMUX_PASSTHROUGH_SIZE {
+                passthrough.push(data);
+            } else {
+                log::error!(
+                    "Ignoring tmux passthrough data because it exceeds the max allowed {}",
+                    MAX_TMUX_PASSTHROUGH_SIZE
+                );
+                self.state.tmux_passthrough_overflow = true;
+                *passthrough = vec![];
+            }
         } else {
             if let Some(tmux_state) = &self.state.tmux_state {
                 let mut tmux_parser = tmux_state.borrow_mut();
                 match tmux_parser.advance_byte(data) {
                     Ok(optional_events) => {
                         if let Some(tmux_event) = optional_events {
-                            (self.callback)(Action::DeviceControl(DeviceControlMode::TmuxEvents(
-                                Box::new(vec![tmux_event]),
-                            )));
+                            self.sink.perform(Action::DeviceControl(
+                                DeviceControlMode::TmuxEvents(Box::new(vec![tmux_event])),
+                            ));
                         }
                     }
                     Err(_) => {
@@ -283,34 +455,71 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
                     }
                 }
             } else {
-                (self.callback)(Action::DeviceControl(DeviceControlMode::Data(data)));
+                self.sink
+                    .perform(Action::DeviceControl(DeviceControlMode::Data(data)));
             }
         }
     }
 
     fn dcs_unhook(&mut self) {
         if let Some(dcs) = self.state.dcs.take() {
-            (self.callback)(Action::DeviceControl(
+            self.sink.perform(Action::DeviceControl(
                 DeviceControlMode::ShortDeviceControl(Box::new(dcs)),
             ));
         } else if let Some(mut sixel) = self.state.sixel.take() {
             sixel.finish();
-            (self.callback)(Action::Sixel(Box::new(sixel.sixel)));
+            self.sink.perform(Action::Sixel(Box::new(sixel.sixel)));
+        } else if let Some(mut stream) = self.state.sixel_stream.take() {
+            for part in stream.finish() {
+                self.sink.perform(Action::SixelStream(part));
+            }
         } else if let Some(tcap) = self.state.get_tcap.take() {
-            (self.callback)(Action::XtGetTcap(tcap.finish()));
+            self.sink.perform(Action::XtGetTcap(tcap.finish()));
+        } else if let Some(data) = self.state.tmux_passthrough.take() {
+            if std::mem::take(&mut self.state.tmux_passthrough_overflow) {
+                return;
+            }
+            match data.strip_prefix(b"mux;") {
+                Some(wrapped) => {
+                    self.sink
+                        .perform(Action::DeviceControl(DeviceControlMode::TmuxPassthrough(
+                            Box::new(TmuxPassthrough::new(wrapped.to_vec())),
+                        )));
+                }
+                None => {
+                    // Some other use of the final byte `t`
+                    self.sink
+                        .perform(Action::DeviceControl(DeviceControlMode::Enter(Box::new(
+                            EnterDeviceControlMode {
+                                byte: b't',
+                                params: vec![],
+                                intermediates: vec![],
+                                ignored_extra_intermediates: false,
+                            },
+                        ))));
+                    for b in data {
+                        self.sink
+                            .perform(Action::DeviceControl(DeviceControlMode::Data(b)));
+                    }
+                    self.sink
+                        .perform(Action::DeviceControl(DeviceControlMode::Exit));
+                }
+            }
         } else {
-            (self.callback)(Action::DeviceControl(DeviceControlMode::Exit));
+            self.sink
+                .perform(Action::DeviceControl(DeviceControlMode::Exit));
         }
     }
 
     fn osc_dispatch(&mut self, osc: &[&[u8]]) {
         let osc = OperatingSystemCommand::parse(osc);
-        (self.callback)(Action::OperatingSystemCommand(Box::new(osc)));
+        self.sink
+            .perform(Action::OperatingSystemCommand(Box::new(osc)));
     }
 
     fn csi_dispatch(&mut self, params: &[CsiParam], parameters_truncated: bool, control: u8) {
         for action in CSI::parse(params, parameters_truncated, control as char) {
-            (self.callback)(Action::CSI(action));
+            self.sink.perform(Action::CSI(action));
         }
     }
 
@@ -324,7 +533,7 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
         // It doesn't appear to be possible for params.len() > 1 due to the way
         // that the state machine in vte functions.  As such, it also seems to
         // be impossible for ignored_extra_intermediates to be true too.
-        (self.callback)(Action::Esc(Esc::parse(
+        self.sink.perform(Action::Esc(Esc::parse(
             if intermediates.len() == 1 {
                 Some(intermediates[0])
             } else {
@@ -341,10 +550,12 @@ mod test {
     use crate::cell::{Intensity, Underline};
     use crate::color::ColorSpec;
     use crate::escape::csi::{
-        CharacterPath, DecPrivateMode, DecPrivateModeCode, Device, Mode, Sgr, Window, XtSmGraphics,
-        XtSmGraphicsItem, XtermKeyModifierResource,
+        CharacterPath, DecPrivateMode, DecPrivateModeCode, Device, KittyKeyEventType,
+        KittyKeyModifiers, KittyKeyReport, Mode, ModeSetting, Rectangle, RectangularArea, Sgr,
+        TerminalMode, TerminalModeCode, Window, XtSmGraphics, XtSmGraphicsItem,
+        XtermKeyModifierResource,
     };
-    use crate::escape::{EscCode, OneBased};
+    use crate::escape::{BatchedAction, ControlCode, EscCode, OneBased, ParseErrorReason};
     use k9::assert_equal as assert_eq;
     use std::io::Write;
 
@@ -545,6 +756,35 @@ mod test {
         assert_eq!(encode(&actions), "hello");
     }
 
+    #[test]
+    fn parse_into_batch() {
+        let mut p = Parser::new();
+        let mut batch = ActionBatch::new();
+        p.parse_into(b"hel", &mut batch);
+        // The text continues across calls
+        p.parse_into("lo\r\nw\u{f6}rld".as_bytes(), &mut batch);
+        assert_eq!(
+            batch.drain().collect::<Vec<_>>(),
+            vec![
+                BatchedAction::Print("hello"),
+                BatchedAction::Action(Action::Control(ControlCode::CarriageReturn)),
+                BatchedAction::Action(Action::Control(ControlCode::LineFeed)),
+                BatchedAction::Print("w\u{f6}rld"),
+            ]
+        );
+
+        // A character that is split across calls
+        p.parse_into(b"caf\xc3", &mut batch);
+        p.parse_into(b"\xa9 ok\x07", &mut batch);
+        assert_eq!(
+            batch.drain().collect::<Vec<_>>(),
+            vec![
+                BatchedAction::Print("caf\u{e9} ok"),
+                BatchedAction::Action(Action::Control(ControlCode::Bell)),
+            ]
+        );
+    }
+
     #[test]
     fn basic_bold() {
         let mut p = Parser::new();
@@ -754,6 +994,128 @@ mod test {
         );
     }
 
+    #[test]
+    fn tmux_control_mode_unknown_line() {
+        let mut p = Parser::new();
+        let mut events = vec![];
+        let mut collect = |action| match action {
+            Action::DeviceControl(DeviceControlMode::TmuxEvents(e)) => events.extend(*e),
+            Action::DeviceControl(DeviceControlMode::Enter(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        };
+        // The first line arrives along with the DCS, and the
+        // rest once the parser is in control mode
+        p.parse(b"\x1bP1000p%bogus 1\n", &mut collect);
+        p.parse(b"%future-notification @1\n%window-add @2\n", &mut collect);
+        assert!(p.is_tmux_control_mode());
+        assert_eq!(p.tmux_protocol_version(), None);
+        p.set_tmux_protocol_version(ProtocolVersion::new(3, 4, 0));
+        assert_eq!(
+            p.tmux_protocol_version(),
+            Some(ProtocolVersion::new(3, 4, 0))
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event::Unknown {
+                    line: "%bogus 1".to_string()
+                },
+                Event::Unknown {
+                    line: "%future-notification @1".to_string()
+                },
+                Event::WindowAdd {
+                    window: crate::tmux_cc::TmuxWindowId(2)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tmux_passthrough() {
+        let actions = round_trip_parse("\x1bPtmux;\x1b\x1b]2;hello\x07\x1b\x1b[1m\x1b\\");
+        assert_eq!(
+            actions,
+            vec![
+                Action::DeviceControl(DeviceControlMode::TmuxPassthrough(Box::new(
+                    TmuxPassthrough::new(b"\x1b]2;hello\x07\x1b[1m".to_vec())
+                ))),
+                Action::Esc(Esc::Code(EscCode::StringTerminator)),
+            ]
+        );
+        match &actions[0] {
+            Action::DeviceControl(DeviceControlMode::TmuxPassthrough(passthrough)) => {
+                assert_eq!(
+                    passthrough.actions(),
+                    vec![
+                        Action::OperatingSystemCommand(Box::new(
+                            OperatingSystemCommand::SetWindowTitle("hello".to_owned())
+                        )),
+                        Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
+                    ]
+                );
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Data beyond the limit is discarded, along with the rest
+        // of the sequence
+        let mut p = Parser::new();
+        let mut huge = b"\x1bPtmux;".to_vec();
+        huge.resize(MAX_TMUX_PASSTHROUGH_SIZE + 20, b'x');
+        huge.extend_from_slice(b"\x1b\\y");
+        assert_eq!(
+            p.parse_as_vec(&huge),
+            vec![
+                Action::Esc(Esc::Code(EscCode::StringTerminator)),
+                Action::Print('y'),
+            ]
+        );
+
+        // Some other DCS with the final byte `t` is left alone
+        assert_eq!(
+            round_trip_parse("\x1bPtoo\x1b\\"),
+            vec![
+                Action::DeviceControl(DeviceControlMode::Enter(Box::new(EnterDeviceControlMode {
+                    byte: b't',
+                    params: vec![],
+                    intermediates: vec![],
+                    ignored_extra_intermediates: false,
+                }))),
+                Action::DeviceControl(DeviceControlMode::Data(b'o')),
+                Action::DeviceControl(DeviceControlMode::Data(b'o')),
+                Action::DeviceControl(DeviceControlMode::Exit),
+                Action::Esc(Esc::Code(EscCode::StringTerminator)),
+            ]
+        );
+    }
+
+    #[test]
+    fn synchronized_update() {
+        let mut p = Parser::new();
+        let actions = p.parse_as_vec(b"\x1bP=1s\x1b\\\x1b[?2026h\x1bP=2s\x1b\\\x1b[?2026lx");
+        assert_eq!(
+            actions
+                .iter()
+                .map(Action::synchronized_update)
+                .collect::<Vec<_>>(),
+            vec![
+                Some(true),
+                None,
+                Some(true),
+                Some(false),
+                None,
+                Some(false),
+                None
+            ]
+        );
+        assert_eq!(actions[0].to_string(), "\x1bP=1s\x1b\\");
+
+        // Other short DCS sequences are not synchronized updates
+        let actions = p.parse_as_vec(b"\x1bP=3s\x1b\\\x1bP$qr\x1b\\");
+        assert_eq!(actions[0].synchronized_update(), None);
+        assert_eq!(actions[2].synchronized_update(), None);
+    }
+
     #[test]
     fn bidi_modes() {
         assert_eq!(
@@ -772,6 +1134,85 @@ mod test {
         );
     }
 
+    #[test]
+    fn kitty_key_report() {
+        assert_eq!(
+            round_trip_parse("\x1b[97;5u"),
+            vec![Action::CSI(CSI::KeyReport(Box::new(KittyKeyReport {
+                key_code: 97,
+                shifted_key: None,
+                base_layout_key: None,
+                modifiers: KittyKeyModifiers::CTRL,
+                event_type: KittyKeyEventType::Press,
+                text: String::new(),
+                control: 'u',
+            })))]
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[97:65:113;2:3;65u"),
+            vec![Action::CSI(CSI::KeyReport(Box::new(KittyKeyReport {
+                key_code: 97,
+                shifted_key: Some(65),
+                base_layout_key: Some(113),
+                modifiers: KittyKeyModifiers::SHIFT,
+                event_type: KittyKeyEventType::Release,
+                text: "A".to_string(),
+                control: 'u',
+            })))]
+        );
+        // Only the layout key, and text without modifiers
+        round_trip_parse("\x1b[97::113u");
+        round_trip_parse("\x1b[97;;97:98u");
+        parse_as("\x1b[97;1u", "\x1b[97u");
+
+        assert_eq!(
+            KittyKeyReport::parse(
+                &[
+                    CsiParam::Integer(1),
+                    CsiParam::P(b';'),
+                    CsiParam::Integer(3),
+                    CsiParam::P(b':'),
+                    CsiParam::Integer(2),
+                ],
+                'A'
+            ),
+            Some(KittyKeyReport {
+                key_code: 1,
+                shifted_key: None,
+                base_layout_key: None,
+                modifiers: KittyKeyModifiers::ALT,
+                event_type: KittyKeyEventType::Repeat,
+                text: String::new(),
+                control: 'A',
+            })
+        );
+        // An unknown event type and a cursor position report
+        for (params, control) in [
+            (
+                &[
+                    CsiParam::Integer(1),
+                    CsiParam::P(b';'),
+                    CsiParam::Integer(1),
+                    CsiParam::P(b':'),
+                    CsiParam::Integer(4),
+                ][..],
+                'A',
+            ),
+            (
+                &[
+                    CsiParam::Integer(1),
+                    CsiParam::P(b';'),
+                    CsiParam::Integer(5),
+                ][..],
+                'R',
+            ),
+        ]
+        .iter()
+        {
+            assert!(KittyKeyReport::parse(params, *control).is_none());
+        }
+    }
+
     #[test]
     fn xterm_key() {
         assert_eq!(
@@ -788,6 +1229,18 @@ mod test {
                 value: None,
             }))]
         );
+        assert_eq!(
+            round_trip_parse("\x1b[>4n"),
+            vec![Action::CSI(CSI::Mode(Mode::DisableXtermKeyMode(
+                XtermKeyModifierResource::OtherKeys
+            )))]
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[?4m"),
+            vec![Action::CSI(CSI::Mode(Mode::QueryXtermKeyMode(
+                XtermKeyModifierResource::OtherKeys
+            )))]
+        );
     }
 
     #[test]
@@ -815,6 +1268,58 @@ mod test {
         );
     }
 
+    #[test]
+    fn rectangular_area() {
+        let area = Rectangle {
+            top: OneBased::new(2),
+            left: OneBased::new(3),
+            bottom: OneBased::new(4),
+            right: OneBased::new(5),
+        };
+        let rect = |area| vec![Action::CSI(CSI::RectangularArea(Box::new(area)))];
+
+        assert_eq!(
+            round_trip_parse("\x1b[2;3;4;5;1;6;7;1$v"),
+            rect(RectangularArea::Copy {
+                area,
+                source_page: OneBased::new(1),
+                top: OneBased::new(6),
+                left: OneBased::new(7),
+                destination_page: OneBased::new(1),
+            })
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[69;2;3;4;5$x"),
+            rect(RectangularArea::Fill {
+                character: 'E',
+                area
+            })
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[2;3;4;5$z"),
+            rect(RectangularArea::Erase(area))
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[2;3;4;5${"),
+            rect(RectangularArea::SelectiveErase(area))
+        );
+
+        // The bottom right corner defaults to that of the page
+        assert_eq!(
+            parse_as("\x1b[$z", "\x1b[1;1;;$z"),
+            rect(RectangularArea::Erase(Rectangle {
+                top: OneBased::new(1),
+                left: OneBased::new(1),
+                bottom: OneBased::new(u32::MAX),
+                right: OneBased::new(u32::MAX),
+            }))
+        );
+        parse_as("\x1b[;;6;7;;8$v", "\x1b[1;1;6;7;1;8;1;1$v");
+
+        // A control character can't be used to fill
+        parse_as("\x1b[10;2;3;4;5$x", "\x1b[10;2;3;4;5$x");
+    }
+
     #[test]
     fn dec_private_modes() {
         assert_eq!(
@@ -981,6 +1486,70 @@ mod test {
     }
     */
 
+    #[test]
+    fn parse_errors() {
+        let input = b"\x1b[1\x1b[2mok\x1b[1?2m";
+        assert_eq!(
+            Parser::new().parse_as_vec(input),
+            vec![
+                Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Half))),
+                Action::Print('o'),
+                Action::Print('k'),
+            ]
+        );
+
+        let mut p = Parser::new();
+        p.set_diagnostics(true);
+        assert_eq!(
+            p.parse_as_vec(input),
+            vec![
+                Action::ParseError {
+                    bytes: b"\x1b[1"[..].into(),
+                    reason: ParseErrorReason::Interrupted,
+                },
+                Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Half))),
+                Action::Print('o'),
+                Action::Print('k'),
+                Action::ParseError {
+                    bytes: b"\x1b[1?2m"[..].into(),
+                    reason: ParseErrorReason::InvalidCsi,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn decrqm() {
+        assert_eq!(
+            round_trip_parse("\x1b[?2026$p\x1b[4$p"),
+            vec![
+                Action::CSI(CSI::Mode(Mode::QueryDecPrivateMode(DecPrivateMode::Code(
+                    DecPrivateModeCode::SynchronizedOutput
+                )))),
+                Action::CSI(CSI::Mode(Mode::QueryMode(TerminalMode::Code(
+                    TerminalModeCode::Insert
+                )))),
+            ]
+        );
+        assert_eq!(
+            round_trip_parse("\x1b[?2026;2$y\x1b[4;1$y\x1b[?9999;0$y"),
+            vec![
+                Action::CSI(CSI::Mode(Mode::ReportDecPrivateMode(
+                    DecPrivateMode::Code(DecPrivateModeCode::SynchronizedOutput),
+                    ModeSetting::Reset
+                ))),
+                Action::CSI(CSI::Mode(Mode::ReportMode(
+                    TerminalMode::Code(TerminalModeCode::Insert),
+                    ModeSetting::Set
+                ))),
+                Action::CSI(CSI::Mode(Mode::ReportDecPrivateMode(
+                    DecPrivateMode::Unspecified(9999),
+                    ModeSetting::NotRecognized
+                ))),
+            ]
+        );
+    }
+
     #[test]
     fn decset() {
         assert_eq!(
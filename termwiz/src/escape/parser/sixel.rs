@@ -1,5 +1,6 @@
-use crate::color::RgbColor;
-use crate::escape::{Sixel, SixelData};
+use crate::color::{RgbColor, SrgbaTuple};
+use crate::escape::{Sixel, SixelData, SixelStream};
+use std::collections::HashMap;
 
 const MAX_PARAMS: usize = 5;
 const MAX_SIXEL_SIZE: usize = 100_000_000;
@@ -9,6 +10,10 @@ pub struct SixelBuilder {
     params: [i64; MAX_PARAMS],
     param_no: usize,
     current_command: u8,
+    /// Whether space for the whole of the data is reserved once the
+    /// raster attributes give its size.  This is not wanted when the
+    /// data is consumed as it arrives, as by `SixelDecoder`.
+    reserve: bool,
 }
 
 impl SixelBuilder {
@@ -30,6 +35,7 @@ impl SixelBuilder {
             sixel: Sixel {
                 pan,
                 pad: 1,
+                raster_aspect_ratio: false,
                 pixel_width: None,
                 pixel_height: None,
                 background_is_transparent,
@@ -39,6 +45,7 @@ impl SixelBuilder {
             param_no: 0,
             params: [-1; MAX_PARAMS],
             current_command: 0,
+            reserve: true,
         }
     }
 
@@ -145,6 +152,7 @@ impl SixelBuilder {
 
                 self.sixel.pan = pan;
                 self.sixel.pad = pad;
+                self.sixel.raster_aspect_ratio = true;
 
                 if self.param_no >= 3 {
                     self.sixel.pixel_width.replace(pixel_width as u32);
@@ -170,7 +178,9 @@ impl SixelBuilder {
                         self.sixel.data.clear();
                         return;
                     }
-                    self.sixel.data.reserve(size);
+                    if self.reserve {
+                        self.sixel.data.reserve(size);
+                    }
                 }
             }
             _ => {}
@@ -185,6 +195,342 @@ impl SixelBuilder {
     }
 }
 
+/// Passes on sixel data as `SixelStream` actions as it is parsed,
+/// rather than collecting the whole of it into a `Sixel`
+pub struct SixelStreamer {
+    builder: SixelBuilder,
+    entered: bool,
+}
+
+impl SixelStreamer {
+    pub fn new(params: &[i64]) -> Self {
+        let mut builder = SixelBuilder::new(params);
+        builder.reserve = false;
+        Self {
+            builder,
+            entered: false,
+        }
+    }
+
+    /// Parses `data`, returning the parts of the image that it completed
+    pub fn push(&mut self, data: u8) -> Vec<SixelStream> {
+        self.builder.push(data);
+        self.take()
+    }
+
+    /// Finishes the image, returning the parts of it that remained
+    pub fn finish(&mut self) -> Vec<SixelStream> {
+        self.builder.finish();
+        let mut stream = self.take();
+        if !self.entered {
+            stream.push(self.enter());
+        }
+        stream.push(SixelStream::Exit);
+        stream
+    }
+
+    fn enter(&mut self) -> SixelStream {
+        self.entered = true;
+        let sixel = &self.builder.sixel;
+        SixelStream::Enter(Box::new(Sixel {
+            data: vec![],
+            ..sixel.clone()
+        }))
+    }
+
+    fn take(&mut self) -> Vec<SixelStream> {
+        if self.builder.sixel.data.is_empty() {
+            return vec![];
+        }
+        let data = std::mem::take(&mut self.builder.sixel.data);
+        let mut stream = Vec::with_capacity(data.len() + 1);
+        if !self.entered {
+            // The raster attributes have to precede the data, so
+            // the attributes of the image are now known
+            stream.push(self.enter());
+        }
+        stream.extend(data.into_iter().map(SixelStream::Data));
+        stream
+    }
+}
+
+/// The limits on the size of the images decoded by `SixelDecoder`.
+/// Anything beyond them is clipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SixelLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+}
+
+impl Default for SixelLimits {
+    fn default() -> Self {
+        Self {
+            max_width: 10_000,
+            max_height: 10_000,
+        }
+    }
+}
+
+/// A band of pixels decoded from a single line of sixels.
+/// Bands are six pixels tall, multiplied by the vertical scale of
+/// the aspect ratio, unless clipped by `SixelLimits`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SixelBand {
+    /// The row of the image at which the band starts
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// RGBA pixels, row by row.  The pixels that were not drawn
+    /// by the sixel data are fully transparent.
+    pub pixels: Vec<u8>,
+}
+
+impl SixelBand {
+    /// Returns the RGBA value of the pixel at x, y within the band
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let idx = ((y * self.width + x) * 4) as usize;
+        let mut pixel = [0u8; 4];
+        pixel.copy_from_slice(&self.pixels[idx..idx + 4]);
+        pixel
+    }
+}
+
+/// Decodes sixel data incrementally, producing a band of pixels as
+/// each line of sixels is completed, so that an image can be shown
+/// as it arrives.  Only the band being decoded is held in memory,
+/// and it is no wider than `SixelLimits::max_width`.
+pub struct SixelDecoder {
+    builder: SixelBuilder,
+    limits: SixelLimits,
+    color_map: HashMap<u16, RgbColor>,
+    foreground: RgbColor,
+    /// The horizontal and vertical scale of the pixels, which is
+    /// fixed by the aspect ratio once the first sixel is decoded
+    scale: Option<(u32, u32)>,
+    x: u32,
+    /// The top row of the band being decoded
+    y: u32,
+    /// The pixels of the band being decoded, column by column, so that
+    /// it can grow as wider lines arrive
+    columns: Vec<u8>,
+}
+
+impl SixelDecoder {
+    /// Creates a decoder for the sixel data that follows a DCS with
+    /// `params`
+    pub fn new(params: &[i64], limits: SixelLimits) -> Self {
+        let mut builder = SixelBuilder::new(params);
+        builder.reserve = false;
+        Self {
+            builder,
+            limits,
+            color_map: HashMap::new(),
+            foreground: RgbColor::new_8bpc(0, 0xff, 0),
+            scale: None,
+            x: 0,
+            y: 0,
+            columns: vec![],
+        }
+    }
+
+    /// Creates a decoder for the data of `sixel`, which is passed
+    /// to `push_data`, applying its aspect ratio
+    pub fn for_sixel(sixel: &Sixel, limits: SixelLimits) -> Self {
+        let mut decoder = Self::new(&[], limits);
+        let attributes = &mut decoder.builder.sixel;
+        attributes.pan = sixel.pan;
+        attributes.pad = sixel.pad;
+        attributes.raster_aspect_ratio = sixel.raster_aspect_ratio;
+        attributes.pixel_width = sixel.pixel_width;
+        attributes.pixel_height = sixel.pixel_height;
+        attributes.background_is_transparent = sixel.background_is_transparent;
+        attributes.horizontal_grid_size = sixel.horizontal_grid_size;
+        decoder
+    }
+
+    /// Sets the color registers; the sixel data may redefine them
+    pub fn with_color_map(mut self, color_map: HashMap<u16, RgbColor>) -> Self {
+        self.color_map = color_map;
+        self
+    }
+
+    pub fn color_map(&self) -> &HashMap<u16, RgbColor> {
+        &self.color_map
+    }
+
+    pub fn into_color_map(self) -> HashMap<u16, RgbColor> {
+        self.color_map
+    }
+
+    /// Returns the attributes of the image, such as its aspect ratio
+    /// and any size given by its raster attributes.  Its data is
+    /// always empty.
+    pub fn sixel(&self) -> &Sixel {
+        &self.builder.sixel
+    }
+
+    /// Decodes `data`, returning the bands that it completed
+    pub fn push(&mut self, data: &[u8]) -> Vec<SixelBand> {
+        let mut bands = vec![];
+        for &b in data {
+            self.builder.push(b);
+            self.drain_builder(&mut bands);
+        }
+        bands
+    }
+
+    /// Finishes decoding, returning the bands that remained,
+    /// including the final band if it has any pixels
+    pub fn finish(&mut self) -> Vec<SixelBand> {
+        let mut bands = vec![];
+        self.builder.finish();
+        self.drain_builder(&mut bands);
+        bands.extend(self.take_band());
+        bands
+    }
+
+    fn drain_builder(&mut self, bands: &mut Vec<SixelBand>) {
+        if self.builder.sixel.data.is_empty() {
+            return;
+        }
+        let data = std::mem::take(&mut self.builder.sixel.data);
+        for d in &data {
+            bands.extend(self.push_data(d));
+        }
+    }
+
+    /// Decodes a single item of sixel data, returning the band that
+    /// it completed, if any
+    pub fn push_data(&mut self, data: &SixelData) -> Option<SixelBand> {
+        match data {
+            SixelData::Data(value) => {
+                self.emit(*value, 1);
+                None
+            }
+            SixelData::Repeat { repeat_count, data } => {
+                self.emit(*data, *repeat_count);
+                None
+            }
+            SixelData::CarriageReturn => {
+                self.x = 0;
+                None
+            }
+            SixelData::NewLine => {
+                let band = self.take_band();
+                let (_, y_scale) = self.scale();
+                self.x = 0;
+                self.y = self.y.saturating_add(y_scale.saturating_mul(6));
+                band
+            }
+            SixelData::DefineColorMapRGB { color_number, rgb } => {
+                self.color_map.insert(*color_number, *rgb);
+                None
+            }
+            SixelData::DefineColorMapHSL {
+                color_number,
+                hue_angle,
+                lightness,
+                saturation,
+            } => {
+                // Sixel's hue angles are: blue=0, red=120, green=240,
+                // whereas Hsl has red=0, green=120, blue=240.
+                // Looking at red, we need to rotate left by 120 to
+                // go from sixel red to standard hsl red.
+                // Negative values wrap around the circle.
+                // https://github.com/wez/wezterm/issues/775
+                let angle = (*hue_angle as f64) - 120.0;
+                let angle = if angle < 0. { 360.0 + angle } else { angle };
+                let rgb = SrgbaTuple::from_hsla(
+                    angle,
+                    *saturation as f64 / 100.,
+                    *lightness as f64 / 100.,
+                    1.,
+                );
+                self.color_map.insert(*color_number, rgb.into());
+                None
+            }
+            SixelData::SelectColorMapEntry(n) => {
+                self.foreground = self.color_map.get(n).cloned().unwrap_or_else(|| {
+                    log::error!("sixel selected noexistent colormap entry {}", n);
+                    RgbColor::new_8bpc(255, 255, 255)
+                });
+                None
+            }
+        }
+    }
+
+    fn scale(&mut self) -> (u32, u32) {
+        let sixel = &self.builder.sixel;
+        *self.scale.get_or_insert_with(|| sixel.aspect_scale())
+    }
+
+    /// The number of rows of the current band that are within the limits
+    fn band_height(&mut self) -> u32 {
+        let (_, y_scale) = self.scale();
+        self.limits
+            .max_height
+            .saturating_sub(self.y)
+            .min(y_scale.saturating_mul(6))
+    }
+
+    /// Draws `value` into the next `count` columns of the band
+    fn emit(&mut self, value: u8, count: u32) {
+        let (x_scale, y_scale) = self.scale();
+        let start = self.x;
+        self.x = self.x.saturating_add(count.saturating_mul(x_scale));
+        let end = self.x.min(self.limits.max_width);
+        let height = self.band_height() as usize;
+        if start >= end || height == 0 {
+            return;
+        }
+
+        let needed = end as usize * height * 4;
+        if self.columns.len() < needed {
+            self.columns.resize(needed, 0);
+        }
+        let (red, green, blue) = self.foreground.to_tuple_rgb8();
+        for x in start..end {
+            for bitno in 0..6 {
+                if value & (1 << bitno) != 0 {
+                    let top = (bitno * y_scale as usize).min(height);
+                    let bottom = ((bitno + 1) * y_scale as usize).min(height);
+                    for y in top..bottom {
+                        let idx = (x as usize * height + y) * 4;
+                        self.columns[idx..idx + 4].copy_from_slice(&[red, green, blue, 0xff]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Takes the band being decoded, converting it from columns to rows
+    fn take_band(&mut self) -> Option<SixelBand> {
+        let columns = std::mem::take(&mut self.columns);
+        let height = self.band_height();
+        if height == 0 {
+            return None;
+        }
+        let width = (columns.len() / (height as usize * 4)) as u32;
+        if width == 0 {
+            return None;
+        }
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+        for x in 0..width as usize {
+            for y in 0..height as usize {
+                let src = (x * height as usize + y) * 4;
+                let dest = (y * width as usize + x) * 4;
+                pixels[dest..dest + 4].copy_from_slice(&columns[src..src + 4]);
+            }
+        }
+        Some(SixelBand {
+            y: self.y,
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -201,6 +547,7 @@ mod test {
                 Action::Sixel(Box::new(Sixel {
                     pan: 2,
                     pad: 1,
+                    raster_aspect_ratio: false,
                     pixel_width: None,
                     pixel_height: None,
                     background_is_transparent: false,
@@ -240,6 +587,7 @@ mod test {
                 Action::Sixel(Box::new(Sixel {
                     pan: 2,
                     pad: 1,
+                    raster_aspect_ratio: false,
                     pixel_width: None,
                     pixel_height: None,
                     background_is_transparent: false,
@@ -301,4 +649,161 @@ mod test {
             actions
         );
     }
+
+    #[test]
+    fn streaming() {
+        let mut p = Parser::new();
+        p.set_sixel_streaming(true);
+        let text = b"\x1bP;1q\"1;1;2;6#1;2;100;0;0#1~-~\x1b\\";
+        // The parts of the image are passed on as the data arrives
+        let (head, tail) = text.split_at(27);
+        let mut actions = p.parse_as_vec(head);
+        assert_eq!(
+            actions,
+            vec![
+                Action::SixelStream(SixelStream::Enter(Box::new(Sixel {
+                    pan: 1,
+                    pad: 1,
+                    raster_aspect_ratio: true,
+                    pixel_width: Some(2),
+                    pixel_height: Some(6),
+                    background_is_transparent: true,
+                    horizontal_grid_size: None,
+                    data: vec![],
+                }))),
+                Action::SixelStream(SixelStream::Data(SixelData::DefineColorMapRGB {
+                    color_number: 1,
+                    rgb: RgbColor::new_8bpc(255, 0, 0),
+                })),
+            ]
+        );
+        actions.extend(p.parse_as_vec(tail));
+        assert_eq!(
+            actions[2..],
+            [
+                Action::SixelStream(SixelStream::Data(SixelData::SelectColorMapEntry(1))),
+                Action::SixelStream(SixelStream::Data(SixelData::Data(63))),
+                Action::SixelStream(SixelStream::Data(SixelData::NewLine)),
+                Action::SixelStream(SixelStream::Data(SixelData::Data(63))),
+                Action::SixelStream(SixelStream::Exit),
+                Action::Esc(Esc::Code(EscCode::StringTerminator)),
+            ]
+        );
+
+        let encoded: String = actions.iter().map(|action| action.to_string()).collect();
+        assert_eq!(encoded, "\x1bP;1q\"1;1;2;6#1;2;100;0;0#1~-~\x1b\\");
+
+        // An image without data is still entered and exited
+        assert_eq!(
+            p.parse_as_vec(b"\x1bPq\x1b\\")[1..],
+            [
+                Action::SixelStream(SixelStream::Exit),
+                Action::Esc(Esc::Code(EscCode::StringTerminator)),
+            ]
+        );
+    }
+
+    #[test]
+    fn decoder() {
+        let red = [255, 0, 0, 255];
+        let mut decoder = SixelDecoder::new(&[], SixelLimits::default());
+        // The data arrives in pieces that split the commands
+        assert_eq!(decoder.push(b"\"1;1;3;8#1;2;10"), vec![]);
+        assert_eq!(decoder.push(b"0;0;0#1~!2"), vec![]);
+        let bands = decoder.push(b"@-#1A");
+        assert_eq!(decoder.sixel().pixel_width, Some(3));
+        assert_eq!(bands.len(), 1);
+        let band = &bands[0];
+        assert_eq!((band.y, band.width, band.height), (0, 3, 6));
+        for y in 0..6 {
+            assert_eq!(band.pixel(0, y), red);
+        }
+        assert_eq!(band.pixel(1, 0), red);
+        assert_eq!(band.pixel(1, 1), [0; 4]);
+        assert_eq!(band.pixel(2, 0), red);
+
+        let bands = decoder.finish();
+        assert_eq!(bands.len(), 1);
+        assert_eq!((bands[0].y, bands[0].width, bands[0].height), (6, 1, 6));
+        assert_eq!(bands[0].pixel(0, 1), red);
+        assert_eq!(
+            decoder.color_map().get(&1),
+            Some(&RgbColor::new_8bpc(255, 0, 0))
+        );
+    }
+
+    #[test]
+    fn decoder_limits() {
+        let mut decoder = SixelDecoder::new(
+            &[],
+            SixelLimits {
+                max_width: 4,
+                max_height: 8,
+            },
+        );
+        // A huge repeat count is clipped rather than drawn
+        let mut bands = decoder.push(b"!4000000000~-~-~");
+        bands.extend(decoder.finish());
+        assert_eq!(
+            bands
+                .iter()
+                .map(|band| (band.y, band.width, band.height))
+                .collect::<Vec<_>>(),
+            vec![(0, 4, 6), (6, 1, 2)]
+        );
+    }
+
+    #[test]
+    fn aspect_ratio() {
+        let blue = [0, 0, 255, 255];
+
+        // The aspect ratio of the DCS isn't applied
+        let mut p = Parser::new();
+        let actions = p.parse_as_vec(b"\x1bP0q@\x1b\\");
+        match &actions[0] {
+            Action::Sixel(sixel) => {
+                assert_eq!(sixel.pan, 2);
+                assert_eq!(sixel.aspect_scale(), (1, 1));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut p = Parser::new();
+        let actions = p.parse_as_vec(b"\x1bPq\"2;1;2;1#1;2;0;0;100#1~\x1b\\");
+        let sixel = match &actions[0] {
+            Action::Sixel(sixel) => sixel,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert!(sixel.raster_aspect_ratio);
+        assert_eq!(sixel.aspect_scale(), (1, 2));
+        assert_eq!(sixel.dimensions(), (2, 2));
+        assert_eq!(
+            format!("{}", actions[0]),
+            "\x1bP;0q\"2;1;2;1#1;2;0;0;100#1~"
+        );
+
+        let mut decoder = SixelDecoder::for_sixel(sixel, SixelLimits::default());
+        let band = sixel
+            .data
+            .iter()
+            .find_map(|d| decoder.push_data(d))
+            .or_else(|| decoder.finish().pop())
+            .unwrap();
+        assert_eq!((band.width, band.height), (1, 12));
+        assert_eq!(band.pixel(0, 11), blue);
+
+        // A wide aspect ratio widens the pixels
+        let mut decoder = SixelDecoder::new(&[], SixelLimits::default());
+        let mut bands = decoder.push(b"\"1;2#1;2;0;0;100#1!2@-@");
+        bands.extend(decoder.finish());
+        assert_eq!(
+            bands
+                .iter()
+                .map(|band| (band.y, band.width, band.height))
+                .collect::<Vec<_>>(),
+            vec![(0, 4, 6), (6, 2, 6)]
+        );
+        assert_eq!(bands[0].pixel(3, 0), blue);
+        assert_eq!(bands[0].pixel(3, 1), [0; 4]);
+    }
 }
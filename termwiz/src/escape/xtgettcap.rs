@@ -0,0 +1,148 @@
+//! XTGETTCAP, with which an application requests the values of
+//! terminfo capabilities from the terminal, and the replies to it.
+//!
+//! The request, `DCS + q Pt ST`, holds the hex encoded names of the
+//! capabilities, separated by `;`; the parser decodes it into
+//! `Action::XtGetTcap`.  Each of the names is answered separately:
+//! `DCS 1 + r Pt ST`, where `Pt` is the hex encoded name followed by
+//! `=` and the hex encoded value, or `DCS 0 + r Pt ST`, where `Pt` is
+//! just the hex encoded name, when the capability isn't known.
+//! As with xterm, a boolean capability is reported without a value.
+use std::fmt::{Display, Error as FmtError, Formatter};
+use terminfo::{Database, Value};
+
+lazy_static::lazy_static! {
+    static ref WEZTERM: Database = {
+        let data = include_bytes!("../../data/wezterm");
+        Database::from_buffer(&data[..]).unwrap()
+    };
+}
+
+/// Returns the terminfo database that describes wezterm
+pub fn wezterm_terminfo() -> &'static Database {
+    &WEZTERM
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CapabilityValue {
+    Boolean,
+    Number(i32),
+    String(Vec<u8>),
+}
+
+impl From<&Value> for CapabilityValue {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::True => Self::Boolean,
+            Value::Number(n) => Self::Number(*n),
+            Value::String(s) => Self::String(s.clone()),
+        }
+    }
+}
+
+/// The reply to the request for a single capability
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilityReport {
+    /// The name as it was requested, which may be either
+    /// the short or the long name of the capability
+    pub name: String,
+    /// `None` if the capability isn't known
+    pub value: Option<CapabilityValue>,
+}
+
+impl CapabilityReport {
+    pub fn new(name: &str, value: Option<CapabilityValue>) -> Self {
+        Self {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    /// Looks up the capability `name` in `db`
+    pub fn lookup(db: &Database, name: &str) -> Self {
+        Self::new(name, db.raw(name).map(CapabilityValue::from))
+    }
+
+    /// Looks up the capability `name` in wezterm's terminfo
+    pub fn wezterm(name: &str) -> Self {
+        Self::lookup(wezterm_terminfo(), name)
+    }
+}
+
+impl Display for CapabilityReport {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let name = hex::encode_upper(&self.name);
+        match &self.value {
+            None => write!(f, "\x1bP0+r{}", name)?,
+            Some(CapabilityValue::Boolean) => write!(f, "\x1bP1+r{}", name)?,
+            Some(CapabilityValue::Number(n)) => {
+                write!(f, "\x1bP1+r{}={}", name, hex::encode_upper(n.to_string()))?
+            }
+            Some(CapabilityValue::String(s)) => {
+                write!(f, "\x1bP1+r{}={}", name, hex::encode_upper(s))?
+            }
+        }
+        write!(f, "\x1b\\")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escape::parser::Parser;
+    use crate::escape::Action;
+    use k9::assert_equal as assert_eq;
+
+    fn request(s: &str) -> Vec<String> {
+        let mut p = Parser::new();
+        match p.parse_as_vec(s.as_bytes()).into_iter().next() {
+            Some(Action::XtGetTcap(names)) => names,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_requests() {
+        assert_eq!(
+            request("\x1bP+q544e;636F6C6f7273;6b63757531\x1b\\"),
+            vec!["TN".to_string(), "colors".to_string(), "kcuu1".to_string()]
+        );
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(
+            CapabilityReport::wezterm("colors").value,
+            Some(CapabilityValue::Number(256))
+        );
+        assert_eq!(
+            CapabilityReport::wezterm("Co"),
+            CapabilityReport::new("Co", Some(CapabilityValue::Number(256)))
+        );
+        assert_eq!(
+            CapabilityReport::wezterm("key_up").value,
+            Some(CapabilityValue::String(b"\x1bOA".to_vec()))
+        );
+        assert_eq!(CapabilityReport::wezterm("bogus").value, None);
+    }
+
+    #[test]
+    fn reports() {
+        assert_eq!(
+            CapabilityReport::wezterm("colors").to_string(),
+            "\x1bP1+r636F6C6F7273=323536\x1b\\"
+        );
+        assert_eq!(
+            CapabilityReport::wezterm("kcuu1").to_string(),
+            "\x1bP1+r6B63757531=1B4F41\x1b\\"
+        );
+        assert_eq!(
+            CapabilityReport::wezterm("am").to_string(),
+            "\x1bP1+r616D\x1b\\"
+        );
+        assert_eq!(
+            CapabilityReport::wezterm("bogus").to_string(),
+            "\x1bP0+r626F677573\x1b\\"
+        );
+    }
+}
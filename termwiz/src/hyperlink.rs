@@ -6,13 +6,15 @@
 //! as we recognize linkable input text during print() processing.
 use crate::{ensure, format_err, Result};
 use fancy_regex::{Captures, Regex};
+use regex::RegexSet;
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 use std::fmt::{Display, Error as FmtError, Formatter};
 use std::hash::{Hash, Hasher};
 use std::ops::Range;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
 use wezterm_dynamic::{FromDynamic, FromDynamicOptions, ToDynamic, Value};
 
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
@@ -43,6 +45,18 @@ impl Hyperlink {
         &self.params
     }
 
+    /// Returns the `id` parameter of the link, if it has a non-empty one.
+    /// Cells that are written by separate OSC 8 sequences are part of
+    /// the same link when those sequences have the same id and uri,
+    /// which allows an application to continue a link after moving
+    /// the cursor, for example when it wraps a long link across lines.
+    pub fn id(&self) -> Option<&str> {
+        self.params
+            .get("id")
+            .map(String::as_str)
+            .filter(|id| !id.is_empty())
+    }
+
     pub fn new<S: Into<String>>(uri: S) -> Self {
         Self {
             uri: uri.into(),
@@ -82,27 +96,31 @@ impl Hyperlink {
         }
     }
 
+    /// Parses the arguments of OSC 8.
+    ///
+    /// Links don't nest: opening a link while another is active replaces
+    /// it, and a sequence with an empty uri closes whichever link is
+    /// active, whatever its params.  A link that is never closed remains
+    /// active until the next link is opened or the terminal is reset.
     pub fn parse(osc: &[&[u8]]) -> Result<Option<Hyperlink>> {
-        ensure!(osc.len() == 3, "wrong param count");
-        if osc[1].is_empty() && osc[2].is_empty() {
+        ensure!(osc.len() >= 3, "wrong param count");
+        // The uri may itself contain semicolons
+        let uri = String::from_utf8(osc[2..].join(&b';'))?;
+        if uri.is_empty() {
             // Clearing current hyperlink
-            Ok(None)
-        } else {
-            let param_str = String::from_utf8(osc[1].to_vec())?;
-            let uri = String::from_utf8(osc[2].to_vec())?;
-
-            let mut params = HashMap::new();
-            if !param_str.is_empty() {
-                for pair in param_str.split(':') {
-                    let mut iter = pair.splitn(2, '=');
-                    let key = iter.next().ok_or_else(|| format_err!("bad params"))?;
-                    let value = iter.next().ok_or_else(|| format_err!("bad params"))?;
-                    params.insert(key.to_owned(), value.to_owned());
-                }
-            }
+            return Ok(None);
+        }
 
-            Ok(Some(Hyperlink::new_with_params(uri, params)))
+        let param_str = String::from_utf8(osc[1].to_vec())?;
+        let mut params = HashMap::new();
+        for pair in param_str.split(':').filter(|pair| !pair.is_empty()) {
+            let mut iter = pair.splitn(2, '=');
+            let key = iter.next().ok_or_else(|| format_err!("bad params"))?;
+            let value = iter.next().ok_or_else(|| format_err!("bad params"))?;
+            params.insert(key.to_owned(), value.to_owned());
         }
+
+        Ok(Some(Hyperlink::new_with_params(uri, params)))
     }
 }
 
@@ -283,8 +301,12 @@ impl Rule {
     /// Given a line of text from the terminal screen, and a set of
     /// rules, return the set of RuleMatches.
     pub fn match_hyperlinks(line: &str, rules: &[Rule]) -> Vec<RuleMatch> {
+        Self::match_rules(line, rules.iter())
+    }
+
+    fn match_rules<'t>(line: &'t str, rules: impl Iterator<Item = &'t Rule>) -> Vec<RuleMatch> {
         let mut matches = Vec::new();
-        for rule in rules.iter() {
+        for rule in rules {
             for capture_result in rule.regex.captures_iter(line) {
                 if let Ok(captures) = capture_result {
                     let m = Match { rule, captures };
@@ -311,6 +333,139 @@ impl Rule {
             })
             .collect()
     }
+
+    fn same_as(&self, other: &Rule) -> bool {
+        self.regex.as_str() == other.regex.as_str()
+            && self.format == other.format
+            && self.highlight == other.highlight
+    }
+}
+
+/// The number of compiled rule sets retained by `RuleSet::cached`
+const RULE_SET_CACHE_SIZE: usize = 4;
+
+lazy_static::lazy_static! {
+    static ref RULE_SETS: Mutex<Vec<Arc<RuleSet>>> = Mutex::new(vec![]);
+}
+
+static NEXT_RULE_SET_ID: AtomicU16 = AtomicU16::new(1);
+
+/// A set of rules that have been compiled so that they can be matched
+/// against a line together.
+/// Most lines don't contain anything that matches any of the rules, so
+/// rather than running each of the rules over the line, a `RegexSet` of
+/// all of the rules is used to find out which, if any, of them match,
+/// and then only those rules are used to produce the hyperlinks.
+/// Rules that use features that `RegexSet` doesn't support, such as
+/// look-around, are always run.
+#[derive(Debug)]
+pub struct RuleSet {
+    id: u16,
+    rules: Vec<Rule>,
+    set: Option<RegexSet>,
+    /// The index in `rules` of each of the patterns in `set`
+    set_rules: Vec<usize>,
+    /// The indices in `rules` of the rules that are not in `set`
+    unfiltered: Vec<usize>,
+}
+
+impl RuleSet {
+    pub fn new(rules: &[Rule]) -> Self {
+        let mut patterns = vec![];
+        let mut set_rules = vec![];
+        let mut unfiltered = vec![];
+        for (idx, rule) in rules.iter().enumerate() {
+            // Only use the set for patterns that regex understands
+            // in the same way as fancy_regex
+            if regex::Regex::new(rule.regex.as_str()).is_ok() {
+                patterns.push(rule.regex.as_str());
+                set_rules.push(idx);
+            } else {
+                unfiltered.push(idx);
+            }
+        }
+
+        let set = if patterns.is_empty() {
+            None
+        } else {
+            match RegexSet::new(&patterns) {
+                Ok(set) => Some(set),
+                Err(_) => {
+                    // The set may be too large to compile; just run
+                    // all of the rules individually
+                    unfiltered = (0..rules.len()).collect();
+                    set_rules.clear();
+                    None
+                }
+            }
+        };
+
+        let mut id = NEXT_RULE_SET_ID.fetch_add(1, Ordering::Relaxed);
+        if id == 0 {
+            // 0 is reserved to mean that a line hasn't been scanned
+            id = NEXT_RULE_SET_ID.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Self {
+            id,
+            rules: rules.to_vec(),
+            set,
+            set_rules,
+            unfiltered,
+        }
+    }
+
+    /// Returns the compiled set for `rules`, compiling it only if
+    /// those rules are not among the most recently used sets.
+    /// The returned set has the same `id` for as long as it remains
+    /// cached.
+    pub fn cached(rules: &[Rule]) -> Arc<Self> {
+        let mut sets = RULE_SETS.lock().unwrap();
+        if let Some(idx) = sets.iter().position(|set| set.is_same_rules(rules)) {
+            let set = sets.remove(idx);
+            sets.push(Arc::clone(&set));
+            return set;
+        }
+        let set = Arc::new(Self::new(rules));
+        if sets.len() >= RULE_SET_CACHE_SIZE {
+            sets.remove(0);
+        }
+        sets.push(Arc::clone(&set));
+        set
+    }
+
+    fn is_same_rules(&self, rules: &[Rule]) -> bool {
+        self.rules.len() == rules.len() && self.rules.iter().zip(rules).all(|(a, b)| a.same_as(b))
+    }
+
+    /// Identifies this set of rules; it is never zero
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns the same matches as `Rule::match_hyperlinks` would
+    /// for the rules in this set
+    pub fn match_hyperlinks(&self, line: &str) -> Vec<RuleMatch> {
+        let mut candidates = self.unfiltered.clone();
+        if let Some(set) = &self.set {
+            candidates.extend(set.matches(line).iter().map(|idx| self.set_rules[idx]));
+        }
+        if candidates.is_empty() {
+            return vec![];
+        }
+        // Preserve the order of the rules, as it determines which of
+        // several equally long matches is preferred
+        candidates.sort_unstable();
+        Rule::match_rules(line, candidates.into_iter().map(|idx| &self.rules[idx]))
+    }
 }
 
 #[cfg(test)]
@@ -347,4 +502,41 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn rule_set() {
+        let rules = vec![
+            Rule::new(r"\b\w+://(?:[\w.-]+)\.[a-z]{2,15}\S*\b", "$0").unwrap(),
+            Rule::new(r"\b\w+@[\w-]+(\.[\w-]+)+\b", "mailto:$0").unwrap(),
+            // Look-behind isn't supported by RegexSet
+            Rule::new(r"(?<=#)\d+\b", "https://example.com/issues/$0").unwrap(),
+        ];
+        let set = RuleSet::new(&rules);
+        assert_eq!(set.unfiltered, vec![2]);
+
+        for line in [
+            "",
+            "nothing to see here",
+            "  http://example.com",
+            "  foo@example.com woot@example.com",
+            "fixed #123 see http://example.com/123",
+        ] {
+            assert_eq!(
+                set.match_hyperlinks(line),
+                Rule::match_hyperlinks(line, &rules),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn rule_set_cache() {
+        let rules = vec![Rule::new(r"\bcached-\d+\b", "$0").unwrap()];
+        let set = RuleSet::cached(&rules);
+        assert_ne!(set.id(), 0);
+        assert!(Arc::ptr_eq(&set, &RuleSet::cached(&rules.clone())));
+
+        let other = vec![Rule::new(r"\bcached-\d+\b", "https://$0").unwrap()];
+        assert_ne!(RuleSet::cached(&other).id(), set.id());
+    }
 }
@@ -2,7 +2,9 @@
 //! input received from a terminal.
 use crate::bail;
 use crate::error::Result;
-use crate::escape::csi::{KittyKeyboardFlags, MouseReport};
+use crate::escape::csi::{
+    CsiParam, KittyKeyEventType, KittyKeyModifiers, KittyKeyReport, KittyKeyboardFlags, MouseReport,
+};
 use crate::escape::parser::Parser;
 use crate::escape::{Action, CSI};
 use crate::keymap::{Found, KeyMap};
@@ -258,6 +260,9 @@ impl KeyCode {
         modes: KeyCodeEncodeModes,
         is_down: bool,
     ) -> Result<String> {
+        if let KeyboardEncoding::Kitty(flags) = modes.encoding {
+            return self.encode_kitty(mods, modes, flags, is_down);
+        }
         if !is_down {
             // We only want down events
             return Ok(String::new());
@@ -580,6 +585,103 @@ impl KeyCode {
 
         Ok(buf)
     }
+
+    /// Encodes the key using the kitty keyboard protocol, with the
+    /// progressive enhancements that the application requested in `flags`.
+    /// <https://sw.kovidgoyal.net/kitty/keyboard-protocol/>
+    fn encode_kitty(
+        &self,
+        mods: Modifiers,
+        modes: KeyCodeEncodeModes,
+        flags: KittyKeyboardFlags,
+        is_down: bool,
+    ) -> Result<String> {
+        use KeyCode::*;
+
+        let report_events = flags.contains(KittyKeyboardFlags::REPORT_EVENT_TYPES);
+        let all_keys = flags.contains(KittyKeyboardFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES);
+        if !is_down && !report_events {
+            return Ok(String::new());
+        }
+
+        let key = match self.normalize_shift_to_upper_case(mods) {
+            Char('\x7f') => Delete,
+            Char('\x08') => Backspace,
+            Char('\r') => Enter,
+            Char('\t') => Tab,
+            Char('\x1b') => Escape,
+            key => key,
+        };
+        let mut modifiers = KittyKeyModifiers::from_modifiers(mods);
+
+        if is_down && !all_keys {
+            let legacy = match key {
+                // Text is sent as-is, as are these keys when unmodified
+                Char(c) if !c.is_control() => (modifiers - KittyKeyModifiers::SHIFT).is_empty(),
+                Enter | Tab | Backspace => modifiers.is_empty(),
+                _ => false,
+            } || !flags.contains(KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES);
+            if legacy {
+                let modes = KeyCodeEncodeModes {
+                    encoding: KeyboardEncoding::Xterm,
+                    ..modes
+                };
+                return key.encode(mods, modes, is_down);
+            }
+            // The modifiers and lock keys are only reported by themselves
+            // when all keys are reported
+            if key.is_modifier() || matches!(key, CapsLock | NumLock | ScrollLock) {
+                return Ok(String::new());
+            }
+        }
+
+        let mut report = KittyKeyReport {
+            key_code: 0,
+            shifted_key: None,
+            base_layout_key: None,
+            modifiers: KittyKeyModifiers::NONE,
+            event_type: if is_down {
+                KittyKeyEventType::Press
+            } else {
+                KittyKeyEventType::Release
+            },
+            text: String::new(),
+            control: 'u',
+        };
+
+        match key {
+            Char(c) if !c.is_control() => {
+                // The key code is the unshifted key; the US layout is
+                // assumed for the letters
+                let unshifted = c.to_ascii_lowercase();
+                if unshifted != c {
+                    modifiers |= KittyKeyModifiers::SHIFT;
+                    if flags.contains(KittyKeyboardFlags::REPORT_ALTERNATE_KEYS) {
+                        report.shifted_key = Some(c as u32);
+                    }
+                }
+                report.key_code = unshifted as u32;
+                if is_down
+                    && flags.contains(KittyKeyboardFlags::REPORT_ASSOCIATED_TEXT)
+                    && !modifiers.intersects(
+                        KittyKeyModifiers::CTRL | KittyKeyModifiers::ALT | KittyKeyModifiers::SUPER,
+                    )
+                {
+                    report.text.push(c);
+                }
+            }
+            _ => match kitty_functional_key(key) {
+                Some((key_code, control)) => {
+                    report.key_code = key_code;
+                    report.control = control;
+                }
+                None => return Ok(String::new()),
+            },
+        }
+        report.modifiers = modifiers;
+
+        Ok(format!("{}{}", CSI, report))
+    }
 }
 
 /// characters that when masked for CTRL could be an ascii control character
@@ -592,6 +694,156 @@ fn is_ambiguous_ascii_ctrl(c: char) -> bool {
     }
 }
 
+/// The numbers by which the kitty keyboard protocol identifies the
+/// functional keys, along with the final character of the sequences
+/// that report them.  Where a key has several encodings, the first is
+/// the one that is sent.  F13 and above are handled separately.
+const KITTY_FUNCTIONAL_KEYS: &[(KeyCode, u32, char)] = &[
+    (KeyCode::Escape, 27, 'u'),
+    (KeyCode::Enter, 13, 'u'),
+    (KeyCode::Tab, 9, 'u'),
+    (KeyCode::Backspace, 127, 'u'),
+    (KeyCode::Insert, 2, '~'),
+    (KeyCode::Delete, 3, '~'),
+    (KeyCode::PageUp, 5, '~'),
+    (KeyCode::PageDown, 6, '~'),
+    (KeyCode::UpArrow, 1, 'A'),
+    (KeyCode::DownArrow, 1, 'B'),
+    (KeyCode::RightArrow, 1, 'C'),
+    (KeyCode::LeftArrow, 1, 'D'),
+    (KeyCode::KeyPadBegin, 1, 'E'),
+    (KeyCode::End, 1, 'F'),
+    (KeyCode::Home, 1, 'H'),
+    (KeyCode::Home, 7, '~'),
+    (KeyCode::End, 8, '~'),
+    (KeyCode::Function(1), 11, '~'),
+    (KeyCode::Function(1), 1, 'P'),
+    (KeyCode::Function(2), 12, '~'),
+    (KeyCode::Function(2), 1, 'Q'),
+    (KeyCode::Function(3), 13, '~'),
+    (KeyCode::Function(4), 14, '~'),
+    (KeyCode::Function(4), 1, 'S'),
+    (KeyCode::Function(5), 15, '~'),
+    (KeyCode::Function(6), 17, '~'),
+    (KeyCode::Function(7), 18, '~'),
+    (KeyCode::Function(8), 19, '~'),
+    (KeyCode::Function(9), 20, '~'),
+    (KeyCode::Function(10), 21, '~'),
+    (KeyCode::Function(11), 23, '~'),
+    (KeyCode::Function(12), 24, '~'),
+    (KeyCode::CapsLock, 57358, 'u'),
+    (KeyCode::ScrollLock, 57359, 'u'),
+    (KeyCode::NumLock, 57360, 'u'),
+    (KeyCode::PrintScreen, 57361, 'u'),
+    (KeyCode::Pause, 57362, 'u'),
+    (KeyCode::Applications, 57363, 'u'),
+    (KeyCode::Numpad0, 57399, 'u'),
+    (KeyCode::Numpad1, 57400, 'u'),
+    (KeyCode::Numpad2, 57401, 'u'),
+    (KeyCode::Numpad3, 57402, 'u'),
+    (KeyCode::Numpad4, 57403, 'u'),
+    (KeyCode::Numpad5, 57404, 'u'),
+    (KeyCode::Numpad6, 57405, 'u'),
+    (KeyCode::Numpad7, 57406, 'u'),
+    (KeyCode::Numpad8, 57407, 'u'),
+    (KeyCode::Numpad9, 57408, 'u'),
+    (KeyCode::Decimal, 57409, 'u'),
+    (KeyCode::Divide, 57410, 'u'),
+    (KeyCode::Multiply, 57411, 'u'),
+    (KeyCode::Subtract, 57412, 'u'),
+    (KeyCode::Add, 57413, 'u'),
+    (KeyCode::Enter, 57414, 'u'),
+    (KeyCode::Separator, 57416, 'u'),
+    (KeyCode::LeftArrow, 57417, 'u'),
+    (KeyCode::RightArrow, 57418, 'u'),
+    (KeyCode::UpArrow, 57419, 'u'),
+    (KeyCode::DownArrow, 57420, 'u'),
+    (KeyCode::KeyPadPageUp, 57421, 'u'),
+    (KeyCode::KeyPadPageDown, 57422, 'u'),
+    (KeyCode::KeyPadHome, 57423, 'u'),
+    (KeyCode::KeyPadEnd, 57424, 'u'),
+    (KeyCode::Insert, 57425, 'u'),
+    (KeyCode::Delete, 57426, 'u'),
+    (KeyCode::KeyPadBegin, 57427, 'u'),
+    (KeyCode::MediaPlayPause, 57430, 'u'),
+    (KeyCode::MediaStop, 57432, 'u'),
+    (KeyCode::MediaNextTrack, 57435, 'u'),
+    (KeyCode::MediaPrevTrack, 57436, 'u'),
+    (KeyCode::VolumeDown, 57438, 'u'),
+    (KeyCode::VolumeUp, 57439, 'u'),
+    (KeyCode::VolumeMute, 57440, 'u'),
+    (KeyCode::LeftShift, 57441, 'u'),
+    (KeyCode::LeftControl, 57442, 'u'),
+    (KeyCode::LeftAlt, 57443, 'u'),
+    (KeyCode::LeftWindows, 57444, 'u'),
+    (KeyCode::Hyper, 57445, 'u'),
+    (KeyCode::Meta, 57446, 'u'),
+    (KeyCode::RightShift, 57447, 'u'),
+    (KeyCode::RightControl, 57448, 'u'),
+    (KeyCode::RightAlt, 57449, 'u'),
+    (KeyCode::RightWindows, 57450, 'u'),
+    (KeyCode::Shift, 57441, 'u'),
+    (KeyCode::Control, 57442, 'u'),
+    (KeyCode::Alt, 57443, 'u'),
+    (KeyCode::Super, 57444, 'u'),
+];
+
+/// The key codes of F13 and above
+const KITTY_F13: u32 = 57376;
+const KITTY_F35: u32 = 57398;
+
+/// The range of key codes in the unicode private use area that
+/// the kitty keyboard protocol uses for the functional keys
+const KITTY_FUNCTIONAL_RANGE: std::ops::RangeInclusive<u32> = 57344..=63743;
+
+/// Returns the key code and final character with which the kitty
+/// keyboard protocol reports `key`
+fn kitty_functional_key(key: KeyCode) -> Option<(u32, char)> {
+    match key {
+        KeyCode::Function(n) if (13..=35).contains(&n) => Some((KITTY_F13 + n as u32 - 13, 'u')),
+        _ => KITTY_FUNCTIONAL_KEYS
+            .iter()
+            .find(|(k, _, _)| *k == key)
+            .map(|(_, code, control)| (*code, *control)),
+    }
+}
+
+/// Returns the key that the kitty keyboard protocol reports with
+/// `key_code` and `control`.  Key codes outside of the range used for
+/// the functional keys are characters, even those for the keys such
+/// as Enter, which is consistent with the `CSI u` sequences in the keymap.
+fn kitty_key(key_code: u32, control: char) -> Option<KeyCode> {
+    match (key_code, control) {
+        (KITTY_F13..=KITTY_F35, 'u') => Some(KeyCode::Function((key_code - KITTY_F13) as u8 + 13)),
+        (code, 'u') if !KITTY_FUNCTIONAL_RANGE.contains(&code) => {
+            char::from_u32(code).map(KeyCode::Char)
+        }
+        _ => KITTY_FUNCTIONAL_KEYS
+            .iter()
+            .find(|(_, code, c)| *code == key_code && *c == control)
+            .map(|(key, _, _)| *key),
+    }
+}
+
+/// Returns the key event reported by `report`, or None for the
+/// release of a key, which has no equivalent in `InputEvent`
+fn kitty_key_event(report: &KittyKeyReport) -> Option<KeyEvent> {
+    if report.event_type == KittyKeyEventType::Release {
+        return None;
+    }
+    let modifiers = report.modifiers.to_modifiers();
+    let key = match (
+        report.shifted_key,
+        kitty_key(report.key_code, report.control)?,
+    ) {
+        (Some(shifted), KeyCode::Char(_)) if modifiers.contains(Modifiers::SHIFT) => {
+            KeyCode::Char(char::from_u32(shifted)?)
+        }
+        (_, key) => key,
+    };
+    Some(KeyEvent { key, modifiers })
+}
+
 fn is_ascii(c: char) -> bool {
     (c as u32) < 0x80
 }
@@ -1355,9 +1607,30 @@ impl InputParser {
                         // parameters out from things like mouse reports.  The keymap tree doesn't
                         // know how to grok this.
                         let mut parser = Parser::new();
-                        if let Some((Action::CSI(CSI::Mouse(mouse)), len)) =
-                            parser.parse_first(self.buf.as_slice())
-                        {
+                        let parsed = parser.parse_first(self.buf.as_slice());
+                        if let Some((Action::CSI(csi), len)) = &parsed {
+                            // Key events from the kitty keyboard protocol.  Those
+                            // that use the other final characters are only taken
+                            // from here when they have subfields, as the keymap
+                            // knows the rest.
+                            let report = match csi {
+                                CSI::KeyReport(report) => Some((**report).clone()),
+                                CSI::Unspecified(unspec)
+                                    if unspec.params.contains(&CsiParam::P(b':')) =>
+                                {
+                                    KittyKeyReport::parse(&unspec.params, unspec.control)
+                                }
+                                _ => None,
+                            };
+                            if let Some(report) = report {
+                                self.buf.advance(*len);
+                                if let Some(event) = kitty_key_event(&report) {
+                                    callback(InputEvent::Key(event));
+                                }
+                                continue;
+                            }
+                        }
+                        if let Some((Action::CSI(CSI::Mouse(mouse)), len)) = parsed {
                             self.buf.advance(len);
 
                             match mouse {
@@ -1933,4 +2206,161 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn parse_kitty_keys() {
+        let mut p = InputParser::new();
+        let inputs = p.parse_as_vec(
+            b"\x1b[97;5u\x1b[97:65;2u\x1b[97;1:3u\x1b[57399;1:2u\x1b[1;3:2A\x1b[57376;5u\x1b[13;1:3~",
+            NO_MORE,
+        );
+        assert_eq!(
+            vec![
+                InputEvent::Key(KeyEvent {
+                    modifiers: Modifiers::CTRL,
+                    key: KeyCode::Char('a'),
+                }),
+                InputEvent::Key(KeyEvent {
+                    modifiers: Modifiers::SHIFT,
+                    key: KeyCode::Char('A'),
+                }),
+                // The release of 'a' is not reported
+                InputEvent::Key(KeyEvent {
+                    modifiers: Modifiers::NONE,
+                    key: KeyCode::Numpad0,
+                }),
+                InputEvent::Key(KeyEvent {
+                    modifiers: Modifiers::ALT,
+                    key: KeyCode::UpArrow,
+                }),
+                InputEvent::Key(KeyEvent {
+                    modifiers: Modifiers::CTRL,
+                    key: KeyCode::Function(13),
+                }),
+            ],
+            inputs
+        );
+    }
+
+    #[test]
+    fn encode_kitty() {
+        let mode = |flags| KeyCodeEncodeModes {
+            encoding: KeyboardEncoding::Kitty(flags),
+            newline_mode: false,
+            application_cursor_keys: false,
+            modify_other_keys: None,
+        };
+        let disambiguate = mode(KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES);
+        let all = mode(
+            KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES
+                | KittyKeyboardFlags::REPORT_EVENT_TYPES
+                | KittyKeyboardFlags::REPORT_ALTERNATE_KEYS
+                | KittyKeyboardFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                | KittyKeyboardFlags::REPORT_ASSOCIATED_TEXT,
+        );
+
+        let cases = [
+            (KeyCode::Char('a'), Modifiers::NONE, disambiguate, true, "a"),
+            (
+                KeyCode::Char('a'),
+                Modifiers::SHIFT,
+                disambiguate,
+                true,
+                "A",
+            ),
+            (
+                KeyCode::Char('a'),
+                Modifiers::CTRL,
+                disambiguate,
+                true,
+                "\x1b[97;5u",
+            ),
+            (KeyCode::Char('a'), Modifiers::CTRL, disambiguate, false, ""),
+            (
+                KeyCode::Escape,
+                Modifiers::NONE,
+                disambiguate,
+                true,
+                "\x1b[27u",
+            ),
+            (KeyCode::Enter, Modifiers::NONE, disambiguate, true, "\r"),
+            (
+                KeyCode::Enter,
+                Modifiers::ALT,
+                disambiguate,
+                true,
+                "\x1b[13;3u",
+            ),
+            (
+                KeyCode::UpArrow,
+                Modifiers::CTRL,
+                disambiguate,
+                true,
+                "\x1b[1;5A",
+            ),
+            (
+                KeyCode::Function(3),
+                Modifiers::NONE,
+                disambiguate,
+                true,
+                "\x1b[13~",
+            ),
+            (
+                KeyCode::Function(14),
+                Modifiers::NONE,
+                disambiguate,
+                true,
+                "\x1b[57377u",
+            ),
+            (KeyCode::LeftShift, Modifiers::SHIFT, disambiguate, true, ""),
+            (
+                KeyCode::Char('a'),
+                Modifiers::SHIFT,
+                all,
+                true,
+                "\x1b[97:65;2;65u",
+            ),
+            (
+                KeyCode::Char('a'),
+                Modifiers::NONE,
+                all,
+                false,
+                "\x1b[97;1:3u",
+            ),
+            (KeyCode::Enter, Modifiers::NONE, all, true, "\x1b[13u"),
+            (KeyCode::UpArrow, Modifiers::NONE, all, true, "\x1b[A"),
+            (KeyCode::UpArrow, Modifiers::NONE, all, false, "\x1b[1;1:3A"),
+            (
+                KeyCode::LeftShift,
+                Modifiers::SHIFT,
+                all,
+                true,
+                "\x1b[57441;2u",
+            ),
+        ];
+        for (key, mods, mode, is_down, expected) in cases.iter() {
+            assert_eq!(
+                key.encode(*mods, *mode, *is_down).unwrap(),
+                *expected,
+                "{:?} {:?} {:?} {}",
+                key,
+                mods,
+                mode.encoding,
+                is_down
+            );
+        }
+
+        // What is encoded parses back to the same key
+        let mut p = InputParser::new();
+        let encoded = KeyCode::Numpad7
+            .encode(Modifiers::CTRL | Modifiers::SHIFT, all, true)
+            .unwrap();
+        assert_eq!(
+            p.parse_as_vec(encoded.as_bytes(), NO_MORE),
+            vec![InputEvent::Key(KeyEvent {
+                modifiers: Modifiers::CTRL | Modifiers::SHIFT,
+                key: KeyCode::Numpad7,
+            })]
+        );
+    }
 }
@@ -23,9 +23,9 @@ impl<'a> CellRef<'a> {
         }
     }
 
-    pub fn str(&self) -> &str {
+    pub fn str(&self) -> &'a str {
         match self {
-            Self::CellRef { cell, .. } => cell.str(),
+            Self::CellRef { cell, .. } => (*cell).str(),
             Self::ClusterRef { text, .. } => text,
         }
     }
@@ -37,9 +37,9 @@ impl<'a> CellRef<'a> {
         }
     }
 
-    pub fn attrs(&self) -> &CellAttributes {
+    pub fn attrs(&self) -> &'a CellAttributes {
         match self {
-            Self::CellRef { cell, .. } => cell.attrs(),
+            Self::CellRef { cell, .. } => (*cell).attrs(),
             Self::ClusterRef { attrs, .. } => attrs,
         }
     }
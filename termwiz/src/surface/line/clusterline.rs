@@ -6,6 +6,7 @@ use fixedbitset::FixedBitSet;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::convert::TryInto;
 use std::num::NonZeroU8;
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
 
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
@@ -95,7 +96,9 @@ impl ClusteredLine {
         cells
     }
 
-    pub fn from_cell_vec<'a>(hint: usize, iter: impl Iterator<Item = CellRef<'a>>) -> Self {
+    /// Returns `None` if the text of the cells would not be segmented
+    /// back into the same cells; see `can_append`
+    pub fn from_cell_vec<'a>(hint: usize, iter: impl Iterator<Item = CellRef<'a>>) -> Option<Self> {
         let mut last_cluster: Option<Cluster> = None;
         let mut is_double_wide = FixedBitSet::with_capacity(hint);
         let mut text = String::new();
@@ -113,6 +116,9 @@ impl ClusteredLine {
                 is_double_wide.set(cell.cell_index(), true);
             }
 
+            if !is_grapheme_boundary(&text, cell.str()) {
+                return None;
+            }
             text.push_str(cell.str());
 
             last_cluster = match last_cluster.take() {
@@ -138,7 +144,7 @@ impl ClusteredLine {
             clusters.push(cluster);
         }
 
-        Self {
+        Some(Self {
             text,
             is_double_wide: if any_double {
                 Some(Box::new(is_double_wide))
@@ -148,7 +154,7 @@ impl ClusteredLine {
             clusters,
             len: len.try_into().unwrap(),
             last_cell_width,
-        }
+        })
     }
 
     pub fn len(&self) -> usize {
@@ -175,6 +181,15 @@ impl ClusteredLine {
         }
     }
 
+    /// Returns true if a cell holding `text` can be appended.
+    /// The cells are recovered by segmenting the text of the line
+    /// into graphemes, so a cell whose text would join the grapheme
+    /// of the cell before it, such as one that was split from an
+    /// emoji ZWJ sequence, cannot be represented here.
+    pub fn can_append(&self, text: &str) -> bool {
+        is_grapheme_boundary(&self.text, text)
+    }
+
     pub fn append_grapheme(&mut self, text: &str, cell_width: usize, attrs: CellAttributes) {
         let new_cluster = match self.clusters.last() {
             Some(cluster) => cluster.attrs != attrs,
@@ -306,6 +321,27 @@ impl ClusteredLine {
     }
 }
 
+/// Returns true if there is a grapheme boundary between `text`
+/// and `next`
+fn is_grapheme_boundary(text: &str, next: &str) -> bool {
+    match (text.as_bytes().last(), next.as_bytes().first()) {
+        (None, _) | (_, None) => return true,
+        // Only CR LF joins two ASCII characters
+        (Some(a), Some(b)) if a.is_ascii() && b.is_ascii() => return !(*a == b'\r' && *b == b'\n'),
+        _ => {}
+    }
+    let mut cursor = GraphemeCursor::new(text.len(), text.len() + next.len(), true);
+    loop {
+        match cursor.is_boundary(next, text.len()) {
+            Ok(boundary) => return boundary,
+            Err(GraphemeIncomplete::PreContext(end)) => {
+                cursor.provide_context(&text[..end], 0);
+            }
+            Err(_) => return false,
+        }
+    }
+}
+
 pub(crate) struct ClusterLineCellIter<'a> {
     graphemes: Graphemes<'a>,
     clusters: std::slice::Iter<'a, Cluster>,
@@ -1,6 +1,6 @@
 use crate::cell::{Cell, CellAttributes, SemanticType, UnicodeVersion};
 use crate::cellcluster::CellCluster;
-use crate::hyperlink::Rule;
+use crate::hyperlink::{Rule, RuleSet};
 use crate::surface::line::cellref::CellRef;
 use crate::surface::line::clusterline::ClusteredLine;
 use crate::surface::line::linebits::LineBits;
@@ -38,6 +38,10 @@ pub struct Line {
     zones: Vec<ZoneRange>,
     seqno: SequenceNo,
     bits: LineBits,
+    /// The id of the RuleSet that was used to scan for implicit
+    /// hyperlinks, if SCANNED_IMPLICIT_HYPERLINKS is set
+    #[cfg_attr(feature = "use_serde", serde(skip))]
+    hyperlink_rules: u16,
     #[cfg_attr(feature = "use_serde", serde(skip))]
     appdata: Mutex<Option<Weak<dyn Any + Send + Sync>>>,
 }
@@ -49,6 +53,7 @@ impl Clone for Line {
             zones: self.zones.clone(),
             seqno: self.seqno,
             bits: self.bits,
+            hyperlink_rules: self.hyperlink_rules,
             appdata: Mutex::new(self.appdata.lock().unwrap().clone()),
         }
     }
@@ -70,6 +75,7 @@ impl Line {
             cells: CellStorage::V(VecStorage::new(cells)),
             seqno,
             zones: vec![],
+            hyperlink_rules: 0,
             appdata: Mutex::new(None),
         }
     }
@@ -81,6 +87,7 @@ impl Line {
             cells: CellStorage::V(VecStorage::new(cells)),
             seqno,
             zones: vec![],
+            hyperlink_rules: 0,
             appdata: Mutex::new(None),
         }
     }
@@ -95,6 +102,7 @@ impl Line {
             cells: CellStorage::C(ClusteredLine::new()),
             seqno,
             zones: vec![],
+            hyperlink_rules: 0,
             appdata: Mutex::new(None),
         }
     }
@@ -125,6 +133,7 @@ impl Line {
             cells: CellStorage::V(VecStorage::new(cells)),
             seqno,
             zones: vec![],
+            hyperlink_rules: 0,
             appdata: Mutex::new(None),
         }
     }
@@ -151,6 +160,7 @@ impl Line {
             bits: LineBits::NONE,
             seqno,
             zones: vec![],
+            hyperlink_rules: 0,
             appdata: Mutex::new(None),
         }
     }
@@ -399,6 +409,20 @@ impl Line {
         )
     }
 
+    /// Returns the flags that describe the line as a whole, such as
+    /// its double width/height and bidi properties, in a form that can
+    /// be restored via `set_line_flags`.  This is intended for use by
+    /// serializers that don't go through serde.
+    pub fn line_flags(&self) -> u16 {
+        self.bits.bits()
+    }
+
+    /// Restore the flags previously returned by `line_flags`.
+    /// This should be called after the cells have been assigned.
+    pub fn set_line_flags(&mut self, flags: u16) {
+        self.bits = LineBits::from_bits_truncate(flags);
+    }
+
     fn invalidate_zones(&mut self) {
         self.zones.clear();
     }
@@ -504,17 +528,30 @@ impl Line {
     /// rules.  Matching sequences are considered to be implicit hyperlinks
     /// and will have a hyperlink attribute associated with them.
     /// This function will only make changes if the line has been invalidated
-    /// since the last time this function was called.
-    /// This function does not remember the values of the `rules` slice, so it
-    /// is the responsibility of the caller to call `invalidate_implicit_hyperlinks`
-    /// if it wishes to call this function with different `rules`.
+    /// since the last time this function was called, or if it was last
+    /// called with different `rules`.
     pub fn scan_and_create_hyperlinks(&mut self, rules: &[Rule]) {
-        if (self.bits & LineBits::SCANNED_IMPLICIT_HYPERLINKS)
-            == LineBits::SCANNED_IMPLICIT_HYPERLINKS
-        {
+        self.scan_and_create_hyperlinks_with_set(&RuleSet::cached(rules));
+    }
+
+    /// Returns true if the line has been scanned for implicit hyperlinks
+    /// using `rules` and has not changed since
+    fn is_scanned_with(&self, rules: &RuleSet) -> bool {
+        self.bits.contains(LineBits::SCANNED_IMPLICIT_HYPERLINKS)
+            && self.hyperlink_rules == rules.id()
+    }
+
+    /// Like `scan_and_create_hyperlinks`, but using rules that have
+    /// already been compiled into a `RuleSet`.
+    pub fn scan_and_create_hyperlinks_with_set(&mut self, rules: &RuleSet) {
+        if self.is_scanned_with(rules) {
             // Has not changed since last time we scanned
             return;
         }
+        if self.hyperlink_rules != rules.id() {
+            // Remove any links produced by some other set of rules
+            self.invalidate_implicit_hyperlinks(self.current_seqno());
+        }
 
         // FIXME: let's build a string and a byte-to-cell map here, and
         // use this as an opportunity to rebuild HAS_HYPERLINK, skip matching
@@ -522,9 +559,10 @@ impl Line {
         // text with zero-width cells.
         self.bits |= LineBits::SCANNED_IMPLICIT_HYPERLINKS;
         self.bits &= !LineBits::HAS_IMPLICIT_HYPERLINKS;
+        self.hyperlink_rules = rules.id();
         let line = self.as_str();
 
-        let matches = Rule::match_hyperlinks(&line, rules);
+        let matches = rules.match_hyperlinks(&line);
         if matches.is_empty() {
             return;
         }
@@ -540,11 +578,9 @@ impl Line {
     /// physical lines and look for sequences that match the provided
     /// rules.  Matching sequences are considered to be implicit hyperlinks
     /// and will have a hyperlink attribute associated with them.
-    /// This function will only make changes if the line has been invalidated
-    /// since the last time this function was called.
-    /// This function does not remember the values of the `rules` slice, so it
-    /// is the responsibility of the caller to call `invalidate_implicit_hyperlinks`
-    /// if it wishes to call this function with different `rules`.
+    /// This function will only make changes if one of the lines has been
+    /// invalidated since the last time this function was called, or if
+    /// it was last called with different `rules`.
     ///
     /// This function will call Line::clear_appdata on lines where
     /// hyperlinks are adjusted.
@@ -552,15 +588,19 @@ impl Line {
         if rules.is_empty() || logical_line.is_empty() {
             return;
         }
+        Self::apply_hyperlink_rule_set(&RuleSet::cached(rules), logical_line);
+    }
 
-        let mut need_scan = false;
-        for line in logical_line.iter() {
-            if !line.bits.contains(LineBits::SCANNED_IMPLICIT_HYPERLINKS) {
-                need_scan = true;
-                break;
-            }
+    /// Like `apply_hyperlink_rules`, but using rules that have already
+    /// been compiled into a `RuleSet`.  When applying the rules to a
+    /// number of lines, compiling them once up front via
+    /// `RuleSet::cached` avoids looking them up for each line.
+    pub fn apply_hyperlink_rule_set(rules: &RuleSet, logical_line: &mut [&mut Line]) {
+        if rules.is_empty() || logical_line.is_empty() {
+            return;
         }
-        if !need_scan {
+
+        if logical_line.iter().all(|line| line.is_scanned_with(rules)) {
             return;
         }
 
@@ -572,11 +612,14 @@ impl Line {
         let seq = logical.current_seqno();
 
         logical.invalidate_implicit_hyperlinks(seq);
-        logical.scan_and_create_hyperlinks(rules);
+        logical.scan_and_create_hyperlinks_with_set(rules);
 
         if !logical.has_hyperlink() {
             for line in logical_line.iter_mut() {
+                // Drop any links left over from an earlier scan
+                line.invalidate_implicit_hyperlinks(seq);
                 line.bits.set(LineBits::SCANNED_IMPLICIT_HYPERLINKS, true);
+                line.hyperlink_rules = rules.id();
                 line.clear_appdata();
             }
             return;
@@ -631,6 +674,7 @@ impl Line {
             cells: CellStorage::V(VecStorage::new(cells)),
             seqno,
             zones: vec![],
+            hyperlink_rules: self.hyperlink_rules,
             appdata: Mutex::new(None),
         }
     }
@@ -715,6 +759,7 @@ impl Line {
             cells: CellStorage::V(VecStorage::new(cells)),
             seqno: self.current_seqno(),
             zones: vec![],
+            hyperlink_rules: 0,
             appdata: Mutex::new(None),
         }
     }
@@ -756,7 +801,7 @@ impl Line {
                 // their intended cell content
                 cl.append_grapheme(" ", 1, CellAttributes::blank());
             }
-            if idx == cl.len() {
+            if idx == cl.len() && cl.can_append(text) {
                 cl.append_grapheme(text, width, attr);
                 self.invalidate_implicit_hyperlinks(seqno);
                 self.invalidate_zones();
@@ -809,7 +854,7 @@ impl Line {
                 // their intended cell content
                 cl.append_grapheme(" ", 1, CellAttributes::blank());
             }
-            if idx == cl.len() {
+            if idx == cl.len() && cl.can_append(cell.str()) {
                 cl.append(cell);
                 return;
             }
@@ -1044,7 +1089,12 @@ impl Line {
     /// for mutation.
     pub fn compress_for_scrollback(&mut self) {
         let cv = match &self.cells {
-            CellStorage::V(v) => ClusteredLine::from_cell_vec(v.len(), self.visible_cells()),
+            CellStorage::V(v) => {
+                match ClusteredLine::from_cell_vec(v.len(), self.visible_cells()) {
+                    Some(cv) => cv,
+                    None => return,
+                }
+            }
             CellStorage::C(_) => return,
         };
         self.cells = CellStorage::C(cv);
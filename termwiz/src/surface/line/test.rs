@@ -7,6 +7,7 @@ use crate::surface::line::clusterline::ClusteredLine;
 use crate::surface::SEQ_ZERO;
 use k9::assert_equal as assert_eq;
 use std::sync::Arc;
+use wezterm_bidi::ParagraphDirectionHint;
 
 /// There are 4 double-wide graphemes that occupy 2 cells each.
 /// When we join the lines, we must preserve the invisible blank
@@ -99,6 +100,43 @@ fn hyperlinks() {
     );
 }
 
+#[test]
+fn hyperlinks_rescan_when_rules_change() {
+    // has_hyperlink() may be conservative for the physical lines that
+    // comprise a logical line, so look at the cells themselves
+    fn linked(line: &Line) -> bool {
+        line.visible_cells()
+            .any(|cell| cell.attrs().hyperlink().is_some())
+    }
+
+    let http = vec![Rule::new(r"\bhttp://\S+", "$0").unwrap()];
+    let mail = vec![Rule::new(r"\b\w+@\w+\.com\b", "mailto:$0").unwrap()];
+
+    let mut line: Line = "http://example.com".into();
+    let mut wrapped: Line = " foo@example.com".into();
+    line.set_last_cell_was_wrapped(true, SEQ_ZERO);
+
+    Line::apply_hyperlink_rules(&http, &mut [&mut line, &mut wrapped]);
+    assert!(linked(&line));
+    assert!(!linked(&wrapped));
+
+    // A rule set that has already been applied is a no-op
+    let seqno = line.current_seqno();
+    Line::apply_hyperlink_rules(&http, &mut [&mut line, &mut wrapped]);
+    assert_eq!(line.current_seqno(), seqno);
+
+    // Different rules replace the links from the old ones
+    Line::apply_hyperlink_rules(&mail, &mut [&mut line, &mut wrapped]);
+    assert!(!linked(&line));
+    assert!(linked(&wrapped));
+
+    let mut line: Line = "http://example.com".into();
+    line.scan_and_create_hyperlinks(&http);
+    assert!(linked(&line));
+    line.scan_and_create_hyperlinks(&mail);
+    assert!(!linked(&line));
+}
+
 #[test]
 fn double_click_range_bounds() {
     let line: Line = "hello".into();
@@ -291,6 +329,7 @@ Line {
     bits: LineBits(
         0x0,
     ),
+    hyperlink_rules: 0,
     appdata: Mutex {
         data: None,
         poisoned: false,
@@ -501,6 +540,31 @@ ClusteredLine {
     );
 }
 
+/// Cells that split a grapheme, as happens when grapheme
+/// clustering is disabled, cannot be held by the clustered
+/// representation, which would join them back together
+#[test]
+fn cluster_split_grapheme() {
+    let mut cl = ClusteredLine::new();
+    cl.append_grapheme("\u{1f469}\u{200d}", 2, CellAttributes::default());
+    assert!(!cl.can_append("\u{1f466}"));
+    assert!(cl.can_append("x"));
+
+    let mut line = Line::new(1);
+    line.set_cell_grapheme(0, "\u{1f469}\u{200d}", 2, CellAttributes::default(), 1);
+    line.set_cell_grapheme(2, "\u{1f466}", 2, CellAttributes::default(), 1);
+    let expected = vec!["\u{1f469}\u{200d}", "\u{1f466}"];
+    let cells = |line: &Line| {
+        line.visible_cells()
+            .map(|cell| cell.str().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(cells(&line), expected);
+
+    line.compress_for_scrollback();
+    assert_eq!(cells(&line), expected);
+}
+
 #[test]
 fn cluster_line_new() {
     let mut line = Line::new(1);
@@ -603,6 +667,7 @@ Line {
     bits: LineBits(
         0x0,
     ),
+    hyperlink_rules: 0,
     appdata: Mutex {
         data: None,
         poisoned: false,
@@ -612,3 +677,30 @@ Line {
 "#
     );
 }
+
+#[test]
+fn line_flags_round_trip() {
+    let mut line = Line::from_text("hello グループ", &bold(), SEQ_ZERO, None);
+    line.set_double_height_top(SEQ_ZERO);
+    line.set_bidi_info(true, ParagraphDirectionHint::RightToLeft, SEQ_ZERO);
+
+    let mut copy = Line::new(SEQ_ZERO);
+    for cell in line.visible_cells() {
+        copy.set_cell_grapheme(
+            cell.cell_index(),
+            cell.str(),
+            cell.width(),
+            cell.attrs().clone(),
+            SEQ_ZERO,
+        );
+    }
+    copy.set_line_flags(line.line_flags());
+
+    assert_eq!(copy.len(), line.len());
+    assert!(copy
+        .visible_cells()
+        .zip(line.visible_cells())
+        .all(|(a, b)| a.same_contents(&b)));
+    assert_eq!(copy.line_flags(), line.line_flags());
+    assert!(copy.is_double_height_top());
+}
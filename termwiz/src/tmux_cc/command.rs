@@ -0,0 +1,297 @@
+//! Constructs the command lines that are sent to tmux in control mode.
+//!
+//! tmux parses each line it receives using its own command language,
+//! so arguments that contain whitespace, quotes or other characters
+//! that are significant to that language must be quoted to reach the
+//! command intact.  `CommandBuilder` takes care of that, so that callers
+//! only need to deal with the individual arguments.
+use super::{TmuxPaneId, TmuxWindowId, WindowLayout};
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// The direction in which `split-window` divides a pane
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    /// The new pane is placed to the right of the existing pane
+    Horizontal,
+    /// The new pane is placed below the existing pane
+    Vertical,
+}
+
+/// Builds a single tmux command line.
+///
+/// ```
+/// use termwiz::tmux_cc::CommandBuilder;
+/// let cmd = CommandBuilder::new("rename-window")
+///     .opt("-t", "@1")
+///     .arg("my window")
+///     .build();
+/// assert_eq!(cmd, "rename-window -t @1 \"my window\"\n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandBuilder {
+    line: String,
+}
+
+impl CommandBuilder {
+    pub fn new(command: &str) -> Self {
+        Self {
+            line: quote_arg(command).into_owned(),
+        }
+    }
+
+    /// Append an argument, quoting it if required
+    pub fn arg<S: AsRef<str>>(mut self, arg: S) -> Self {
+        self.line.push(' ');
+        self.line.push_str(&quote_arg(arg.as_ref()));
+        self
+    }
+
+    pub fn args<I, S>(self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        args.into_iter().fold(self, |cmd, arg| cmd.arg(arg))
+    }
+
+    /// Append a flag and its value, eg: `-t %1`
+    pub fn opt<S: AsRef<str>>(self, flag: &str, value: S) -> Self {
+        self.arg(flag).arg(value)
+    }
+
+    /// Append `-t %pane`
+    pub fn target_pane(self, pane: TmuxPaneId) -> Self {
+        self.opt("-t", pane.to_string())
+    }
+
+    /// Returns the command line, terminated by a newline,
+    /// ready to be written to tmux
+    pub fn build(self) -> String {
+        let mut line = self.line;
+        line.push('\n');
+        line
+    }
+
+    /// Returns the command line without the terminating newline
+    pub fn as_str(&self) -> &str {
+        &self.line
+    }
+
+    /// Sends each of `keys` to `pane` as a hex key code, which tmux
+    /// passes through verbatim rather than interpreting as a key name.
+    pub fn send_keys(pane: TmuxPaneId, keys: &[u8]) -> Self {
+        let mut cmd = Self::new("send-keys").target_pane(pane);
+        for &byte in keys {
+            write!(&mut cmd.line, " 0x{:X}", byte).expect("writing to a String cannot fail");
+        }
+        cmd
+    }
+
+    pub fn resize_pane(pane: TmuxPaneId, cols: usize, rows: usize) -> Self {
+        Self::new("resize-pane")
+            .target_pane(pane)
+            .opt("-x", cols.to_string())
+            .opt("-y", rows.to_string())
+    }
+
+    /// Splits `pane`, printing the id of the new pane
+    pub fn split_window(pane: TmuxPaneId, direction: SplitDirection) -> Self {
+        Self::new("split-window")
+            .arg(match direction {
+                SplitDirection::Horizontal => "-h",
+                SplitDirection::Vertical => "-v",
+            })
+            .target_pane(pane)
+            .opt("-PF", "#{pane_id}")
+    }
+
+    /// Sets the size of the control mode client
+    pub fn refresh_client_size(cols: usize, rows: usize) -> Self {
+        Self::new("refresh-client").opt("-C", format!("{}x{}", cols, rows))
+    }
+
+    /// Resumes output from `pane` after tmux paused it
+    pub fn continue_pane(pane: TmuxPaneId) -> Self {
+        Self::new("refresh-client").opt("-A", format!("{}:continue", pane))
+    }
+
+    /// Asks tmux to pause a pane once its output has been waiting
+    /// for the client for `seconds`, rather than buffering it
+    /// indefinitely.  Requires tmux 3.2 or later.
+    pub fn set_pause_after(seconds: u64) -> Self {
+        Self::new("refresh-client").opt("-f", format!("pause-after={}", seconds))
+    }
+
+    /// Captures the content of `pane`, including escape sequences
+    /// for its attributes
+    pub fn capture_pane(pane: TmuxPaneId) -> Self {
+        Self::new("capture-pane")
+            .arg("-p")
+            .target_pane(pane)
+            .arg("-e")
+            .arg("-C")
+    }
+
+    /// Applies `layout` to `window`
+    pub fn select_layout(window: TmuxWindowId, layout: &WindowLayout) -> Self {
+        Self::new("select-layout")
+            .opt("-t", window.to_string())
+            .arg(layout.to_layout_string())
+    }
+
+    pub fn select_pane(pane: TmuxPaneId) -> Self {
+        Self::new("select-pane").target_pane(pane)
+    }
+
+    pub fn kill_pane(pane: TmuxPaneId) -> Self {
+        Self::new("kill-pane").target_pane(pane)
+    }
+
+    /// Lists the panes of all sessions using the supplied format,
+    /// such as `PaneInfo::FORMAT`
+    pub fn list_all_panes(format: &str) -> Self {
+        Self::new("list-panes").arg("-a").opt("-F", format)
+    }
+
+    /// Lists the windows of all sessions using the supplied format,
+    /// such as `WindowInfo::FORMAT`
+    pub fn list_all_windows(format: &str) -> Self {
+        Self::new("list-windows").arg("-a").opt("-F", format)
+    }
+
+    /// Lists the sessions using the supplied format,
+    /// such as `SessionInfo::FORMAT`
+    pub fn list_sessions(format: &str) -> Self {
+        Self::new("list-sessions").opt("-F", format)
+    }
+
+    /// Subscribes to changes in the expansion of `format`, which
+    /// tmux reports with `%subscription-changed`.
+    /// `target` is empty to watch the attached session, or names the
+    /// panes or windows to watch, such as `%*`, `%1`, `@*` or `@2`.
+    pub fn subscribe(name: &str, target: &str, format: &str) -> Self {
+        Self::new("refresh-client").opt("-B", format!("{}:{}:{}", name, target, format))
+    }
+
+    /// Removes a subscription added with `subscribe`
+    pub fn unsubscribe(name: &str) -> Self {
+        Self::new("refresh-client").opt("-B", name)
+    }
+
+    /// Replies with the content of the paste buffer `name`,
+    /// which `PasteBuffer::from_reply` decodes
+    pub fn show_buffer(name: &str) -> Self {
+        Self::new("show-buffer").opt("-b", name)
+    }
+
+    /// Replies with the expansion of the supplied format,
+    /// such as `ProtocolVersion::FORMAT`
+    pub fn display_message(format: &str) -> Self {
+        Self::new("display-message").arg("-p").arg(format)
+    }
+}
+
+impl std::fmt::Display for CommandBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.line)
+    }
+}
+
+fn is_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric()
+        || matches!(c, '-' | '_' | '.' | '/' | ':' | '%' | '@' | ',' | '=' | '+')
+}
+
+/// Quotes `arg` so that tmux's command parser produces the same string.
+/// Arguments that don't need quoting are returned unchanged.
+/// Otherwise the argument is placed in double quotes, in which tmux
+/// expands backslash escapes, so that control characters (which would
+/// otherwise end the command line) can be represented.
+pub fn quote_arg(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return Cow::Borrowed(arg);
+    }
+
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        match c {
+            '"' | '\\' | '$' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\x1b' => quoted.push_str("\\e"),
+            c if c.is_ascii_control() => {
+                write!(&mut quoted, "\\{:03o}", c as u32).expect("writing to a String cannot fail")
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux_cc::ProtocolVersion;
+    use k9::assert_equal as assert_eq;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote_arg("%1"), "%1");
+        assert_eq!(quote_arg(""), "\"\"");
+        assert_eq!(quote_arg("hello world"), "\"hello world\"");
+        assert_eq!(quote_arg("it's \"$HOME\""), "\"it's \\\"\\$HOME\\\"\"");
+        assert_eq!(quote_arg("a;b"), "\"a;b\"");
+        assert_eq!(quote_arg("\x1b[1m\n\x07"), "\"\\e[1m\\n\\007\"");
+        assert_eq!(quote_arg("ünïcode"), "\"ünïcode\"");
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            CommandBuilder::send_keys(TmuxPaneId(2), b"a\r").build(),
+            "send-keys -t %2 0x61 0xD\n"
+        );
+        assert_eq!(
+            CommandBuilder::resize_pane(TmuxPaneId(1), 80, 24).build(),
+            "resize-pane -t %1 -x 80 -y 24\n"
+        );
+        assert_eq!(
+            CommandBuilder::split_window(TmuxPaneId(3), SplitDirection::Vertical).build(),
+            "split-window -v -t %3 -PF \"#{pane_id}\"\n"
+        );
+        assert_eq!(
+            CommandBuilder::refresh_client_size(120, 40).build(),
+            "refresh-client -C 120x40\n"
+        );
+        assert_eq!(
+            CommandBuilder::capture_pane(TmuxPaneId(0)).to_string(),
+            "capture-pane -p -t %0 -e -C"
+        );
+        assert_eq!(
+            CommandBuilder::list_all_panes("#{pane_id} #{pane_width}").build(),
+            "list-panes -a -F \"#{pane_id} #{pane_width}\"\n"
+        );
+        assert_eq!(
+            CommandBuilder::subscribe("title", "%*", "#{pane_title}").build(),
+            "refresh-client -B \"title:%*:#{pane_title}\"\n"
+        );
+        assert_eq!(
+            CommandBuilder::unsubscribe("title").build(),
+            "refresh-client -B title\n"
+        );
+        assert_eq!(
+            CommandBuilder::display_message(ProtocolVersion::FORMAT).build(),
+            "display-message -p \"#{version}\"\n"
+        );
+    }
+}
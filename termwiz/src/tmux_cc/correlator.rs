@@ -0,0 +1,217 @@
+//! Matches the replies that tmux sends in `%begin`/`%end` blocks to
+//! the commands that produced them.
+//!
+//! tmux executes the commands sent by a control mode client in the
+//! order that they were sent, and sends exactly one guarded block in
+//! reply to each, so replies are matched to commands in order.
+//! The `%begin` line of a reply to a command from the control client
+//! has bit 0 of its flags set; blocks without it, such as the reply
+//! to the command that started the client, don't correspond to a
+//! command that was sent and are left for the caller to handle.
+use super::{Event, Guarded};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Identifies a command registered with a `CommandCorrelator`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommandId(pub u64);
+
+#[derive(Default)]
+struct Slot {
+    result: Option<anyhow::Result<Guarded>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the reply to a command, or to an error if the
+/// correlator was cancelled or dropped before the reply arrived.
+/// A reply for which tmux reported an error resolves successfully,
+/// with `Guarded::error` set.
+pub struct ReplyFuture {
+    id: CommandId,
+    slot: Arc<Mutex<Slot>>,
+}
+
+impl ReplyFuture {
+    pub fn id(&self) -> CommandId {
+        self.id
+    }
+}
+
+impl Future for ReplyFuture {
+    type Output = anyhow::Result<Guarded>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker.replace(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn complete(slot: &Mutex<Slot>, result: anyhow::Result<Guarded>) {
+    let mut slot = slot.lock().unwrap();
+    slot.result.replace(result);
+    if let Some(waker) = slot.waker.take() {
+        waker.wake();
+    }
+}
+
+#[derive(Default)]
+pub struct CommandCorrelator {
+    next_id: u64,
+    pending: VecDeque<(CommandId, Arc<Mutex<Slot>>)>,
+}
+
+impl CommandCorrelator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a command.  This must be called in the same order
+    /// that the commands are written to tmux.
+    pub fn register(&mut self) -> ReplyFuture {
+        let id = CommandId(self.next_id);
+        self.next_id += 1;
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        self.pending.push_back((id, Arc::clone(&slot)));
+        ReplyFuture { id, slot }
+    }
+
+    /// The number of commands that are waiting for a reply
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Resolves the oldest pending command with `guarded`.
+    /// Returns the id of that command, or gives back `guarded` if
+    /// it isn't a reply to a command from this client, or if there
+    /// is no command waiting for a reply.
+    pub fn resolve(&mut self, guarded: Guarded) -> Result<CommandId, Guarded> {
+        if guarded.flags & 1 == 0 {
+            return Err(guarded);
+        }
+        match self.pending.pop_front() {
+            Some((id, slot)) => {
+                complete(&slot, Ok(guarded));
+                Ok(id)
+            }
+            None => {
+                log::error!("tmux reply {:?} does not match a command", guarded);
+                Err(guarded)
+            }
+        }
+    }
+
+    /// Resolves the matching command if `event` is a reply to one,
+    /// otherwise returns `event`.
+    /// When the parser is streaming guarded output, the command is
+    /// resolved by the `GuardedDone` event, with only the output that
+    /// followed the last `GuardedChunk`.
+    pub fn advance(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::Guarded(guarded) => self.resolve(guarded).err().map(Event::Guarded),
+            Event::GuardedDone(guarded) => self.resolve(guarded).err().map(Event::GuardedDone),
+            event => Some(event),
+        }
+    }
+
+    /// Fails all pending commands, for example because the
+    /// connection to tmux was lost
+    pub fn cancel_all(&mut self) {
+        for (id, slot) in self.pending.drain(..) {
+            complete(
+                &slot,
+                Err(anyhow::anyhow!("tmux command {:?} was cancelled", id)),
+            );
+        }
+    }
+}
+
+impl Drop for CommandCorrelator {
+    fn drop(&mut self) {
+        self.cancel_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k9::assert_equal as assert_eq;
+    use std::task::Wake;
+
+    struct NoopWaker;
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn poll(future: &mut ReplyFuture) -> Poll<anyhow::Result<Guarded>> {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        Pin::new(future).poll(&mut cx)
+    }
+
+    fn guarded(number: u64, flags: i64) -> Guarded {
+        Guarded {
+            error: false,
+            timestamp: 1,
+            number,
+            flags,
+            output: String::new(),
+        }
+    }
+
+    #[test]
+    fn replies_resolve_in_order() {
+        let mut correlator = CommandCorrelator::new();
+        let mut first = correlator.register();
+        let mut second = correlator.register();
+        assert_eq!(correlator.pending(), 2);
+        assert!(poll(&mut first).is_pending());
+
+        // The reply to the command that started the client
+        // isn't ours
+        assert_eq!(
+            correlator.advance(Event::Guarded(guarded(1, 0))),
+            Some(Event::Guarded(guarded(1, 0)))
+        );
+        assert_eq!(
+            correlator.advance(Event::SessionsChanged),
+            Some(Event::SessionsChanged)
+        );
+
+        assert_eq!(correlator.advance(Event::Guarded(guarded(2, 1))), None);
+        assert_eq!(correlator.resolve(guarded(3, 1)), Ok(second.id()));
+        assert_eq!(correlator.pending(), 0);
+
+        match poll(&mut first) {
+            Poll::Ready(Ok(reply)) => {
+                assert_eq!(reply.number, 2);
+            }
+            _ => panic!("first should be resolved"),
+        }
+        match poll(&mut second) {
+            Poll::Ready(Ok(reply)) => {
+                assert_eq!(reply.number, 3);
+            }
+            _ => panic!("second should be resolved"),
+        }
+
+        // Nothing is waiting for this reply
+        assert_eq!(correlator.resolve(guarded(4, 1)), Err(guarded(4, 1)));
+    }
+
+    #[test]
+    fn dropping_cancels() {
+        let mut correlator = CommandCorrelator::new();
+        let mut reply = correlator.register();
+        drop(correlator);
+        assert!(matches!(poll(&mut reply), Poll::Ready(Err(_))));
+    }
+}
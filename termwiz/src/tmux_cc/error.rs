@@ -0,0 +1,87 @@
+//! Describes where in a control mode line parsing failed.
+//!
+//! The errors returned by `Parser` carry a `ParseError`, which can
+//! be retrieved from the `anyhow::Error` with `downcast_ref`:
+//!
+//! ```
+//! use termwiz::tmux_cc::{ParseError, Parser};
+//! let mut parser = Parser::new();
+//! let err = parser.advance_string("%window-add @\n").unwrap_err();
+//! let err = err.downcast_ref::<ParseError>().unwrap();
+//! assert_eq!(err.column(), 14);
+//! ```
+use super::parser::Rule;
+use pest::error::{ErrorVariant, InputLocation};
+use pest::iterators::Pair;
+use std::fmt::Display;
+use std::ops::Range;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message} at column {} of {line:?}", self.column())]
+pub struct ParseError {
+    /// The line that failed to parse
+    pub line: String,
+    /// The range of bytes within `line` at which parsing failed.
+    /// This is empty if the failure is at a single position, such
+    /// as an unexpected end of the line.
+    pub span: Range<usize>,
+    /// The name of the field that failed to parse, such as
+    /// `pane_id`, or of the field that was expected at `span`,
+    /// if known
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl ParseError {
+    /// Returns the column, counting from 1 in characters, at which
+    /// parsing failed
+    pub fn column(&self) -> usize {
+        match self.line.get(..self.span.start) {
+            Some(prefix) => prefix.chars().count() + 1,
+            None => self.span.start + 1,
+        }
+    }
+
+    pub(crate) fn from_pest(err: pest::error::Error<Rule>) -> Self {
+        let span = match err.location {
+            InputLocation::Pos(pos) => pos..pos,
+            InputLocation::Span((start, end)) => start..end,
+        };
+        let field = match &err.variant {
+            ErrorVariant::ParsingError { positives, .. } => {
+                positives.first().map(|rule| format!("{:?}", rule))
+            }
+            ErrorVariant::CustomError { .. } => None,
+        };
+        Self {
+            line: err.line().to_owned(),
+            span,
+            field,
+            message: err.variant.message().into_owned(),
+        }
+    }
+
+    /// Reports that the text matched by `pair` is not valid
+    pub(crate) fn in_pair(pair: &Pair<Rule>, message: impl Display) -> Self {
+        let span = pair.as_span();
+        Self {
+            line: span.get_input().to_owned(),
+            span: span.start()..span.end(),
+            field: Some(format!("{:?}", pair.as_rule())),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Parses the text matched by `pair`, reporting its position if
+/// that fails
+pub(crate) fn parse_pair<T>(pair: &Pair<Rule>) -> Result<T, ParseError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    pair.as_str()
+        .parse()
+        .map_err(|err| ParseError::in_pair(pair, err))
+}
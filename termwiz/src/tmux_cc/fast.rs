@@ -0,0 +1,256 @@
+//! Recognizes the lines that tmux sends most often without going
+//! through the grammar.
+//!
+//! Every byte of output from a pane arrives in a `%output` or
+//! `%extended-output` line, and every line of a reply to a command
+//! has to be checked for `%end` or `%error`, so these are parsed
+//! directly from the bytes of the line.  The functions here return
+//! `None` for anything that they don't recognize, or that isn't well
+//! formed, leaving it to the grammar, which also reports the errors.
+use super::{decode_output, Event, TmuxPaneId};
+use bytes::{Bytes, BytesMut};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Parses a non-empty sequence of ASCII digits
+fn parse_number<T: FromStr>(digits: &[u8]) -> Option<T> {
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+fn pane_id(id: &[u8]) -> Option<TmuxPaneId> {
+    parse_number(id.strip_prefix(b"%")?).map(TmuxPaneId)
+}
+
+/// Splits `bytes` at the first space, returning the bytes before
+/// and after it
+fn split_space(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let idx = memchr::memchr(b' ', bytes)?;
+    Some((&bytes[..idx], &bytes[idx + 1..]))
+}
+
+/// The part of an `%output` or `%extended-output` line that
+/// precedes the encoded text
+pub(crate) struct OutputHeader {
+    pane: TmuxPaneId,
+    /// The age of `%extended-output`
+    age: Option<Duration>,
+    /// The length of the header, including the separator
+    /// that precedes the text
+    pub len: usize,
+}
+
+impl OutputHeader {
+    pub fn event(&self, text: Bytes) -> Event {
+        match self.age {
+            Some(age) => Event::ExtendedOutput {
+                pane: self.pane,
+                age,
+                text,
+            },
+            None => Event::Output {
+                pane: self.pane,
+                text,
+            },
+        }
+    }
+}
+
+/// Parses the header of `%output` and `%extended-output`, which
+/// may be followed by just part of the text
+pub(crate) fn parse_output_header(line: &[u8]) -> Option<OutputHeader> {
+    if let Some(rest) = line.strip_prefix(b"%output ") {
+        let (pane, encoded) = split_space(rest)?;
+        return Some(OutputHeader {
+            pane: pane_id(pane)?,
+            age: None,
+            len: line.len() - encoded.len(),
+        });
+    }
+
+    let rest = line.strip_prefix(b"%extended-output ")?;
+    let (pane, rest) = split_space(rest)?;
+    let pane = pane_id(pane)?;
+    let (age, rest) = split_space(rest)?;
+    let age = Duration::from_millis(parse_number(age)?);
+    // Any arguments precede the " : " that separates them from
+    // the text.  As the arguments can't contain spaces, the first
+    // occurrence is the separator.
+    let encoded = match rest.strip_prefix(b": ") {
+        Some(encoded) => encoded,
+        None => {
+            let idx = memchr::memmem::find(rest, b" : ")?;
+            if rest[..idx].split(|&b| b == b' ').any(<[u8]>::is_empty) {
+                return None;
+            }
+            &rest[idx + 3..]
+        }
+    };
+    Some(OutputHeader {
+        pane,
+        age: Some(age),
+        len: line.len() - encoded.len(),
+    })
+}
+
+/// Parses `%output` and `%extended-output`, decoding the text into
+/// `output` in the same way as `parse_line`
+pub(crate) fn parse_output(line: &[u8], output: &mut BytesMut) -> Option<Event> {
+    let header = parse_output_header(line)?;
+    let text = decode_output(&line[header.len..], output).ok()?;
+    Some(header.event(text))
+}
+
+/// Returns the length of the longest prefix of the vis encoded
+/// `encoded` that doesn't end part way through an escape sequence,
+/// allowing for the octal escapes of fewer than three digits that
+/// `unvis` accepts
+pub(crate) fn complete_escapes(encoded: &[u8]) -> usize {
+    let mut idx = 0;
+    while idx < encoded.len() {
+        let len = if encoded[idx] != b'\\' {
+            1
+        } else {
+            match encoded.get(idx + 1) {
+                Some(b'0'..=b'7') => {
+                    let digits = encoded[idx + 1..]
+                        .iter()
+                        .take(3)
+                        .take_while(|b| matches!(b, b'0'..=b'7'))
+                        .count();
+                    if digits < 3 && idx + 1 + digits == encoded.len() {
+                        // More digits may follow
+                        return idx;
+                    }
+                    1 + digits
+                }
+                Some(b'M') => 4,
+                Some(b'^') => 3,
+                _ => 2,
+            }
+        };
+        if idx + len > encoded.len() {
+            return idx;
+        }
+        idx += len;
+    }
+    idx
+}
+
+/// Parses `%begin`, `%end` and `%error`
+pub(crate) fn parse_guard_line(line: &[u8]) -> Option<Event> {
+    let (kind, rest) = split_space(line)?;
+    if !matches!(kind, b"%begin" | b"%end" | b"%error") {
+        return None;
+    }
+    let (timestamp, rest) = split_space(rest)?;
+    let (number, flags) = split_space(rest)?;
+    let timestamp = parse_number(timestamp)?;
+    let number = parse_number(number)?;
+    let flags = parse_number(flags)?;
+    match kind {
+        b"%begin" => Some(Event::Begin {
+            timestamp,
+            number,
+            flags,
+        }),
+        b"%end" => Some(Event::End {
+            timestamp,
+            number,
+            flags,
+        }),
+        b"%error" => Some(Event::Error {
+            timestamp,
+            number,
+            flags,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux_cc::parse_line;
+    use k9::assert_equal as assert_eq;
+
+    /// The lines must be parsed the same way as by the grammar
+    #[test]
+    fn matches_grammar() {
+        let lines = [
+            "%output %1 hello\\033[1mworld",
+            "%output %1 ",
+            "%output %12 a b  c",
+            "%extended-output %3 25 : hello",
+            "%extended-output %3 25 foo bar : hello : world",
+            "%extended-output %3 25 : : x",
+            "%extended-output %3 25 a: b : c",
+            "%begin 1 2 1",
+            "%end 1 2 1",
+            "%error 1578920019 258 1",
+        ];
+        for line in lines.iter() {
+            let expected = parse_line(line, &mut BytesMut::new(), false).unwrap();
+            let fast = parse_output(line.as_bytes(), &mut BytesMut::new())
+                .or_else(|| parse_guard_line(line.as_bytes()));
+            assert_eq!(fast, Some(expected), "{}", line);
+        }
+    }
+
+    /// Lines that are malformed are left to the grammar to report
+    #[test]
+    fn malformed() {
+        let lines = [
+            "%output %1",
+            "%output 1 hello",
+            "%output %99999999999999999999 hello",
+            "%output %1 bad\\q",
+            "%extended-output %3 25",
+            "%extended-output %3 25  : hello",
+            "%extended-output %3 25 foo  bar : hello",
+            "%extended-output %3 x : hello",
+            "%begin 1 2",
+            "%begin 1 2 3 4",
+            "%end 1 -2 3",
+            "%endx 1 2 3",
+        ];
+        let mut output = BytesMut::new();
+        for line in lines.iter() {
+            assert!(
+                parse_line(line, &mut BytesMut::new(), false).is_err(),
+                "{}",
+                line
+            );
+            assert_eq!(parse_output(line.as_bytes(), &mut output), None, "{}", line);
+            assert_eq!(parse_guard_line(line.as_bytes()), None, "{}", line);
+        }
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn escapes_are_not_split() {
+        assert_eq!(complete_escapes(b"abc"), 3);
+        assert_eq!(complete_escapes(b"ab\\"), 2);
+        assert_eq!(complete_escapes(b"ab\\\\"), 4);
+        assert_eq!(complete_escapes(b"ab\\03"), 2);
+        assert_eq!(complete_escapes(b"ab\\033"), 6);
+        // A shorter octal escape is complete once it is followed
+        // by something else
+        assert_eq!(complete_escapes(b"ab\\3x"), 5);
+        assert_eq!(complete_escapes(b"\\M-"), 0);
+        assert_eq!(complete_escapes(b"\\M-a\\^"), 4);
+    }
+
+    #[test]
+    fn not_utf8() {
+        assert_eq!(
+            parse_output(b"%output %1 \xff\\377", &mut BytesMut::new()),
+            Some(Event::Output {
+                pane: TmuxPaneId(1),
+                text: bytes::Bytes::from_static(b"\xff\xff"),
+            })
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Tracks the panes that tmux has paused.
+//!
+//! When the `pause-after` flag is set on a control mode client
+//! (see `CommandBuilder::set_pause_after`), tmux stops sending the
+//! output of a pane once the client has fallen too far behind it,
+//! and reports `%pause`.  The client asks for the output to resume
+//! once it has caught up, and tmux reports `%continue`.
+use super::{CommandBuilder, Event, TmuxPaneId};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct PaneFlow {
+    paused: bool,
+    /// Set once the command to continue the pane has been produced,
+    /// so that it is only produced once per pause
+    resuming: bool,
+    /// Bytes of output that have been received but not yet consumed
+    backlog: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct FlowControl {
+    panes: BTreeMap<TmuxPaneId, PaneFlow>,
+}
+
+impl FlowControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the state of the panes from `event`
+    pub fn advance(&mut self, event: &Event) {
+        match event {
+            Event::Output { pane, text } | Event::ExtendedOutput { pane, text, .. } => {
+                self.panes.entry(*pane).or_default().backlog += text.len();
+            }
+            Event::Pause { pane } => {
+                let flow = self.panes.entry(*pane).or_default();
+                flow.paused = true;
+                flow.resuming = false;
+            }
+            Event::Continue { pane } => {
+                let flow = self.panes.entry(*pane).or_default();
+                flow.paused = false;
+                flow.resuming = false;
+            }
+            _ => {}
+        }
+    }
+
+    /// Records that `size` bytes of the output of `pane` have been
+    /// processed, reducing its backlog
+    pub fn consumed(&mut self, pane: TmuxPaneId, size: usize) {
+        if let Some(flow) = self.panes.get_mut(&pane) {
+            flow.backlog = flow.backlog.saturating_sub(size);
+        }
+    }
+
+    /// Forgets a pane that has been closed
+    pub fn remove_pane(&mut self, pane: TmuxPaneId) {
+        self.panes.remove(&pane);
+    }
+
+    pub fn is_paused(&self, pane: TmuxPaneId) -> bool {
+        self.panes
+            .get(&pane)
+            .map(|flow| flow.paused)
+            .unwrap_or(false)
+    }
+
+    /// Returns the number of bytes of output from `pane` that have
+    /// been received but not yet consumed
+    pub fn backlog(&self, pane: TmuxPaneId) -> usize {
+        self.panes.get(&pane).map(|flow| flow.backlog).unwrap_or(0)
+    }
+
+    pub fn paused_panes(&self) -> impl Iterator<Item = TmuxPaneId> + '_ {
+        self.panes
+            .iter()
+            .filter(|(_, flow)| flow.paused)
+            .map(|(pane, _)| *pane)
+    }
+
+    /// Returns the commands, in pane order, to continue each of the
+    /// paused panes whose backlog is no more than `max_backlog` bytes.
+    /// A pane is only returned once per pause, even though it remains
+    /// paused until tmux reports `%continue`.
+    pub fn resume_ready(&mut self, max_backlog: usize) -> Vec<CommandBuilder> {
+        let mut commands = vec![];
+        for (pane, flow) in self.panes.iter_mut() {
+            if flow.paused && !flow.resuming && flow.backlog <= max_backlog {
+                flow.resuming = true;
+                commands.push(CommandBuilder::continue_pane(*pane));
+            }
+        }
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use k9::assert_equal as assert_eq;
+
+    #[test]
+    fn pause_and_resume() {
+        let mut flow = FlowControl::new();
+        flow.advance(&Event::Output {
+            pane: TmuxPaneId(1),
+            text: Bytes::from_static(b"hello"),
+        });
+        flow.advance(&Event::Output {
+            pane: TmuxPaneId(2),
+            text: Bytes::from_static(b"world!"),
+        });
+        flow.advance(&Event::Pause {
+            pane: TmuxPaneId(1),
+        });
+        flow.advance(&Event::Pause {
+            pane: TmuxPaneId(2),
+        });
+        assert!(flow.is_paused(TmuxPaneId(1)));
+        assert!(!flow.is_paused(TmuxPaneId(3)));
+        assert_eq!(flow.backlog(TmuxPaneId(1)), 5);
+
+        flow.consumed(TmuxPaneId(1), 3);
+        assert_eq!(flow.backlog(TmuxPaneId(1)), 2);
+        let commands = flow
+            .resume_ready(2)
+            .into_iter()
+            .map(|cmd| cmd.build())
+            .collect::<Vec<_>>();
+        assert_eq!(commands, vec!["refresh-client -A %1:continue\n".to_owned()]);
+        // Not produced again while waiting for %continue
+        assert!(flow.resume_ready(2).is_empty());
+
+        flow.advance(&Event::Continue {
+            pane: TmuxPaneId(1),
+        });
+        assert!(!flow.is_paused(TmuxPaneId(1)));
+        assert_eq!(flow.paused_panes().collect::<Vec<_>>(), vec![TmuxPaneId(2)]);
+
+        flow.consumed(TmuxPaneId(2), 100);
+        assert_eq!(flow.backlog(TmuxPaneId(2)), 0);
+        assert_eq!(flow.resume_ready(0).len(), 1);
+    }
+}
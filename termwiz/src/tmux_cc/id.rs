@@ -0,0 +1,121 @@
+//! The ids by which tmux refers to sessions, windows and panes.
+//!
+//! Each kind of id is written with its own sigil, such as `$1` for a
+//! session, `@3` for a window and `%5` for a pane, both in the
+//! notifications that tmux sends and in the targets of commands.
+//! The ids here display in that form and parse from it:
+//!
+//! ```
+//! use termwiz::tmux_cc::TmuxPaneId;
+//! let pane: TmuxPaneId = "%5".parse().unwrap();
+//! assert_eq!(pane, TmuxPaneId(5));
+//! assert_eq!(pane.to_string(), "%5");
+//! ```
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// The error returned when parsing an id that doesn't have the
+/// expected sigil, or whose number isn't valid
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid tmux {kind} id {id:?}")]
+pub struct ParseIdError {
+    pub kind: &'static str,
+    pub id: String,
+}
+
+macro_rules! tmux_id {
+    ($(#[$attr:meta])* $name:ident, $kind:literal, $sigil:literal) => {
+        $(#[$attr])*
+        #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+        pub struct $name(pub u64);
+
+        impl $name {
+            /// The character that precedes the number of the id
+            pub const SIGIL: char = $sigil;
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{}{}", Self::SIGIL, self.0)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseIdError;
+
+            fn from_str(id: &str) -> Result<Self, ParseIdError> {
+                id.strip_prefix(Self::SIGIL)
+                    .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+                    .and_then(|digits| digits.parse().ok())
+                    .map(Self)
+                    .ok_or_else(|| ParseIdError {
+                        kind: $kind,
+                        id: id.to_owned(),
+                    })
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(id: u64) -> Self {
+                Self(id)
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(id: $name) -> u64 {
+                id.0
+            }
+        }
+    };
+}
+
+tmux_id!(
+    /// Identifies a session, such as `$1`
+    TmuxSessionId,
+    "session",
+    '$'
+);
+tmux_id!(
+    /// Identifies a window, such as `@3`
+    TmuxWindowId,
+    "window",
+    '@'
+);
+tmux_id!(
+    /// Identifies a pane, such as `%5`
+    TmuxPaneId,
+    "pane",
+    '%'
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k9::assert_equal as assert_eq;
+
+    #[test]
+    fn display_and_parse() {
+        assert_eq!(TmuxSessionId(1).to_string(), "$1");
+        assert_eq!(TmuxWindowId(3).to_string(), "@3");
+        assert_eq!(TmuxPaneId(5).to_string(), "%5");
+
+        assert_eq!("$1".parse::<TmuxSessionId>(), Ok(TmuxSessionId(1)));
+        assert_eq!("@30".parse::<TmuxWindowId>(), Ok(TmuxWindowId(30)));
+        assert_eq!(
+            "@3".parse::<TmuxPaneId>(),
+            Err(ParseIdError {
+                kind: "pane",
+                id: "@3".to_owned()
+            })
+        );
+        for id in ["%", "5", "%+5", "%5 ", "%99999999999999999999"].iter() {
+            assert!(id.parse::<TmuxPaneId>().is_err(), "{}", id);
+        }
+        assert_eq!(
+            "%x".parse::<TmuxPaneId>().unwrap_err().to_string(),
+            "invalid tmux pane id \"%x\""
+        );
+    }
+}
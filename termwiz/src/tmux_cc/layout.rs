@@ -0,0 +1,537 @@
+//! Window layouts, as reported by `%layout-change` and accepted
+//! by `select-layout`.
+//!
+//! A layout is a tree of nodes.  Each node has a size and position
+//! and either holds a pane or is split into a sequence of child
+//! nodes, arranged either left to right or top to bottom:
+//!
+//! ```text
+//! 1780,80x24,0,0{40x24,0,0,1,39x24,41,0[39x12,41,0,2,39x11,41,13,3]}
+//! ```
+use super::error::parse_pair;
+use super::parser::{Rule, TmuxParser};
+use super::{ParseError, SplitDirection, TmuxPaneId};
+use pest::iterators::Pair;
+use pest::Parser as _;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowLayout {
+    /// The checksum that prefixed the layout, as it was reported
+    pub layout_id: String,
+    pub width: u64,
+    pub height: u64,
+    pub root: LayoutNode,
+}
+
+/// The size and position of a pane within its window
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaneLayout {
+    pub pane_id: TmuxPaneId,
+    pub pane_width: u64,
+    pub pane_height: u64,
+    pub pane_left: u64,
+    pub pane_top: u64,
+    /// Whether the pane is zoomed to fill its window.
+    /// This isn't part of the layout string; it is determined from
+    /// the rest of `%layout-change`.
+    pub zoomed: bool,
+}
+
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutNode {
+    /// A region that is divided into `children`, which are
+    /// arranged side by side for `SplitDirection::Horizontal`,
+    /// or one above the other for `SplitDirection::Vertical`
+    Split {
+        direction: SplitDirection,
+        width: u64,
+        height: u64,
+        left: u64,
+        top: u64,
+        children: Vec<LayoutNode>,
+    },
+    Leaf(PaneLayout),
+}
+
+impl LayoutNode {
+    pub fn width(&self) -> u64 {
+        match self {
+            Self::Split { width, .. } => *width,
+            Self::Leaf(pane) => pane.pane_width,
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        match self {
+            Self::Split { height, .. } => *height,
+            Self::Leaf(pane) => pane.pane_height,
+        }
+    }
+
+    pub fn left(&self) -> u64 {
+        match self {
+            Self::Split { left, .. } => *left,
+            Self::Leaf(pane) => pane.pane_left,
+        }
+    }
+
+    pub fn top(&self) -> u64 {
+        match self {
+            Self::Split { top, .. } => *top,
+            Self::Leaf(pane) => pane.pane_top,
+        }
+    }
+
+    pub fn children(&self) -> &[LayoutNode] {
+        match self {
+            Self::Split { children, .. } => children,
+            Self::Leaf(_) => &[],
+        }
+    }
+
+    /// Returns the panes in this part of the layout, in the order
+    /// that tmux lists them
+    pub fn panes(&self) -> Vec<&PaneLayout> {
+        let mut panes = vec![];
+        self.collect_panes(&mut panes);
+        panes
+    }
+
+    fn for_each_pane_mut<F: FnMut(&mut PaneLayout)>(&mut self, f: &mut F) {
+        match self {
+            Self::Split { children, .. } => {
+                for child in children {
+                    child.for_each_pane_mut(f);
+                }
+            }
+            Self::Leaf(pane) => f(pane),
+        }
+    }
+
+    fn collect_panes<'a>(&'a self, panes: &mut Vec<&'a PaneLayout>) {
+        match self {
+            Self::Split { children, .. } => {
+                for child in children {
+                    child.collect_panes(panes);
+                }
+            }
+            Self::Leaf(pane) => panes.push(pane),
+        }
+    }
+
+    /// Returns the indices of the children that lead from this node
+    /// to the leaf holding `pane_id`
+    pub fn path_to(&self, pane_id: TmuxPaneId) -> Option<Vec<usize>> {
+        match self {
+            Self::Leaf(pane) if pane.pane_id == pane_id => Some(vec![]),
+            Self::Leaf(_) => None,
+            Self::Split { children, .. } => children.iter().enumerate().find_map(|(idx, child)| {
+                let mut path = child.path_to(pane_id)?;
+                path.insert(0, idx);
+                Some(path)
+            }),
+        }
+    }
+
+    /// Returns the node at the end of `path`, as returned by `path_to`
+    pub fn node_at(&self, path: &[usize]) -> Option<&LayoutNode> {
+        path.iter()
+            .try_fold(self, |node, &idx| node.children().get(idx))
+    }
+}
+
+impl WindowLayout {
+    /// Parses a layout string, such as the value of the
+    /// `window_layout` format.
+    /// Fails if the checksum doesn't match the rest of the string.
+    pub fn parse(layout: &str) -> anyhow::Result<Self> {
+        Self::parse_impl(layout, false)
+    }
+
+    /// Parses a layout string without verifying its checksum
+    pub fn parse_lenient(layout: &str) -> anyhow::Result<Self> {
+        Self::parse_impl(layout, true)
+    }
+
+    fn parse_impl(layout: &str, lenient: bool) -> anyhow::Result<Self> {
+        let pair = TmuxParser::parse(Rule::window_layout_entire, layout)
+            .map_err(ParseError::from_pest)?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("no pairs!?"))?;
+        parse_window_layout(pair, lenient)
+    }
+
+    /// Produces the layout string for this layout, in the form
+    /// accepted by `select-layout`.
+    /// The checksum is computed from the nodes rather than taken
+    /// from `layout_id`, so that a modified layout is accepted.
+    pub fn to_layout_string(&self) -> String {
+        let body = self.root.to_string();
+        format!("{:04x},{}", layout_checksum(&body), body)
+    }
+
+    pub fn panes(&self) -> Vec<&PaneLayout> {
+        self.root.panes()
+    }
+
+    /// Returns the pane that is zoomed, if any
+    pub fn zoomed_pane(&self) -> Option<TmuxPaneId> {
+        self.panes()
+            .into_iter()
+            .find(|pane| pane.zoomed)
+            .map(|pane| pane.pane_id)
+    }
+
+    /// Marks `pane_id` as the zoomed pane, or marks no pane as zoomed
+    /// if it is `None`
+    pub fn set_zoomed(&mut self, pane_id: Option<TmuxPaneId>) {
+        self.root
+            .for_each_pane_mut(&mut |pane| pane.zoomed = Some(pane.pane_id) == pane_id);
+    }
+
+    /// Returns the split that directly contains `pane_id`, or `None`
+    /// if the pane isn't present or is the only pane in the window
+    pub fn parent_of(&self, pane_id: TmuxPaneId) -> Option<&LayoutNode> {
+        let path = self.root.path_to(pane_id)?;
+        let (_, parent) = path.split_last()?;
+        self.root.node_at(parent)
+    }
+}
+
+/// A change to the geometry of a pane from one layout of a window
+/// to the next, as produced by `diff_layouts`
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutDelta {
+    Added(PaneLayout),
+    Removed(TmuxPaneId),
+    Resized {
+        pane_id: TmuxPaneId,
+        width: u64,
+        height: u64,
+    },
+    Moved {
+        pane_id: TmuxPaneId,
+        left: u64,
+        top: u64,
+    },
+}
+
+/// Compares two successive layouts of a window, returning the changes
+/// that turn the panes of `old` into those of `new`.
+/// The removals come first, followed by the changes to the remaining
+/// panes in the order of `new`; a pane that changed both its size
+/// and position is both `Resized` and `Moved`.
+pub fn diff_layouts(old: &WindowLayout, new: &WindowLayout) -> Vec<LayoutDelta> {
+    let old_panes = old.panes();
+    let new_panes = new.panes();
+    let mut deltas = vec![];
+
+    for pane in &old_panes {
+        if !new_panes.iter().any(|p| p.pane_id == pane.pane_id) {
+            deltas.push(LayoutDelta::Removed(pane.pane_id));
+        }
+    }
+
+    for pane in new_panes {
+        let prior = match old_panes.iter().find(|p| p.pane_id == pane.pane_id) {
+            Some(prior) => prior,
+            None => {
+                deltas.push(LayoutDelta::Added(pane.clone()));
+                continue;
+            }
+        };
+        if (prior.pane_width, prior.pane_height) != (pane.pane_width, pane.pane_height) {
+            deltas.push(LayoutDelta::Resized {
+                pane_id: pane.pane_id,
+                width: pane.pane_width,
+                height: pane.pane_height,
+            });
+        }
+        if (prior.pane_left, prior.pane_top) != (pane.pane_left, pane.pane_top) {
+            deltas.push(LayoutDelta::Moved {
+                pane_id: pane.pane_id,
+                left: pane.pane_left,
+                top: pane.pane_top,
+            });
+        }
+    }
+
+    deltas
+}
+
+impl std::fmt::Display for LayoutNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{},{},{}",
+            self.width(),
+            self.height(),
+            self.left(),
+            self.top()
+        )?;
+        let (open, close, children) = match self {
+            Self::Leaf(pane) => return write!(f, ",{}", pane.pane_id.0),
+            Self::Split {
+                direction: SplitDirection::Horizontal,
+                children,
+                ..
+            } => ('{', '}', children),
+            Self::Split {
+                direction: SplitDirection::Vertical,
+                children,
+                ..
+            } => ('[', ']', children),
+        };
+        f.write_char(open)?;
+        for (idx, child) in children.iter().enumerate() {
+            if idx > 0 {
+                f.write_char(',')?;
+            }
+            child.fmt(f)?;
+        }
+        f.write_char(close)
+    }
+}
+
+/// Computes the checksum that tmux expects at the start of a layout
+/// string, over the remainder of the string.
+/// See layout_checksum in tmux's layout-custom.c
+pub fn layout_checksum(layout: &str) -> u16 {
+    layout
+        .bytes()
+        .fold(0u16, |csum, b| csum.rotate_right(1).wrapping_add(b as u16))
+}
+
+/// Parses a window_layout, for example "b25d,80x24,0,0,0".
+/// Unless `lenient` is set, the checksum must match.
+pub(crate) fn parse_window_layout(pair: Pair<Rule>, lenient: bool) -> anyhow::Result<WindowLayout> {
+    anyhow::ensure!(
+        pair.as_rule() == Rule::window_layout,
+        "parse_window_layout can only parse Rule::window_layout, got {:?}",
+        pair
+    );
+    let mut pairs = pair.into_inner();
+    let checksum = pairs.next().unwrap();
+    let body = pairs.next().unwrap();
+    let root = parse_layout_node(body.clone())?;
+    if !lenient {
+        let expected = layout_checksum(body.as_str());
+        if u16::from_str_radix(checksum.as_str(), 16).ok() != Some(expected) {
+            return Err(ParseError::in_pair(
+                &checksum,
+                format!("layout checksum should be {:04x}", expected),
+            )
+            .into());
+        }
+    }
+    let layout_id = checksum.as_str().to_owned();
+    Ok(WindowLayout {
+        layout_id,
+        width: root.width(),
+        height: root.height(),
+        root,
+    })
+}
+
+fn parse_layout_node(pair: Pair<Rule>) -> anyhow::Result<LayoutNode> {
+    let mut pairs = pair.into_inner();
+    let mut number = || -> anyhow::Result<u64> { Ok(parse_pair(&pairs.next().unwrap())?) };
+    let width = number()?;
+    let height = number()?;
+    let left = number()?;
+    let top = number()?;
+
+    let content = pairs.next().unwrap();
+    let direction = match content.as_rule() {
+        Rule::number => {
+            return Ok(LayoutNode::Leaf(PaneLayout {
+                pane_id: TmuxPaneId(parse_pair(&content)?),
+                pane_width: width,
+                pane_height: height,
+                pane_left: left,
+                pane_top: top,
+                zoomed: false,
+            }))
+        }
+        Rule::layout_left_right => SplitDirection::Horizontal,
+        Rule::layout_top_bottom => SplitDirection::Vertical,
+        _ => anyhow::bail!("unexpected layout content {:?}", content),
+    };
+
+    Ok(LayoutNode::Split {
+        direction,
+        width,
+        height,
+        left,
+        top,
+        children: content
+            .into_inner()
+            .map(parse_layout_node)
+            .collect::<anyhow::Result<_>>()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k9::assert_equal as assert_eq;
+
+    fn parse(layout: &str) -> WindowLayout {
+        WindowLayout::parse(layout).unwrap()
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(layout_checksum("80x24,0,0,0"), 0xb25d);
+        assert_eq!(layout_checksum("120x29,0,0,0"), 0xcafd);
+    }
+
+    fn leaf(
+        pane_id: u64,
+        pane_width: u64,
+        pane_height: u64,
+        pane_left: u64,
+        pane_top: u64,
+    ) -> LayoutNode {
+        LayoutNode::Leaf(PaneLayout {
+            pane_id: TmuxPaneId(pane_id),
+            pane_width,
+            pane_height,
+            pane_left,
+            pane_top,
+            zoomed: false,
+        })
+    }
+
+    #[test]
+    fn nested() {
+        let text = "1780,80x24,0,0{40x24,0,0,1,39x24,41,0[39x12,41,0,2,39x11,41,13,3]}";
+        let layout = parse(text);
+        let right = LayoutNode::Split {
+            direction: SplitDirection::Vertical,
+            width: 39,
+            height: 24,
+            left: 41,
+            top: 0,
+            children: vec![leaf(2, 39, 12, 41, 0), leaf(3, 39, 11, 41, 13)],
+        };
+        assert_eq!(
+            layout.root,
+            LayoutNode::Split {
+                direction: SplitDirection::Horizontal,
+                width: 80,
+                height: 24,
+                left: 0,
+                top: 0,
+                children: vec![leaf(1, 40, 24, 0, 0), right.clone()],
+            }
+        );
+
+        assert_eq!(
+            layout.panes().iter().map(|p| p.pane_id).collect::<Vec<_>>(),
+            vec![TmuxPaneId(1), TmuxPaneId(2), TmuxPaneId(3)]
+        );
+        assert_eq!(layout.root.path_to(TmuxPaneId(3)), Some(vec![1, 1]));
+        assert_eq!(layout.parent_of(TmuxPaneId(3)), Some(&right));
+        assert_eq!(layout.parent_of(TmuxPaneId(1)), Some(&layout.root));
+        assert_eq!(layout.parent_of(TmuxPaneId(4)), None);
+
+        let serialized = layout.to_layout_string();
+        assert_eq!(&serialized[4..], &text[4..]);
+        assert_eq!(parse(&serialized), {
+            let mut layout = layout.clone();
+            layout.layout_id = serialized[0..4].to_owned();
+            layout
+        });
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(WindowLayout::parse("b25d,80x24,0,0").is_err());
+        assert!(WindowLayout::parse("b25d,80x24,0,0,0 trailing").is_err());
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let err = WindowLayout::parse("b25e,80x24,0,0,0").unwrap_err();
+        let err = err.downcast_ref::<ParseError>().unwrap();
+        assert_eq!(err.span, 0..4);
+        assert_eq!(err.message, "layout checksum should be b25d");
+
+        let layout = WindowLayout::parse_lenient("b25e,80x24,0,0,0").unwrap();
+        assert_eq!(layout.layout_id, "b25e");
+        assert_eq!(layout.to_layout_string(), "b25d,80x24,0,0,0");
+    }
+
+    #[test]
+    fn zoomed() {
+        let mut layout =
+            parse("1780,80x24,0,0{40x24,0,0,1,39x24,41,0[39x12,41,0,2,39x11,41,13,3]}");
+        assert_eq!(layout.zoomed_pane(), None);
+        layout.set_zoomed(Some(TmuxPaneId(2)));
+        assert_eq!(layout.zoomed_pane(), Some(TmuxPaneId(2)));
+        assert_eq!(layout.panes().iter().filter(|pane| pane.zoomed).count(), 1);
+        layout.set_zoomed(None);
+        assert_eq!(layout.zoomed_pane(), None);
+    }
+
+    #[test]
+    fn diff() {
+        let single = parse("b25d,80x24,0,0,0");
+        let split = parse("8205,80x24,0,0{40x24,0,0,0,39x24,41,0,1}");
+        assert_eq!(diff_layouts(&single, &single), vec![]);
+        assert_eq!(
+            diff_layouts(&single, &split),
+            vec![
+                LayoutDelta::Resized {
+                    pane_id: TmuxPaneId(0),
+                    width: 40,
+                    height: 24
+                },
+                LayoutDelta::Added(PaneLayout {
+                    pane_id: TmuxPaneId(1),
+                    pane_width: 39,
+                    pane_height: 24,
+                    pane_left: 41,
+                    pane_top: 0,
+                    zoomed: false,
+                }),
+            ]
+        );
+
+        // Closing the left pane of the split leaves the right pane
+        // to fill the window
+        let remaining = parse("b25e,80x24,0,0,1");
+        assert_eq!(
+            diff_layouts(&split, &remaining),
+            vec![
+                LayoutDelta::Removed(TmuxPaneId(0)),
+                LayoutDelta::Resized {
+                    pane_id: TmuxPaneId(1),
+                    width: 80,
+                    height: 24
+                },
+                LayoutDelta::Moved {
+                    pane_id: TmuxPaneId(1),
+                    left: 0,
+                    top: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trip_single_pane() {
+        let layout = parse("b25d,80x24,0,0,0");
+        assert_eq!(layout.root, leaf(0, 80, 24, 0, 0));
+        assert_eq!(layout.parent_of(TmuxPaneId(0)), None);
+        assert_eq!(layout.to_layout_string(), "b25d,80x24,0,0,0");
+    }
+}
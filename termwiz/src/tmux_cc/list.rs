@@ -0,0 +1,279 @@
+//! Parses the output of `list-panes`, `list-windows` and
+//! `list-sessions` when they are run with the corresponding
+//! `FORMAT`, for example:
+//!
+//! ```
+//! use termwiz::tmux_cc::{CommandBuilder, PaneInfo};
+//! let command = CommandBuilder::list_all_panes(PaneInfo::FORMAT).build();
+//! ```
+//!
+//! The formats quote the fields that may contain spaces using tmux's
+//! `q:` modifier, which requires tmux 3.0 or later.
+use super::{ParseIdError, TmuxPaneId, TmuxSessionId, TmuxWindowId, WindowFlags, WindowLayout};
+use anyhow::Context;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaneInfo {
+    pub session_id: TmuxSessionId,
+    pub window_id: TmuxWindowId,
+    pub pane_id: TmuxPaneId,
+    pub pane_index: u64,
+    pub cursor_x: u64,
+    pub cursor_y: u64,
+    pub pane_width: u64,
+    pub pane_height: u64,
+    pub pane_left: u64,
+    pub pane_top: u64,
+    pub pane_active: bool,
+    pub pane_title: String,
+    pub pane_current_path: String,
+}
+
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowInfo {
+    pub session_id: TmuxSessionId,
+    pub window_id: TmuxWindowId,
+    pub window_index: u64,
+    pub window_width: u64,
+    pub window_height: u64,
+    pub window_active: bool,
+    pub window_flags: WindowFlags,
+    pub window_layout: WindowLayout,
+    pub window_name: String,
+}
+
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub session_id: TmuxSessionId,
+    pub session_windows: u64,
+    /// The number of clients attached to the session
+    pub session_attached: u64,
+    pub session_name: String,
+}
+
+impl PaneInfo {
+    pub const FORMAT: &'static str = "#{session_id} #{window_id} #{pane_id} #{pane_index} \
+        #{cursor_x} #{cursor_y} #{pane_width} #{pane_height} #{pane_left} #{pane_top} \
+        #{pane_active} #{q:pane_title} #{q:pane_current_path}";
+}
+
+impl WindowInfo {
+    pub const FORMAT: &'static str = "#{session_id} #{window_id} #{window_index} \
+        #{window_width} #{window_height} #{window_active} #{q:window_flags} \
+        #{window_layout} #{q:window_name}";
+}
+
+impl SessionInfo {
+    pub const FORMAT: &'static str =
+        "#{session_id} #{session_windows} #{session_attached} #{q:session_name}";
+}
+
+/// Splits a line on spaces, removing the backslashes with which the
+/// `q:` modifier escapes spaces and other characters
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    field.push(c);
+                }
+            }
+            ' ' => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+struct Fields {
+    fields: std::vec::IntoIter<String>,
+}
+
+impl Fields {
+    fn new(line: &str, expected: usize) -> anyhow::Result<Self> {
+        let fields = split_fields(line);
+        anyhow::ensure!(
+            fields.len() == expected,
+            "expected {} fields but got {} in {:?}",
+            expected,
+            fields.len(),
+            line
+        );
+        Ok(Self {
+            fields: fields.into_iter(),
+        })
+    }
+
+    fn string(&mut self) -> String {
+        self.fields.next().expect("number of fields was checked")
+    }
+
+    fn number(&mut self, name: &str) -> anyhow::Result<u64> {
+        let field = self.string();
+        field
+            .parse()
+            .with_context(|| format!("{} {:?} is not a number", name, field))
+    }
+
+    /// Parses an id such as `%1`, `@2` or `$3`
+    fn id<T: FromStr<Err = ParseIdError>>(&mut self, name: &str) -> anyhow::Result<T> {
+        self.string()
+            .parse()
+            .with_context(|| format!("parsing {}", name))
+    }
+
+    fn flag(&mut self, name: &str) -> anyhow::Result<bool> {
+        Ok(self.number(name)? != 0)
+    }
+}
+
+fn parse_lines<T, F>(output: &str, parse: F) -> anyhow::Result<Vec<T>>
+where
+    F: Fn(&str) -> anyhow::Result<T>,
+{
+    output
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(parse)
+        .collect()
+}
+
+/// Parses the output of `list-panes -F` with `PaneInfo::FORMAT`
+pub fn parse_list_panes(output: &str) -> anyhow::Result<Vec<PaneInfo>> {
+    parse_lines(output, |line| {
+        let mut fields = Fields::new(line, 13)?;
+        Ok(PaneInfo {
+            session_id: fields.id("session_id")?,
+            window_id: fields.id("window_id")?,
+            pane_id: fields.id("pane_id")?,
+            pane_index: fields.number("pane_index")?,
+            cursor_x: fields.number("cursor_x")?,
+            cursor_y: fields.number("cursor_y")?,
+            pane_width: fields.number("pane_width")?,
+            pane_height: fields.number("pane_height")?,
+            pane_left: fields.number("pane_left")?,
+            pane_top: fields.number("pane_top")?,
+            pane_active: fields.flag("pane_active")?,
+            pane_title: fields.string(),
+            pane_current_path: fields.string(),
+        })
+    })
+}
+
+/// Parses the output of `list-windows -F` with `WindowInfo::FORMAT`
+pub fn parse_list_windows(output: &str) -> anyhow::Result<Vec<WindowInfo>> {
+    parse_lines(output, |line| {
+        let mut fields = Fields::new(line, 9)?;
+        Ok(WindowInfo {
+            session_id: fields.id("session_id")?,
+            window_id: fields.id("window_id")?,
+            window_index: fields.number("window_index")?,
+            window_width: fields.number("window_width")?,
+            window_height: fields.number("window_height")?,
+            window_active: fields.flag("window_active")?,
+            window_flags: WindowFlags::from_window_flags(&fields.string()),
+            window_layout: WindowLayout::parse(&fields.string())?,
+            window_name: fields.string(),
+        })
+    })
+}
+
+/// Parses the output of `list-sessions -F` with `SessionInfo::FORMAT`
+pub fn parse_list_sessions(output: &str) -> anyhow::Result<Vec<SessionInfo>> {
+    parse_lines(output, |line| {
+        let mut fields = Fields::new(line, 4)?;
+        Ok(SessionInfo {
+            session_id: fields.id("session_id")?,
+            session_windows: fields.number("session_windows")?,
+            session_attached: fields.number("session_attached")?,
+            session_name: fields.string(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k9::assert_equal as assert_eq;
+
+    #[test]
+    fn panes() {
+        let output = "$0 @0 %0 0 2 5 80 24 0 0 1 my\\ host ~/src/wez\\ term\n\
+                      $0 @1 %3 1 0 0 40 24 41 0 0  /tmp\n";
+        let panes = parse_list_panes(output).unwrap();
+        assert_eq!(panes.len(), 2);
+        assert_eq!(
+            panes[0],
+            PaneInfo {
+                session_id: TmuxSessionId(0),
+                window_id: TmuxWindowId(0),
+                pane_id: TmuxPaneId(0),
+                pane_index: 0,
+                cursor_x: 2,
+                cursor_y: 5,
+                pane_width: 80,
+                pane_height: 24,
+                pane_left: 0,
+                pane_top: 0,
+                pane_active: true,
+                pane_title: "my host".to_owned(),
+                pane_current_path: "~/src/wez term".to_owned(),
+            }
+        );
+        assert_eq!(panes[1].pane_id, TmuxPaneId(3));
+        assert_eq!(panes[1].pane_title, "");
+        assert_eq!(panes[1].pane_current_path, "/tmp");
+
+        assert!(parse_list_panes("$0 @0 0 0 2 5 80 24 0 0 1 a b").is_err());
+        assert!(parse_list_panes("$0 @0 %0").is_err());
+    }
+
+    #[test]
+    fn windows() {
+        let windows =
+            parse_list_windows("$1 @2 3 80 24 1 \\*Z b25d,80x24,0,0,0 vim\\ \\#1\n").unwrap();
+        assert_eq!(windows.len(), 1);
+        let window = &windows[0];
+        assert_eq!(window.session_id, TmuxSessionId(1));
+        assert_eq!(window.window_id, TmuxWindowId(2));
+        assert_eq!(window.window_index, 3);
+        assert!(window.window_active);
+        assert_eq!(
+            window.window_flags,
+            WindowFlags::CURRENT | WindowFlags::ZOOMED
+        );
+        assert_eq!(window.window_layout.to_layout_string(), "b25d,80x24,0,0,0");
+        assert_eq!(window.window_name, "vim #1");
+    }
+
+    #[test]
+    fn sessions() {
+        assert_eq!(
+            parse_list_sessions("$0 2 1 main\n$4 1 0 other\\ one\n").unwrap(),
+            vec![
+                SessionInfo {
+                    session_id: TmuxSessionId(0),
+                    session_windows: 2,
+                    session_attached: 1,
+                    session_name: "main".to_owned(),
+                },
+                SessionInfo {
+                    session_id: TmuxSessionId(4),
+                    session_windows: 1,
+                    session_attached: 0,
+                    session_name: "other one".to_owned(),
+                },
+            ]
+        );
+    }
+}
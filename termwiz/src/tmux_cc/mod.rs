@@ -1,11 +1,43 @@
-use anyhow::Context;
+use bitflags::bitflags;
+use bytes::{BufMut, Bytes, BytesMut};
 use parser::Rule;
 use pest::iterators::{Pair, Pairs};
 use pest::Parser as _;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::time::Duration;
 
-pub type TmuxWindowId = u64;
-pub type TmuxPaneId = u64;
-pub type TmuxSessionId = u64;
+mod command;
+mod correlator;
+mod error;
+mod fast;
+mod flow;
+mod id;
+mod layout;
+mod list;
+mod paste;
+mod record;
+#[cfg(feature = "tmux_stream")]
+mod stream;
+mod version;
+pub use command::{quote_arg, CommandBuilder, SplitDirection};
+pub use correlator::{CommandCorrelator, CommandId, ReplyFuture};
+use error::parse_pair;
+pub use error::ParseError;
+pub use flow::FlowControl;
+pub use id::{ParseIdError, TmuxPaneId, TmuxSessionId, TmuxWindowId};
+pub use layout::{
+    diff_layouts, layout_checksum, LayoutDelta, LayoutNode, PaneLayout, WindowLayout,
+};
+pub use list::{
+    parse_list_panes, parse_list_sessions, parse_list_windows, PaneInfo, SessionInfo, WindowInfo,
+};
+pub use paste::PasteBuffer;
+pub use record::{Record, Recorder, ReplaySpeed, Replayer};
+#[cfg(feature = "tmux_stream")]
+pub use stream::EventStream;
+pub use version::ProtocolVersion;
 
 mod parser {
     use pest_derive::Parser;
@@ -14,6 +46,7 @@ mod parser {
     pub struct TmuxParser;
 }
 
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Guarded {
     pub error: bool,
@@ -23,13 +56,49 @@ pub struct Guarded {
     pub output: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct WindowLayout {
-    pub layout_id: String,
-    pub width: u64,
-    pub height: u64,
+bitflags! {
+    /// The state of a window, as shown by the `window_flags` format
+    #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct WindowFlags: u8 {
+        /// `*`: the current window of its session
+        const CURRENT = 1<<0;
+        /// `-`: the previously selected window
+        const LAST = 1<<1;
+        /// `#`: there has been activity in the window
+        const ACTIVITY = 1<<2;
+        /// `!`: a bell has occurred in the window
+        const BELL = 1<<3;
+        /// `~`: the window has been silent for the monitor interval
+        const SILENCE = 1<<4;
+        /// `M`: the window contains the marked pane
+        const MARKED = 1<<5;
+        /// `Z`: the active pane of the window is zoomed
+        const ZOOMED = 1<<6;
+    }
+}
+
+impl WindowFlags {
+    /// Parses flags such as `*Z`.  Characters that don't correspond
+    /// to a known flag are ignored.
+    pub fn from_window_flags(flags: &str) -> Self {
+        flags
+            .chars()
+            .map(|c| match c {
+                '*' => Self::CURRENT,
+                '-' => Self::LAST,
+                '#' => Self::ACTIVITY,
+                '!' => Self::BELL,
+                '~' => Self::SILENCE,
+                'M' => Self::MARKED,
+                'Z' => Self::ZOOMED,
+                _ => Self::empty(),
+            })
+            .fold(Self::empty(), |flags, flag| flags | flag)
+    }
 }
 
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Event {
     Begin {
@@ -48,9 +117,31 @@ pub enum Event {
         flags: i64,
     },
     Guarded(Guarded),
+    /// When streaming of guarded output is enabled, part of the
+    /// output of the block that `number` began
+    GuardedChunk {
+        timestamp: i64,
+        number: u64,
+        flags: i64,
+        output: String,
+    },
+    /// When streaming of guarded output is enabled, the end of a
+    /// guarded block.  Its `output` holds whatever followed the last
+    /// `GuardedChunk`.
+    GuardedDone(Guarded),
     Output {
         pane: TmuxPaneId,
-        text: String,
+        /// The decoded output.  The events produced by a `Parser`
+        /// share a buffer rather than each having its own allocation.
+        text: Bytes,
+    },
+    /// Output from a pane, sent instead of `Output` when the client
+    /// has enabled flow control
+    ExtendedOutput {
+        pane: TmuxPaneId,
+        /// How long tmux held the output before sending it
+        age: Duration,
+        text: Bytes,
     },
     Exit {
         reason: Option<String>,
@@ -75,9 +166,49 @@ pub enum Event {
     ClientDetached {
         client_name: String,
     },
+    ClientAttached {
+        client_name: String,
+    },
+    /// An error in the tmux configuration file
+    ConfigError {
+        file: String,
+        line: Option<u32>,
+        message: String,
+    },
+    /// A message that tmux would have shown in the status line
+    Message {
+        message: String,
+    },
+    PasteBufferChanged {
+        name: String,
+    },
+    PasteBufferDeleted {
+        name: String,
+    },
+    /// The value of a format that the client subscribed to with
+    /// `refresh-client -B` has changed.
+    /// The window and pane are only present for subscriptions to
+    /// windows or panes.
+    SubscriptionChanged {
+        name: String,
+        session: TmuxSessionId,
+        window: Option<TmuxWindowId>,
+        window_index: Option<u64>,
+        pane: Option<TmuxPaneId>,
+        value: String,
+    },
     PaneModeChanged {
         pane: TmuxPaneId,
     },
+    /// tmux has stopped sending output for the pane, because the
+    /// client was too far behind
+    Pause {
+        pane: TmuxPaneId,
+    },
+    /// tmux has resumed sending output for the pane
+    Continue {
+        pane: TmuxPaneId,
+    },
     WindowAdd {
         window: TmuxWindowId,
     },
@@ -92,40 +223,41 @@ pub enum Event {
         window: TmuxWindowId,
         name: String,
     },
+    /// A window was added to a session other than the one that
+    /// the client is attached to
+    UnlinkedWindowAdd {
+        window: TmuxWindowId,
+    },
+    UnlinkedWindowClose {
+        window: TmuxWindowId,
+    },
+    UnlinkedWindowRenamed {
+        window: TmuxWindowId,
+        name: String,
+    },
     LayoutChange {
         window: TmuxWindowId,
         layout: WindowLayout,
         visible_layout: Option<WindowLayout>,
-        raw_flags: Option<String>,
+        flags: WindowFlags,
+    },
+    /// A line that wasn't recognized, produced instead of an error
+    /// when the parser is lenient
+    Unknown {
+        line: String,
     },
 }
 
 fn parse_pane_id(pair: Pair<Rule>) -> anyhow::Result<TmuxPaneId> {
     match pair.as_rule() {
-        Rule::pane_id => {
-            let mut pairs = pair.into_inner();
-            pairs
-                .next()
-                .unwrap()
-                .as_str()
-                .parse()
-                .context("pane_id is somehow not digits")
-        }
+        Rule::pane_id => Ok(parse_pair(&pair)?),
         _ => anyhow::bail!("parse_pane_id can only parse Rule::pane_id, got {:?}", pair),
     }
 }
 
 fn parse_window_id(pair: Pair<Rule>) -> anyhow::Result<TmuxWindowId> {
     match pair.as_rule() {
-        Rule::window_id => {
-            let mut pairs = pair.into_inner();
-            pairs
-                .next()
-                .unwrap()
-                .as_str()
-                .parse()
-                .context("window_id is somehow not digits")
-        }
+        Rule::window_id => Ok(parse_pair(&pair)?),
         _ => anyhow::bail!(
             "parse_window_id can only parse Rule::window_id, got {:?}",
             pair
@@ -135,15 +267,7 @@ fn parse_window_id(pair: Pair<Rule>) -> anyhow::Result<TmuxWindowId> {
 
 fn parse_session_id(pair: Pair<Rule>) -> anyhow::Result<TmuxSessionId> {
     match pair.as_rule() {
-        Rule::session_id => {
-            let mut pairs = pair.into_inner();
-            pairs
-                .next()
-                .unwrap()
-                .as_str()
-                .parse()
-                .context("session_id is somehow not digits")
-        }
+        Rule::session_id => Ok(parse_pair(&pair)?),
         _ => anyhow::bail!(
             "parse_session_id can only parse Rule::session_id, got {:?}",
             pair
@@ -151,39 +275,90 @@ fn parse_session_id(pair: Pair<Rule>) -> anyhow::Result<TmuxSessionId> {
     }
 }
 
+/// Determines the zoomed pane of a window.
+/// When a window is zoomed, its visible layout holds just the zoomed
+/// pane, whereas its layout holds all of the panes.
+fn zoomed_pane(
+    layout: &WindowLayout,
+    visible_layout: Option<&WindowLayout>,
+    flags: WindowFlags,
+) -> Option<TmuxPaneId> {
+    if !flags.contains(WindowFlags::ZOOMED) {
+        return None;
+    }
+    let visible_layout = visible_layout?;
+    match &visible_layout.root {
+        LayoutNode::Leaf(pane) if visible_layout.root != layout.root => Some(pane.pane_id),
+        _ => None,
+    }
+}
+
 /// Parses a %begin, %end, %error guard line tuple
 fn parse_guard(mut pairs: Pairs<Rule>) -> anyhow::Result<(i64, u64, i64)> {
-    let timestamp = pairs.next().unwrap().as_str().parse::<i64>()?;
-    let number = pairs.next().unwrap().as_str().parse::<u64>()?;
-    let flags = pairs.next().unwrap().as_str().parse::<i64>()?;
+    let timestamp = parse_pair(&pairs.next().unwrap())?;
+    let number = parse_pair(&pairs.next().unwrap())?;
+    let flags = parse_pair(&pairs.next().unwrap())?;
     Ok((timestamp, number, flags))
 }
 
-/// Parses a window_layout line, for example "b25d,80x24,0,0,0"
-fn parse_window_layout(pair: Pair<Rule>) -> Option<WindowLayout> {
-    match pair.as_rule() {
-        Rule::window_layout => {
-            let mut pairs = pair.into_inner();
-            let layout_id_option = pairs.next()?.as_str().parse::<String>().ok();
-            let width_option = pairs.next()?.as_str().parse::<u64>().ok();
-            let height_option = pairs.next()?.as_str().parse::<u64>().ok();
-            if let (Some(layout_id), Some(width), Some(height)) =
-                (layout_id_option, width_option, height_option)
-            {
-                return Some(WindowLayout {
-                    layout_id,
-                    width,
-                    height,
-                });
+/// Splits a configuration error, which has the form
+/// `file:line: message`, or `file: message` if there is no line
+/// number, into those parts
+fn parse_config_error(error: &str) -> (String, Option<u32>, String) {
+    // The file name may itself contain colons, so look for the
+    // first `:line: ` rather than the first colon
+    for (idx, _) in error.match_indices(':') {
+        let rest = &error[idx + 1..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            continue;
+        }
+        if let Some(message) = rest[digits..].strip_prefix(": ") {
+            if let Ok(line) = rest[..digits].parse() {
+                return (error[..idx].to_owned(), Some(line), message.to_owned());
             }
-            return None;
         }
-        _ => None,
+    }
+    match error.split_once(": ") {
+        Some((file, message)) => (file.to_owned(), None, message.to_owned()),
+        None => (String::new(), None, error.to_owned()),
     }
 }
 
-fn parse_line(line: &str) -> anyhow::Result<Event> {
-    let mut pairs = parser::TmuxParser::parse(Rule::line_entire, line)?;
+/// Decodes a vis encoded field
+fn unvis_pair(pair: &Pair<Rule>) -> anyhow::Result<String> {
+    Ok(unvis(pair.as_str()).map_err(|err| ParseError::in_pair(pair, err))?)
+}
+
+/// Decodes the text of `%output` or `%extended-output` into `output`,
+/// and splits it off.
+fn decode_output(encoded: &[u8], output: &mut BytesMut) -> anyhow::Result<Bytes> {
+    // The decoded text is never longer than its encoding.
+    // If the events that previously shared the buffer have
+    // all been dropped, this reclaims its allocation.
+    output.reserve(encoded.len());
+    if let Err(err) = unvis_into(encoded, output) {
+        // Don't leave a partially decoded line for the next event
+        output.clear();
+        return Err(err);
+    }
+    Ok(output.split().freeze())
+}
+
+/// Decodes the text of the `any_text` pair of `%output` or
+/// `%extended-output`
+fn decode_output_pair(pair: &Pair<Rule>, output: &mut BytesMut) -> anyhow::Result<Bytes> {
+    Ok(decode_output(pair.as_str().as_bytes(), output)
+        .map_err(|err| ParseError::in_pair(pair, err))?)
+}
+
+/// Parses a single notification line.
+/// The text of `%output` is decoded into `output`, from which it is
+/// split off, so that output events can share a buffer.
+/// Unless `lenient_layouts` is set, layouts must have a valid checksum.
+fn parse_line(line: &str, output: &mut BytesMut, lenient_layouts: bool) -> anyhow::Result<Event> {
+    let mut pairs =
+        parser::TmuxParser::parse(Rule::line_entire, line).map_err(ParseError::from_pest)?;
     let pair = pairs.next().ok_or_else(|| anyhow::anyhow!("no pairs!?"))?;
     match pair.as_rule() {
         Rule::begin => {
@@ -221,6 +396,16 @@ fn parse_line(line: &str) -> anyhow::Result<Event> {
             let pane = parse_pane_id(pairs.next().unwrap())?;
             Ok(Event::PaneModeChanged { pane })
         }
+        Rule::pause => {
+            let mut pairs = pair.into_inner();
+            let pane = parse_pane_id(pairs.next().unwrap())?;
+            Ok(Event::Pause { pane })
+        }
+        Rule::r#continue => {
+            let mut pairs = pair.into_inner();
+            let pane = parse_pane_id(pairs.next().unwrap())?;
+            Ok(Event::Continue { pane })
+        }
         Rule::window_add => {
             let mut pairs = pair.into_inner();
             let window = parse_window_id(pairs.next().unwrap())?;
@@ -240,26 +425,99 @@ fn parse_line(line: &str) -> anyhow::Result<Event> {
         Rule::window_renamed => {
             let mut pairs = pair.into_inner();
             let window = parse_window_id(pairs.next().unwrap())?;
-            let name = unvis(pairs.next().unwrap().as_str())?;
+            let name = unvis_pair(&pairs.next().unwrap())?;
             Ok(Event::WindowRenamed { window, name })
         }
         Rule::output => {
             let mut pairs = pair.into_inner();
             let pane = parse_pane_id(pairs.next().unwrap())?;
-            let text = unvis(pairs.next().unwrap().as_str())?;
+            let text = decode_output_pair(&pairs.next().unwrap(), output)?;
             Ok(Event::Output { pane, text })
         }
+        Rule::extended_output => {
+            let mut pairs = pair.into_inner();
+            let pane = parse_pane_id(pairs.next().unwrap())?;
+            let age = Duration::from_millis(parse_pair(&pairs.next().unwrap())?);
+            // Skip any arguments that precede the data
+            let data = pairs.find(|pair| pair.as_rule() == Rule::any_text).unwrap();
+            let text = decode_output_pair(&data, output)?;
+            Ok(Event::ExtendedOutput { pane, age, text })
+        }
+        Rule::client_attached => {
+            let mut pairs = pair.into_inner();
+            let client_name = unvis_pair(&pairs.next().unwrap())?;
+            Ok(Event::ClientAttached { client_name })
+        }
+        Rule::config_error => {
+            let mut pairs = pair.into_inner();
+            let (file, line, message) = parse_config_error(pairs.next().unwrap().as_str());
+            Ok(Event::ConfigError {
+                file,
+                line,
+                message,
+            })
+        }
+        Rule::message => {
+            let mut pairs = pair.into_inner();
+            let message = pairs.next().unwrap().as_str().to_owned();
+            Ok(Event::Message { message })
+        }
+        Rule::paste_buffer_changed => {
+            let mut pairs = pair.into_inner();
+            let name = unvis_pair(&pairs.next().unwrap())?;
+            Ok(Event::PasteBufferChanged { name })
+        }
+        Rule::paste_buffer_deleted => {
+            let mut pairs = pair.into_inner();
+            let name = unvis_pair(&pairs.next().unwrap())?;
+            Ok(Event::PasteBufferDeleted { name })
+        }
+        Rule::subscription_changed => {
+            let mut pairs = pair.into_inner();
+            let name = pairs.next().unwrap().as_str().to_owned();
+            let session = parse_session_id(pairs.next().unwrap())?;
+            // Each of these is either the value or `-`
+            let mut optional = || pairs.next().unwrap().into_inner().next();
+            let window = optional().map(parse_window_id).transpose()?;
+            let window_index = optional().map(|pair| parse_pair(&pair)).transpose()?;
+            let pane = optional().map(parse_pane_id).transpose()?;
+            let value = pairs.next().unwrap().as_str().to_owned();
+            Ok(Event::SubscriptionChanged {
+                name,
+                session,
+                window,
+                window_index,
+                pane,
+                value,
+            })
+        }
+        Rule::unlinked_window_add => {
+            let mut pairs = pair.into_inner();
+            let window = parse_window_id(pairs.next().unwrap())?;
+            Ok(Event::UnlinkedWindowAdd { window })
+        }
+        Rule::unlinked_window_close => {
+            let mut pairs = pair.into_inner();
+            let window = parse_window_id(pairs.next().unwrap())?;
+            Ok(Event::UnlinkedWindowClose { window })
+        }
+        Rule::unlinked_window_renamed => {
+            let mut pairs = pair.into_inner();
+            let window = parse_window_id(pairs.next().unwrap())?;
+            let name = unvis_pair(&pairs.next().unwrap())?;
+            Ok(Event::UnlinkedWindowRenamed { window, name })
+        }
         Rule::session_changed => {
             let mut pairs = pair.into_inner();
             let session = parse_session_id(pairs.next().unwrap())?;
-            let name = unvis(pairs.next().unwrap().as_str())?;
+            let name = unvis_pair(&pairs.next().unwrap())?;
             Ok(Event::SessionChanged { session, name })
         }
         Rule::client_session_changed => {
             let mut pairs = pair.into_inner();
-            let client_name = unvis(pairs.next().unwrap().as_str())?;
+            let client_name = unvis_pair(&pairs.next().unwrap())?;
             let session = parse_session_id(pairs.next().unwrap())?;
-            let session_name = unvis(pairs.next().unwrap().as_str())?;
+            let session_name = unvis_pair(&pairs.next().unwrap())?;
             Ok(Event::ClientSessionChanged {
                 client_name,
                 session,
@@ -268,12 +526,12 @@ fn parse_line(line: &str) -> anyhow::Result<Event> {
         }
         Rule::client_detached => {
             let mut pairs = pair.into_inner();
-            let client_name = unvis(pairs.next().unwrap().as_str())?;
+            let client_name = unvis_pair(&pairs.next().unwrap())?;
             Ok(Event::ClientDetached { client_name })
         }
         Rule::session_renamed => {
             let mut pairs = pair.into_inner();
-            let name = unvis(pairs.next().unwrap().as_str())?;
+            let name = unvis_pair(&pairs.next().unwrap())?;
             Ok(Event::SessionRenamed { name })
         }
         Rule::session_window_changed => {
@@ -285,14 +543,25 @@ fn parse_line(line: &str) -> anyhow::Result<Event> {
         Rule::layout_change => {
             let mut pairs = pair.into_inner();
             let window = parse_window_id(pairs.next().unwrap())?;
-            let layout = pairs.next().and_then(parse_window_layout).unwrap();
-            let visible_layout = pairs.next().and_then(parse_window_layout);
-            let raw_flags = pairs.next().map(|r| r.as_str().to_owned());
+            let mut layout = layout::parse_window_layout(pairs.next().unwrap(), lenient_layouts)?;
+            let mut visible_layout = pairs
+                .next()
+                .map(|pair| layout::parse_window_layout(pair, lenient_layouts))
+                .transpose()?;
+            let flags = pairs
+                .next()
+                .map(|r| WindowFlags::from_window_flags(r.as_str()))
+                .unwrap_or_default();
+            let zoomed = zoomed_pane(&layout, visible_layout.as_ref(), flags);
+            layout.set_zoomed(zoomed);
+            if let Some(visible_layout) = &mut visible_layout {
+                visible_layout.set_zoomed(zoomed);
+            }
             Ok(Event::LayoutChange {
                 window,
                 layout,
                 visible_layout,
-                raw_flags,
+                flags,
             })
         }
         Rule::pane_id
@@ -301,9 +570,19 @@ fn parse_line(line: &str) -> anyhow::Result<Event> {
         | Rule::window_id
         | Rule::session_id
         | Rule::window_layout
+        | Rule::layout_checksum
+        | Rule::layout_cell
+        | Rule::layout_left_right
+        | Rule::layout_top_bottom
         | Rule::any_text
         | Rule::line
         | Rule::line_entire
+        | Rule::extended_output_arg
+        | Rule::subscription_name
+        | Rule::subscription_window
+        | Rule::subscription_window_index
+        | Rule::subscription_pane
+        | Rule::window_layout_entire
         | Rule::EOI
         | Rule::number => unreachable!(),
     }
@@ -312,6 +591,19 @@ fn parse_line(line: &str) -> anyhow::Result<Event> {
 /// Decode OpenBSD `vis` encoded strings
 /// See: https://github.com/tmux/tmux/blob/486ce9b09855ae30a2bf5e576cb6f7ad37792699/compat/unvis.c
 pub fn unvis(s: &str) -> anyhow::Result<String> {
+    String::from_utf8(unvis_bytes(s.as_bytes())?)
+        .map_err(|err| anyhow::anyhow!("Unescaped string is not valid UTF8: {}", err))
+}
+
+/// Decode OpenBSD `vis` encoded bytes, which need not be UTF-8
+pub fn unvis_bytes(s: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut result = Vec::with_capacity(s.len());
+    unvis_into(s, &mut result)?;
+    Ok(result)
+}
+
+/// Decodes `s`, appending the result to `result`
+fn unvis_into<B: BufMut>(s: &[u8], result: &mut B) -> anyhow::Result<()> {
     enum State {
         Ground,
         Start,
@@ -323,27 +615,26 @@ pub fn unvis(s: &str) -> anyhow::Result<String> {
     }
 
     let mut state = State::Ground;
-    let mut result: Vec<u8> = vec![];
-    let mut bytes = s.as_bytes().iter();
+    let mut bytes = s.iter();
 
     fn is_octal(b: u8) -> bool {
         b >= b'0' && b <= b'7'
     }
 
-    fn unvis_byte(b: u8, state: &mut State, result: &mut Vec<u8>) -> anyhow::Result<bool> {
+    fn unvis_byte<B: BufMut>(b: u8, state: &mut State, result: &mut B) -> anyhow::Result<bool> {
         match state {
             State::Ground => {
                 if b == b'\\' {
                     *state = State::Start;
                 } else {
-                    result.push(b);
+                    result.put_u8(b);
                 }
             }
 
             State::Start => {
                 match b {
                     b'\\' => {
-                        result.push(b'\\');
+                        result.put_u8(b'\\');
                         *state = State::Ground;
                     }
                     b'0' | b'1' | b'2' | b'3' | b'4' | b'5' | b'6' | b'7' => {
@@ -357,44 +648,44 @@ pub fn unvis(s: &str) -> anyhow::Result<String> {
                         *state = State::Ctrl(0);
                     }
                     b'n' => {
-                        result.push(b'\n');
+                        result.put_u8(b'\n');
                         *state = State::Ground;
                     }
                     b'r' => {
-                        result.push(b'\r');
+                        result.put_u8(b'\r');
                         *state = State::Ground;
                     }
                     b'b' => {
-                        result.push(b'\x08');
+                        result.put_u8(b'\x08');
                         *state = State::Ground;
                     }
                     b'a' => {
-                        result.push(b'\x07');
+                        result.put_u8(b'\x07');
                         *state = State::Ground;
                     }
                     b'v' => {
-                        result.push(b'\x0b');
+                        result.put_u8(b'\x0b');
                         *state = State::Ground;
                     }
                     b't' => {
-                        result.push(b'\t');
+                        result.put_u8(b'\t');
                         *state = State::Ground;
                     }
                     b'f' => {
-                        result.push(b'\x0c');
+                        result.put_u8(b'\x0c');
                         *state = State::Ground;
                     }
                     b's' => {
-                        result.push(b' ');
+                        result.put_u8(b' ');
                         *state = State::Ground;
                     }
                     b'E' => {
-                        result.push(b'\x1b');
+                        result.put_u8(b'\x1b');
                         *state = State::Ground;
                     }
                     b'\n' => {
                         // Hidden newline
-                        // result.push(b'\n');
+                        // result.put_u8(b'\n');
                         *state = State::Ground;
                     }
                     b'$' => {
@@ -419,15 +710,15 @@ pub fn unvis(s: &str) -> anyhow::Result<String> {
             }
 
             State::Meta1 => {
-                result.push(b | 0o200);
+                result.put_u8(b | 0o200);
                 *state = State::Ground;
             }
 
             State::Ctrl(c) => {
                 if b == b'?' {
-                    result.push(*c | 0o177);
+                    result.put_u8(*c | 0o177);
                 } else {
-                    result.push((b & 0o37) | *c);
+                    result.put_u8((b & 0o37) | *c);
                 }
                 *state = State::Ground;
             }
@@ -439,7 +730,7 @@ pub fn unvis(s: &str) -> anyhow::Result<String> {
                     *state = State::Octal3(value);
                 } else {
                     // Prior character was a single octal value
-                    result.push(*prior);
+                    result.put_u8(*prior);
                     *state = State::Ground;
                     // re-process the current byte
                     return Ok(true);
@@ -450,11 +741,11 @@ pub fn unvis(s: &str) -> anyhow::Result<String> {
                 if is_octal(b) {
                     // It's the third in a 3 byte octal sequence
                     let value = (*prior << 3) + (b - b'0');
-                    result.push(value);
+                    result.put_u8(value);
                     *state = State::Ground;
                 } else {
                     // Prior was a 2-byte octal sequence
-                    result.push(*prior);
+                    result.put_u8(*prior);
                     *state = State::Ground;
                     // re-process the current byte
                     return Ok(true);
@@ -466,19 +757,86 @@ pub fn unvis(s: &str) -> anyhow::Result<String> {
     }
 
     while let Some(&b) = bytes.next() {
-        let again = unvis_byte(b, &mut state, &mut result)?;
+        let again = unvis_byte(b, &mut state, result)?;
         if again {
-            unvis_byte(b, &mut state, &mut result)?;
+            unvis_byte(b, &mut state, result)?;
         }
     }
 
-    String::from_utf8(result)
-        .map_err(|err| anyhow::anyhow!("Unescaped string is not valid UTF8: {}", err))
+    Ok(())
+}
+
+/// Encode `s` in the OpenBSD `vis` style that `unvis` decodes.
+/// Backslashes, control characters and DEL are encoded as octal
+/// escapes; everything else, including non-ASCII text, is unchanged.
+pub fn vis(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_ascii() {
+            vis_byte(c as u8, &mut result);
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Encode `bytes` in the OpenBSD `vis` style that `unvis_bytes`
+/// decodes.  Unlike `vis`, bytes outside the printable ASCII range
+/// are always encoded, so the input need not be UTF-8.
+pub fn vis_bytes(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+    for &b in bytes {
+        vis_byte(b, &mut result);
+    }
+    result
+}
+
+fn vis_byte(b: u8, result: &mut String) {
+    match b {
+        b'\\' => result.push_str("\\\\"),
+        // Always use three digits, so that a digit that follows
+        // isn't taken to be part of the escape
+        b if !(0x20..0x7f).contains(&b) => {
+            use std::fmt::Write;
+            write!(result, "\\{:03o}", b).expect("writing to a String cannot fail");
+        }
+        b => result.push(b as char),
+    }
+}
+
+/// How `Parser` handles text that isn't valid UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Utf8Policy {
+    /// The text of `%output` and `%extended-output` is passed through
+    /// unchanged, as the bytes that were written to the pane.  Other
+    /// lines are decoded as for `Lossy`.
+    #[default]
+    Raw,
+    /// Invalid sequences are replaced with U+FFFD, both in the text
+    /// of output and in other lines
+    Lossy,
+    /// A line, or the text of output, that isn't valid UTF-8 is
+    /// returned as an error
+    Strict,
 }
 
 pub struct Parser {
     buffer: Vec<u8>,
     begun: Option<Guarded>,
+    /// Holds the decoded text of output events
+    output: BytesMut,
+    guarded_chunk_size: Option<usize>,
+    max_output_size: Option<usize>,
+    /// Set once part of the text of the line in `buffer` has been
+    /// produced by `output_chunk`
+    output_chunked: bool,
+    lenient: bool,
+    lenient_layouts: bool,
+    version: Option<ProtocolVersion>,
+    utf8_policy: Utf8Policy,
+    /// Set by `resync` until the start of a line is found
+    resyncing: bool,
 }
 
 impl Parser {
@@ -486,15 +844,192 @@ impl Parser {
         Self {
             buffer: vec![],
             begun: None,
+            output: BytesMut::new(),
+            guarded_chunk_size: None,
+            max_output_size: None,
+            output_chunked: false,
+            lenient: false,
+            lenient_layouts: false,
+            version: None,
+            utf8_policy: Utf8Policy::default(),
+            resyncing: false,
         }
     }
 
-    pub fn advance_byte(&mut self, c: u8) -> anyhow::Result<Option<Event>> {
+    /// When `lenient` is true, a line that isn't recognized produces
+    /// `Event::Unknown` and parsing continues, rather than the line
+    /// being returned as an error.  This allows notifications that
+    /// were added in newer versions of tmux to be skipped.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// When `lenient` is true, the checksums of layouts aren't
+    /// verified, so a layout whose checksum doesn't match is accepted
+    /// rather than the line being treated as unrecognized.
+    pub fn set_lenient_layouts(&mut self, lenient: bool) {
+        self.lenient_layouts = lenient;
+    }
+
+    /// Sets the version of tmux that is being parsed, once it is known.
+    /// Lines from a version newer than `ProtocolVersion::NEWEST_KNOWN`
+    /// that aren't recognized are treated as though the parser were
+    /// lenient, as they are likely to be notifications that were added
+    /// in that version.
+    pub fn set_protocol_version(&mut self, version: ProtocolVersion) {
+        self.version.replace(version);
+    }
+
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.version
+    }
+
+    /// Sets how text that isn't valid UTF-8 is handled.
+    /// tmux may divide the text of a pane's output between lines
+    /// part way through a character, as may `set_max_output_size`,
+    /// so with `Lossy` or `Strict` such a character is replaced or
+    /// rejected even though the output as a whole is valid.
+    pub fn set_utf8_policy(&mut self, policy: Utf8Policy) {
+        self.utf8_policy = policy;
+    }
+
+    pub fn utf8_policy(&self) -> Utf8Policy {
+        self.utf8_policy
+    }
+
+    /// Applies the UTF-8 policy to the text of an output event,
+    /// which was decoded from the line in `buffer`
+    fn checked_output(&self, event: Event) -> anyhow::Result<Event> {
+        let (pane, text) = match &event {
+            Event::Output { pane, text } | Event::ExtendedOutput { pane, text, .. } => {
+                (*pane, text)
+            }
+            _ => return Ok(event),
+        };
+        let err = match (self.utf8_policy, std::str::from_utf8(text)) {
+            (Utf8Policy::Raw, _) | (_, Ok(_)) => return Ok(event),
+            (_, Err(err)) => err,
+        };
+        if self.utf8_policy == Utf8Policy::Strict {
+            return Err(
+                anyhow::anyhow!("output from pane {} is not valid UTF-8: {}", pane, err)
+                    .context(String::from_utf8_lossy(&self.buffer).into_owned()),
+            );
+        }
+        let lossy = Bytes::from(String::from_utf8_lossy(text).into_owned());
+        Ok(match event {
+            Event::ExtendedOutput { pane, age, .. } => Event::ExtendedOutput {
+                pane,
+                age,
+                text: lossy,
+            },
+            _ => Event::Output { pane, text: lossy },
+        })
+    }
+
+    fn is_lenient(&self) -> bool {
+        self.lenient
+            || self
+                .version
+                .map(|version| version > ProtocolVersion::NEWEST_KNOWN)
+                .unwrap_or(false)
+    }
+
+    /// When `Some`, rather than accumulating the output of a guarded
+    /// block and producing a single `Event::Guarded`, the output is
+    /// produced in `Event::GuardedChunk`s of around `size` bytes,
+    /// followed by an `Event::GuardedDone`.
+    /// A chunk holds whole lines, so a chunk with a line longer than
+    /// `size` is correspondingly larger.
+    pub fn set_guarded_chunk_size(&mut self, size: Option<usize>) {
+        self.guarded_chunk_size = size;
+    }
+
+    /// When `Some`, the text of `%output` and `%extended-output` is
+    /// produced in events of no more than `size` bytes, as each piece
+    /// of the line is received, rather than in a single event once the
+    /// whole line has been received.  `size` is at least 4 bytes.
+    pub fn set_max_output_size(&mut self, size: Option<usize>) {
+        self.max_output_size = size.map(|size| size.max(4));
+    }
+
+    /// Produces the text received so far in an output line, once it
+    /// has reached the maximum size
+    fn output_chunk(&mut self) -> Option<Event> {
+        let size = self.max_output_size?;
+        if self.begun.is_some() || self.buffer.len() <= size {
+            return None;
+        }
+        let header = fast::parse_output_header(&self.buffer)?;
+        let encoded = &self.buffer[header.len..];
+        // Wait for a byte beyond the chunk, so that a chunk never
+        // includes the `\r` of a line ending in `\r\n`
+        if encoded.len() <= size {
+            return None;
+        }
+        // The text is never longer than its encoding
+        let complete = fast::complete_escapes(&encoded[..size]);
+        let text = decode_output(&encoded[..complete], &mut self.output).ok()?;
+        self.buffer.drain(header.len..header.len + complete);
+        self.output_chunked = true;
+        Some(header.event(text))
+    }
+
+    fn guarded_complete(&self, begun: Guarded) -> Event {
+        if self.guarded_chunk_size.is_some() {
+            Event::GuardedDone(begun)
+        } else {
+            Event::Guarded(begun)
+        }
+    }
+
+    /// Discards the partially received line and guarded block,
+    /// keeping the configuration of the parser.
+    /// This is the way to continue after a line is returned as an
+    /// error, as the line is otherwise left in the buffer.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.begun.take();
+        self.output.clear();
+        self.output_chunked = false;
+        self.resyncing = false;
+    }
+
+    /// Resets the parser and then discards the input that follows,
+    /// up to and including the next newline, or up to the next
+    /// `%begin`, whichever comes first.
+    /// This recovers from input having been lost, which may have left
+    /// the parser part way through a line.
+    pub fn resync(&mut self) {
+        self.reset();
+        self.resyncing = true;
+    }
+
+    fn resync_byte(&mut self, c: u8) {
+        const BEGIN: &[u8] = b"%begin ";
         if c == b'\n' {
-            self.process_line()
+            self.buffer.clear();
+            self.resyncing = false;
         } else {
             self.buffer.push(c);
+            if self.buffer.ends_with(BEGIN) {
+                self.buffer.drain(..self.buffer.len() - BEGIN.len());
+                self.resyncing = false;
+            }
+        }
+    }
+
+    pub fn advance_byte(&mut self, c: u8) -> anyhow::Result<Option<Event>> {
+        if self.resyncing {
+            self.resync_byte(c);
             Ok(None)
+        } else if c == b'\n' {
+            self.process_line()
+        } else {
+            self.buffer.push(c);
+            self.output_chunk()
+                .map(|event| self.checked_output(event))
+                .transpose()
         }
     }
 
@@ -513,11 +1048,8 @@ impl Parser {
                 }
                 Err(err) => {
                     // concat remained bytes after digested bytes
-                    return Err(anyhow::anyhow!(format!(
-                        "{}{}",
-                        err,
-                        String::from_utf8_lossy(&bytes[i..])
-                    )));
+                    let unparsed = format!("{}{}", err, String::from_utf8_lossy(&bytes[i..]));
+                    return Err(err.context(unparsed));
                 }
             }
         }
@@ -525,8 +1057,10 @@ impl Parser {
     }
 
     fn process_guarded_line(&mut self, line: String) -> anyhow::Result<Option<Event>> {
-        let result = match parse_line(&line) {
-            Ok(Event::End {
+        // Only the end of the block is of interest, so the
+        // grammar isn't needed
+        let result = match fast::parse_guard_line(line.as_bytes()) {
+            Some(Event::End {
                 timestamp,
                 number,
                 flags,
@@ -536,7 +1070,7 @@ impl Parser {
                         && begun.number == number
                         && begun.flags == flags
                     {
-                        Some(Event::Guarded(begun))
+                        Some(self.guarded_complete(begun))
                     } else {
                         log::error!("mismatched %end; expected {:?} but got {}", begun, line);
                         None
@@ -546,7 +1080,7 @@ impl Parser {
                     None
                 }
             }
-            Ok(Event::Error {
+            Some(Event::Error {
                 timestamp,
                 number,
                 flags,
@@ -557,7 +1091,7 @@ impl Parser {
                         && begun.flags == flags
                     {
                         begun.error = true;
-                        Some(Event::Guarded(begun))
+                        Some(self.guarded_complete(begun))
                     } else {
                         log::error!("mismatched %error; expected {:?} but got {}", begun, line);
                         None
@@ -571,7 +1105,15 @@ impl Parser {
                 let begun = self.begun.as_mut().unwrap();
                 begun.output.push_str(&line);
                 begun.output.push('\n');
-                None
+                match self.guarded_chunk_size {
+                    Some(size) if begun.output.len() >= size => Some(Event::GuardedChunk {
+                        timestamp: begun.timestamp,
+                        number: begun.number,
+                        flags: begun.flags,
+                        output: std::mem::take(&mut begun.output),
+                    }),
+                    _ => None,
+                }
             }
         };
         self.buffer.clear();
@@ -582,40 +1124,64 @@ impl Parser {
         if self.buffer.last() == Some(&b'\r') {
             self.buffer.pop();
         }
-        let result = match std::str::from_utf8(&self.buffer) {
-            Ok(line) => {
-                if self.begun.is_some() {
-                    let line = line.to_owned();
-                    return self.process_guarded_line(line);
-                }
-                match parse_line(line) {
-                    Ok(Event::Begin {
-                        timestamp,
-                        number,
-                        flags,
-                    }) => {
-                        if self.begun.is_some() {
-                            log::error!("expected %end or %error before %begin ({})", line);
-                        }
-                        self.begun.replace(Guarded {
-                            timestamp,
-                            number,
-                            flags,
-                            error: false,
-                            output: String::new(),
-                        });
+        let chunked = std::mem::take(&mut self.output_chunked);
+        if self.begun.is_none() {
+            if let Some(event) = fast::parse_output(&self.buffer, &mut self.output) {
+                let event = match event {
+                    // All of the text was in the chunks
+                    Event::Output { text, .. } | Event::ExtendedOutput { text, .. }
+                        if chunked && text.is_empty() =>
+                    {
                         None
                     }
-                    Ok(event) => Some(event),
-                    Err(err) => {
-                        log::error!("Unrecognized tmux cc line: {}", err);
-                        return Err(anyhow::anyhow!(line.to_owned()));
-                    }
+                    event => Some(self.checked_output(event)?),
+                };
+                self.buffer.clear();
+                return Ok(event);
+            }
+        }
+        let line = match std::str::from_utf8(&self.buffer) {
+            Ok(line) => Cow::Borrowed(line),
+            Err(err) if self.utf8_policy == Utf8Policy::Strict => {
+                let line = String::from_utf8_lossy(&self.buffer).into_owned();
+                return Err(anyhow::anyhow!("line is not valid UTF-8: {}", err).context(line));
+            }
+            Err(_) => String::from_utf8_lossy(&self.buffer),
+        };
+        if self.begun.is_some() {
+            let line = line.into_owned();
+            return self.process_guarded_line(line);
+        }
+        let result = match parse_line(&line, &mut self.output, self.lenient_layouts) {
+            Ok(Event::Begin {
+                timestamp,
+                number,
+                flags,
+            }) => {
+                if self.begun.is_some() {
+                    log::error!("expected %end or %error before %begin ({})", line);
                 }
+                self.begun.replace(Guarded {
+                    timestamp,
+                    number,
+                    flags,
+                    error: false,
+                    output: String::new(),
+                });
+                None
+            }
+            Ok(event) => Some(event),
+            Err(err) if self.is_lenient() => {
+                log::debug!("Unrecognized tmux cc line: {}", err);
+                Some(Event::Unknown {
+                    line: line.into_owned(),
+                })
             }
             Err(err) => {
-                log::error!("Failed to parse line from tmux: {}", err);
-                None
+                log::error!("Unrecognized tmux cc line: {}", err);
+                // The error displays as just the line, which the
+                // escape parser falls back to parsing as output
+                return Err(err.context(line.into_owned()));
             }
         };
         self.buffer.clear();
@@ -628,6 +1194,21 @@ mod tests {
     use super::*;
     use k9::assert_equal as assert_eq;
 
+    #[test]
+    fn vis_round_trip() {
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        let encoded = vis_bytes(&all_bytes);
+        assert!(encoded.is_ascii());
+        assert_eq!(unvis_bytes(encoded.as_bytes()).unwrap(), all_bytes);
+
+        assert_eq!(vis_bytes(b"a\\b\x1b[1m\n7"), "a\\\\b\\033[1m\\0127");
+
+        let text = "caf\u{e9} \u{1f600}\t\\0\x7f";
+        let encoded = vis(text);
+        assert_eq!(encoded, "caf\u{e9} \u{1f600}\\011\\\\0\\177");
+        assert_eq!(unvis(&encoded).unwrap(), text);
+    }
+
     #[test]
     fn test_parse_line() {
         let _ = env_logger::Builder::new()
@@ -641,7 +1222,7 @@ mod tests {
                 number: 321,
                 flags: 0,
             },
-            parse_line("%begin 12345 321 0").unwrap()
+            parse_line("%begin 12345 321 0", &mut BytesMut::new(), false).unwrap()
         );
 
         assert_eq!(
@@ -650,10 +1231,527 @@ mod tests {
                 number: 321,
                 flags: 0,
             },
-            parse_line("%end 12345 321 0").unwrap()
+            parse_line("%end 12345 321 0", &mut BytesMut::new(), false).unwrap()
         );
     }
 
+    #[test]
+    fn output_need_not_be_utf8() {
+        let mut p = Parser::new();
+        let events = p
+            .advance_bytes(b"%output %1 \\377a\\015\n%output %2 b\n")
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                Event::Output {
+                    pane: TmuxPaneId(1),
+                    text: Bytes::from_static(b"\xffa\r"),
+                },
+                Event::Output {
+                    pane: TmuxPaneId(2),
+                    text: Bytes::from_static(b"b"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn utf8_policy() {
+        let input = b"%output %1 \\377ok\n%window-renamed @1 \xffname\n";
+        let output = |text: &'static [u8]| Event::Output {
+            pane: TmuxPaneId(1),
+            text: Bytes::from_static(text),
+        };
+
+        let mut p = Parser::new();
+        assert_eq!(p.utf8_policy(), Utf8Policy::Raw);
+        assert_eq!(
+            p.advance_bytes(input).unwrap(),
+            vec![
+                output(b"\xffok"),
+                Event::WindowRenamed {
+                    window: TmuxWindowId(1),
+                    name: "\u{fffd}name".to_owned(),
+                },
+            ]
+        );
+
+        let mut p = Parser::new();
+        p.set_utf8_policy(Utf8Policy::Lossy);
+        assert_eq!(
+            p.advance_bytes(input).unwrap()[0],
+            output("\u{fffd}ok".as_bytes())
+        );
+
+        let mut p = Parser::new();
+        p.set_utf8_policy(Utf8Policy::Strict);
+        let err = p.advance_bytes(input).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            "output from pane %1 is not valid UTF-8: \
+             invalid utf-8 sequence of 1 bytes from index 0"
+        );
+        p.reset();
+        assert!(p.advance_bytes(b"%window-renamed @1 \xffname\n").is_err());
+        p.reset();
+        assert_eq!(
+            p.advance_bytes(b"%output %1 ok\n").unwrap(),
+            vec![output(b"ok")]
+        );
+    }
+
+    #[test]
+    fn reset_and_resync() {
+        let mut p = Parser::new();
+        assert!(p.advance_bytes(b"%bogus\n").is_err());
+        p.reset();
+        assert_eq!(
+            p.advance_bytes(b"%window-add @1\n").unwrap(),
+            vec![Event::WindowAdd {
+                window: TmuxWindowId(1)
+            }]
+        );
+
+        // Part way through a guarded block
+        p.advance_bytes(b"%begin 1 2 1\npartial").unwrap();
+        p.resync();
+        assert_eq!(
+            p.advance_bytes(b" of a lost line\n%window-add @2\n")
+                .unwrap(),
+            vec![Event::WindowAdd {
+                window: TmuxWindowId(2)
+            }]
+        );
+
+        p.resync();
+        assert_eq!(
+            p.advance_bytes(b"lost%begin 1 3 1\nreply\n%end 1 3 1\n")
+                .unwrap(),
+            vec![Event::Guarded(Guarded {
+                error: false,
+                timestamp: 1,
+                number: 3,
+                flags: 1,
+                output: "reply\n".to_owned(),
+            })]
+        );
+    }
+
+    #[test]
+    fn max_output_size() {
+        let mut p = Parser::new();
+        p.set_max_output_size(Some(8));
+        let input = b"%output %1 abcdefgh\\033[1mxyz\r\n\
+            %extended-output %2 5 : 0123456789abcdef\n\
+            %output %1 a\\134\\134\n";
+        let events = p.advance_bytes(input).unwrap();
+        let text = Bytes::from_static;
+        assert_eq!(
+            events,
+            vec![
+                Event::Output {
+                    pane: TmuxPaneId(1),
+                    text: text(b"abcdefgh"),
+                },
+                Event::Output {
+                    pane: TmuxPaneId(1),
+                    text: text(b"\x1b[1mx"),
+                },
+                Event::Output {
+                    pane: TmuxPaneId(1),
+                    text: text(b"yz"),
+                },
+                Event::ExtendedOutput {
+                    pane: TmuxPaneId(2),
+                    age: Duration::from_millis(5),
+                    text: text(b"01234567"),
+                },
+                Event::ExtendedOutput {
+                    pane: TmuxPaneId(2),
+                    age: Duration::from_millis(5),
+                    text: text(b"89abcdef"),
+                },
+                // An escape isn't split between chunks
+                Event::Output {
+                    pane: TmuxPaneId(1),
+                    text: text(b"a\\"),
+                },
+                Event::Output {
+                    pane: TmuxPaneId(1),
+                    text: text(b"\\"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn streaming_guarded_output() {
+        let mut p = Parser::new();
+        p.set_guarded_chunk_size(Some(8));
+        let events = p
+            .advance_bytes(
+                b"%begin 1 2 1\none\ntwo\nthree\n%end 1 2 1\n%begin 1 3 1\n%error 1 3 1\n",
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                Event::GuardedChunk {
+                    timestamp: 1,
+                    number: 2,
+                    flags: 1,
+                    output: "one\ntwo\n".to_owned(),
+                },
+                Event::GuardedDone(Guarded {
+                    error: false,
+                    timestamp: 1,
+                    number: 2,
+                    flags: 1,
+                    output: "three\n".to_owned(),
+                }),
+                Event::GuardedDone(Guarded {
+                    error: true,
+                    timestamp: 1,
+                    number: 3,
+                    flags: 1,
+                    output: String::new(),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn lenient() {
+        let input = b"%window-add @1\n%future-notification 1 2\n%window-add @2\n";
+
+        let mut p = Parser::new();
+        assert!(p.advance_bytes(input).is_err());
+
+        let mut p = Parser::new();
+        p.set_lenient(true);
+        assert_eq!(
+            p.advance_bytes(input).unwrap(),
+            vec![
+                Event::WindowAdd {
+                    window: TmuxWindowId(1)
+                },
+                Event::Unknown {
+                    line: "%future-notification 1 2".to_owned()
+                },
+                Event::WindowAdd {
+                    window: TmuxWindowId(2)
+                },
+            ]
+        );
+
+        let mut p = Parser::new();
+        let bad_checksum = b"%layout-change @1 b25e,80x24,0,0,0\n";
+        assert!(p.advance_bytes(bad_checksum).is_err());
+        let mut p = Parser::new();
+        p.set_lenient_layouts(true);
+        match p.advance_bytes(bad_checksum).unwrap().as_slice() {
+            [Event::LayoutChange { layout, .. }] => {
+                assert_eq!(layout.layout_id, "b25e");
+            }
+            events => panic!("unexpected {:?}", events),
+        }
+    }
+
+    #[test]
+    fn parse_error_spans() {
+        let parse_error = |line: &str| {
+            let err = parse_line(line, &mut BytesMut::new(), false).unwrap_err();
+            err.downcast::<ParseError>().unwrap()
+        };
+
+        let err = parse_error("%window-pane-changed @1 2");
+        assert_eq!(err.span, 24..24);
+        assert_eq!(err.column(), 25);
+
+        // Too large for the id
+        let err = parse_error("%output %99999999999999999999 hello");
+        assert_eq!(err.span, 8..29);
+        assert_eq!(err.field.as_deref(), Some("pane_id"));
+
+        let err = parse_error("%session-renamed caf\\\u{e9}\\x");
+        assert_eq!(err.field.as_deref(), Some("any_text"));
+        assert_eq!(err.span, 17..25);
+        assert_eq!(err.column(), 18);
+
+        let err = parse_error("%layout-change @1 b25d,80x24,0,0,99999999999999999999");
+        assert_eq!(err.field.as_deref(), Some("number"));
+        assert_eq!(err.span, 33..53);
+
+        // The line is still what is displayed for errors from the parser
+        let mut p = Parser::new();
+        let err = p.advance_string("%exit\n%window-add @x\nmore").unwrap_err();
+        assert_eq!(err.to_string(), "%window-add @x\nmore");
+        assert_eq!(err.downcast_ref::<ParseError>().unwrap().span, 13..13);
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn roundtrip_events() {
+        let mut p = Parser::new();
+        let events = p
+            .advance_bytes(
+                b"%output %1 a\\033b\n\
+                %layout-change @1 020a,80x24,0,0{40x24,0,0,1,39x24,41,0,2} b25e,80x24,0,0,1 *Z\n\
+                %begin 1 2 1\nline\n%end 1 2 1\n",
+            )
+            .unwrap();
+        let data = varbincode::serialize(&events).unwrap();
+        let decoded: Vec<Event> = varbincode::deserialize(data.as_slice()).unwrap();
+        assert_eq!(decoded, events);
+    }
+
+    /// Notifications, with a version of tmux that is expected to send them
+    #[test]
+    fn notifications_by_version() {
+        let mut output = BytesMut::new();
+        let cases: &[(&str, &str, Event)] = &[
+            (
+                "3.0",
+                "%unlinked-window-add @4",
+                Event::UnlinkedWindowAdd {
+                    window: TmuxWindowId(4),
+                },
+            ),
+            (
+                "3.0",
+                "%session-window-changed $1 @2",
+                Event::SessionWindowChanged {
+                    session: TmuxSessionId(1),
+                    window: TmuxWindowId(2),
+                },
+            ),
+            (
+                "3.0",
+                "%window-pane-changed @1 %3",
+                Event::WindowPaneChanged {
+                    window: TmuxWindowId(1),
+                    pane: TmuxPaneId(3),
+                },
+            ),
+            (
+                "3.0",
+                "%unlinked-window-close @4",
+                Event::UnlinkedWindowClose {
+                    window: TmuxWindowId(4),
+                },
+            ),
+            (
+                "3.0",
+                "%unlinked-window-renamed @4 logs",
+                Event::UnlinkedWindowRenamed {
+                    window: TmuxWindowId(4),
+                    name: "logs".to_owned(),
+                },
+            ),
+            (
+                "3.2",
+                "%pause %1",
+                Event::Pause {
+                    pane: TmuxPaneId(1),
+                },
+            ),
+            (
+                "3.2",
+                "%continue %1",
+                Event::Continue {
+                    pane: TmuxPaneId(1),
+                },
+            ),
+            (
+                "3.2",
+                "%extended-output %1 1234 : hello\\040world",
+                Event::ExtendedOutput {
+                    pane: TmuxPaneId(1),
+                    age: Duration::from_millis(1234),
+                    text: Bytes::from_static(b"hello world"),
+                },
+            ),
+            (
+                "3.2",
+                "%extended-output %1 5 future args : x",
+                Event::ExtendedOutput {
+                    pane: TmuxPaneId(1),
+                    age: Duration::from_millis(5),
+                    text: Bytes::from_static(b"x"),
+                },
+            ),
+            (
+                "3.2",
+                "%paste-buffer-changed buffer0",
+                Event::PasteBufferChanged {
+                    name: "buffer0".to_owned(),
+                },
+            ),
+            (
+                "3.2",
+                "%paste-buffer-deleted buffer1",
+                Event::PasteBufferDeleted {
+                    name: "buffer1".to_owned(),
+                },
+            ),
+            (
+                "3.2",
+                "%client-detached /dev/pts/1",
+                Event::ClientDetached {
+                    client_name: "/dev/pts/1".to_owned(),
+                },
+            ),
+            (
+                "3.2",
+                "%subscription-changed title $1 @2 0 %3 : vim",
+                Event::SubscriptionChanged {
+                    name: "title".to_owned(),
+                    session: TmuxSessionId(1),
+                    window: Some(TmuxWindowId(2)),
+                    window_index: Some(0),
+                    pane: Some(TmuxPaneId(3)),
+                    value: "vim".to_owned(),
+                },
+            ),
+            (
+                "3.3",
+                "%config-error /home/wez/.tmux.conf:3: unknown command: bogus",
+                Event::ConfigError {
+                    file: "/home/wez/.tmux.conf".to_owned(),
+                    line: Some(3),
+                    message: "unknown command: bogus".to_owned(),
+                },
+            ),
+            (
+                "3.3",
+                "%message no previous window",
+                Event::Message {
+                    message: "no previous window".to_owned(),
+                },
+            ),
+            (
+                "3.5",
+                "%client-attached /dev/pts/2",
+                Event::ClientAttached {
+                    client_name: "/dev/pts/2".to_owned(),
+                },
+            ),
+        ];
+        for (version, line, expected) in cases {
+            let event = parse_line(line, &mut output, false)
+                .unwrap_or_else(|err| panic!("tmux {}: {}: {:#}", version, line, err));
+            assert_eq!(&event, expected, "tmux {}: {}", version, line);
+        }
+    }
+
+    #[test]
+    fn zoomed_layout_change() {
+        let line = "%layout-change @1 020a,80x24,0,0{40x24,0,0,1,39x24,41,0,2} b25f,80x24,0,0,2 *Z";
+        match parse_line(line, &mut BytesMut::new(), false).unwrap() {
+            Event::LayoutChange {
+                layout,
+                visible_layout: Some(visible_layout),
+                ..
+            } => {
+                assert_eq!(layout.zoomed_pane(), Some(TmuxPaneId(2)));
+                assert_eq!(visible_layout.zoomed_pane(), Some(TmuxPaneId(2)));
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+
+        let line = "%layout-change @1 020a,80x24,0,0{40x24,0,0,1,39x24,41,0,2} 020a,80x24,0,0{40x24,0,0,1,39x24,41,0,2} *";
+        match parse_line(line, &mut BytesMut::new(), false).unwrap() {
+            Event::LayoutChange { layout, .. } => {
+                assert_eq!(layout.zoomed_pane(), None);
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn subscription_changed() {
+        let parse = |line| parse_line(line, &mut BytesMut::new(), false).unwrap();
+        assert_eq!(
+            parse("%subscription-changed cwd $0 - - - : /home/wez"),
+            Event::SubscriptionChanged {
+                name: "cwd".to_owned(),
+                session: TmuxSessionId(0),
+                window: None,
+                window_index: None,
+                pane: None,
+                value: "/home/wez".to_owned(),
+            }
+        );
+        assert_eq!(
+            parse("%subscription-changed activity $0 @4 2 - : 1"),
+            Event::SubscriptionChanged {
+                name: "activity".to_owned(),
+                session: TmuxSessionId(0),
+                window: Some(TmuxWindowId(4)),
+                window_index: Some(2),
+                pane: None,
+                value: "1".to_owned(),
+            }
+        );
+        // Arguments added by a later version are skipped, and the
+        // value may itself contain the separator
+        assert_eq!(
+            parse("%subscription-changed title $1 @2 0 %3 future args : a : b"),
+            Event::SubscriptionChanged {
+                name: "title".to_owned(),
+                session: TmuxSessionId(1),
+                window: Some(TmuxWindowId(2)),
+                window_index: Some(0),
+                pane: Some(TmuxPaneId(3)),
+                value: "a : b".to_owned(),
+            }
+        );
+        assert!(parse_line("%subscription-changed title", &mut BytesMut::new(), false).is_err());
+    }
+
+    #[test]
+    fn config_error() {
+        assert_eq!(
+            parse_config_error("/etc/tmux:v2.conf:12: invalid option: foo"),
+            (
+                "/etc/tmux:v2.conf".to_owned(),
+                Some(12),
+                "invalid option: foo".to_owned()
+            )
+        );
+        assert_eq!(
+            parse_config_error("/etc/tmux.conf: No such file or directory"),
+            (
+                "/etc/tmux.conf".to_owned(),
+                None,
+                "No such file or directory".to_owned()
+            )
+        );
+        assert_eq!(
+            parse_config_error("something odd"),
+            (String::new(), None, "something odd".to_owned())
+        );
+    }
+
+    #[test]
+    fn window_flags() {
+        assert_eq!(
+            WindowFlags::from_window_flags("*Z"),
+            WindowFlags::CURRENT | WindowFlags::ZOOMED
+        );
+        assert_eq!(
+            WindowFlags::from_window_flags("-#!~M"),
+            WindowFlags::LAST
+                | WindowFlags::ACTIVITY
+                | WindowFlags::BELL
+                | WindowFlags::SILENCE
+                | WindowFlags::MARKED
+        );
+        assert_eq!(WindowFlags::from_window_flags(""), WindowFlags::empty());
+        assert_eq!(WindowFlags::from_window_flags("?*"), WindowFlags::CURRENT);
+    }
+
     #[test]
     fn test_parse_sequence() {
         let input = b"%sessions-changed
@@ -668,6 +1766,8 @@ here
 %session-changed $1 1
 %client-session-changed /dev/pts/5 $1 home
 %client-detached /dev/pts/10
+%pause %1
+%continue %1
 %layout-change @1 b25d,80x24,0,0,0
 %layout-change @1 cafd,120x29,0,0,0 cafd,120x29,0,0,0 *
 %output %1 \\033[1m\\033[7m%\\033[27m\\033[1m\\033[0m    \\015 \\015
@@ -683,7 +1783,9 @@ here
         assert_eq!(
             vec![
                 Event::SessionsChanged,
-                Event::PaneModeChanged { pane: 0 },
+                Event::PaneModeChanged {
+                    pane: TmuxPaneId(0)
+                },
                 Event::Guarded(Guarded {
                     timestamp: 1604279270,
                     number: 310,
@@ -691,60 +1793,93 @@ here
                     error: false,
                     output: "stuff\nin\nhere\n".to_owned()
                 }),
-                Event::WindowAdd { window: 1 },
+                Event::WindowAdd {
+                    window: TmuxWindowId(1)
+                },
                 Event::SessionsChanged,
                 Event::SessionChanged {
-                    session: 1,
+                    session: TmuxSessionId(1),
                     name: "1".to_owned(),
                 },
                 Event::ClientSessionChanged {
                     client_name: "/dev/pts/5".to_owned(),
-                    session: 1,
+                    session: TmuxSessionId(1),
                     session_name: "home".to_owned()
                 },
                 Event::ClientDetached {
                     client_name: "/dev/pts/10".to_owned()
                 },
+                Event::Pause {
+                    pane: TmuxPaneId(1)
+                },
+                Event::Continue {
+                    pane: TmuxPaneId(1)
+                },
                 Event::LayoutChange {
-                    window: 1,
+                    window: TmuxWindowId(1),
                     layout: WindowLayout {
                         layout_id: "b25d".to_owned(),
                         width: 80,
-                        height: 24
+                        height: 24,
+                        root: LayoutNode::Leaf(PaneLayout {
+                            pane_id: TmuxPaneId(0),
+                            pane_width: 80,
+                            pane_height: 24,
+                            pane_left: 0,
+                            pane_top: 0,
+                            zoomed: false,
+                        }),
                     },
                     visible_layout: None,
-                    raw_flags: None
+                    flags: WindowFlags::empty(),
                 },
                 Event::LayoutChange {
-                    window: 1,
+                    window: TmuxWindowId(1),
                     layout: WindowLayout {
                         layout_id: "cafd".to_owned(),
                         width: 120,
-                        height: 29
+                        height: 29,
+                        root: LayoutNode::Leaf(PaneLayout {
+                            pane_id: TmuxPaneId(0),
+                            pane_width: 120,
+                            pane_height: 29,
+                            pane_left: 0,
+                            pane_top: 0,
+                            zoomed: false,
+                        }),
                     },
                     visible_layout: Some(WindowLayout {
                         layout_id: "cafd".to_owned(),
                         width: 120,
-                        height: 29
+                        height: 29,
+                        root: LayoutNode::Leaf(PaneLayout {
+                            pane_id: TmuxPaneId(0),
+                            pane_width: 120,
+                            pane_height: 29,
+                            pane_left: 0,
+                            pane_top: 0,
+                            zoomed: false,
+                        }),
                     }),
-                    raw_flags: Some("*".to_owned())
+                    flags: WindowFlags::CURRENT,
                 },
                 Event::Output {
-                    pane: 1,
-                    text: "\x1b[1m\x1b[7m%\x1b[27m\x1b[1m\x1b[0m    \r \r".to_owned()
+                    pane: TmuxPaneId(1),
+                    text: Bytes::from_static(b"\x1b[1m\x1b[7m%\x1b[27m\x1b[1m\x1b[0m    \r \r")
                 },
                 Event::Output {
-                    pane: 1,
-                    text: "\x1bkwez@cube-localdomain:~\x1b\\\x1b]2;wez@cube-localdomain:~\x1b\\"
-                        .to_owned()
+                    pane: TmuxPaneId(1),
+                    text: Bytes::from_static(
+                        b"\x1bkwez@cube-localdomain:~\x1b\\\x1b]2;wez@cube-localdomain:~\x1b\\"
+                    )
                 },
                 Event::Output {
-                    pane: 1,
-                    text: "\x1b]7;file://cube-localdomain/home/wez\x1b\\".to_owned(),
+                    pane: TmuxPaneId(1),
+                    text: Bytes::from_static(b"\x1b]7;file://cube-localdomain/home/wez\x1b\\"),
                 },
                 Event::Output {
-                    pane: 1,
-                    text: "\x1b[K\x1b[?2004h".to_owned(),
+                    pane: TmuxPaneId(1),
+                    text: Bytes::from_static(b"\x1b[K\x1b[?2004h"),
                 },
                 Event::Exit { reason: None },
                 Event::Exit {
@@ -0,0 +1,95 @@
+//! Retrieves the content of the paste buffers whose changes tmux
+//! reports with `%paste-buffer-changed`, by sending
+//! `CommandBuilder::show_buffer` and passing the reply to
+//! `PasteBuffer::from_reply`.
+//!
+//! In control mode, `show-buffer` replies with each line of the
+//! buffer encoded with `vis`, so that the content can include bytes
+//! that would otherwise disrupt the protocol.
+use super::{unvis_bytes, Guarded};
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasteBuffer {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl PasteBuffer {
+    /// Decodes the reply to `CommandBuilder::show_buffer(name)`.
+    /// tmux doesn't indicate whether the buffer ends with a newline,
+    /// so the content is returned without one.
+    pub fn from_reply(name: &str, reply: &Guarded) -> anyhow::Result<Self> {
+        if reply.error {
+            anyhow::bail!(
+                "failed to show tmux buffer {}: {}",
+                name,
+                reply.output.trim_end()
+            );
+        }
+
+        let mut data = vec![];
+        let output = reply.output.strip_suffix('\n').unwrap_or(&reply.output);
+        if !output.is_empty() {
+            for (idx, line) in output.split('\n').enumerate() {
+                if idx > 0 {
+                    data.push(b'\n');
+                }
+                data.extend(unvis_bytes(line.as_bytes())?);
+            }
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux_cc::{CommandBuilder, Event, Parser};
+    use k9::assert_equal as assert_eq;
+
+    fn reply(input: &[u8]) -> Guarded {
+        let mut parser = Parser::new();
+        match parser.advance_bytes(input).unwrap().pop() {
+            Some(Event::Guarded(reply)) => reply,
+            event => panic!("expected a reply, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn show_buffer() {
+        assert_eq!(
+            CommandBuilder::show_buffer("buffer0").build(),
+            "show-buffer -b buffer0\n"
+        );
+
+        let reply = reply(b"%begin 1 266 1\na\\tb\\001 \xc3\xa9\n\\377\n%end 1 266 1\n");
+        assert_eq!(
+            PasteBuffer::from_reply("t1", &reply).unwrap(),
+            PasteBuffer {
+                name: "t1".to_owned(),
+                data: b"a\tb\x01 \xc3\xa9\n\xff".to_vec(),
+            }
+        );
+
+        let empty = self::reply(b"%begin 1 268 1\n%end 1 268 1\n");
+        assert_eq!(
+            PasteBuffer::from_reply("t2", &empty).unwrap().data,
+            Vec::<u8>::new()
+        );
+
+        let missing = self::reply(b"%begin 1 267 1\nno buffer nope\n%error 1 267 1\n");
+        assert_eq!(
+            PasteBuffer::from_reply("nope", &missing)
+                .unwrap_err()
+                .to_string(),
+            "failed to show tmux buffer nope: no buffer nope"
+        );
+    }
+}
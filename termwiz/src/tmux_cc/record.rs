@@ -0,0 +1,221 @@
+//! Records the raw output of `tmux -CC`, so that a session can be
+//! replayed through `Parser` later, for example to reproduce a
+//! problem that was reported by someone else.
+//!
+//! A recording is a sequence of records, each holding the bytes of a
+//! single read and the time at which it was made, relative to the
+//! start of the recording.  A record is written as a header line,
+//! holding the time in microseconds and the number of bytes, followed
+//! by the bytes and a newline:
+//!
+//! ```text
+//! 1520 18
+//! %window-add @1
+//! %exit
+//!
+//! ```
+use super::{Event, Parser};
+use anyhow::Context;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The time since the start of the recording
+    pub elapsed: Duration,
+    pub data: Vec<u8>,
+}
+
+pub struct Recorder<W: Write> {
+    writer: W,
+    start: Instant,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("creating tmux recording {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    /// Starts a recording; the times of the records are relative
+    /// to now
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            start: Instant::now(),
+        }
+    }
+
+    /// Records `data`, which was just read from tmux
+    pub fn record(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.record_at(self.start.elapsed(), data)
+    }
+
+    /// Records `data` as having been read at `elapsed`
+    pub fn record_at(&mut self, elapsed: Duration, data: &[u8]) -> std::io::Result<()> {
+        writeln!(self.writer, "{} {}", elapsed.as_micros(), data.len())?;
+        self.writer.write_all(data)?;
+        self.writer.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// How quickly to replay a recording
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Without waiting between records
+    Instant,
+    /// With the same timing as the recording
+    Original,
+    /// This many times faster than the recording
+    Accelerated(f64),
+}
+
+pub struct Replayer<R: BufRead> {
+    reader: R,
+}
+
+impl Replayer<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("opening tmux recording {}", path.display()))?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> Replayer<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Reads the next record, returning `None` at the end of the
+    /// recording
+    pub fn next_record(&mut self) -> anyhow::Result<Option<Record>> {
+        let mut header = String::new();
+        if self.reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let (micros, len) = header
+            .trim_end()
+            .split_once(' ')
+            .ok_or_else(|| anyhow::anyhow!("invalid tmux record header {:?}", header))?;
+        let elapsed = Duration::from_micros(
+            micros
+                .parse()
+                .with_context(|| format!("invalid tmux record time {:?}", micros))?,
+        );
+        let len: usize = len
+            .parse()
+            .with_context(|| format!("invalid tmux record length {:?}", len))?;
+
+        let mut data = vec![0u8; len + 1];
+        self.reader
+            .read_exact(&mut data)
+            .context("reading tmux record")?;
+        anyhow::ensure!(
+            data.pop() == Some(b'\n'),
+            "tmux record of {} bytes is not followed by a newline",
+            len
+        );
+        Ok(Some(Record { elapsed, data }))
+    }
+
+    /// Feeds the recording through `parser`, calling `on_event` with
+    /// each of the events that it produces, and waiting between the
+    /// records according to `speed`
+    pub fn replay<F: FnMut(Event)>(
+        &mut self,
+        parser: &mut Parser,
+        speed: ReplaySpeed,
+        mut on_event: F,
+    ) -> anyhow::Result<()> {
+        let start = Instant::now();
+        while let Some(record) = self.next_record()? {
+            let due = match speed {
+                ReplaySpeed::Instant => None,
+                ReplaySpeed::Original => Some(record.elapsed),
+                ReplaySpeed::Accelerated(factor) => Some(record.elapsed.div_f64(factor)),
+            };
+            if let Some(wait) = due.and_then(|due| due.checked_sub(start.elapsed())) {
+                std::thread::sleep(wait);
+            }
+            for event in parser.advance_bytes(&record.data)? {
+                on_event(event);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux_cc::TmuxWindowId;
+    use k9::assert_equal as assert_eq;
+
+    #[test]
+    fn record_and_replay() {
+        let mut recorder = Recorder::new(vec![]);
+        recorder
+            .record_at(Duration::from_micros(1520), b"%window-add @1\n%ex")
+            .unwrap();
+        recorder
+            .record_at(Duration::from_millis(3), b"it\n")
+            .unwrap();
+        let recording = recorder.into_inner();
+        assert_eq!(
+            String::from_utf8(recording.clone()).unwrap(),
+            "1520 18\n%window-add @1\n%ex\n3000 3\nit\n\n"
+        );
+
+        let mut replayer = Replayer::new(recording.as_slice());
+        assert_eq!(
+            replayer.next_record().unwrap(),
+            Some(Record {
+                elapsed: Duration::from_micros(1520),
+                data: b"%window-add @1\n%ex".to_vec(),
+            })
+        );
+
+        let mut events = vec![];
+        let mut replayer = Replayer::new(recording.as_slice());
+        replayer
+            .replay(
+                &mut Parser::new(),
+                ReplaySpeed::Accelerated(10.0),
+                |event| events.push(event),
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                Event::WindowAdd {
+                    window: TmuxWindowId(1)
+                },
+                Event::Exit { reason: None }
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let mut replayer = Replayer::new(&b"10 5\nabc"[..]);
+        assert!(replayer.next_record().is_err());
+        let mut replayer = Replayer::new(&b"10\nabc\n"[..]);
+        assert!(replayer.next_record().is_err());
+    }
+}
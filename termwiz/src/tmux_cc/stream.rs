@@ -0,0 +1,158 @@
+//! Adapts `Parser` to a `Stream` of the events read from an
+//! `AsyncRead`, such as the output of `tmux -CC`.
+use super::{Event, Parser};
+use futures_core::Stream;
+use futures_io::AsyncRead;
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const READ_SIZE: usize = 8192;
+
+/// Reads from `reader`, producing the events that are parsed from it.
+///
+/// ```ignore
+/// # async fn example(reader: impl futures_io::AsyncRead + Unpin) -> anyhow::Result<()> {
+/// use futures_util::StreamExt;
+/// use termwiz::tmux_cc::EventStream;
+/// let mut events = EventStream::new(reader);
+/// while let Some(event) = events.next().await {
+///     println!("{:?}", event?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The stream ends when `reader` reaches EOF, or after yielding
+/// an error, whether from reading or from parsing.
+pub struct EventStream<R> {
+    reader: R,
+    parser: Parser,
+    pending: VecDeque<anyhow::Result<Event>>,
+    buf: Box<[u8]>,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> EventStream<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_parser(reader, Parser::new())
+    }
+
+    /// Uses `parser`, which may already have been fed some input
+    pub fn with_parser(reader: R, parser: Parser) -> Self {
+        Self {
+            reader,
+            parser,
+            pending: VecDeque::new(),
+            buf: vec![0u8; READ_SIZE].into_boxed_slice(),
+            done: false,
+        }
+    }
+
+    /// Returns the reader and parser.
+    /// Events that were parsed but not yet yielded are discarded.
+    pub fn into_inner(self) -> (R, Parser) {
+        (self.reader, self.parser)
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for EventStream<R> {
+    type Item = anyhow::Result<Event>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(result) = this.pending.pop_front() {
+                return Poll::Ready(Some(result));
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            let size = match Pin::new(&mut this.reader).poll_read(cx, &mut this.buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Ok(0)) => {
+                    this.done = true;
+                    continue;
+                }
+                Poll::Ready(Ok(size)) => size,
+                Poll::Ready(Err(err)) if err.kind() == ErrorKind::Interrupted => continue,
+                Poll::Ready(Err(err)) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+            };
+
+            // Parse a byte at a time, rather than using advance_bytes,
+            // so that the events that precede an error are still yielded
+            for &b in &this.buf[..size] {
+                match this.parser.advance_byte(b) {
+                    Ok(Some(event)) => this.pending.push_back(Ok(event)),
+                    Ok(None) => {}
+                    Err(err) => {
+                        this.pending.push_back(Err(err));
+                        this.done = true;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux_cc::TmuxWindowId;
+    use k9::assert_equal as assert_eq;
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+
+    struct NoopWaker;
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Collects the stream, which must never be pending
+    fn collect<R: AsyncRead + Unpin>(mut stream: EventStream<R>) -> Vec<anyhow::Result<Event>> {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut results = vec![];
+        loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(result)) => results.push(result),
+                Poll::Ready(None) => return results,
+                Poll::Pending => panic!("unexpectedly pending"),
+            }
+        }
+    }
+
+    #[test]
+    fn events_from_reader() {
+        let input: &[u8] = b"%sessions-changed\n%window-add @1\n%exit\n";
+        let events = collect(EventStream::new(input))
+            .into_iter()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                Event::SessionsChanged,
+                Event::WindowAdd {
+                    window: TmuxWindowId(1)
+                },
+                Event::Exit { reason: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_after_error() {
+        let input: &[u8] = b"%sessions-changed\n%bogus\n%window-add @1\n";
+        let results = collect(EventStream::new(input));
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[0], Ok(Event::SessionsChanged)));
+        assert!(results[1].is_err());
+    }
+}
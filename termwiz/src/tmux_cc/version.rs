@@ -0,0 +1,180 @@
+//! Identifies the version of tmux at the other end of a control
+//! mode connection.
+//!
+//! tmux doesn't announce its version when control mode starts, so
+//! it has to be asked, by sending `CommandBuilder::display_message`
+//! with `ProtocolVersion::FORMAT` and passing the reply to
+//! `ProtocolVersion::from_reply`.
+use super::Guarded;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+
+/// A tmux version, such as `3.3a`, which determines the notifications
+/// and commands that are available
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProtocolVersion {
+    pub major: u32,
+    pub minor: u32,
+    /// The letter that follows a patch release, counting from 1 for
+    /// `a`, or 0 if there is none
+    pub revision: u8,
+}
+
+impl ProtocolVersion {
+    /// Expands to the version of tmux
+    pub const FORMAT: &'static str = "#{version}";
+
+    /// The newest version whose notifications are all recognized
+    pub const NEWEST_KNOWN: Self = Self::new(3, 5, 0);
+
+    /// The version that added `%extended-output`, `%pause` and
+    /// `%continue`, which are sent once the `pause-after` flag is set
+    pub const PAUSE_AFTER: Self = Self::new(3, 2, 0);
+
+    /// The version that added `refresh-client -B` and
+    /// `%subscription-changed`
+    pub const SUBSCRIPTIONS: Self = Self::new(3, 2, 0);
+
+    pub const fn new(major: u32, minor: u32, revision: u8) -> Self {
+        Self {
+            major,
+            minor,
+            revision,
+        }
+    }
+
+    /// Parses a version as reported by `#{version}` or `tmux -V`,
+    /// for example `3.3a`, `tmux 3.4` or `next-3.5`.
+    /// A development version is treated as the release that it leads up
+    /// to.  Returns `None` for versions that don't have this form, such
+    /// as those of the tmux in OpenBSD's base system.
+    pub fn parse(version: &str) -> Option<Self> {
+        let version = version.trim();
+        let version = version.strip_prefix("tmux ").unwrap_or(version);
+        let version = version.strip_prefix("next-").unwrap_or(version);
+        // Release candidates, such as 3.4-rc2
+        let version = match version.split_once('-') {
+            Some((version, _)) => version,
+            None => version,
+        };
+
+        let (major, minor) = version.split_once('.')?;
+        let digits = minor.bytes().take_while(u8::is_ascii_digit).count();
+        let (minor, revision) = minor.split_at(digits);
+        let revision = match revision.as_bytes() {
+            [] => 0,
+            [letter @ b'a'..=b'z'] => letter - b'a' + 1,
+            _ => return None,
+        };
+        Some(Self {
+            major: major.parse().ok()?,
+            minor: minor.parse().ok()?,
+            revision,
+        })
+    }
+
+    /// Determines the version from the reply to
+    /// `display-message -p "#{version}"`.
+    /// Versions of tmux that predate the format reply with an empty
+    /// line, producing `None`.
+    pub fn from_reply(reply: &Guarded) -> Option<Self> {
+        if reply.error {
+            return None;
+        }
+        Self::parse(&reply.output)
+    }
+
+    pub fn has_pause_after(&self) -> bool {
+        *self >= Self::PAUSE_AFTER
+    }
+
+    pub fn has_subscriptions(&self) -> bool {
+        *self >= Self::SUBSCRIPTIONS
+    }
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.revision > 0 {
+            write!(f, "{}", (b'a' + self.revision - 1) as char)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux_cc::{Event, Parser};
+    use k9::assert_equal as assert_eq;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            ProtocolVersion::parse("3.3a"),
+            Some(ProtocolVersion::new(3, 3, 1))
+        );
+        assert_eq!(
+            ProtocolVersion::parse("tmux 3.4\n"),
+            Some(ProtocolVersion::new(3, 4, 0))
+        );
+        assert_eq!(
+            ProtocolVersion::parse("next-3.5"),
+            Some(ProtocolVersion::new(3, 5, 0))
+        );
+        assert_eq!(
+            ProtocolVersion::parse("3.4-rc2"),
+            Some(ProtocolVersion::new(3, 4, 0))
+        );
+        assert_eq!(ProtocolVersion::parse("openbsd-7.4"), None);
+        assert_eq!(ProtocolVersion::parse(""), None);
+        assert_eq!(ProtocolVersion::parse("3.3ab"), None);
+
+        let version = ProtocolVersion::parse("3.1c").unwrap();
+        assert_eq!(version.to_string(), "3.1c");
+        assert!(version < ProtocolVersion::new(3, 2, 0));
+        assert!(version > ProtocolVersion::new(3, 1, 2));
+        assert!(!version.has_pause_after());
+        assert!(ProtocolVersion::new(3, 2, 1).has_subscriptions());
+    }
+
+    #[test]
+    fn from_reply() {
+        let mut reply = Guarded {
+            error: false,
+            timestamp: 1,
+            number: 2,
+            flags: 1,
+            output: "3.2a\n".to_owned(),
+        };
+        assert_eq!(
+            ProtocolVersion::from_reply(&reply),
+            Some(ProtocolVersion::new(3, 2, 1))
+        );
+        reply.output = "\n".to_owned();
+        assert_eq!(ProtocolVersion::from_reply(&reply), None);
+        reply.output = "3.2a\n".to_owned();
+        reply.error = true;
+        assert_eq!(ProtocolVersion::from_reply(&reply), None);
+    }
+
+    #[test]
+    fn newer_versions_are_lenient() {
+        let input = b"%future-notification 1 2\n";
+
+        let mut p = Parser::new();
+        p.set_protocol_version(ProtocolVersion::NEWEST_KNOWN);
+        assert!(p.advance_bytes(input).is_err());
+
+        let mut p = Parser::new();
+        p.set_protocol_version(ProtocolVersion::new(3, 6, 0));
+        assert_eq!(
+            p.advance_bytes(input).unwrap(),
+            vec![Event::Unknown {
+                line: "%future-notification 1 2".to_owned()
+            }]
+        );
+    }
+}
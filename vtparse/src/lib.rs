@@ -11,6 +11,7 @@
 //! [termwiz](https://docs.rs/termwiz/) crate if you don't want to have to research
 //! all those possible escape sequences for yourself.
 #![allow(clippy::upper_case_acronyms)]
+use std::convert::TryInto;
 use utf8parse::Parser as Utf8Parser;
 mod enums;
 use crate::enums::*;
@@ -101,6 +102,17 @@ pub trait VTActor {
     /// character.
     fn print(&mut self, b: char);
 
+    /// A run of printable text has arrived in the ground state.
+    /// `VTParser::parse` finds these runs more quickly than it could
+    /// parse each of their characters, and passes them here as
+    /// a whole.  The default implementation calls `print` for each
+    /// of the characters.
+    fn print_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.print(c);
+        }
+    }
+
     /// The C0 or C1 control function should be executed, which may have any one of a variety of
     /// effects, including changing the cursor position, suspending or resuming communications or
     /// changing the shift states in effect.
@@ -171,6 +183,34 @@ pub trait VTActor {
     /// Called when an APC string is terminated by ST
     /// `data` is the data contained within the APC sequence.
     fn apc_dispatch(&mut self, data: Vec<u8>);
+
+    /// Called, once diagnostics have been enabled with
+    /// `VTParser::set_diagnostics`, for a malformed sequence.
+    /// `bytes` holds the bytes of the sequence, or the first
+    /// `MAX_DIAGNOSTIC_BYTES` of them.
+    fn parse_error(&mut self, _bytes: &[u8], _reason: ParseErrorReason) {}
+}
+
+/// The ways in which a sequence can be malformed
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParseErrorReason {
+    /// The sequence was cancelled by CAN or SUB, and discarded
+    Cancelled,
+    /// The sequence was interrupted by the start of another
+    /// sequence, and discarded
+    Interrupted,
+    /// A CSI sequence had a byte where it isn't allowed, such as
+    /// a private marker after the parameters, and was discarded
+    InvalidCsi,
+    /// A DCS sequence had a byte where it isn't allowed, and was
+    /// discarded
+    InvalidDcs,
+    /// The sequence had more parameters or intermediates than can
+    /// be held, or an OSC was too long; the excess was discarded
+    Truncated,
+    /// The bytes aren't valid UTF-8, and were replaced by the
+    /// unicode REPLACEMENT_CHARACTER
+    InvalidUtf8,
 }
 
 /// `VTAction` is an alternative way to work with the parser; rather
@@ -201,6 +241,10 @@ pub enum VTAction {
     },
     OscDispatch(Vec<Vec<u8>>),
     ApcDispatch(Vec<u8>),
+    ParseError {
+        bytes: Vec<u8>,
+        reason: ParseErrorReason,
+    },
 }
 
 /// This is an implementation of `VTActor` that captures the events
@@ -291,11 +335,20 @@ impl VTActor for CollectingVTActor {
     fn apc_dispatch(&mut self, data: Vec<u8>) {
         self.actions.push(VTAction::ApcDispatch(data));
     }
+
+    fn parse_error(&mut self, bytes: &[u8], reason: ParseErrorReason) {
+        self.actions.push(VTAction::ParseError {
+            bytes: bytes.to_vec(),
+            reason,
+        });
+    }
 }
 
 const MAX_INTERMEDIATES: usize = 2;
 const MAX_OSC: usize = 64;
 const MAX_PARAMS: usize = 32;
+/// The most bytes of a sequence that are passed to `VTActor::parse_error`
+pub const MAX_DIAGNOSTIC_BYTES: usize = 256;
 
 struct OscState {
     buffer: Vec<u8>,
@@ -344,8 +397,18 @@ pub struct VTParser {
     params_full: bool,
     apc_data: Vec<u8>,
 
+    /// Set while in the `DCS tmux; ... ST` passthrough sequence,
+    /// in which an ESC of the wrapped sequences is doubled
+    tmux_passthrough: bool,
+    /// Set when an ESC has been seen in the tmux passthrough sequence,
+    /// until the next byte shows whether it was doubled
+    tmux_escape: bool,
+
     utf8_parser: Utf8Parser,
     utf8_return_state: State,
+
+    /// When diagnostics are enabled, the bytes of the current sequence
+    diagnostics: Option<Vec<u8>>,
 }
 
 /// Represents a parameter to a CSI-based escaped sequence.
@@ -429,16 +492,116 @@ impl VTParser {
 
             utf8_parser: Utf8Parser::new(),
             apc_data: vec![],
+
+            tmux_passthrough: false,
+            tmux_escape: false,
+
+            diagnostics: None,
+        }
+    }
+
+    /// Enables or disables the reporting of malformed sequences
+    /// to `VTActor::parse_error`.  This has a cost for every byte
+    /// that is parsed, so it is intended for debugging.
+    pub fn set_diagnostics(&mut self, enable: bool) {
+        if enable != self.diagnostics.is_some() {
+            self.diagnostics = if enable { Some(vec![]) } else { None };
         }
     }
 
+    fn report_parse_error(&self, reason: ParseErrorReason, actor: &mut dyn VTActor) {
+        if let Some(bytes) = &self.diagnostics {
+            actor.parse_error(bytes, reason);
+        }
+    }
+
+    /// Keeps track of the bytes of the current sequence, and reports
+    /// the sequence if `byte` causes it to be discarded
+    fn diagnose(&mut self, byte: u8, actor: &mut dyn VTActor) {
+        let mut bytes = match self.diagnostics.take() {
+            Some(bytes) => bytes,
+            None => return,
+        };
+        if self.state == State::Ground {
+            bytes.clear();
+        }
+
+        // Whether `state` is the start of a sequence; the sequence
+        // is interrupted if `byte` would also start it from the ground
+        let is_start = |state| {
+            matches!(
+                state,
+                State::Escape
+                    | State::CsiEntry
+                    | State::DcsEntry
+                    | State::OscString
+                    | State::SosPmString
+                    | State::ApcString
+            )
+        };
+        let cancel = byte == 0x18 || byte == 0x1a;
+        let next = match self.state {
+            // There are no transitions for these
+            State::Ground | State::Utf8Sequence | State::Anywhere => self.state,
+            state => lookup(state, byte).1,
+        };
+        // The reason that the sequence is discarded, and whether
+        // `byte` is part of it
+        let discarded = match self.state {
+            State::Escape
+            | State::EscapeIntermediate
+            | State::CsiEntry
+            | State::CsiParam
+            | State::CsiIntermediate
+            | State::DcsEntry
+            | State::DcsParam
+            | State::DcsIntermediate => {
+                if cancel {
+                    Some((ParseErrorReason::Cancelled, false))
+                } else if is_start(next) && lookup(State::Ground, byte).1 == next {
+                    Some((ParseErrorReason::Interrupted, false))
+                } else {
+                    None
+                }
+            }
+            State::CsiIgnore if cancel => Some((ParseErrorReason::Cancelled, false)),
+            State::CsiIgnore if next == State::Ground => Some((ParseErrorReason::InvalidCsi, true)),
+            State::CsiIgnore if next != State::CsiIgnore => {
+                Some((ParseErrorReason::InvalidCsi, false))
+            }
+            State::DcsIgnore if next != State::DcsIgnore => {
+                Some((ParseErrorReason::InvalidDcs, false))
+            }
+            _ => None,
+        };
+
+        let included = match discarded {
+            Some((reason, included)) => {
+                if included && bytes.len() < MAX_DIAGNOSTIC_BYTES {
+                    bytes.push(byte);
+                }
+                actor.parse_error(&bytes, reason);
+                bytes.clear();
+                included
+            }
+            None => false,
+        };
+        if !included && bytes.len() < MAX_DIAGNOSTIC_BYTES {
+            bytes.push(byte);
+        }
+        self.diagnostics = Some(bytes);
+    }
+
     /// Returns if the state machine is in the ground state,
     /// i.e. there is no pending state held by the state machine.
     pub fn is_ground(&self) -> bool {
         self.state == State::Ground
     }
 
-    fn as_integer_params(&self) -> [i64; MAX_PARAMS] {
+    /// Returns the integer parameters along with the number of them.
+    /// `num_params` can't be used for that, as it also counts the
+    /// `;` separators.
+    fn as_integer_params(&self) -> ([i64; MAX_PARAMS], usize) {
         let mut res = [0i64; MAX_PARAMS];
         let mut i = 0;
         for src in &self.params[0..self.num_params] {
@@ -448,7 +611,12 @@ impl VTParser {
                 i += 1;
             }
         }
-        res
+        let count = if self.num_params == 0 {
+            0
+        } else {
+            (i + 1).min(MAX_PARAMS)
+        };
+        (res, count)
     }
 
     fn finish_param(&mut self) {
@@ -536,18 +704,49 @@ impl VTParser {
             }
             Action::Hook => {
                 self.finish_param();
+                if self.params_full || self.ignored_excess_intermediates {
+                    self.report_parse_error(ParseErrorReason::Truncated, actor);
+                }
+                // A private marker, such as the `=` of `DCS = 1 s`, was
+                // promoted to the params by the digits that followed it.
+                // It isn't an integer parameter, so it is passed as the
+                // first of the intermediates, just as it would be had
+                // there been no digits.
+                let mut intermediates = [0u8; MAX_INTERMEDIATES + 1];
+                let mut num_intermediates = 0;
+                if let Some(CsiParam::P(marker @ 0x3c..=0x3f)) =
+                    self.params[0..self.num_params].first()
+                {
+                    intermediates[0] = *marker;
+                    num_intermediates = 1;
+                    self.params[0..self.num_params].rotate_left(1);
+                    self.num_params -= 1;
+                }
+                for &b in &self.intermediates[0..self.num_intermediates] {
+                    intermediates[num_intermediates] = b;
+                    num_intermediates += 1;
+                }
+                // `DCS tmux;` has the final byte `t`.  This collides with
+                // decVT105G, but that isn't something that we support.
+                self.tmux_passthrough =
+                    param == b't' && self.num_params == 0 && num_intermediates == 0;
+                let (params, num_params) = self.as_integer_params();
                 actor.dcs_hook(
                     param,
-                    &self.as_integer_params()[0..self.num_params],
-                    &self.intermediates[0..self.num_intermediates],
+                    &params[0..num_params],
+                    &intermediates[0..num_intermediates],
                     self.ignored_excess_intermediates,
                 );
             }
             Action::Put => actor.dcs_put(param),
             Action::EscDispatch => {
                 self.finish_param();
+                if self.ignored_excess_intermediates {
+                    self.report_parse_error(ParseErrorReason::Truncated, actor);
+                }
+                let (params, num_params) = self.as_integer_params();
                 actor.esc_dispatch(
-                    &self.as_integer_params()[0..self.num_params],
+                    &params[0..num_params],
                     &self.intermediates[0..self.num_intermediates],
                     self.ignored_excess_intermediates,
                     param,
@@ -555,6 +754,9 @@ impl VTParser {
             }
             Action::CsiDispatch => {
                 self.finish_param();
+                if self.params_full || self.ignored_excess_intermediates {
+                    self.report_parse_error(ParseErrorReason::Truncated, actor);
+                }
                 self.promote_intermediates_to_params();
                 actor.csi_dispatch(
                     &self.params[0..self.num_params],
@@ -562,7 +764,10 @@ impl VTParser {
                     param,
                 );
             }
-            Action::Unhook => actor.dcs_unhook(),
+            Action::Unhook => {
+                self.tmux_passthrough = false;
+                actor.dcs_unhook()
+            }
             Action::OscStart => {
                 self.osc.buffer.clear();
                 self.osc.num_params = 0;
@@ -571,6 +776,9 @@ impl VTParser {
             Action::OscPut => self.osc.put(param as char),
 
             Action::OscEnd => {
+                if self.osc.full {
+                    self.report_parse_error(ParseErrorReason::Truncated, actor);
+                }
                 if self.osc.num_params == 0 {
                     actor.osc_dispatch(&[]);
                 } else {
@@ -615,6 +823,7 @@ impl VTParser {
     fn next_utf8(&mut self, actor: &mut dyn VTActor, byte: u8) {
         struct Decoder {
             codepoint: Option<char>,
+            invalid: bool,
         }
 
         impl utf8parse::Receiver for Decoder {
@@ -623,13 +832,20 @@ impl VTParser {
             }
 
             fn invalid_sequence(&mut self) {
+                self.invalid = true;
                 self.codepoint(std::char::REPLACEMENT_CHARACTER);
             }
         }
 
-        let mut decoder = Decoder { codepoint: None };
+        let mut decoder = Decoder {
+            codepoint: None,
+            invalid: false,
+        };
 
         self.utf8_parser.advance(&mut decoder, byte);
+        if decoder.invalid && self.utf8_return_state == State::Ground {
+            self.report_parse_error(ParseErrorReason::InvalidUtf8, actor);
+        }
         if let Some(c) = decoder.codepoint {
             // Slightly gross special cases C1 controls that were
             // encoded as UTF-8 rather than emitted as raw 8-bit.
@@ -666,6 +882,10 @@ impl VTParser {
     /// methods on the provided `actor`.
     #[inline(always)]
     pub fn parse_byte(&mut self, byte: u8, actor: &mut dyn VTActor) {
+        if self.diagnostics.is_some() {
+            self.diagnose(byte, actor);
+        }
+
         // While in utf-8 parsing mode, co-opt the vt state
         // table and instead use the utf-8 state table from the
         // parser.  It will drop us back into the Ground state
@@ -675,6 +895,27 @@ impl VTParser {
             return;
         }
 
+        if self.tmux_passthrough {
+            if self.tmux_escape {
+                self.tmux_escape = false;
+                if byte == 0x1b {
+                    actor.dcs_put(byte);
+                    return;
+                }
+                // The ESC wasn't doubled, so it ends the passthrough
+                // sequence; normally as the start of the ST
+                self.transition(0x1b, actor);
+            } else if byte == 0x1b && self.state == State::DcsPassthrough {
+                self.tmux_escape = true;
+                return;
+            }
+        }
+
+        self.transition(byte, actor);
+    }
+
+    #[inline(always)]
+    fn transition(&mut self, byte: u8, actor: &mut dyn VTActor) {
         let (action, state) = lookup(self.state, byte);
 
         if state != self.state {
@@ -694,10 +935,83 @@ impl VTParser {
     /// This may result in some number of calls to the methods on the
     /// provided `actor`.
     pub fn parse(&mut self, bytes: &[u8], actor: &mut dyn VTActor) {
-        for b in bytes {
-            self.parse_byte(*b, actor);
+        let mut i = 0;
+        while i < bytes.len() {
+            if self.state == State::Ground {
+                let text = printable_run(&bytes[i..]);
+                if !text.is_empty() {
+                    actor.print_str(text);
+                    i += text.len();
+                    continue;
+                }
+            }
+            self.parse_byte(bytes[i], actor);
+            i += 1;
+        }
+    }
+}
+
+/// Returns the length of the run of printable ASCII at the start
+/// of `bytes`.  Most of the output of a typical application is
+/// printable ASCII, so this checks a word at a time.
+fn printable_ascii_len(bytes: &[u8]) -> usize {
+    const WORD: usize = std::mem::size_of::<usize>();
+    // 0x0101..01 and 0x8080..80
+    const LO: usize = usize::MAX / 0xff;
+    const HI: usize = LO * 0x80;
+
+    let mut i = 0;
+    while let Some(chunk) = bytes.get(i..i + WORD) {
+        let word = usize::from_ne_bytes(chunk.try_into().unwrap());
+        let del = word ^ (LO * 0x7f);
+        // Non-zero if any of the bytes is non-ASCII, is less
+        // than 0x20, or is DEL
+        let unprintable =
+            (word | (word.wrapping_sub(LO * 0x20) & !word) | (del.wrapping_sub(LO) & !del)) & HI;
+        if unprintable != 0 {
+            break;
+        }
+        i += WORD;
+    }
+
+    i + bytes[i..]
+        .iter()
+        .position(|b| !(0x20..0x7f).contains(b))
+        .unwrap_or(bytes.len() - i)
+}
+
+/// Returns the length of the UTF-8 sequence at the start of `bytes`
+/// if it is complete and valid, and isn't a C1 control
+fn printable_utf8_len(bytes: &[u8]) -> Option<usize> {
+    match bytes {
+        [0xc2, 0xa0..=0xbf, ..] | [0xc3..=0xdf, 0x80..=0xbf, ..] => Some(2),
+        [0xe0, 0xa0..=0xbf, 0x80..=0xbf, ..]
+        | [0xe1..=0xec | 0xee..=0xef, 0x80..=0xbf, 0x80..=0xbf, ..]
+        | [0xed, 0x80..=0x9f, 0x80..=0xbf, ..] => Some(3),
+        [0xf0, 0x90..=0xbf, 0x80..=0xbf, 0x80..=0xbf, ..]
+        | [0xf1..=0xf3, 0x80..=0xbf, 0x80..=0xbf, 0x80..=0xbf, ..]
+        | [0xf4, 0x80..=0x8f, 0x80..=0xbf, 0x80..=0xbf, ..] => Some(4),
+        _ => None,
+    }
+}
+
+/// Returns the run of text at the start of `bytes` that would be
+/// printed in the ground state: printable ASCII, and complete UTF-8
+/// sequences other than those of the C1 controls.  Anything else,
+/// including a UTF-8 sequence that is invalid or is split from the
+/// rest of its bytes, is left to the state machine.
+fn printable_run(bytes: &[u8]) -> &str {
+    let mut end = 0;
+    loop {
+        end += printable_ascii_len(&bytes[end..]);
+        match printable_utf8_len(&bytes[end..]) {
+            Some(len) => end += len,
+            None => break,
         }
     }
+    // Safety: the run is made up of printable ASCII and of
+    // sequences that printable_utf8_len has validated
+    unsafe { std::str::from_utf8_unchecked(&bytes[..end]) }
 }
 
 #[cfg(test)]
@@ -1028,6 +1342,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn dcs_private_marker() {
+        for (seq, params) in [("\x1bP=1s\x1b\\", vec![1]), ("\x1bP=s\x1b\\", vec![])] {
+            assert_eq!(
+                parse_as_vec(seq.as_bytes()),
+                vec![
+                    VTAction::DcsHook {
+                        byte: b's',
+                        params,
+                        intermediates: vec![b'='],
+                        ignored_excess_intermediates: false,
+                    },
+                    VTAction::DcsUnhook,
+                    VTAction::EscDispatch {
+                        params: vec![],
+                        intermediates: vec![],
+                        ignored_excess_intermediates: false,
+                        byte: b'\\',
+                    }
+                ]
+            );
+        }
+    }
+
     #[test]
     fn tmux_passthru() {
         // I'm not convinced that we *should* represent this tmux sequence
@@ -1062,6 +1400,63 @@ mod test {
         );
     }
 
+    #[test]
+    fn tmux_passthru_doubled_esc() {
+        let put = |s: &str| s.bytes().map(VTAction::DcsPut).collect::<Vec<_>>();
+        let mut expected = vec![VTAction::DcsHook {
+            byte: b't',
+            params: vec![],
+            intermediates: vec![],
+            ignored_excess_intermediates: false,
+        }];
+        expected.extend(put("mux;\x1b]0;hi\x1b\\"));
+        expected.push(VTAction::DcsUnhook);
+        expected.push(VTAction::EscDispatch {
+            params: vec![],
+            intermediates: vec![],
+            ignored_excess_intermediates: false,
+            byte: b'\\',
+        });
+        expected.push(VTAction::Print('x'));
+        assert_eq!(
+            parse_as_vec(b"\x1bPtmux;\x1b\x1b]0;hi\x1b\x1b\\\x1b\\x"),
+            expected
+        );
+
+        // An ESC that isn't doubled ends the passthrough sequence,
+        // and isn't treated specially in other sequences
+        assert_eq!(
+            parse_as_vec(b"\x1bPtmux;a\x1b[1m\x1bPqa\x1b\x1b"),
+            vec![
+                VTAction::DcsHook {
+                    byte: b't',
+                    params: vec![],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                },
+                VTAction::DcsPut(b'm'),
+                VTAction::DcsPut(b'u'),
+                VTAction::DcsPut(b'x'),
+                VTAction::DcsPut(b';'),
+                VTAction::DcsPut(b'a'),
+                VTAction::DcsUnhook,
+                VTAction::CsiDispatch {
+                    params: vec![CsiParam::Integer(1)],
+                    parameters_truncated: false,
+                    byte: b'm',
+                },
+                VTAction::DcsHook {
+                    byte: b'q',
+                    params: vec![],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                },
+                VTAction::DcsPut(b'a'),
+                VTAction::DcsUnhook,
+            ]
+        );
+    }
+
     #[test]
     fn kitty_img() {
         assert_eq!(
@@ -1105,6 +1500,149 @@ mod test {
         );
     }
 
+    #[test]
+    fn printable_runs() {
+        assert_eq!(printable_run(b"hello, world\x1b[m"), "hello, world");
+        assert_eq!(
+            printable_run("héllo wörld \u{1f600}\r\n".as_bytes()),
+            "héllo wörld \u{1f600}"
+        );
+        assert_eq!(printable_run(b"abcdefghijklmno\x7fpq"), "abcdefghijklmno");
+        assert_eq!(printable_run(b"abc\xc2\x9b1m"), "abc");
+        assert_eq!(printable_run(b"abc\xe2\x82"), "abc");
+        assert_eq!(printable_run(b"abc\xed\xa0\x80"), "abc");
+        assert_eq!(printable_run(b"\x1b[m"), "");
+    }
+
+    #[test]
+    fn printable_utf8() {
+        let tails = [0x00, 0x7f, 0x80, 0x8f, 0x90, 0x9f, 0xa0, 0xbf, 0xc0, 0xff];
+        for lead in 0x80..=0xff {
+            for b1 in 0..=0xff {
+                for &b2 in &tails {
+                    for &b3 in &tails {
+                        let seq = [lead, b1, b2, b3];
+                        let expected = match std::str::from_utf8(&seq) {
+                            Ok(s) => s.chars().next(),
+                            Err(e) => std::str::from_utf8(&seq[..e.valid_up_to()])
+                                .unwrap()
+                                .chars()
+                                .next(),
+                        }
+                        .filter(|c| !('\u{80}'..='\u{9f}').contains(c))
+                        .map(char::len_utf8);
+                        assert_eq!(printable_utf8_len(&seq), expected, "{:x?}", seq);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parse_runs_like_bytes() {
+        let input = "plain ascii text that is longer than a word\r\n\
+                     \x1b[1mbold\x1b[0m héllo \u{1f600}\u{1f44d}\x07\
+                     \u{9b}1m\x1b]0;t\u{e9}tle\x07\x1bPqdata\x1b\\after\x7fdel"
+            .as_bytes()
+            .iter()
+            .chain(b"bad \xff\xc0\xed\xa0\x80 utf8 \xe2\x82")
+            .copied()
+            .collect::<Vec<u8>>();
+
+        let mut parser = VTParser::new();
+        let mut actor = CollectingVTActor::default();
+        for &b in &input {
+            parser.parse_byte(b, &mut actor);
+        }
+        let expected = actor.into_vec();
+
+        // Split the input at each position, so that every sequence
+        // is also split across two calls to parse
+        for split in 0..=input.len() {
+            let mut parser = VTParser::new();
+            let mut actor = CollectingVTActor::default();
+            parser.parse(&input[..split], &mut actor);
+            parser.parse(&input[split..], &mut actor);
+            assert_eq!(actor.into_vec(), expected);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let input = b"\x1b[1;2\x18\x1b[1\x1b[2m\x1b[1?2mok\xc3(\x1bP1?q\x1b\\";
+        // Malformed sequences are only reported once enabled
+        assert!(!parse_as_vec(input)
+            .iter()
+            .any(|action| matches!(action, VTAction::ParseError { .. })));
+
+        let mut parser = VTParser::new();
+        parser.set_diagnostics(true);
+        let mut actor = CollectingVTActor::default();
+        parser.parse(input, &mut actor);
+        assert_eq!(
+            actor.into_vec(),
+            vec![
+                VTAction::ParseError {
+                    bytes: b"\x1b[1;2".to_vec(),
+                    reason: ParseErrorReason::Cancelled,
+                },
+                VTAction::ExecuteC0orC1(0x18),
+                VTAction::ParseError {
+                    bytes: b"\x1b[1".to_vec(),
+                    reason: ParseErrorReason::Interrupted,
+                },
+                VTAction::CsiDispatch {
+                    params: vec![CsiParam::Integer(2)],
+                    parameters_truncated: false,
+                    byte: b'm',
+                },
+                VTAction::ParseError {
+                    bytes: b"\x1b[1?2m".to_vec(),
+                    reason: ParseErrorReason::InvalidCsi,
+                },
+                VTAction::Print('o'),
+                VTAction::Print('k'),
+                VTAction::ParseError {
+                    bytes: b"\xc3(".to_vec(),
+                    reason: ParseErrorReason::InvalidUtf8,
+                },
+                VTAction::Print(std::char::REPLACEMENT_CHARACTER),
+                VTAction::ParseError {
+                    bytes: b"\x1bP1?q".to_vec(),
+                    reason: ParseErrorReason::InvalidDcs,
+                },
+                VTAction::EscDispatch {
+                    params: vec![],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'\\',
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dcs_params() {
+        assert_eq!(
+            parse_as_vec("\x1bP1;2|\x1b\\".as_bytes()),
+            vec![
+                VTAction::DcsHook {
+                    byte: b'|',
+                    params: vec![1, 2],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                },
+                VTAction::DcsUnhook,
+                VTAction::EscDispatch {
+                    params: vec![],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'\\',
+                }
+            ]
+        );
+    }
+
     #[test]
     fn test_ommitted_dcs_param() {
         assert_eq!(
@@ -30,6 +30,7 @@ use std::os::unix::process::CommandExt;
 #[cfg(windows)]
 use std::os::windows::io::{AsRawSocket, AsSocket, BorrowedSocket, RawSocket};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 use thiserror::Error;
@@ -48,6 +49,8 @@ enum ReaderMessage {
         pdu: Pdu,
         promise: Sender<anyhow::Result<Pdu>>,
     },
+    /// Route MuxEvent PDUs from the server to the sender
+    AddEventSink(Sender<MuxEventKind>),
     Readable,
 }
 
@@ -59,6 +62,7 @@ pub struct Client {
     client_domain_config: ClientDomainConfig,
     pub is_reconnectable: bool,
     pub is_local: bool,
+    ssh_session: Arc<Mutex<Option<wezterm_ssh::Session>>>,
 }
 
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
@@ -373,6 +377,7 @@ async fn client_thread_async(
     let mut promises = Promises {
         map: HashMap::new(),
     };
+    let mut event_sinks: Vec<Sender<MuxEventKind>> = vec![];
 
     let mut stream = reconnectable.take_stream().unwrap();
 
@@ -393,6 +398,9 @@ async fn client_thread_async(
                     .context("encoding a PDU to send to the server")?;
                 stream.flush().await.context("flushing PDU to server")?;
             }
+            Ok(ReaderMessage::AddEventSink(sink)) => {
+                event_sinks.push(sink);
+            }
             Ok(ReaderMessage::Readable) => {
                 match Pdu::decode_async(&mut stream, Some(next_serial)).await {
                     Ok(decoded) => {
@@ -401,7 +409,11 @@ async fn client_thread_async(
                             decoded.serial,
                             decoded.pdu.pdu_name()
                         );
-                        if decoded.serial == 0 {
+                        if let (0, Pdu::MuxEvent(MuxEvent { event })) =
+                            (decoded.serial, &decoded.pdu)
+                        {
+                            event_sinks.retain(|sink| sink.try_send(event.clone()).is_ok());
+                        } else if decoded.serial == 0 {
                             process_unilateral(local_domain_id, decoded)
                                 .context("processing unilateral PDU from server")
                                 .map_err(|e| {
@@ -540,6 +552,13 @@ struct Reconnectable {
     config: ClientDomainConfig,
     stream: Option<Box<dyn AsyncReadAndWrite>>,
     tls_creds: Option<GetTlsCredsResponse>,
+    port_forwards: Vec<mux::ssh::PortForwardEntry>,
+    /// The ssh session that is carrying the proxy for an ssh domain.
+    /// We hold on to it so that we can tell whether a disconnect
+    /// was caused by losing the connection to the host, and it is
+    /// shared with the `Client` so that it can be used for other
+    /// purposes, such as transferring files.
+    ssh_session: Arc<Mutex<Option<wezterm_ssh::Session>>>,
 }
 
 struct SshStream {
@@ -604,6 +623,8 @@ impl Reconnectable {
             config,
             stream,
             tls_creds: None,
+            port_forwards: vec![],
+            ssh_session: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -636,12 +657,38 @@ impl Reconnectable {
             // the set of tabs and we'd have confusing and inconsistent state
             ClientDomainConfig::Unix(_) => false,
             ClientDomainConfig::Tls(_) => true,
-            // It *does* make sense to reconnect with an ssh session, but we
-            // need to grow some smarts about whether the disconnect was because
-            // we sent CTRL-D to close the last session, or whether it was a network
-            // level disconnect, because we will otherwise throw up authentication
-            // dialogs that would be annoying
-            ClientDomainConfig::Ssh(_) => false,
+            // It makes sense to reconnect an ssh session when the network
+            // connection was lost, but not when the proxy exited because
+            // the remote mux server went away; that case is distinguished
+            // via `ssh_connection_lost` when the disconnect is handled.
+            ClientDomainConfig::Ssh(_) => true,
+        }
+    }
+
+    /// Returns true if the ssh session carrying the proxy failed,
+    /// rather than the proxy exiting.  The session can take a moment
+    /// to notice that the connection is gone after the proxy stream
+    /// reports EOF, so we give it a little time to do so.
+    fn ssh_connection_lost(&self) -> bool {
+        const GRACE_PERIOD: Duration = Duration::from_secs(2);
+        const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+        let session = match self.ssh_session.lock().unwrap().clone() {
+            Some(session) => session,
+            None => return false,
+        };
+
+        let deadline = std::time::Instant::now() + GRACE_PERIOD;
+        while session.is_connected() && std::time::Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        match session.error() {
+            Some(err) => {
+                log::warn!("ssh connection lost: {err}");
+                true
+            }
+            None => false,
         }
     }
 
@@ -681,8 +728,15 @@ impl Reconnectable {
         ui: &mut ConnectionUI,
     ) -> anyhow::Result<()> {
         let ssh_config = mux::ssh::ssh_domain_to_ssh_config(&ssh_dom)?;
+        let forwards = mux::ssh::ssh_domain_port_forwards(&ssh_dom, &ssh_config);
 
+        // Release any prior session before establishing its replacement
+        self.ssh_session.lock().unwrap().take();
         let sess = ssh_connect_with_ui(ssh_config, ui)?;
+        self.port_forwards = mux::ssh::establish_port_forwards(&sess, forwards);
+        for entry in &self.port_forwards {
+            ui.output_str(&format!("Forwarding {}\n", entry));
+        }
         let proxy_bin = Self::wezterm_bin_path(&ssh_dom.remote_wezterm_path);
 
         let cmd = if let Some(cmd) = ssh_dom.override_proxy_command.clone() {
@@ -725,6 +779,7 @@ impl Reconnectable {
             stdout: exec.stdout,
         })?);
         self.stream.replace(stream);
+        self.ssh_session.lock().unwrap().replace(sess);
         Ok(())
     }
 
@@ -1042,6 +1097,7 @@ impl Client {
         let client_domain_config = reconnectable.config.clone();
         let is_reconnectable = reconnectable.reconnectable();
         let is_local = reconnectable.is_local();
+        let ssh_session = Arc::clone(&reconnectable.ssh_session);
         let (sender, mut receiver) = unbounded();
         let client_id = ClientId::new();
 
@@ -1061,9 +1117,12 @@ impl Client {
 
                     if let Some(ioerr) = e.root_cause().downcast_ref::<std::io::Error>() {
                         if let std::io::ErrorKind::UnexpectedEof = ioerr.kind() {
-                            // Don't reconnect for a simple EOF
-                            log::error!("server closed connection ({})", e);
-                            break;
+                            // Don't reconnect for a simple EOF, unless it
+                            // was caused by losing the ssh connection
+                            if !reconnectable.ssh_connection_lost() {
+                                log::error!("server closed connection ({})", e);
+                                break;
+                            }
                         }
                     }
 
@@ -1138,9 +1197,15 @@ impl Client {
             is_local,
             client_id,
             client_domain_config,
+            ssh_session,
         }
     }
 
+    /// If this client is connected via ssh, returns that session
+    pub fn ssh_session(&self) -> Option<wezterm_ssh::Session> {
+        self.ssh_session.lock().unwrap().clone()
+    }
+
     pub fn into_client_domain_config(self) -> ClientDomainConfig {
         self.client_domain_config
     }
@@ -1168,6 +1233,21 @@ impl Client {
                     is_proxy: false,
                 })
                 .await?;
+                match self
+                    .set_codec_capabilities(SetCodecCapabilities {
+                        capabilities: CodecCapabilities::supported(),
+                    })
+                    .await
+                {
+                    Ok(resp) => {
+                        log::trace!("Using codec capabilities {:?}", resp.capabilities);
+                    }
+                    Err(err) => {
+                        // Servers that predate capability negotiation
+                        // respond with an error; carry on without them
+                        log::trace!("Server doesn't support codec capabilities: {:#}", err);
+                    }
+                }
                 Ok(info)
             }
             Ok(info) => {
@@ -1374,12 +1454,24 @@ impl Client {
     );
     rpc!(kill_pane, KillPane, UnitResponse);
     rpc!(set_client_id, SetClientId, UnitResponse);
+    rpc!(
+        set_codec_capabilities,
+        SetCodecCapabilities,
+        SetCodecCapabilitiesResponse
+    );
     rpc!(list_clients, GetClientList = (), GetClientListResponse);
+    rpc!(get_stats, GetStats = (), GetStatsResponse);
+    rpc!(
+        get_pane_screenshot,
+        GetPaneScreenshot,
+        GetPaneScreenshotResponse
+    );
     rpc!(set_window_workspace, SetWindowWorkspace, UnitResponse);
     rpc!(set_focused_pane_id, SetFocusedPane, UnitResponse);
     rpc!(get_image_cell, GetImageCell, GetImageCellResponse);
     rpc!(set_configured_palette_for_pane, SetPalette, UnitResponse);
     rpc!(set_tab_title, TabTitleChanged, UnitResponse);
+    rpc!(set_pane_title, PaneTitleChanged, UnitResponse);
     rpc!(set_window_title, WindowTitleChanged, UnitResponse);
     rpc!(rename_workspace, RenameWorkspace, UnitResponse);
     rpc!(erase_scrollback, EraseScrollbackRequest, UnitResponse);
@@ -1389,4 +1481,32 @@ impl Client {
         GetPaneDirectionResponse
     );
     rpc!(adjust_pane_size, AdjustPaneSize, UnitResponse);
+    rpc!(ssh_forward, SshForward, SshForwardResponse);
+    rpc!(ssh_transfer_open, SshTransferOpen, SshTransferOpenResponse);
+    rpc!(ssh_transfer_write, SshTransferWrite, UnitResponse);
+    rpc!(ssh_transfer_read, SshTransferRead, SshTransferReadResponse);
+    rpc!(ssh_transfer_close, SshTransferClose, UnitResponse);
+    rpc!(wait_for_pane_exit, WaitForPaneExit, WaitForPaneExitResponse);
+    rpc!(subscribe_to_events, SubscribeToEvents, UnitResponse);
+    rpc!(move_tab, MoveTab, MoveTabResponse);
+    rpc!(set_active_workspace, SetActiveWorkspace, UnitResponse);
+    rpc!(exec_command, ExecCommand, ExecCommandResponse);
+    rpc!(read_exec_output, ReadExecOutput, ReadExecOutputResponse);
+
+    /// Ask the server to send the named events, returning a channel
+    /// through which they will be delivered.
+    /// See `MuxEventKind::NAMES` for the set of valid names.
+    pub async fn subscribe_events(
+        &self,
+        events: Vec<String>,
+    ) -> anyhow::Result<Receiver<MuxEventKind>> {
+        let (tx, rx) = unbounded();
+        self.sender
+            .send(ReaderMessage::AddEventSink(tx))
+            .await
+            .map_err(|_| ChannelSendError)?;
+        self.subscribe_to_events(SubscribeToEvents { events })
+            .await?;
+        Ok(rx)
+    }
 }
@@ -354,6 +354,27 @@ fn mux_notify_client_domain(local_domain_id: DomainId, notif: MuxNotification) -
                 }
             }
         }
+        MuxNotification::PaneTitleChanged { pane_id, title } => {
+            let remote_pane_id = mux
+                .get_pane(pane_id)
+                .filter(|pane| pane.domain_id() == local_domain_id)
+                .and_then(|pane| {
+                    pane.downcast_ref::<ClientPane>()
+                        .map(|pane| pane.remote_pane_id)
+                });
+            if let (Some(remote_pane_id), Some(inner)) = (remote_pane_id, client_domain.inner()) {
+                promise::spawn::spawn(async move {
+                    inner
+                        .client
+                        .set_pane_title(codec::PaneTitleChanged {
+                            pane_id: remote_pane_id,
+                            title,
+                        })
+                        .await
+                })
+                .detach();
+            }
+        }
         MuxNotification::WindowTitleChanged {
             window_id,
             title: _,
@@ -1005,4 +1026,8 @@ impl Domain for ClientDomain {
             DomainState::Detached
         }
     }
+
+    fn ssh_session(&self) -> Option<wezterm_ssh::Session> {
+        self.inner()?.client.ssh_session()
+    }
 }
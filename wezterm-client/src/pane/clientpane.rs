@@ -48,6 +48,7 @@ pub struct ClientPane {
     user_vars: Mutex<HashMap<String, String>>,
     config: Mutex<Option<Arc<dyn TerminalConfiguration>>>,
     unseen_output: Mutex<bool>,
+    title_override: Mutex<Option<String>>,
 }
 
 impl ClientPane {
@@ -127,6 +128,7 @@ impl ClientPane {
             mouse_grabbed: Mutex::new(false),
             ignore_next_kill: Mutex::new(false),
             unseen_output: Mutex::new(false),
+            title_override: Mutex::new(None),
             user_vars: Mutex::new(HashMap::new()),
             config: Mutex::new(None),
         }
@@ -197,6 +199,23 @@ impl ClientPane {
                     alert,
                 });
             }
+            Pdu::PaneTitleChanged(PaneTitleChanged { title, .. }) => {
+                let changed = {
+                    let mut title_override = self.title_override.lock();
+                    let changed = *title_override != title;
+                    *title_override = title.clone();
+                    changed
+                };
+                // Only notify when the title actually changed; the notification
+                // is relayed back to the server, and doing that unconditionally
+                // would cycle forever
+                if changed {
+                    Mux::get().notify(MuxNotification::PaneTitleChanged {
+                        pane_id: self.local_pane_id,
+                        title,
+                    });
+                }
+            }
             Pdu::PaneRemoved(PaneRemoved { pane_id }) => {
                 log::trace!("remote pane {} has been removed", pane_id);
                 self.renderable.lock().inner.borrow_mut().dead = true;
@@ -311,11 +330,18 @@ impl Pane for ClientPane {
     }
 
     fn get_title(&self) -> String {
+        if let Some(title) = self.title_override.lock().as_ref() {
+            return title.to_string();
+        }
         let renderable = self.renderable.lock();
         let inner = renderable.inner.borrow();
         inner.title.clone()
     }
 
+    fn set_title_override(&self, title: Option<String>) {
+        *self.title_override.lock() = title;
+    }
+
     fn send_paste(&self, text: &str) -> anyhow::Result<()> {
         let client = Arc::clone(&self.client);
         let remote_pane_id = self.remote_pane_id;
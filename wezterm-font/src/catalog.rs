@@ -0,0 +1,285 @@
+//! A persistent cache of the metadata of the fonts that we find in
+//! the configured `font_dirs`, and of the built-in fonts.
+//!
+//! Finding out the names, weight and so on of a font requires opening
+//! and parsing it, which adds up to a noticeable delay at startup when
+//! there are many fonts to consider.  The catalog remembers what was
+//! learned about each font so that it can be reused the next time.
+//!
+//! The fonts found in a directory are reused for as long as the
+//! modification time of that directory is unchanged, which is the
+//! case until files are added to, removed from or renamed within it.
+//! The built-in fonts are compiled into the executable, so they are
+//! reused for as long as the version of wezterm is unchanged.
+use crate::locator::{FontDataSource, FontOrigin};
+use crate::parser::{FontPaletteInfo, Names, ParsedFont};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Changes whenever the layout of `CachedFont` changes, so that we
+/// don't try to use a catalog written by a different version
+const CATALOG_FORMAT: u32 = 1;
+
+/// The metadata for a font, as produced by `ParsedFont::from_face`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CachedFont {
+    pub index: u32,
+    pub variation: u32,
+    pub names: Names,
+    pub weight: u16,
+    pub stretch: u16,
+    pub style: CachedStyle,
+    pub cap_height: Option<f64>,
+    pub pixel_sizes: Vec<u16>,
+    pub palettes: Vec<FontPaletteInfo>,
+    pub assume_emoji_presentation: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub(crate) enum CachedStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedFile {
+    path: PathBuf,
+    fonts: Vec<CachedFont>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedDir {
+    modified: SystemTime,
+    files: Vec<CachedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CatalogData {
+    format: u32,
+    version: String,
+    /// Fonts with svg glyphs are skipped when ignore_svg_fonts is
+    /// set, so the catalog depends upon its value
+    ignore_svg_fonts: bool,
+    built_in: HashMap<String, CachedFont>,
+    dirs: HashMap<PathBuf, CachedDir>,
+}
+
+impl CatalogData {
+    fn is_compatible(&self) -> bool {
+        self.format == CATALOG_FORMAT
+            && self.version == config::wezterm_version()
+            && self.ignore_svg_fonts == config::configuration().ignore_svg_fonts
+    }
+}
+
+/// The font catalog, loaded from disk.
+/// Entries that are used are carried over into the catalog that is
+/// written back by `save`, and any that are not are dropped.
+pub(crate) struct Catalog {
+    previous: CatalogData,
+    current: CatalogData,
+    changed: bool,
+    used_built_in: bool,
+    used_dirs: bool,
+}
+
+fn catalog_file_name() -> PathBuf {
+    config::DATA_DIR.join("font-catalog.json")
+}
+
+fn dir_modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Catalog {
+    pub fn load() -> Self {
+        let previous = match std::fs::File::open(catalog_file_name()) {
+            Ok(f) => match serde_json::from_reader::<_, CatalogData>(std::io::BufReader::new(f)) {
+                Ok(data) if data.is_compatible() => data,
+                Ok(_) => {
+                    log::trace!("font catalog is from a different version, ignoring it");
+                    CatalogData::default()
+                }
+                Err(err) => {
+                    log::warn!("font catalog is invalid, ignoring it: {:#}", err);
+                    CatalogData::default()
+                }
+            },
+            Err(_) => CatalogData::default(),
+        };
+
+        Self {
+            previous,
+            current: CatalogData {
+                format: CATALOG_FORMAT,
+                version: config::wezterm_version().to_string(),
+                ignore_svg_fonts: config::configuration().ignore_svg_fonts,
+                ..CatalogData::default()
+            },
+            changed: false,
+            used_built_in: false,
+            used_dirs: false,
+        }
+    }
+
+    /// Returns the font that was parsed from the built-in font `source`
+    /// when it was cataloged
+    pub fn built_in(&mut self, name: &str, source: &FontDataSource) -> Option<ParsedFont> {
+        self.used_built_in = true;
+        let cached = self.previous.built_in.remove(name)?;
+        self.current
+            .built_in
+            .insert(name.to_string(), cached.clone());
+        Some(ParsedFont::from_cached(
+            cached,
+            source.clone(),
+            FontOrigin::BuiltIn,
+        ))
+    }
+
+    pub fn add_built_in(&mut self, name: &str, parsed: &ParsedFont) {
+        self.current
+            .built_in
+            .insert(name.to_string(), parsed.to_cached());
+        self.changed = true;
+    }
+
+    /// Returns the fonts that were found in the files of `dir`, if
+    /// `dir` has not changed since they were cataloged
+    pub fn dir(&mut self, dir: &Path) -> Option<Vec<ParsedFont>> {
+        self.used_dirs = true;
+        let modified = dir_modified(dir)?;
+        let cached = self.previous.dirs.remove(dir)?;
+        if cached.modified != modified {
+            self.changed = true;
+            return None;
+        }
+
+        let mut fonts = vec![];
+        for file in &cached.files {
+            let source = FontDataSource::OnDisk(file.path.clone());
+            for font in &file.fonts {
+                fonts.push(ParsedFont::from_cached(
+                    font.clone(),
+                    source.clone(),
+                    FontOrigin::FontDirs,
+                ));
+            }
+        }
+        self.current.dirs.insert(dir.to_path_buf(), cached);
+        Some(fonts)
+    }
+
+    /// Record the fonts that were parsed from `file`.  Nothing is
+    /// recorded for files that aren't in a directory that was passed
+    /// to `begin_dir`.
+    pub fn add_file(&mut self, file: &Path, fonts: &[ParsedFont]) {
+        let dir = match file.parent().and_then(|dir| self.current.dirs.get_mut(dir)) {
+            Some(dir) => dir,
+            None => return,
+        };
+        dir.files.push(CachedFile {
+            path: file.to_path_buf(),
+            fonts: fonts.iter().map(|font| font.to_cached()).collect(),
+        });
+        self.changed = true;
+    }
+
+    /// Start cataloging the files in `dir`, which is about to be
+    /// scanned
+    pub fn begin_dir(&mut self, dir: &Path) {
+        if let Some(modified) = dir_modified(dir) {
+            self.current.dirs.insert(
+                dir.to_path_buf(),
+                CachedDir {
+                    modified,
+                    files: vec![],
+                },
+            );
+            self.changed = true;
+        }
+    }
+
+    /// Write the catalog back to disk, if it has changed
+    pub fn save(mut self) {
+        // Entries that were looked for but not used are stale
+        if self.used_built_in && !self.previous.built_in.is_empty() {
+            self.changed = true;
+        }
+        if self.used_dirs && !self.previous.dirs.is_empty() {
+            self.changed = true;
+        }
+        if !self.changed {
+            return;
+        }
+
+        // Keep the entries of the kinds that weren't looked at
+        if !self.used_built_in {
+            self.current.built_in = std::mem::take(&mut self.previous.built_in);
+        }
+        if !self.used_dirs {
+            self.current.dirs = std::mem::take(&mut self.previous.dirs);
+        }
+
+        if let Err(err) = self.save_impl() {
+            log::warn!("Failed to save font catalog: {:#}", err);
+        }
+    }
+
+    fn save_impl(&self) -> anyhow::Result<()> {
+        if cfg!(test) {
+            // Don't write to the user's data directory from tests
+            return Ok(());
+        }
+        let file_name = catalog_file_name();
+        if let Some(dir) = file_name.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Write to a temporary file and rename it into place so that
+        // another wezterm process never sees a partially written file
+        let temp_name = file_name.with_extension(format!("json.{}", std::process::id()));
+        let json = serde_json::to_string(&self.current)?;
+        std::fs::write(&temp_name, json)?;
+        if let Err(err) = std::fs::rename(&temp_name, &file_name) {
+            std::fs::remove_file(&temp_name).ok();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::locator::FontDataHandle;
+
+    #[test]
+    fn cached_font_round_trip() {
+        let source = FontDataSource::BuiltIn {
+            data: include_bytes!("../../assets/fonts/JetBrainsMono-BoldItalic.ttf"),
+            name: "JetBrainsMono-BoldItalic.ttf",
+        };
+        let handle = FontDataHandle {
+            source: source.clone(),
+            index: 0,
+            variation: 0,
+            origin: FontOrigin::BuiltIn,
+            coverage: None,
+            coords: None,
+        };
+        let lib = crate::ftwrap::Library::new().unwrap();
+        let face = lib.face_from_locator(&handle).unwrap();
+        let parsed = ParsedFont::from_face(&face, handle.clone()).unwrap();
+
+        let json = serde_json::to_string(&parsed.to_cached()).unwrap();
+        let cached: CachedFont = serde_json::from_str(&json).unwrap();
+        let restored = ParsedFont::from_cached(cached, source, FontOrigin::BuiltIn);
+
+        assert_eq!(restored, parsed);
+        assert_eq!(restored.handle, handle);
+        assert_eq!(serde_json::to_string(&restored.to_cached()).unwrap(), json);
+    }
+}
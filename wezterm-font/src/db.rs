@@ -1,11 +1,13 @@
 //! A font-database to keep track of fonts that we've located
 
+use crate::catalog::Catalog;
 use crate::locator::{FontDataSource, FontOrigin};
 use crate::parser::{load_built_in_fonts, parse_and_collect_font_info, ParsedFont};
 use anyhow::Context;
 use config::{Config, FontAttributes};
 use rangeset::RangeSet;
 use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
 pub struct FontDatabase {
     by_full_name: HashMap<String, Vec<ParsedFont>>,
@@ -34,9 +36,15 @@ impl FontDatabase {
     }
 
     /// Build up the database from the fonts found in the configured font dirs
-    /// and from the built-in selection of fonts
+    /// and from the built-in selection of fonts.
+    /// The fonts in directories that haven't changed since they were last
+    /// scanned are taken from the font catalog rather than being parsed again.
     pub fn with_font_dirs(config: &Config) -> anyhow::Result<Self> {
+        let start = Instant::now();
+        let mut catalog = Catalog::load();
         let mut font_info = vec![];
+        // Directories whose fonts came from the catalog
+        let mut cataloged = HashSet::new();
         for path in &config.font_dirs {
             for entry in walkdir::WalkDir::new(path).into_iter() {
                 let entry = match entry {
@@ -44,15 +52,42 @@ impl FontDatabase {
                     Err(_) => continue,
                 };
 
+                if entry.file_type().is_dir() {
+                    match catalog.dir(entry.path()) {
+                        Some(mut fonts) => {
+                            font_info.append(&mut fonts);
+                            cataloged.insert(entry.path().to_path_buf());
+                        }
+                        None => catalog.begin_dir(entry.path()),
+                    }
+                    continue;
+                }
+                if entry
+                    .path()
+                    .parent()
+                    .map_or(false, |dir| cataloged.contains(dir))
+                {
+                    continue;
+                }
+
                 let source = FontDataSource::OnDisk(entry.path().to_path_buf());
-                parse_and_collect_font_info(&source, &mut font_info, FontOrigin::FontDirs)
-                    .map_err(|err| {
+                let mut parsed = vec![];
+                match parse_and_collect_font_info(&source, &mut parsed, FontOrigin::FontDirs) {
+                    Ok(()) => {
+                        catalog.add_file(entry.path(), &parsed);
+                        font_info.append(&mut parsed);
+                    }
+                    Err(err) => {
                         log::trace!("failed to read {:?}: {:#}", source, err);
-                        err
-                    })
-                    .ok();
+                    }
+                }
             }
         }
+        catalog.save();
+
+        let elapsed = start.elapsed();
+        metrics::histogram!("font.dirs.scan").record(elapsed);
+        log::debug!("scanned font_dirs in {:?}", elapsed);
 
         let mut db = Self::new();
         db.load_font_info(font_info);
@@ -4,7 +4,7 @@ use crate::locator::{FontDataHandle, FontDataSource};
 use crate::parser::ParsedFont;
 use crate::rasterizer::colr::DrawOp;
 use anyhow::{anyhow, Context};
-use config::{configuration, FreeTypeLoadFlags, FreeTypeLoadTarget};
+use config::{configuration, FontVariations, FreeTypeLoadFlags, FreeTypeLoadTarget};
 pub use freetype::*;
 use memmap2::{Mmap, MmapOptions};
 use rangeset::RangeSet;
@@ -195,6 +195,7 @@ impl Face {
                     variation: i,
                     origin: self.source.origin.clone(),
                     coverage: self.source.coverage.clone(),
+                    coords: None,
                 };
                 res.push(ParsedFont::from_face(&self, source)?);
             }
@@ -378,6 +379,54 @@ impl Face {
         (weight.round() as u16, width.round() as u16)
     }
 
+    /// Apply the specified axis coordinates to this variable font.
+    /// Axes that are not specified retain the values from the
+    /// currently selected named instance, and values are clamped
+    /// to the range supported by the font.
+    pub fn set_variation_coords(&mut self, variations: &FontVariations) -> anyhow::Result<()> {
+        let mut mm = std::ptr::null_mut();
+
+        unsafe {
+            ft_result(FT_Get_MM_Var(self.face, &mut mm), ()).context("FT_Get_MM_Var")?;
+
+            let num_axis = (*mm).num_axis;
+            let axes = from_raw_parts((*mm).axis, num_axis as usize);
+            let mut coords: Vec<FT_Fixed> = axes.iter().map(|axis| axis.def).collect();
+            let res = ft_result(
+                FT_Get_Var_Design_Coordinates(self.face, num_axis, coords.as_mut_ptr()),
+                (),
+            )
+            .context("FT_Get_Var_Design_Coordinates");
+
+            if res.is_ok() {
+                for (tag, value) in variations.iter() {
+                    let wanted = FontVariations::tag_to_u32(tag) as FT_ULong;
+                    match axes.iter().position(|axis| axis.tag == wanted) {
+                        Some(idx) => {
+                            let axis = &axes[idx];
+                            coords[idx] =
+                                FT_Fixed::from_num(value).clamp(axis.minimum, axis.maximum);
+                        }
+                        None => log::warn!(
+                            "{:?} has no `{}` variation axis; ignoring it",
+                            self.source,
+                            tag
+                        ),
+                    }
+                }
+            }
+
+            FT_Done_MM_Var(self.lib, mm);
+            res?;
+
+            ft_result(
+                FT_Set_Var_Design_Coordinates(self.face, num_axis, coords.as_mut_ptr()),
+                (),
+            )
+            .context("FT_Set_Var_Design_Coordinates")
+        }
+    }
+
     pub fn italic(&self) -> bool {
         unsafe { ((*self.face).style_flags & FT_STYLE_FLAG_ITALIC as FT_Long) != 0 }
     }
@@ -914,6 +963,7 @@ impl Face {
         load_flags: FT_Int32,
         render_mode: FT_Render_Mode,
         synthesize_bold: bool,
+        synthetic_bold_strength: Option<f64>,
     ) -> anyhow::Result<&FT_GlyphSlotRec_> {
         unsafe {
             ft_result(
@@ -933,7 +983,17 @@ impl Face {
             }
 
             if synthesize_bold {
-                FT_GlyphSlot_Embolden(slot as *mut _);
+                match synthetic_bold_strength {
+                    Some(strength) if slot.format == FT_Glyph_Format_::FT_GLYPH_FORMAT_OUTLINE => {
+                        // The strength is relative to the em size; y_scale
+                        // converts from font units to 26.6 fixed point pixels
+                        let y_scale = (*(*self.face).size).metrics.y_scale.to_num::<f64>();
+                        let em = (*self.face).units_per_EM as f64 * y_scale / 64.;
+                        let amount: FT_Pos = FT_F26Dot6::from_num(em * strength).into();
+                        FT_Outline_EmboldenXY(&mut slot.outline, amount, amount);
+                    }
+                    _ => FT_GlyphSlot_Embolden(slot as *mut _),
+                }
             }
 
             // Current versions of freetype overload the operation of FT_LOAD_COLOR
@@ -976,7 +1036,8 @@ impl Face {
             anyhow::bail!("no I from which to compute cap height");
         }
         let (load_flags, render_mode) = compute_load_flags_from_config(None, None, None, None);
-        let ft_glyph = self.load_and_render_glyph(glyph_pos, load_flags, render_mode, false)?;
+        let ft_glyph =
+            self.load_and_render_glyph(glyph_pos, load_flags, render_mode, false, None)?;
 
         let mode: FT_Pixel_Mode =
             unsafe { std::mem::transmute(u32::from(ft_glyph.bitmap.pixel_mode)) };
@@ -1221,13 +1282,26 @@ impl Library {
             .new_face(&source.source, index as _)
             .with_context(|| format!("face_from_locator({:?})", handle))?;
 
-        Ok(Face {
+        let mut face = Face {
             face,
             lib: self.lib,
             source,
             size: None,
             palette: None,
-        })
+        };
+
+        if let Some(coords) = &handle.coords {
+            if let Err(err) = face.set_variation_coords(coords) {
+                log::warn!(
+                    "Failed to apply variations {} to {:?}: {:#}",
+                    coords,
+                    handle,
+                    err
+                );
+            }
+        }
+
+        Ok(face)
     }
 
     fn new_face(&self, source: &FontDataSource, face_index: FT_Long) -> anyhow::Result<FT_Face> {
@@ -7,6 +7,7 @@ use crate::locator::{FontDataHandle, FontDataSource};
 use crate::rasterizer::colr::{ColorLine, ColorStop, DrawOp};
 use anyhow::{ensure, Context, Error};
 use cairo::Extend;
+use config::FontVariations;
 use memmap2::{Mmap, MmapOptions};
 use std::ffi::CStr;
 use std::io::Read;
@@ -33,6 +34,34 @@ pub fn language_from_string(s: &str) -> Result<hb_language_t, Error> {
     }
 }
 
+#[allow(clashing_extern_declarations)]
+extern "C" {
+    /// `hb_buffer_set_script`, declared as taking the script as a plain
+    /// tag so that we never materialize an `hb_script_t` with a value
+    /// that isn't one of its variants
+    #[link_name = "hb_buffer_set_script"]
+    fn hb_buffer_set_script_tag(buffer: *mut hb_buffer_t, script: hb_tag_t);
+}
+
+/// Returns the ISO 15924 tag of the script that `c` belongs to
+pub fn script_tag_for_char(c: char) -> hb_tag_t {
+    unsafe { hb_unicode_script(hb_unicode_funcs_get_default(), c as hb_codepoint_t) as hb_tag_t }
+}
+
+/// Converts a four letter ISO 15924 script code, such as `Hani`,
+/// into the tag used by harfbuzz for that script
+pub fn script_tag_from_string(s: &str) -> Option<hb_tag_t> {
+    match s.as_bytes() {
+        &[a, b, c, d] if s.bytes().all(|byte| byte.is_ascii_alphabetic()) => Some(hb_tag(
+            a.to_ascii_uppercase(),
+            b.to_ascii_lowercase(),
+            c.to_ascii_lowercase(),
+            d.to_ascii_lowercase(),
+        )),
+        _ => None,
+    }
+}
+
 pub fn feature_from_string(s: &str) -> Result<hb_feature_t, Error> {
     unsafe {
         let mut feature = mem::zeroed();
@@ -285,7 +314,26 @@ impl Font {
         if font.is_null() {
             anyhow::bail!("failed to create harfbuzz Font");
         }
-        Ok(Self { font })
+        let mut font = Self { font };
+        if let Some(coords) = &handle.coords {
+            font.set_variations(coords);
+        }
+        Ok(font)
+    }
+
+    /// Set the coordinates of the specified variation axes.
+    /// Fonts created via `Font::new` pick up the coordinates
+    /// from the freetype face instead.
+    pub fn set_variations(&mut self, variations: &FontVariations) {
+        for (tag, value) in variations.iter() {
+            unsafe {
+                hb_font_set_variation(
+                    self.font,
+                    FontVariations::tag_to_u32(tag) as hb_tag_t,
+                    value as f32,
+                );
+            }
+        }
     }
 
     #[allow(dead_code)]
@@ -910,11 +958,10 @@ impl ColorLine {
                 .into_iter()
                 .map(|stop| ColorStop {
                     offset: stop.offset.into(),
-                    color: if stop.is_foreground != 0 {
-                        SrgbaPixel::rgba(0xff, 0xff, 0xff, 0xff)
-                    } else {
-                        hb_color_to_srgba_pixel(stop.color)
-                    },
+                    // Foreground stops hold the foreground color that
+                    // we passed to hb_font_paint_glyph, with the alpha
+                    // of the stop applied to it
+                    color: hb_color_to_srgba_pixel(stop.color),
                 })
                 .collect(),
             extend: hb_extend_to_cairo(extend),
@@ -1013,6 +1060,15 @@ impl Buffer {
         }
     }
 
+    /// Set the script from its tag, as returned by `script_tag_from_string`.
+    /// Unlike `set_script`, this accepts scripts that are not enumerated
+    /// by `hb_script_t`.
+    pub fn set_script_tag(&mut self, script: hb_tag_t) {
+        unsafe {
+            hb_buffer_set_script_tag(self.buf, script);
+        }
+    }
+
     pub fn set_language(&mut self, lang: hb_language_t) {
         unsafe {
             hb_buffer_set_language(self.buf, lang);
@@ -1056,6 +1112,21 @@ impl Buffer {
         }
     }
 
+    /// Replace the codepoints in the buffer with those returned by `f`,
+    /// preserving their cluster values.  This is only valid before
+    /// calling font->shape() on this buffer instance.
+    pub fn map_codepoints(&mut self, mut f: impl FnMut(char) -> Option<char>) {
+        unsafe {
+            let mut len: u32 = 0;
+            let info = hb_buffer_get_glyph_infos(self.buf, &mut len as *mut _);
+            for info in slice::from_raw_parts_mut(info, len as usize) {
+                if let Some(c) = char::from_u32(info.codepoint).and_then(&mut f) {
+                    info.codepoint = c as u32;
+                }
+            }
+        }
+    }
+
     /// Returns glyph positions.  This is only valid after calling
     /// font->shape() on this buffer instance.
     pub fn glyph_positions(&self) -> &[hb_glyph_position_t] {
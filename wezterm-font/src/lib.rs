@@ -2,6 +2,7 @@ use crate::db::FontDatabase;
 use crate::locator::{new_locator, FontLocator};
 use crate::parser::ParsedFont;
 use crate::rasterizer::{new_rasterizer, FontRasterizer};
+use crate::shaper::pool::ShapeRequest;
 use crate::shaper::{new_shaper, FontShaper, PresentationWidth};
 use anyhow::{Context, Error};
 use config::{
@@ -17,11 +18,13 @@ use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use termwiz::cell::Presentation;
+use termwiz::cellcluster::CellCluster;
 use thiserror::Error;
 use wezterm_bidi::Direction;
 use wezterm_term::{CellAttributes, Intensity};
 use wezterm_toast_notification::ToastNotification;
 
+mod catalog;
 mod hbwrap;
 
 pub mod db;
@@ -67,6 +70,9 @@ pub struct LoadedFont {
     /// Glyphs for which no font was found and for which we should
     /// stop searching
     tried_glyphs: RefCell<HashSet<char>>,
+    /// A copy of the handles that can be sent to the shaping pool;
+    /// discarded when fallback handles are added
+    shared_handles: RefCell<Option<Arc<Vec<ParsedFont>>>>,
 }
 
 impl std::fmt::Debug for LoadedFont {
@@ -107,6 +113,7 @@ impl LoadedFont {
             }
             if loaded {
                 log::trace!("revised fallback: {:#?}", handles);
+                self.shared_handles.borrow_mut().take();
             }
         }
         if loaded {
@@ -156,6 +163,35 @@ impl LoadedFont {
         }
     }
 
+    /// Prepare a request to shape the text of `cluster` on the ShapePool.
+    /// Returns None if fallback fonts are waiting to be added, as the
+    /// text must then be shaped via `shape` in order to pick them up.
+    pub fn shape_request(
+        &self,
+        cluster: &CellCluster,
+        filter_out_synthetic: fn(&mut Vec<char>),
+    ) -> Option<ShapeRequest> {
+        if !self.pending_fallback.lock().unwrap().is_empty() {
+            return None;
+        }
+        let font_config = self.font_config.upgrade()?;
+        let config = font_config.config.borrow().clone();
+        let handles = Arc::clone(
+            self.shared_handles
+                .borrow_mut()
+                .get_or_insert_with(|| Arc::new(self.handles.borrow().clone())),
+        );
+        Some(ShapeRequest {
+            font_id: self.id,
+            handles,
+            config,
+            font_size: self.font_size,
+            dpi: self.dpi,
+            cluster: cluster.clone(),
+            filter_out_synthetic,
+        })
+    }
+
     pub fn shape<F: FnOnce() + Send + 'static, FS: FnOnce(&mut Vec<char>)>(
         &self,
         text: &str,
@@ -457,6 +493,9 @@ struct FontConfigInner {
     metrics: RefCell<Option<FontMetrics>>,
     dpi: RefCell<usize>,
     font_scale: RefCell<f64>,
+    /// Overrides display_pixel_geometry for the display
+    /// that we are rendering to
+    pixel_geometry: RefCell<Option<DisplayPixelGeometry>>,
     config: RefCell<ConfigHandle>,
     locator: Arc<dyn FontLocator + Send + Sync>,
     font_dirs: RefCell<Arc<FontDatabase>>,
@@ -488,6 +527,7 @@ impl FontConfigInner {
             command_palette_font: RefCell::new(None),
             font_scale: RefCell::new(1.0),
             dpi: RefCell::new(dpi),
+            pixel_geometry: RefCell::new(None),
             config: RefCell::new(config.clone()),
             font_dirs: RefCell::new(Arc::new(FontDatabase::with_font_dirs(&config)?)),
             built_in: RefCell::new(Arc::new(FontDatabase::with_built_in()?)),
@@ -624,7 +664,8 @@ impl FontConfigInner {
             text_style: text_style.clone(),
             id: alloc_font_id(),
             tried_glyphs: RefCell::new(HashSet::new()),
-            pixel_geometry: config.display_pixel_geometry,
+            shared_handles: RefCell::new(None),
+            pixel_geometry: self.pixel_geometry(&config),
         });
 
         Ok(loaded)
@@ -791,7 +832,12 @@ impl FontConfigInner {
     ) -> anyhow::Result<(Box<dyn FontShaper>, Vec<ParsedFont>)> {
         let attributes = style.font_with_fallback();
 
-        let (handles, loaded) = self.resolve_font_helper_impl(&attributes, pixel_size)?;
+        let (mut handles, loaded) = self.resolve_font_helper_impl(&attributes, pixel_size)?;
+
+        // The fonts for font_fallback_rules and icon_substitutions are appended after the regular
+        // fonts so that they don't influence the metrics, which are taken
+        // from the first font
+        handles.append(&mut self.resolve_fallback_rule_fonts(style, config, pixel_size)?);
 
         for attr in &attributes {
             if !attr.is_synthetic && !attr.is_fallback && !loaded.contains(attr) {
@@ -844,6 +890,69 @@ impl FontConfigInner {
         Ok((new_shaper(&*config, &handles)?, handles))
     }
 
+    /// Load the fonts for each of the effective font_fallback_rules,
+    /// adjusted to the weight and style of `style`, and tag them with the
+    /// index of their rule so that the shaper only uses them for matching
+    /// text.
+    fn resolve_fallback_rule_fonts(
+        &self,
+        style: &TextStyle,
+        config: &ConfigHandle,
+        pixel_size: u16,
+    ) -> anyhow::Result<Vec<ParsedFont>> {
+        let mut result = vec![];
+        let base = style.font.first();
+
+        for (rule_idx, (origin, rule)) in config
+            .effective_font_fallback_rules(style)
+            .into_iter()
+            .enumerate()
+        {
+            let attributes: Vec<FontAttributes> = rule
+                .font
+                .font
+                .iter()
+                .map(|attr| {
+                    let mut attr = attr.clone();
+                    if let Some(base) = base {
+                        if base.weight != FontWeight::REGULAR && attr.weight == FontWeight::REGULAR
+                        {
+                            attr.weight = base.weight;
+                            attr.is_synthetic = true;
+                        }
+                        if base.style != FontStyle::Normal && attr.style == FontStyle::Normal {
+                            attr.style = base.style;
+                            attr.is_synthetic = true;
+                        }
+                    }
+                    attr
+                })
+                .collect();
+
+            let (handles, loaded) = self.resolve_font_helper_impl(&attributes, pixel_size)?;
+
+            for attr in &attributes {
+                if !attr.is_synthetic && !loaded.contains(attr) {
+                    config::show_error(&format!(
+                        "Unable to load a font matching {} \
+                        ({}): {}. Text matched by that rule will use the \
+                        regular fallback fonts instead",
+                        origin,
+                        rule.describe(),
+                        attr
+                    ));
+                }
+            }
+
+            result.extend(handles.into_iter().map(|mut p| {
+                p.fallback_rule = Some(rule_idx);
+                p
+            }));
+        }
+
+        Ok(result)
+    }
+
     /// Given a text style, load (with caching) the font that best
     /// matches according to the fontconfig pattern.
     fn resolve_font(&self, myself: &Rc<Self>, style: &TextStyle) -> anyhow::Result<Rc<LoadedFont>> {
@@ -924,7 +1033,8 @@ impl FontConfigInner {
             text_style: style.clone(),
             id: alloc_font_id(),
             tried_glyphs: RefCell::new(HashSet::new()),
-            pixel_geometry: config.display_pixel_geometry,
+            shared_handles: RefCell::new(None),
+            pixel_geometry: self.pixel_geometry(&config),
         });
 
         fonts.insert(style.clone(), Rc::clone(&loaded));
@@ -954,6 +1064,25 @@ impl FontConfigInner {
         *self.font_scale.borrow()
     }
 
+    fn pixel_geometry(&self, config: &ConfigHandle) -> DisplayPixelGeometry {
+        self.pixel_geometry
+            .borrow()
+            .unwrap_or(config.display_pixel_geometry)
+    }
+
+    fn set_pixel_geometry(&self, pixel_geometry: Option<DisplayPixelGeometry>) -> bool {
+        if *self.pixel_geometry.borrow() == pixel_geometry {
+            return false;
+        }
+        *self.pixel_geometry.borrow_mut() = pixel_geometry;
+        self.fonts.borrow_mut().clear();
+        self.title_font.borrow_mut().take();
+        self.pane_select_font.borrow_mut().take();
+        self.char_select_font.borrow_mut().take();
+        self.command_palette_font.borrow_mut().take();
+        true
+    }
+
     pub fn get_dpi(&self) -> usize {
         *self.dpi.borrow()
     }
@@ -1084,6 +1213,19 @@ impl FontConfiguration {
         self.inner.get_font_scale()
     }
 
+    /// Returns the effective subpixel geometry used when rasterizing
+    pub fn pixel_geometry(&self) -> DisplayPixelGeometry {
+        self.inner.pixel_geometry(&self.inner.config.borrow())
+    }
+
+    /// Override the display_pixel_geometry from the configuration with
+    /// that of the display that is being rendered to.
+    /// Returns true if that changed, in which case any previously
+    /// rasterized glyphs need to be discarded.
+    pub fn set_pixel_geometry(&self, pixel_geometry: Option<DisplayPixelGeometry>) -> bool {
+        self.inner.set_pixel_geometry(pixel_geometry)
+    }
+
     pub fn get_dpi(&self) -> usize {
         self.inner.get_dpi()
     }
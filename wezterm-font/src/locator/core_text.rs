@@ -286,6 +286,12 @@ fn build_fallback_list_impl() -> anyhow::Result<Vec<ParsedFont>> {
         freetype_load_flags: None,
         scale: None,
         assume_emoji_presentation: None,
+        variations: None,
+        synthetic_bold_strength: None,
+        synthetic_oblique_angle: None,
+        harfbuzz_language: None,
+        harfbuzz_script: None,
+        decoration_metrics: None,
     };
     if let Ok(descriptors) = descriptor_from_attr(&symbols) {
         for descriptor in descriptors.iter() {
@@ -83,6 +83,7 @@ impl FontLocator for FontConfigFontLocator {
                     .map(FontOrigin::FontConfigMatch)
                     .unwrap_or(FontOrigin::FontConfig),
                 coverage: pat.get_charset().ok().map(|c| c.to_range_set()),
+                coords: None,
             })
         }
 
@@ -246,6 +247,7 @@ impl FontLocator for FontConfigFontLocator {
                                 variation: 0,
                                 origin: FontOrigin::FontConfig,
                                 coverage: pat.get_charset().ok().map(|c| c.to_range_set()),
+                                coords: None,
                             };
                             if let Ok(parsed) = crate::parser::ParsedFont::from_locator(&handle) {
                                 fonts.push(parsed);
@@ -327,6 +327,12 @@ impl FontLocator for GdiFontLocator {
                         freetype_load_flags: None,
                         scale: None,
                         assume_emoji_presentation: None,
+                        variations: None,
+                        synthetic_bold_strength: None,
+                        synthetic_oblique_angle: None,
+                        harfbuzz_language: None,
+                        harfbuzz_script: None,
+                        decoration_metrics: None,
                     };
 
                     if !resolved.contains(&attr) {
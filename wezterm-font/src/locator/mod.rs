@@ -1,5 +1,5 @@
 use crate::parser::ParsedFont;
-use config::FontAttributes;
+use config::{FontAttributes, FontVariations};
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::HashSet;
@@ -131,6 +131,9 @@ pub struct FontDataHandle {
     pub variation: u32,
     pub origin: FontOrigin,
     pub coverage: Option<rangeset::RangeSet<u32>>,
+    /// Coordinates for the axes of a variable font, applied on
+    /// top of the named instance selected by `variation`
+    pub coords: Option<FontVariations>,
 }
 
 impl std::hash::Hash for FontDataHandle {
@@ -138,29 +141,52 @@ impl std::hash::Hash for FontDataHandle {
     where
         H: std::hash::Hasher,
     {
-        (&self.source, self.index, self.variation, &self.origin).hash(hasher)
+        (
+            &self.source,
+            self.index,
+            self.variation,
+            &self.origin,
+            &self.coords,
+        )
+            .hash(hasher)
     }
 }
 
 impl PartialOrd for FontDataHandle {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        (&self.source, self.index, self.variation, &self.origin).partial_cmp(&(
-            &other.source,
-            other.index,
-            other.variation,
-            &other.origin,
-        ))
+        (
+            &self.source,
+            self.index,
+            self.variation,
+            &self.origin,
+            &self.coords,
+        )
+            .partial_cmp(&(
+                &other.source,
+                other.index,
+                other.variation,
+                &other.origin,
+                &other.coords,
+            ))
     }
 }
 
 impl Ord for FontDataHandle {
     fn cmp(&self, other: &Self) -> Ordering {
-        (&self.source, self.index, self.variation, &self.origin).cmp(&(
-            &other.source,
-            other.index,
-            other.variation,
-            &other.origin,
-        ))
+        (
+            &self.source,
+            self.index,
+            self.variation,
+            &self.origin,
+            &self.coords,
+        )
+            .cmp(&(
+                &other.source,
+                other.index,
+                other.variation,
+                &other.origin,
+                &other.coords,
+            ))
     }
 }
 
@@ -188,12 +214,17 @@ impl FontDataHandle {
             FontDataSource::Memory { .. } => "<imported to RAM>".to_string(),
         };
 
+        let coords = match &self.coords {
+            Some(coords) => format!(" coords={}", coords),
+            None => String::new(),
+        };
+
         if self.index == 0 && self.variation == 0 {
-            format!("{}, {}", source, self.origin)
+            format!("{}{}, {}", source, coords, self.origin)
         } else {
             format!(
-                "{} index={} variation={}, {}",
-                source, self.index, self.variation, self.origin
+                "{} index={} variation={}{}, {}",
+                source, self.index, self.variation, coords, self.origin
             )
         }
     }
@@ -1,8 +1,11 @@
+use crate::catalog::{CachedFont, CachedStyle, Catalog};
 use crate::locator::{FontDataHandle, FontDataSource, FontOrigin};
+use crate::rasterizer::FAKE_ITALIC_SKEW;
 use crate::shaper::GlyphInfo;
-use config::{FontAttributes, FontStyle, FreeTypeLoadFlags, FreeTypeLoadTarget};
+use config::{DecorationMetrics, FontAttributes, FontStyle, FreeTypeLoadFlags, FreeTypeLoadTarget};
 pub use config::{FontStretch, FontWeight};
 use rangeset::RangeSet;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::sync::Mutex;
 
@@ -12,7 +15,7 @@ pub enum MaybeShaped {
     Unresolved { raw: String, slice_start: usize },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FontPaletteInfo {
     pub name: String,
     pub palette_index: usize,
@@ -32,12 +35,24 @@ pub struct ParsedFont {
     pub synthesize_italic: bool,
     pub synthesize_bold: bool,
     pub synthesize_dim: bool,
+    /// Overrides the amount of stroke widening used when
+    /// synthesizing bold, as a fraction of the em size
+    pub synthetic_bold_strength: Option<f64>,
+    /// Overrides the angle, in degrees, used when synthesizing italics
+    pub synthetic_oblique_angle: Option<f64>,
     pub assume_emoji_presentation: bool,
     pub pixel_sizes: Vec<u16>,
     pub is_built_in_fallback: bool,
+    /// When set, this font was loaded for the entry with this index in
+    /// Config::effective_font_fallback_rules and is only used for the
+    /// text matched by that rule
+    pub fallback_rule: Option<usize>,
     pub palettes: Vec<FontPaletteInfo>,
 
     pub harfbuzz_features: Option<Vec<String>>,
+    pub harfbuzz_language: Option<String>,
+    pub harfbuzz_script: Option<String>,
+    pub decoration_metrics: Option<DecorationMetrics>,
     pub freetype_load_target: Option<FreeTypeLoadTarget>,
     pub freetype_render_target: Option<FreeTypeLoadTarget>,
     pub freetype_load_flags: Option<FreeTypeLoadFlags>,
@@ -56,13 +71,19 @@ impl std::fmt::Debug for ParsedFont {
             .field("synthesize_italic", &self.synthesize_italic)
             .field("synthesize_bold", &self.synthesize_bold)
             .field("synthesize_dim", &self.synthesize_dim)
+            .field("synthetic_bold_strength", &self.synthetic_bold_strength)
+            .field("synthetic_oblique_angle", &self.synthetic_oblique_angle)
             .field("assume_emoji_presentation", &self.assume_emoji_presentation)
             .field("pixel_sizes", &self.pixel_sizes)
             .field("harfbuzz_features", &self.harfbuzz_features)
+            .field("harfbuzz_language", &self.harfbuzz_language)
+            .field("harfbuzz_script", &self.harfbuzz_script)
+            .field("decoration_metrics", &self.decoration_metrics)
             .field("freetype_load_target", &self.freetype_load_target)
             .field("freetype_render_target", &self.freetype_render_target)
             .field("freetype_load_flags", &self.freetype_load_flags)
             .field("scale", &self.scale)
+            .field("fallback_rule", &self.fallback_rule)
             .finish()
     }
 }
@@ -77,16 +98,22 @@ impl Clone for ParsedFont {
             synthesize_italic: self.synthesize_italic,
             synthesize_bold: self.synthesize_bold,
             synthesize_dim: self.synthesize_dim,
+            synthetic_bold_strength: self.synthetic_bold_strength,
+            synthetic_oblique_angle: self.synthetic_oblique_angle,
             assume_emoji_presentation: self.assume_emoji_presentation,
             handle: self.handle.clone(),
             cap_height: self.cap_height,
             coverage: Mutex::new(self.coverage.lock().unwrap().clone()),
             pixel_sizes: self.pixel_sizes.clone(),
             harfbuzz_features: self.harfbuzz_features.clone(),
+            harfbuzz_language: self.harfbuzz_language.clone(),
+            harfbuzz_script: self.harfbuzz_script.clone(),
+            decoration_metrics: self.decoration_metrics,
             freetype_load_target: self.freetype_load_target,
             freetype_render_target: self.freetype_render_target,
             freetype_load_flags: self.freetype_load_flags,
             is_built_in_fallback: self.is_built_in_fallback,
+            fallback_rule: self.fallback_rule,
             scale: self.scale,
             palettes: self.palettes.clone(),
         }
@@ -101,6 +128,7 @@ impl PartialEq for ParsedFont {
             && self.weight == rhs.weight
             && self.style == rhs.style
             && self.names == rhs.names
+            && self.fallback_rule == rhs.fallback_rule
     }
 }
 
@@ -128,7 +156,7 @@ impl PartialOrd for ParsedFont {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Names {
     pub full_name: String,
     pub family: String,
@@ -292,13 +320,23 @@ impl ParsedFont {
     pub fn lua_fallback(handles: &[Self]) -> String {
         let mut code = "wezterm.font_with_fallback({\n".to_string();
 
-        for p in handles {
+        // Fonts pinned by font_fallback_rules are not part of the
+        // regular fallback list
+        for p in handles.iter().filter(|p| p.fallback_rule.is_none()) {
             code.push_str(&format!("  -- {}\n", p.handle.diagnostic_string()));
             if p.synthesize_italic {
-                code.push_str("  -- Will synthesize italics\n");
+                code.push_str(&format!(
+                    "  -- Will synthesize italics with a {:.1} degree slant\n",
+                    p.synthetic_italic_skew().atan().to_degrees()
+                ));
             }
             if p.synthesize_bold {
-                code.push_str("  -- Will synthesize bold\n");
+                match p.synthetic_bold_strength {
+                    Some(strength) => code.push_str(&format!(
+                        "  -- Will synthesize bold with strength {strength}\n"
+                    )),
+                    None => code.push_str("  -- Will synthesize bold\n"),
+                }
             } else if p.synthesize_dim {
                 code.push_str("  -- Will synthesize dim\n");
             }
@@ -336,7 +374,12 @@ impl ParsedFont {
                 && p.freetype_load_target.is_none()
                 && p.freetype_load_flags.is_none()
                 && p.harfbuzz_features.is_none()
+                && p.harfbuzz_language.is_none()
+                && p.harfbuzz_script.is_none()
                 && p.scale.is_none()
+                && p.handle.coords.is_none()
+                && p.synthetic_bold_strength.is_none()
+                && p.synthetic_oblique_angle.is_none()
             {
                 code.push_str(&format!("  \"{}\",\n", p.names.family));
             } else {
@@ -353,6 +396,15 @@ impl ParsedFont {
                 if let Some(scale) = p.scale {
                     code.push_str(&format!(", scale={}", scale));
                 }
+                if let Some(coords) = &p.handle.coords {
+                    code.push_str(&format!(", variations={}", coords));
+                }
+                if let Some(strength) = p.synthetic_bold_strength {
+                    code.push_str(&format!(", synthetic_bold_strength={}", strength));
+                }
+                if let Some(angle) = p.synthetic_oblique_angle {
+                    code.push_str(&format!(", synthetic_oblique_angle={}", angle));
+                }
                 if let Some(item) = p.freetype_load_flags {
                     code.push_str(&format!(", freetype_load_flags=\"{}\"", item.to_string()));
                 }
@@ -362,6 +414,12 @@ impl ParsedFont {
                 if let Some(item) = p.freetype_render_target {
                     code.push_str(&format!(", freetype_render_target=\"{:?}\"", item));
                 }
+                if let Some(lang) = &p.harfbuzz_language {
+                    code.push_str(&format!(", harfbuzz_language=\"{}\"", lang));
+                }
+                if let Some(script) = &p.harfbuzz_script {
+                    code.push_str(&format!(", harfbuzz_script=\"{}\"", script));
+                }
                 if let Some(feat) = &p.harfbuzz_features {
                     code.push_str(", harfbuzz_features={");
                     for (idx, f) in feat.iter().enumerate() {
@@ -518,13 +576,19 @@ impl ParsedFont {
             synthesize_italic: false,
             synthesize_bold: false,
             synthesize_dim: false,
+            synthetic_bold_strength: None,
+            synthetic_oblique_angle: None,
             is_built_in_fallback: false,
+            fallback_rule: None,
             assume_emoji_presentation,
             handle,
             coverage: Mutex::new(RangeSet::new()),
             cap_height,
             pixel_sizes,
             harfbuzz_features: None,
+            harfbuzz_language: None,
+            harfbuzz_script: None,
+            decoration_metrics: None,
             freetype_render_target: None,
             freetype_load_target: None,
             freetype_load_flags: None,
@@ -533,6 +597,73 @@ impl ParsedFont {
         })
     }
 
+    /// Reconstruct a font from the metadata that was recorded for it
+    /// in the font catalog, without having to open and parse it
+    pub(crate) fn from_cached(
+        cached: CachedFont,
+        source: FontDataSource,
+        origin: FontOrigin,
+    ) -> Self {
+        Self {
+            names: cached.names,
+            weight: FontWeight::from_opentype_weight(cached.weight),
+            stretch: FontStretch::from_opentype_stretch(cached.stretch),
+            style: match cached.style {
+                CachedStyle::Normal => FontStyle::Normal,
+                CachedStyle::Italic => FontStyle::Italic,
+                CachedStyle::Oblique => FontStyle::Oblique,
+            },
+            synthesize_italic: false,
+            synthesize_bold: false,
+            synthesize_dim: false,
+            synthetic_bold_strength: None,
+            synthetic_oblique_angle: None,
+            is_built_in_fallback: false,
+            fallback_rule: None,
+            assume_emoji_presentation: cached.assume_emoji_presentation,
+            handle: FontDataHandle {
+                source,
+                index: cached.index,
+                variation: cached.variation,
+                origin,
+                coverage: None,
+                coords: None,
+            },
+            coverage: Mutex::new(RangeSet::new()),
+            cap_height: cached.cap_height,
+            pixel_sizes: cached.pixel_sizes,
+            harfbuzz_features: None,
+            harfbuzz_language: None,
+            harfbuzz_script: None,
+            decoration_metrics: None,
+            freetype_render_target: None,
+            freetype_load_target: None,
+            freetype_load_flags: None,
+            scale: None,
+            palettes: cached.palettes,
+        }
+    }
+
+    /// Returns the metadata to record for this font in the font catalog
+    pub(crate) fn to_cached(&self) -> CachedFont {
+        CachedFont {
+            index: self.handle.index,
+            variation: self.handle.variation,
+            names: self.names.clone(),
+            weight: self.weight.to_opentype_weight(),
+            stretch: self.stretch.to_opentype_stretch(),
+            style: match self.style {
+                FontStyle::Normal => CachedStyle::Normal,
+                FontStyle::Italic => CachedStyle::Italic,
+                FontStyle::Oblique => CachedStyle::Oblique,
+            },
+            cap_height: self.cap_height,
+            pixel_sizes: self.pixel_sizes.clone(),
+            palettes: self.palettes.clone(),
+            assume_emoji_presentation: self.assume_emoji_presentation,
+        }
+    }
+
     /// Computes the intersection of the wanted set of codepoints with
     /// the set of codepoints covered by this font entry.
     /// Computes the codepoint coverage for this font entry if we haven't
@@ -773,19 +904,52 @@ impl ParsedFont {
         fonts.drain(idx..=idx).next().map(|p| p.synthesize(attr))
     }
 
+    /// Returns the horizontal skew to apply to the glyphs
+    /// when synthesizing italics for this font
+    pub fn synthetic_italic_skew(&self) -> f64 {
+        match self.synthetic_oblique_angle {
+            Some(angle) => angle.to_radians().tan(),
+            None => FAKE_ITALIC_SKEW,
+        }
+    }
+
     /// Update self to reflect whether the rasterizer might need to synthesize
     /// italic for this font.
     pub fn synthesize(mut self, attr: &FontAttributes) -> Self {
         self.harfbuzz_features = attr.harfbuzz_features.clone();
+        self.harfbuzz_language = attr.harfbuzz_language.clone();
+        self.harfbuzz_script = attr.harfbuzz_script.clone();
+        self.decoration_metrics = attr.decoration_metrics;
         self.freetype_render_target = attr.freetype_render_target;
         self.freetype_load_target = attr.freetype_load_target;
         self.freetype_load_flags = attr.freetype_load_flags;
         self.scale = attr.scale.map(|f| *f);
+        self.handle.coords = attr.variations.clone().filter(|v| !v.is_empty());
+
+        // The axes of a variable font take the place of synthesis,
+        // so reflect them in the weight and style of this font
+        if let Some(coords) = &self.handle.coords {
+            if let Some(wght) = coords.get("wght") {
+                self.weight = FontWeight::from_opentype_weight(wght.clamp(1., 1000.) as u16);
+            }
+            if coords.get("ital").map(|v| v >= 1.).unwrap_or(false) {
+                self.style = FontStyle::Italic;
+            } else if coords.get("slnt").map(|v| v != 0.).unwrap_or(false) {
+                self.style = FontStyle::Oblique;
+            }
+        }
+
+        self.synthetic_bold_strength = attr.synthetic_bold_strength.map(|s| *s);
+        self.synthetic_oblique_angle = attr.synthetic_oblique_angle.map(|a| *a);
 
-        self.synthesize_italic = self.style == FontStyle::Normal && attr.style != FontStyle::Normal;
+        // A value of zero disables the corresponding synthesis
+        self.synthesize_italic = self.style == FontStyle::Normal
+            && attr.style != FontStyle::Normal
+            && self.synthetic_oblique_angle != Some(0.);
         self.synthesize_bold = attr.weight >= FontWeight::DEMIBOLD
             && attr.weight > self.weight
-            && self.weight <= FontWeight::REGULAR;
+            && self.weight <= FontWeight::REGULAR
+            && self.synthetic_bold_strength != Some(0.);
         self.synthesize_dim = attr.weight < FontWeight::REGULAR
             && attr.weight < self.weight
             && self.weight >= FontWeight::REGULAR;
@@ -824,7 +988,8 @@ pub(crate) fn load_built_in_fonts(font_info: &mut Vec<ParsedFont>) -> anyhow::Re
             (include_bytes!($font) as &'static [u8], $font)
         };
     }
-    let lib = crate::ftwrap::Library::new()?;
+    let mut lib = None;
+    let mut catalog = Catalog::load();
 
     let built_ins: &[&[(&[u8], &str)]] = &[
         #[cfg(any(test, feature = "vendor-jetbrains"))]
@@ -868,19 +1033,33 @@ pub(crate) fn load_built_in_fonts(font_info: &mut Vec<ParsedFont>) -> anyhow::Re
     ];
     for bundle in built_ins {
         for (data, name) in bundle.iter() {
-            let locator = FontDataHandle {
-                source: FontDataSource::BuiltIn { data, name },
-                index: 0,
-                variation: 0,
-                origin: FontOrigin::BuiltIn,
-                coverage: None,
+            let source = FontDataSource::BuiltIn { data, name };
+            let mut parsed = match catalog.built_in(name, &source) {
+                Some(parsed) => parsed,
+                None => {
+                    let locator = FontDataHandle {
+                        source,
+                        index: 0,
+                        variation: 0,
+                        origin: FontOrigin::BuiltIn,
+                        coverage: None,
+                        coords: None,
+                    };
+                    // Only pay for initializing freetype if we need it
+                    if lib.is_none() {
+                        lib = Some(crate::ftwrap::Library::new()?);
+                    }
+                    let face = lib.as_ref().unwrap().face_from_locator(&locator)?;
+                    let parsed = ParsedFont::from_face(&face, locator)?;
+                    catalog.add_built_in(name, &parsed);
+                    parsed
+                }
             };
-            let face = lib.face_from_locator(&locator)?;
-            let mut parsed = ParsedFont::from_face(&face, locator)?;
             parsed.is_built_in_fallback = true;
             font_info.push(parsed);
         }
     }
+    catalog.save();
 
     Ok(())
 }
@@ -918,6 +1097,7 @@ pub(crate) fn parse_and_collect_font_info(
             variation: 0,
             origin: origin.clone(),
             coverage: None,
+            coords: None,
         };
 
         let face = lib.face_from_locator(&locator)?;
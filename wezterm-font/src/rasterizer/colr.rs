@@ -94,6 +94,9 @@ pub fn paint_linear_gradient(
     y2: f64,
     mut color_line: ColorLine,
 ) -> anyhow::Result<()> {
+    if color_line.color_stops.is_empty() {
+        return Ok(());
+    }
     let (min_stop, max_stop) = normalize_color_line(&mut color_line);
     let anchors = reduce_anchors(ReduceAnchorsIn {
         x0,
@@ -133,6 +136,9 @@ pub fn paint_radial_gradient(
     r1: f64,
     mut color_line: ColorLine,
 ) -> anyhow::Result<()> {
+    if color_line.color_stops.is_empty() {
+        return Ok(());
+    }
     let (min_stop, max_stop) = normalize_color_line(&mut color_line);
 
     let xx0 = x0 + min_stop * (x1 - x0);
@@ -481,8 +487,17 @@ pub fn paint_sweep_gradient(
     y0: f64,
     start_angle: f64,
     end_angle: f64,
-    color_line: ColorLine,
+    mut color_line: ColorLine,
 ) -> anyhow::Result<()> {
+    if color_line.color_stops.is_empty() {
+        return Ok(());
+    }
+    // The patches are computed by walking the stops in order,
+    // but fonts are not required to list them that way
+    color_line
+        .color_stops
+        .sort_by(|a, b| a.offset.total_cmp(&b.offset));
+
     let (x1, y1, x2, y2) = context.clip_extents()?;
 
     let max_x = ((x1 - x0) * (x1 - x0)).max((x2 - x0) * (x2 - x0));
@@ -498,18 +513,16 @@ pub fn paint_sweep_gradient(
     Ok(())
 }
 
+/// Sort the stops and rescale their offsets to the range 0.0-1.0,
+/// returning the original smallest and largest offsets.
+/// The color line must have at least one stop.
 fn normalize_color_line(color_line: &mut ColorLine) -> (f64, f64) {
-    let mut smallest = color_line.color_stops[0].offset;
-    let mut largest = smallest;
-
     color_line
         .color_stops
-        .sort_by(|a, b| a.offset.partial_cmp(&b.offset).unwrap());
+        .sort_by(|a, b| a.offset.total_cmp(&b.offset));
 
-    for stop in &color_line.color_stops[1..] {
-        smallest = smallest.min(stop.offset);
-        largest = largest.max(stop.offset);
-    }
+    let smallest = color_line.color_stops[0].offset;
+    let largest = color_line.color_stops[color_line.color_stops.len() - 1].offset;
 
     if smallest != largest {
         for stop in &mut color_line.color_stops {
@@ -517,7 +530,7 @@ fn normalize_color_line(color_line: &mut ColorLine) -> (f64, f64) {
         }
     }
 
-    (smallest as f64, largest as f64)
+    (smallest, largest)
 }
 
 struct ReduceAnchorsIn {
@@ -629,3 +642,36 @@ pub fn apply_draw_ops_to_context(ops: &[DrawOp], context: &Context) -> anyhow::R
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stop(offset: f64) -> ColorStop {
+        ColorStop {
+            offset,
+            color: SrgbaPixel::rgba(0xff, 0xff, 0xff, 0xff),
+        }
+    }
+
+    #[test]
+    fn normalize_unordered_color_line() {
+        let mut color_line = ColorLine {
+            color_stops: vec![stop(0.75), stop(-0.25), stop(0.25)],
+            extend: Extend::Pad,
+        };
+        assert_eq!(normalize_color_line(&mut color_line), (-0.25, 0.75));
+        let offsets: Vec<f64> = color_line.color_stops.iter().map(|s| s.offset).collect();
+        assert_eq!(offsets, vec![0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn normalize_single_stop() {
+        let mut color_line = ColorLine {
+            color_stops: vec![stop(0.5)],
+            extend: Extend::Repeat,
+        };
+        assert_eq!(normalize_color_line(&mut color_line), (0.5, 0.5));
+        assert_eq!(color_line.color_stops[0].offset, 0.5);
+    }
+}
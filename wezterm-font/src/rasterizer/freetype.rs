@@ -9,7 +9,7 @@ use crate::rasterizer::colr::{
     ColorLine, ColorStop, PaintOp,
 };
 use crate::rasterizer::harfbuzz::{argb_to_rgba, HarfbuzzRasterizer};
-use crate::rasterizer::{FontRasterizer, FAKE_ITALIC_SKEW};
+use crate::rasterizer::FontRasterizer;
 use crate::units::*;
 use crate::{ftwrap, FontRasterizerSelection, RasterizedGlyph};
 use ::freetype::{
@@ -29,6 +29,7 @@ pub struct FreeTypeRasterizer {
     face: RefCell<ftwrap::Face>,
     _lib: ftwrap::Library,
     synthesize_bold: bool,
+    synthetic_bold_strength: Option<f64>,
     freetype_load_target: Option<FreeTypeLoadTarget>,
     freetype_render_target: Option<FreeTypeLoadTarget>,
     freetype_load_flags: Option<FreeTypeLoadFlags>,
@@ -62,6 +63,7 @@ impl FontRasterizer for FreeTypeRasterizer {
             load_flags,
             render_mode,
             self.synthesize_bold,
+            self.synthetic_bold_strength,
         ) {
             Ok(g) => g,
             Err(err) => {
@@ -79,7 +81,11 @@ impl FontRasterizer for FreeTypeRasterizer {
                             );
                         }
                         FontRasterizerSelection::Harfbuzz => {
-                            return self.hb_raster.rasterize_glyph(glyph_pos, size, dpi);
+                            return self.hb_raster.rasterize_glyph(
+                                glyph_pos,
+                                size * self.scale,
+                                dpi,
+                            );
                         }
                     }
                 }
@@ -208,6 +214,21 @@ impl FreeTypeRasterizer {
         }
     }
 
+    /// Map the coverage of the first, second and third subpixels of an
+    /// LCD rendered pixel to red, green and blue according to the pixel
+    /// geometry.  When subpixel antialiasing isn't possible on the display,
+    /// the subpixels are combined into the same grayscale coverage.
+    fn order_subpixels(&self, first: u8, second: u8, third: u8) -> (u8, u8, u8) {
+        match self.display_pixel_geometry {
+            DisplayPixelGeometry::RGB | DisplayPixelGeometry::Auto => (first, second, third),
+            DisplayPixelGeometry::BGR => (third, second, first),
+            DisplayPixelGeometry::None => {
+                let gray = ((first as u16 + second as u16 + third as u16) / 3) as u8;
+                (gray, gray, gray)
+            }
+        }
+    }
+
     fn rasterize_lcd(
         &self,
         pitch: usize,
@@ -223,9 +244,11 @@ impl FreeTypeRasterizer {
             let src_offset = y * pitch as usize;
             let dest_offset = y * width * 4;
             for x in 0..width {
-                let red = data[src_offset + (x * 3)];
-                let green = data[src_offset + (x * 3) + 1];
-                let blue = data[src_offset + (x * 3) + 2];
+                let (red, green, blue) = self.order_subpixels(
+                    data[src_offset + (x * 3)],
+                    data[src_offset + (x * 3) + 1],
+                    data[src_offset + (x * 3) + 2],
+                );
 
                 let linear_alpha = red.max(green).max(blue);
 
@@ -238,11 +261,6 @@ impl FreeTypeRasterizer {
                 let green = linear_u8_to_srgb8(green);
                 let blue = linear_u8_to_srgb8(blue);
 
-                let (red, blue) = match self.display_pixel_geometry {
-                    DisplayPixelGeometry::RGB => (red, blue),
-                    DisplayPixelGeometry::BGR => (blue, red),
-                };
-
                 rgba[dest_offset + (x * 4)] = red;
                 rgba[dest_offset + (x * 4) + 1] = green;
                 rgba[dest_offset + (x * 4) + 2] = blue;
@@ -276,9 +294,11 @@ impl FreeTypeRasterizer {
             let src_offset = y * pitch * 3;
             let dest_offset = y * width * 4;
             for x in 0..width {
-                let red = data[src_offset + x];
-                let green = data[src_offset + x + pitch];
-                let blue = data[src_offset + x + 2 * pitch];
+                let (red, green, blue) = self.order_subpixels(
+                    data[src_offset + x],
+                    data[src_offset + x + pitch],
+                    data[src_offset + x + 2 * pitch],
+                );
 
                 let linear_alpha = red.max(green).max(blue);
 
@@ -291,11 +311,6 @@ impl FreeTypeRasterizer {
                 let green = linear_u8_to_srgb8(green);
                 let blue = linear_u8_to_srgb8(blue);
 
-                let (red, blue) = match self.display_pixel_geometry {
-                    DisplayPixelGeometry::RGB => (red, blue),
-                    DisplayPixelGeometry::BGR => (blue, red),
-                };
-
                 rgba[dest_offset + (x * 4)] = red;
                 rgba[dest_offset + (x * 4) + 1] = green;
                 rgba[dest_offset + (x * 4) + 2] = blue;
@@ -390,10 +405,10 @@ impl FreeTypeRasterizer {
 
         if parsed.synthesize_italic {
             face.set_transform(Some(FT_Matrix {
-                xx: FT_Fixed::from_num(1),                // scale x
-                yy: FT_Fixed::from_num(1),                // scale y
-                xy: FT_Fixed::from_num(FAKE_ITALIC_SKEW), // skew x
-                yx: FT_Fixed::from_num(0),                // skew y
+                xx: FT_Fixed::from_num(1),                              // scale x
+                yy: FT_Fixed::from_num(1),                              // scale y
+                xy: FT_Fixed::from_num(parsed.synthetic_italic_skew()), // skew x
+                yx: FT_Fixed::from_num(0),                              // skew y
             }));
         }
 
@@ -402,6 +417,7 @@ impl FreeTypeRasterizer {
             face: RefCell::new(face),
             has_color,
             synthesize_bold: parsed.synthesize_bold,
+            synthetic_bold_strength: parsed.synthetic_bold_strength,
             freetype_load_flags: parsed.freetype_load_flags,
             freetype_load_target: parsed.freetype_load_target,
             freetype_render_target: parsed.freetype_render_target,
@@ -566,8 +582,8 @@ impl<'a> Walker<'a> {
                         y0,
                         x1,
                         y1,
-                        r0: grad.r0.font_units() as f32,
-                        r1: grad.r1.font_units() as f32,
+                        r0: grad.r0.f16d16().to_num(),
+                        r1: grad.r1.f16d16().to_num(),
                         color_line: self.decode_color_line(&grad.colorline)?,
                     };
                     self.ops.push(paint);
@@ -576,8 +592,10 @@ impl<'a> Walker<'a> {
                     let grad = paint.u.sweep_gradient.as_ref();
                     log::trace!("{level:>3} {grad:?}");
                     let (x0, y0) = vector_x_y(&grad.center);
-                    let start_angle = grad.start_angle.to_num();
-                    let end_angle = grad.end_angle.to_num();
+                    // The angles are expressed in units of 180 degrees;
+                    // convert them to radians
+                    let start_angle = PI as f32 * grad.start_angle.to_num::<f32>();
+                    let end_angle = PI as f32 * grad.end_angle.to_num::<f32>();
 
                     let paint = PaintOp::PaintSweepGradient {
                         x0,
@@ -643,7 +661,7 @@ impl<'a> Walker<'a> {
 
                     // Scaling around a center coordinate
                     let center_x = scale.center_x.to_num();
-                    let center_y = scale.center_x.to_num();
+                    let center_y = scale.center_y.to_num();
 
                     let mut p1 = Matrix::identity();
                     p1.translate(center_x, center_y);
@@ -668,7 +686,7 @@ impl<'a> Walker<'a> {
 
                     // Rotating around a center coordinate
                     let center_x = rot.center_x.to_num();
-                    let center_y = rot.center_x.to_num();
+                    let center_y = rot.center_y.to_num();
 
                     let mut p1 = Matrix::identity();
                     p1.translate(center_x, center_y);
@@ -693,7 +711,7 @@ impl<'a> Walker<'a> {
 
                     // Skewing around a center coordinate
                     let center_x = skew.center_x.to_num();
-                    let center_y = skew.center_x.to_num();
+                    let center_y = skew.center_y.to_num();
 
                     let mut p1 = Matrix::identity();
                     p1.translate(center_x, center_y);
@@ -793,8 +811,8 @@ fn affine2x3_to_matrix(t: FT_Affine23) -> Matrix {
         t.yx.to_num(),
         t.xy.to_num(),
         t.yy.to_num(),
-        t.dy.to_num(),
         t.dx.to_num(),
+        t.dy.to_num(),
     )
 }
 
@@ -837,7 +855,10 @@ fn record_to_cairo_surface(
                 context.restore()?;
             }
             PaintOp::PaintSolid(color) => {
-                if color.as_srgba32() != 0xffffffff {
+                // The foreground is painted as white, with any alpha
+                // from the paint, so that it can be tinted later
+                let (r, g, b, _a) = color.as_rgba();
+                if (r, g, b) != (0xff, 0xff, 0xff) {
                     has_color = true;
                 }
                 let (r, g, b, a) = color.as_srgba_tuple();
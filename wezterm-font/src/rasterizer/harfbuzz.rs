@@ -1,11 +1,10 @@
 use crate::hbwrap::{
     hb_color, hb_color_get_alpha, hb_color_get_blue, hb_color_get_green, hb_color_get_red,
-    hb_color_t, hb_paint_composite_mode_t, hb_tag_to_string, Font, PaintOp, IS_PNG,
+    hb_color_t, hb_paint_composite_mode_t, hb_tag_to_string, Font, PaintOp, IS_BGRA, IS_PNG,
 };
 use crate::rasterizer::colr::{
     apply_draw_ops_to_context, paint_linear_gradient, paint_radial_gradient, paint_sweep_gradient,
 };
-use crate::rasterizer::FAKE_ITALIC_SKEW;
 use crate::units::PixelLength;
 use crate::{FontRasterizer, ParsedFont, RasterizedGlyph};
 use cairo::{Content, Context, Format, ImageSurface, Matrix, Operator, RecordingSurface};
@@ -22,10 +21,11 @@ impl HarfbuzzRasterizer {
         font.set_ot_funcs();
 
         if parsed.synthesize_italic {
-            font.set_synthetic_slant(FAKE_ITALIC_SKEW as f32);
+            font.set_synthetic_slant(parsed.synthetic_italic_skew() as f32);
         }
         if parsed.synthesize_bold {
-            font.set_synthetic_bold(0.02, 0.02, false);
+            let strength = parsed.synthetic_bold_strength.unwrap_or(0.02) as f32;
+            font.set_synthetic_bold(strength, strength, false);
         }
 
         Ok(Self { font })
@@ -165,10 +165,12 @@ fn record_to_cairo_surface(paint_ops: Vec<PaintOp>) -> anyhow::Result<(Recording
                 context.restore()?;
             }
             PaintOp::PaintSolid {
-                is_foreground: _,
+                is_foreground,
                 color,
             } => {
-                if color != 0xffffffff {
+                // The foreground is painted as white, with any alpha
+                // from the paint, so that it can be tinted later
+                if !is_foreground {
                     has_color = true;
                 }
                 let (r, g, b, a) = hb_color_to_rgba(color);
@@ -236,8 +238,8 @@ fn record_to_cairo_surface(paint_ops: Vec<PaintOp>) -> anyhow::Result<(Recording
             }
             PaintOp::PaintImage {
                 image,
-                width: _,
-                height: _,
+                width,
+                height,
                 format,
                 slant,
                 extents,
@@ -260,6 +262,23 @@ fn record_to_cairo_surface(paint_ops: Vec<PaintOp>) -> anyhow::Result<(Recording
                     rgba_to_argb_and_multiply(&mut data);
                     // premultiply(&mut data);
 
+                    let width = width as i32;
+                    let height = height as i32;
+                    ImageSurface::create_for_data(data, Format::ARgb32, width, height, width * 4)?
+                } else if format == IS_BGRA {
+                    // Pre-multiplied BGRA32, which is the same as
+                    // the ARGB32 used by cairo on little endian systems
+                    let mut data = image.as_slice().to_vec();
+                    anyhow::ensure!(
+                        data.len() >= (width * height * 4) as usize,
+                        "BGRA image data is too short for {width}x{height}"
+                    );
+                    #[cfg(target_endian = "big")]
+                    for pixel in data.chunks_exact_mut(4) {
+                        pixel.reverse();
+                    }
+                    has_color = true;
+
                     let width = width as i32;
                     let height = height as i32;
                     ImageSurface::create_for_data(data, Format::ARgb32, width, height, width * 4)?
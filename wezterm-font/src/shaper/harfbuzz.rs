@@ -3,7 +3,7 @@ use crate::shaper::{FallbackIdx, FontMetrics, FontShaper, GlyphInfo, Presentatio
 use crate::units::*;
 use crate::{ftwrap, hbwrap as harfbuzz};
 use anyhow::{anyhow, Context};
-use config::ConfigHandle;
+use config::{ConfigHandle, UnicodeRange};
 use finl_unicode::grapheme_clusters::Graphemes;
 use log::error;
 use ordered_float::NotNan;
@@ -69,6 +69,8 @@ struct FontPair {
     shaped_any: bool,
     presentation: Presentation,
     features: Vec<harfbuzz::hb_feature_t>,
+    language: harfbuzz::hb_language_t,
+    script: Option<harfbuzz::hb_tag_t>,
     last_size_and_dpi: RefCell<Option<(f64, u32)>>,
 }
 
@@ -79,13 +81,48 @@ struct MetricsKey {
     dpi: u32,
 }
 
+/// The fonts to try, in order, for the text matched by
+/// one of the effective font_fallback_rules
+struct FallbackRuleOrder {
+    unicode_ranges: Vec<UnicodeRange>,
+    scripts: Vec<harfbuzz::hb_tag_t>,
+    order: Vec<FallbackIdx>,
+}
+
 pub struct HarfbuzzShaper {
     handles: Vec<ParsedFont>,
+    /// The fonts to try, in order, for text that isn't matched
+    /// by any of the font_fallback_rules
+    default_order: Vec<FallbackIdx>,
+    rules: Vec<FallbackRuleOrder>,
     fonts: Vec<RefCell<Option<FontPair>>>,
     lib: ftwrap::Library,
     metrics: RefCell<HashMap<MetricsKey, FontMetrics>>,
     features: Vec<harfbuzz::hb_feature_t>,
     lang: harfbuzz::hb_language_t,
+    script: Option<harfbuzz::hb_tag_t>,
+    /// Additional features for text in a particular language
+    language_features: Vec<(harfbuzz::hb_language_t, Vec<harfbuzz::hb_feature_t>)>,
+    /// The replacement codepoints from the icon_substitutions
+    substitutions: HashMap<char, char>,
+}
+
+/// Resolve a BCP 47 language tag, falling back to english
+/// if it isn't valid
+fn language_or_default(lang: &str) -> harfbuzz::hb_language_t {
+    harfbuzz::language_from_string(lang)
+        .or_else(|err| {
+            log::warn!("{err:#}, using `en` instead");
+            harfbuzz::language_from_string("en")
+        })
+        .expect("`en` is a valid language")
+}
+
+fn features_from_strings(features: &[String]) -> Vec<harfbuzz::hb_feature_t> {
+    features
+        .iter()
+        .filter_map(|s| harfbuzz::feature_from_string(s).ok())
+        .collect()
 }
 
 /// Make a string holding a set of unicode replacement
@@ -124,21 +161,80 @@ impl HarfbuzzShaper {
             fonts.push(RefCell::new(None));
         }
 
-        let lang = harfbuzz::language_from_string("en")?;
+        let lang = language_or_default(&config.harfbuzz_language);
+        let script = config
+            .harfbuzz_script
+            .as_deref()
+            .and_then(harfbuzz::script_tag_from_string);
+
+        let features = features_from_strings(&config.harfbuzz_features);
+        let language_features = config
+            .harfbuzz_language_features
+            .iter()
+            .filter_map(|(lang, features)| {
+                Some((
+                    harfbuzz::language_from_string(lang).ok()?,
+                    features_from_strings(features),
+                ))
+            })
+            .collect();
+
+        let default_order: Vec<FallbackIdx> = handles
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.fallback_rule.is_none())
+            .map(|(idx, _)| idx)
+            .collect();
+
+        // Only the text matched by the rules is used here, and that
+        // doesn't depend upon the style used for icons that are scaled
+        let rules = config
+            .effective_font_fallback_rules(&config.font)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(rule_idx, (_, rule))| {
+                let mut order: Vec<FallbackIdx> = handles
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, p)| p.fallback_rule == Some(rule_idx))
+                    .map(|(idx, _)| idx)
+                    .collect();
+                if order.is_empty() {
+                    // None of its fonts could be loaded, so the rule
+                    // would behave the same as the default order
+                    return None;
+                }
+                order.extend_from_slice(&default_order);
+                Some(FallbackRuleOrder {
+                    unicode_ranges: rule.unicode_ranges,
+                    scripts: rule
+                        .scripts
+                        .iter()
+                        .filter_map(|s| harfbuzz::script_tag_from_string(s))
+                        .collect(),
+                    order,
+                })
+            })
+            .collect();
 
-        let features: Vec<harfbuzz::hb_feature_t> = config
-            .harfbuzz_features
+        let substitutions = config
+            .icon_substitutions
             .iter()
-            .filter_map(|s| harfbuzz::feature_from_string(s).ok())
+            .filter_map(|icon| Some((icon.icon.0, icon.replacement?.0)))
             .collect();
 
         Ok(Self {
             fonts,
             handles,
+            default_order,
+            rules,
             lib,
             metrics: RefCell::new(HashMap::new()),
             features,
             lang,
+            script,
+            language_features,
+            substitutions,
         })
     }
 
@@ -173,13 +269,24 @@ impl HarfbuzzShaper {
                         font
                     };
 
-                    let features = match &handle.harfbuzz_features {
-                        Some(features) => features
-                            .iter()
-                            .filter_map(|s| harfbuzz::feature_from_string(s).ok())
-                            .collect(),
+                    let language = match &handle.harfbuzz_language {
+                        Some(lang) => language_or_default(lang),
+                        None => self.lang,
+                    };
+                    let script = match &handle.harfbuzz_script {
+                        Some(script) => harfbuzz::script_tag_from_string(script),
+                        None => self.script,
+                    };
+
+                    let mut features = match &handle.harfbuzz_features {
+                        Some(features) => features_from_strings(features),
                         None => self.features.clone(),
                     };
+                    for (lang, lang_features) in &self.language_features {
+                        if *lang == language {
+                            features.extend_from_slice(lang_features);
+                        }
+                    }
 
                     *opt_pair = Some(FontPair {
                         face,
@@ -191,6 +298,8 @@ impl HarfbuzzShaper {
                             Presentation::Text
                         },
                         features,
+                        language,
+                        script,
                         last_size_and_dpi: RefCell::new(None),
                     });
                 }
@@ -202,9 +311,12 @@ impl HarfbuzzShaper {
         }
     }
 
+    /// Shape `range` of `s` using the fonts listed in `order`, starting
+    /// with the font at position `pos` in that list
     fn do_shape(
         &self,
-        mut font_idx: FallbackIdx,
+        order: &[FallbackIdx],
+        mut pos: usize,
         s: &str,
         font_size: f64,
         dpi: u32,
@@ -215,12 +327,12 @@ impl HarfbuzzShaper {
         presentation_width: Option<&PresentationWidth>,
     ) -> anyhow::Result<Vec<GlyphInfo>> {
         let mut buf = harfbuzz::Buffer::new()?;
-        // We deliberately omit setting the script and leave it to harfbuzz
-        // to infer from the buffer contents so that it can correctly
-        // enable appropriate preprocessing for eg: Hangul.
+        // Unless the user has configured one, we deliberately omit
+        // setting the script and leave it to harfbuzz to infer from
+        // the buffer contents so that it can correctly enable
+        // appropriate preprocessing for eg: Hangul.
         // <https://github.com/wez/wezterm/issues/1474> and
         // <https://github.com/wez/wezterm/issues/1573>
-        // buf.set_script(harfbuzz::hb_script_t::HB_SCRIPT_LATIN);
         buf.set_direction(match direction {
             Direction::LeftToRight => harfbuzz::hb_direction_t::HB_DIRECTION_LTR,
             Direction::RightToLeft => harfbuzz::hb_direction_t::HB_DIRECTION_RTL,
@@ -228,6 +340,9 @@ impl HarfbuzzShaper {
         buf.set_language(self.lang);
 
         buf.add_str(s, range.clone());
+        if !self.substitutions.is_empty() {
+            buf.map_codepoints(|c| self.substitutions.get(&c).copied());
+        }
         buf.guess_segment_properties();
         buf.set_cluster_level(
             harfbuzz::hb_buffer_cluster_level_t::HB_BUFFER_CLUSTER_LEVEL_MONOTONE_GRAPHEMES,
@@ -239,8 +354,14 @@ impl HarfbuzzShaper {
         // In that case, we accept shaper info with codepoint==0 and
         // will use the notdef glyph from the base font.
         let mut no_more_fallbacks = false;
+        let mut font_idx;
 
         loop {
+            font_idx = if no_more_fallbacks {
+                0
+            } else {
+                order.get(pos).copied().unwrap_or(self.handles.len())
+            };
             match self.load_fallback(font_idx, dpi).context("load_fallback")? {
                 Some(mut pair) => {
                     if let Some(p) = presentation {
@@ -251,7 +372,7 @@ impl HarfbuzzShaper {
                                      font_idx={font_idx}",
                                 pair.presentation
                             );
-                            font_idx += 1;
+                            pos += 1;
                             continue;
                         }
                     }
@@ -286,6 +407,12 @@ impl HarfbuzzShaper {
                             .replace((point_size, dpi));
                     }
 
+                    // The language and script may be overridden per font
+                    buf.set_language(pair.language);
+                    if let Some(script) = pair.script {
+                        buf.set_script_tag(script);
+                    }
+
                     let mut font = pair.font.borrow_mut();
                     shaped_any = pair.shaped_any;
                     font.shape(&mut buf, pair.features.as_slice());
@@ -299,7 +426,7 @@ impl HarfbuzzShaper {
                 }
                 None => {
                     for c in s.chars() {
-                        no_glyphs.push(c);
+                        no_glyphs.push(self.substitutions.get(&c).copied().unwrap_or(c));
                     }
 
                     if presentation.is_some() {
@@ -319,6 +446,7 @@ impl HarfbuzzShaper {
                         // that glyph in a fallback font and swap it out a little
                         // later after a flash of showing the emoji one.
                         return self.do_shape(
+                            order,
                             0,
                             s,
                             font_size,
@@ -334,7 +462,6 @@ impl HarfbuzzShaper {
                     // One more go around to pick up the base font and
                     // accept using the notdef glyph from that.
                     no_more_fallbacks = true;
-                    font_idx = 0;
                     continue;
                 }
             }
@@ -480,7 +607,8 @@ impl HarfbuzzShaper {
                 let first_info = &infos[0];
 
                 let mut shape = match self.do_shape(
-                    font_idx + 1,
+                    order,
+                    pos + 1,
                     s,
                     font_size,
                     dpi,
@@ -495,6 +623,7 @@ impl HarfbuzzShaper {
                     Err(e) => {
                         error!("{:?} for {:?}", e, substr);
                         self.do_shape(
+                            order,
                             0,
                             &make_question_string(substr),
                             font_size,
@@ -561,6 +690,86 @@ impl HarfbuzzShaper {
 
         Ok(cluster)
     }
+
+    /// Returns the index into `self.rules` of the rule that matches
+    /// the grapheme, `Some(None)` if no rule matches it, or `None`
+    /// if the grapheme is common to all scripts and should be shaped
+    /// with the text that surrounds it
+    fn rule_for_grapheme(&self, grapheme: &str) -> Option<Option<usize>> {
+        let c = grapheme.chars().next()?;
+        if let Some(idx) = self
+            .rules
+            .iter()
+            .position(|rule| rule.unicode_ranges.iter().any(|r| r.contains(c)))
+        {
+            return Some(Some(idx));
+        }
+
+        let script = harfbuzz::script_tag_for_char(c);
+        if script == harfbuzz::hb_script_t::HB_SCRIPT_COMMON as harfbuzz::hb_tag_t
+            || script == harfbuzz::hb_script_t::HB_SCRIPT_INHERITED as harfbuzz::hb_tag_t
+        {
+            return None;
+        }
+        Some(
+            self.rules
+                .iter()
+                .position(|rule| rule.scripts.contains(&script)),
+        )
+    }
+
+    /// Split `range` into runs of text that are matched by the same
+    /// effective font_fallback_rules entry, or by none of them, and shape each
+    /// run with the fonts for its rule
+    fn shape_with_rules(
+        &self,
+        text: &str,
+        size: f64,
+        dpi: u32,
+        no_glyphs: &mut Vec<char>,
+        presentation: Option<Presentation>,
+        direction: Direction,
+        range: Range<usize>,
+        presentation_width: Option<&PresentationWidth>,
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        let mut runs: Vec<(Option<usize>, Range<usize>)> = vec![];
+        let mut start = range.start;
+        for grapheme in Graphemes::new(&text[range.clone()]) {
+            let end = start + grapheme.len();
+            match (self.rule_for_grapheme(grapheme), runs.last_mut()) {
+                (Some(rule), Some((last_rule, run))) if *last_rule == rule => run.end = end,
+                (None, Some((_, run))) => run.end = end,
+                (rule, _) => runs.push((rule.flatten(), start..end)),
+            }
+            start = end;
+        }
+
+        // The glyphs for right-to-left text are produced in visual order
+        if direction == Direction::RightToLeft {
+            runs.reverse();
+        }
+
+        let mut result = vec![];
+        for (rule, run) in runs {
+            let order = match rule {
+                Some(idx) => &self.rules[idx].order,
+                None => &self.default_order,
+            };
+            result.append(&mut self.do_shape(
+                order,
+                0,
+                text,
+                size,
+                dpi,
+                no_glyphs,
+                presentation,
+                direction,
+                run,
+                presentation_width,
+            )?);
+        }
+        Ok(result)
+    }
 }
 
 impl FontShaper for HarfbuzzShaper {
@@ -582,17 +791,31 @@ impl FontShaper for HarfbuzzShaper {
             text.escape_debug()
         );
         let start = std::time::Instant::now();
-        let result = self.do_shape(
-            0,
-            text,
-            size,
-            dpi,
-            no_glyphs,
-            presentation,
-            direction,
-            range,
-            presentation_width,
-        );
+        let result = if self.rules.is_empty() {
+            self.do_shape(
+                &self.default_order,
+                0,
+                text,
+                size,
+                dpi,
+                no_glyphs,
+                presentation,
+                direction,
+                range,
+                presentation_width,
+            )
+        } else {
+            self.shape_with_rules(
+                text,
+                size,
+                dpi,
+                no_glyphs,
+                presentation,
+                direction,
+                range,
+                presentation_width,
+            )
+        };
         metrics::histogram!("shape.harfbuzz").record(start.elapsed());
         /*
         if let Ok(glyphs) = &result {
@@ -644,8 +867,15 @@ impl FontShaper for HarfbuzzShaper {
             is_scaled: selected_size.is_scaled,
             presentation: pair.presentation,
             force_y_adjust: PixelLength::new(0.),
+            strikethrough_row: None,
+            overline_row: None,
+            curly_underline_amplitude: None,
         };
 
+        if let Some(decoration) = &self.handles[font_idx].decoration_metrics {
+            metrics.apply_decoration_metrics(decoration);
+        }
+
         // When the user has overridden the scale, we need to stash a y-adjustment
         // so that the glyphs are better vertically aligned
         // <https://github.com/wez/wezterm/issues/1803>
@@ -862,6 +1092,12 @@ mod test {
                     harfbuzz_features: None,
                     scale: None,
                     assume_emoji_presentation: None,
+                    variations: None,
+                    synthetic_bold_strength: None,
+                    synthetic_oblique_angle: None,
+                    harfbuzz_language: None,
+                    harfbuzz_script: None,
+                    decoration_metrics: None,
                 },
                 14,
             )
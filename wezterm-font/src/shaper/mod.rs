@@ -1,10 +1,13 @@
 use crate::parser::ParsedFont;
 use crate::units::PixelLength;
+use config::DecorationMetrics;
+use ordered_float::NotNan;
 use std::ops::Range;
 use termwiz::cell::Presentation;
 use termwiz::cellcluster::CellCluster;
 
 pub mod harfbuzz;
+pub mod pool;
 pub use wezterm_bidi::Direction;
 
 /// Holds information about a shaped glyph
@@ -81,6 +84,35 @@ pub struct FontMetrics {
     /// the glyph to make it appear to line up better.
     /// <https://github.com/wez/wezterm/issues/1803>
     pub force_y_adjust: PixelLength,
+
+    /// When overridden by the font configuration, the position of the
+    /// strikethrough and overline, measured from the top of the cell
+    pub strikethrough_row: Option<PixelLength>,
+    pub overline_row: Option<PixelLength>,
+    /// When overridden by the font configuration, the amplitude
+    /// of the wave of a curly underline
+    pub curly_underline_amplitude: Option<PixelLength>,
+}
+
+impl FontMetrics {
+    /// Apply the overrides from the font configuration, whose values
+    /// are relative to the cell height
+    pub fn apply_decoration_metrics(&mut self, decoration: &DecorationMetrics) {
+        let cell_height = self.cell_height.get();
+        let to_pixels = |v: Option<NotNan<f64>>| v.map(|v| PixelLength::new(*v * cell_height));
+
+        if let Some(thickness) = to_pixels(decoration.underline_thickness) {
+            self.underline_thickness = thickness;
+        }
+        if let Some(row) = to_pixels(decoration.underline_position) {
+            // underline_position is relative to the baseline,
+            // which is `descender` up from the bottom of the cell
+            self.underline_position = PixelLength::new(cell_height) + self.descender - row;
+        }
+        self.strikethrough_row = to_pixels(decoration.strikethrough_position);
+        self.overline_row = to_pixels(decoration.overline_position);
+        self.curly_underline_amplitude = to_pixels(decoration.curly_underline_amplitude);
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,314 @@
+//! Shapes text on a pool of worker threads.
+//!
+//! Shapers hold harfbuzz and freetype state that cannot be shared between
+//! threads, so each worker builds its own shapers from the font handles
+//! of the `LoadedFont` on whose behalf it is shaping.  The results are
+//! retained in a cache that is shared between all of the windows and panes,
+//! so that a run of text is shaped only once regardless of where it appears.
+use crate::parser::ParsedFont;
+use crate::shaper::{new_shaper, FontShaper, GlyphInfo, PresentationWidth};
+use crate::LoadedFontId;
+use config::ConfigHandle;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use termwiz::cell::Presentation;
+use termwiz::cellcluster::CellCluster;
+use wezterm_bidi::Direction;
+
+/// The most workers that we'll spawn, regardless of the number of cpus
+const MAX_WORKERS: usize = 4;
+
+/// Each worker keeps at most this many shapers; they are cheap enough
+/// to rebuild that there's no need for anything smarter than discarding
+/// all of them when the limit is reached
+const MAX_SHAPERS_PER_WORKER: usize = 32;
+
+/// A request to shape the text of a cluster on the pool.
+/// Use `LoadedFont::shape_request` to create one.
+pub struct ShapeRequest {
+    pub(crate) font_id: LoadedFontId,
+    pub(crate) handles: Arc<Vec<ParsedFont>>,
+    pub(crate) config: ConfigHandle,
+    pub(crate) font_size: f64,
+    pub(crate) dpi: u32,
+    pub(crate) cluster: CellCluster,
+    pub(crate) filter_out_synthetic: fn(&mut Vec<char>),
+}
+
+impl ShapeRequest {
+    fn key(&self) -> ShapedRunKey {
+        ShapedRunKey {
+            font_id: self.font_id,
+            num_handles: self.handles.len(),
+            config_generation: self.config.generation(),
+            font_size: self.font_size.to_bits(),
+            dpi: self.dpi,
+            text: self.cluster.text.clone(),
+            presentation: self.cluster.presentation,
+            direction: self.cluster.direction,
+        }
+    }
+
+    /// Shape the text, returning None if any of the glyphs are missing
+    /// from the fonts; resolving fallback fonts for them requires the
+    /// LoadedFont, so that is left to the caller.
+    fn run(
+        &self,
+        shapers: &mut HashMap<ShaperKey, Box<dyn FontShaper>>,
+    ) -> Option<anyhow::Result<Vec<GlyphInfo>>> {
+        let key = ShaperKey {
+            font_id: self.font_id,
+            num_handles: self.handles.len(),
+            config_generation: self.config.generation(),
+        };
+        if !shapers.contains_key(&key) {
+            if shapers.len() >= MAX_SHAPERS_PER_WORKER {
+                shapers.clear();
+            }
+            match new_shaper(&self.config, &self.handles) {
+                Ok(shaper) => {
+                    shapers.insert(key.clone(), shaper);
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        let shaper = &shapers[&key];
+
+        let presentation_width = PresentationWidth::with_cluster(&self.cluster);
+        let mut no_glyphs = vec![];
+        let result = shaper.shape(
+            &self.cluster.text,
+            self.font_size,
+            self.dpi,
+            &mut no_glyphs,
+            Some(self.cluster.presentation),
+            self.cluster.direction,
+            None,
+            Some(&presentation_width),
+        );
+
+        no_glyphs.retain(|&c| c != '\u{FE0F}' && c != '\u{FE0E}');
+        (self.filter_out_synthetic)(&mut no_glyphs);
+        if !no_glyphs.is_empty() {
+            return None;
+        }
+        Some(result)
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct ShaperKey {
+    font_id: LoadedFontId,
+    /// Fallback fonts are only ever appended to the handles,
+    /// so this changes whenever the set of fonts changes
+    num_handles: usize,
+    config_generation: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ShapedRunKey {
+    font_id: LoadedFontId,
+    num_handles: usize,
+    config_generation: usize,
+    font_size: u64,
+    dpi: u32,
+    text: String,
+    presentation: Presentation,
+    direction: Direction,
+}
+
+impl Hash for ShapedRunKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.font_id.hash(state);
+        self.num_handles.hash(state);
+        self.config_generation.hash(state);
+        self.font_size.hash(state);
+        self.dpi.hash(state);
+        self.text.hash(state);
+        (self.presentation == Presentation::Emoji).hash(state);
+        (self.direction == Direction::RightToLeft).hash(state);
+    }
+}
+
+/// Retains shaped runs.  Entries are evicted a generation at a time:
+/// once `current` is full it becomes `previous`, dropping whatever was
+/// there before, and any entry that is used while in `previous` is
+/// promoted back to `current`.
+struct ShapedRunCache<K, V> {
+    current: HashMap<K, V>,
+    previous: HashMap<K, V>,
+}
+
+impl<K: Hash + Eq, V: Clone> ShapedRunCache<K, V> {
+    fn new() -> Self {
+        Self {
+            current: HashMap::new(),
+            previous: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: K, capacity: usize) -> Option<V> {
+        if let Some(value) = self.current.get(&key) {
+            return Some(value.clone());
+        }
+        let value = self.previous.remove(&key)?;
+        self.put(key, value.clone(), capacity);
+        Some(value)
+    }
+
+    fn put(&mut self, key: K, value: V, capacity: usize) {
+        if self.current.len() >= capacity.max(1) {
+            self.previous = std::mem::take(&mut self.current);
+        }
+        self.current.insert(key, value);
+    }
+}
+
+struct Job {
+    index: usize,
+    request: ShapeRequest,
+    reply: Sender<(usize, Option<anyhow::Result<Vec<GlyphInfo>>>)>,
+}
+
+/// A pool of threads that shape text in parallel
+pub struct ShapePool {
+    jobs: Mutex<Sender<Job>>,
+    num_workers: usize,
+    cache: Mutex<ShapedRunCache<ShapedRunKey, Arc<Vec<GlyphInfo>>>>,
+}
+
+lazy_static::lazy_static! {
+    static ref POOL: ShapePool = ShapePool::new();
+}
+
+impl ShapePool {
+    fn new() -> Self {
+        let num_workers = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(MAX_WORKERS);
+        let (tx, rx) = channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        for idx in 0..num_workers {
+            let rx = Arc::clone(&rx);
+            if let Err(err) = std::thread::Builder::new()
+                .name(format!("shaper-{idx}"))
+                .spawn(move || Self::worker(rx))
+            {
+                log::error!("Failed to spawn shaper thread: {err:#}");
+            }
+        }
+        Self {
+            jobs: Mutex::new(tx),
+            num_workers,
+            cache: Mutex::new(ShapedRunCache::new()),
+        }
+    }
+
+    /// Returns the process-wide shaping pool
+    pub fn get() -> &'static Self {
+        &POOL
+    }
+
+    pub fn num_workers(&self) -> usize {
+        self.num_workers
+    }
+
+    fn worker(jobs: Arc<Mutex<Receiver<Job>>>) {
+        let mut shapers = HashMap::new();
+        loop {
+            let job = match jobs.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            let result = job.request.run(&mut shapers);
+            job.reply.send((job.index, result)).ok();
+        }
+    }
+
+    /// Shape each of the requests, spreading them across the workers,
+    /// and wait for all of them to complete.
+    /// The results are in the same order as the requests.  The result
+    /// for a request is None if it has glyphs that are missing from the
+    /// font, in which case it must be shaped via `LoadedFont::shape`
+    /// so that fallback fonts can be resolved for them.
+    pub fn shape_all(
+        &self,
+        requests: Vec<ShapeRequest>,
+    ) -> Vec<Option<anyhow::Result<Arc<Vec<GlyphInfo>>>>> {
+        let capacity = requests
+            .first()
+            .map(|request| request.config.shape_cache_size)
+            .unwrap_or(0);
+        let mut results: Vec<Option<anyhow::Result<Arc<Vec<GlyphInfo>>>>> =
+            Vec::with_capacity(requests.len());
+        let mut keys = HashMap::new();
+        let (reply, replies) = channel();
+        let mut pending = 0;
+
+        {
+            let mut cache = self.cache.lock().unwrap();
+            let jobs = self.jobs.lock().unwrap();
+            for (index, request) in requests.into_iter().enumerate() {
+                let key = request.key();
+                if let Some(glyphs) = cache.get(key.clone(), capacity) {
+                    results.push(Some(Ok(glyphs)));
+                    continue;
+                }
+                results.push(None);
+                keys.insert(index, key);
+                let job = Job {
+                    index,
+                    request,
+                    reply: reply.clone(),
+                };
+                if let Err(err) = jobs.send(job) {
+                    // There are no workers; shape it right here instead
+                    let job = err.0;
+                    let result = job.request.run(&mut HashMap::new());
+                    job.reply.send((job.index, result)).ok();
+                }
+                pending += 1;
+            }
+        }
+        drop(reply);
+
+        for (index, result) in replies.iter().take(pending) {
+            results[index] = result.map(|result| -> anyhow::Result<Arc<Vec<GlyphInfo>>> {
+                let glyphs = Arc::new(result?);
+                if let Some(key) = keys.remove(&index) {
+                    self.cache
+                        .lock()
+                        .unwrap()
+                        .put(key, Arc::clone(&glyphs), capacity);
+                }
+                Ok(glyphs)
+            });
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shaped_run_cache_promotes_from_previous_generation() {
+        let mut cache = ShapedRunCache::new();
+        cache.put(1, "one", 2);
+        cache.put(2, "two", 2);
+        // Starts a new generation; 1 and 2 are now in the previous one
+        cache.put(3, "three", 2);
+        assert_eq!(cache.get(1, 2), Some("one"));
+        // Starts another generation, dropping 2, which wasn't used
+        cache.put(4, "four", 2);
+        assert_eq!(cache.get(2, 2), None);
+        assert_eq!(cache.get(1, 2), Some("one"));
+        assert_eq!(cache.get(3, 2), Some("three"));
+        assert_eq!(cache.get(4, 2), Some("four"));
+    }
+}
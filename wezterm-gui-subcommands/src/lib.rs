@@ -95,7 +95,8 @@ pub struct StartCommand {
     pub domain: Option<String>,
 
     /// When used with --domain, if the domain already has running panes,
-    /// wezterm will simply attach and will NOT spawn the specified PROG.
+    /// wezterm will simply attach, and will only spawn PROG if it, or
+    /// --cwd, was explicitly specified.
     /// If you omit --attach when using --domain, wezterm will attach
     /// AND then spawn PROG.
     #[arg(long, requires = "domain")]
@@ -207,11 +208,17 @@ pub struct ConnectCommand {
     /// to which you'd like to connect
     pub domain_name: String,
 
-    /// When spawning into an existing GUI instance, spawn a new
-    /// tab into the active window rather than spawn a new window.
+    /// When the domain already has running panes, PROG is spawned
+    /// into a new window. This option spawns it into a new tab in
+    /// the window that shows those panes instead.
     #[arg(long)]
     pub new_tab: bool,
 
+    /// Specify the current working directory for PROG.
+    /// The path is interpreted on the host of the domain.
+    #[arg(long = "cwd", value_parser, value_hint=ValueHint::DirPath)]
+    pub cwd: Option<PathBuf>,
+
     /// Override the default windowing system class.
     /// The default is "org.wezfurlong.wezterm".
     /// Under X11 and Windows this changes the window class.
@@ -237,8 +244,10 @@ pub struct ConnectCommand {
     pub position: Option<GuiPosition>,
 
     /// Instead of executing your shell, run PROG.
-    /// For example: `wezterm start -- bash -l` will spawn bash
+    /// For example: `wezterm connect unix -- bash -l` will spawn bash
     /// as if it were a login shell.
+    /// If the domain already has running panes, PROG is spawned
+    /// alongside them.
     #[arg(value_parser, value_hint=ValueHint::CommandWithArguments, num_args=1..)]
     pub prog: Vec<OsString>,
 }
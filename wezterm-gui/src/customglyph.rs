@@ -192,6 +192,25 @@ pub enum BlockKey {
     CellDiagonals(CellDiagonal),
     /// A braille dot pattern
     Braille(u8),
+    /// An octant pattern; bit N-1 is set when octant N is filled
+    // ╭───┬───╮
+    // │ 1 │ 2 │
+    // ├───┼───┤
+    // │ 3 │ 4 │
+    // ├───┼───┤
+    // │ 5 │ 6 │
+    // ├───┼───┤
+    // │ 7 │ 8 │
+    // ╰───┴───╯
+    Octants(u8),
+    /// A separated mosaic with 2 columns and the specified number
+    /// of rows, where each filled piece is surrounded by a gap.
+    /// Bit N-1 of the pattern is set when piece N is filled,
+    /// with the pieces numbered left to right, top to bottom.
+    Separated {
+        rows: u8,
+        pattern: u8,
+    },
 
     Poly(&'static [Poly]),
 
@@ -306,6 +325,36 @@ impl PolyStyle {
     }
 }
 
+/// The octants that are already encoded by other characters, such as
+/// the quadrants and the half and quarter blocks, and which are
+/// therefore skipped by the octants in
+/// <https://www.unicode.org/charts/PDF/U1CC00.pdf>
+const PRE_ENCODED_OCTANTS: [u8; 26] = [
+    0x00, 0x01, 0x02, 0x03, 0x05, 0x0a, 0x0f, 0x14, 0x28, 0x3f, 0x40, 0x50, 0x55, 0x5a, 0x5f, 0x80,
+    0xa0, 0xa5, 0xaa, 0xaf, 0xc0, 0xf0, 0xf5, 0xfa, 0xfc, 0xff,
+];
+
+/// Maps U+1CD00..=U+1CDE5 to their octant patterns.  The characters
+/// are assigned to the remaining patterns in ascending order.
+const OCTANT_PATTERNS: [u8; 230] = {
+    let mut patterns = [0u8; 230];
+    let mut idx = 0;
+    let mut pattern = 0usize;
+    let mut pre_encoded = 0;
+    while pattern < 256 {
+        if pre_encoded < PRE_ENCODED_OCTANTS.len()
+            && PRE_ENCODED_OCTANTS[pre_encoded] as usize == pattern
+        {
+            pre_encoded += 1;
+        } else {
+            patterns[idx] = pattern as u8;
+            idx += 1;
+        }
+        pattern += 1;
+    }
+    patterns
+};
+
 impl BlockKey {
     pub fn filter_out_synthetic(glyphs: &mut Vec<char>) {
         let config = config::configuration();
@@ -4445,6 +4494,72 @@ impl BlockKey {
             // ⣠ ⣡ ⣢ ⣣ ⣤ ⣥ ⣦ ⣧ ⣨ ⣩ ⣪ ⣫ ⣬ ⣭ ⣮ ⣯
             // ⣰ ⣱ ⣲ ⣳ ⣴ ⣵ ⣶ ⣷ ⣸ ⣹ ⣺ ⣻ ⣼ ⣽ ⣾ ⣿
             n @ 0x2800..=0x28ff => Self::Braille((n & 0xff) as u8),
+            // [𜰡] SEPARATED BLOCK QUADRANT-1 .. [𜰯] SEPARATED BLOCK QUADRANT-1234
+            n @ 0x1cc21..=0x1cc2f => Self::Separated {
+                rows: 2,
+                pattern: (n - 0x1cc20) as u8,
+            },
+            // [𜴀] BLOCK OCTANT-3 .. [𜷥] BLOCK OCTANT-2345678
+            n @ 0x1cd00..=0x1cde5 => Self::Octants(OCTANT_PATTERNS[(n - 0x1cd00) as usize]),
+            // [𜹑] SEPARATED BLOCK SEXTANT-1 .. [𜺏] SEPARATED BLOCK SEXTANT-123456
+            n @ 0x1ce51..=0x1ce8f => Self::Separated {
+                rows: 3,
+                pattern: (n - 0x1ce50) as u8,
+            },
+            // [𜺐] UPPER LEFT ONE SIXTEENTH BLOCK .. [𜺟] LOWER RIGHT ONE SIXTEENTH BLOCK
+            n @ 0x1ce90..=0x1ce9f => {
+                static SIXTEENTHS: [[Block; 1]; 16] = {
+                    let mut blocks = [[Block::Custom(0, 0, 0, 0, BlockAlpha::Full)]; 16];
+                    let mut idx = 0;
+                    while idx < 16 {
+                        let (x, y) = ((idx % 4) as u8 * 2, (idx / 4) as u8 * 2);
+                        blocks[idx] = [Block::Custom(x, x + 2, y, y + 2, BlockAlpha::Full)];
+                        idx += 1;
+                    }
+                    blocks
+                };
+                Self::Blocks(&SIXTEENTHS[(n - 0x1ce90) as usize])
+            }
+            // [𜺠] RIGHT HALF LOWER ONE QUARTER BLOCK
+            0x1cea0 => Self::Blocks(&[Block::Custom(4, 8, 6, 8, BlockAlpha::Full)]),
+            // [𜺡] RIGHT THREE QUARTERS LOWER ONE QUARTER BLOCK
+            0x1cea1 => Self::Blocks(&[Block::Custom(2, 8, 6, 8, BlockAlpha::Full)]),
+            // [𜺢] LEFT THREE QUARTERS LOWER ONE QUARTER BLOCK
+            0x1cea2 => Self::Blocks(&[Block::Custom(0, 6, 6, 8, BlockAlpha::Full)]),
+            // [𜺣] LEFT HALF LOWER ONE QUARTER BLOCK
+            0x1cea3 => Self::Blocks(&[Block::Custom(0, 4, 6, 8, BlockAlpha::Full)]),
+            // [𜺤] LOWER HALF LEFT ONE QUARTER BLOCK
+            0x1cea4 => Self::Blocks(&[Block::Custom(0, 2, 4, 8, BlockAlpha::Full)]),
+            // [𜺥] LOWER THREE QUARTERS LEFT ONE QUARTER BLOCK
+            0x1cea5 => Self::Blocks(&[Block::Custom(0, 2, 2, 8, BlockAlpha::Full)]),
+            // [𜺦] UPPER THREE QUARTERS LEFT ONE QUARTER BLOCK
+            0x1cea6 => Self::Blocks(&[Block::Custom(0, 2, 0, 6, BlockAlpha::Full)]),
+            // [𜺧] UPPER HALF LEFT ONE QUARTER BLOCK
+            0x1cea7 => Self::Blocks(&[Block::Custom(0, 2, 0, 4, BlockAlpha::Full)]),
+            // [𜺨] LEFT HALF UPPER ONE QUARTER BLOCK
+            0x1cea8 => Self::Blocks(&[Block::Custom(0, 4, 0, 2, BlockAlpha::Full)]),
+            // [𜺩] LEFT THREE QUARTERS UPPER ONE QUARTER BLOCK
+            0x1cea9 => Self::Blocks(&[Block::Custom(0, 6, 0, 2, BlockAlpha::Full)]),
+            // [𜺪] RIGHT THREE QUARTERS UPPER ONE QUARTER BLOCK
+            0x1ceaa => Self::Blocks(&[Block::Custom(2, 8, 0, 2, BlockAlpha::Full)]),
+            // [𜺫] RIGHT HALF UPPER ONE QUARTER BLOCK
+            0x1ceab => Self::Blocks(&[Block::Custom(4, 8, 0, 2, BlockAlpha::Full)]),
+            // [𜺬] UPPER HALF RIGHT ONE QUARTER BLOCK
+            0x1ceac => Self::Blocks(&[Block::Custom(6, 8, 0, 4, BlockAlpha::Full)]),
+            // [𜺭] UPPER THREE QUARTERS RIGHT ONE QUARTER BLOCK
+            0x1cead => Self::Blocks(&[Block::Custom(6, 8, 0, 6, BlockAlpha::Full)]),
+            // [𜺮] LOWER THREE QUARTERS RIGHT ONE QUARTER BLOCK
+            0x1ceae => Self::Blocks(&[Block::Custom(6, 8, 2, 8, BlockAlpha::Full)]),
+            // [𜺯] LOWER HALF RIGHT ONE QUARTER BLOCK
+            0x1ceaf => Self::Blocks(&[Block::Custom(6, 8, 4, 8, BlockAlpha::Full)]),
+            // [🯤] UPPER CENTRE ONE QUARTER BLOCK
+            0x1fbe4 => Self::Blocks(&[Block::Custom(2, 6, 0, 4, BlockAlpha::Full)]),
+            // [🯥] LOWER CENTRE ONE QUARTER BLOCK
+            0x1fbe5 => Self::Blocks(&[Block::Custom(2, 6, 4, 8, BlockAlpha::Full)]),
+            // [🯦] MIDDLE LEFT ONE QUARTER BLOCK
+            0x1fbe6 => Self::Blocks(&[Block::Custom(0, 4, 2, 6, BlockAlpha::Full)]),
+            // [🯧] MIDDLE RIGHT ONE QUARTER BLOCK
+            0x1fbe7 => Self::Blocks(&[Block::Custom(4, 8, 2, 6, BlockAlpha::Full)]),
             // [] Powerline filled right arrow
             0xe0b0 => Self::Poly(&[Poly {
                 path: &[
@@ -4670,6 +4785,7 @@ impl GlyphCache {
         width: u8,
     ) -> anyhow::Result<Sprite> {
         if let Some(sprite) = self.cursor_glyphs.get(&(shape, width)) {
+            self.atlas.touch(sprite);
             return Ok(sprite.clone());
         }
 
@@ -4766,6 +4882,8 @@ impl GlyphCache {
                 descender_plus_two: 0,
                 underline_height: *underline_height,
                 strike_row: 0,
+                overline_row: 0,
+                curly_amplitude: 1,
                 cell_size: cell_size.clone(),
             },
             _ => render_metrics.clone(),
@@ -5062,6 +5180,47 @@ impl GlyphCache {
                     pixmap.fill_path(&path, &paint, FillRule::Winding, identity, None);
                 }
             }
+            BlockKey::Octants(pattern) => {
+                let x_half = metrics.cell_size.width as f32 / 2.;
+                let y_quarter = metrics.cell_size.height as f32 / 4.;
+                for octant in 0..8 {
+                    if pattern & (1 << octant) == 0 {
+                        continue;
+                    }
+                    let left = (octant % 2) as f32 * x_half;
+                    let top = (octant / 2) as f32 * y_quarter;
+                    fill_rect(
+                        &mut buffer,
+                        left..left + x_half,
+                        top..top + y_quarter,
+                        BlockAlpha::Full,
+                    );
+                }
+            }
+            BlockKey::Separated { rows, pattern } => {
+                // Each piece is separated from its neighbors, including
+                // those in adjacent cells, by leaving a gap along its
+                // left and bottom edges
+                let piece_width = metrics.cell_size.width as f32 / 2.;
+                let piece_height = metrics.cell_size.height as f32 / rows as f32;
+                let gap = (metrics.underline_height as f32)
+                    .max(1.)
+                    .min(piece_width / 2.)
+                    .min(piece_height / 2.);
+                for piece in 0..rows * 2 {
+                    if pattern & (1 << piece) == 0 {
+                        continue;
+                    }
+                    let left = (piece % 2) as f32 * piece_width;
+                    let top = (piece / 2) as f32 * piece_height;
+                    fill_rect(
+                        &mut buffer,
+                        left + gap..left + piece_width,
+                        top..top + piece_height - gap,
+                        BlockAlpha::Full,
+                    );
+                }
+            }
             BlockKey::Poly(polys) | BlockKey::PolyWithCustomMetrics { polys, .. } => {
                 self.draw_polys(
                     &metrics,
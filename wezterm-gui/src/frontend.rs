@@ -12,6 +12,7 @@ use mux::{Mux, MuxNotification};
 use promise::{Future, Promise};
 use std::cell::RefCell;
 use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
 use std::rc::Rc;
 use std::sync::Arc;
 use wezterm_term::{Alert, ClipboardSelection};
@@ -88,12 +89,18 @@ impl GuiFrontEnd {
                 }
                 MuxNotification::TabTitleChanged { .. } => {}
                 MuxNotification::WindowTitleChanged { .. } => {}
+                MuxNotification::PaneTitleChanged { .. } => {}
                 MuxNotification::TabResized(_) => {}
                 MuxNotification::TabAddedToWindow { .. } => {}
                 MuxNotification::PaneRemoved(_) => {}
                 MuxNotification::WindowInvalidated(_) => {}
                 MuxNotification::PaneOutput(_) => {}
                 MuxNotification::PaneAdded(_) => {}
+                MuxNotification::PaneExited { .. } => {}
+                MuxNotification::PasteProgress(_) => {}
+                MuxNotification::PanePrompt(_) => {
+                    // Handled via TermWindowNotif
+                }
                 MuxNotification::Alert {
                     pane_id,
                     alert:
@@ -200,6 +207,46 @@ impl GuiFrontEnd {
                     })
                     .detach();
                 }
+                MuxNotification::QueryClipboard { pane_id, query } => {
+                    promise::spawn::spawn_into_main_thread(async move {
+                        let fe = crate::frontend::front_end();
+                        let window = fe.known_windows.borrow().keys().next().cloned();
+                        let window = match window {
+                            Some(window) => window,
+                            None => {
+                                log::error!("Cannot read clipboard as there are no windows");
+                                return;
+                            }
+                        };
+                        for target in query.targets() {
+                            let text = match window
+                                .get_clipboard(match target {
+                                    ClipboardSelection::Clipboard => Clipboard::Clipboard,
+                                    ClipboardSelection::PrimarySelection => {
+                                        Clipboard::PrimarySelection
+                                    }
+                                })
+                                .await
+                            {
+                                Ok(text) => text,
+                                Err(err) => {
+                                    log::error!("failed to read clipboard for OSC 52: {:#}", err);
+                                    return;
+                                }
+                            };
+                            if text.is_empty() {
+                                continue;
+                            }
+                            if let Some(pane) = Mux::get().get_pane(pane_id) {
+                                let mut writer = pane.writer();
+                                writer.write_all(query.reply(text).as_bytes()).ok();
+                                writer.flush().ok();
+                            }
+                            return;
+                        }
+                    })
+                    .detach();
+                }
             }
             true
         });
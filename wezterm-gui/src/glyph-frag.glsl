@@ -20,6 +20,7 @@ uniform vec3 foreground_text_hsb;
 uniform sampler2D atlas_nearest_sampler;
 uniform sampler2D atlas_linear_sampler;
 uniform bool subpixel_aa;
+uniform float subpixel_gamma;
 uniform uint milliseconds;
 
 struct ColorEase {
@@ -110,6 +111,22 @@ vec4 to_srgb(vec4 linearRGB)
   return vec4(mix(higher, lower, cutoff), linearRGB.a);
 }
 
+// Subpixel antialiased text is blended with the sRGB encoded values in the
+// framebuffer rather than in linear space, which makes light text on a dark
+// background look too thin and dark text on a light background too heavy.
+// We don't know the background color here, so assume that light text is
+// drawn on a dark background and vice versa, and adjust the coverage of
+// each subpixel to produce the result of blending in linear space.
+vec3 gamma_adjust_coverage(vec3 coverage, vec3 fg) {
+  if (subpixel_gamma == 1.0) {
+    return coverage;
+  }
+  float luminance = dot(fg, vec3(0.2126, 0.7152, 0.0722));
+  vec3 on_dark = pow(coverage, vec3(1.0 / subpixel_gamma));
+  vec3 on_light = vec3(1.0) - pow(vec3(1.0) - coverage, vec3(1.0 / subpixel_gamma));
+  return mix(on_light, on_dark, luminance);
+}
+
 void main() {
   vec4 fg_color = mix(o_fg_color, o_fg_color_alt, o_fg_color_mix);
   if (o_has_color == 3.0) {
@@ -151,6 +168,9 @@ void main() {
       color.a = colorMask.a;
     }
     color = apply_hsv(color, foreground_text_hsb);
+    if (subpixel_aa) {
+      colorMask.rgb = gamma_adjust_coverage(colorMask.rgb, color.rgb);
+    }
   }
 
   color = apply_hsv(color, o_hsv);
@@ -635,6 +635,9 @@ impl GlyphCache {
 
         if let Some(entry) = self.glyph_cache.get(&key as &dyn GlyphKeyTrait) {
             metrics::histogram!("glyph_cache.glyph_cache.hit.rate").record(1.);
+            if let Some(texture) = &entry.texture {
+                self.atlas.touch(texture);
+            }
             return Ok(Rc::clone(entry));
         }
         metrics::histogram!("glyph_cache.glyph_cache.miss.rate").record(1.);
@@ -678,6 +681,29 @@ impl GlyphCache {
         Ok(glyph)
     }
 
+    /// Evict the least recently used pages of the atlas, and forget
+    /// the sprites that were allocated from them.
+    /// Returns false if there was nothing that could be evicted.
+    pub fn evict_least_recently_used(&mut self) -> bool {
+        if self.atlas.evict_least_recently_used() == 0 {
+            return false;
+        }
+        let atlas = &self.atlas;
+        self.glyph_cache.retain(|_, glyph| {
+            glyph
+                .texture
+                .as_ref()
+                .map_or(true, |sprite| atlas.contains(sprite))
+        });
+        self.frame_cache.retain(|_, sprite| atlas.contains(sprite));
+        self.line_glyphs.retain(|_, sprite| atlas.contains(sprite));
+        self.block_glyphs.retain(|_, sprite| atlas.contains(sprite));
+        self.cursor_glyphs
+            .retain(|_, sprite| atlas.contains(sprite));
+        self.color.retain(|_, sprite| atlas.contains(sprite));
+        true
+    }
+
     pub fn config_changed(&mut self) {
         let config = self.fonts.config();
         self.image_cache.update_config(&config);
@@ -904,6 +930,7 @@ impl GlyphCache {
         match &*handle.h {
             ImageDataType::Rgba8 { hash, .. } => {
                 if let Some(sprite) = frame_cache.get(hash) {
+                    atlas.touch(sprite);
                     return Ok((sprite.clone(), None, LoadState::Loaded));
                 }
                 let sprite = atlas
@@ -959,6 +986,7 @@ impl GlyphCache {
                 let hash = hashes[*decoded_current_frame];
 
                 if let Some(sprite) = frame_cache.get(&hash) {
+                    atlas.touch(sprite);
                     return Ok((sprite.clone(), next, LoadState::Loaded));
                 }
 
@@ -1022,6 +1050,7 @@ impl GlyphCache {
                 let hash = frames.frame_hash();
 
                 if let Some(sprite) = frame_cache.get(&hash) {
+                    atlas.touch(sprite);
                     return Ok((sprite.clone(), next, frames.load_state));
                 }
 
@@ -1083,6 +1112,7 @@ impl GlyphCache {
         let key = (color, NotNan::new(alpha).unwrap());
 
         if let Some(s) = self.color.get(&key) {
+            self.atlas.touch(s);
             return Ok(s.clone());
         }
 
@@ -1110,6 +1140,7 @@ impl GlyphCache {
             size: metrics.into(),
         };
         if let Some(s) = self.block_glyphs.get(&key) {
+            self.atlas.touch(s);
             return Ok(s.clone());
         }
         self.block_sprite(metrics, key)
@@ -1190,11 +1221,7 @@ impl GlyphCache {
             let max_y = metrics.cell_size.height as usize - 1;
             let x_factor = (2. * std::f32::consts::PI) / metrics.cell_size.width as f32;
 
-            // Have the wave go from the descender to the bottom of the cell
-            let wave_height =
-                metrics.cell_size.height - (cell_rect.origin.y + metrics.descender_row);
-
-            let half_height = (wave_height as f32 / 4.).max(1.);
+            let half_height = (metrics.curly_amplitude as f32).max(1.);
             let y = ((cell_rect.origin.y + metrics.descender_row) as usize)
                 .saturating_sub(half_height as usize);
 
@@ -1278,10 +1305,13 @@ impl GlyphCache {
         let draw_overline = |buffer: &mut Image| {
             for row in 0..metrics.underline_height {
                 buffer.draw_line(
-                    Point::new(cell_rect.origin.x, cell_rect.origin.y + row),
+                    Point::new(
+                        cell_rect.origin.x,
+                        cell_rect.origin.y + metrics.overline_row + row,
+                    ),
                     Point::new(
                         cell_rect.origin.x + metrics.cell_size.width,
-                        cell_rect.origin.y + row,
+                        cell_rect.origin.y + metrics.overline_row + row,
                     ),
                     white,
                 );
@@ -1334,6 +1364,7 @@ impl GlyphCache {
         };
 
         if let Some(s) = self.line_glyphs.get(&key) {
+            self.atlas.touch(s);
             return Ok(s.clone());
         }
 
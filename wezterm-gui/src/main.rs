@@ -12,6 +12,7 @@ use config::keyassignment::{SpawnCommand, SpawnTabDomain};
 use config::{ConfigHandle, SerialDomain, SshDomain, SshMultiplexing};
 use mux::activity::Activity;
 use mux::domain::{Domain, LocalDomain};
+use mux::window::WindowId;
 use mux::Mux;
 use mux_lua::MuxDomain;
 use portable_pty::cmdbuilder::CommandBuilder;
@@ -50,7 +51,6 @@ mod scrollbar;
 mod selection;
 mod shapecache;
 mod spawn;
-mod stats;
 mod tabbar;
 mod termwindow;
 mod unicode_names;
@@ -284,6 +284,7 @@ fn have_panes_in_domain_and_ws(domain: &Arc<dyn Domain>, workspace: &Option<Stri
 async fn spawn_tab_in_domain_if_mux_is_empty(
     cmd: Option<CommandBuilder>,
     is_connecting: bool,
+    new_tab: bool,
     domain: Option<Arc<dyn Domain>>,
     workspace: Option<String>,
 ) -> anyhow::Result<()> {
@@ -316,6 +317,11 @@ async fn spawn_tab_in_domain_if_mux_is_empty(
     domain.attach(Some(window_id)).await?;
 
     if have_panes_in_domain_and_ws(&domain, &workspace) {
+        if let Some(cmd) = cmd {
+            // A specific program was requested, so run it alongside
+            // the panes that are already present in the domain
+            spawn_into_attached_domain(&domain, cmd, new_tab, window_id, workspace).await?;
+        }
         trigger_and_log_gui_attached(MuxDomain(domain.domain_id())).await;
         return Ok(());
     }
@@ -490,7 +496,46 @@ async fn async_run_terminal_gui(
             trigger_and_log_gui_attached(MuxDomain(domain.domain_id())).await;
         }
     }
-    spawn_tab_in_domain_if_mux_is_empty(cmd, is_connecting, domain, opts.workspace).await
+    spawn_tab_in_domain_if_mux_is_empty(cmd, is_connecting, opts.new_tab, domain, opts.workspace)
+        .await
+}
+
+/// Spawn `cmd` into a domain that has just been attached, either as
+/// a new tab in `window_id`, the window that was populated by the attach,
+/// or in a new window in `workspace`
+async fn spawn_into_attached_domain(
+    domain: &Arc<dyn Domain>,
+    cmd: CommandBuilder,
+    new_tab: bool,
+    window_id: WindowId,
+    workspace: Option<String>,
+) -> anyhow::Result<()> {
+    let mux = Mux::get();
+    let window_id = if new_tab && mux.get_window(window_id).is_some() {
+        window_id
+    } else {
+        let position = None;
+        let builder = mux.new_empty_window(workspace, position);
+        *builder
+    };
+
+    let config = config::configuration();
+    let dpi = config.dpi.unwrap_or_else(|| ::window::default_dpi());
+    let tab = domain
+        .spawn(
+            config.initial_size(dpi as u32, Some(cell_pixel_dims(&config, dpi)?)),
+            Some(cmd),
+            None,
+            window_id,
+        )
+        .await?;
+    let mut window = mux
+        .get_window_mut(window_id)
+        .ok_or_else(|| anyhow!("failed to get mux window id {window_id}"))?;
+    if let Some(tab_idx) = window.idx_by_id(tab.tab_id()) {
+        window.set_active_without_saving(tab_idx);
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -731,13 +776,22 @@ fn run_terminal_gui(opts: StartCommand, default_domain_name: Option<String>) ->
 
     let cmd = if need_builder {
         let prog = opts.prog.iter().map(|s| s.as_os_str()).collect::<Vec<_>>();
-        let mut builder = config.build_prog(
-            if prog.is_empty() { None } else { Some(prog) },
-            config.default_prog.as_ref(),
-            config.default_cwd.as_ref(),
-        )?;
+        let prog = if prog.is_empty() { None } else { Some(prog) };
+        let mut builder = if opts.attach {
+            // The program runs on the host of the domain, where our
+            // default_prog and default_cwd may not make sense
+            config.build_prog(prog, None, None)?
+        } else {
+            config.build_prog(
+                prog,
+                config.default_prog.as_ref(),
+                config.default_cwd.as_ref(),
+            )?
+        };
         if let Some(cwd) = &opts.cwd {
-            builder.cwd(if cwd.is_relative() {
+            // When attaching, the path is interpreted by the host of
+            // the domain, so it isn't relative to our own directory
+            builder.cwd(if cwd.is_relative() && !opts.attach {
                 current_dir()?.join(cwd).into_os_string().into()
             } else {
                 Cow::Borrowed(cwd.as_ref())
@@ -877,6 +931,7 @@ pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyho
     )?);
 
     let render_metrics = crate::utilsprites::RenderMetrics::new(&font_config)?;
+    let fallback_rules = config.effective_font_fallback_rules(&config.font);
 
     let bidi_hint = if config.bidi_enabled {
         Some(config.bidi_direction)
@@ -1025,6 +1080,11 @@ pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyho
                         "",
                         parsed.handle.diagnostic_string()
                     );
+                    if let Some(rule_idx) = parsed.fallback_rule {
+                        if let Some((origin, rule)) = fallback_rules.get(rule_idx) {
+                            println!("{:38}matched {}: {}", "", origin, rule.describe());
+                        }
+                    }
                 }
 
                 if cmd.rasterize_ascii {
@@ -1075,12 +1135,26 @@ pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyho
 
     println!("Primary font:");
     let default_font = font_config.default_font()?;
-    println!(
-        "{}",
-        ParsedFont::lua_fallback(&default_font.clone_handles())
-    );
+    let default_handles = default_font.clone_handles();
+    println!("{}", ParsedFont::lua_fallback(&default_handles));
     println!();
 
+    for (rule_idx, (origin, rule)) in fallback_rules.iter().enumerate() {
+        println!("{}, for {}:", origin, rule.describe());
+        let mut found = false;
+        for p in default_handles
+            .iter()
+            .filter(|p| p.fallback_rule == Some(rule_idx))
+        {
+            println!("  {} -- {}", p.lua_name(), p.handle.diagnostic_string());
+            found = true;
+        }
+        if !found {
+            println!("  No matching font was found; the regular fallback fonts are used instead");
+        }
+        println!();
+    }
+
     for rule in &config.font_rules {
         println!();
 
@@ -1208,9 +1282,9 @@ fn run() -> anyhow::Result<()> {
     // headless mux server.
     config::lua::add_context_setup_func(window_funcs::register);
     config::lua::add_context_setup_func(crate::scripting::register);
-    config::lua::add_context_setup_func(crate::stats::register);
+    config::lua::add_context_setup_func(mux::stats::register);
 
-    stats::Stats::init()?;
+    mux::stats::Stats::init()?;
     let _saver = umask::UmaskSaver::new();
 
     config::common_init(
@@ -1272,7 +1346,7 @@ fn run() -> anyhow::Result<()> {
                 attach: true,
                 _cmd: false,
                 no_auto_connect: false,
-                cwd: None,
+                cwd: connect.cwd,
             },
             Some(connect.domain_name),
         ),
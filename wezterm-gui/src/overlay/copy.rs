@@ -10,6 +10,7 @@ use mux::pane::{
     CachePolicy, ForEachPaneLogicalLine, LogicalLine, Pane, PaneId, Pattern, PatternType,
     PerformAssignmentResult, SearchResult, WithPaneLines,
 };
+use mux::paste::PasteProgress;
 use mux::renderable::*;
 use mux::tab::TabId;
 use ordered_float::NotNan;
@@ -1118,6 +1119,14 @@ impl Pane for CopyOverlay {
         Ok(())
     }
 
+    fn paste_progress(&self) -> Option<PasteProgress> {
+        self.delegate.paste_progress()
+    }
+
+    fn cancel_paste(&self) -> bool {
+        self.delegate.cancel_paste()
+    }
+
     fn reader(&self) -> anyhow::Result<Option<Box<dyn std::io::Read + Send>>> {
         Ok(None)
     }
@@ -1305,6 +1314,10 @@ impl Pane for CopyOverlay {
         self.delegate.perform_actions(actions)
     }
 
+    fn perform_action_batch(&self, batch: &mut termwiz::escape::ActionBatch) {
+        self.delegate.perform_action_batch(batch)
+    }
+
     fn is_dead(&self) -> bool {
         self.delegate.is_dead()
     }
@@ -19,6 +19,10 @@ lazy_static::lazy_static! {
     static ref LATEST_LOG_ENTRY: Mutex<Option<DateTime<Local>>> = Mutex::new(None);
 }
 
+/// Entered in place of a lua expression to show the current
+/// values of the metrics that have been recorded
+const STATS_COMMAND: &str = ":stats";
+
 struct LuaReplHost {
     history: BasicHistory,
     lua: mlua::Lua,
@@ -120,6 +124,10 @@ impl LineEditorHost for LuaReplHost {
     fn render_preview(&self, line: &str) -> Vec<OutputElement> {
         let mut preview = vec![];
 
+        if line == STATS_COMMAND {
+            return preview;
+        }
+
         if let Err(err) = fragment_to_expr_or_statement(&self.lua, line) {
             preview.push(OutputElement::Text(err))
         }
@@ -133,6 +141,7 @@ pub fn show_debug_overlay(
     gui_win: GuiWin,
     opengl_info: String,
     connection_info: String,
+    atlas_info: String,
 ) -> anyhow::Result<()> {
     term.no_grab_mouse_in_raw_mode();
 
@@ -202,7 +211,9 @@ pub fn show_debug_overlay(
          Window Environment: {connection_info}\r\n\
          Lua Version: {lua_version}\r\n\
          {opengl_info}\r\n\
+         Glyph atlas: {atlas_info}\r\n\
          Enter lua statements or expressions and hit Enter.\r\n\
+         Enter {STATS_COMMAND} to show performance statistics.\r\n\
          Press ESC or CTRL-D to exit\r\n",
     ))])?;
 
@@ -216,6 +227,14 @@ pub fn show_debug_overlay(
             }
             host.as_mut().unwrap().add_history(&line);
 
+            if line == STATS_COMMAND {
+                let mut stats = vec![];
+                mux::stats::snapshot().render(&mut stats)?;
+                let stats = String::from_utf8_lossy(&stats).replace("\n", "\r\n");
+                term.render(&[Change::Text(stats)])?;
+                continue;
+            }
+
             let passed_host = host.take().unwrap();
 
             let (host_res, text) =
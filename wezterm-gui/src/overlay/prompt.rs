@@ -1,8 +1,11 @@
 use crate::scripting::guiwin::GuiWin;
 use config::keyassignment::{KeyAssignment, PromptInputLine};
+use mux::connui::PanePrompt;
 use mux::termwiztermtab::TermWizTerminal;
 use mux_lua::MuxPane;
 use std::rc::Rc;
+use std::sync::Arc;
+use termwiz::cell::{unicode_column_width, AttributeChange, Intensity};
 use termwiz::input::{InputEvent, KeyCode, KeyEvent};
 use termwiz::lineedit::*;
 use termwiz::surface::Change;
@@ -10,12 +13,15 @@ use termwiz::terminal::Terminal;
 
 struct PromptHost {
     history: BasicHistory,
+    /// Obscure the input, for passwords and the like
+    mask: bool,
 }
 
 impl PromptHost {
     fn new() -> Self {
         Self {
             history: BasicHistory::default(),
+            mask: false,
         }
     }
 }
@@ -25,6 +31,16 @@ impl LineEditorHost for PromptHost {
         &mut self.history
     }
 
+    fn highlight_line(&self, line: &str, cursor_position: usize) -> (Vec<OutputElement>, usize) {
+        let cursor_x_pos = unicode_column_width(&line[0..cursor_position], None);
+        let text = if self.mask {
+            "*".repeat(unicode_column_width(line, None))
+        } else {
+            line.to_string()
+        };
+        (vec![OutputElement::Text(text)], cursor_x_pos)
+    }
+
     fn resolve_action(
         &mut self,
         event: &InputEvent,
@@ -79,6 +95,51 @@ pub fn show_line_prompt_overlay(
     Ok(())
 }
 
+/// Collect the input requested by a `PanePrompt`, such as the
+/// responses to ssh authentication challenges
+pub fn show_pane_prompt_overlay(
+    mut term: TermWizTerminal,
+    prompt: Arc<PanePrompt>,
+) -> anyhow::Result<()> {
+    term.no_grab_mouse_in_raw_mode();
+    let mut message = prompt.message.replace("\r\n", "\n").replace("\n", "\r\n");
+    message.push_str("\r\n\r\n");
+    term.render(&[
+        AttributeChange::Intensity(Intensity::Bold).into(),
+        Change::Text(format!("{}\r\n\r\n", prompt.title)),
+        AttributeChange::Intensity(Intensity::Normal).into(),
+        Change::Text(message),
+    ])?;
+
+    if prompt.fields.is_empty() {
+        let mut host = PromptHost::new();
+        let mut editor = LineEditor::new(&mut term);
+        editor.set_prompt("Enter [y/n]> ");
+        match editor.read_line(&mut host)?.as_deref() {
+            Some("y" | "Y" | "yes" | "YES") => prompt.respond(vec![]),
+            _ => prompt.cancel(),
+        }
+        return Ok(());
+    }
+
+    let mut answers = vec![];
+    for field in &prompt.fields {
+        let mut host = PromptHost::new();
+        host.mask = !field.echo;
+        let mut editor = LineEditor::new(&mut term);
+        editor.set_prompt(&field.label);
+        match editor.read_line(&mut host)? {
+            Some(line) => answers.push(line),
+            None => {
+                prompt.cancel();
+                return Ok(());
+            }
+        }
+    }
+    prompt.respond(answers);
+    Ok(())
+}
+
 fn trampoline(name: String, window: GuiWin, pane: MuxPane, line: Option<String>) {
     promise::spawn::spawn(async move {
         config::with_lua_config_on_main_thread(move |lua| do_event(lua, name, window, pane, line))
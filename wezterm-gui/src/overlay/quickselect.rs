@@ -445,6 +445,10 @@ impl Pane for QuickSelectOverlay {
         self.delegate.perform_actions(actions)
     }
 
+    fn perform_action_batch(&self, batch: &mut termwiz::escape::ActionBatch) {
+        self.delegate.perform_action_batch(batch)
+    }
+
     fn is_dead(&self) -> bool {
         self.delegate.is_dead()
     }
@@ -588,6 +588,9 @@ impl RenderState {
             let result = UtilSprites::new(&mut *glyph_cache.borrow_mut(), metrics);
             match result {
                 Ok(util_sprites) => {
+                    // The util sprites are held outside of the glyph cache,
+                    // so they must not be evicted
+                    glyph_cache.borrow_mut().atlas.pin_allocated_pages();
                     let glyph_prog = match &context {
                         RenderContext::Glium(context) => {
                             Some(Self::compile_prog(&context, Self::glyph_shader)?)
@@ -714,6 +717,12 @@ impl RenderState {
         self.glyph_cache.borrow_mut().config_changed();
     }
 
+    /// Evict the least recently used sprites from the texture atlas to
+    /// make room for new ones.  Returns false if nothing could be evicted.
+    pub fn evict_least_recently_used(&self) -> bool {
+        self.glyph_cache.borrow_mut().evict_least_recently_used()
+    }
+
     pub fn recreate_texture_atlas(
         &mut self,
         fonts: &Rc<FontConfiguration>,
@@ -761,6 +770,7 @@ impl RenderState {
         let size = size.unwrap_or_else(|| self.glyph_cache.borrow().atlas.size());
         let mut new_glyph_cache = GlyphCache::new_gl(&self.context, fonts, size)?;
         self.util_sprites = UtilSprites::new(&mut new_glyph_cache, metrics)?;
+        new_glyph_cache.atlas.pin_allocated_pages();
 
         let mut glyph_cache = self.glyph_cache.borrow_mut();
 
@@ -122,6 +122,9 @@ fn compute_tab_title(
                 } else {
                     tab.tab_title.clone()
                 };
+                if let Some(percent) = pane.paste_progress {
+                    title = format!("Pasting {percent}% - {title}");
+                }
                 let classic_spacing = if config.use_fancy_tab_bar { "" } else { " " };
                 if config.show_tab_index_in_tab_bar {
                     title = format!(
@@ -666,7 +669,9 @@ pub fn parse_status_text(text: &str, default_cell: CellAttributes) -> Line {
             | Action::Esc(_)
             | Action::KittyImage(_)
             | Action::XtGetTcap(_)
-            | Action::Sixel(_) => {
+            | Action::ParseError { .. }
+            | Action::Sixel(_)
+            | Action::SixelStream(_) => {
                 flush_print(&mut print_buffer, &mut cells, &pen);
             }
         }
@@ -23,10 +23,11 @@ use crate::termwindow::modal::Modal;
 use crate::termwindow::render::paint::AllowImage;
 use crate::termwindow::render::{
     CachedLineState, LineQuadCacheKey, LineQuadCacheValue, LineToEleShapeCacheKey,
-    LineToElementShapeItem,
+    LineToElementShapeItem, PaneQuadCacheValue,
 };
 use crate::termwindow::webgpu::WebGpuState;
 use ::wezterm_term::input::{ClickPosition, MouseButton as TMB};
+use ::window::screen::SubpixelOrder;
 use ::window::*;
 use anyhow::{anyhow, ensure, Context};
 use config::keyassignment::{
@@ -35,11 +36,13 @@ use config::keyassignment::{
 };
 use config::window::WindowLevel;
 use config::{
-    configuration, AudibleBell, ConfigHandle, Dimension, DimensionContext, FrontEndSelection,
-    GeometryOrigin, GuiPosition, TermConfig, WindowCloseConfirmation,
+    configuration, AudibleBell, ConfigHandle, Dimension, DimensionContext, DisplayPixelGeometry,
+    FreeTypeLoadTarget, FrontEndSelection, GeometryOrigin, GuiPosition, TermConfig,
+    WindowCloseConfirmation,
 };
 use lfucache::*;
 use mlua::{FromLua, UserData, UserDataFields};
+use mux::connui::PanePrompt;
 use mux::pane::{
     CachePolicy, CloseReason, Pane, PaneId, Pattern as MuxPattern, PerformAssignmentResult,
 };
@@ -204,6 +207,8 @@ pub struct PaneState {
 
     bell_start: Option<Instant>,
     pub mouse_terminal_coords: Option<(ClickPosition, StableRowIndex)>,
+    /// The quads from the last time that this pane was painted
+    quad_cache: Option<PaneQuadCacheValue>,
 }
 
 /// Data used when synchronously formatting pane and window titles
@@ -269,6 +274,9 @@ pub struct PaneInformation {
     pub pixel_height: usize,
     pub title: String,
     pub user_vars: HashMap<String, String>,
+    /// The percentage of a large paste that has been written
+    /// to the pane, while it is being written
+    pub paste_progress: Option<u8>,
 }
 
 impl UserData for PaneInformation {
@@ -286,6 +294,7 @@ impl UserData for PaneInformation {
         fields.add_field_method_get("pixel_height", |_, this| Ok(this.pixel_width));
         fields.add_field_method_get("title", |_, this| Ok(this.title.clone()));
         fields.add_field_method_get("user_vars", |_, this| Ok(this.user_vars.clone()));
+        fields.add_field_method_get("paste_progress", |_, this| Ok(this.paste_progress));
         fields.add_field_method_get("foreground_process_name", |_, this| {
             let mut name = None;
             if let Some(mux) = Mux::try_get() {
@@ -462,6 +471,22 @@ pub struct TermWindow {
 }
 
 impl TermWindow {
+    /// Apply the subpixel geometry of the display that the window is on,
+    /// discarding the rasterized glyphs if it changed
+    fn update_pixel_geometry(&mut self) {
+        if self
+            .fonts
+            .set_pixel_geometry(resolve_pixel_geometry(&self.config))
+        {
+            if let Err(err) = self.recreate_texture_atlas(None) {
+                log::error!("recreate_texture_atlas: {:#}", err);
+            }
+            if let Some(window) = self.window.as_ref() {
+                window.invalidate();
+            }
+        }
+    }
+
     fn load_os_parameters(&mut self) {
         if let Some(ref window) = self.window {
             self.os_parameters = match window.get_os_parameters(&self.config, self.window_state) {
@@ -523,6 +548,11 @@ impl TermWindow {
         self.focused = if focused { Some(Instant::now()) } else { None };
         self.quad_generation += 1;
         self.load_os_parameters();
+        if focused {
+            // The window may have been moved to another display
+            // while it didn't have the focus
+            self.update_pixel_geometry();
+        }
 
         if self.focused.is_none() {
             self.last_mouse_click = None;
@@ -582,6 +612,7 @@ impl TermWindow {
         let config = configuration();
         let dpi = config.dpi.unwrap_or_else(|| ::window::default_dpi()) as usize;
         let fontconfig = Rc::new(FontConfiguration::new(Some(config.clone()), dpi)?);
+        fontconfig.set_pixel_geometry(resolve_pixel_geometry(&config));
 
         let mux = Mux::get();
         let size = match mux.get_active_tab_for_window(mux_window_id) {
@@ -1198,6 +1229,8 @@ impl TermWindow {
                     self.emit_user_var_event(pane_id, name, value);
                 }
                 MuxNotification::WindowTitleChanged { .. }
+                | MuxNotification::PaneTitleChanged { .. }
+                | MuxNotification::PasteProgress(_)
                 | MuxNotification::Alert {
                     alert:
                         Alert::OutputSinceFocusLost
@@ -1282,7 +1315,8 @@ impl TermWindow {
                 MuxNotification::WindowRemoved(_window_id) => {
                     // Handled by frontend
                 }
-                MuxNotification::AssignClipboard { .. } => {
+                MuxNotification::AssignClipboard { .. }
+                | MuxNotification::QueryClipboard { .. } => {
                     // Handled by frontend
                 }
                 MuxNotification::SaveToDownloads { .. } => {
@@ -1299,9 +1333,13 @@ impl TermWindow {
                 MuxNotification::TabTitleChanged { .. } => {
                     self.update_title_post_status();
                 }
+                MuxNotification::PanePrompt(prompt) => {
+                    self.show_pane_prompt(prompt);
+                }
                 MuxNotification::PaneAdded(_)
                 | MuxNotification::WorkspaceRenamed { .. }
                 | MuxNotification::PaneRemoved(_)
+                | MuxNotification::PaneExited { .. }
                 | MuxNotification::WindowWorkspaceChanged(_)
                 | MuxNotification::ActiveWorkspaceChanged(_)
                 | MuxNotification::Empty
@@ -1455,6 +1493,8 @@ impl TermWindow {
                     | Alert::Bell,
             }
             | MuxNotification::PaneFocused(pane_id)
+            | MuxNotification::PaneTitleChanged { pane_id, .. }
+            | MuxNotification::PasteProgress(pane_id)
             | MuxNotification::PaneRemoved(pane_id)
             | MuxNotification::PaneOutput(pane_id) => {
                 // Ideally we'd check to see if pane_id is part of this window,
@@ -1498,15 +1538,26 @@ impl TermWindow {
                     return true;
                 }
             }
+            MuxNotification::PanePrompt(ref prompt) => {
+                let mux = Mux::get();
+                match mux.resolve_pane_id(prompt.pane_id) {
+                    Some((_domain_id, window_id, _tab_id)) if window_id == mux_window_id => {
+                        // fall through
+                    }
+                    _ => return true,
+                }
+            }
             MuxNotification::Alert {
                 alert: Alert::ToastNotification { .. },
                 ..
             }
             | MuxNotification::AssignClipboard { .. }
+            | MuxNotification::QueryClipboard { .. }
             | MuxNotification::SaveToDownloads { .. }
             | MuxNotification::WindowCreated(_)
             | MuxNotification::ActiveWorkspaceChanged(_)
             | MuxNotification::WorkspaceRenamed { .. }
+            | MuxNotification::PaneExited { .. }
             | MuxNotification::Empty
             | MuxNotification::WindowWorkspaceChanged(_) => return true,
             MuxNotification::Alert {
@@ -1779,6 +1830,7 @@ impl TermWindow {
         if let Err(err) = self.fonts.config_changed(&config) {
             log::error!("Failed to load font configuration: {:#}", err);
         }
+        self.update_pixel_geometry();
 
         if let Some(window) = mux.get_window(self.mux_window_id) {
             let term_config: Arc<dyn TerminalConfiguration> =
@@ -2299,6 +2351,23 @@ impl TermWindow {
         promise::spawn::spawn(future).detach();
     }
 
+    fn show_pane_prompt(&mut self, prompt: Arc<PanePrompt>) {
+        let mux = Mux::get();
+        let pane = match mux.get_pane(prompt.pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+        if !prompt.claim() {
+            return;
+        }
+
+        let (overlay, future) = start_overlay_pane(self, &pane, move |_pane_id, term| {
+            crate::overlay::prompt::show_pane_prompt_overlay(term, prompt)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
     fn show_debug_overlay(&mut self) {
         let mux = Mux::get();
         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -2310,9 +2379,27 @@ impl TermWindow {
 
         let opengl_info = self.opengl_info.as_deref().unwrap_or("Unknown").to_string();
         let connection_info = self.connection_name.clone();
+        let atlas_info = self
+            .render_state
+            .as_ref()
+            .map(|render_state| {
+                render_state
+                    .glyph_cache
+                    .borrow()
+                    .atlas
+                    .occupancy()
+                    .to_string()
+            })
+            .unwrap_or_else(|| "Unknown".to_string());
 
         let (overlay, future) = start_overlay(self, &tab, move |_tab_id, term| {
-            crate::overlay::show_debug_overlay(term, gui_win, opengl_info, connection_info)
+            crate::overlay::show_debug_overlay(
+                term,
+                gui_win,
+                opengl_info,
+                connection_info,
+                atlas_info,
+            )
         });
         self.assign_overlay(tab.tab_id(), overlay);
         promise::spawn::spawn(future).detach();
@@ -2634,6 +2721,9 @@ impl TermWindow {
             PasteFrom(source) => {
                 self.paste_from_clipboard(pane, *source);
             }
+            CancelPaste => {
+                pane.cancel_paste();
+            }
             ActivateTabRelative(n) => {
                 self.activate_tab_relative(*n, true)?;
             }
@@ -3359,6 +3449,7 @@ impl TermWindow {
             pixel_height: pos.pixel_height,
             title: pos.pane.get_title(),
             user_vars: pos.pane.copy_user_vars(),
+            paste_progress: pos.pane.paste_progress().map(|p| p.percent()),
         }
     }
 
@@ -3531,3 +3622,51 @@ impl Drop for TermWindow {
         }
     }
 }
+
+/// Determine the subpixel geometry of the display that the window is on,
+/// when that may differ from the `display_pixel_geometry` in the config.
+/// Displays whose subpixel order is unknown, or which are rotated, or
+/// whose subpixels are arranged in the other direction from the one that
+/// we render for, get grayscale antialiasing.
+fn resolve_pixel_geometry(config: &ConfigHandle) -> Option<DisplayPixelGeometry> {
+    let vertical = match config
+        .freetype_render_target
+        .unwrap_or(config.freetype_load_target)
+    {
+        FreeTypeLoadTarget::HorizontalLcd => false,
+        FreeTypeLoadTarget::VerticalLcd => true,
+        _ => return None,
+    };
+    if config.display_pixel_geometry_by_screen.is_empty()
+        && config.display_pixel_geometry != DisplayPixelGeometry::Auto
+    {
+        return None;
+    }
+
+    // The active screen is the one that contains the focused window
+    let screen = Connection::get()
+        .and_then(|conn| conn.screens().ok())
+        .map(|screens| screens.active);
+
+    let geometry = screen
+        .as_ref()
+        .and_then(|screen| config.display_pixel_geometry_by_screen.get(&screen.name))
+        .copied()
+        .unwrap_or(config.display_pixel_geometry);
+    if geometry != DisplayPixelGeometry::Auto {
+        return Some(geometry);
+    }
+
+    Some(match screen {
+        Some(screen) if !screen.rotated => match (screen.subpixel_order, vertical) {
+            (SubpixelOrder::HorizontalRgb, false) | (SubpixelOrder::VerticalRgb, true) => {
+                DisplayPixelGeometry::RGB
+            }
+            (SubpixelOrder::HorizontalBgr, false) | (SubpixelOrder::VerticalBgr, true) => {
+                DisplayPixelGeometry::BGR
+            }
+            _ => DisplayPixelGeometry::None,
+        },
+        _ => DisplayPixelGeometry::None,
+    })
+}
@@ -7,7 +7,7 @@ use ::window::glium::uniforms::{
     MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction,
 };
 use ::window::glium::{BlendingFunction, LinearBlendingFactor, Surface};
-use config::FreeTypeLoadTarget;
+use config::{DisplayPixelGeometry, FreeTypeLoadTarget};
 
 impl crate::TermWindow {
     pub fn call_draw(&mut self, frame: &mut RenderFrame) -> anyhow::Result<()> {
@@ -173,9 +173,12 @@ impl crate::TermWindow {
             .freetype_render_target
             .unwrap_or(self.config.freetype_load_target)
         {
-            FreeTypeLoadTarget::HorizontalLcd | FreeTypeLoadTarget::VerticalLcd => true,
+            FreeTypeLoadTarget::HorizontalLcd | FreeTypeLoadTarget::VerticalLcd => {
+                self.fonts.pixel_geometry() != DisplayPixelGeometry::None
+            }
             _ => false,
         };
+        let subpixel_gamma = self.config.subpixel_gamma;
 
         let dual_source_blending = glium::DrawParameters {
             blend: glium::Blend {
@@ -249,6 +252,7 @@ impl crate::TermWindow {
                     uniforms.add("atlas_linear_sampler", &atlas_linear_sampler);
                     uniforms.add("foreground_text_hsb", &foreground_text_hsb);
                     uniforms.add("subpixel_aa", &subpixel_aa);
+                    uniforms.add("subpixel_gamma", &subpixel_gamma);
                     uniforms.add("milliseconds", &milliseconds);
                     uniforms.add_struct("cursor_blink", &cursor_blink);
                     uniforms.add_struct("blink", &blink);
@@ -5,6 +5,7 @@ use crate::quad::{
     HeapQuadAllocator, QuadAllocator, QuadImpl, QuadTrait, TripleLayerQuadAllocator,
     TripleLayerQuadAllocatorTrait,
 };
+use crate::selection::SelectionRange;
 use crate::shapecache::*;
 use crate::termwindow::render::paint::AllowImage;
 use crate::termwindow::{BorrowedShapeCacheKey, RenderState, ShapedInfo, TermWindowNotif};
@@ -74,14 +75,66 @@ pub struct LineQuadCacheKey {
 pub struct LineQuadCacheValue {
     /// For resolving hash collisions
     pub line: Line,
+    pub quads: RowQuads,
+}
+
+/// The quads produced by rendering a single row of a pane
+#[derive(Clone)]
+pub struct RowQuads {
+    pub layers: Rc<HeapQuadAllocator>,
     pub expires: Option<Instant>,
-    pub layers: HeapQuadAllocator,
     // Only set if the line contains any hyperlinks, so
     // that we can invalidate when it changes
     pub current_highlight: Option<Arc<Hyperlink>>,
     pub invalidate_on_hover_change: bool,
 }
 
+impl RowQuads {
+    /// Returns true if the quads need to be regenerated, either
+    /// because they are animated and the next frame is due, or
+    /// because the hovered hyperlink has changed
+    pub fn is_stale(&self, current_highlight: Option<&Arc<Hyperlink>>) -> bool {
+        let expired = self.expires.map(|i| Instant::now() >= i).unwrap_or(false);
+        let hover_changed = self.invalidate_on_hover_change
+            && !same_hyperlink(self.current_highlight.as_ref(), current_highlight);
+        expired || hover_changed
+    }
+}
+
+/// The state that affects the rendering of every visible row of a pane.
+/// If any of this changes, none of the rows in the corresponding
+/// PaneQuadCacheValue can be reused.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaneQuadCacheKey {
+    pub config_generation: usize,
+    pub shape_generation: usize,
+    pub quad_generation: usize,
+    pub stable_top: StableRowIndex,
+    pub cols: usize,
+    pub viewport_rows: usize,
+    pub top_pixel_y: f32,
+    pub left_pixel_x: f32,
+    pub pane_top: usize,
+    pub pane_is_active: bool,
+    pub selection: Option<SelectionRange>,
+    pub rectangular: bool,
+    pub dead_key_or_leader: bool,
+    pub composing: Option<String>,
+    pub password_input: bool,
+    pub reverse_video: bool,
+}
+
+/// The quads for the visible rows of a pane as of the last time that
+/// it was painted.  The rows that haven't changed since then are
+/// copied from here, so that only the damaged rows need to be rebuilt.
+pub struct PaneQuadCacheValue {
+    pub key: PaneQuadCacheKey,
+    /// The pane seqno from just before the rows were rendered
+    pub seqno: SequenceNo,
+    pub cursor: StableCursorPosition,
+    pub rows: Vec<Option<RowQuads>>,
+}
+
 pub struct LineToElementParams<'a> {
     pub line: &'a Line,
     pub config: &'a ConfigHandle,
@@ -808,6 +861,21 @@ impl crate::TermWindow {
         }
     }
 
+    /// Evict the least recently used sprites from the texture atlas,
+    /// discarding any shaped text that may refer to them.
+    /// Returns false if nothing could be evicted.
+    pub fn evict_texture_atlas(&mut self) -> bool {
+        let evicted = self.render_state.as_ref().map_or(false, |render_state| {
+            render_state.evict_least_recently_used()
+        });
+        if evicted {
+            self.shape_generation += 1;
+            self.shape_cache.borrow_mut().clear();
+            self.line_to_ele_shape_cache.borrow_mut().clear();
+        }
+        evicted
+    }
+
     pub fn recreate_texture_atlas(&mut self, size: Option<usize>) -> anyhow::Result<()> {
         self.shape_generation += 1;
         self.shape_cache.borrow_mut().clear();
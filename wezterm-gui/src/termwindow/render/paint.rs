@@ -3,6 +3,7 @@ use ::window::bitmaps::atlas::OutOfTextureSpace;
 use ::window::WindowOps;
 use anyhow::Context;
 use smol::Timer;
+use std::collections::HashSet;
 use std::time::{Duration, Instant};
 use wezterm_font::ClearShapeCache;
 
@@ -24,6 +25,10 @@ impl crate::TermWindow {
 
         let start = Instant::now();
 
+        if let Some(render_state) = self.render_state.as_ref() {
+            render_state.glyph_cache.borrow_mut().atlas.begin_frame();
+        }
+
         {
             let diff = start.duration_since(self.last_fps_check_time);
             if diff > Duration::from_secs(1) {
@@ -51,15 +56,15 @@ impl crate::TermWindow {
                 },
                 Err(err) => {
                     if let Some(&OutOfTextureSpace {
-                        size: Some(size),
-                        current_size,
+                        size: Some(size), ..
                     }) = err.root_cause().downcast_ref::<OutOfTextureSpace>()
                     {
-                        let result = if pass == 0 {
-                            // Let's try clearing out the atlas and trying again
-                            // self.clear_texture_atlas()
-                            log::trace!("recreate_texture_atlas");
-                            self.recreate_texture_atlas(Some(current_size))
+                        let result = if pass == 0 && self.evict_texture_atlas() {
+                            // We made room by evicting the least recently
+                            // used sprites; only grow the atlas if what is
+                            // needed for this frame still doesn't fit
+                            log::trace!("evicted sprites from the texture atlas");
+                            Ok(())
                         } else {
                             log::trace!("grow texture atlas to {}", size);
                             self.recreate_texture_atlas(Some(size))
@@ -74,11 +79,7 @@ impl crate::TermWindow {
                                 AllowImage::Scale(4) => AllowImage::Scale(8),
                                 AllowImage::Scale(8) => AllowImage::No,
                                 AllowImage::No | _ => {
-                                    log::error!(
-                                        "Failed to {} texture: {}",
-                                        if pass == 0 { "clear" } else { "resize" },
-                                        err
-                                    );
+                                    log::error!("Failed to resize texture: {}", err);
                                     break 'pass;
                                 }
                             };
@@ -246,6 +247,9 @@ impl crate::TermWindow {
             .context("filled_rectangle for window background")?;
         }
 
+        self.preshape_panes(&panes).context("preshape_panes")?;
+
+        let mut painted = HashSet::new();
         for pos in panes {
             if pos.is_active {
                 self.update_text_cursor(&pos);
@@ -255,6 +259,15 @@ impl crate::TermWindow {
                 }
             }
             self.paint_pane(&pos, &mut layers).context("paint_pane")?;
+            painted.insert(pos.pane.pane_id());
+        }
+
+        // Don't hold on to the quads of panes that are no longer visible,
+        // such as those in other tabs or those that have been closed
+        for (pane_id, state) in self.pane_state.borrow_mut().iter_mut() {
+            if !painted.contains(pane_id) {
+                state.quad_cache.take();
+            }
         }
 
         if let Some(pane) = self.get_active_pane_or_overlay() {
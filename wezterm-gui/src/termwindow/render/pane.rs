@@ -1,25 +1,34 @@
+use crate::customglyph::BlockKey;
 use crate::quad::{HeapQuadAllocator, QuadTrait, TripleLayerQuadAllocator};
 use crate::selection::SelectionRange;
+use crate::shapecache::{BorrowedShapeCacheKey, ShapeCacheKey, ShapedInfo};
 use crate::termwindow::box_model::*;
 use crate::termwindow::render::{
-    same_hyperlink, CursorProperties, LineQuadCacheKey, LineQuadCacheValue, LineToEleShapeCacheKey,
-    RenderScreenLineParams,
+    CursorProperties, LineQuadCacheKey, LineQuadCacheValue, LineToEleShapeCacheKey,
+    PaneQuadCacheKey, PaneQuadCacheValue, RenderScreenLineParams, RowQuads,
 };
 use crate::termwindow::{ScrollHit, UIItem, UIItemType};
 use ::window::bitmaps::TextureRect;
 use ::window::DeadKeyStatus;
 use anyhow::Context;
-use config::VisualBellTarget;
+use config::{ConfigHandle, VisualBellTarget};
 use mux::pane::{PaneId, WithPaneLines};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::PositionedPane;
 use ordered_float::NotNan;
+use std::collections::HashSet;
+use std::rc::Rc;
 use std::time::Instant;
 use wezterm_dynamic::Value;
+use wezterm_font::shaper::pool::{ShapePool, ShapeRequest};
+use wezterm_font::LoadedFont;
 use wezterm_term::color::{ColorAttribute, ColorPalette};
 use wezterm_term::{Line, StableRowIndex};
 use window::color::LinearRgba;
 
+/// The fewest runs of text for which it is worth using the ShapePool
+const MIN_PARALLEL_SHAPE_RUNS: usize = 8;
+
 impl crate::TermWindow {
     fn paint_pane_box_model(&mut self, pos: &PositionedPane) -> anyhow::Result<()> {
         let computed = self.build_pane(pos)?;
@@ -311,6 +320,26 @@ impl crate::TermWindow {
             pos.pane
                 .apply_hyperlinks(stable_range.clone(), &self.config.hyperlink_rules);
 
+            let dead_key_or_leader =
+                self.dead_key_status != DeadKeyStatus::None || self.leader_is_active();
+            let composing = match (pos.is_active, &self.dead_key_status) {
+                (true, DeadKeyStatus::Composing(composing)) => Some(composing.to_string()),
+                _ => None,
+            };
+            let password_input = if config.detect_password_input {
+                match pos.pane.get_metadata() {
+                    Value::Object(obj) => {
+                        match obj.get(&Value::String("password_input".to_string())) {
+                            Some(Value::Bool(b)) => *b,
+                            _ => false,
+                        }
+                    }
+                    _ => false,
+                }
+            } else {
+                false
+            };
+
             struct LineRender<'a, 'b> {
                 term_window: &'a mut crate::TermWindow,
                 selrange: Option<SelectionRange>,
@@ -333,6 +362,11 @@ impl crate::TermWindow {
                 white_space: TextureRect,
                 filled_box: TextureRect,
                 window_is_transparent: bool,
+                dead_key_or_leader: bool,
+                composing: Option<String>,
+                password_input: bool,
+                viewport_top: StableRowIndex,
+                rows: &'a mut Vec<Option<RowQuads>>,
                 layers: &'a mut TripleLayerQuadAllocator<'b>,
                 error: Option<anyhow::Error>,
             }
@@ -341,6 +375,59 @@ impl crate::TermWindow {
                 + border.left.get() as f32
                 + (pos.left as f32 * self.render_metrics.cell_size.width as f32);
 
+            let pane_key = PaneQuadCacheKey {
+                config_generation: config.generation(),
+                shape_generation: self.shape_generation,
+                quad_generation: self.quad_generation,
+                stable_top: stable_range.start,
+                cols: dims.cols,
+                viewport_rows: dims.viewport_rows,
+                top_pixel_y,
+                left_pixel_x,
+                pane_top: pos.top,
+                pane_is_active: pos.is_active,
+                selection: selrange,
+                rectangular,
+                dead_key_or_leader,
+                composing: composing.clone(),
+                password_input,
+                reverse_video: dims.reverse_video,
+            };
+            // Sample the seqno prior to rendering, so that any changes
+            // made while we are rendering are considered to be damage
+            // the next time around
+            let seqno = pos.pane.get_current_seqno();
+
+            // Figure out which of the rows that we painted last time
+            // can be reused as-is; any that can't are None and will
+            // be rendered again below
+            let num_rows = dims.viewport_rows;
+            let cached = self.pane_state(pane_id).quad_cache.take();
+            let mut rows: Vec<Option<RowQuads>> = match cached {
+                Some(cached) if cached.key == pane_key => {
+                    let changed = pos
+                        .pane
+                        .get_changed_since(stable_range.clone(), cached.seqno);
+                    let prior_cursor = cached.cursor;
+                    let cursor_changed = prior_cursor != cursor;
+                    cached
+                        .rows
+                        .into_iter()
+                        .zip(stable_range.clone())
+                        .map(|(row, stable_row)| {
+                            let damaged = changed.contains(stable_row)
+                                || (cursor_changed
+                                    && (stable_row == prior_cursor.y || stable_row == cursor.y));
+                            row.filter(|row| {
+                                !damaged && !row.is_stale(self.current_highlight.as_ref())
+                            })
+                        })
+                        .collect()
+                }
+                _ => vec![None; num_rows],
+            };
+            let num_damaged = rows.iter().filter(|row| row.is_none()).count();
+
             let mut render = LineRender {
                 term_window: self,
                 selrange,
@@ -363,6 +450,11 @@ impl crate::TermWindow {
                 white_space,
                 filled_box,
                 window_is_transparent,
+                dead_key_or_leader,
+                composing,
+                password_input,
+                viewport_top: stable_range.start,
+                rows: &mut rows,
                 layers,
                 error: None,
             };
@@ -370,11 +462,10 @@ impl crate::TermWindow {
             impl<'a, 'b> LineRender<'a, 'b> {
                 fn render_line(
                     &mut self,
-                    stable_top: StableRowIndex,
-                    line_idx: usize,
+                    stable_row: StableRowIndex,
+                    row: usize,
                     line: &&mut Line,
-                ) -> anyhow::Result<()> {
-                    let stable_row = stable_top + line_idx as StableRowIndex;
+                ) -> anyhow::Result<RowQuads> {
                     let selrange = self
                         .selrange
                         .map_or(0..0, |sel| sel.cols_for_row(stable_row, self.rectangular));
@@ -388,34 +479,14 @@ impl crate::TermWindow {
                                     y: 0,
                                     ..*self.cursor
                                 },
-                                dead_key_or_leader: self.term_window.dead_key_status
-                                    != DeadKeyStatus::None
-                                    || self.term_window.leader_is_active(),
+                                dead_key_or_leader: self.dead_key_or_leader,
                                 cursor_fg: self.cursor_fg,
                                 cursor_bg: self.cursor_bg,
                                 cursor_border_color: self.cursor_border_color,
                                 cursor_is_default_color: self.cursor_is_default_color,
                             }),
-                            match (self.pos.is_active, &self.term_window.dead_key_status) {
-                                (true, DeadKeyStatus::Composing(composing)) => {
-                                    Some(composing.to_string())
-                                }
-                                _ => None,
-                            },
-                            if self.term_window.config.detect_password_input {
-                                match self.pos.pane.get_metadata() {
-                                    Value::Object(obj) => {
-                                        match obj.get(&Value::String("password_input".to_string()))
-                                        {
-                                            Some(Value::Bool(b)) => *b,
-                                            _ => false,
-                                        }
-                                    }
-                                    _ => false,
-                                }
-                            } else {
-                                false
-                            },
+                            self.composing.clone(),
+                            self.password_input,
                         )
                     } else {
                         (None, None, false)
@@ -435,35 +506,23 @@ impl crate::TermWindow {
                         cursor,
                         shape_hash,
                         top_pixel_y: NotNan::new(self.top_pixel_y).unwrap()
-                            + (line_idx + self.pos.top) as f32
+                            + (row + self.pos.top) as f32
                                 * self.term_window.render_metrics.cell_size.height as f32,
                         left_pixel_x: NotNan::new(self.left_pixel_x).unwrap(),
-                        phys_line_idx: line_idx,
+                        phys_line_idx: row,
                         reverse_video: self.dims.reverse_video,
                     };
 
                     if let Some(cached_quad) =
                         self.term_window.line_quad_cache.borrow_mut().get(&quad_key)
                     {
-                        let expired = cached_quad
-                            .expires
-                            .map(|i| Instant::now() >= i)
-                            .unwrap_or(false);
-                        let hover_changed = if cached_quad.invalidate_on_hover_change {
-                            !same_hyperlink(
-                                cached_quad.current_highlight.as_ref(),
-                                self.term_window.current_highlight.as_ref(),
-                            )
-                        } else {
-                            false
-                        };
-                        if !expired && !hover_changed {
-                            cached_quad
-                                .layers
-                                .apply_to(self.layers)
-                                .context("cached_quad.layers.apply_to")?;
-                            self.term_window.update_next_frame_time(cached_quad.expires);
-                            return Ok(());
+                        if !cached_quad
+                            .quads
+                            .is_stale(self.term_window.current_highlight.as_ref())
+                        {
+                            self.term_window
+                                .update_next_frame_time(cached_quad.quads.expires);
+                            return Ok(cached_quad.quads.clone());
                         }
                     }
 
@@ -531,13 +590,9 @@ impl crate::TermWindow {
                     let expires = self.term_window.has_animation.borrow().as_ref().cloned();
                     self.term_window.update_next_frame_time(next_due);
 
-                    buf.apply_to(self.layers)
-                        .context("HeapQuadAllocator::apply_to")?;
-
-                    let quad_value = LineQuadCacheValue {
-                        layers: buf,
+                    let quads = RowQuads {
+                        layers: Rc::new(buf),
                         expires,
-                        line: (*line).clone(),
                         invalidate_on_hover_change: render_result.invalidate_on_hover_change,
                         current_highlight: if render_result.invalidate_on_hover_change {
                             self.term_window.current_highlight.clone()
@@ -546,30 +601,84 @@ impl crate::TermWindow {
                         },
                     };
 
-                    self.term_window
-                        .line_quad_cache
-                        .borrow_mut()
-                        .put(quad_key, quad_value);
+                    self.term_window.line_quad_cache.borrow_mut().put(
+                        quad_key,
+                        LineQuadCacheValue {
+                            line: (*line).clone(),
+                            quads: quads.clone(),
+                        },
+                    );
 
-                    Ok(())
+                    Ok(quads)
                 }
             }
 
             impl<'a, 'b> WithPaneLines for LineRender<'a, 'b> {
                 fn with_lines_mut(&mut self, stable_top: StableRowIndex, lines: &mut [&mut Line]) {
                     for (line_idx, line) in lines.iter().enumerate() {
-                        if let Err(err) = self.render_line(stable_top, line_idx, line) {
-                            self.error.replace(err);
-                            return;
+                        let stable_row = stable_top + line_idx as StableRowIndex;
+                        let row = (stable_row - self.viewport_top) as usize;
+                        let result = self.render_line(stable_row, row, line).and_then(|quads| {
+                            quads
+                                .layers
+                                .apply_to(self.layers)
+                                .context("HeapQuadAllocator::apply_to")?;
+                            Ok(quads)
+                        });
+                        match result {
+                            Ok(quads) => {
+                                if let Some(slot) = self.rows.get_mut(row) {
+                                    slot.replace(quads);
+                                }
+                            }
+                            Err(err) => {
+                                self.error.replace(err);
+                                return;
+                            }
                         }
                     }
                 }
             }
 
-            pos.pane.with_lines_mut(stable_range.clone(), &mut render);
-            if let Some(error) = render.error.take() {
-                return Err(error).context("error while calling with_lines_mut");
+            // Walk the rows in order, so that the quads are emitted in the
+            // same order as a full repaint would produce them, copying the
+            // cached quads for undamaged rows and rendering each run of
+            // damaged rows
+            let mut row = 0;
+            while row < num_rows {
+                let damaged = render.rows[row].is_none();
+                let run_end = (row..num_rows)
+                    .find(|&r| render.rows[r].is_none() != damaged)
+                    .unwrap_or(num_rows);
+                if damaged {
+                    let range = stable_range.start + row as StableRowIndex
+                        ..stable_range.start + run_end as StableRowIndex;
+                    pos.pane.with_lines_mut(range, &mut render);
+                    if let Some(error) = render.error.take() {
+                        return Err(error).context("error while calling with_lines_mut");
+                    }
+                } else {
+                    for quads in render.rows[row..run_end].iter().flatten() {
+                        quads
+                            .layers
+                            .apply_to(render.layers)
+                            .context("cached RowQuads apply_to")?;
+                        render.term_window.update_next_frame_time(quads.expires);
+                    }
+                }
+                row = run_end;
             }
+            drop(render);
+
+            metrics::histogram!("paint_pane.damaged_rows").record(num_damaged as f64);
+            self.pane_state(pane_id)
+                .quad_cache
+                .replace(PaneQuadCacheValue {
+                    key: pane_key,
+                    seqno,
+                    cursor,
+                    rows,
+                });
         }
 
         /*
@@ -583,6 +692,170 @@ impl crate::TermWindow {
         Ok(())
     }
 
+    /// Shape the text of the visible lines that are missing from the
+    /// shape caches using the ShapePool, so that the shaping for busy
+    /// panes happens in parallel rather than one pane after the other
+    /// as they are painted.  Any text that can't be shaped that way is
+    /// left for render_screen_line to take care of as usual.
+    pub fn preshape_panes(&mut self, panes: &[PositionedPane]) -> anyhow::Result<()> {
+        let pool = ShapePool::get();
+        if pool.num_workers() < 2 || self.config.use_box_model_render {
+            return Ok(());
+        }
+        let start = Instant::now();
+
+        struct Collector<'a> {
+            term_window: &'a mut crate::TermWindow,
+            config: ConfigHandle,
+            /// The shape hashes of the lines that we've already seen;
+            /// identical lines are frequently found in multiple panes
+            seen_lines: HashSet<[u8; 16]>,
+            seen_runs: HashSet<ShapeCacheKey>,
+            keys: Vec<(ShapeCacheKey, Rc<LoadedFont>)>,
+            requests: Vec<ShapeRequest>,
+            error: Option<anyhow::Error>,
+        }
+
+        impl<'a> Collector<'a> {
+            fn collect_line(&mut self, line: &Line) -> anyhow::Result<()> {
+                let shape_hash = self.term_window.shape_hash_for_line(line);
+                if !self.seen_lines.insert(shape_hash) {
+                    return Ok(());
+                }
+                let line_key = LineToEleShapeCacheKey {
+                    shape_hash,
+                    composing: None,
+                    shape_generation: self.term_window.shape_generation,
+                };
+                if self
+                    .term_window
+                    .line_to_ele_shape_cache
+                    .borrow_mut()
+                    .get(&line_key)
+                    .is_some()
+                {
+                    return Ok(());
+                }
+
+                let (bidi_enabled, bidi_direction) = line.bidi_info();
+                let bidi_hint = if bidi_enabled {
+                    Some(bidi_direction)
+                } else {
+                    None
+                };
+                for cluster in line.cluster(bidi_hint) {
+                    let style = self
+                        .term_window
+                        .fonts
+                        .match_style(&self.config, &cluster.attrs);
+                    let key = BorrowedShapeCacheKey {
+                        style,
+                        text: &cluster.text,
+                    };
+                    if self.term_window.lookup_cached_shape(&key).is_some() {
+                        continue;
+                    }
+                    let key = key.to_owned();
+                    if !self.seen_runs.insert(key.clone()) {
+                        continue;
+                    }
+                    let font = self.term_window.fonts.resolve_font(style)?;
+                    if let Some(request) =
+                        font.shape_request(&cluster, BlockKey::filter_out_synthetic)
+                    {
+                        self.keys.push((key, font));
+                        self.requests.push(request);
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        impl<'a> WithPaneLines for Collector<'a> {
+            fn with_lines_mut(&mut self, _stable_top: StableRowIndex, lines: &mut [&mut Line]) {
+                for line in lines.iter() {
+                    if let Err(err) = self.collect_line(line) {
+                        self.error.replace(err);
+                        return;
+                    }
+                }
+            }
+        }
+
+        let mut collector = Collector {
+            config: self.config.clone(),
+            term_window: self,
+            seen_lines: HashSet::new(),
+            seen_runs: HashSet::new(),
+            keys: vec![],
+            requests: vec![],
+            error: None,
+        };
+
+        for pos in panes {
+            let pane_id = pos.pane.pane_id();
+            let dims = pos.pane.get_dimensions();
+            let top = collector
+                .term_window
+                .get_viewport(pane_id)
+                .unwrap_or(dims.physical_top);
+            let stable_range = top..top + dims.viewport_rows as StableRowIndex;
+
+            // Skip panes that haven't changed since they were last painted
+            let unchanged_since = collector
+                .term_window
+                .pane_state(pane_id)
+                .quad_cache
+                .as_ref()
+                .filter(|cached| {
+                    cached.key.shape_generation == collector.term_window.shape_generation
+                        && cached.key.stable_top == top
+                })
+                .map(|cached| cached.seqno);
+            if let Some(seqno) = unchanged_since {
+                if pos
+                    .pane
+                    .get_changed_since(stable_range.clone(), seqno)
+                    .is_empty()
+                {
+                    continue;
+                }
+            }
+
+            pos.pane.with_lines_mut(stable_range, &mut collector);
+            if let Some(error) = collector.error.take() {
+                return Err(error).context("error while collecting lines to shape");
+            }
+        }
+
+        let Collector { keys, requests, .. } = collector;
+        // Handing off to the pool isn't worth it for a handful of runs
+        if requests.len() < MIN_PARALLEL_SHAPE_RUNS {
+            return Ok(());
+        }
+
+        let num_runs = requests.len();
+        let results = pool.shape_all(requests);
+        let gl_state = self.render_state.as_ref().unwrap();
+        for ((key, font), result) in keys.into_iter().zip(results) {
+            if let Some(Ok(infos)) = result {
+                let glyphs = self.glyph_infos_to_glyphs(
+                    &key.style,
+                    &mut gl_state.glyph_cache.borrow_mut(),
+                    &infos,
+                    &font,
+                    &self.render_metrics,
+                )?;
+                let shaped = Rc::new(ShapedInfo::process(&infos, &glyphs));
+                self.shape_cache.borrow_mut().put(key, Ok(shaped));
+            }
+        }
+
+        metrics::histogram!("preshape_panes").record(start.elapsed());
+        log::trace!("preshaped {} runs in {:?}", num_runs, start.elapsed());
+        Ok(())
+    }
+
     pub fn build_pane(&mut self, pos: &PositionedPane) -> anyhow::Result<ComputedElement> {
         // First compute the bounds for the pane background
 
@@ -62,6 +62,8 @@ impl super::TermWindow {
         if live_resizing && self.dimensions.dpi == dimensions.dpi {
             self.apply_dimensions(&dimensions, None, window);
         } else {
+            // The window may have been moved to another display
+            self.update_pixel_geometry();
             self.scaling_changed(dimensions, self.fonts.get_font_scale(), window);
         }
         if let Some(modal) = self.get_modal() {
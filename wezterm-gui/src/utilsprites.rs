@@ -16,9 +16,18 @@ pub struct RenderMetrics {
     pub descender_plus_two: IntPixelLength,
     pub underline_height: IntPixelLength,
     pub strike_row: IntPixelLength,
+    pub overline_row: IntPixelLength,
+    /// The amplitude of the wave of a curly underline
+    pub curly_amplitude: IntPixelLength,
     pub cell_size: Size,
 }
 
+/// The default amplitude of a curly underline, which spans
+/// from the underline position to the bottom of the cell
+fn default_curly_amplitude(cell_height: isize, descender_row: isize) -> f64 {
+    ((cell_height - descender_row) as f64 / 4.).max(1.)
+}
+
 impl RenderMetrics {
     pub fn with_font_metrics(metrics: &FontMetrics) -> Self {
         let (cell_height, cell_width) = (
@@ -32,13 +41,28 @@ impl RenderMetrics {
             (cell_height as f64 + (metrics.descender - metrics.underline_position).get()) as isize;
         let descender_plus_two =
             (2 * underline_height + descender_row).min(cell_height as isize - underline_height);
-        let strike_row = descender_row / 2;
+        let strike_row = match metrics.strikethrough_row {
+            Some(row) => row.get() as isize,
+            None => descender_row / 2,
+        };
+        let overline_row = metrics
+            .overline_row
+            .map(|row| row.get() as isize)
+            .unwrap_or(0);
+        let curly_amplitude = match metrics.curly_underline_amplitude {
+            Some(amplitude) => amplitude.get(),
+            None => default_curly_amplitude(cell_height as isize, descender_row),
+        }
+        .round()
+        .max(1.) as isize;
 
         Self {
             descender: metrics.descender,
             descender_row,
             descender_plus_two,
             strike_row,
+            overline_row,
+            curly_amplitude,
             cell_size: Size::new(cell_width as isize, cell_height as isize),
             underline_height,
         }
@@ -58,6 +82,8 @@ impl RenderMetrics {
             descender_plus_two: self.descender_plus_two - adjust,
             underline_height: self.underline_height,
             strike_row: self.strike_row,
+            overline_row: self.overline_row,
+            curly_amplitude: self.curly_amplitude,
             cell_size: size,
         }
     }
@@ -111,10 +137,13 @@ impl RenderMetrics {
         let descender_plus_two =
             (2 * underline_height + descender_row).min(cell_height as isize - underline_height);
         let strike_row = match &config.strikethrough_position {
-            None => {
-                ((cell_height as f64 + (metrics.descender.get() - underline_position)) / 2.)
-                    as isize
-            }
+            None => match metrics.strikethrough_row {
+                Some(row) => (row.get() + line_height_y_adjust) as isize,
+                None => {
+                    ((cell_height as f64 + (metrics.descender.get() - underline_position)) / 2.)
+                        as isize
+                }
+            },
             Some(d) => d
                 .evaluate_as_pixels(DimensionContext {
                     dpi: fonts.get_dpi() as f32,
@@ -124,11 +153,43 @@ impl RenderMetrics {
                 .round() as isize,
         };
 
+        let overline_row = match &config.overline_position {
+            None => metrics
+                .overline_row
+                .map(|row| (row.get() + line_height_y_adjust) as isize)
+                .unwrap_or(0),
+            Some(d) => d
+                .evaluate_as_pixels(DimensionContext {
+                    dpi: fonts.get_dpi() as f32,
+                    pixel_max: cell_height as f32,
+                    pixel_cell: cell_height as f32,
+                })
+                .round() as isize,
+        }
+        .clamp(0, (cell_height as isize - underline_height).max(0));
+
+        let default_amplitude = metrics
+            .curly_underline_amplitude
+            .map(|amplitude| amplitude.get())
+            .unwrap_or_else(|| default_curly_amplitude(cell_height as isize, descender_row));
+        let curly_amplitude = match &config.curly_underline_amplitude {
+            None => default_amplitude,
+            Some(d) => d.evaluate_as_pixels(DimensionContext {
+                dpi: fonts.get_dpi() as f32,
+                pixel_max: default_amplitude as f32,
+                pixel_cell: cell_height as f32,
+            }) as f64,
+        }
+        .round()
+        .max(1.) as isize;
+
         Ok(Self {
             descender: metrics.descender - PixelLength::new(line_height_y_adjust),
             descender_row,
             descender_plus_two,
             strike_row,
+            overline_row,
+            curly_amplitude,
             cell_size: Size::new(cell_width as isize, cell_height as isize),
             underline_height,
         })
@@ -68,7 +68,18 @@ where
         let rx_msg = item_rx.recv();
         let wait_for_read = stream.readable().map(|_| Ok(Item::Readable));
 
-        match smol::future::or(rx_msg, wait_for_read).await {
+        let item = smol::future::or(rx_msg, wait_for_read).await;
+
+        if let Ok(Item::Notif(notif)) = &item {
+            if let Some(event) = handler.mux_event(notif) {
+                Pdu::MuxEvent(codec::MuxEvent { event })
+                    .encode_async(&mut stream, 0)
+                    .await?;
+                stream.flush().await.context("flushing PDU to client")?;
+            }
+        }
+
+        match item {
             Ok(Item::Readable) => {
                 let decoded = match Pdu::decode_async(&mut stream, None).await {
                     Ok(data) => data,
@@ -85,7 +96,15 @@ where
                 handler.process_one(decoded);
             }
             Ok(Item::WritePdu(decoded)) => {
-                match decoded.pdu.encode_async(&mut stream, decoded.serial).await {
+                match decoded
+                    .pdu
+                    .encode_async_with_capabilities(
+                        &mut stream,
+                        decoded.serial,
+                        handler.codec_capabilities(),
+                    )
+                    .await
+                {
                     Ok(()) => {}
                     Err(err) => {
                         if let Some(err) = err.root_cause().downcast_ref::<std::io::Error>() {
@@ -127,6 +146,8 @@ where
                 handler.schedule_pane_push(pane_id);
             }
             Ok(Item::Notif(MuxNotification::SaveToDownloads { .. })) => {}
+            // There is no clipboard to read in the server
+            Ok(Item::Notif(MuxNotification::QueryClipboard { .. })) => {}
             Ok(Item::Notif(MuxNotification::AssignClipboard {
                 pane_id,
                 selection,
@@ -190,6 +211,12 @@ where
                     .await?;
                 stream.flush().await.context("flushing PDU to client")?;
             }
+            Ok(Item::Notif(MuxNotification::PaneTitleChanged { pane_id, title })) => {
+                Pdu::PaneTitleChanged(codec::PaneTitleChanged { pane_id, title })
+                    .encode_async(&mut stream, 0)
+                    .await?;
+                stream.flush().await.context("flushing PDU to client")?;
+            }
             Ok(Item::Notif(MuxNotification::WorkspaceRenamed {
                 old_workspace,
                 new_workspace,
@@ -204,6 +231,9 @@ where
             }
             Ok(Item::Notif(MuxNotification::ActiveWorkspaceChanged(_))) => {}
             Ok(Item::Notif(MuxNotification::Empty)) => {}
+            Ok(Item::Notif(MuxNotification::PanePrompt(_))) => {}
+            Ok(Item::Notif(MuxNotification::PaneExited { .. })) => {}
+            Ok(Item::Notif(MuxNotification::PasteProgress(_))) => {}
             Err(err) => {
                 log::error!("process_async Err {}", err);
                 return Ok(());
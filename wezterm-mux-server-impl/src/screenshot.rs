@@ -0,0 +1,240 @@
+//! Renders the content of a pane to a png image for
+//! `wezterm cli screenshot`.
+//! The text is rasterized with the fonts from the configuration of
+//! this process, so no GPU or window is required.
+use anyhow::Context;
+use config::{BoldBrightening, ConfigHandle};
+use image::{Rgba, RgbaImage};
+use mux::pane::Pane;
+use std::sync::Arc;
+use termwiz::cell::{Intensity, Underline};
+use termwiz::color::{ColorAttribute, SrgbaTuple};
+use termwiz::surface::Line;
+use wezterm_font::{FontConfiguration, RasterizedGlyph};
+use wezterm_term::color::ColorPalette;
+use wezterm_term::StableRowIndex;
+
+/// Captures the visible content of `pane` and renders it to a png
+/// image at `dpi`, or the `dpi` from the configuration if not specified.
+/// The rendering itself is carried out on a separate thread.
+pub async fn screenshot(pane: Arc<dyn Pane>, dpi: Option<f64>) -> anyhow::Result<Vec<u8>> {
+    let config = config::configuration();
+    let dims = pane.get_dimensions();
+    let rows = dims.viewport_rows;
+    let cols = dims.cols;
+    let (_first_row, mut lines) =
+        pane.get_lines(dims.physical_top..dims.physical_top + rows as StableRowIndex);
+    lines.truncate(rows);
+    let palette = pane.palette();
+    let dpi = dpi.or(config.dpi).unwrap_or(96.);
+
+    promise::spawn::spawn_into_new_thread(move || {
+        let image = render_lines(&config, &palette, dpi, &lines, cols, rows)?;
+        let mut png = vec![];
+        image
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .context("encoding png")?;
+        Ok(png)
+    })
+    .await
+}
+
+/// Rasterize `lines` using the fonts from the configuration and
+/// the colors from `palette`, producing an image of a `cols` x `rows`
+/// terminal screen.
+fn render_lines(
+    config: &ConfigHandle,
+    palette: &ColorPalette,
+    dpi: f64,
+    lines: &[Line],
+    cols: usize,
+    rows: usize,
+) -> anyhow::Result<RgbaImage> {
+    let fonts = FontConfiguration::new(Some(config.clone()), dpi as usize)?;
+    let metrics = fonts.default_font_metrics()?;
+    let cell_width = metrics.cell_width.get().ceil();
+    let cell_height = metrics.cell_height.get().ceil();
+    let baseline = cell_height + metrics.descender.get();
+
+    let mut image = RgbaImage::from_pixel(
+        (cols as f64 * cell_width) as u32,
+        (rows as f64 * cell_height) as u32,
+        to_pixel(palette.background),
+    );
+
+    for (row, line) in lines.iter().enumerate() {
+        let top = row as f64 * cell_height;
+        for cluster in line.cluster(None) {
+            let attrs = &cluster.attrs;
+            let fg_attr = match attrs.foreground() {
+                ColorAttribute::PaletteIndex(idx)
+                    if idx < 8
+                        && attrs.intensity() == Intensity::Bold
+                        && config.bold_brightens_ansi_colors != BoldBrightening::No =>
+                {
+                    ColorAttribute::PaletteIndex(idx + 8)
+                }
+                color => color,
+            };
+            let (fg, bg) = if attrs.reverse() {
+                (
+                    palette.resolve_bg(attrs.background()),
+                    palette.resolve_fg(fg_attr),
+                )
+            } else {
+                (
+                    palette.resolve_fg(fg_attr),
+                    palette.resolve_bg(attrs.background()),
+                )
+            };
+            let coverage = if attrs.intensity() == Intensity::Half {
+                0.5
+            } else {
+                1.0
+            };
+
+            let left = cluster.first_cell_idx as f64 * cell_width;
+            let width = cluster.width as f64 * cell_width;
+            if bg != palette.background {
+                fill_rect(&mut image, left, top, width, cell_height, bg);
+            }
+
+            if !attrs.invisible() {
+                let font = fonts.resolve_font(fonts.match_style(config, attrs))?;
+                let glyphs = font.blocking_shape(
+                    &cluster.text,
+                    Some(cluster.presentation),
+                    cluster.direction,
+                    None,
+                    None,
+                )?;
+                for info in &glyphs {
+                    let glyph = font.rasterize_glyph(info.glyph_pos, info.font_idx)?;
+                    if glyph.width == 0 || glyph.height == 0 {
+                        continue;
+                    }
+                    let cell_idx = cluster.byte_to_cell_idx(info.cluster as usize);
+                    // Shrink glyphs that would otherwise spill over into
+                    // the cells that follow them, such as color emoji
+                    // that are rendered from a large bitmap strike
+                    let max_width = info.num_cells.max(1) as f64 * cell_width;
+                    let scale = if glyph.has_color {
+                        (cell_height / glyph.height as f64).min(max_width / glyph.width as f64)
+                    } else {
+                        max_width / glyph.width as f64
+                    }
+                    .min(1.0);
+                    let x = cell_idx as f64 * cell_width
+                        + (info.x_offset.get() + glyph.bearing_x.get()) * scale;
+                    let y = if glyph.has_color {
+                        top + (cell_height - glyph.height as f64 * scale) / 2.
+                    } else {
+                        top + baseline - (info.y_offset.get() + glyph.bearing_y.get())
+                    };
+                    draw_glyph(&mut image, &glyph, x, y, scale, fg, coverage);
+                }
+            }
+
+            let thickness = metrics.underline_thickness.get().max(1.);
+            let underline_top = top + baseline - metrics.underline_position.get();
+            match attrs.underline() {
+                Underline::None => {}
+                Underline::Double => {
+                    fill_rect(&mut image, left, underline_top, width, thickness, fg);
+                    fill_rect(
+                        &mut image,
+                        left,
+                        underline_top - thickness * 2.,
+                        width,
+                        thickness,
+                        fg,
+                    );
+                }
+                _ => fill_rect(&mut image, left, underline_top, width, thickness, fg),
+            }
+            if attrs.strikethrough() {
+                let strike_top = top + (baseline - thickness) / 2.;
+                fill_rect(&mut image, left, strike_top, width, thickness, fg);
+            }
+            if attrs.overline() {
+                fill_rect(&mut image, left, top, width, thickness, fg);
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+fn to_pixel(color: SrgbaTuple) -> Rgba<u8> {
+    let (r, g, b, _a) = color.as_rgba_u8();
+    Rgba([r, g, b, 0xff])
+}
+
+fn fill_rect(image: &mut RgbaImage, x: f64, y: f64, width: f64, height: f64, color: SrgbaTuple) {
+    let pixel = to_pixel(color);
+    let x_end = ((x + width) as u32).min(image.width());
+    let y_end = ((y + height) as u32).min(image.height());
+    for py in (y.max(0.) as u32)..y_end {
+        for px in (x.max(0.) as u32)..x_end {
+            image.put_pixel(px, py, pixel);
+        }
+    }
+}
+
+/// Composite `glyph` onto the image with its top left corner at `x`, `y`.
+/// Color glyphs hold pre-multiplied RGBA data that is drawn as-is,
+/// while the alpha channel of other glyphs is the coverage with
+/// which to draw `fg`.
+fn draw_glyph(
+    image: &mut RgbaImage,
+    glyph: &RasterizedGlyph,
+    x: f64,
+    y: f64,
+    scale: f64,
+    fg: SrgbaTuple,
+    coverage: f32,
+) {
+    let width = (glyph.width as f64 * scale).round() as i64;
+    let height = (glyph.height as f64 * scale).round() as i64;
+    let (fg_r, fg_g, fg_b, _) = fg.as_rgba_u8();
+    let (x, y) = (x.round() as i64, y.round() as i64);
+
+    for dy in 0..height {
+        let py = y + dy;
+        if py < 0 || py >= image.height() as i64 {
+            continue;
+        }
+        let src_y = ((dy as f64 / scale) as usize).min(glyph.height - 1);
+        for dx in 0..width {
+            let px = x + dx;
+            if px < 0 || px >= image.width() as i64 {
+                continue;
+            }
+            let src_x = ((dx as f64 / scale) as usize).min(glyph.width - 1);
+            let idx = (src_y * glyph.width + src_x) * 4;
+            let src = &glyph.data[idx..idx + 4];
+            let alpha = src[3] as f32 / 255. * coverage;
+            if alpha == 0. {
+                continue;
+            }
+
+            let dest = image.get_pixel_mut(px as u32, py as u32);
+            let source = if glyph.has_color {
+                [
+                    src[0] as f32 * coverage,
+                    src[1] as f32 * coverage,
+                    src[2] as f32 * coverage,
+                ]
+            } else {
+                [
+                    fg_r as f32 * alpha,
+                    fg_g as f32 * alpha,
+                    fg_b as f32 * alpha,
+                ]
+            };
+            for (channel, value) in source.iter().enumerate() {
+                dest[channel] = (value + dest[channel] as f32 * (1. - alpha)).clamp(0., 255.) as u8;
+            }
+        }
+    }
+}
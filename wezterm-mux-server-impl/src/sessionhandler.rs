@@ -6,10 +6,11 @@ use mux::client::ClientId;
 use mux::domain::SplitSource;
 use mux::pane::{CachePolicy, Pane, PaneId};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
+use mux::ssh::RemoteSshDomain;
 use mux::tab::TabId;
 use mux::{Mux, MuxNotification};
 use promise::spawn::spawn_into_main_thread;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use termwiz::surface::SequenceNo;
@@ -200,6 +201,15 @@ pub struct SessionHandler {
     per_pane: HashMap<TabId, Arc<Mutex<PerPane>>>,
     client_id: Option<Arc<ClientId>>,
     proxy_client_id: Option<ClientId>,
+    /// Files opened via SshTransferOpen that have yet to be closed
+    transfers: Vec<u64>,
+    /// Panes spawned via ExecCommand whose exit status has yet to be read.
+    /// Shared with the spawning future; None once the session has ended.
+    exec_panes: Arc<Mutex<Option<Vec<PaneId>>>>,
+    /// The names of the events requested via SubscribeToEvents
+    event_subscriptions: HashSet<String>,
+    /// The optional protocol features enabled via SetCodecCapabilities
+    codec_capabilities: CodecCapabilities,
 }
 
 impl Drop for SessionHandler {
@@ -208,6 +218,14 @@ impl Drop for SessionHandler {
             let mux = Mux::get();
             mux.unregister_client(&client_id);
         }
+        for transfer_id in self.transfers.drain(..) {
+            mux::ssh_transfer::discard(transfer_id);
+        }
+        if let Some(exec_panes) = self.exec_panes.lock().unwrap().take() {
+            for pane_id in exec_panes {
+                mux::exec::discard(pane_id);
+            }
+        }
     }
 }
 
@@ -218,6 +236,101 @@ impl SessionHandler {
             per_pane: HashMap::new(),
             client_id: None,
             proxy_client_id: None,
+            transfers: vec![],
+            exec_panes: Arc::new(Mutex::new(Some(vec![]))),
+            event_subscriptions: HashSet::new(),
+            codec_capabilities: CodecCapabilities::NONE,
+        }
+    }
+
+    /// The capabilities to use when encoding PDUs for this client
+    pub fn codec_capabilities(&self) -> CodecCapabilities {
+        self.codec_capabilities
+    }
+
+    /// If the client subscribed to the event corresponding to `notif`,
+    /// returns the MuxEventKind that describes it
+    pub fn mux_event(&self, notif: &MuxNotification) -> Option<MuxEventKind> {
+        if self.event_subscriptions.is_empty() {
+            return None;
+        }
+        let event = match notif {
+            MuxNotification::PaneOutput(pane_id) => MuxEventKind::PaneOutput { pane_id: *pane_id },
+            MuxNotification::PaneAdded(pane_id) => MuxEventKind::PaneAdded { pane_id: *pane_id },
+            MuxNotification::PaneRemoved(pane_id) => {
+                MuxEventKind::PaneRemoved { pane_id: *pane_id }
+            }
+            MuxNotification::PaneFocused(pane_id) => {
+                MuxEventKind::PaneFocused { pane_id: *pane_id }
+            }
+            MuxNotification::PaneExited { pane_id, status } => MuxEventKind::PaneExited {
+                pane_id: *pane_id,
+                exit_code: Some(status.exit_code()),
+                signal: status.signal().map(|s| s.to_string()),
+            },
+            MuxNotification::Alert {
+                pane_id,
+                alert: Alert::Bell,
+            } => MuxEventKind::Bell { pane_id: *pane_id },
+            MuxNotification::Alert {
+                pane_id,
+                alert: Alert::SetUserVar { name, value },
+            } => MuxEventKind::UserVar {
+                pane_id: *pane_id,
+                name: name.to_string(),
+                value: value.to_string(),
+            },
+            MuxNotification::PaneTitleChanged { pane_id, title } => {
+                MuxEventKind::PaneTitleChanged {
+                    pane_id: *pane_id,
+                    title: title.clone(),
+                }
+            }
+            MuxNotification::TabAddedToWindow { tab_id, window_id } => {
+                MuxEventKind::TabAddedToWindow {
+                    tab_id: *tab_id,
+                    window_id: *window_id,
+                }
+            }
+            MuxNotification::TabTitleChanged { tab_id, title } => MuxEventKind::TabTitleChanged {
+                tab_id: *tab_id,
+                title: title.to_string(),
+            },
+            MuxNotification::WindowCreated(window_id) => MuxEventKind::WindowCreated {
+                window_id: *window_id,
+            },
+            MuxNotification::WindowRemoved(window_id) => MuxEventKind::WindowRemoved {
+                window_id: *window_id,
+            },
+            MuxNotification::WindowTitleChanged { window_id, title } => {
+                MuxEventKind::WindowTitleChanged {
+                    window_id: *window_id,
+                    title: title.to_string(),
+                }
+            }
+            MuxNotification::WindowWorkspaceChanged(window_id) => {
+                let workspace = Mux::get()
+                    .get_window(*window_id)?
+                    .get_workspace()
+                    .to_string();
+                MuxEventKind::WindowWorkspaceChanged {
+                    window_id: *window_id,
+                    workspace,
+                }
+            }
+            MuxNotification::WorkspaceRenamed {
+                old_workspace,
+                new_workspace,
+            } => MuxEventKind::WorkspaceRenamed {
+                old_workspace: old_workspace.to_string(),
+                new_workspace: new_workspace.to_string(),
+            },
+            _ => return None,
+        };
+        if self.event_subscriptions.contains(event.name()) {
+            Some(event)
+        } else {
+            None
         }
     }
 
@@ -247,6 +360,7 @@ impl SessionHandler {
         let start = Instant::now();
         let sender = self.to_write_tx.clone();
         let serial = decoded.serial;
+        let pdu_name = decoded.pdu.pdu_name();
 
         if let Some(client_id) = &self.client_id {
             if decoded.pdu.is_user_input() {
@@ -261,7 +375,9 @@ impl SessionHandler {
                     reason: format!("Error: {err:#}"),
                 }),
             };
-            log::trace!("{} processing time {:?}", serial, start.elapsed());
+            let elapsed = start.elapsed();
+            log::trace!("{} processing time {:?}", serial, elapsed);
+            metrics::histogram!("sessionhandler.process_one", "pdu" => pdu_name).record(elapsed);
             sender.send(DecodedPdu { pdu, serial }).ok();
         };
 
@@ -384,6 +500,40 @@ impl SessionHandler {
                 })
                 .detach();
             }
+            Pdu::GetStats(GetStats {}) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            Ok(Pdu::GetStatsResponse(GetStatsResponse {
+                                stats: mux::stats::snapshot(),
+                            }))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+            Pdu::GetPaneScreenshot(GetPaneScreenshot { pane_id, dpi }) => {
+                async fn do_screenshot(pane_id: PaneId, dpi: Option<f64>) -> anyhow::Result<Pdu> {
+                    let mux = Mux::get();
+                    let pane = mux
+                        .get_pane(pane_id)
+                        .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+                    let png = crate::screenshot::screenshot(pane, dpi).await?;
+                    Ok(Pdu::GetPaneScreenshotResponse(GetPaneScreenshotResponse {
+                        png,
+                    }))
+                }
+
+                spawn_into_main_thread(async move {
+                    promise::spawn::spawn(async move {
+                        let result = do_screenshot(pane_id, dpi).await;
+                        send_response(result);
+                    })
+                    .detach();
+                })
+                .detach();
+            }
             Pdu::ListPanes(ListPanes {}) => {
                 spawn_into_main_thread(async move {
                     catch(
@@ -430,6 +580,46 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::SetActiveWorkspace(SetActiveWorkspace { workspace }) => {
+                let client_id = self.client_id.clone();
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            for info in mux.iter_clients() {
+                                if Some(&info.client_id) != client_id.as_ref() {
+                                    mux.set_active_workspace_for_client(
+                                        &info.client_id,
+                                        &workspace,
+                                    );
+                                }
+                            }
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    );
+                })
+                .detach();
+            }
+
+            Pdu::MoveTab(MoveTab {
+                tab_id,
+                window_id,
+                workspace,
+            }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            let window_id = mux.move_tab_to_window(tab_id, window_id, workspace)?;
+                            Ok(Pdu::MoveTabResponse(MoveTabResponse { window_id }))
+                        },
+                        send_response,
+                    );
+                })
+                .detach();
+            }
+
             Pdu::WriteToPane(WriteToPane { pane_id, data }) => {
                 let sender = self.to_write_tx.clone();
                 let per_pane = self.per_pane(pane_id);
@@ -865,6 +1055,15 @@ impl SessionHandler {
                 }
             }
 
+            Pdu::SetCodecCapabilities(SetCodecCapabilities { capabilities }) => {
+                self.codec_capabilities = capabilities.intersection(CodecCapabilities::supported());
+                send_response(Ok(Pdu::SetCodecCapabilitiesResponse(
+                    SetCodecCapabilitiesResponse {
+                        capabilities: self.codec_capabilities,
+                    },
+                )))
+            }
+
             Pdu::GetTlsCreds(_) => {
                 catch(
                     move || {
@@ -914,6 +1113,19 @@ impl SessionHandler {
                 })
                 .detach();
             }
+            Pdu::PaneTitleChanged(PaneTitleChanged { pane_id, title }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            mux.set_pane_title(pane_id, title)?;
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
             Pdu::SetPalette(SetPalette { pane_id, palette }) => {
                 spawn_into_main_thread(async move {
                     catch(
@@ -986,6 +1198,212 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::SshForward(SshForward { domain, spec }) => {
+                async fn do_ssh_forward(
+                    domain: String,
+                    spec: Option<String>,
+                ) -> anyhow::Result<Pdu> {
+                    let mux = Mux::get();
+                    let domain = mux
+                        .get_domain_by_name(&domain)
+                        .ok_or_else(|| anyhow!("no such domain {}", domain))?;
+                    let ssh_domain = domain.downcast_ref::<RemoteSshDomain>().ok_or_else(|| {
+                        anyhow!(
+                            "domain {} is not an ssh domain with multiplexing = \"None\"",
+                            domain.domain_name()
+                        )
+                    })?;
+                    if let Some(spec) = spec {
+                        ssh_domain.add_port_forward(&spec).await?;
+                    }
+                    Ok(Pdu::SshForwardResponse(SshForwardResponse {
+                        forwards: ssh_domain.port_forward_status(),
+                    }))
+                }
+
+                spawn_into_main_thread(async move {
+                    promise::spawn::spawn(async move {
+                        let result = do_ssh_forward(domain, spec).await;
+                        send_response(result);
+                    })
+                    .detach();
+                })
+                .detach();
+            }
+
+            Pdu::SshTransferOpen(SshTransferOpen {
+                domain,
+                path,
+                write,
+            }) => {
+                let transfer_id = mux::ssh_transfer::alloc_transfer_id();
+                self.transfers.push(transfer_id);
+
+                async fn do_ssh_transfer_open(
+                    transfer_id: u64,
+                    domain: String,
+                    path: String,
+                    write: bool,
+                ) -> anyhow::Result<Pdu> {
+                    let mux = Mux::get();
+                    let domain = mux
+                        .get_domain_by_name(&domain)
+                        .ok_or_else(|| anyhow!("no such domain {}", domain))?;
+                    let session = domain.ssh_session().ok_or_else(|| {
+                        anyhow!("domain {} is not connected via ssh", domain.domain_name())
+                    })?;
+                    let size = mux::ssh_transfer::open(transfer_id, &session, &path, write).await?;
+                    Ok(Pdu::SshTransferOpenResponse(SshTransferOpenResponse {
+                        transfer_id,
+                        size,
+                    }))
+                }
+
+                spawn_into_main_thread(async move {
+                    promise::spawn::spawn(async move {
+                        let result = do_ssh_transfer_open(transfer_id, domain, path, write).await;
+                        send_response(result);
+                    })
+                    .detach();
+                })
+                .detach();
+            }
+
+            Pdu::SshTransferWrite(SshTransferWrite { transfer_id, data }) => {
+                spawn_into_main_thread(async move {
+                    promise::spawn::spawn(async move {
+                        let result = mux::ssh_transfer::write(transfer_id, &data)
+                            .await
+                            .map(|_| Pdu::UnitResponse(UnitResponse {}));
+                        send_response(result);
+                    })
+                    .detach();
+                })
+                .detach();
+            }
+
+            Pdu::SshTransferRead(SshTransferRead {
+                transfer_id,
+                max_len,
+            }) => {
+                spawn_into_main_thread(async move {
+                    promise::spawn::spawn(async move {
+                        let result =
+                            mux::ssh_transfer::read(transfer_id, max_len)
+                                .await
+                                .map(|data| {
+                                    Pdu::SshTransferReadResponse(SshTransferReadResponse { data })
+                                });
+                        send_response(result);
+                    })
+                    .detach();
+                })
+                .detach();
+            }
+
+            Pdu::SshTransferClose(SshTransferClose { transfer_id }) => {
+                self.transfers.retain(|id| *id != transfer_id);
+                spawn_into_main_thread(async move {
+                    promise::spawn::spawn(async move {
+                        let result = mux::ssh_transfer::close(transfer_id)
+                            .await
+                            .map(|_| Pdu::UnitResponse(UnitResponse {}));
+                        send_response(result);
+                    })
+                    .detach();
+                })
+                .detach();
+            }
+
+            Pdu::WaitForPaneExit(WaitForPaneExit { pane_id }) => {
+                spawn_into_main_thread(async move {
+                    promise::spawn::spawn(async move {
+                        let mux = Mux::get();
+                        let result = mux.wait_for_pane_exit(pane_id).await.map(|status| {
+                            Pdu::WaitForPaneExitResponse(WaitForPaneExitResponse {
+                                exit_code: status.as_ref().map(|s| s.exit_code()),
+                                signal: status.and_then(|s| s.signal().map(|s| s.to_string())),
+                            })
+                        });
+                        send_response(result);
+                    })
+                    .detach();
+                })
+                .detach();
+            }
+
+            Pdu::ExecCommand(ExecCommand {
+                domain,
+                command,
+                command_dir,
+                size,
+            }) => {
+                let exec_panes = Arc::clone(&self.exec_panes);
+                spawn_into_main_thread(async move {
+                    promise::spawn::spawn(async move {
+                        let result = mux::exec::spawn(&domain, command, command_dir, size)
+                            .await
+                            .map(|pane_id| {
+                                // Kill the command if this client goes away
+                                // before it has read the exit status
+                                match exec_panes.lock().unwrap().as_mut() {
+                                    Some(exec_panes) => exec_panes.push(pane_id),
+                                    None => mux::exec::discard(pane_id),
+                                }
+                                Pdu::ExecCommandResponse(ExecCommandResponse { pane_id })
+                            });
+                        send_response(result);
+                    })
+                    .detach();
+                })
+                .detach();
+            }
+
+            Pdu::ReadExecOutput(ReadExecOutput { pane_id, max_len }) => {
+                let exec_panes = Arc::clone(&self.exec_panes);
+                spawn_into_main_thread(async move {
+                    promise::spawn::spawn(async move {
+                        let result = mux::exec::read(pane_id, max_len).await.map(|output| {
+                            let finished = output.status.is_some();
+                            if finished {
+                                if let Some(exec_panes) = exec_panes.lock().unwrap().as_mut() {
+                                    exec_panes.retain(|id| *id != pane_id);
+                                }
+                            }
+                            Pdu::ReadExecOutputResponse(ReadExecOutputResponse {
+                                data: output.data,
+                                finished,
+                                exit_code: output.status.as_ref().map(|s| s.exit_code()),
+                                signal: output
+                                    .status
+                                    .and_then(|s| s.signal().map(|s| s.to_string())),
+                            })
+                        });
+                        send_response(result);
+                    })
+                    .detach();
+                })
+                .detach();
+            }
+
+            Pdu::SubscribeToEvents(SubscribeToEvents { events }) => {
+                let unknown: Vec<&str> = events
+                    .iter()
+                    .map(|name| name.as_str())
+                    .filter(|name| !MuxEventKind::NAMES.contains(name))
+                    .collect();
+                if unknown.is_empty() {
+                    self.event_subscriptions = events.into_iter().collect();
+                    send_response(Ok(Pdu::UnitResponse(UnitResponse {})));
+                } else {
+                    send_response(Err(anyhow!(
+                        "unknown event(s) {}; valid events are {}",
+                        unknown.join(", "),
+                        MuxEventKind::NAMES.join(", ")
+                    )));
+                }
+            }
+
             Pdu::Invalid { .. } => send_response(Err(anyhow!("invalid PDU {:?}", decoded.pdu))),
             Pdu::Pong { .. }
             | Pdu::ListPanesResponse { .. }
@@ -996,17 +1414,28 @@ impl SessionHandler {
             | Pdu::UnitResponse { .. }
             | Pdu::LivenessResponse { .. }
             | Pdu::GetPaneDirectionResponse { .. }
+            | Pdu::SshForwardResponse { .. }
+            | Pdu::SshTransferOpenResponse { .. }
+            | Pdu::SshTransferReadResponse { .. }
+            | Pdu::WaitForPaneExitResponse { .. }
+            | Pdu::MuxEvent { .. }
             | Pdu::SearchScrollbackResponse { .. }
             | Pdu::GetLinesResponse { .. }
             | Pdu::GetCodecVersionResponse { .. }
+            | Pdu::SetCodecCapabilitiesResponse { .. }
             | Pdu::WindowWorkspaceChanged { .. }
             | Pdu::GetTlsCredsResponse { .. }
             | Pdu::GetClientListResponse { .. }
+            | Pdu::GetStatsResponse { .. }
+            | Pdu::GetPaneScreenshotResponse { .. }
             | Pdu::PaneRemoved { .. }
             | Pdu::PaneFocused { .. }
             | Pdu::TabResized { .. }
             | Pdu::GetImageCellResponse { .. }
             | Pdu::MovePaneToNewTabResponse { .. }
+            | Pdu::MoveTabResponse { .. }
+            | Pdu::ExecCommandResponse { .. }
+            | Pdu::ReadExecOutputResponse { .. }
             | Pdu::TabAddedToWindow { .. }
             | Pdu::GetPaneRenderableDimensionsResponse { .. }
             | Pdu::ErrorResponse { .. } => {
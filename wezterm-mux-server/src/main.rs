@@ -75,7 +75,7 @@ fn main() {
 fn run() -> anyhow::Result<()> {
     env_bootstrap::bootstrap();
 
-    //stats::Stats::init()?;
+    mux::stats::Stats::init()?;
     config::designate_this_as_the_main_thread();
     let _saver = umask::UmaskSaver::new();
 
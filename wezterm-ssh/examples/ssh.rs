@@ -115,6 +115,9 @@ fn main() {
                 SessionEvent::HostVerificationFailed(failed) => {
                     anyhow::bail!("{}", failed);
                 }
+                SessionEvent::SecurityKeyTouch(touch) => {
+                    eprintln!("{}", touch.message());
+                }
                 SessionEvent::Error(err) => {
                     anyhow::bail!("{}", err);
                 }
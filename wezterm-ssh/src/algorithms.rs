@@ -0,0 +1,197 @@
+//! Resolve the algorithm preference lists from ssh_config(5), such
+//! as `Ciphers` and `KexAlgorithms`, against the algorithms that
+//! are supported by the ssh backend.
+use crate::config::wildcard_to_pattern;
+use anyhow::Context;
+use regex::Regex;
+
+#[cfg_attr(not(feature = "ssh2"), allow(dead_code))]
+fn compile_patterns(names: &[&str]) -> anyhow::Result<Vec<Regex>> {
+    names
+        .iter()
+        .map(|name| {
+            let (pattern, _) = wildcard_to_pattern(name);
+            Regex::new(&pattern).with_context(|| format!("compiling pattern {name}"))
+        })
+        .collect()
+}
+
+/// Resolve an ssh_config style algorithm list `spec` for the option
+/// `option` into a comma separated preference list, given the backend's
+/// `defaults`, in their order of preference, and the full list of
+/// `supported` algorithms.
+///
+/// As with OpenSSH, the list may be prefixed with `+` to append the
+/// listed algorithms to the defaults, `-` to remove the matching
+/// algorithms from the defaults or `^` to move the listed algorithms
+/// to the front of the defaults.
+/// Algorithms that aren't supported are skipped, but it is an
+/// error for the resulting list to be empty.
+#[cfg_attr(not(feature = "ssh2"), allow(dead_code))]
+pub(crate) fn resolve_algorithms(
+    option: &str,
+    spec: &str,
+    defaults: &[&str],
+    supported: &[&str],
+) -> anyhow::Result<String> {
+    let spec = spec.trim();
+    let (modifier, list) = match spec.chars().next() {
+        Some(c @ ('+' | '-' | '^')) => (Some(c), &spec[1..]),
+        _ => (None, spec),
+    };
+    let names: Vec<&str> = list
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    let patterns = compile_patterns(&names)?;
+
+    if modifier != Some('-') {
+        // Removing an algorithm that doesn't exist is harmless, but
+        // asking to use one that we don't have is worth mentioning.
+        // Such algorithms are skipped, so that an ssh_config file
+        // written for a newer version of OpenSSH remains usable.
+        for (name, pattern) in names.iter().zip(patterns.iter()) {
+            if !supported.iter().any(|alg| pattern.is_match(alg)) {
+                log::warn!("{option}: ignoring unsupported algorithm {name}");
+            }
+        }
+    }
+
+    let matches = |alg: &str| patterns.iter().any(|p| p.is_match(alg));
+
+    // The supported algorithms that are listed, in the order
+    // in which they are listed
+    let listed = || {
+        let mut listed = vec![];
+        for pattern in &patterns {
+            for alg in supported {
+                if pattern.is_match(alg) && !listed.contains(alg) {
+                    listed.push(*alg);
+                }
+            }
+        }
+        listed
+    };
+
+    let result: Vec<&str> = match modifier {
+        Some('+') => {
+            let mut result = defaults.to_vec();
+            for alg in listed() {
+                if !result.contains(&alg) {
+                    result.push(alg);
+                }
+            }
+            result
+        }
+        Some('-') => defaults
+            .iter()
+            .copied()
+            .filter(|alg| !matches(alg))
+            .collect(),
+        Some('^') => {
+            let mut result = listed();
+            for alg in defaults {
+                if !result.contains(alg) {
+                    result.push(*alg);
+                }
+            }
+            result
+        }
+        None => listed(),
+        Some(_) => unreachable!(),
+    };
+
+    if result.is_empty() {
+        anyhow::bail!(
+            "{option}: {spec} leaves no supported algorithms to use. \
+             Supported algorithms are: {}",
+            supported.join(",")
+        );
+    }
+
+    Ok(result.join(","))
+}
+
+/// Parse the value of the `Compression` option
+pub(crate) fn compression_enabled(value: Option<&String>) -> anyhow::Result<bool> {
+    match value.map(|s| s.to_lowercase()).as_deref() {
+        None | Some("no") => Ok(false),
+        Some("yes") => Ok(true),
+        Some(other) => anyhow::bail!("Compression: expected yes or no, got {other}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DEFAULTS: &[&str] = &["aes256-ctr", "aes192-ctr", "aes128-ctr", "aes256-cbc"];
+    const SUPPORTED: &[&str] = &[
+        "aes256-ctr",
+        "aes192-ctr",
+        "aes128-ctr",
+        "aes256-cbc",
+        "3des-cbc",
+        "blowfish-cbc",
+    ];
+
+    fn resolve(spec: &str) -> anyhow::Result<String> {
+        resolve_algorithms("ciphers", spec, DEFAULTS, SUPPORTED)
+    }
+
+    #[test]
+    fn algorithm_lists() {
+        assert_eq!(
+            resolve("aes128-ctr,3des-cbc").unwrap(),
+            "aes128-ctr,3des-cbc"
+        );
+        assert_eq!(
+            resolve("-*-cbc,cast128-cbc").unwrap(),
+            "aes256-ctr,aes192-ctr,aes128-ctr"
+        );
+        assert_eq!(
+            resolve("^3des-cbc,aes128-ctr").unwrap(),
+            "3des-cbc,aes128-ctr,aes256-ctr,aes192-ctr,aes256-cbc"
+        );
+        assert_eq!(resolve("cast128-cbc,3des-cbc").unwrap(), "3des-cbc");
+        assert_eq!(
+            resolve("cast128-cbc").unwrap_err().to_string(),
+            "ciphers: cast128-cbc leaves no supported algorithms to use. \
+             Supported algorithms are: \
+             aes256-ctr,aes192-ctr,aes128-ctr,aes256-cbc,3des-cbc,blowfish-cbc"
+        );
+        assert!(resolve("-aes*").is_err());
+    }
+
+    #[test]
+    fn append_to_defaults() {
+        assert_eq!(
+            resolve("+3des-cbc").unwrap(),
+            "aes256-ctr,aes192-ctr,aes128-ctr,aes256-cbc,3des-cbc"
+        );
+        // Listed algorithms are appended in the order that they are
+        // listed, skipping those that are already in the defaults
+        assert_eq!(
+            resolve("+blowfish-cbc,aes128-ctr,3des-cbc").unwrap(),
+            "aes256-ctr,aes192-ctr,aes128-ctr,aes256-cbc,blowfish-cbc,3des-cbc"
+        );
+        assert_eq!(
+            resolve("+*-cbc").unwrap(),
+            "aes256-ctr,aes192-ctr,aes128-ctr,aes256-cbc,3des-cbc,blowfish-cbc"
+        );
+        assert_eq!(
+            resolve("+cast128-cbc").unwrap(),
+            "aes256-ctr,aes192-ctr,aes128-ctr,aes256-cbc"
+        );
+    }
+
+    #[test]
+    fn compression() {
+        assert!(!compression_enabled(None).unwrap());
+        assert!(compression_enabled(Some(&"yes".to_string())).unwrap());
+        assert!(!compression_enabled(Some(&"no".to_string())).unwrap());
+        assert!(compression_enabled(Some(&"maybe".to_string())).is_err());
+    }
+}
@@ -26,7 +26,77 @@ impl AuthenticationEvent {
     }
 }
 
+/// Sent just before we attempt to authenticate using a FIDO2/U2F
+/// security key, as the authenticator will typically block until
+/// the user physically touches it.
+#[derive(Debug)]
+pub struct SecurityKeyTouchEvent {
+    pub username: String,
+    /// The path or agent comment that identifies the key
+    pub identity: String,
+    /// The ssh key type, eg: `sk-ssh-ed25519@openssh.com`
+    pub key_type: String,
+}
+
+impl SecurityKeyTouchEvent {
+    pub fn message(&self) -> String {
+        format!(
+            "Confirm user presence for {} key {} by touching your security key",
+            self.key_type, self.identity
+        )
+    }
+}
+
+/// Returns true if the key type is one of the FIDO2/U2F backed key
+/// types, such as `sk-ssh-ed25519@openssh.com` or
+/// `sk-ecdsa-sha2-nistp256@openssh.com`, or a certificate for one.
+pub fn is_security_key_type(key_type: &str) -> bool {
+    key_type.starts_with("sk-")
+}
+
+/// Extracts the key type from an ssh wire format public key blob.
+/// The blob begins with a u32 big endian length prefixed key type string.
+pub fn key_type_from_public_key_blob(blob: &[u8]) -> Option<&str> {
+    let len = match blob.get(0..4)? {
+        &[a, b, c, d] => u32::from_be_bytes([a, b, c, d]) as usize,
+        _ => return None,
+    };
+    let key_type = blob.get(4..4usize.checked_add(len)?)?;
+    std::str::from_utf8(key_type).ok()
+}
+
+/// Extracts the key type from the contents of an OpenSSH `.pub` file,
+/// which has the form `<key-type> <base64-blob> [comment]`.
+pub fn key_type_from_public_key_text(text: &str) -> Option<&str> {
+    text.lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#'))?
+        .split_whitespace()
+        .next()
+}
+
+/// Returns the security key type of the identity file, if its associated
+/// `.pub` file indicates that it is a FIDO2/U2F backed key.
+pub(crate) fn security_key_type_for_identity(file: &str) -> Option<String> {
+    let text = std::fs::read_to_string(format!("{}.pub", file)).ok()?;
+    key_type_from_public_key_text(&text)
+        .filter(|key_type| is_security_key_type(key_type))
+        .map(|key_type| key_type.to_string())
+}
+
 impl crate::sessioninner::SessionInner {
+    fn notify_security_key_touch(&self, username: &str, identity: &str, key_type: &str) {
+        let event = SecurityKeyTouchEvent {
+            username: username.to_string(),
+            identity: identity.to_string(),
+            key_type: key_type.to_string(),
+        };
+        log::info!("{}", event.message());
+        self.tx_event
+            .try_send(SessionEvent::SecurityKeyTouch(event))
+            .ok();
+    }
+
     #[cfg(feature = "ssh2")]
     fn agent_auth(&mut self, sess: &ssh2::Session, user: &str) -> anyhow::Result<bool> {
         if let Some(only) = self.config.get("identitiesonly") {
@@ -45,6 +115,14 @@ impl crate::sessioninner::SessionInner {
         agent.list_identities()?;
         let identities = agent.identities()?;
         for identity in identities {
+            // Resident security keys are discovered by loading them into
+            // the agent (eg: `ssh-add -K`), which will then ask the
+            // authenticator to sign on our behalf.
+            if let Some(key_type) = key_type_from_public_key_blob(identity.blob())
+                .filter(|key_type| is_security_key_type(key_type))
+            {
+                self.notify_security_key_touch(user, identity.comment(), key_type);
+            }
             if agent.userauth(user, &identity).is_ok() {
                 return Ok(true);
             }
@@ -53,6 +131,57 @@ impl crate::sessioninner::SessionInner {
         Ok(false)
     }
 
+    /// Returns the certificates to offer along with the identity `file`,
+    /// whose public key is `pubkey`: `<file>-cert.pub` and any
+    /// `CertificateFile` that certifies the same key.
+    /// Certificates that aren't currently valid are skipped, as the
+    /// server would reject them anyway.
+    #[cfg(feature = "ssh2")]
+    fn certificates_for_identity(
+        &self,
+        file: &std::path::Path,
+        pubkey: &std::path::Path,
+    ) -> Vec<std::path::PathBuf> {
+        use crate::certificate::{decode_public_key_text, now, Certificate};
+        use std::path::PathBuf;
+
+        let public_key = std::fs::read_to_string(pubkey)
+            .ok()
+            .and_then(|text| decode_public_key_text(&text));
+        let implicit: PathBuf = format!("{}-cert.pub", file.display()).into();
+        let mut candidates = vec![implicit.clone()];
+        if let Some(files) = self.config.get("certificatefile") {
+            candidates.extend(files.split_whitespace().map(PathBuf::from));
+        }
+
+        let mut certificates = vec![];
+        for candidate in candidates {
+            if !candidate.exists() || certificates.contains(&candidate) {
+                continue;
+            }
+            let cert = match Certificate::load(&candidate) {
+                Ok(cert) => cert,
+                Err(err) => {
+                    log::warn!("{:#}", err);
+                    continue;
+                }
+            };
+            let certifies_identity = match &public_key {
+                Some(key) => *key == cert.public_key,
+                None => candidate == implicit,
+            };
+            if !certifies_identity || !cert.is_user_certificate() {
+                continue;
+            }
+            if let Err(err) = cert.check_validity(now()) {
+                log::warn!("Not using {}: {:#}", candidate.display(), err);
+                continue;
+            }
+            certificates.push(candidate);
+        }
+        certificates
+    }
+
     #[cfg(feature = "ssh2")]
     fn pubkey_auth(
         &mut self,
@@ -71,14 +200,46 @@ impl crate::sessioninner::SessionInner {
                     continue;
                 }
 
-                let pubkey = if pubkey.exists() {
+                if let Some(key_type) = security_key_type_for_identity(&file.to_string_lossy()) {
+                    log::warn!(
+                        "Skipping {} because it is a {} security key; \
+                         the ssh2 backend can only use security keys that \
+                         are loaded into ssh-agent. Use the libssh backend \
+                         or `ssh-add` the key instead.",
+                        file.display(),
+                        key_type
+                    );
+                    continue;
+                }
+
+                // Offer any certificates for the key before the plain key
+                let certificates = self.certificates_for_identity(file, &pubkey);
+                let mut pubkeys: Vec<Option<&Path>> = certificates
+                    .iter()
+                    .map(|cert| Some(cert.as_path()))
+                    .collect();
+                pubkeys.push(if pubkey.exists() {
                     Some(pubkey.as_ref())
                 } else {
                     None
+                });
+
+                let try_pubkeys = |passphrase: Option<&str>| -> Result<(), ssh2::Error> {
+                    let mut result = Ok(());
+                    for pubkey in &pubkeys {
+                        result = sess.userauth_pubkey_file(user, *pubkey, &file, passphrase);
+                        if result.is_ok() {
+                            if let Some(pubkey) = pubkey {
+                                log::info!("authenticated using {}", pubkey.display());
+                            }
+                            break;
+                        }
+                    }
+                    result
                 };
 
                 // We try with no passphrase first, in case the key is unencrypted
-                match sess.userauth_pubkey_file(user, pubkey, &file, None) {
+                match try_pubkeys(None) {
                     Ok(_) => {
                         log::info!("pubkey_file immediately ok for {}", file.display());
                         return Ok(true);
@@ -113,7 +274,7 @@ impl crate::sessioninner::SessionInner {
 
                         let passphrase = &answers[0];
 
-                        match sess.userauth_pubkey_file(user, pubkey, &file, Some(passphrase)) {
+                        match try_pubkeys(Some(passphrase)) {
                             Ok(_) => {
                                 return Ok(true);
                             }
@@ -128,6 +289,85 @@ impl crate::sessioninner::SessionInner {
         Ok(false)
     }
 
+    /// Tries each security key identity file in turn.  These are not
+    /// added to the libssh identities (see `run_impl_libssh`) so that
+    /// the user is only asked to touch the authenticator once the server
+    /// has indicated that it will accept that particular key.
+    #[cfg(feature = "libssh-rs")]
+    fn security_key_auth_libssh(
+        &mut self,
+        sess: &libssh_rs::Session,
+    ) -> anyhow::Result<Option<libssh_rs::AuthStatus>> {
+        use libssh_rs::{AuthStatus, SshKey};
+
+        let files = match self.config.get("identityfile") {
+            Some(files) => files.clone(),
+            None => return Ok(None),
+        };
+        let username = sess.get_user_name()?;
+
+        for file in files.split_whitespace() {
+            let key_type = match security_key_type_for_identity(file) {
+                Some(key_type) => key_type,
+                None => continue,
+            };
+
+            let key = match SshKey::from_privkey_file(file, None) {
+                Ok(key) => key,
+                Err(_) => {
+                    // Most likely the key handle is encrypted, so prompt
+                    // the user for the passphrase.
+                    let (reply, answers) = bounded(1);
+                    self.tx_event
+                        .try_send(SessionEvent::Authenticate(AuthenticationEvent {
+                            username: "".to_string(),
+                            instructions: "".to_string(),
+                            prompts: vec![AuthenticationPrompt {
+                                prompt: format!("Passphrase to decrypt {}:\n> ", file),
+                                echo: false,
+                            }],
+                            reply,
+                        }))
+                        .context("sending Authenticate request to user")?;
+
+                    let answers = smol::block_on(answers.recv())
+                        .context("waiting for authentication answers from user")?;
+                    if answers.is_empty() {
+                        anyhow::bail!("user cancelled authentication");
+                    }
+
+                    match SshKey::from_privkey_file(file, Some(&answers[0])) {
+                        Ok(key) => key,
+                        Err(err) => {
+                            log::warn!("loading security key {}: {:#}", file, err);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            match sess.userauth_try_publickey(None, &key)? {
+                AuthStatus::Success => {}
+                status => {
+                    log::trace!("server won't accept {}: {:?}", file, status);
+                    continue;
+                }
+            }
+
+            self.notify_security_key_touch(&username, file, &key_type);
+            match sess.userauth_publickey(None, &key)? {
+                status @ (AuthStatus::Success | AuthStatus::Partial) => {
+                    return Ok(Some(status));
+                }
+                status => {
+                    log::warn!("security key auth with {}: {:?}", file, status);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
     #[cfg(feature = "libssh-rs")]
     pub fn authenticate_libssh(&mut self, sess: &libssh_rs::Session) -> anyhow::Result<()> {
         use std::collections::HashMap;
@@ -167,6 +407,12 @@ impl crate::sessioninner::SessionInner {
             let mut status_by_method = HashMap::new();
 
             if auth_methods.contains(AuthMethods::PUBLIC_KEY) {
+                match self.security_key_auth_libssh(sess)? {
+                    Some(AuthStatus::Success) => return Ok(()),
+                    Some(AuthStatus::Partial) => continue,
+                    _ => {}
+                }
+
                 match sess.userauth_public_key_auto(None, None)? {
                     AuthStatus::Success => return Ok(()),
                     AuthStatus::Partial => continue,
@@ -363,3 +609,44 @@ impl crate::sessioninner::SessionInner {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn security_key_types() {
+        assert!(is_security_key_type("sk-ssh-ed25519@openssh.com"));
+        assert!(is_security_key_type("sk-ecdsa-sha2-nistp256@openssh.com"));
+        assert!(is_security_key_type("sk-ssh-ed25519-cert-v01@openssh.com"));
+        assert!(!is_security_key_type("ssh-ed25519"));
+        assert!(!is_security_key_type("ecdsa-sha2-nistp256"));
+    }
+
+    #[test]
+    fn key_type_from_blob() {
+        let mut blob = vec![];
+        let key_type = b"sk-ssh-ed25519@openssh.com";
+        blob.extend_from_slice(&(key_type.len() as u32).to_be_bytes());
+        blob.extend_from_slice(key_type);
+        blob.extend_from_slice(&[0, 0, 0, 32]);
+        assert_eq!(
+            key_type_from_public_key_blob(&blob),
+            Some("sk-ssh-ed25519@openssh.com")
+        );
+
+        assert_eq!(key_type_from_public_key_blob(&[0, 0, 0, 42, b's']), None);
+        assert_eq!(key_type_from_public_key_blob(&[0, 0]), None);
+    }
+
+    #[test]
+    fn key_type_from_text() {
+        assert_eq!(
+            key_type_from_public_key_text(
+                "sk-ecdsa-sha2-nistp256@openssh.com AAAAInNr user@host\n"
+            ),
+            Some("sk-ecdsa-sha2-nistp256@openssh.com")
+        );
+        assert_eq!(key_type_from_public_key_text("\n\n"), None);
+    }
+}
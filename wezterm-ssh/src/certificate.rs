@@ -0,0 +1,608 @@
+//! OpenSSH certificates, as described in PROTOCOL.certkeys in the
+//! OpenSSH distribution.
+//! We parse them for ourselves so that host certificates can be checked
+//! against the `@cert-authority` entries in the known hosts files, and
+//! so that expired user certificates aren't offered to the server.
+#![cfg_attr(not(feature = "ssh2"), allow(dead_code))]
+use crate::config::wildcard_to_pattern;
+use anyhow::{anyhow, Context};
+use base64::Engine;
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey};
+use openssl::rsa::Rsa;
+use openssl::sign::{Signer, Verifier};
+use regex::Regex;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CERT_SUFFIX: &str = "-cert-v01@openssh.com";
+const SSH_CERT_TYPE_USER: u32 = 1;
+const SSH_CERT_TYPE_HOST: u32 = 2;
+
+/// Returns true if the key `blob` is a certificate, rather than a plain key
+pub(crate) fn is_certificate(blob: &[u8]) -> bool {
+    Reader::new(blob)
+        .read_str()
+        .map(|key_type| key_type.ends_with(CERT_SUFFIX))
+        .unwrap_or(false)
+}
+
+/// The current time in the form used by the validity period of a certificate
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Decodes the blob from a public key in the `<key-type> <base64-blob> [comment]`
+/// form used by `.pub` files and known hosts entries
+pub(crate) fn decode_public_key_text(text: &str) -> Option<Vec<u8>> {
+    let blob = text.split_whitespace().nth(1)?;
+    base64::engine::general_purpose::STANDARD.decode(blob).ok()
+}
+
+/// Reads the ssh wire encoding
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| anyhow!("truncated data"))?;
+        let result = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(result)
+    }
+
+    fn read_u32(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_u64(&mut self) -> anyhow::Result<u64> {
+        let hi = self.read_u32()? as u64;
+        let lo = self.read_u32()? as u64;
+        Ok((hi << 32) | lo)
+    }
+
+    /// Reads a `string`; an `mpint` has the same encoding
+    fn read_string(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    fn read_str(&mut self) -> anyhow::Result<String> {
+        let bytes = self.read_string()?;
+        Ok(std::str::from_utf8(bytes)
+            .context("invalid utf8 in string")?
+            .to_string())
+    }
+}
+
+fn encode_string(data: &[u8]) -> Vec<u8> {
+    let mut result = (data.len() as u32).to_be_bytes().to_vec();
+    result.extend_from_slice(data);
+    result
+}
+
+#[derive(Debug)]
+pub(crate) struct Certificate {
+    /// The blob of the plain key that is certified
+    pub public_key: Vec<u8>,
+    pub cert_type: u32,
+    pub key_id: String,
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    critical_options: Vec<u8>,
+    /// The blob of the key of the certificate authority
+    pub signature_key: Vec<u8>,
+    signature: Vec<u8>,
+    /// The portion of the certificate covered by the signature
+    signed_data: Vec<u8>,
+}
+
+impl Certificate {
+    pub fn parse(blob: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(blob);
+        let key_type = reader.read_str()?;
+        let base_type = key_type
+            .strip_suffix(CERT_SUFFIX)
+            .ok_or_else(|| anyhow!("{key_type} is not a certificate"))?;
+        // The security key types have the suffix on the plain key
+        // type name, but not on the certificate type name
+        let base_type = if base_type.starts_with("sk-") {
+            format!("{base_type}@openssh.com")
+        } else {
+            base_type.to_string()
+        };
+
+        let _nonce = reader.read_string()?;
+
+        let key_start = reader.pos;
+        let num_fields = match base_type.as_str() {
+            "ssh-ed25519" => 1,
+            "ssh-rsa" => 2,
+            "ecdsa-sha2-nistp256" | "ecdsa-sha2-nistp384" | "ecdsa-sha2-nistp521" => 2,
+            "sk-ssh-ed25519@openssh.com" => 2,
+            "sk-ecdsa-sha2-nistp256@openssh.com" => 3,
+            "ssh-dss" => 4,
+            _ => anyhow::bail!("unsupported certificate type {key_type}"),
+        };
+        for _ in 0..num_fields {
+            reader.read_string()?;
+        }
+        let mut public_key = encode_string(base_type.as_bytes());
+        public_key.extend_from_slice(&blob[key_start..reader.pos]);
+
+        let _serial = reader.read_u64()?;
+        let cert_type = reader.read_u32()?;
+        let key_id = reader.read_str()?;
+
+        let mut principals = vec![];
+        let mut principal_reader = Reader::new(reader.read_string()?);
+        while !principal_reader.is_empty() {
+            principals.push(principal_reader.read_str()?);
+        }
+
+        let valid_after = reader.read_u64()?;
+        let valid_before = reader.read_u64()?;
+        let critical_options = reader.read_string()?.to_vec();
+        let _extensions = reader.read_string()?;
+        let _reserved = reader.read_string()?;
+        let signature_key = reader.read_string()?.to_vec();
+        let signed_data = blob[..reader.pos].to_vec();
+        let signature = reader.read_string()?.to_vec();
+
+        Ok(Self {
+            public_key,
+            cert_type,
+            key_id,
+            principals,
+            valid_after,
+            valid_before,
+            critical_options,
+            signature_key,
+            signature,
+            signed_data,
+        })
+    }
+
+    /// Parses a certificate from the text of a `-cert.pub` file
+    pub fn from_public_key_text(text: &str) -> anyhow::Result<Self> {
+        let blob =
+            decode_public_key_text(text).ok_or_else(|| anyhow!("invalid public key text"))?;
+        Self::parse(&blob)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_public_key_text(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Checks that the certificate authority signed this certificate
+    pub fn verify_signature(&self) -> anyhow::Result<()> {
+        let mut key = Reader::new(&self.signature_key);
+        let ca_key_type = key.read_str()?;
+        let mut signature = Reader::new(&self.signature);
+        let sig_type = signature.read_str()?;
+        let sig = signature.read_string()?;
+
+        let valid = match ca_key_type.as_str() {
+            "ssh-ed25519" => {
+                anyhow::ensure!(
+                    sig_type == ca_key_type,
+                    "{sig_type} signature from {ca_key_type} key"
+                );
+                let pkey = PKey::public_key_from_raw_bytes(key.read_string()?, Id::ED25519)?;
+                let mut verifier = Verifier::new_without_digest(&pkey)?;
+                verifier.verify_oneshot(sig, &self.signed_data)?
+            }
+            "ssh-rsa" => {
+                let digest = match sig_type.as_str() {
+                    "rsa-sha2-512" => MessageDigest::sha512(),
+                    "rsa-sha2-256" => MessageDigest::sha256(),
+                    "ssh-rsa" => MessageDigest::sha1(),
+                    _ => anyhow::bail!("{sig_type} signature from {ca_key_type} key"),
+                };
+                let e = BigNum::from_slice(key.read_string()?)?;
+                let n = BigNum::from_slice(key.read_string()?)?;
+                let pkey = PKey::from_rsa(Rsa::from_public_components(n, e)?)?;
+                let mut verifier = Verifier::new(digest, &pkey)?;
+                verifier.update(&self.signed_data)?;
+                verifier.verify(sig)?
+            }
+            "ecdsa-sha2-nistp256" | "ecdsa-sha2-nistp384" | "ecdsa-sha2-nistp521" => {
+                anyhow::ensure!(
+                    sig_type == ca_key_type,
+                    "{sig_type} signature from {ca_key_type} key"
+                );
+                let (nid, digest) = match ca_key_type.as_str() {
+                    "ecdsa-sha2-nistp256" => (Nid::X9_62_PRIME256V1, MessageDigest::sha256()),
+                    "ecdsa-sha2-nistp384" => (Nid::SECP384R1, MessageDigest::sha384()),
+                    _ => (Nid::SECP521R1, MessageDigest::sha512()),
+                };
+                let _curve = key.read_string()?;
+                let group = EcGroup::from_curve_name(nid)?;
+                let mut ctx = BigNumContext::new()?;
+                let point = EcPoint::from_bytes(&group, key.read_string()?, &mut ctx)?;
+                let ec_key = EcKey::from_public_key(&group, &point)?;
+
+                let mut rs = Reader::new(sig);
+                let r = BigNum::from_slice(rs.read_string()?)?;
+                let s = BigNum::from_slice(rs.read_string()?)?;
+                let hash = openssl::hash::hash(digest, &self.signed_data)?;
+                EcdsaSig::from_private_components(r, s)?.verify(&hash, &ec_key)?
+            }
+            _ => anyhow::bail!("unsupported certificate authority key type {ca_key_type}"),
+        };
+
+        anyhow::ensure!(valid, "the certificate signature is not valid");
+        Ok(())
+    }
+
+    /// Checks that `now` is within the validity period of the certificate
+    pub fn check_validity(&self, now: u64) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            now >= self.valid_after,
+            "certificate {} is not yet valid",
+            self.key_id
+        );
+        anyhow::ensure!(
+            now < self.valid_before,
+            "certificate {} has expired",
+            self.key_id
+        );
+        Ok(())
+    }
+
+    pub fn is_user_certificate(&self) -> bool {
+        self.cert_type == SSH_CERT_TYPE_USER
+    }
+
+    /// Checks that this is a currently valid host certificate for
+    /// `hostname`.  This does not check the signature.
+    pub fn check_host(&self, hostname: &str, now: u64) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.cert_type == SSH_CERT_TYPE_HOST,
+            "certificate {} is not a host certificate",
+            self.key_id
+        );
+        // No critical options are defined for host certificates
+        anyhow::ensure!(
+            self.critical_options.is_empty(),
+            "host certificate {} has critical options",
+            self.key_id
+        );
+        self.check_validity(now)?;
+
+        // As with OpenSSH, a host certificate without principals
+        // is valid for any host, and the principals may be wildcards
+        if !self.principals.is_empty()
+            && !self
+                .principals
+                .iter()
+                .any(|principal| wildcard_match(principal, hostname))
+        {
+            anyhow::bail!(
+                "certificate {} is not valid for {hostname}; it is valid for {}",
+                self.key_id,
+                self.principals.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (pattern, _) = wildcard_to_pattern(&pattern.to_lowercase());
+    Regex::new(&pattern)
+        .map(|re| re.is_match(&text.to_lowercase()))
+        .unwrap_or(false)
+}
+
+/// Returns true if the host field of a known hosts entry matches `name`.
+/// The field is either a comma separated list of patterns, any of which
+/// may be negated with a `!` prefix, or a hashed name.
+fn host_field_matches(field: &str, name: &str) -> bool {
+    if let Some(hashed) = field.strip_prefix("|1|") {
+        return hashed_host_matches(hashed, name).unwrap_or(false);
+    }
+    let mut matched = false;
+    for pattern in field.split(',') {
+        match pattern.strip_prefix('!') {
+            Some(negated) => {
+                if wildcard_match(negated, name) {
+                    return false;
+                }
+            }
+            None => {
+                if wildcard_match(pattern, name) {
+                    matched = true;
+                }
+            }
+        }
+    }
+    matched
+}
+
+/// Hashed names have the form `|1|base64(salt)|base64(hmac-sha1(salt, name))`
+fn hashed_host_matches(hashed: &str, name: &str) -> anyhow::Result<bool> {
+    let (salt, hash) = hashed
+        .split_once('|')
+        .ok_or_else(|| anyhow!("invalid hashed host"))?;
+    let engine = &base64::engine::general_purpose::STANDARD;
+    let salt = engine.decode(salt)?;
+    let hash = engine.decode(hash)?;
+    let key = PKey::hmac(&salt)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+    signer.update(name.as_bytes())?;
+    Ok(signer.sign_to_vec()? == hash)
+}
+
+/// The `@cert-authority` and `@revoked` entries from the known hosts
+/// files that apply to a particular host.
+/// Neither ssh backend understands these markers.
+#[derive(Debug, Default)]
+pub(crate) struct KnownHostMarkers {
+    cert_authorities: Vec<Vec<u8>>,
+    revoked: Vec<Vec<u8>>,
+}
+
+impl KnownHostMarkers {
+    /// Load the entries that match `name`, which is in the
+    /// `host` or `[host]:port` form used by known hosts files,
+    /// from the whitespace separated list of `files`.
+    pub fn load(files: Option<&String>, name: &str) -> Self {
+        let mut markers = Self::default();
+        for file in files.map(|f| f.split_whitespace()).into_iter().flatten() {
+            if let Ok(text) = std::fs::read_to_string(file) {
+                markers.parse(&text, name);
+            }
+        }
+        markers
+    }
+
+    fn parse(&mut self, text: &str, name: &str) {
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let list = match fields.next() {
+                Some("@cert-authority") => &mut self.cert_authorities,
+                Some("@revoked") => &mut self.revoked,
+                _ => continue,
+            };
+            let (hosts, key_type, blob) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(hosts), Some(key_type), Some(blob)) => (hosts, key_type, blob),
+                _ => {
+                    log::warn!("ignoring malformed known hosts entry: {line}");
+                    continue;
+                }
+            };
+            if !host_field_matches(hosts, name) {
+                continue;
+            }
+            match base64::engine::general_purpose::STANDARD.decode(blob) {
+                Ok(blob) => list.push(blob),
+                Err(err) => log::warn!("ignoring {key_type} known hosts entry for {hosts}: {err}"),
+            }
+        }
+    }
+
+    pub fn has_cert_authority(&self) -> bool {
+        !self.cert_authorities.is_empty()
+    }
+
+    pub fn is_revoked(&self, key: &[u8]) -> bool {
+        self.revoked.iter().any(|k| k == key)
+    }
+
+    /// Verifies the host certificate `blob` presented by the server for
+    /// `hostname`: it must be signed by one of the certificate authorities,
+    /// and neither the certificate authority nor the host key may be revoked.
+    pub fn verify_host_certificate(
+        &self,
+        blob: &[u8],
+        hostname: &str,
+        now: u64,
+    ) -> anyhow::Result<()> {
+        let cert = Certificate::parse(blob)?;
+        if self.is_revoked(&cert.signature_key) {
+            anyhow::bail!("the certificate authority for {hostname} has been revoked");
+        }
+        if self.is_revoked(&cert.public_key) || self.is_revoked(blob) {
+            anyhow::bail!("the host key for {hostname} has been revoked");
+        }
+        if !self.cert_authorities.contains(&cert.signature_key) {
+            anyhow::bail!(
+                "the host certificate for {hostname} was not signed by a certificate \
+                 authority listed in your known hosts file"
+            );
+        }
+        cert.check_host(hostname, now)?;
+        cert.verify_signature()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Generated using:
+    // ssh-keygen -t ed25519 -f ca_ed25519
+    // ssh-keygen -s ca_ed25519 -h -I host-ed \
+    //    -n 'host.example.com,*.example.org' -V 20200101:20400101 host.pub
+    // and similarly for the ecdsa and rsa (with -t rsa-sha2-512) CAs,
+    // and for a user certificate (without -h).
+    const CA_ED25519: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIPFPrvnl/DstIqBqilc+YbxUj9zVmARX9CThlmtNJBLV";
+    const CA_ECDSA: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBMeEQi3RUSnSrWR6wm6aVx7IgQP6PTzW4IDeIp69YpQhc39BB/wuuHGCfnWcM5GesPH6ymOsNHZZAymV9E9TE/E=";
+    const CA_RSA: &str = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAAgQDadWqH3Zh7tLKoSToe11xpLuMt18lQLp85Zy0OQ+qC1x64ihejQqNUTOoht9UZC1Eid3SHoLLtJlFg/RcmGuueOqPc2ESYAuHyaT0D+tRDX0EXgL8ygeGKldTF/q7rqVggSkaRIzd9mzcQqnbZ8KIszrAgIrPu+QOZw7jQ9ZXJ2Q==";
+    const HOST_KEY: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIODjdfQvnzdT9/prHcHu5k1oEmeNUmr2FJuRiR6LzfuM";
+    const HOST_CERT_ED25519: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAINlWa459AbXqY4/OAWbqxfX3lPh6QCY/uu9l0PWWFUKWAAAAIODjdfQvnzdT9/prHcHu5k1oEmeNUmr2FJuRiR6LzfuMAAAAAAAAAAAAAAACAAAAB2hvc3QtZWQAAAAlAAAAEGhvc3QuZXhhbXBsZS5jb20AAAANKi5leGFtcGxlLm9yZwAAAABeC+EAAAAAAIOqfoAAAAAAAAAAAAAAAAAAAAAzAAAAC3NzaC1lZDI1NTE5AAAAIPFPrvnl/DstIqBqilc+YbxUj9zVmARX9CThlmtNJBLVAAAAUwAAAAtzc2gtZWQyNTUxOQAAAEAJUCIjCtgVmMNI6qUGeAjQPN3yb2c++KVc/aaA8c2I8UnSWzrUybaCtW2OZklf3g3c/U/DYwzB+15cvydnv5UH";
+    const HOST_CERT_ECDSA: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAILbem+cNminP2Lh467fqL/Lt39v3qquFLugsjO+VaHO/AAAAIODjdfQvnzdT9/prHcHu5k1oEmeNUmr2FJuRiR6LzfuMAAAAAAAAAAAAAAACAAAACmhvc3QtZWNkc2EAAAAUAAAAEGhvc3QuZXhhbXBsZS5jb20AAAAAXgvhAAAAAACDqn6AAAAAAAAAAAAAAAAAAAAAaAAAABNlY2RzYS1zaGEyLW5pc3RwMjU2AAAACG5pc3RwMjU2AAAAQQTHhEIt0VEp0q1kesJumlceyIED+j081uCA3iKevWKUIXN/QQf8Lrhxgn51nDORnrDx+spjrDR2WQMplfRPUxPxAAAAZAAAABNlY2RzYS1zaGEyLW5pc3RwMjU2AAAASQAAACAMz1aFlGVL/vsIRRCrmcSPybgMmv7Q6bJQscOB93/iVQAAACEAwhzz3ur5hZCtXFJIn/Vqu7QP+WOUd8G05cG5a6E65uA=";
+    const HOST_CERT_RSA: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAINuuP7brdn6NlgPlbF0ASYpYDHrSR1DwolQCRWu6hi6SAAAAIODjdfQvnzdT9/prHcHu5k1oEmeNUmr2FJuRiR6LzfuMAAAAAAAAAAAAAAACAAAACGhvc3QtcnNhAAAAFAAAABBob3N0LmV4YW1wbGUuY29tAAAAAF4L4QAAAAAAg6p+gAAAAAAAAAAAAAAAAAAAAJcAAAAHc3NoLXJzYQAAAAMBAAEAAACBANp1aofdmHu0sqhJOh7XXGku4y3XyVAunzlnLQ5D6oLXHriKF6NCo1RM6iG31RkLUSJ3dIegsu0mUWD9FyYa6546o9zYRJgC4fJpPQP61ENfQReAvzKB4YqV1MX+ruupWCBKRpEjN32bNxCqdtnwoizOsCAis+75A5nDuND1lcnZAAAAlAAAAAxyc2Etc2hhMi01MTIAAACAaB37/v2qaqD3+6jiCpGEjCjnhgsqnSyqCmVkkXjTW2MyGxXC4BAyxSiEZadbQZhpGiA3d5O9GH8+JdrMYcpmNQz1kiOEIO0Tb6I665IHLScvIdkZhNV4Q2Nr0DBhHM+PgFdb/IvQh6rcDjkB3IK+D8fWoEbPoT49P46MTGzf89g=";
+    const USER_CERT: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAIL1pU7uMZDRb4qq9/4SOeswtekWd7HNJJ0IPXelK2zBsAAAAIODjdfQvnzdT9/prHcHu5k1oEmeNUmr2FJuRiR6LzfuMAAAAAAAAAAAAAAABAAAABHVzZXIAAAAGAAAAAm1lAAAAAF4L4QAAAAAAg6p+gAAAAAAAAACCAAAAFXBlcm1pdC1YMTEtZm9yd2FyZGluZwAAAAAAAAAXcGVybWl0LWFnZW50LWZvcndhcmRpbmcAAAAAAAAAFnBlcm1pdC1wb3J0LWZvcndhcmRpbmcAAAAAAAAACnBlcm1pdC1wdHkAAAAAAAAADnBlcm1pdC11c2VyLXJjAAAAAAAAAAAAAAAzAAAAC3NzaC1lZDI1NTE5AAAAIPFPrvnl/DstIqBqilc+YbxUj9zVmARX9CThlmtNJBLVAAAAUwAAAAtzc2gtZWQyNTUxOQAAAECiXJ6opuQv/U+wmakIpIyiVSDkY3/0qUCgKX+NkKqB9inm4RSY0ADKniQhTlbQKlYnSq/ZRhIbGuwdHg6l++cF";
+
+    // 2030-01-01
+    const NOW: u64 = 1893456000;
+
+    fn markers(known_hosts: &str, name: &str) -> KnownHostMarkers {
+        let mut markers = KnownHostMarkers::default();
+        markers.parse(known_hosts, name);
+        markers
+    }
+
+    fn blob(text: &str) -> Vec<u8> {
+        decode_public_key_text(text).unwrap()
+    }
+
+    #[test]
+    fn parse_certificate() {
+        let cert = Certificate::from_public_key_text(HOST_CERT_ED25519).unwrap();
+        assert_eq!(cert.key_id, "host-ed");
+        assert_eq!(cert.cert_type, SSH_CERT_TYPE_HOST);
+        assert_eq!(cert.principals, vec!["host.example.com", "*.example.org"]);
+        assert_eq!(cert.public_key, blob(HOST_KEY));
+        assert_eq!(cert.signature_key, blob(CA_ED25519));
+
+        assert!(is_certificate(&blob(HOST_CERT_ED25519)));
+        assert!(!is_certificate(&blob(HOST_KEY)));
+        assert!(Certificate::from_public_key_text(HOST_KEY).is_err());
+        assert!(Certificate::parse(&blob(HOST_CERT_ED25519)[..100]).is_err());
+    }
+
+    #[test]
+    fn verify_signatures() {
+        for text in &[HOST_CERT_ED25519, HOST_CERT_ECDSA, HOST_CERT_RSA, USER_CERT] {
+            let cert = Certificate::from_public_key_text(text).unwrap();
+            cert.verify_signature().unwrap();
+        }
+
+        // Changing the key id invalidates the signature
+        let mut blob = blob(HOST_CERT_ED25519);
+        let pos = blob.windows(7).position(|w| w == b"host-ed").unwrap();
+        blob[pos] = b'H';
+        let cert = Certificate::parse(&blob).unwrap();
+        assert!(cert.verify_signature().is_err());
+    }
+
+    #[test]
+    fn check_host() {
+        let cert = Certificate::from_public_key_text(HOST_CERT_ED25519).unwrap();
+        cert.check_host("host.example.com", NOW).unwrap();
+        cert.check_host("HOST.example.com", NOW).unwrap();
+        cert.check_host("foo.example.org", NOW).unwrap();
+        assert!(cert.check_host("example.com", NOW).is_err());
+        assert_eq!(
+            cert.check_host("host.example.com", 0)
+                .unwrap_err()
+                .to_string(),
+            "certificate host-ed is not yet valid"
+        );
+        assert_eq!(
+            cert.check_host("host.example.com", 2240524800)
+                .unwrap_err()
+                .to_string(),
+            "certificate host-ed has expired"
+        );
+
+        let user = Certificate::from_public_key_text(USER_CERT).unwrap();
+        assert!(user.is_user_certificate());
+        assert!(user.check_host("host.example.com", NOW).is_err());
+    }
+
+    #[test]
+    fn host_patterns() {
+        assert!(host_field_matches("host.example.com", "host.example.com"));
+        assert!(host_field_matches("*.example.com", "host.example.com"));
+        assert!(host_field_matches("foo,*.example.com", "host.example.com"));
+        assert!(!host_field_matches(
+            "*.example.com,!host.example.com",
+            "host.example.com"
+        ));
+        assert!(host_field_matches(
+            "[host.example.com]:2222",
+            "[host.example.com]:2222"
+        ));
+        assert!(!host_field_matches(
+            "host.example.com",
+            "[host.example.com]:2222"
+        ));
+        // `ssh-keygen -H` of host.example.com
+        assert!(host_field_matches(
+            "|1|ODlqXa7v71w2WNyLPm/Jf+3thXQ=|ha15w8gqdRfa/iP9moz2kFao9RA=",
+            "host.example.com"
+        ));
+        assert!(!host_field_matches(
+            "|1|ODlqXa7v71w2WNyLPm/Jf+3thXQ=|ha15w8gqdRfa/iP9moz2kFao9RA=",
+            "other.example.com"
+        ));
+    }
+
+    #[test]
+    fn verify_host_certificate() {
+        let known_hosts = format!(
+            "host.example.com {HOST_KEY}\n\
+             @cert-authority *.example.com {CA_ED25519}\n\
+             @cert-authority *.example.com,*.example.net {CA_ECDSA}\n\
+             @cert-authority other.example.com {CA_RSA}\n"
+        );
+        let kh = markers(&known_hosts, "host.example.com");
+        assert!(kh.has_cert_authority());
+        for cert in &[HOST_CERT_ED25519, HOST_CERT_ECDSA] {
+            kh.verify_host_certificate(&blob(cert), "host.example.com", NOW)
+                .unwrap();
+        }
+        assert_eq!(
+            kh.verify_host_certificate(&blob(HOST_CERT_RSA), "host.example.com", NOW)
+                .unwrap_err()
+                .to_string(),
+            "the host certificate for host.example.com was not signed by a certificate \
+             authority listed in your known hosts file"
+        );
+
+        let kh = markers(&known_hosts, "host.example.org");
+        assert!(!kh.has_cert_authority());
+
+        let kh = markers(
+            &format!("{known_hosts}@revoked * {CA_ECDSA}\n"),
+            "host.example.com",
+        );
+        kh.verify_host_certificate(&blob(HOST_CERT_ED25519), "host.example.com", NOW)
+            .unwrap();
+        assert!(kh
+            .verify_host_certificate(&blob(HOST_CERT_ECDSA), "host.example.com", NOW)
+            .is_err());
+
+        let kh = markers(
+            &format!("{known_hosts}@revoked host.example.com {HOST_KEY}\n"),
+            "host.example.com",
+        );
+        assert_eq!(
+            kh.verify_host_certificate(&blob(HOST_CERT_ED25519), "host.example.com", NOW)
+                .unwrap_err()
+                .to_string(),
+            "the host key for host.example.com has been revoked"
+        );
+    }
+}
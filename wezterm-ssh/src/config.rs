@@ -2,6 +2,7 @@
 use regex::{Captures, Regex};
 use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 pub type ConfigMap = BTreeMap<String, String>;
 
@@ -15,7 +16,7 @@ struct Pattern {
 }
 
 /// Compile a glob style pattern string into a regex pattern string
-fn wildcard_to_pattern(s: &str) -> (String, bool) {
+pub(crate) fn wildcard_to_pattern(s: &str) -> (String, bool) {
     let mut pattern = String::new();
     let mut is_literal = true;
     pattern.push('^');
@@ -73,12 +74,33 @@ impl Pattern {
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 enum Criteria {
+    /// Matches the host name as given on the command line;
+    /// used by `Host` stanzas.
     Host(Vec<Pattern>),
+    /// `Match host`: matches the target host name after any
+    /// substitution made by a preceding `HostName` option.
+    HostName(Vec<Pattern>),
     Exec(String),
     OriginalHost(Vec<Pattern>),
     User(Vec<Pattern>),
     LocalUser(Vec<Pattern>),
     All,
+    /// A criteria prefixed with `!` in a `Match` line
+    Not(Box<Criteria>),
+}
+
+/// Default for how long a `Match exec` command may run before we
+/// consider it to have failed.
+/// Can be overridden by setting the `wezterm_ssh_match_exec_timeout`
+/// option to a number of seconds.
+const DEFAULT_MATCH_EXEC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The state against which the criteria of a `Match` are evaluated
+struct MatchState<'a> {
+    original_host: &'a str,
+    local_user: &'a str,
+    context: Context,
+    config: &'a Config,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -97,43 +119,80 @@ struct MatchGroup {
     options: ConfigMap,
 }
 
+impl Criteria {
+    /// Returns true if this criteria is satisfied.
+    /// `target` holds the options that have been resolved so far,
+    /// which may have changed the effective host name or user.
+    fn is_match(&self, state: &MatchState, target: &ConfigMap) -> bool {
+        match self {
+            Criteria::Host(patterns) | Criteria::OriginalHost(patterns) => {
+                Pattern::match_group(state.original_host, patterns)
+            }
+            Criteria::HostName(patterns) => {
+                let hostname = match target.get("hostname") {
+                    Some(hostname) => hostname.replace("%h", state.original_host),
+                    None => state.original_host.to_string(),
+                };
+                Pattern::match_group(&hostname, patterns)
+            }
+            Criteria::Exec(command) => state.config.run_match_exec(command, state, target),
+            Criteria::User(patterns) => {
+                let user = target
+                    .get("user")
+                    .map(|s| s.as_str())
+                    .unwrap_or(state.local_user);
+                Pattern::match_group(user, patterns)
+            }
+            Criteria::LocalUser(patterns) => Pattern::match_group(state.local_user, patterns),
+            Criteria::All => true,
+            Criteria::Not(criteria) => !criteria.is_match(state, target),
+        }
+    }
+}
+
 impl MatchGroup {
-    fn is_match(&self, hostname: &str, user: &str, local_user: &str, context: Context) -> bool {
-        if self.context != context {
+    fn is_match(&self, state: &MatchState, target: &ConfigMap) -> bool {
+        if self.context != state.context {
             return false;
         }
-        for c in &self.criteria {
-            match c {
-                Criteria::Host(patterns) => {
-                    if !Pattern::match_group(hostname, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::Exec(_) => {
-                    log::warn!("Match Exec is not implemented");
-                }
-                Criteria::OriginalHost(patterns) => {
-                    if !Pattern::match_group(hostname, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::User(patterns) => {
-                    if !Pattern::match_group(user, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::LocalUser(patterns) => {
-                    if !Pattern::match_group(local_user, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::All => {
-                    // Always matches
+        // Criteria are evaluated in order and short circuit, so that
+        // an expensive `exec` can be guarded by cheaper criteria
+        self.criteria.iter().all(|c| c.is_match(state, target))
+    }
+}
+
+/// Split the arguments of a `Match` line into tokens, respecting
+/// double quoted strings so that `exec "some command"` is a
+/// single token
+fn tokenize_match_arguments(v: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_token = false;
+
+    for c in v.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_token = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_token {
+                    tokens.push(std::mem::take(&mut current));
+                    has_token = false;
                 }
             }
+            c => {
+                current.push(c);
+                has_token = true;
+            }
         }
-        true
     }
+    if has_token {
+        tokens.push(current);
+    }
+
+    tokens
 }
 
 /// Holds the ordered set of parsed options.
@@ -293,45 +352,50 @@ impl ParsedConfigFile {
                     let mut criteria = vec![];
                     let mut context = Context::FirstPass;
 
-                    let mut tokens = v.split_ascii_whitespace();
+                    // The value had its outer quotes stripped above, but a
+                    // match line may have quoted arguments anywhere, so
+                    // we re-tokenize the original text
+                    let args = line[sep..].trim().trim_start_matches('=').trim();
+                    let tokens = tokenize_match_arguments(args);
+                    let mut tokens = tokens.iter().map(|s| s.as_str());
 
                     while let Some(cname) = tokens.next() {
-                        match cname.to_lowercase().as_str() {
-                            "all" => {
-                                criteria.push(Criteria::All);
-                            }
+                        let (negated, cname) = match cname.strip_prefix('!') {
+                            Some(cname) => (true, cname),
+                            None => (false, cname),
+                        };
+                        let criterion = match cname.to_lowercase().as_str() {
+                            "all" => Criteria::All,
                             "canonical" => {
                                 context = Context::Canonical;
+                                continue;
                             }
                             "final" => {
                                 context = Context::Final;
+                                continue;
                             }
-                            "exec" => {
-                                criteria.push(Criteria::Exec(
-                                    tokens.next().unwrap_or("false").to_string(),
-                                ));
-                            }
+                            "exec" => Criteria::Exec(tokens.next().unwrap_or("false").to_string()),
                             "host" => {
-                                criteria.push(Criteria::Host(parse_pattern_list(
-                                    tokens.next().unwrap_or(""),
-                                )));
-                            }
-                            "originalhost" => {
-                                criteria.push(Criteria::OriginalHost(parse_pattern_list(
-                                    tokens.next().unwrap_or(""),
-                                )));
+                                Criteria::HostName(parse_pattern_list(tokens.next().unwrap_or("")))
                             }
+                            "originalhost" => Criteria::OriginalHost(parse_pattern_list(
+                                tokens.next().unwrap_or(""),
+                            )),
                             "user" => {
-                                criteria.push(Criteria::User(parse_pattern_list(
-                                    tokens.next().unwrap_or(""),
-                                )));
+                                Criteria::User(parse_pattern_list(tokens.next().unwrap_or("")))
                             }
                             "localuser" => {
-                                criteria.push(Criteria::LocalUser(parse_pattern_list(
-                                    tokens.next().unwrap_or(""),
-                                )));
+                                Criteria::LocalUser(parse_pattern_list(tokens.next().unwrap_or("")))
+                            }
+                            _ => {
+                                log::warn!("Unsupported Match criteria `{}` in `{}`", cname, line);
+                                break;
                             }
-                            _ => break,
+                        };
+                        if negated {
+                            criteria.push(Criteria::Not(Box::new(criterion)));
+                        } else {
+                            criteria.push(criterion);
                         }
                     }
 
@@ -345,14 +409,19 @@ impl ParsedConfigFile {
 
                 fn add_option(options: &mut ConfigMap, k: String, v: &str) {
                     // first option wins in ssh_config, except for identityfile
-                    // which explicitly allows multiple entries to combine together
+                    // and the forwarding options which explicitly allow
+                    // multiple entries to combine together
                     let is_identity_file = k == "identityfile";
+                    let is_forward = is_forward_option(&k);
                     options
                         .entry(k)
                         .and_modify(|e| {
                             if is_identity_file {
                                 e.push(' ');
                                 e.push_str(v);
+                            } else if is_forward {
+                                e.push('\n');
+                                e.push_str(v);
                             }
                         })
                         .or_insert_with(|| v.to_string());
@@ -370,26 +439,34 @@ impl ParsedConfigFile {
     /// Apply configuration values that match the specified hostname to target,
     /// but only if a given key is not already present in target, because the
     /// semantics are that the first match wins
-    fn apply_matches(
-        &self,
-        hostname: &str,
-        user: &str,
-        local_user: &str,
-        context: Context,
-        target: &mut ConfigMap,
-    ) -> bool {
+    fn apply_matches(&self, state: &MatchState, target: &mut ConfigMap) -> bool {
         let mut needs_reparse = false;
 
+        fn merge_option(target: &mut ConfigMap, k: &str, v: &str) {
+            // Forwards from every matching section are combined,
+            // as they are in ssh
+            let is_forward = is_forward_option(k);
+            target
+                .entry(k.to_string())
+                .and_modify(|e| {
+                    if is_forward {
+                        e.push('\n');
+                        e.push_str(v);
+                    }
+                })
+                .or_insert_with(|| v.to_string());
+        }
+
         for (k, v) in &self.options {
-            target.entry(k.to_string()).or_insert_with(|| v.to_string());
+            merge_option(target, k, v);
         }
         for group in &self.groups {
             if group.context != Context::FirstPass {
                 needs_reparse = true;
             }
-            if group.is_match(hostname, user, local_user, context) {
+            if group.is_match(state, target) {
                 for (k, v) in &group.options {
-                    target.entry(k.to_string()).or_insert_with(|| v.to_string());
+                    merge_option(target, k, v);
                 }
             }
         }
@@ -398,6 +475,12 @@ impl ParsedConfigFile {
     }
 }
 
+/// Returns true for the options that may be given several times
+/// to set up several forwards.  Their values are combined one per line.
+fn is_forward_option(key: &str) -> bool {
+    matches!(key, "localforward" | "remoteforward" | "dynamicforward")
+}
+
 /// A context for resolving configuration values.
 /// Holds a combination of environment and token expansion state,
 /// as well as the set of configs that should be consulted.
@@ -496,6 +579,102 @@ impl Config {
         "unknown-user".to_string()
     }
 
+    /// How long a `Match exec` command is permitted to run
+    fn match_exec_timeout(&self) -> Duration {
+        match self.options.get("wezterm_ssh_match_exec_timeout") {
+            Some(value) => match value.parse::<f64>() {
+                Ok(secs) if secs.is_finite() && secs >= 0. => Duration::from_secs_f64(secs),
+                _ => {
+                    log::warn!(
+                        "invalid wezterm_ssh_match_exec_timeout `{}`, using default",
+                        value
+                    );
+                    DEFAULT_MATCH_EXEC_TIMEOUT
+                }
+            },
+            None => DEFAULT_MATCH_EXEC_TIMEOUT,
+        }
+    }
+
+    /// Evaluate a `Match exec` criteria by running the command via the
+    /// shell. The criteria is satisfied if the command exits with status 0
+    /// before the timeout expires.
+    fn run_match_exec(&self, command: &str, state: &MatchState, target: &ConfigMap) -> bool {
+        let mut token_map = self.tokens.clone();
+        let hostname = match target.get("hostname") {
+            Some(hostname) => hostname.replace("%h", state.original_host),
+            None => state.original_host.to_string(),
+        };
+        token_map.insert("%h".to_string(), hostname);
+        token_map.insert("%n".to_string(), state.original_host.to_string());
+        token_map.insert(
+            "%r".to_string(),
+            target
+                .get("user")
+                .cloned()
+                .unwrap_or_else(|| state.local_user.to_string()),
+        );
+        token_map.insert(
+            "%p".to_string(),
+            target
+                .get("port")
+                .cloned()
+                .unwrap_or_else(|| "22".to_string()),
+        );
+
+        let mut command = command.to_string();
+        self.expand_tokens(
+            &mut command,
+            &["%C", "%d", "%h", "%L", "%l", "%n", "%p", "%r", "%u"],
+            &token_map,
+        );
+
+        let mut cmd = if cfg!(windows) {
+            let mut cmd = std::process::Command::new("cmd.exe");
+            cmd.arg("/c").arg(&command);
+            cmd
+        } else {
+            let shell = self
+                .resolve_env("SHELL")
+                .unwrap_or_else(|| "/bin/sh".to_string());
+            let mut cmd = std::process::Command::new(shell);
+            cmd.arg("-c").arg(&command);
+            cmd
+        };
+        cmd.stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                log::error!("Match exec `{}` failed to spawn: {:#}", command, err);
+                return false;
+            }
+        };
+
+        let deadline = Instant::now() + self.match_exec_timeout();
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    log::trace!("Match exec `{}` exited with {:?}", command, status);
+                    return status.success();
+                }
+                Ok(None) if Instant::now() >= deadline => {
+                    log::warn!("Match exec `{}` timed out; treating as no match", command);
+                    child.kill().ok();
+                    child.wait().ok();
+                    return false;
+                }
+                Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+                Err(err) => {
+                    log::error!("Match exec `{}`: {:#}", command, err);
+                    return false;
+                }
+            }
+        }
+    }
+
     /// Resolve the configuration for a given host.
     /// The returned map will expand environment and tokens for options
     /// where that is specified.
@@ -512,14 +691,15 @@ impl Config {
         let mut result = self.options.clone();
         let mut needs_reparse = false;
 
+        let state = MatchState {
+            original_host: host,
+            local_user: &local_user,
+            context: Context::FirstPass,
+            config: self,
+        };
+
         for config in &self.config_files {
-            if config.apply_matches(
-                host,
-                target_user,
-                &local_user,
-                Context::FirstPass,
-                &mut result,
-            ) {
+            if config.apply_matches(&state, &mut result) {
                 needs_reparse = true;
             }
         }
@@ -657,18 +837,25 @@ impl Config {
                 *value = value.replace(t, &self.resolve_local_host(true));
             } else if t == "%d" {
                 if let Some(home) = self.resolve_home() {
-                    let mut items = value
-                        .split_whitespace()
-                        .map(|s| s.to_string())
+                    // Keep the line breaks that separate repeated options
+                    let lines = value
+                        .lines()
+                        .map(|line| {
+                            let mut items = line
+                                .split_whitespace()
+                                .map(|s| s.to_string())
+                                .collect::<Vec<String>>();
+                            for item in &mut items {
+                                if item.starts_with("~/") {
+                                    item.replace_range(0..1, &home);
+                                } else {
+                                    *item = item.replace(t, &home);
+                                }
+                            }
+                            items.join(" ")
+                        })
                         .collect::<Vec<String>>();
-                    for item in &mut items {
-                        if item.starts_with("~/") {
-                            item.replace_range(0..1, &home);
-                        } else {
-                            *item = item.replace(t, &home);
-                        }
-                    }
-                    *value = items.join(" ");
+                    *value = lines.join("\n");
                 }
             } else if value.contains(t) {
                 log::warn!("Unsupported token {t} when evaluating `{orig_value}`");
@@ -728,7 +915,7 @@ impl Config {
         for config in &self.config_files {
             for group in &config.groups {
                 for c in &group.criteria {
-                    if let Criteria::Host(patterns) = c {
+                    if let Criteria::Host(patterns) | Criteria::HostName(patterns) = c {
                         for pattern in patterns {
                             if pattern.is_literal && !pattern.negated {
                                 if !hosts.contains(&pattern.original) {
@@ -1032,6 +1219,48 @@ Config {
         );
     }
 
+    #[test]
+    fn multiple_forwards() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Host foo
+            LocalForward 8080 localhost:80
+            LocalForward 5432 db:5432
+            RemoteForward 2222 localhost:22
+
+        Host *
+            LocalForward 9000 localhost:9000
+            DynamicForward 1080
+            "#,
+        );
+
+        let opts = config.for_host("foo");
+        snapshot!(
+            opts,
+            r#"
+{
+    "dynamicforward": "1080",
+    "hostname": "foo",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "localforward": "8080 localhost:80
+5432 db:5432
+9000 localhost:9000",
+    "port": "22",
+    "remoteforward": "2222 localhost:22",
+    "user": "me",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+    }
+
     #[test]
     fn sub_tilde() {
         let mut config = Config::new();
@@ -1109,7 +1338,7 @@ Config {
             groups: [
                 MatchGroup {
                     criteria: [
-                        Host(
+                        HostName(
                             [
                                 Pattern {
                                     negated: false,
@@ -1134,7 +1363,7 @@ Config {
                 },
                 MatchGroup {
                     criteria: [
-                        Host(
+                        HostName(
                             [
                                 Pattern {
                                     negated: true,
@@ -1169,7 +1398,7 @@ Config {
                 },
                 MatchGroup {
                     criteria: [
-                        Host(
+                        HostName(
                             [
                                 Pattern {
                                     negated: true,
@@ -1517,4 +1746,93 @@ Config {
 "#
         );
     }
+
+    #[test]
+    fn match_host_after_hostname() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Host foo
+            HostName foo.example.com
+
+        Match host *.example.com !user root
+            Port 2222
+
+        Match originalhost *.example.com
+            ForwardAgent yes
+            "#,
+        );
+
+        let opts = config.for_host("foo");
+        snapshot!(
+            opts,
+            r#"
+{
+    "hostname": "foo.example.com",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "port": "2222",
+    "user": "me",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+
+        config.set_option("user", "root");
+        let opts = config.for_host("foo");
+        assert_eq!(opts.get("port").map(|s| s.as_str()), Some("22"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn match_exec() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Match host foo exec "test %h = foo"
+            Port 2222
+
+        Match exec "sleep 10"
+            User slow
+
+        Match exec false
+            User never
+
+        Match all
+            ForwardAgent no
+            "#,
+        );
+        config.set_option("wezterm_ssh_match_exec_timeout", "0.2");
+
+        let opts = config.for_host("foo");
+        assert_eq!(opts.get("port").map(|s| s.as_str()), Some("2222"));
+        assert_eq!(opts.get("user").map(|s| s.as_str()), Some("me"));
+        assert_eq!(opts.get("forwardagent").map(|s| s.as_str()), Some("no"));
+
+        let opts = config.for_host("bar");
+        assert_eq!(opts.get("port").map(|s| s.as_str()), Some("22"));
+    }
+
+    #[test]
+    fn match_tokenize() {
+        assert_eq!(
+            tokenize_match_arguments(r#"host foo exec "test -f /tmp/x" !user root"#),
+            vec!["host", "foo", "exec", "test -f /tmp/x", "!user", "root"]
+        );
+        assert_eq!(
+            tokenize_match_arguments(r#"exec """#),
+            vec!["exec".to_string(), String::new()]
+        );
+    }
 }
@@ -0,0 +1,637 @@
+//! Port forwarding over an established session, equivalent to the
+//! `-L`, `-R` and `-D` options of `ssh(1)`.
+use crate::config::ConfigMap;
+use crate::session::{
+    DeadSession, DirectTcpIp, LocalForward, RemoteForward, SessionRequest, SessionSender,
+};
+use anyhow::{anyhow, bail, Context};
+use filedescriptor::FileDescriptor;
+use smol::channel::bounded;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForwardKind {
+    /// Listen locally and connect to `host:host_port` from the remote
+    /// end; `ssh -L`
+    Local,
+    /// Ask the server to listen and connect to `host:host_port` from
+    /// the local end; `ssh -R`
+    Remote,
+    /// Listen locally and act as a SOCKS4/SOCKS5 proxy, connecting
+    /// to the requested destination from the remote end; `ssh -D`
+    Dynamic,
+}
+
+impl ForwardKind {
+    fn flag(self) -> char {
+        match self {
+            Self::Local => 'L',
+            Self::Remote => 'R',
+            Self::Dynamic => 'D',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardSpec {
+    pub kind: ForwardKind,
+    /// The address to listen on.  When not specified, local and
+    /// dynamic forwards listen on the loopback address and remote
+    /// forwards use the server's default.
+    /// `*` and the empty string mean all interfaces.
+    pub bind_address: Option<String>,
+    pub bind_port: u16,
+    /// The destination; unused for dynamic forwards
+    pub host: String,
+    pub host_port: u16,
+}
+
+impl ForwardSpec {
+    /// Parse the value of a `LocalForward`, `RemoteForward` or
+    /// `DynamicForward` ssh_config option, which separates the
+    /// listen and destination portions with whitespace.
+    pub fn from_ssh_config(kind: ForwardKind, value: &str) -> anyhow::Result<Self> {
+        let fields = value.split_whitespace().collect::<Vec<_>>().join(":");
+        Self::parse_fields(kind, &fields)
+    }
+
+    /// Returns the forwards configured via the `LocalForward`,
+    /// `RemoteForward` and `DynamicForward` options, each of which
+    /// may have been given several times.
+    /// Invalid entries are logged and skipped.
+    pub fn from_config_map(config: &ConfigMap) -> Vec<Self> {
+        let mut specs = vec![];
+        for (key, kind) in &[
+            ("localforward", ForwardKind::Local),
+            ("remoteforward", ForwardKind::Remote),
+            ("dynamicforward", ForwardKind::Dynamic),
+        ] {
+            for value in config.get(*key).into_iter().flat_map(|v| v.lines()) {
+                match Self::from_ssh_config(*kind, value) {
+                    Ok(spec) => specs.push(spec),
+                    Err(err) => log::error!("ignoring invalid {} {}: {:#}", key, value, err),
+                }
+            }
+        }
+        specs
+    }
+
+    fn parse_fields(kind: ForwardKind, spec: &str) -> anyhow::Result<Self> {
+        let fields = split_fields(spec)?;
+        let port = |s: &str| -> anyhow::Result<u16> {
+            s.parse::<u16>()
+                .with_context(|| format!("invalid port number `{}`", s))
+        };
+
+        match (kind, fields.as_slice()) {
+            (ForwardKind::Dynamic, [bind_port]) => Ok(Self {
+                kind,
+                bind_address: None,
+                bind_port: port(bind_port)?,
+                host: String::new(),
+                host_port: 0,
+            }),
+            (ForwardKind::Dynamic, [bind_address, bind_port]) => Ok(Self {
+                kind,
+                bind_address: Some(bind_address.to_string()),
+                bind_port: port(bind_port)?,
+                host: String::new(),
+                host_port: 0,
+            }),
+            (ForwardKind::Dynamic, _) => bail!("expected [bind_address:]port, got `{}`", spec),
+            (_, [bind_port, host, host_port]) => Ok(Self {
+                kind,
+                bind_address: None,
+                bind_port: port(bind_port)?,
+                host: host.to_string(),
+                host_port: port(host_port)?,
+            }),
+            (_, [bind_address, bind_port, host, host_port]) => Ok(Self {
+                kind,
+                bind_address: Some(bind_address.to_string()),
+                bind_port: port(bind_port)?,
+                host: host.to_string(),
+                host_port: port(host_port)?,
+            }),
+            _ => bail!("expected [bind_address:]port:host:hostport, got `{}`", spec),
+        }
+    }
+}
+
+/// Split a colon separated forwarding specification into its fields.
+/// IPv6 addresses must be enclosed in square brackets.
+fn split_fields(spec: &str) -> anyhow::Result<Vec<String>> {
+    let mut fields = vec![];
+    let mut current = String::new();
+    let mut in_brackets = false;
+    for c in spec.chars() {
+        match c {
+            '[' if !in_brackets && current.is_empty() => in_brackets = true,
+            ']' if in_brackets => in_brackets = false,
+            ':' if !in_brackets => fields.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    if in_brackets {
+        bail!("unterminated `[` in `{}`", spec);
+    }
+    fields.push(current);
+    Ok(fields)
+}
+
+fn bracket_ipv6(addr: &str) -> String {
+    if addr.contains(':') {
+        format!("[{}]", addr)
+    } else {
+        addr.to_string()
+    }
+}
+
+/// Parses `-L 8080:localhost:80`, `L8080:localhost:80` and the
+/// equivalent `R` and `D` forms
+impl FromStr for ForwardSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let s = s.strip_prefix('-').unwrap_or(s);
+        let mut chars = s.chars();
+        let kind = match chars.next() {
+            Some('L') => ForwardKind::Local,
+            Some('R') => ForwardKind::Remote,
+            Some('D') => ForwardKind::Dynamic,
+            _ => bail!("forwarding specification `{}` must start with L, R or D", s),
+        };
+        Self::parse_fields(kind, chars.as_str().trim())
+    }
+}
+
+impl std::fmt::Display for ForwardSpec {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "-{} ", self.kind.flag())?;
+        if let Some(bind) = &self.bind_address {
+            write!(fmt, "{}:", bracket_ipv6(bind))?;
+        }
+        write!(fmt, "{}", self.bind_port)?;
+        if self.kind != ForwardKind::Dynamic {
+            write!(fmt, ":{}:{}", bracket_ipv6(&self.host), self.host_port)?;
+        }
+        Ok(())
+    }
+}
+
+/// An active port forward.
+/// The forward is torn down when this is dropped.
+#[derive(Debug)]
+pub struct PortForward {
+    spec: ForwardSpec,
+    bound_port: u16,
+    /// The address of the local listener; not used by remote forwards
+    listen_addr: Option<SocketAddr>,
+    connections: Arc<AtomicUsize>,
+    tx: SessionSender,
+}
+
+impl PortForward {
+    pub fn spec(&self) -> &ForwardSpec {
+        &self.spec
+    }
+
+    /// The port that is being listened on; this is different from
+    /// the port in the spec when that was specified as `0`
+    pub fn bound_port(&self) -> u16 {
+        self.bound_port
+    }
+
+    /// The number of connections that have been forwarded so far
+    pub fn connection_count(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+}
+
+impl std::fmt::Display for PortForward {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let side = if self.spec.kind == ForwardKind::Remote {
+            "remote"
+        } else {
+            "local"
+        };
+        write!(
+            fmt,
+            "{} (listening on {} port {}, {} connections)",
+            self.spec,
+            side,
+            self.bound_port,
+            self.connection_count()
+        )
+    }
+}
+
+impl Drop for PortForward {
+    fn drop(&mut self) {
+        let request = match self.listen_addr {
+            Some(addr) => SessionRequest::CancelLocalForward(addr),
+            None => SessionRequest::CancelRemoteForward(self.bound_port),
+        };
+        self.tx.try_send(request).ok();
+    }
+}
+
+pub(crate) async fn start_forward(
+    tx: &SessionSender,
+    spec: ForwardSpec,
+) -> anyhow::Result<PortForward> {
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let (bound_port, listen_addr) = match spec.kind {
+        ForwardKind::Remote => {
+            let (reply, rx) = bounded(1);
+            tx.send(SessionRequest::RemoteForward(
+                RemoteForward {
+                    bind_address: spec.bind_address.clone(),
+                    bind_port: spec.bind_port,
+                    host: spec.host.clone(),
+                    host_port: spec.host_port,
+                    connections: Arc::clone(&connections),
+                    tx: tx.clone(),
+                },
+                reply,
+            ))
+            .await
+            .map_err(|_| DeadSession)?;
+            (rx.recv().await??, None)
+        }
+        ForwardKind::Local | ForwardKind::Dynamic => {
+            let bind_address = match spec.bind_address.as_deref() {
+                None | Some("localhost") => "127.0.0.1",
+                Some("") | Some("*") => "0.0.0.0",
+                Some(addr) => addr,
+            };
+            let listener = TcpListener::bind((bind_address, spec.bind_port))
+                .with_context(|| format!("listening on {}:{}", bind_address, spec.bind_port))?;
+            // Connections are accepted by the session loop
+            listener.set_nonblocking(true)?;
+            let listen_addr = listener.local_addr()?;
+
+            tx.send(SessionRequest::LocalForward(LocalForward {
+                listener,
+                spec: spec.clone(),
+                connections: Arc::clone(&connections),
+                tx: tx.clone(),
+            }))
+            .await
+            .map_err(|_| DeadSession)?;
+            (listen_addr.port(), Some(listen_addr))
+        }
+    };
+
+    Ok(PortForward {
+        spec,
+        bound_port,
+        listen_addr,
+        connections,
+        tx: tx.clone(),
+    })
+}
+
+/// Sets up the forwarding of a connection that was accepted by the
+/// listener of a local or dynamic forward.
+/// This blocks while waiting for the SOCKS request of the client and
+/// for the channel to be opened, so it is run on a thread of its own.
+pub(crate) fn forward_connection(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    spec: &ForwardSpec,
+    tx: &SessionSender,
+) -> anyhow::Result<()> {
+    stream.set_nonblocking(false)?;
+
+    let socks = if spec.kind == ForwardKind::Dynamic {
+        Some(socks_request(&mut stream).context("SOCKS handshake")?)
+    } else {
+        None
+    };
+    let (host, port) = match &socks {
+        Some(req) => (req.host.clone(), req.port),
+        None => (spec.host.clone(), spec.host_port),
+    };
+    log::trace!("{}: forwarding {} to {}:{}", spec, peer, host, port);
+
+    let mut reply_stream = stream.try_clone()?;
+    let fd = {
+        #[cfg(unix)]
+        {
+            FileDescriptor::new(stream)
+        }
+        #[cfg(windows)]
+        unsafe {
+            use std::os::windows::io::{FromRawSocket, IntoRawSocket};
+            FileDescriptor::from_raw_socket(stream.into_raw_socket())
+        }
+    };
+
+    let (reply, rx) = bounded(1);
+    smol::block_on(
+        tx.send(SessionRequest::DirectTcpIp(
+            DirectTcpIp {
+                host,
+                port,
+                originator_host: peer.ip().to_string(),
+                originator_port: peer.port(),
+                fd,
+                preamble: socks
+                    .as_ref()
+                    .map(|req| req.reply(true))
+                    .unwrap_or_default(),
+            },
+            reply,
+        )),
+    )
+    .map_err(|_| DeadSession)?;
+
+    match smol::block_on(rx.recv())? {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            if let Some(req) = &socks {
+                reply_stream.write_all(&req.reply(false)).ok();
+            }
+            Err(err)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SocksVersion {
+    V4,
+    V5,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SocksRequest {
+    version: SocksVersion,
+    host: String,
+    port: u16,
+}
+
+impl SocksRequest {
+    /// Returns the reply to send to the client once we know
+    /// whether the destination could be reached
+    fn reply(&self, success: bool) -> Vec<u8> {
+        match self.version {
+            SocksVersion::V4 => vec![0, if success { 0x5a } else { 0x5b }, 0, 0, 0, 0, 0, 0],
+            SocksVersion::V5 => vec![5, if success { 0 } else { 1 }, 0, 1, 0, 0, 0, 0, 0, 0],
+        }
+    }
+}
+
+fn read_byte<S: Read>(stream: &mut S) -> anyhow::Result<u8> {
+    let mut buf = [0u8; 1];
+    stream.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_port<S: Read>(stream: &mut S) -> anyhow::Result<u16> {
+    let mut buf = [0u8; 2];
+    stream.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_nul_terminated<S: Read>(stream: &mut S) -> anyhow::Result<String> {
+    let mut bytes = vec![];
+    loop {
+        match read_byte(stream)? {
+            0 => break,
+            b => bytes.push(b),
+        }
+        if bytes.len() > 255 {
+            bail!("SOCKS4 string is too long");
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| anyhow!("SOCKS4 string is not UTF-8"))
+}
+
+/// Performs the server side of a SOCKS4, SOCKS4a or SOCKS5 (without
+/// authentication) CONNECT handshake, returning the requested
+/// destination.  The final reply is left to the caller.
+fn socks_request<S: Read + Write>(stream: &mut S) -> anyhow::Result<SocksRequest> {
+    match read_byte(stream)? {
+        4 => {
+            let command = read_byte(stream)?;
+            let port = read_port(stream)?;
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip)?;
+            let _user_id = read_nul_terminated(stream)?;
+            let req = SocksRequest {
+                version: SocksVersion::V4,
+                // SOCKS4a signals that a hostname follows by
+                // using an address of the form 0.0.0.x
+                host: if ip[0..3] == [0, 0, 0] && ip[3] != 0 {
+                    read_nul_terminated(stream)?
+                } else {
+                    Ipv4Addr::from(ip).to_string()
+                },
+                port,
+            };
+            if command != 1 {
+                stream.write_all(&req.reply(false))?;
+                bail!("unsupported SOCKS4 command {}", command);
+            }
+            Ok(req)
+        }
+        5 => {
+            let num_methods = read_byte(stream)? as usize;
+            let mut methods = vec![0u8; num_methods];
+            stream.read_exact(&mut methods)?;
+            if !methods.contains(&0) {
+                stream.write_all(&[5, 0xff])?;
+                bail!("SOCKS5 client does not support unauthenticated access");
+            }
+            stream.write_all(&[5, 0])?;
+
+            let mut header = [0u8; 4];
+            stream.read_exact(&mut header)?;
+            let [version, command, _reserved, address_type] = header;
+            if version != 5 {
+                bail!("unexpected SOCKS version {} in request", version);
+            }
+            let host = match address_type {
+                1 => {
+                    let mut ip = [0u8; 4];
+                    stream.read_exact(&mut ip)?;
+                    Ipv4Addr::from(ip).to_string()
+                }
+                3 => {
+                    let len = read_byte(stream)? as usize;
+                    let mut name = vec![0u8; len];
+                    stream.read_exact(&mut name)?;
+                    String::from_utf8(name).map_err(|_| anyhow!("SOCKS5 hostname is not UTF-8"))?
+                }
+                4 => {
+                    let mut ip = [0u8; 16];
+                    stream.read_exact(&mut ip)?;
+                    Ipv6Addr::from(ip).to_string()
+                }
+                _ => {
+                    stream.write_all(&[5, 8, 0, 1, 0, 0, 0, 0, 0, 0])?;
+                    bail!("unsupported SOCKS5 address type {}", address_type);
+                }
+            };
+            let port = read_port(stream)?;
+            if command != 1 {
+                stream.write_all(&[5, 7, 0, 1, 0, 0, 0, 0, 0, 0])?;
+                bail!("unsupported SOCKS5 command {}", command);
+            }
+            Ok(SocksRequest {
+                version: SocksVersion::V5,
+                host,
+                port,
+            })
+        }
+        version => bail!("unsupported SOCKS version {}", version),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A stream that reads from a canned request and
+    /// captures whatever is written back
+    struct FakeStream {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl FakeStream {
+        fn new(input: &[u8]) -> Self {
+            Self {
+                input: std::io::Cursor::new(input.to_vec()),
+                output: vec![],
+            }
+        }
+    }
+
+    impl Read for FakeStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for FakeStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parse_specs() {
+        let spec: ForwardSpec = "-L 8080:localhost:80".parse().unwrap();
+        assert_eq!(
+            spec,
+            ForwardSpec {
+                kind: ForwardKind::Local,
+                bind_address: None,
+                bind_port: 8080,
+                host: "localhost".to_string(),
+                host_port: 80,
+            }
+        );
+        assert_eq!(spec.to_string(), "-L 8080:localhost:80");
+
+        let spec: ForwardSpec = "R*:2222:[::1]:22".parse().unwrap();
+        assert_eq!(
+            spec,
+            ForwardSpec {
+                kind: ForwardKind::Remote,
+                bind_address: Some("*".to_string()),
+                bind_port: 2222,
+                host: "::1".to_string(),
+                host_port: 22,
+            }
+        );
+        assert_eq!(spec.to_string(), "-R *:2222:[::1]:22");
+
+        let spec: ForwardSpec = "D 1080".parse().unwrap();
+        assert_eq!(spec.kind, ForwardKind::Dynamic);
+        assert_eq!(spec.bind_port, 1080);
+        assert_eq!(spec.to_string(), "-D 1080");
+
+        let spec =
+            ForwardSpec::from_ssh_config(ForwardKind::Local, "127.0.0.1:5432 db:5432").unwrap();
+        assert_eq!(spec.to_string(), "-L 127.0.0.1:5432:db:5432");
+
+        assert!("8080:localhost:80".parse::<ForwardSpec>().is_err());
+        assert!("L8080:localhost".parse::<ForwardSpec>().is_err());
+        assert!("Lhttp:localhost:80".parse::<ForwardSpec>().is_err());
+        assert!("D1:2:3".parse::<ForwardSpec>().is_err());
+    }
+
+    #[test]
+    fn config_map_specs() {
+        let mut config = ConfigMap::new();
+        config.insert(
+            "localforward".to_string(),
+            "8080 localhost:80\n5432 db:5432\nbogus".to_string(),
+        );
+        config.insert("dynamicforward".to_string(), "1080".to_string());
+
+        let specs = ForwardSpec::from_config_map(&config)
+            .iter()
+            .map(|spec| spec.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            specs,
+            vec!["-L 8080:localhost:80", "-L 5432:db:5432", "-D 1080"]
+        );
+    }
+
+    #[test]
+    fn socks5_connect() {
+        let mut request = vec![5, 1, 0, 5, 1, 0, 3, 11];
+        request.extend_from_slice(b"example.com");
+        request.extend_from_slice(&443u16.to_be_bytes());
+        let mut stream = FakeStream::new(&request);
+
+        let req = socks_request(&mut stream).unwrap();
+        assert_eq!(
+            req,
+            SocksRequest {
+                version: SocksVersion::V5,
+                host: "example.com".to_string(),
+                port: 443,
+            }
+        );
+        assert_eq!(stream.output, vec![5, 0]);
+    }
+
+    #[test]
+    fn socks5_requires_no_auth() {
+        let mut stream = FakeStream::new(&[5, 1, 2]);
+        assert!(socks_request(&mut stream).is_err());
+        assert_eq!(stream.output, vec![5, 0xff]);
+    }
+
+    #[test]
+    fn socks4a_connect() {
+        let mut request = vec![4, 1, 0, 80, 0, 0, 0, 1];
+        request.extend_from_slice(b"user\0example.com\0");
+        let mut stream = FakeStream::new(&request);
+
+        let req = socks_request(&mut stream).unwrap();
+        assert_eq!(req.host, "example.com");
+        assert_eq!(req.port, 80);
+        assert_eq!(req.reply(true), vec![0, 0x5a, 0, 0, 0, 0, 0, 0]);
+
+        let mut stream = FakeStream::new(&[4, 1, 0, 22, 10, 0, 0, 1, 0]);
+        let req = socks_request(&mut stream).unwrap();
+        assert_eq!(req.host, "10.0.0.1");
+        assert_eq!(req.port, 22);
+    }
+}
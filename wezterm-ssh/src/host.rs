@@ -1,6 +1,8 @@
+use crate::certificate::KnownHostMarkers;
 use crate::session::SessionEvent;
 use anyhow::Context;
 use smol::channel::{bounded, Sender};
+use std::path::Path;
 
 #[derive(Debug, thiserror::Error)]
 #[error("host key mismatch for ssh server {remote_address}. Got fingerprint {key} instead of the expected value from your known hosts file {file:?}.")]
@@ -8,6 +10,76 @@ pub struct HostVerificationFailed {
     pub remote_address: String,
     pub key: String,
     pub file: Option<std::path::PathBuf>,
+    pub(crate) reply: Option<Sender<bool>>,
+}
+
+impl HostVerificationFailed {
+    /// Returns true if the user may choose to replace the known_hosts
+    /// entry with the new key, for example because the host key was
+    /// rotated by the administrator.
+    pub fn can_update_known_hosts(&self) -> bool {
+        self.reply.is_some()
+    }
+
+    /// The question to put to the user when offering to update
+    /// the known_hosts file
+    pub fn update_prompt(&self) -> String {
+        let file = match &self.file {
+            Some(file) => file.display().to_string(),
+            None => "your known_hosts file".to_string(),
+        };
+        format!(
+            "The host key for {} has changed.\n\
+             New fingerprint: {}\n\
+             Only accept the new key if you have confirmed with the administrator\n\
+             that it has been changed, otherwise someone may be intercepting\n\
+             your connection.\n\
+             Replace the old key in {} and continue connecting?",
+            self.remote_address, self.key, file
+        )
+    }
+
+    /// Answer the update request.  If `update_known_hosts` is true then
+    /// the old key is replaced and the connection continues, otherwise
+    /// the connection fails.
+    /// Dropping the event without answering is equivalent to declining.
+    pub async fn answer(mut self, update_known_hosts: bool) -> anyhow::Result<()> {
+        if let Some(reply) = self.reply.take() {
+            reply.send(update_known_hosts).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Remove the entries for `host` from the known hosts `file`.
+/// We use `ssh-keygen -R` for this as it knows how to deal with
+/// hashed host names, which neither backend can match up for us.
+fn remove_known_host(file: &Path, host: &str) -> anyhow::Result<()> {
+    let output = std::process::Command::new("ssh-keygen")
+        .arg("-R")
+        .arg(host)
+        .arg("-f")
+        .arg(file)
+        .output()
+        .context("running ssh-keygen to remove the old host key")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "ssh-keygen -R {} -f {} failed: {}",
+            host,
+            file.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// The form of the host name used in known_hosts entries
+pub(crate) fn known_hosts_name(host: &str, port: u16) -> String {
+    if port != 22 {
+        format!("[{}]:{}", host, port)
+    } else {
+        host.to_string()
+    }
 }
 
 #[derive(Debug)]
@@ -40,6 +112,18 @@ impl crate::sessioninner::SessionInner {
         match sess.is_known_server()? {
             libssh_rs::KnownHosts::Ok => Ok(()),
             libssh_rs::KnownHosts::NotFound | libssh_rs::KnownHosts::Unknown => {
+                if KnownHostMarkers::load(
+                    self.config.get("userknownhostsfile"),
+                    &known_hosts_name(hostname, port),
+                )
+                .has_cert_authority()
+                {
+                    log::warn!(
+                        "Your known hosts file has a @cert-authority entry for {hostname}, \
+                         but host certificates can only be verified by the ssh2 backend. \
+                         Set `ssh_backend = \"Ssh2\"` to use it."
+                    );
+                }
                 let (reply, confirm) = bounded(1);
                 self.tx_event
                     .try_send(SessionEvent::HostVerify(HostVerificationEvent {
@@ -71,15 +155,31 @@ impl crate::sessioninner::SessionInner {
                     }
                 }
 
+                let (reply, confirm) = bounded(1);
                 let failed = HostVerificationFailed {
                     remote_address: format!("{hostname}:{port}"),
                     key,
                     file,
+                    reply: Some(reply),
                 };
                 self.tx_event
                     .try_send(SessionEvent::HostVerificationFailed(failed))
                     .context("sending HostVerificationFailed event to user")?;
-                anyhow::bail!("Host key verification failed");
+
+                let update = smol::block_on(confirm.recv()).unwrap_or(false);
+                if !update {
+                    anyhow::bail!("Host key verification failed");
+                }
+
+                let name = known_hosts_name(hostname, port);
+                if let Some(kh) = self.config.get("userknownhostsfile") {
+                    for file in kh.split_whitespace().map(Path::new) {
+                        if file.exists() {
+                            remove_known_host(file, &name)?;
+                        }
+                    }
+                }
+                Ok(sess.update_known_hosts_file()?)
             }
             libssh_rs::KnownHosts::Other => {
                 anyhow::bail!(
@@ -102,7 +202,21 @@ impl crate::sessioninner::SessionInner {
     ) -> anyhow::Result<()> {
         use anyhow::anyhow;
         use std::io::Write;
-        use std::path::Path;
+
+        let (key, _) = sess
+            .host_key()
+            .ok_or_else(|| anyhow!("failed to get ssh host key"))?;
+        if crate::certificate::is_certificate(key) {
+            // libssh2 doesn't know about certificate authorities,
+            // so we check the certificate for ourselves
+            let markers = KnownHostMarkers::load(
+                self.config.get("userknownhostsfile"),
+                &known_hosts_name(remote_host_name, port),
+            );
+            return markers
+                .verify_host_certificate(key, remote_host_name, crate::certificate::now())
+                .with_context(|| format!("verifying the host certificate for {remote_address}"));
+        }
 
         let mut known_hosts = sess.known_hosts().context("preparing known hosts")?;
 
@@ -152,6 +266,8 @@ impl crate::sessioninner::SessionInner {
                 })
                 .ok_or_else(|| anyhow!("failed to get host fingerprint"))?;
 
+            let host_and_port = known_hosts_name(remote_host_name, port);
+
             match known_hosts.check_port(&remote_host_name, port, key) {
                 ssh2::CheckResult::Match => {}
                 ssh2::CheckResult::NotFound => {
@@ -175,12 +291,6 @@ impl crate::sessioninner::SessionInner {
                         anyhow::bail!("user declined to trust host");
                     }
 
-                    let host_and_port = if port != 22 {
-                        format!("[{}]:{}", remote_host_name, port)
-                    } else {
-                        remote_host_name.to_string()
-                    };
-
                     known_hosts
                         .add(&host_and_port, key, &remote_address, key_type.into())
                         .context("adding known_hosts entry in memory")?;
@@ -190,15 +300,36 @@ impl crate::sessioninner::SessionInner {
                         .with_context(|| format!("writing known_hosts file {}", file.display()))?;
                 }
                 ssh2::CheckResult::Mismatch => {
+                    let (reply, confirm) = bounded(1);
                     let failed = HostVerificationFailed {
                         remote_address: remote_address.to_string(),
                         key: fingerprint,
                         file: Some(file.to_path_buf()),
+                        reply: Some(reply),
                     };
                     self.tx_event
                         .try_send(SessionEvent::HostVerificationFailed(failed))
                         .context("sending HostVerificationFailed event to user")?;
-                    anyhow::bail!("Host key verification failed");
+
+                    let update = smol::block_on(confirm.recv()).unwrap_or(false);
+                    if !update {
+                        anyhow::bail!("Host key verification failed");
+                    }
+
+                    remove_known_host(file, &host_and_port)?;
+
+                    // Start over with just the updated file, as the
+                    // combined state above still holds the old key
+                    let mut updated = sess.known_hosts().context("preparing known hosts")?;
+                    updated
+                        .read_file(&file, ssh2::KnownHostFileKind::OpenSSH)
+                        .with_context(|| format!("reading known_hosts file {}", file.display()))?;
+                    updated
+                        .add(&host_and_port, key, &remote_address, key_type.into())
+                        .context("adding known_hosts entry in memory")?;
+                    updated
+                        .write_file(&file, ssh2::KnownHostFileKind::OpenSSH)
+                        .with_context(|| format!("writing known_hosts file {}", file.display()))?;
                 }
                 ssh2::CheckResult::Failure => {
                     anyhow::bail!("failed to check the known hosts");
@@ -1,11 +1,14 @@
 #[cfg(not(any(feature = "libssh-rs", feature = "ssh2")))]
 compile_error!("Either libssh-rs or ssh2 must be enabled!");
 
+mod algorithms;
 mod auth;
+mod certificate;
 mod channelwrap;
 mod config;
 mod dirwrap;
 mod filewrap;
+mod forward;
 mod host;
 mod pty;
 mod session;
@@ -16,6 +19,7 @@ mod sftpwrap;
 
 pub use auth::*;
 pub use config::*;
+pub use forward::*;
 pub use host::*;
 pub use pty::*;
 pub use session::*;
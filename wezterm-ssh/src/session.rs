@@ -1,5 +1,6 @@
 use crate::auth::*;
 use crate::config::ConfigMap;
+use crate::forward::{start_forward, ForwardSpec, PortForward};
 use crate::host::*;
 use crate::pty::*;
 use crate::sessioninner::*;
@@ -9,7 +10,10 @@ use portable_pty::PtySize;
 use smol::channel::{bounded, Receiver, Sender};
 use std::collections::HashMap;
 use std::io::Write;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum SessionEvent {
@@ -17,6 +21,7 @@ pub enum SessionEvent {
     HostVerify(HostVerificationEvent),
     Authenticate(AuthenticationEvent),
     HostVerificationFailed(HostVerificationFailed),
+    SecurityKeyTouch(SecurityKeyTouchEvent),
     Error(String),
     Authenticated,
 }
@@ -57,6 +62,14 @@ pub(crate) enum SessionRequest {
     Exec(Exec, Sender<anyhow::Result<ExecResult>>),
     Sftp(SftpRequest),
     SignalChannel(SignalChannel),
+    DirectTcpIp(DirectTcpIp, Sender<anyhow::Result<()>>),
+    RemoteForward(RemoteForward, Sender<anyhow::Result<u16>>),
+    CancelRemoteForward(u16),
+    /// The local end of a connection to a remote forward is ready,
+    /// or could not be reached
+    RemoteForwardConnected(usize, anyhow::Result<FileDescriptor>),
+    LocalForward(LocalForward),
+    CancelLocalForward(SocketAddr),
     SessionDropped,
 }
 
@@ -72,9 +85,78 @@ pub(crate) struct Exec {
     pub env: Option<HashMap<String, String>>,
 }
 
+/// Open a `direct-tcpip` channel to `host:port` and shuttle
+/// data between it and `fd`.  `preamble` is written to `fd`
+/// once the channel has been opened.
+#[derive(Debug)]
+pub(crate) struct DirectTcpIp {
+    pub host: String,
+    pub port: u16,
+    pub originator_host: String,
+    pub originator_port: u16,
+    pub fd: FileDescriptor,
+    pub preamble: Vec<u8>,
+}
+
+/// Ask the server to listen on `bind_port` and to forward
+/// connections to `host:host_port` on our side
+#[derive(Debug, Clone)]
+pub(crate) struct RemoteForward {
+    pub bind_address: Option<String>,
+    pub bind_port: u16,
+    pub host: String,
+    pub host_port: u16,
+    pub connections: Arc<AtomicUsize>,
+    /// Used to hand back the connections to `host:host_port`,
+    /// which are made away from the session loop
+    pub tx: SessionSender,
+}
+
+/// Accept connections on `listener` as part of the session loop,
+/// and forward them as specified by `spec`
+#[derive(Debug)]
+pub(crate) struct LocalForward {
+    pub listener: TcpListener,
+    pub spec: ForwardSpec,
+    pub connections: Arc<AtomicUsize>,
+    /// Used by the threads that set up the forwarded connections
+    pub tx: SessionSender,
+}
+
+/// Statistics about the traffic carried by a session.
+/// Only the data carried by channels is counted; the overhead of
+/// the ssh protocol and sftp traffic are not included.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Bytes per second sent over the most recent sampling interval
+    pub send_rate: u64,
+    /// Bytes per second received over the most recent sampling interval
+    pub receive_rate: u64,
+    /// The number of channels that are currently open
+    pub open_channels: usize,
+    /// The number of channels that have been opened since connecting
+    pub total_channels: usize,
+    /// How long it took to establish the TCP connection to the server.
+    /// Not available when using a ProxyCommand.
+    pub connect_time: Option<Duration>,
+    /// The smoothed round trip time measured by the TCP stack.
+    /// Only available on Linux, and not when using a ProxyCommand.
+    pub rtt: Option<Duration>,
+}
+
 #[derive(Clone)]
 pub struct Session {
     tx: SessionSender,
+    error: Arc<Mutex<Option<String>>>,
+    stats: Arc<Mutex<SessionStats>>,
+}
+
+impl std::fmt::Debug for Session {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "Session {{...}}")
+    }
 }
 
 impl Drop for Session {
@@ -89,6 +171,8 @@ impl Session {
         let (tx_event, rx_event) = bounded(8);
         let (tx_req, rx_req) = bounded(8);
         let (mut sender_write, mut sender_read) = socketpair()?;
+        let error = Arc::new(Mutex::new(None));
+        let stats = Arc::new(Mutex::new(SessionStats::default()));
         sender_write.set_non_blocking(true)?;
         sender_read.set_non_blocking(true)?;
 
@@ -107,11 +191,50 @@ impl Session {
             next_channel_id: 1,
             next_file_id: 1,
             sender_read,
+            remote_forwards: HashMap::new(),
+            pending_remote_channels: HashMap::new(),
+            next_pending_remote_channel: 1,
+            local_forwards: HashMap::new(),
+            error: Arc::clone(&error),
+            stats: Arc::clone(&stats),
+            stats_socket: None,
+            stats_sampled: Instant::now(),
+            bytes_sent: 0,
+            bytes_received: 0,
             session_was_dropped: false,
             shown_accept_env_error: false,
         };
         std::thread::spawn(move || inner.run());
-        Ok((Self { tx: session_sender }, rx_event))
+        Ok((
+            Self {
+                tx: session_sender,
+                error,
+                stats,
+            },
+            rx_event,
+        ))
+    }
+
+    /// Returns true if the session is still running.
+    /// Once this returns false, any further requests will fail
+    /// with `DeadSession`.
+    pub fn is_connected(&self) -> bool {
+        !self.tx.tx.is_closed()
+    }
+
+    /// If the session terminated because of an error, such as the
+    /// connection to the server being lost, returns a description
+    /// of that error.
+    /// Channels of a session that failed in this way are closed
+    /// without reporting an exit status.
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+
+    /// Returns statistics about the traffic carried by the session.
+    /// They are sampled about once per second.
+    pub fn stats(&self) -> SessionStats {
+        self.stats.lock().unwrap().clone()
     }
 
     pub async fn request_pty(
@@ -161,6 +284,13 @@ impl Session {
         Ok(exec)
     }
 
+    /// Establishes a port forward over this session.
+    /// The forward remains active until the returned `PortForward`
+    /// is dropped.
+    pub async fn forward(&self, spec: ForwardSpec) -> anyhow::Result<PortForward> {
+        start_forward(&self.tx, spec).await
+    }
+
     /// Creates a new reference to the sftp channel for filesystem operations
     ///
     /// ### Note
@@ -2,8 +2,12 @@ use crate::channelwrap::ChannelWrap;
 use crate::config::ConfigMap;
 use crate::dirwrap::DirWrap;
 use crate::filewrap::FileWrap;
+use crate::forward::forward_connection;
 use crate::pty::*;
-use crate::session::{Exec, ExecResult, SessionEvent, SessionRequest, SignalChannel};
+use crate::session::{
+    DirectTcpIp, Exec, ExecResult, LocalForward, RemoteForward, SessionEvent, SessionRequest,
+    SessionStats, SignalChannel,
+};
 use crate::sessionwrap::SessionWrap;
 use crate::sftp::dir::{Dir, DirId, DirRequest};
 use crate::sftp::file::{File, FileId, FileRequest};
@@ -19,8 +23,10 @@ use smol::channel::{bounded, Receiver, Sender, TryRecvError};
 use socket2::{Domain, Socket, Type};
 use std::collections::{HashMap, VecDeque};
 use std::io::{Read, Write};
-use std::net::ToSocketAddrs;
-use std::time::Duration;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub(crate) struct DescriptorState {
@@ -48,6 +54,26 @@ pub(crate) struct SessionInner {
     pub next_channel_id: ChannelId,
     pub next_file_id: FileId,
     pub sender_read: FileDescriptor,
+    /// Active remote forwards, keyed by the port bound on the server
+    pub remote_forwards: HashMap<u16, RemoteForward>,
+    /// Channels of remote forwards that are waiting for the
+    /// connection to their destination to be made
+    pub pending_remote_channels: HashMap<usize, ChannelWrap>,
+    pub next_pending_remote_channel: usize,
+    /// Active local and dynamic forwards, keyed by the address
+    /// that they are listening on
+    pub local_forwards: HashMap<SocketAddr, LocalForward>,
+    /// Shared with `Session` so that the reason for the session
+    /// failing can be queried after the fact
+    pub error: Arc<Mutex<Option<String>>>,
+    /// Shared with `Session`, and refreshed by `update_stats`
+    pub stats: Arc<Mutex<SessionStats>>,
+    /// A duplicate of the connection to the server, used to
+    /// query the round trip time
+    pub stats_socket: Option<Socket>,
+    pub stats_sampled: Instant,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
     pub session_was_dropped: bool,
     pub shown_accept_env_error: bool,
 }
@@ -61,13 +87,28 @@ impl Drop for SessionInner {
 impl SessionInner {
     pub fn run(&mut self) {
         if let Err(err) = self.run_impl() {
-            self.tx_event
-                .try_send(SessionEvent::Error(format!("{:#}", err)))
-                .ok();
+            let err = format!("{:#}", err);
+            self.error.lock().unwrap().replace(err.clone());
+            self.tx_event.try_send(SessionEvent::Error(err)).ok();
         }
+        // Stop accepting requests before our channels are closed as
+        // we are dropped, so that someone that sees EOF on a channel
+        // can tell whether the session is still alive
+        self.rx_req.close();
     }
 
     fn run_impl(&mut self) -> anyhow::Result<()> {
+        match self.config.get("updatehostkeys").map(|s| s.as_str()) {
+            None | Some("no") => {}
+            Some(value) => {
+                // Not implemented: neither ssh2 nor libssh-rs lets us
+                // handle the `hostkeys-00@openssh.com` global request or
+                // send `hostkeys-prove-00@openssh.com`, so we can't learn
+                // or verify the keys that the server advertises.
+                log::warn!("ignoring unsupported option UpdateHostKeys {value}");
+            }
+        }
+
         let backend = self
             .config
             .get("wezterm_ssh_backend")
@@ -184,6 +225,10 @@ impl SessionInner {
         }
         if let Some(files) = self.config.get("identityfile") {
             for file in files.split_whitespace() {
+                // Security keys are tried separately by authenticate_libssh
+                if crate::auth::security_key_type_for_identity(file).is_some() {
+                    continue;
+                }
                 sess.set_option(libssh_rs::SshOption::AddIdentity(file.to_string()))?;
             }
         }
@@ -201,12 +246,11 @@ impl SessionInner {
         if let Some(bind_addr) = self.config.get("bindaddress") {
             sess.set_option(libssh_rs::SshOption::BindAddress(bind_addr.to_string()))?;
         }
-        if let Some(host_key) = self.config.get("hostkeyalgorithms") {
-            sess.set_option(libssh_rs::SshOption::HostKeys(host_key.to_string()))?;
-        }
+        self.apply_algorithm_preferences_libssh(&sess)?;
 
         let sock =
             self.connect_to_host(&hostname, port, verbose, self.config.get("proxycommand"))?;
+        self.stats_socket = sock.try_clone().ok();
         let raw = {
             #[cfg(unix)]
             {
@@ -290,11 +334,13 @@ impl SessionInner {
 
         let sock =
             self.connect_to_host(&hostname, port, verbose, self.config.get("proxycommand"))?;
+        self.stats_socket = sock.try_clone().ok();
 
         let mut sess = ssh2::Session::new()?;
         if verbose {
             sess.trace(ssh2::TraceFlags::all());
         }
+        self.apply_algorithm_preferences_ssh2(&sess)?;
         sess.set_blocking(true);
         sess.set_tcp_stream(sock);
         sess.handshake()
@@ -320,6 +366,88 @@ impl SessionInner {
         self.request_loop(&mut sess)
     }
 
+    /// Apply the algorithm preference lists from the config.
+    /// libssh understands the ssh_config list syntax for itself, and
+    /// rejects lists that name no algorithms that it supports.
+    #[cfg(feature = "libssh-rs")]
+    fn apply_algorithm_preferences_libssh(&self, sess: &libssh_rs::Session) -> anyhow::Result<()> {
+        use libssh_rs::SshOption;
+        let options: &[(&str, &dyn Fn(String) -> Vec<SshOption>)] = &[
+            ("kexalgorithms", &|v| vec![SshOption::KeyExchange(v)]),
+            ("hostkeyalgorithms", &|v| vec![SshOption::HostKeys(v)]),
+            ("ciphers", &|v| {
+                vec![SshOption::CiphersCS(v.clone()), SshOption::CiphersSC(v)]
+            }),
+            ("macs", &|v| {
+                vec![SshOption::HmacCS(v.clone()), SshOption::HmacSC(v)]
+            }),
+        ];
+        for (option, make_options) in options {
+            if let Some(value) = self.config.get(*option) {
+                for opt in make_options(value.to_string()) {
+                    sess.set_option(opt)
+                        .with_context(|| format!("setting {option} to {value}"))?;
+                }
+            }
+        }
+        if self.config.contains_key("compression") {
+            let compression =
+                crate::algorithms::compression_enabled(self.config.get("compression"))?;
+            set_compression_libssh(sess, compression)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the algorithm preference lists and compression setting
+    /// from the config; this must happen prior to the handshake.
+    #[cfg(feature = "ssh2")]
+    fn apply_algorithm_preferences_ssh2(&self, sess: &ssh2::Session) -> anyhow::Result<()> {
+        use crate::algorithms::{compression_enabled, resolve_algorithms};
+        use ssh2::MethodType;
+        let options: &[(&str, &[MethodType])] = &[
+            ("kexalgorithms", &[MethodType::Kex]),
+            ("hostkeyalgorithms", &[MethodType::HostKey]),
+            ("ciphers", &[MethodType::CryptCs, MethodType::CryptSc]),
+            ("macs", &[MethodType::MacCs, MethodType::MacSc]),
+        ];
+        for (option, methods) in options {
+            if let Some(value) = self.config.get(*option) {
+                for method in methods.iter() {
+                    // libssh2 enables every algorithm that it supports
+                    // by default
+                    let supported = sess.supported_algs(*method)?;
+                    let prefs = resolve_algorithms(option, value, &supported, &supported)?;
+                    sess.method_pref(*method, &prefs)
+                        .with_context(|| format!("setting {option} to {prefs}"))?;
+                }
+            }
+        }
+        sess.set_compress(compression_enabled(self.config.get("compression"))?);
+
+        // As with OpenSSH, ask for a host certificate when the known
+        // hosts file lists a certificate authority for this host.
+        // libssh2 would otherwise prefer the plain host key.
+        if !self.config.contains_key("hostkeyalgorithms") {
+            let port = self.config.get("port").and_then(|p| p.parse::<u16>().ok());
+            if let (Some(hostname), Some(port)) = (self.config.get("hostname"), port) {
+                let markers = crate::certificate::KnownHostMarkers::load(
+                    self.config.get("userknownhostsfile"),
+                    &crate::host::known_hosts_name(hostname, port),
+                );
+                if markers.has_cert_authority() {
+                    let supported = sess.supported_algs(MethodType::HostKey)?;
+                    let (mut prefs, plain): (Vec<&str>, Vec<&str>) = supported
+                        .into_iter()
+                        .partition(|alg| alg.ends_with("-cert-v01@openssh.com"));
+                    prefs.extend(plain);
+                    sess.method_pref(MethodType::HostKey, &prefs.join(","))
+                        .context("preferring host certificates")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Explicitly and directly connect to the requested host because
     /// neither libssh no libssh2 respect addressfamily, so we must
     /// handle it for ourselves.
@@ -390,8 +518,10 @@ impl SessionInner {
                 .with_context(|| format!("binding to {bind_addr:?}"))?;
         }
 
+        let start = Instant::now();
         sock.connect(&addr.into())
             .with_context(|| format!("Connecting to {hostname}:{port} ({addr:?})"))?;
+        self.stats.lock().unwrap().connect_time = Some(start.elapsed());
         Ok(sock)
     }
 
@@ -413,6 +543,9 @@ impl SessionInner {
             self.drain_request_pipe();
             self.dispatch_pending_requests(sess)?;
             self.connect_pending_agent_forward_channels(sess);
+            self.connect_pending_remote_forward_channels(sess);
+            self.accept_local_forward_connections();
+            self.update_stats();
 
             if self.channels.is_empty() && self.session_was_dropped {
                 log::trace!(
@@ -454,14 +587,24 @@ impl SessionInner {
                 }
             }
 
-            poll(&mut poll_array, Some(sleep_delay)).context("poll")?;
+            let first_listener = poll_array.len();
+            for forward in self.local_forwards.values() {
+                poll_array.push(pollfd {
+                    fd: forward.listener.as_socket_descriptor(),
+                    events: POLLIN,
+                    revents: 0,
+                });
+            }
+
+            // Wake up at least as often as the stats are sampled
+            poll(&mut poll_array, Some(sleep_delay.min(STATS_INTERVAL))).context("poll")?;
             sleep_delay += sleep_delay;
 
             for (idx, poll) in poll_array.iter().enumerate() {
                 if poll.revents != 0 {
                     sleep_delay = Duration::from_millis(100);
                 }
-                if idx == 0 || idx == 1 {
+                if idx == 0 || idx == 1 || idx >= first_listener {
                     // Dealt with at the top of the loop
                 } else if poll.revents != 0 {
                     let (channel_id, fd_num) = mapping[idx - 2];
@@ -525,9 +668,10 @@ impl SessionInner {
 
             let stdin = &mut chan.descriptors[0];
             if stdin.fd.is_some() && !stdin.buf.is_empty() {
-                if let Err(err) = write_from_buf(&mut chan.channel.writer(), &mut stdin.buf)
-                    .context("writing to channel")
-                {
+                let pending = stdin.buf.len();
+                let result = write_from_buf(&mut chan.channel.writer(), &mut stdin.buf);
+                self.bytes_sent += (pending - stdin.buf.len()) as u64;
+                if let Err(err) = result.context("writing to channel") {
                     log::trace!(
                         "Failed to write data to channel {} stdin: {:#}, closing pipe",
                         id,
@@ -552,7 +696,9 @@ impl SessionInner {
                 if room == 0 {
                     continue;
                 }
-                match read_into_buf(&mut chan.channel.reader(idx), &mut out.buf) {
+                let result = read_into_buf(&mut chan.channel.reader(idx), &mut out.buf);
+                self.bytes_received += (out.buf.len() - current_len) as u64;
+                match result {
                     Ok(_) => {}
                     Err(err) => {
                         if out.buf.is_empty() {
@@ -591,6 +737,26 @@ impl SessionInner {
         Ok(())
     }
 
+    /// Refresh the stats that are shared with `Session`,
+    /// at most once per `STATS_INTERVAL`
+    fn update_stats(&mut self) {
+        let elapsed = self.stats_sampled.elapsed();
+        if elapsed < STATS_INTERVAL {
+            return;
+        }
+        self.stats_sampled = Instant::now();
+
+        let mut stats = self.stats.lock().unwrap();
+        let rate = |bytes: u64| (bytes as f64 / elapsed.as_secs_f64()) as u64;
+        stats.send_rate = rate(self.bytes_sent - stats.bytes_sent);
+        stats.receive_rate = rate(self.bytes_received - stats.bytes_received);
+        stats.bytes_sent = self.bytes_sent;
+        stats.bytes_received = self.bytes_received;
+        stats.open_channels = self.channels.len();
+        stats.total_channels = self.next_channel_id - 1;
+        stats.rtt = self.stats_socket.as_ref().and_then(tcp_rtt);
+    }
+
     fn drain_request_pipe(&mut self) {
         let mut buf = [0u8; 16];
         let _ = self.sender_read.read(&mut buf);
@@ -633,6 +799,36 @@ impl SessionInner {
                         }
                         Ok(true)
                     }
+                    SessionRequest::DirectTcpIp(msg, reply) => {
+                        dispatch(reply, || self.direct_tcpip(sess, msg), "direct_tcpip")
+                    }
+                    SessionRequest::RemoteForward(msg, reply) => {
+                        dispatch(reply, || self.remote_forward(sess, msg), "remote_forward")
+                    }
+                    SessionRequest::CancelRemoteForward(port) => {
+                        self.remote_forwards.remove(&port);
+                        sess.cancel_forward(port);
+                        Ok(true)
+                    }
+                    SessionRequest::RemoteForwardConnected(pending_id, result) => {
+                        if let Err(err) = self.remote_forward_connected(pending_id, result) {
+                            log::error!("error connecting remote forward: {:#}", err);
+                        }
+                        Ok(true)
+                    }
+                    SessionRequest::LocalForward(forward) => {
+                        match forward.listener.local_addr() {
+                            Ok(addr) => {
+                                self.local_forwards.insert(addr, forward);
+                            }
+                            Err(err) => log::error!("{}: {:#}", forward.spec, err),
+                        }
+                        Ok(true)
+                    }
+                    SessionRequest::CancelLocalForward(addr) => {
+                        self.local_forwards.remove(&addr);
+                        Ok(true)
+                    }
                     SessionRequest::Sftp(SftpRequest::OpenWithMode(msg, reply)) => {
                         dispatch(reply, || self.open_with_mode(sess, &msg), "OpenWithMode")
                     }
@@ -876,6 +1072,180 @@ impl SessionInner {
         }
     }
 
+    /// Register a channel whose data is relayed to and from `fd`
+    fn add_forwarded_channel(
+        &mut self,
+        channel: ChannelWrap,
+        mut fd: FileDescriptor,
+        preamble: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        fd.set_non_blocking(true)?;
+
+        let read_from_fd = fd;
+        let write_to_fd = read_from_fd.try_clone()?;
+        let mut write_buf = VecDeque::with_capacity(8192);
+        write_buf.extend(preamble);
+
+        let channel_id = self.next_channel_id;
+        self.next_channel_id += 1;
+        let info = ChannelInfo {
+            channel_id,
+            channel,
+            exit: None,
+            exited: false,
+            descriptors: [
+                DescriptorState {
+                    fd: Some(read_from_fd),
+                    buf: VecDeque::with_capacity(8192),
+                },
+                DescriptorState {
+                    fd: Some(write_to_fd),
+                    buf: write_buf,
+                },
+                DescriptorState {
+                    fd: None,
+                    buf: VecDeque::with_capacity(8192),
+                },
+            ],
+        };
+        self.channels.insert(channel_id, info);
+        Ok(())
+    }
+
+    fn direct_tcpip(&mut self, sess: &mut SessionWrap, msg: DirectTcpIp) -> anyhow::Result<()> {
+        let channel = sess
+            .open_direct_tcpip(
+                &msg.host,
+                msg.port,
+                &msg.originator_host,
+                msg.originator_port,
+            )
+            .with_context(|| format!("opening channel to {}:{}", msg.host, msg.port))?;
+        self.add_forwarded_channel(channel, msg.fd, msg.preamble)
+    }
+
+    fn remote_forward(
+        &mut self,
+        sess: &mut SessionWrap,
+        msg: RemoteForward,
+    ) -> anyhow::Result<u16> {
+        let bind_address = match msg.bind_address.as_deref() {
+            Some("*") => Some(""),
+            addr => addr,
+        };
+        let bound_port = sess
+            .listen_forward(bind_address, msg.bind_port)
+            .with_context(|| format!("requesting remote forward of port {}", msg.bind_port))?;
+        self.remote_forwards.insert(bound_port, msg);
+        Ok(bound_port)
+    }
+
+    fn connect_pending_remote_forward_channels(&mut self, sess: &mut SessionWrap) {
+        while let Some((port, mut channel)) = sess.accept_forward() {
+            let forward = match self.remote_forwards.get(&port) {
+                Some(forward) => forward.clone(),
+                None => {
+                    log::warn!("closing connection to unknown remote forward port {}", port);
+                    channel.close();
+                    continue;
+                }
+            };
+            forward.connections.fetch_add(1, Ordering::Relaxed);
+
+            // Connecting can take a while, so it is done on another
+            // thread which hands the connection back to the session
+            // loop via `RemoteForwardConnected`
+            let pending_id = self.next_pending_remote_channel;
+            self.next_pending_remote_channel += 1;
+            self.pending_remote_channels.insert(pending_id, channel);
+
+            std::thread::spawn(move || {
+                let result = TcpStream::connect((forward.host.as_str(), forward.host_port))
+                    .with_context(|| {
+                        format!(
+                            "connecting port {} to {}:{}",
+                            port, forward.host, forward.host_port
+                        )
+                    })
+                    .map(|stream| {
+                        #[cfg(unix)]
+                        {
+                            FileDescriptor::new(stream)
+                        }
+                        #[cfg(windows)]
+                        unsafe {
+                            use std::os::windows::io::{FromRawSocket, IntoRawSocket};
+                            FileDescriptor::from_raw_socket(stream.into_raw_socket())
+                        }
+                    });
+                smol::block_on(
+                    forward
+                        .tx
+                        .send(SessionRequest::RemoteForwardConnected(pending_id, result)),
+                )
+                .ok();
+            });
+        }
+    }
+
+    fn remote_forward_connected(
+        &mut self,
+        pending_id: usize,
+        result: anyhow::Result<FileDescriptor>,
+    ) -> anyhow::Result<()> {
+        let mut channel = self
+            .pending_remote_channels
+            .remove(&pending_id)
+            .ok_or_else(|| anyhow!("no pending remote forward channel {}", pending_id))?;
+        match result {
+            Ok(fd) => self.add_forwarded_channel(channel, fd, vec![]),
+            Err(err) => {
+                channel.close();
+                Err(err)
+            }
+        }
+    }
+
+    /// Accept the connections that are waiting on the listeners of
+    /// the local and dynamic forwards.
+    /// Each one is set up on a thread of its own, as that involves
+    /// waiting for the session loop to open its channel.
+    fn accept_local_forward_connections(&mut self) {
+        let mut failed = vec![];
+        for (addr, forward) in &self.local_forwards {
+            loop {
+                match forward.listener.accept() {
+                    Ok((stream, peer)) => {
+                        forward.connections.fetch_add(1, Ordering::Relaxed);
+                        let spec = forward.spec.clone();
+                        let tx = forward.tx.clone();
+                        std::thread::spawn(move || {
+                            if let Err(err) = forward_connection(stream, peer, &spec, &tx) {
+                                log::error!(
+                                    "{}: forwarding connection from {}: {:#}",
+                                    spec,
+                                    peer,
+                                    err
+                                );
+                            }
+                        });
+                    }
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        log::error!("{}: accept failed: {:#}", forward.spec, err);
+                        failed.push(*addr);
+                        break;
+                    }
+                }
+            }
+        }
+        for addr in failed {
+            if let Some(forward) = self.local_forwards.remove(&addr) {
+                log::trace!("{}: stopped listening", forward.spec);
+            }
+        }
+    }
+
     pub fn signal_channel(&mut self, info: &SignalChannel) -> anyhow::Result<()> {
         let chan_info = self
             .channels
@@ -1032,6 +1402,70 @@ impl SessionInner {
     }
 }
 
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Returns the kernel's smoothed estimate of the round trip time
+/// for a TCP connection
+#[cfg(target_os = "linux")]
+fn tcp_rtt(sock: &Socket) -> Option<Duration> {
+    use std::os::unix::io::AsRawFd;
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            sock.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut libc::tcp_info as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if res != 0 {
+        // Most likely not a TCP socket because a ProxyCommand is in use
+        return None;
+    }
+    Some(Duration::from_micros(info.tcpi_rtt as u64))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn tcp_rtt(_sock: &Socket) -> Option<Duration> {
+    None
+}
+
+/// libssh-rs doesn't expose the compression option, but libssh
+/// applies the `Compression` keyword from a config file, so the
+/// setting is passed to it via a temporary one.
+#[cfg(feature = "libssh-rs")]
+fn set_compression_libssh(sess: &libssh_rs::Session, compression: bool) -> anyhow::Result<()> {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "wezterm-ssh-compression-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .with_context(|| format!("creating {}", path.display()))?;
+    let result = writeln!(
+        file,
+        "Compression {}",
+        if compression { "yes" } else { "no" }
+    )
+    .map_err(anyhow::Error::from)
+    .and_then(|_| {
+        let path = path
+            .to_str()
+            .ok_or_else(|| anyhow!("{} is not valid UTF-8", path.display()))?;
+        sess.options_parse_config(Some(path))
+            .context("applying the Compression option")
+    });
+    drop(file);
+    std::fs::remove_file(&path).ok();
+    result
+}
+
 fn write_from_buf<W: Write>(w: &mut W, buf: &mut VecDeque<u8>) -> std::io::Result<()> {
     match w.write(buf.make_contiguous()) {
         Ok(len) => {
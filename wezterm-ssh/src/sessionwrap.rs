@@ -6,6 +6,8 @@ use filedescriptor::{AsRawSocketDescriptor, SocketDescriptor, POLLIN, POLLOUT};
 pub(crate) struct Ssh2Session {
     pub sess: ssh2::Session,
     pub sftp: Option<SftpWrap>,
+    /// Remote port forwards, keyed by the port bound on the server
+    pub listeners: Vec<(u16, ssh2::Listener)>,
 }
 
 #[cfg(feature = "libssh-rs")]
@@ -25,7 +27,11 @@ pub(crate) enum SessionWrap {
 impl SessionWrap {
     #[cfg(feature = "ssh2")]
     pub fn with_ssh2(sess: ssh2::Session) -> Self {
-        Self::Ssh2(Ssh2Session { sess, sftp: None })
+        Self::Ssh2(Ssh2Session {
+            sess,
+            sftp: None,
+            listeners: vec![],
+        })
     }
 
     #[cfg(feature = "libssh-rs")]
@@ -104,4 +110,87 @@ impl SessionWrap {
             Self::LibSsh(sess) => sess.sess.accept_agent_forward().map(ChannelWrap::LibSsh),
         }
     }
+
+    pub fn open_direct_tcpip(
+        &self,
+        host: &str,
+        port: u16,
+        originator_host: &str,
+        originator_port: u16,
+    ) -> anyhow::Result<ChannelWrap> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => {
+                let channel = sess.sess.channel_direct_tcpip(
+                    host,
+                    port,
+                    Some((originator_host, originator_port)),
+                )?;
+                Ok(ChannelWrap::Ssh2(channel))
+            }
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => {
+                let channel = sess.sess.new_channel()?;
+                channel.open_forward(host, port, originator_host, originator_port)?;
+                Ok(ChannelWrap::LibSsh(channel))
+            }
+        }
+    }
+
+    /// Ask the server to listen on the specified port, returning
+    /// the port that it actually bound
+    pub fn listen_forward(&mut self, bind_address: Option<&str>, port: u16) -> anyhow::Result<u16> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => {
+                let (listener, bound_port) =
+                    sess.sess.channel_forward_listen(port, bind_address, None)?;
+                sess.listeners.push((bound_port, listener));
+                Ok(bound_port)
+            }
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => {
+                let bound_port = sess.sess.listen_forward(bind_address, port)?;
+                Ok(if bound_port == 0 { port } else { bound_port })
+            }
+        }
+    }
+
+    pub fn cancel_forward(&mut self, port: u16) {
+        match self {
+            // Dropping the listener cancels the forward
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => sess.listeners.retain(|(bound_port, _)| *bound_port != port),
+
+            // libssh doesn't expose a way to cancel; the caller
+            // will close any channels that arrive for the port
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(_sess) => {}
+        }
+    }
+
+    /// Returns the next pending connection to a remote forward,
+    /// along with the port on the server that it arrived on
+    pub fn accept_forward(&mut self) -> Option<(u16, ChannelWrap)> {
+        match self {
+            #[cfg(feature = "ssh2")]
+            Self::Ssh2(sess) => {
+                for (bound_port, listener) in sess.listeners.iter_mut() {
+                    if let Ok(channel) = listener.accept() {
+                        return Some((*bound_port, ChannelWrap::Ssh2(channel)));
+                    }
+                }
+                None
+            }
+
+            #[cfg(feature = "libssh-rs")]
+            Self::LibSsh(sess) => sess
+                .sess
+                .accept_forward(std::time::Duration::from_millis(0))
+                .ok()
+                .map(|(port, channel)| (port, ChannelWrap::LibSsh(channel))),
+        }
+    }
 }
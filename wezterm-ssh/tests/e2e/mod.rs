@@ -1,2 +1,3 @@
 mod agent_forward;
+mod port_forward;
 mod sftp;
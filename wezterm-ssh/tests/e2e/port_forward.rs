@@ -0,0 +1,63 @@
+use crate::sshd::*;
+use rstest::*;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use wezterm_ssh::ForwardSpec;
+
+/// Starts a server on the loopback address that echoes back
+/// whatever its clients send, returning its port
+fn echo_server() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            std::thread::spawn(move || {
+                let mut reader = stream.try_clone().unwrap();
+                std::io::copy(&mut reader, &mut stream).ok();
+            });
+        }
+    });
+    port
+}
+
+fn assert_echoes(port: u16, message: &[u8]) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.write_all(message).unwrap();
+    let mut reply = vec![0u8; message.len()];
+    stream.read_exact(&mut reply).unwrap();
+    assert_eq!(reply, message);
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn local_forward_should_relay_each_connection(#[future] session: SessionWithSshd) {
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+        let echo_port = echo_server();
+
+        let spec: ForwardSpec = format!("L0:127.0.0.1:{}", echo_port).parse().unwrap();
+        let forward = session.forward(spec).await.unwrap();
+
+        assert_echoes(forward.bound_port(), b"hello");
+        assert_echoes(forward.bound_port(), b"world");
+        assert_eq!(forward.connection_count(), 2);
+    })
+}
+
+#[rstest]
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), ignore)]
+fn remote_forward_should_relay_each_connection(#[future] session: SessionWithSshd) {
+    smol::block_on(async {
+        let session: SessionWithSshd = session.await;
+        let echo_port = echo_server();
+
+        let spec: ForwardSpec = format!("R0:127.0.0.1:{}", echo_port).parse().unwrap();
+        let forward = session.forward(spec).await.unwrap();
+
+        // sshd is listening on the same host as us
+        assert_echoes(forward.bound_port(), b"hello");
+        assert_echoes(forward.bound_port(), b"world");
+        assert_eq!(forward.connection_count(), 2);
+    })
+}
@@ -513,6 +513,9 @@ pub async fn session(#[default(Config::new())] mut config: Config, sshd: Sshd) -
             SessionEvent::HostVerificationFailed(failed) => {
                 panic!("{}", failed);
             }
+            SessionEvent::SecurityKeyTouch(touch) => {
+                eprintln!("{}", touch.message());
+            }
             SessionEvent::Error(err) => {
                 panic!("{}", err);
             }
@@ -1,7 +1,8 @@
 use anyhow::Context;
 use chrono::serde::ts_seconds_option;
 use chrono::{DateTime, Utc};
-use clap::Parser;
+use clap::builder::ValueParser;
+use clap::{Parser, ValueHint};
 use config::ConfigHandle;
 use filedescriptor::FileDescriptor;
 use portable_pty::{native_pty_system, PtySize};
@@ -10,7 +11,7 @@ use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::PathBuf;
-use std::sync::mpsc::channel;
+use std::sync::mpsc::{channel, RecvTimeoutError};
 use std::time::{Duration, Instant};
 use termwiz::escape::parser::Parser as TWParser;
 use termwiz::escape::Action;
@@ -59,7 +60,7 @@ pub struct Header {
 }
 
 impl Header {
-    fn new(config: &ConfigHandle, size: PtySize, prog: &[&OsStr]) -> Self {
+    pub(crate) fn new(config: &ConfigHandle, size: PtySize, prog: &[&OsStr]) -> Self {
         let mut env = HashMap::new();
         env.insert("TERM".to_string(), config.term.to_string());
         env.insert(
@@ -126,10 +127,56 @@ pub struct Theme {
 pub struct Event(pub f32, pub String, pub String);
 
 impl Event {
-    fn log_output<W: Write>(mut w: W, elapsed: f32, output: &str) -> std::io::Result<()> {
-        let event = Event(elapsed, "o".to_string(), output.to_string());
+    fn log<W: Write>(mut w: W, elapsed: f32, code: &str, data: &str) -> std::io::Result<()> {
+        let event = Event(elapsed, code.to_string(), data.to_string());
         writeln!(w, "{}", serde_json::to_string(&event)?)
     }
+
+    pub(crate) fn log_output<W: Write>(w: W, elapsed: f32, output: &str) -> std::io::Result<()> {
+        Self::log(w, elapsed, "o", output)
+    }
+
+    fn log_input<W: Write>(w: W, elapsed: f32, input: &str) -> std::io::Result<()> {
+        Self::log(w, elapsed, "i", input)
+    }
+
+    fn log_resize<W: Write>(w: W, elapsed: f32, size: PtySize) -> std::io::Result<()> {
+        Self::log(w, elapsed, "r", &format!("{}x{}", size.cols, size.rows))
+    }
+}
+
+/// Accumulates bytes that may end with an incomplete utf8 sequence
+/// that straddles a buffer boundary.  JSON requires strings to be
+/// utf8, so only the currently-valid portion is returned and the
+/// remainder is buffered up until more data arrives.
+#[derive(Default)]
+struct Utf8Buffer {
+    buffer: Vec<u8>,
+}
+
+impl Utf8Buffer {
+    fn push(&mut self, data: &[u8]) -> String {
+        self.buffer.extend_from_slice(data);
+        match std::str::from_utf8(&self.buffer) {
+            Ok(valid) => {
+                let valid = valid.to_string();
+                self.buffer.clear();
+                valid
+            }
+            Err(error) => {
+                let valid_len = error.valid_up_to();
+                let valid = String::from_utf8_lossy(&self.buffer[0..valid_len]).to_string();
+
+                self.buffer.drain(0..valid_len);
+
+                if let Some(invalid_sequence_length) = error.error_len() {
+                    // Invalid sequence: skip it
+                    self.buffer.drain(0..invalid_sequence_length);
+                }
+                valid
+            }
+        }
+    }
 }
 
 #[cfg(windows)]
@@ -342,6 +389,25 @@ pub struct RecordCommand {
     #[arg(long)]
     cwd: Option<std::path::PathBuf>,
 
+    /// Write the recording to the specified file, rather than
+    /// to a newly created file in the temporary directory
+    #[arg(long, short, value_hint=ValueHint::FilePath)]
+    output: Option<PathBuf>,
+
+    /// Also record the keys that you type.
+    /// Take care: this will capture any passwords that you type!
+    #[arg(long)]
+    input: bool,
+
+    /// A title to include in the recording
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Suggest that players limit the delay between events to
+    /// at most this number of seconds
+    #[arg(long)]
+    idle_time_limit: Option<f32>,
+
     /// Start prog instead of the default_prog defined by your
     /// wezterm configuration
     #[arg(value_parser)]
@@ -353,16 +419,25 @@ impl RecordCommand {
         let prog = self.prog.iter().map(|s| s.as_os_str()).collect::<Vec<_>>();
 
         let mut tty = Tty::new()?;
-        let size = tty.get_size()?;
-
-        let header = Header::new(&config, size, &prog);
-
-        let (cast_file, cast_file_name) = tempfile::Builder::new()
-            .prefix("wezterm-recording-")
-            // We use a .txt suffix for convenice when uploading to GH
-            .suffix(".cast.txt")
-            .tempfile()?
-            .keep()?;
+        let mut size = tty.get_size()?;
+
+        let mut header = Header::new(&config, size, &prog);
+        header.title = self.title.clone();
+        header.idle_time_limit = self.idle_time_limit;
+
+        let (cast_file, cast_file_name) = match &self.output {
+            Some(path) => (
+                std::fs::File::create(path)
+                    .with_context(|| format!("creating {}", path.display()))?,
+                path.clone(),
+            ),
+            None => tempfile::Builder::new()
+                .prefix("wezterm-recording-")
+                // We use a .txt suffix for convenice when uploading to GH
+                .suffix(".cast.txt")
+                .tempfile()?
+                .keep()?,
+        };
         let mut cast_file = BufWriter::new(cast_file);
         writeln!(cast_file, "{}", serde_json::to_string(&header)?)?;
 
@@ -429,44 +504,41 @@ impl RecordCommand {
 
         let mut child_status = None;
         let first_output = Instant::now();
-        let mut buffer = vec![];
+        let mut output = Utf8Buffer::default();
+        let mut input = Utf8Buffer::default();
         let mut writer = pair.master.take_writer()?;
 
-        for msg in rx {
+        loop {
+            // Wake up periodically so that we notice when the
+            // terminal is resized, even if nothing is happening
+            let msg = match rx.recv_timeout(Duration::from_millis(250)) {
+                Ok(msg) => Some(msg),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            let elapsed = first_output.elapsed().as_secs_f32();
+
+            if let Ok(new_size) = tty.get_size() {
+                if (new_size.rows, new_size.cols) != (size.rows, size.cols) {
+                    size = new_size;
+                    pair.master.resize(size)?;
+                    Event::log_resize(&mut cast_file, elapsed, size)?;
+                }
+            }
+
             match msg {
-                Message::Stdin(data) => {
+                None => {}
+                Some(Message::Stdin(data)) => {
                     writer.write_all(&data)?;
+                    if self.input {
+                        Event::log_input(&mut cast_file, elapsed, &input.push(&data))?;
+                    }
                 }
-                Message::Stdout(mut data) => {
-                    let elapsed = first_output.elapsed().as_secs_f32();
+                Some(Message::Stdout(data)) => {
                     tty.write_all(&data)?;
-
-                    // The end of the data may be an incomplete utf8 sequence
-                    // that straddles the buffer boundary.  JSON requires strings
-                    // to be utf-8 so we need to send the currently-valid portions
-                    // through to the .cast file and buffer up the remainder
-                    buffer.append(&mut data);
-                    match std::str::from_utf8(&buffer) {
-                        Ok(valid) => {
-                            Event::log_output(&mut cast_file, elapsed, valid)?;
-                            buffer.clear();
-                        }
-                        Err(error) => {
-                            let valid_len = error.valid_up_to();
-                            Event::log_output(&mut cast_file, elapsed, unsafe {
-                                std::str::from_utf8_unchecked(&buffer[0..valid_len])
-                            })?;
-
-                            buffer.drain(0..valid_len);
-
-                            if let Some(invalid_sequence_length) = error.error_len() {
-                                // Invalid sequence: skip it
-                                buffer.drain(0..invalid_sequence_length);
-                            }
-                        }
-                    }
+                    Event::log_output(&mut cast_file, elapsed, &output.push(&data))?;
                 }
-                Message::Terminated(status) => {
+                Some(Message::Terminated(status)) => {
                     child_status.replace(status);
                     break;
                 }
@@ -496,9 +568,123 @@ pub struct PlayCommand {
     #[arg(long, conflicts_with = "explain")]
     cat: bool,
 
+    /// Play back at this multiple of the recorded speed.
+    /// While playing, press `+` or `-` to double or halve the speed,
+    /// space to pause or resume, `.` to advance by a single event
+    /// while paused, and `q` to stop.
+    #[arg(long, default_value = "1.0", value_parser=ValueParser::new(playback_speed))]
+    speed: f32,
+
+    /// Limit the delay between events to at most this number of
+    /// seconds, overriding any limit specified by the recording
+    #[arg(long)]
+    idle_time_limit: Option<f32>,
+
+    /// Start out paused
+    #[arg(long)]
+    paused: bool,
+
+    #[arg(value_hint=ValueHint::FilePath)]
     cast_file: PathBuf,
 }
 
+fn playback_speed(arg: &str) -> Result<f32, String> {
+    let speed: f32 = arg
+        .parse()
+        .map_err(|err| format!("Expected a number, got {arg}: {err:#}"))?;
+    if speed.is_finite() && speed > 0. {
+        Ok(speed)
+    } else {
+        Err(format!("Expected a speed greater than 0, got {arg}"))
+    }
+}
+
+/// Tracks the position in the recording, which advances
+/// at `speed` times real time while not paused
+struct PlaybackClock {
+    position: f32,
+    last_update: Instant,
+    speed: f32,
+    paused: bool,
+}
+
+impl PlaybackClock {
+    fn new(speed: f32, paused: bool) -> Self {
+        Self {
+            position: 0.,
+            last_update: Instant::now(),
+            speed: Self::clamp_speed(speed),
+            paused,
+        }
+    }
+
+    fn update(&mut self) -> f32 {
+        let now = Instant::now();
+        if !self.paused {
+            self.position += (now - self.last_update).as_secs_f32() * self.speed;
+        }
+        self.last_update = now;
+        self.position
+    }
+
+    /// How long to wait in real time until the recording
+    /// reaches `target`, or None if paused
+    fn time_until(&mut self, target: f32) -> Option<Duration> {
+        let position = self.update();
+        if self.paused {
+            None
+        } else {
+            Some(Duration::from_secs_f32(
+                ((target - position) / self.speed).max(0.),
+            ))
+        }
+    }
+
+    fn clamp_speed(speed: f32) -> f32 {
+        speed.clamp(1. / 64., 64.)
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.update();
+        self.speed = Self::clamp_speed(speed);
+    }
+
+    fn toggle_pause(&mut self) {
+        self.update();
+        self.paused = !self.paused;
+    }
+
+    /// Jump forwards to `target`
+    fn seek(&mut self, target: f32) {
+        self.update();
+        self.position = self.position.max(target);
+    }
+}
+
+enum PlaybackControl {
+    TogglePause,
+    Step,
+    Faster,
+    Slower,
+    Quit,
+}
+
+impl PlaybackControl {
+    /// Interpret a key press; anything else that arrives on stdin is
+    /// most likely a response from the terminal to a query that was
+    /// made by the recorded session
+    fn from_input(data: &[u8]) -> Option<Self> {
+        match data {
+            b" " => Some(Self::TogglePause),
+            b"." => Some(Self::Step),
+            b"+" | b"=" => Some(Self::Faster),
+            b"-" => Some(Self::Slower),
+            b"q" | b"\x03" => Some(Self::Quit),
+            _ => None,
+        }
+    }
+}
+
 impl PlayCommand {
     pub fn run(&self) -> anyhow::Result<()> {
         let mut cast_file = BufReader::new(
@@ -528,6 +714,8 @@ impl PlayCommand {
         let (tx, rx) = channel();
         let mut sent_parser = TWParser::new();
         let mut sent_actions = vec![];
+        // Data that arrived on stdin while playing back
+        let mut answers = vec![];
 
         if self.explain_only {
             for line in cast_file.lines() {
@@ -573,17 +761,57 @@ impl PlayCommand {
                 });
             }
 
-            let start = Instant::now();
+            let idle_time_limit = self.idle_time_limit.or(header.idle_time_limit);
+            let mut clock = PlaybackClock::new(self.speed, self.paused);
+            let mut recorded_time = 0.;
+            let mut target = 0.;
 
-            for line in cast_file.lines() {
+            'events: for line in cast_file.lines() {
                 let line = line?;
                 let event: Event = serde_json::from_str(&line)?;
                 if event.1 != "o" {
                     continue;
                 }
-                let target = start + Duration::from_secs_f32(event.0);
-                let duration = target.saturating_duration_since(Instant::now());
-                std::thread::sleep(duration);
+
+                let delay = (event.0 - recorded_time).max(0.);
+                recorded_time = event.0;
+                target += match idle_time_limit {
+                    Some(limit) => delay.min(limit),
+                    None => delay,
+                };
+
+                loop {
+                    let msg = match clock.time_until(target) {
+                        Some(duration) if duration.is_zero() => break,
+                        Some(duration) => match rx.recv_timeout(duration) {
+                            Ok(msg) => msg,
+                            Err(RecvTimeoutError::Timeout) => break,
+                            Err(RecvTimeoutError::Disconnected) => {
+                                std::thread::sleep(duration);
+                                break;
+                            }
+                        },
+                        None => match rx.recv() {
+                            Ok(msg) => msg,
+                            Err(_) => break,
+                        },
+                    };
+                    let data = match msg {
+                        Message::Stdin(data) => data,
+                        _ => unreachable!(),
+                    };
+                    match PlaybackControl::from_input(&data) {
+                        Some(PlaybackControl::TogglePause) => clock.toggle_pause(),
+                        Some(PlaybackControl::Step) => {
+                            clock.seek(target);
+                            break;
+                        }
+                        Some(PlaybackControl::Faster) => clock.set_speed(clock.speed * 2.),
+                        Some(PlaybackControl::Slower) => clock.set_speed(clock.speed / 2.),
+                        Some(PlaybackControl::Quit) => break 'events,
+                        None => answers.push(data),
+                    }
+                }
 
                 tty.write_all(&event.2.as_bytes())?;
                 sent_parser.parse(&event.2.as_bytes(), |act| sent_actions.push(act));
@@ -608,18 +836,19 @@ impl PlayCommand {
             let mut parser = TWParser::new();
             while let Ok(msg) = rx.try_recv() {
                 match msg {
-                    Message::Stdin(data) => {
-                        if self.explain {
-                            let answer_back = String::from_utf8_lossy(&data);
-                            println!("\t{:?}", answer_back);
-                            parser.parse(&data, |action| {
-                                println!("\t{:?}", action);
-                            });
-                        }
-                    }
+                    Message::Stdin(data) => answers.push(data),
                     _ => unreachable!(),
                 }
             }
+            if self.explain {
+                for data in answers {
+                    let answer_back = String::from_utf8_lossy(&data);
+                    println!("\t{:?}", answer_back);
+                    parser.parse(&data, |action| {
+                        println!("\t{:?}", action);
+                    });
+                }
+            }
         }
 
         Ok(())
@@ -653,3 +882,31 @@ fn summarize(actions: Vec<Action>) -> Vec<Summarized> {
     }
     res
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn playback_speed_is_clamped() {
+        let mut clock = PlaybackClock::new(0.0, false);
+        assert_eq!(clock.speed, 1. / 64.);
+        assert!(clock.time_until(1.).is_some());
+
+        let clock = PlaybackClock::new(-2.0, false);
+        assert_eq!(clock.speed, 1. / 64.);
+
+        let clock = PlaybackClock::new(1000.0, false);
+        assert_eq!(clock.speed, 64.);
+    }
+
+    #[test]
+    fn playback_speed_arg() {
+        assert_eq!(playback_speed("2"), Ok(2.));
+        assert!(playback_speed("0").is_err());
+        assert!(playback_speed("-1").is_err());
+        assert!(playback_speed("inf").is_err());
+        assert!(playback_speed("NaN").is_err());
+        assert!(playback_speed("fast").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::asciicast::{Event, Header};
+use crate::cli::get_text::get_lines;
+use anyhow::Context;
+use clap::{Parser, ValueEnum, ValueHint};
+use config::ConfigHandle;
+use mux::pane::PaneId;
+use portable_pty::PtySize;
+use std::convert::TryInto;
+use std::io::Write;
+use std::path::PathBuf;
+use termwiz_funcs::{lines_to_escapes, lines_to_html};
+use wezterm_client::client::Client;
+use wezterm_term::color::ColorPalette;
+use wezterm_term::ScrollbackOrVisibleRowIndex;
+
+#[derive(Debug, Clone, Copy, ValueEnum, Default)]
+enum Format {
+    /// Plain, unattributed text
+    #[default]
+    Text,
+    /// Text with escape sequences that color and style it
+    Ansi,
+    /// An html document using the colors from your configuration
+    Html,
+    /// An asciicast v2 file that can be played back using
+    /// `wezterm replay` or asciinema
+    Asciicast,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CapturePane {
+    /// Specify the target pane.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// The starting line number.
+    /// 0 is the first line of terminal screen.
+    /// Negative numbers proceed backwards into the scrollback.
+    /// The default is the first line of the terminal screen.
+    #[arg(long, allow_hyphen_values = true)]
+    start: Option<ScrollbackOrVisibleRowIndex>,
+
+    /// The ending line number, using the same numbering as --start.
+    /// The default is the bottom of the terminal screen.
+    #[arg(long, allow_hyphen_values = true)]
+    end: Option<ScrollbackOrVisibleRowIndex>,
+
+    /// How to format the captured lines
+    #[arg(long, default_value = "text")]
+    format: Format,
+
+    /// Write the capture to the specified file rather than stdout
+    #[arg(long, short, value_hint=ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+impl CapturePane {
+    pub async fn run(self, client: Client, config: &ConfigHandle) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+        let lines = get_lines(&client, pane_id, self.start, self.end).await?;
+
+        let mut out: Vec<u8> = vec![];
+        match self.format {
+            Format::Text => {
+                for line in &lines {
+                    writeln!(out, "{}", line.as_str())?;
+                }
+            }
+            Format::Ansi => {
+                write!(out, "{}", lines_to_escapes(lines)?)?;
+            }
+            Format::Html => {
+                let palette: ColorPalette = config.resolved_palette.clone().into();
+                writeln!(
+                    out,
+                    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                     <title>wezterm pane {pane_id}</title>\n</head>\n\
+                     <body style=\"background-color:{}\">\n{}\n</body>\n</html>",
+                    palette.background.to_rgb_string(),
+                    lines_to_html(&lines, &palette)
+                )?;
+            }
+            Format::Asciicast => {
+                let info = client
+                    .get_dimensions(codec::GetPaneRenderableDimensions { pane_id })
+                    .await?;
+                let size = PtySize {
+                    rows: info.dimensions.viewport_rows.try_into()?,
+                    cols: info.dimensions.cols.try_into()?,
+                    pixel_width: 0,
+                    pixel_height: 0,
+                };
+                let header = Header::new(config, size, &[]);
+                writeln!(out, "{}", serde_json::to_string(&header)?)?;
+                Event::log_output(&mut out, 0.0, &lines_to_escapes(lines)?)?;
+            }
+        }
+
+        match &self.output {
+            Some(path) => {
+                std::fs::write(path, &out).with_context(|| format!("writing {}", path.display()))?
+            }
+            None => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(&out)?;
+                stdout.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
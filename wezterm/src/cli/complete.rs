@@ -0,0 +1,268 @@
+use crate::cli::CliCommand;
+use clap::{Parser, ValueEnum};
+use config::ConfigHandle;
+use std::io::Write;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CompletionKind {
+    PaneId,
+    TabId,
+    WindowId,
+    Workspace,
+    Domain,
+}
+
+/// The options whose values are completed by querying wezterm,
+/// and the kind of value that each of them takes
+const DYNAMIC_OPTIONS: &[(&str, &str)] = &[
+    ("--pane-id", "pane-id"),
+    ("--target-pane-id", "pane-id"),
+    ("--move-pane-id", "pane-id"),
+    ("--tab-id", "tab-id"),
+    ("--window-id", "window-id"),
+    ("--workspace", "workspace"),
+    ("--domain", "domain"),
+    ("--domain-name", "domain"),
+];
+
+#[derive(Debug, Parser, Clone)]
+pub struct Complete {
+    /// The kind of value to complete
+    kind: CompletionKind,
+}
+
+impl Complete {
+    pub async fn run(&self, cli: &CliCommand, config: &ConfigHandle) -> anyhow::Result<()> {
+        let candidates = if self.kind == CompletionKind::Domain {
+            domain_names(config)
+        } else {
+            let mut ui = mux::connui::ConnectionUI::new_headless();
+            // Never start a mux server just to complete a command line
+            let no_auto_start = true;
+            let client = Client::new_default_unix_domain(
+                true,
+                &mut ui,
+                no_auto_start,
+                cli.prefer_mux,
+                cli.class
+                    .as_deref()
+                    .unwrap_or(wezterm_gui_subcommands::DEFAULT_WINDOW_CLASS),
+            )?;
+            self.mux_candidates(&client).await?
+        };
+
+        let mut stdout = std::io::stdout().lock();
+        for (value, description) in candidates {
+            if description.is_empty() {
+                writeln!(stdout, "{value}")?;
+            } else {
+                writeln!(stdout, "{value}\t{description}")?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn mux_candidates(&self, client: &Client) -> anyhow::Result<Vec<(String, String)>> {
+        let panes = client.list_panes().await?;
+        let mut candidates: Vec<(String, String)> = vec![];
+
+        for (tabroot, tab_title) in panes.tabs.into_iter().zip(panes.tab_titles) {
+            let mut cursor = tabroot.into_tree().cursor();
+            loop {
+                if let Some(entry) = cursor.leaf_mut() {
+                    let candidate = match self.kind {
+                        CompletionKind::PaneId => {
+                            Some((entry.pane_id.to_string(), entry.title.clone()))
+                        }
+                        CompletionKind::TabId if entry.is_active_pane => {
+                            let title = if tab_title.is_empty() {
+                                entry.title.clone()
+                            } else {
+                                tab_title.clone()
+                            };
+                            Some((entry.tab_id.to_string(), title))
+                        }
+                        CompletionKind::WindowId => Some((
+                            entry.window_id.to_string(),
+                            panes
+                                .window_titles
+                                .get(&entry.window_id)
+                                .cloned()
+                                .unwrap_or_default(),
+                        )),
+                        CompletionKind::Workspace => Some((entry.workspace.clone(), String::new())),
+                        CompletionKind::TabId | CompletionKind::Domain => None,
+                    };
+                    if let Some(candidate) = candidate {
+                        if !candidates.iter().any(|(value, _)| *value == candidate.0) {
+                            candidates.push(candidate);
+                        }
+                    }
+                }
+                match cursor.preorder_next() {
+                    Ok(c) => cursor = c,
+                    Err(_) => break,
+                }
+            }
+        }
+        Ok(candidates)
+    }
+}
+
+/// The names of the domains defined by the configuration
+fn domain_names(config: &ConfigHandle) -> Vec<(String, String)> {
+    let mut names = vec![("local".to_string(), "local".to_string())];
+    let mut add = |name: &str, kind: &str| names.push((name.to_string(), kind.to_string()));
+    for dom in &config.unix_domains {
+        add(&dom.name, "unix");
+    }
+    for dom in config.ssh_domains() {
+        add(&dom.name, "ssh");
+    }
+    for dom in &config.tls_clients {
+        add(&dom.name, "tls");
+    }
+    for dom in &config.exec_domains {
+        add(&dom.name, "exec");
+    }
+    for dom in config.wsl_domains() {
+        add(&dom.name, "wsl");
+    }
+    names
+}
+
+/// Produce the arms of a shell `case` statement that set `kind`
+/// to the kind of value taken by the option held in the subject
+fn case_arms() -> String {
+    let mut kinds: Vec<&str> = vec![];
+    for (_, kind) in DYNAMIC_OPTIONS {
+        if !kinds.contains(kind) {
+            kinds.push(kind);
+        }
+    }
+    kinds
+        .iter()
+        .map(|kind| {
+            let options: Vec<&str> = DYNAMIC_OPTIONS
+                .iter()
+                .filter(|(_, k)| k == kind)
+                .map(|(option, _)| *option)
+                .collect();
+            format!("        {}) kind={kind} ;;\n", options.join("|"))
+        })
+        .collect()
+}
+
+/// Extend a bash completion script generated by clap so that the values
+/// of the options in `DYNAMIC_OPTIONS` are completed by querying wezterm
+pub fn add_bash_completions(script: String, name: &str) -> String {
+    format!(
+        r#"{script}
+_{name}_dynamic() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}" kind
+    if declare -F _get_comp_words_by_ref >/dev/null; then
+        _get_comp_words_by_ref -n : cur prev
+    fi
+    case "$prev" in
+{arms}    esac
+    if [[ -n "$kind" ]]; then
+        local IFS=$'\n'
+        COMPREPLY=($(compgen -W "$({name} cli complete "$kind" 2>/dev/null | cut -f1)" -- "$cur"))
+        if declare -F __ltrim_colon_completions >/dev/null; then
+            __ltrim_colon_completions "$cur"
+        fi
+        return 0
+    fi
+    _{name} "$@"
+}}
+
+if [[ "${{BASH_VERSINFO[0]}}" -eq 4 && "${{BASH_VERSINFO[1]}}" -ge 4 || "${{BASH_VERSINFO[0]}}" -gt 4 ]]; then
+    complete -F _{name}_dynamic -o nosort -o bashdefault -o default {name}
+else
+    complete -F _{name}_dynamic -o bashdefault -o default {name}
+fi
+"#,
+        arms = case_arms()
+    )
+}
+
+/// Extend a zsh completion script generated by clap so that the values
+/// of the options in `DYNAMIC_OPTIONS` are completed by querying wezterm.
+/// The generated `_wezterm` function is renamed and wrapped, and that
+/// has to happen before the script either calls it or registers it
+/// with `compdef`.
+pub fn add_zsh_completions(script: String, name: &str) -> String {
+    let dynamic = format!(
+        r#"_{name}_dynamic_values() {{
+    local -a candidates
+    local value desc
+    while IFS=$'\t' read -r value desc; do
+        candidates+=("${{value//:/\\:}}${{desc:+:$desc}}")
+    done < <({name} cli complete "$1" 2>/dev/null)
+    _describe -t "$1" "$1" candidates
+}}
+
+(( $+functions[_{name}_static] )) || functions[_{name}_static]=$functions[_{name}]
+
+_{name}() {{
+    local kind
+    case "${{words[CURRENT-1]}}" in
+{arms}    esac
+    if [[ -n "$kind" ]]; then
+        _{name}_dynamic_values "$kind"
+    else
+        _{name}_static "$@"
+    fi
+}}
+
+"#,
+        arms = case_arms()
+    );
+
+    let mut script = script;
+    let marker = format!("if [ \"$funcstack[1]\" = \"_{name}\" ]; then");
+    match script.rfind(&marker) {
+        Some(idx) => script.insert_str(idx, &dynamic),
+        None => {
+            script.push('\n');
+            script.push_str(&dynamic);
+        }
+    }
+    script
+}
+
+/// Extend a fish completion script generated by clap so that the values
+/// of the options in `DYNAMIC_OPTIONS` are completed by querying wezterm
+pub fn add_fish_completions(script: String, name: &str) -> String {
+    let mut result = String::with_capacity(script.len());
+    for line in script.lines() {
+        result.push_str(line);
+        if let Some(kind) = fish_option_kind(line) {
+            result.push_str(&format!(
+                " -f -a \"({name} cli complete {kind} 2>/dev/null)\""
+            ));
+        }
+        result.push('\n');
+    }
+    result
+}
+
+/// If `line` describes one of `DYNAMIC_OPTIONS`, returns the kind
+/// of value that it takes
+fn fish_option_kind(line: &str) -> Option<&'static str> {
+    if !line.starts_with("complete ") {
+        return None;
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if !words.contains(&"-r") {
+        return None;
+    }
+    words.windows(2).find_map(|pair| match pair {
+        ["-l", long] => DYNAMIC_OPTIONS
+            .iter()
+            .find(|(option, _)| option.strip_prefix("--") == Some(long))
+            .map(|(_, kind)| *kind),
+        _ => None,
+    })
+}
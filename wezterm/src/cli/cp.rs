@@ -0,0 +1,378 @@
+use anyhow::Context;
+use clap::Parser;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use wezterm_client::client::Client;
+
+/// The amount of data sent or requested per round trip
+const CHUNK_SIZE: usize = 128 * 1024;
+
+#[derive(Debug, Parser, Clone)]
+pub struct Cp {
+    /// Don't show the progress of the transfer
+    #[arg(long, short)]
+    quiet: bool,
+
+    /// The file to copy.
+    /// A file on the remote host is written as `DOMAIN:PATH`,
+    /// where DOMAIN is the name of an ssh domain, eg: `my.server:notes.txt`.
+    /// Relative remote paths are relative to the home directory.
+    source: String,
+
+    /// Where to copy the file to, using the same syntax as SOURCE.
+    /// When the path ends with a `/`, or is an existing local
+    /// directory, the file name of SOURCE is appended to it.
+    /// Exactly one of SOURCE and DESTINATION must be remote.
+    destination: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Location {
+    Local(PathBuf),
+    Remote { domain: String, path: String },
+}
+
+impl Location {
+    fn parse(spec: &str) -> Self {
+        // Windows paths such as `C:\foo` are local
+        let bytes = spec.as_bytes();
+        if bytes.len() > 2
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && (bytes[2] == b'\\' || bytes[2] == b'/')
+        {
+            return Self::Local(spec.into());
+        }
+
+        // The automatically populated domain names have a prefix
+        // that contains a colon; keep it as part of the domain name
+        let (prefix, rest) = ["SSH:", "SSHMUX:"]
+            .iter()
+            .find_map(|prefix| spec.strip_prefix(prefix).map(|rest| (*prefix, rest)))
+            .unwrap_or(("", spec));
+
+        match rest.split_once(':') {
+            Some((domain, path)) if !domain.is_empty() && !domain.contains('/') => {
+                let path = path.strip_prefix("~/").unwrap_or(path);
+                Self::Remote {
+                    domain: format!("{prefix}{domain}"),
+                    path: if path.is_empty() || path == "~" {
+                        ".".to_string()
+                    } else {
+                        path.to_string()
+                    },
+                }
+            }
+            _ => Self::Local(spec.into()),
+        }
+    }
+
+    fn file_name(&self) -> Option<String> {
+        match self {
+            Self::Local(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string()),
+            Self::Remote { path, .. } => Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string()),
+        }
+    }
+}
+
+impl Cp {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let source = Location::parse(&self.source);
+        let destination = Location::parse(&self.destination);
+        let name = source
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("{} doesn't name a file", self.source))?;
+
+        match (&source, destination) {
+            (Location::Local(local), Location::Remote { domain, mut path }) => {
+                if path.ends_with('/') || path == "." {
+                    path = format!("{}/{name}", path.trim_end_matches('/'));
+                }
+                let file = std::fs::File::open(local)
+                    .with_context(|| format!("opening {}", local.display()))?;
+                let size = file.metadata().ok().map(|meta| meta.len());
+                self.upload(&client, file, size, domain, path, &name).await
+            }
+            (Location::Remote { domain, path }, Location::Local(mut local)) => {
+                if local.is_dir() {
+                    local.push(&name);
+                }
+                self.download(&client, &local, domain.clone(), path.clone(), &name)
+                    .await
+            }
+            (Location::Local(_), Location::Local(_)) => anyhow::bail!(
+                "neither {} nor {} is on a remote domain; \
+                 remote files are written as DOMAIN:PATH",
+                self.source,
+                self.destination
+            ),
+            (Location::Remote { .. }, Location::Remote { .. }) => {
+                anyhow::bail!("copying between two remote domains is not supported")
+            }
+        }
+    }
+
+    async fn upload(
+        &self,
+        client: &Client,
+        mut file: std::fs::File,
+        size: Option<u64>,
+        domain: String,
+        path: String,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        let transfer_id = client
+            .ssh_transfer_open(codec::SshTransferOpen {
+                domain,
+                path,
+                write: true,
+            })
+            .await?
+            .transfer_id;
+
+        let mut progress = Progress::new(name, size, self.quiet);
+        let result: anyhow::Result<()> = async {
+            let mut buf = vec![0u8; CHUNK_SIZE];
+            loop {
+                let len = file.read(&mut buf)?;
+                if len == 0 {
+                    return Ok(());
+                }
+                client
+                    .ssh_transfer_write(codec::SshTransferWrite {
+                        transfer_id,
+                        data: buf[0..len].to_vec(),
+                    })
+                    .await?;
+                progress.advance(len);
+            }
+        }
+        .await;
+
+        let closed = client
+            .ssh_transfer_close(codec::SshTransferClose { transfer_id })
+            .await;
+        progress.finish();
+        result?;
+        closed?;
+        Ok(())
+    }
+
+    async fn download(
+        &self,
+        client: &Client,
+        local: &Path,
+        domain: String,
+        path: String,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        let response = client
+            .ssh_transfer_open(codec::SshTransferOpen {
+                domain,
+                path,
+                write: false,
+            })
+            .await?;
+        let transfer_id = response.transfer_id;
+
+        let mut pending = match PendingFile::new(local) {
+            Ok(pending) => pending,
+            Err(err) => {
+                client
+                    .ssh_transfer_close(codec::SshTransferClose { transfer_id })
+                    .await
+                    .ok();
+                return Err(err);
+            }
+        };
+        let file = pending.file();
+
+        let mut progress = Progress::new(name, response.size, self.quiet);
+        let result: anyhow::Result<()> = async {
+            loop {
+                let data = client
+                    .ssh_transfer_read(codec::SshTransferRead {
+                        transfer_id,
+                        max_len: CHUNK_SIZE,
+                    })
+                    .await?
+                    .data;
+                if data.is_empty() {
+                    file.flush()?;
+                    return Ok(());
+                }
+                file.write_all(&data)?;
+                progress.advance(data.len());
+            }
+        }
+        .await;
+
+        let closed = client
+            .ssh_transfer_close(codec::SshTransferClose { transfer_id })
+            .await;
+        progress.finish();
+        result?;
+        closed?;
+        pending.persist()
+    }
+}
+
+/// A file that is written alongside its destination, and which only
+/// replaces the destination once it is complete; if it is dropped
+/// before then, the file is removed and the destination is untouched.
+struct PendingFile {
+    temp: tempfile::NamedTempFile,
+    destination: PathBuf,
+}
+
+impl PendingFile {
+    fn new(destination: &Path) -> anyhow::Result<Self> {
+        let dir = match destination.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let temp = tempfile::Builder::new()
+            .prefix(".wezterm-cp")
+            .tempfile_in(dir)
+            .with_context(|| format!("creating a temporary file in {}", dir.display()))?;
+        Ok(Self {
+            temp,
+            destination: destination.to_path_buf(),
+        })
+    }
+
+    fn file(&mut self) -> &mut std::fs::File {
+        self.temp.as_file_mut()
+    }
+
+    fn persist(self) -> anyhow::Result<()> {
+        // Keep the permissions of a file that is being replaced
+        if let Ok(meta) = std::fs::metadata(&self.destination) {
+            self.temp
+                .as_file()
+                .set_permissions(meta.permissions())
+                .with_context(|| format!("setting permissions for {}", self.destination.display()))?;
+        }
+        let destination = self.destination;
+        self.temp
+            .persist(&destination)
+            .with_context(|| format!("writing {}", destination.display()))?;
+        Ok(())
+    }
+}
+
+/// Renders a single line progress bar on stderr, when it is a terminal
+struct Progress {
+    name: String,
+    size: Option<u64>,
+    done: u64,
+    started: Instant,
+    last_render: Option<Instant>,
+    enabled: bool,
+}
+
+impl Progress {
+    fn new(name: &str, size: Option<u64>, quiet: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            size,
+            done: 0,
+            started: Instant::now(),
+            last_render: None,
+            enabled: !quiet && std::io::stderr().is_terminal(),
+        }
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.done += len as u64;
+        let due = self
+            .last_render
+            .map(|last| last.elapsed() >= Duration::from_millis(100))
+            .unwrap_or(true);
+        if due {
+            self.render();
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.enabled && self.last_render.is_some() {
+            self.render();
+            eprintln!();
+        }
+    }
+
+    fn render(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        self.last_render.replace(Instant::now());
+
+        let elapsed = self.started.elapsed().as_secs_f64().max(0.001);
+        let rate = format!("{}/s", human_size((self.done as f64 / elapsed) as u64));
+        let line = match self.size {
+            Some(size) if size > 0 => {
+                let fraction = (self.done as f64 / size as f64).min(1.0);
+                const WIDTH: usize = 30;
+                let filled = (fraction * WIDTH as f64) as usize;
+                format!(
+                    "{} [{}{}] {:3.0}% {}/{} {rate}",
+                    self.name,
+                    "#".repeat(filled),
+                    " ".repeat(WIDTH - filled),
+                    fraction * 100.,
+                    human_size(self.done),
+                    human_size(size),
+                )
+            }
+            _ => format!("{} {} {rate}", self.name, human_size(self.done)),
+        };
+        // Clear to the end of the line in case the line got shorter
+        eprint!("\r{line}\x1b[K");
+        let _ = std::io::stderr().flush();
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}B")
+    } else {
+        format!("{value:.1}{}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pending_file_only_replaces_destination_on_success() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("notes.txt");
+        std::fs::write(&destination, "original").unwrap();
+
+        // A download that fails part way through
+        let mut pending = PendingFile::new(&destination).unwrap();
+        pending.file().write_all(b"partial").unwrap();
+        drop(pending);
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "original");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let mut pending = PendingFile::new(&destination).unwrap();
+        pending.file().write_all(b"complete").unwrap();
+        pending.persist().unwrap();
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "complete");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
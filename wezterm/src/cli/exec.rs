@@ -0,0 +1,76 @@
+use crate::cli::resolve_relative_cwd;
+use clap::{Parser, ValueHint};
+use config::keyassignment::SpawnTabDomain;
+use config::ConfigHandle;
+use portable_pty::cmdbuilder::CommandBuilder;
+use std::ffi::OsString;
+use std::io::Write;
+use wezterm_client::client::Client;
+
+/// The amount of output requested per round trip
+const CHUNK_SIZE: usize = 128 * 1024;
+
+#[derive(Debug, Parser, Clone)]
+pub struct Exec {
+    /// Run the command in the named multiplexer domain,
+    /// rather than the default domain.
+    /// For example: `--domain-name SSH:my.server`
+    #[arg(long, alias = "domain")]
+    domain_name: Option<String>,
+
+    /// Specify the current working directory for the command
+    #[arg(long, value_parser, value_hint=ValueHint::DirPath)]
+    cwd: Option<OsString>,
+
+    /// The command to run, and its arguments.
+    /// For example: `wezterm cli exec --domain-name SSH:my.server -- uptime`
+    #[arg(
+        value_parser,
+        value_hint=ValueHint::CommandWithArguments,
+        num_args=1..,
+        required = true
+    )]
+    prog: Vec<OsString>,
+}
+
+impl Exec {
+    pub async fn run(self, client: Client, config: &ConfigHandle) -> anyhow::Result<()> {
+        let spawned = client
+            .exec_command(codec::ExecCommand {
+                domain: self
+                    .domain_name
+                    .map_or(SpawnTabDomain::DefaultDomain, |name| {
+                        SpawnTabDomain::DomainName(name)
+                    }),
+                command: Some(CommandBuilder::from_argv(self.prog)),
+                command_dir: resolve_relative_cwd(self.cwd)?,
+                size: config.initial_size(0, None),
+            })
+            .await?;
+        log::debug!("{:?}", spawned);
+        let pane_id = spawned.pane_id;
+
+        let mut stdout = std::io::stdout();
+        loop {
+            let output = client
+                .read_exec_output(codec::ReadExecOutput {
+                    pane_id,
+                    max_len: CHUNK_SIZE,
+                })
+                .await?;
+            if !output.data.is_empty() {
+                stdout.write_all(&output.data)?;
+                stdout.flush()?;
+            }
+            if output.finished {
+                if let Some(signal) = &output.signal {
+                    eprintln!("The process was terminated: {signal}");
+                }
+                match output.exit_code {
+                    Some(code) => std::process::exit(code as i32),
+                    None => anyhow::bail!("The exit status of the process is unknown"),
+                }
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use clap::Parser;
 use mux::pane::PaneId;
+use termwiz::surface::Line;
 use termwiz_funcs::lines_to_escapes;
 use wezterm_client::client::Client;
 use wezterm_term::{ScrollbackOrVisibleRowIndex, StableRowIndex};
@@ -38,50 +39,7 @@ impl GetText {
     pub async fn run(self, client: Client) -> anyhow::Result<()> {
         let pane_id = client.resolve_pane_id(self.pane_id).await?;
 
-        let info = client
-            .get_dimensions(codec::GetPaneRenderableDimensions { pane_id })
-            .await?;
-
-        let start_line = match self.start_line {
-            None => info.dimensions.physical_top,
-            Some(n) if n >= 0 => info.dimensions.physical_top + n as StableRowIndex,
-            Some(n) => {
-                let line = info.dimensions.physical_top as isize + n as isize;
-                if line < info.dimensions.scrollback_top as isize {
-                    info.dimensions.scrollback_top
-                } else {
-                    line as StableRowIndex
-                }
-            }
-        };
-
-        let end_line = match self.end_line {
-            None => info.dimensions.physical_top + info.dimensions.viewport_rows as StableRowIndex,
-            Some(n) if n >= 0 => info.dimensions.physical_top + n as StableRowIndex,
-            Some(n) => {
-                let line = info.dimensions.physical_top as isize + n as isize;
-                if line < info.dimensions.scrollback_top as isize {
-                    info.dimensions.scrollback_top
-                } else {
-                    line as StableRowIndex
-                }
-            }
-        };
-
-        let lines = client
-            .get_lines(codec::GetLines {
-                pane_id: pane_id.into(),
-                lines: vec![start_line..end_line + 1],
-            })
-            .await?;
-
-        let lines = lines
-            .lines
-            .extract_data()
-            .0
-            .into_iter()
-            .map(|(_idx, line)| line)
-            .collect();
+        let lines = get_lines(&client, pane_id, self.start_line, self.end_line).await?;
 
         if self.escapes {
             println!("{}", lines_to_escapes(lines)?);
@@ -91,3 +49,52 @@ impl GetText {
         Ok(())
     }
 }
+
+/// Fetch the lines from `start_line` through `end_line` inclusive,
+/// which are expressed relative to the top of the terminal screen,
+/// clamping them to the available scrollback.
+pub async fn get_lines(
+    client: &Client,
+    pane_id: PaneId,
+    start_line: Option<ScrollbackOrVisibleRowIndex>,
+    end_line: Option<ScrollbackOrVisibleRowIndex>,
+) -> anyhow::Result<Vec<Line>> {
+    let info = client
+        .get_dimensions(codec::GetPaneRenderableDimensions { pane_id })
+        .await?;
+
+    let resolve = |n: ScrollbackOrVisibleRowIndex| {
+        if n >= 0 {
+            info.dimensions.physical_top + n as StableRowIndex
+        } else {
+            let line = info.dimensions.physical_top as isize + n as isize;
+            if line < info.dimensions.scrollback_top as isize {
+                info.dimensions.scrollback_top
+            } else {
+                line as StableRowIndex
+            }
+        }
+    };
+
+    let start_line = start_line
+        .map(resolve)
+        .unwrap_or(info.dimensions.physical_top);
+    let end_line = end_line
+        .map(resolve)
+        .unwrap_or(info.dimensions.physical_top + info.dimensions.viewport_rows as StableRowIndex);
+
+    let lines = client
+        .get_lines(codec::GetLines {
+            pane_id: pane_id.into(),
+            lines: vec![start_line..end_line + 1],
+        })
+        .await?;
+
+    Ok(lines
+        .lines
+        .extract_data()
+        .0
+        .into_iter()
+        .map(|(_idx, line)| line)
+        .collect())
+}
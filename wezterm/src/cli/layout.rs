@@ -0,0 +1,353 @@
+use anyhow::Context;
+use clap::{Parser, ValueHint};
+use config::keyassignment::SpawnTabDomain;
+use config::ConfigHandle;
+use mux::pane::PaneId;
+use mux::tab::{PaneEntry, PaneNode, SplitDirection, SplitRequest, SplitSize};
+use mux::window::WindowId;
+use portable_pty::cmdbuilder::CommandBuilder;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use wezterm_client::client::Client;
+
+/// A description of a set of windows, their tabs and how the
+/// tabs are split into panes.
+/// This is the format used by `wezterm cli dump-layout` and
+/// `wezterm cli apply-layout`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Layout {
+    windows: Vec<LayoutWindow>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LayoutWindow {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workspace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    tabs: Vec<LayoutTab>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LayoutTab {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    root: LayoutNode,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LayoutNode {
+    Pane(LayoutPane),
+    Split(Box<LayoutSplit>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LayoutSplit {
+    /// Horizontal places `second` to the right of `first`,
+    /// Vertical places it below `first`
+    direction: SplitDirection,
+    /// The size of `second` as a percentage of the split
+    #[serde(default = "default_percent")]
+    percent: u8,
+    first: LayoutNode,
+    second: LayoutNode,
+}
+
+fn default_percent() -> u8 {
+    50
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LayoutPane {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cwd: Option<String>,
+    /// The program to run instead of the default program
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    active: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    zoomed: bool,
+}
+
+impl LayoutNode {
+    fn from_pane_node(node: PaneNode) -> Option<Self> {
+        match node {
+            PaneNode::Empty => None,
+            PaneNode::Leaf(entry) => Some(Self::Pane(LayoutPane::from_entry(entry))),
+            PaneNode::Split { left, right, node } => {
+                let (first, second) = match node.direction {
+                    SplitDirection::Horizontal => (node.first.cols, node.second.cols),
+                    SplitDirection::Vertical => (node.first.rows, node.second.rows),
+                };
+                let percent = (second as f32 * 100. / (first + second).max(1) as f32).round();
+                Some(Self::Split(Box::new(LayoutSplit {
+                    direction: node.direction,
+                    percent: (percent as u8).clamp(1, 99),
+                    first: Self::from_pane_node(*left)?,
+                    second: Self::from_pane_node(*right)?,
+                })))
+            }
+        }
+    }
+
+    /// The pane that occupies the top left of this node
+    fn first_pane(&self) -> &LayoutPane {
+        match self {
+            Self::Pane(pane) => pane,
+            Self::Split(split) => split.first.first_pane(),
+        }
+    }
+}
+
+impl LayoutPane {
+    fn from_entry(entry: PaneEntry) -> Self {
+        let cwd = entry.working_dir.map(|dir| {
+            let mut url = dir.url;
+            // Allow the path to be extracted even if the shell
+            // reported the name of the host in the url
+            if url.scheme() == "file" {
+                url.set_host(Some("localhost")).ok();
+            }
+            match url.to_file_path() {
+                Ok(path) => path.to_string_lossy().into_owned(),
+                Err(_) => url.path().to_string(),
+            }
+        });
+        Self {
+            cwd,
+            command: entry.spawn_command,
+            active: entry.is_active_pane,
+            zoomed: entry.is_zoomed_pane,
+        }
+    }
+
+    fn command(&self) -> Option<CommandBuilder> {
+        self.command
+            .as_ref()
+            .map(|argv| CommandBuilder::from_argv(argv.iter().map(Into::into).collect()))
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct DumpLayout {
+    /// Only include the windows in the specified workspace
+    #[arg(long)]
+    workspace: Option<String>,
+}
+
+impl DumpLayout {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let panes = client.list_panes().await?;
+
+        let mut layout = Layout::default();
+        let mut window_ids: Vec<WindowId> = vec![];
+
+        for (tabroot, tab_title) in panes.tabs.into_iter().zip(panes.tab_titles) {
+            let window_id = match tabroot.window_and_tab_ids() {
+                Some((window_id, _tab_id)) => window_id,
+                None => continue,
+            };
+            let workspace = first_entry(&tabroot).map(|entry| entry.workspace.clone());
+            if self.workspace.is_some() && workspace != self.workspace {
+                continue;
+            }
+            let root = match LayoutNode::from_pane_node(tabroot) {
+                Some(root) => root,
+                None => continue,
+            };
+
+            let idx = match window_ids.iter().position(|id| *id == window_id) {
+                Some(idx) => idx,
+                None => {
+                    window_ids.push(window_id);
+                    layout.windows.push(LayoutWindow {
+                        workspace,
+                        title: panes
+                            .window_titles
+                            .get(&window_id)
+                            .filter(|title| !title.is_empty())
+                            .cloned(),
+                        tabs: vec![],
+                    });
+                    layout.windows.len() - 1
+                }
+            };
+
+            layout.windows[idx].tabs.push(LayoutTab {
+                title: if tab_title.is_empty() {
+                    None
+                } else {
+                    Some(tab_title)
+                },
+                root,
+            });
+        }
+
+        println!("{}", serde_json::to_string_pretty(&layout)?);
+        Ok(())
+    }
+}
+
+fn first_entry(node: &PaneNode) -> Option<&PaneEntry> {
+    match node {
+        PaneNode::Empty => None,
+        PaneNode::Leaf(entry) => Some(entry),
+        PaneNode::Split { left, right, .. } => first_entry(left).or_else(|| first_entry(right)),
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ApplyLayout {
+    /// Spawn the panes into the named multiplexer domain,
+    /// rather than the default domain
+    #[arg(long)]
+    domain_name: Option<String>,
+
+    /// Place all of the windows into the specified workspace,
+    /// rather than the workspaces named in the layout
+    #[arg(long)]
+    workspace: Option<String>,
+
+    /// The layout file, as produced by `wezterm cli dump-layout`.
+    /// Use `-` to read it from stdin.
+    #[arg(value_hint=ValueHint::FilePath)]
+    file: PathBuf,
+}
+
+impl ApplyLayout {
+    pub async fn run(self, client: Client, config: &ConfigHandle) -> anyhow::Result<()> {
+        let data = if self.file.as_os_str() == "-" {
+            let mut data = String::new();
+            std::io::Read::read_to_string(&mut std::io::stdin(), &mut data)
+                .context("reading stdin")?;
+            data
+        } else {
+            std::fs::read_to_string(&self.file)
+                .with_context(|| format!("reading {}", self.file.display()))?
+        };
+        let layout: Layout = serde_json::from_str(&data)
+            .with_context(|| format!("parsing layout {}", self.file.display()))?;
+
+        let domain = match &self.domain_name {
+            Some(name) => SpawnTabDomain::DomainName(name.to_string()),
+            None => SpawnTabDomain::DefaultDomain,
+        };
+        let size = config.initial_size(0, None);
+
+        for window in &layout.windows {
+            let workspace = self
+                .workspace
+                .as_deref()
+                .or(window.workspace.as_deref())
+                .or(config.default_workspace.as_deref())
+                .unwrap_or(mux::DEFAULT_WORKSPACE)
+                .to_string();
+
+            let mut window_id = None;
+            for tab in &window.tabs {
+                let pane = tab.root.first_pane();
+                let spawned = client
+                    .spawn_v2(codec::SpawnV2 {
+                        domain: domain.clone(),
+                        window_id,
+                        command: pane.command(),
+                        command_dir: pane.cwd.clone(),
+                        size,
+                        workspace: workspace.clone(),
+                    })
+                    .await?;
+                window_id.replace(spawned.window_id);
+
+                let mut state = TabState::default();
+                self.build(&client, &tab.root, spawned.pane_id, &mut state)
+                    .await?;
+
+                if let Some(title) = &tab.title {
+                    client
+                        .set_tab_title(codec::TabTitleChanged {
+                            tab_id: spawned.tab_id,
+                            title: title.to_string(),
+                        })
+                        .await?;
+                }
+                if let Some(pane_id) = state.active {
+                    client
+                        .set_focused_pane_id(codec::SetFocusedPane { pane_id })
+                        .await?;
+                }
+                if let Some(pane_id) = state.zoomed {
+                    client
+                        .set_zoomed(codec::SetPaneZoomed {
+                            containing_tab_id: spawned.tab_id,
+                            pane_id,
+                            zoomed: true,
+                        })
+                        .await?;
+                }
+            }
+
+            if let (Some(window_id), Some(title)) = (window_id, &window.title) {
+                client
+                    .set_window_title(codec::WindowTitleChanged {
+                        window_id,
+                        title: title.to_string(),
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Split `pane_id`, which was spawned using the first pane
+    /// of `root`, until the tab matches `root`
+    async fn build(
+        &self,
+        client: &Client,
+        root: &LayoutNode,
+        pane_id: PaneId,
+        state: &mut TabState,
+    ) -> anyhow::Result<()> {
+        let mut stack = vec![(root, pane_id)];
+        while let Some((node, pane_id)) = stack.pop() {
+            match node {
+                LayoutNode::Pane(pane) => {
+                    if pane.active {
+                        state.active.replace(pane_id);
+                    }
+                    if pane.zoomed {
+                        state.zoomed.replace(pane_id);
+                    }
+                }
+                LayoutNode::Split(split) => {
+                    let pane = split.second.first_pane();
+                    let spawned = client
+                        .split_pane(codec::SplitPane {
+                            pane_id,
+                            split_request: SplitRequest {
+                                direction: split.direction,
+                                target_is_second: true,
+                                top_level: false,
+                                size: SplitSize::Percent(split.percent.clamp(1, 99)),
+                            },
+                            command: pane.command(),
+                            command_dir: pane.cwd.clone(),
+                            domain: SpawnTabDomain::CurrentPaneDomain,
+                            move_pane_id: None,
+                        })
+                        .await?;
+                    stack.push((&split.second, spawned.pane_id));
+                    stack.push((&split.first, pane_id));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct TabState {
+    active: Option<PaneId>,
+    zoomed: Option<PaneId>,
+}
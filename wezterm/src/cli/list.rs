@@ -1,22 +1,119 @@
 use crate::cli::CliOutputFormatKind;
+use clap::builder::PossibleValuesParser;
 use clap::Parser;
+use codec::MuxEventKind;
 use serde::Serializer as _;
+use std::io::Write;
+use std::time::Duration;
 use tabout::{tabulate_output, Alignment, Column};
 use wezterm_client::client::Client;
 use wezterm_term::TerminalSize;
 
-#[derive(Debug, Parser, Clone, Copy)]
+struct Field {
+    name: &'static str,
+    heading: &'static str,
+    alignment: Alignment,
+}
+
+impl Field {
+    const fn new(name: &'static str, heading: &'static str, alignment: Alignment) -> Self {
+        Self {
+            name,
+            heading,
+            alignment,
+        }
+    }
+}
+
+/// The fields that can be selected via `--fields`, and the headings
+/// that are used for them in table output.
+/// The names match the keys of the JSON output.
+const FIELDS: &[Field] = &[
+    Field::new("window_id", "WINID", Alignment::Right),
+    Field::new("tab_id", "TABID", Alignment::Right),
+    Field::new("pane_id", "PANEID", Alignment::Right),
+    Field::new("workspace", "WORKSPACE", Alignment::Left),
+    Field::new("size", "SIZE", Alignment::Left),
+    Field::new("title", "TITLE", Alignment::Left),
+    Field::new("cwd", "CWD", Alignment::Left),
+    Field::new("cursor_x", "CURSOR_X", Alignment::Right),
+    Field::new("cursor_y", "CURSOR_Y", Alignment::Right),
+    Field::new("cursor_shape", "CURSOR_SHAPE", Alignment::Left),
+    Field::new("cursor_visibility", "CURSOR_VISIBILITY", Alignment::Left),
+    Field::new("left_col", "LEFT_COL", Alignment::Right),
+    Field::new("top_row", "TOP_ROW", Alignment::Right),
+    Field::new("tab_title", "TAB_TITLE", Alignment::Left),
+    Field::new("window_title", "WINDOW_TITLE", Alignment::Left),
+    Field::new("is_active", "ACTIVE", Alignment::Left),
+    Field::new("is_zoomed", "ZOOMED", Alignment::Left),
+    Field::new("tty_name", "TTY", Alignment::Left),
+    Field::new("foreground_process_name", "PROCESS", Alignment::Left),
+];
+
+/// The fields shown in table output when `--fields` is not used
+const DEFAULT_TABLE_FIELDS: usize = 7;
+
+/// Events that don't change the output, and which are too
+/// frequent to be worth re-listing for
+const IGNORED_EVENTS: &[&str] = &["pane-output", "bell", "user-var"];
+
+/// How long to wait after a change before re-listing, so that a
+/// burst of related changes is reported together
+const WATCH_SETTLE_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Parser, Clone)]
 pub struct ListCommand {
     /// Controls the output format.
     /// "table" and "json" are possible formats.
     #[arg(long = "format", default_value = "table")]
     format: CliOutputFormatKind,
+
+    /// A comma separated list of the fields to output, in order.
+    /// The default for table output is
+    /// `window_id,tab_id,pane_id,workspace,size,title,cwd`,
+    /// while JSON output includes all of the fields.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = PossibleValuesParser::new(FIELDS.iter().map(|f| f.name)),
+    )]
+    fields: Vec<String>,
+
+    /// Keep running, and output the list again whenever windows,
+    /// tabs or panes are added, removed, moved or retitled.
+    /// JSON output is written as a single line per update.
+    #[arg(long)]
+    watch: bool,
 }
 
 impl ListCommand {
     pub async fn run(&self, client: Client) -> anyhow::Result<()> {
-        let out = std::io::stdout();
+        if !self.watch {
+            return self.list(&client).await;
+        }
+
+        let events = MuxEventKind::NAMES
+            .iter()
+            .filter(|name| !IGNORED_EVENTS.contains(name))
+            .map(|name| name.to_string())
+            .collect();
+        let rx = client.subscribe_events(events).await?;
+
+        loop {
+            self.list(&client).await?;
+            // The channel is closed when the connection to the mux goes away
+            if rx.recv().await.is_err() {
+                return Ok(());
+            }
+            smol::Timer::after(WATCH_SETTLE_TIME).await;
+            while rx.try_recv().is_ok() {}
+            if let CliOutputFormatKind::Table = self.format {
+                println!();
+            }
+        }
+    }
 
+    async fn list(&self, client: &Client) -> anyhow::Result<()> {
         let mut output_items = vec![];
         let panes = client.list_panes().await?;
 
@@ -44,59 +141,87 @@ impl ListCommand {
         }
         match self.format {
             CliOutputFormatKind::Json => {
-                let mut writer = serde_json::Serializer::pretty(out.lock());
-                writer.collect_seq(output_items.iter())?;
+                if self.fields.is_empty() {
+                    self.write_json(&output_items)?;
+                } else {
+                    let mut items = vec![];
+                    for output_item in &output_items {
+                        let item = serde_json::to_value(output_item)?;
+                        items.push(SelectedFields(
+                            self.fields
+                                .iter()
+                                .map(|name| (name.as_str(), item[name.as_str()].clone()))
+                                .collect(),
+                        ));
+                    }
+                    self.write_json(&items)?;
+                }
             }
             CliOutputFormatKind::Table => {
-                let cols = vec![
-                    Column {
-                        name: "WINID".to_string(),
-                        alignment: Alignment::Right,
-                    },
-                    Column {
-                        name: "TABID".to_string(),
-                        alignment: Alignment::Right,
-                    },
-                    Column {
-                        name: "PANEID".to_string(),
-                        alignment: Alignment::Right,
-                    },
-                    Column {
-                        name: "WORKSPACE".to_string(),
-                        alignment: Alignment::Left,
-                    },
-                    Column {
-                        name: "SIZE".to_string(),
-                        alignment: Alignment::Left,
-                    },
-                    Column {
-                        name: "TITLE".to_string(),
-                        alignment: Alignment::Left,
-                    },
-                    Column {
-                        name: "CWD".to_string(),
-                        alignment: Alignment::Left,
-                    },
-                ];
-                let data = output_items
+                let fields: Vec<&Field> = if self.fields.is_empty() {
+                    FIELDS[..DEFAULT_TABLE_FIELDS].iter().collect()
+                } else {
+                    self.fields
+                        .iter()
+                        .filter_map(|name| FIELDS.iter().find(|f| f.name == name))
+                        .collect()
+                };
+                let cols = fields
                     .iter()
-                    .map(|output_item| {
-                        vec![
-                            output_item.window_id.to_string(),
-                            output_item.tab_id.to_string(),
-                            output_item.pane_id.to_string(),
-                            output_item.workspace.to_string(),
-                            format!("{}x{}", output_item.size.cols, output_item.size.rows),
-                            output_item.title.to_string(),
-                            output_item.cwd.to_string(),
-                        ]
+                    .map(|field| Column {
+                        name: field.heading.to_string(),
+                        alignment: field.alignment,
                     })
                     .collect::<Vec<_>>();
-                tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
+                let mut data = vec![];
+                for output_item in &output_items {
+                    let item = serde_json::to_value(output_item)?;
+                    data.push(
+                        fields
+                            .iter()
+                            .map(|field| table_cell(&item, field.name))
+                            .collect::<Vec<_>>(),
+                    );
+                }
+                let mut out = std::io::stdout().lock();
+                tabulate_output(&cols, &data, &mut out)?;
+                out.flush()?;
             }
         }
         Ok(())
     }
+
+    fn write_json<T: serde::Serialize>(&self, items: &[T]) -> anyhow::Result<()> {
+        let mut out = std::io::stdout().lock();
+        if self.watch {
+            serde_json::to_writer(&mut out, items)?;
+            writeln!(out)?;
+        } else {
+            let mut writer = serde_json::Serializer::pretty(&mut out);
+            writer.collect_seq(items.iter())?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// The fields selected by `--fields`, which are serialized as
+/// an object with its keys in the order that they were requested
+struct SelectedFields<'a>(Vec<(&'a str, serde_json::Value)>);
+
+impl serde::Serialize for SelectedFields<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, value)))
+    }
+}
+
+fn table_cell(item: &serde_json::Value, name: &str) -> String {
+    match (name, &item[name]) {
+        ("size", size) => format!("{}x{}", size["cols"], size["rows"]),
+        (_, serde_json::Value::Null) => String::new(),
+        (_, serde_json::Value::String(s)) => s.to_string(),
+        (_, value) => value.to_string(),
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -139,6 +264,7 @@ struct CliListResultItem {
     is_active: bool,
     is_zoomed: bool,
     tty_name: Option<String>,
+    foreground_process_name: Option<String>,
 }
 
 impl CliListResultItem {
@@ -157,6 +283,7 @@ impl CliListResultItem {
             is_active_pane,
             is_zoomed_pane,
             tty_name,
+            foreground_process_name,
             size:
                 TerminalSize {
                     rows,
@@ -197,6 +324,7 @@ impl CliListResultItem {
             is_active: is_active_pane,
             is_zoomed: is_zoomed_pane,
             tty_name,
+            foreground_process_name,
         }
     }
 }
@@ -0,0 +1,146 @@
+use crate::cli::CliOutputFormatKind;
+use clap::Parser;
+use serde::Serializer as _;
+use std::collections::HashSet;
+use tabout::{tabulate_output, Alignment, Column};
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone, Copy)]
+pub struct ListWorkspacesCommand {
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+}
+
+impl ListWorkspacesCommand {
+    pub async fn run(&self, client: Client) -> anyhow::Result<()> {
+        let panes = client.list_panes().await?;
+        let clients = client.list_clients().await?;
+
+        let mut output_items: Vec<CliListWorkspacesResultItem> = vec![];
+        let mut windows = HashSet::new();
+
+        for tabroot in panes.tabs {
+            let mut cursor = tabroot.into_tree().cursor();
+            let mut first_pane_in_tab = true;
+
+            loop {
+                if let Some(entry) = cursor.leaf_mut() {
+                    let idx = match output_items
+                        .iter()
+                        .position(|item| item.workspace == entry.workspace)
+                    {
+                        Some(idx) => idx,
+                        None => {
+                            output_items.push(CliListWorkspacesResultItem::new(&entry.workspace));
+                            output_items.len() - 1
+                        }
+                    };
+                    let item = &mut output_items[idx];
+                    if windows.insert(entry.window_id) {
+                        item.windows += 1;
+                    }
+                    if first_pane_in_tab {
+                        item.tabs += 1;
+                        first_pane_in_tab = false;
+                    }
+                    item.panes += 1;
+                }
+                match cursor.preorder_next() {
+                    Ok(c) => cursor = c,
+                    Err(_) => break,
+                }
+            }
+        }
+
+        // A client may have switched to a workspace that has
+        // no windows just yet
+        for info in clients.clients {
+            if let Some(workspace) = info.active_workspace {
+                match output_items
+                    .iter_mut()
+                    .find(|item| item.workspace == workspace)
+                {
+                    Some(item) => item.clients += 1,
+                    None => {
+                        let mut item = CliListWorkspacesResultItem::new(&workspace);
+                        item.clients = 1;
+                        output_items.push(item);
+                    }
+                }
+            }
+        }
+
+        match self.format {
+            CliOutputFormatKind::Json => {
+                let mut writer = serde_json::Serializer::pretty(std::io::stdout().lock());
+                writer.collect_seq(output_items.iter())?;
+            }
+            CliOutputFormatKind::Table => {
+                let cols = vec![
+                    Column {
+                        name: "WORKSPACE".to_string(),
+                        alignment: Alignment::Left,
+                    },
+                    Column {
+                        name: "WINDOWS".to_string(),
+                        alignment: Alignment::Right,
+                    },
+                    Column {
+                        name: "TABS".to_string(),
+                        alignment: Alignment::Right,
+                    },
+                    Column {
+                        name: "PANES".to_string(),
+                        alignment: Alignment::Right,
+                    },
+                    Column {
+                        name: "CLIENTS".to_string(),
+                        alignment: Alignment::Right,
+                    },
+                ];
+                let data = output_items
+                    .iter()
+                    .map(|item| {
+                        vec![
+                            item.workspace.to_string(),
+                            item.windows.to_string(),
+                            item.tabs.to_string(),
+                            item.panes.to_string(),
+                            item.clients.to_string(),
+                        ]
+                    })
+                    .collect::<Vec<_>>();
+                tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// This will be serialized to JSON via the 'ListWorkspaces' command.
+// As such it is intended to be a stable output format,
+// Thus we need to be careful about both the fields and their types,
+// herein as they are directly reflected in the output.
+#[derive(serde::Serialize)]
+struct CliListWorkspacesResultItem {
+    workspace: String,
+    windows: usize,
+    tabs: usize,
+    panes: usize,
+    /// The number of clients for which this is the active workspace
+    clients: usize,
+}
+
+impl CliListWorkspacesResultItem {
+    fn new(workspace: &str) -> Self {
+        Self {
+            workspace: workspace.to_string(),
+            windows: 0,
+            tabs: 0,
+            panes: 0,
+            clients: 0,
+        }
+    }
+}
@@ -7,22 +7,40 @@ mod activate_pane;
 mod activate_pane_direction;
 mod activate_tab;
 mod adjust_pane_size;
+mod capture_pane;
+mod complete;
+mod cp;
+mod exec;
 mod get_pane_direction;
 mod get_text;
 mod kill_pane;
+mod layout;
 mod list;
 mod list_clients;
+mod list_workspaces;
+mod move_pane;
 mod move_pane_to_new_tab;
+mod move_tab;
 mod proxy;
 mod rename_workspace;
+mod screenshot;
+mod send_keys;
 mod send_text;
+mod set_pane_title;
 mod set_tab_title;
 mod set_window_title;
 mod spawn_command;
 mod split_pane;
+mod ssh_forward;
+mod stats;
+mod subscribe;
+mod switch_workspace;
 mod tls_creds;
+mod wait_for_exit;
 mod zoom_pane;
 
+pub use complete::{add_bash_completions, add_fish_completions, add_zsh_completions};
+
 #[derive(Debug, Parser, Clone, Copy)]
 enum CliOutputFormatKind {
     #[command(name = "table", about = "multi line space separated table")]
@@ -81,6 +99,9 @@ enum CliSubCommand {
     #[command(name = "list-clients", about = "list clients")]
     ListClients(list_clients::ListClientsCommand),
 
+    #[command(name = "list-workspaces", about = "list workspaces")]
+    ListWorkspaces(list_workspaces::ListWorkspacesCommand),
+
     #[command(name = "proxy", about = "start rpc proxy pipe")]
     Proxy(proxy::ProxyCommand),
 
@@ -94,6 +115,17 @@ enum CliSubCommand {
     )]
     MovePaneToNewTab(move_pane_to_new_tab::MovePaneToNewTab),
 
+    /// Move a pane alongside another pane, into another tab,
+    /// or break it out into a new tab or window
+    #[command(name = "move-pane", rename_all = "kebab")]
+    MovePane(move_pane::MovePane),
+
+    /// Move a tab into another window, or into a new window,
+    /// optionally in a different workspace.
+    /// Outputs the window-id of the window that now contains the tab
+    #[command(name = "move-tab", rename_all = "kebab")]
+    MoveTab(move_tab::MoveTab),
+
     #[command(
         name = "split-pane",
         rename_all = "kebab",
@@ -117,10 +149,26 @@ Outputs the pane-id for the newly created pane on success"
     #[command(name = "send-text", rename_all = "kebab")]
     SendText(send_text::SendText),
 
+    /// Send key presses to a pane, as though they were typed.
+    /// Keys are encoded according to the keyboard modes that are
+    /// currently active in the pane.
+    #[command(name = "send-keys", rename_all = "kebab")]
+    SendKeys(send_keys::SendKeys),
+
     /// Retrieves the textual content of a pane and output it to stdout
     #[command(name = "get-text", rename_all = "kebab")]
     GetText(get_text::GetText),
 
+    /// Captures the content of a pane, including its scrollback,
+    /// as text, text with escape sequences, html or an asciicast
+    #[command(name = "capture-pane", rename_all = "kebab")]
+    CapturePane(capture_pane::CapturePane),
+
+    /// Render the content of a pane to a png image, using the
+    /// fonts and colors of the multiplexer server
+    #[command(name = "screenshot", rename_all = "kebab")]
+    Screenshot(screenshot::Screenshot),
+
     /// Activate an adjacent pane in the specified direction.
     #[command(name = "activate-pane-direction", rename_all = "kebab")]
     ActivatePaneDirection(activate_pane_direction::ActivatePaneDirection),
@@ -156,16 +204,75 @@ Outputs the pane-id for the newly created pane on success"
     #[command(name = "set-window-title", rename_all = "kebab")]
     SetWindowTitle(set_window_title::SetWindowTitle),
 
+    /// Change the title of a pane, overriding the title
+    /// set by the program running in it
+    #[command(name = "set-pane-title", rename_all = "kebab")]
+    SetPaneTitle(set_pane_title::SetPaneTitle),
+
     /// Rename a workspace
     #[command(name = "rename-workspace", rename_all = "kebab")]
     RenameWorkspace(rename_workspace::RenameWorkspace),
 
+    /// Switch the gui to a different workspace
+    #[command(name = "switch-workspace", rename_all = "kebab")]
+    SwitchWorkspace(switch_workspace::SwitchWorkspace),
+
+    /// Establish or list port forwards for an ssh domain
+    #[command(name = "ssh-forward", rename_all = "kebab")]
+    SshForward(ssh_forward::SshForward),
+
+    /// Copy a file to or from the host of an ssh domain
+    #[command(name = "cp", rename_all = "kebab")]
+    Cp(cp::Cp),
+
+    /// Wait for the process running in a pane to terminate,
+    /// and exit with its exit status
+    #[command(name = "wait-for-exit", rename_all = "kebab")]
+    WaitForExit(wait_for_exit::WaitForExit),
+
+    /// Run a command in a hidden pane, optionally in a different
+    /// domain, copying its output to stdout and exiting with its
+    /// exit status
+    #[command(name = "exec", rename_all = "kebab", trailing_var_arg = true)]
+    Exec(exec::Exec),
+
+    /// Output a JSON description of the windows, tabs and panes,
+    /// that can be used with `wezterm cli apply-layout`
+    #[command(name = "dump-layout", rename_all = "kebab")]
+    DumpLayout(layout::DumpLayout),
+
+    /// Create the windows, tabs and panes described by a layout
+    /// file produced by `wezterm cli dump-layout`
+    #[command(name = "apply-layout", rename_all = "kebab")]
+    ApplyLayout(layout::ApplyLayout),
+
+    /// Print a line of JSON for each event of interest that
+    /// happens in the multiplexer, until interrupted
+    #[command(name = "subscribe", rename_all = "kebab")]
+    Subscribe(subscribe::Subscribe),
+
+    /// Print the metrics that have been recorded by the multiplexer,
+    /// such as parse throughput, frame times and the memory used by
+    /// each pane
+    #[command(name = "stats", rename_all = "kebab")]
+    Stats(stats::Stats),
+
     /// Zoom, unzoom, or toggle zoom state
     #[command(name = "zoom-pane", rename_all = "kebab")]
     ZoomPane(zoom_pane::ZoomPane),
+
+    /// Print the candidates for the value of an option, such as the
+    /// ids of the panes; used by the scripts produced by
+    /// `wezterm shell-completion`
+    #[command(name = "complete", rename_all = "kebab", hide = true)]
+    Complete(complete::Complete),
 }
 
 async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()> {
+    if let CliSubCommand::Complete(cmd) = &cli.sub {
+        return cmd.run(&cli, &crate::init_config(opts)?).await;
+    }
+
     let mut ui = mux::connui::ConnectionUI::new_headless();
     let initial = true;
 
@@ -182,10 +289,16 @@ async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()>
     match cli.sub {
         CliSubCommand::ListClients(cmd) => cmd.run(client).await,
         CliSubCommand::List(cmd) => cmd.run(client).await,
+        CliSubCommand::ListWorkspaces(cmd) => cmd.run(client).await,
         CliSubCommand::MovePaneToNewTab(cmd) => cmd.run(client).await,
+        CliSubCommand::MovePane(cmd) => cmd.run(client).await,
+        CliSubCommand::MoveTab(cmd) => cmd.run(client).await,
         CliSubCommand::SplitPane(cmd) => cmd.run(client).await,
         CliSubCommand::SendText(cmd) => cmd.run(client).await,
+        CliSubCommand::SendKeys(cmd) => cmd.run(client).await,
         CliSubCommand::GetText(cmd) => cmd.run(client).await,
+        CliSubCommand::CapturePane(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
+        CliSubCommand::Screenshot(cmd) => cmd.run(client).await,
         CliSubCommand::SpawnCommand(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
         CliSubCommand::Proxy(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
         CliSubCommand::TlsCreds(cmd) => cmd.run(client).await,
@@ -197,8 +310,19 @@ async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()>
         CliSubCommand::ActivateTab(cmd) => cmd.run(client).await,
         CliSubCommand::SetTabTitle(cmd) => cmd.run(client).await,
         CliSubCommand::SetWindowTitle(cmd) => cmd.run(client).await,
+        CliSubCommand::SetPaneTitle(cmd) => cmd.run(client).await,
         CliSubCommand::RenameWorkspace(cmd) => cmd.run(client).await,
+        CliSubCommand::SwitchWorkspace(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
         CliSubCommand::ZoomPane(cmd) => cmd.run(client).await,
+        CliSubCommand::SshForward(cmd) => cmd.run(client).await,
+        CliSubCommand::Cp(cmd) => cmd.run(client).await,
+        CliSubCommand::WaitForExit(cmd) => cmd.run(client).await,
+        CliSubCommand::Exec(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
+        CliSubCommand::DumpLayout(cmd) => cmd.run(client).await,
+        CliSubCommand::ApplyLayout(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
+        CliSubCommand::Subscribe(cmd) => cmd.run(client).await,
+        CliSubCommand::Stats(cmd) => cmd.run(client).await,
+        CliSubCommand::Complete(_) => unreachable!("handled before connecting"),
     }
 }
 
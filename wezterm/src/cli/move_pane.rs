@@ -0,0 +1,186 @@
+use clap::Parser;
+use mux::pane::PaneId;
+use mux::tab::{SplitDirection, SplitRequest, SplitSize, TabId};
+use mux::window::WindowId;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct MovePane {
+    /// Specify the pane that should be moved.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// Move the pane into a split alongside the specified pane,
+    /// which may be in a different tab or window.
+    #[arg(
+        long,
+        required_unless_present_any=&["tab_id", "new_tab", "new_window"],
+        conflicts_with_all=&["tab_id", "new_tab", "new_window"],
+    )]
+    target_pane_id: Option<PaneId>,
+
+    /// Move the pane into a split alongside the active pane of
+    /// the specified tab.
+    #[arg(long, conflicts_with_all=&["new_tab", "new_window"])]
+    tab_id: Option<TabId>,
+
+    /// Break the pane out into a new tab.
+    /// The tab is created in the window that currently contains
+    /// the pane, unless `--window-id` is specified.
+    #[arg(long, conflicts_with = "new_window")]
+    new_tab: bool,
+
+    /// Break the pane out into a new tab in a new window.
+    #[arg(long)]
+    new_window: bool,
+
+    /// When using `--new-tab`, specify the window into which
+    /// the new tab will be created.
+    #[arg(long, requires = "new_tab")]
+    window_id: Option<WindowId>,
+
+    /// When using `--new-window`, override the default workspace
+    /// name with the provided name.  The default name is "default".
+    #[arg(long, requires = "new_window")]
+    workspace: Option<String>,
+
+    /// Place the pane on the left of the target pane
+    #[arg(long, conflicts_with_all=&["right", "top", "bottom"])]
+    left: bool,
+
+    /// Place the pane on the right of the target pane
+    #[arg(long, conflicts_with_all=&["left", "top", "bottom"])]
+    right: bool,
+
+    /// Place the pane above the target pane
+    #[arg(long, conflicts_with_all=&["left", "right", "bottom"])]
+    top: bool,
+
+    /// Place the pane below the target pane.
+    /// This is the default if no other direction is specified.
+    #[arg(long, conflicts_with_all=&["left", "right", "top"])]
+    bottom: bool,
+
+    /// Rather than splitting the target pane, split the entire
+    /// tab that contains it.
+    #[arg(long)]
+    top_level: bool,
+
+    /// The number of cells that the moved pane should have.
+    /// If omitted, 50% of the available space is used.
+    #[arg(long)]
+    cells: Option<usize>,
+
+    /// Specify the number of cells that the moved pane should
+    /// have, expressed as a percentage of the available space.
+    #[arg(long, conflicts_with = "cells")]
+    percent: Option<u8>,
+}
+
+impl MovePane {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+
+        if self.new_tab || self.new_window {
+            let window_id = if self.new_window {
+                None
+            } else {
+                match self.window_id {
+                    Some(window_id) => Some(window_id),
+                    None => Some(self.window_containing(&client, pane_id).await?),
+                }
+            };
+            let moved = client
+                .move_pane_to_new_tab(codec::MovePaneToNewTab {
+                    pane_id,
+                    window_id,
+                    workspace_for_new_window: self.workspace.clone(),
+                })
+                .await?;
+            log::debug!("{:?}", moved);
+            return Ok(());
+        }
+
+        let target_pane_id = match (self.target_pane_id, self.tab_id) {
+            (Some(target), _) => target,
+            (None, Some(tab_id)) => self.active_pane_in_tab(&client, tab_id).await?,
+            (None, None) => anyhow::bail!("no destination was specified"),
+        };
+        if target_pane_id == pane_id {
+            anyhow::bail!("cannot move pane {pane_id} alongside itself");
+        }
+
+        let direction = if self.left || self.right {
+            SplitDirection::Horizontal
+        } else {
+            SplitDirection::Vertical
+        };
+        let size = match (self.cells, self.percent) {
+            (Some(c), _) => SplitSize::Cells(c),
+            (_, Some(p)) => SplitSize::Percent(p),
+            (None, None) => SplitSize::Percent(50),
+        };
+
+        let moved = client
+            .split_pane(codec::SplitPane {
+                pane_id: target_pane_id,
+                split_request: SplitRequest {
+                    direction,
+                    target_is_second: !(self.left || self.top),
+                    size,
+                    top_level: self.top_level,
+                },
+                domain: config::keyassignment::SpawnTabDomain::CurrentPaneDomain,
+                command: None,
+                command_dir: None,
+                move_pane_id: Some(pane_id),
+            })
+            .await?;
+        log::debug!("{:?}", moved);
+        Ok(())
+    }
+
+    async fn window_containing(
+        &self,
+        client: &Client,
+        pane_id: PaneId,
+    ) -> anyhow::Result<WindowId> {
+        let panes = client.list_panes().await?;
+        for tabroot in panes.tabs {
+            let mut cursor = tabroot.into_tree().cursor();
+            loop {
+                if let Some(entry) = cursor.leaf_mut() {
+                    if entry.pane_id == pane_id {
+                        return Ok(entry.window_id);
+                    }
+                }
+                match cursor.preorder_next() {
+                    Ok(c) => cursor = c,
+                    Err(_) => break,
+                }
+            }
+        }
+        anyhow::bail!("pane {pane_id} not found");
+    }
+
+    async fn active_pane_in_tab(&self, client: &Client, tab_id: TabId) -> anyhow::Result<PaneId> {
+        let panes = client.list_panes().await?;
+        for tabroot in panes.tabs {
+            let mut cursor = tabroot.into_tree().cursor();
+            loop {
+                if let Some(entry) = cursor.leaf_mut() {
+                    if entry.tab_id == tab_id && entry.is_active_pane {
+                        return Ok(entry.pane_id);
+                    }
+                }
+                match cursor.preorder_next() {
+                    Ok(c) => cursor = c,
+                    Err(_) => break,
+                }
+            }
+        }
+        anyhow::bail!("tab {tab_id} not found");
+    }
+}
@@ -0,0 +1,81 @@
+use clap::Parser;
+use mux::pane::PaneId;
+use mux::tab::TabId;
+use mux::window::WindowId;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct MoveTab {
+    /// Specify the tab to move.
+    /// The default is the tab containing the current pane.
+    #[arg(long)]
+    tab_id: Option<TabId>,
+
+    /// Specify the current pane.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    /// The pane is used to determine the tab when `--tab-id`
+    /// is not specified.
+    #[arg(long, conflicts_with = "tab_id")]
+    pane_id: Option<PaneId>,
+
+    /// Move the tab into the specified window
+    #[arg(
+        long,
+        required_unless_present_any=&["new_window", "workspace"],
+        conflicts_with_all=&["new_window", "workspace"],
+    )]
+    window_id: Option<WindowId>,
+
+    /// Move the tab into a new window, in the same workspace as
+    /// its current window
+    #[arg(long)]
+    new_window: bool,
+
+    /// Move the tab into a new window in the specified workspace.
+    /// Implies `--new-window`.
+    #[arg(long)]
+    workspace: Option<String>,
+}
+
+impl MoveTab {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let tab_id = match self.tab_id {
+            Some(tab_id) => tab_id,
+            None => {
+                let pane_id = client.resolve_pane_id(self.pane_id).await?;
+                self.tab_containing(&client, pane_id).await?
+            }
+        };
+
+        let moved = client
+            .move_tab(codec::MoveTab {
+                tab_id,
+                window_id: self.window_id,
+                workspace: self.workspace,
+            })
+            .await?;
+        log::debug!("{:?}", moved);
+        println!("{}", moved.window_id);
+        Ok(())
+    }
+
+    async fn tab_containing(&self, client: &Client, pane_id: PaneId) -> anyhow::Result<TabId> {
+        let panes = client.list_panes().await?;
+        for tabroot in panes.tabs {
+            let mut cursor = tabroot.into_tree().cursor();
+            loop {
+                if let Some(entry) = cursor.leaf_mut() {
+                    if entry.pane_id == pane_id {
+                        return Ok(entry.tab_id);
+                    }
+                }
+                match cursor.preorder_next() {
+                    Ok(c) => cursor = c,
+                    Err(_) => break,
+                }
+            }
+        }
+        anyhow::bail!("pane {pane_id} not found");
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::Context;
+use clap::{Parser, ValueHint};
+use mux::pane::PaneId;
+use std::path::PathBuf;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct Screenshot {
+    /// Specify the target pane.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// The file to which the png image will be written
+    #[arg(long, short, value_hint=ValueHint::FilePath)]
+    out: PathBuf,
+
+    /// The DPI at which to render the text.
+    /// The default is to use the `dpi` from the configuration
+    /// of the multiplexer server, or 96 if that is not set.
+    #[arg(long)]
+    dpi: Option<f64>,
+}
+
+impl Screenshot {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+        let response = client
+            .get_pane_screenshot(codec::GetPaneScreenshot {
+                pane_id,
+                dpi: self.dpi,
+            })
+            .await?;
+        std::fs::write(&self.out, response.png)
+            .with_context(|| format!("writing {}", self.out.display()))?;
+        Ok(())
+    }
+}
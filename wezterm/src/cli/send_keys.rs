@@ -0,0 +1,257 @@
+use clap::Parser;
+use codec::InputSerial;
+use mux::pane::PaneId;
+use termwiz::input::{KeyCode, KeyEvent, Modifiers};
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct SendKeys {
+    /// Specify the target pane.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// Type each KEY as literal text, rather than interpreting
+    /// key names and modifiers.
+    #[arg(long, short)]
+    literal: bool,
+
+    /// The keys to send, in order.
+    /// A KEY is either a key name such as `Enter`, `Up` or `F5`,
+    /// or a single character, optionally prefixed by one or more
+    /// modifiers: `C-` (Ctrl), `M-` or `A-` (Alt), `S-` (Shift)
+    /// and `D-` (Super), eg: `C-c` or `C-S-Left`.
+    /// Anything else is typed as literal text.
+    #[arg(required = true)]
+    keys: Vec<String>,
+}
+
+impl SendKeys {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+
+        // Parse everything up front so that a typo doesn't
+        // leave the keys half sent
+        let mut events = vec![];
+        for key in &self.keys {
+            if self.literal {
+                events.extend(literal_text(key));
+            } else {
+                events.extend(parse_key(key)?);
+            }
+        }
+
+        for event in events {
+            client
+                .key_down(codec::SendKeyDown {
+                    pane_id,
+                    event,
+                    input_serial: InputSerial::now(),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn literal_text(text: &str) -> impl Iterator<Item = KeyEvent> + '_ {
+    text.chars().map(|c| KeyEvent {
+        key: KeyCode::Char(c),
+        modifiers: Modifiers::NONE,
+    })
+}
+
+fn key_by_name(name: &str) -> Option<KeyEvent> {
+    let mut modifiers = Modifiers::NONE;
+    let key = match name.to_ascii_lowercase().as_str() {
+        "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "btab" => {
+            modifiers = Modifiers::SHIFT;
+            KeyCode::Tab
+        }
+        "escape" | "esc" => KeyCode::Escape,
+        "space" => KeyCode::Char(' '),
+        "bspace" | "backspace" => KeyCode::Backspace,
+        "up" | "uparrow" => KeyCode::UpArrow,
+        "down" | "downarrow" => KeyCode::DownArrow,
+        "left" | "leftarrow" => KeyCode::LeftArrow,
+        "right" | "rightarrow" => KeyCode::RightArrow,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" | "pgup" | "ppage" => KeyCode::PageUp,
+        "pagedown" | "pgdn" | "npage" => KeyCode::PageDown,
+        "insert" | "ic" => KeyCode::Insert,
+        "delete" | "del" | "dc" => KeyCode::Delete,
+        lower => {
+            let n: u8 = lower.strip_prefix('f')?.parse().ok()?;
+            if n == 0 || n > 24 {
+                return None;
+            }
+            KeyCode::Function(n)
+        }
+    };
+    Some(KeyEvent { key, modifiers })
+}
+
+/// Parse a key specification such as `C-c`, `M-S-Up` or `F5`.
+/// Specifications that don't describe a key are treated as
+/// literal text, unless they include modifiers.
+fn parse_key(spec: &str) -> anyhow::Result<Vec<KeyEvent>> {
+    let mut modifiers = Modifiers::NONE;
+    let mut remain = spec;
+    loop {
+        // Don't mistake a `-` that is the key itself for a modifier
+        if remain.len() < 3 {
+            break;
+        }
+        let modifier = match remain.get(0..2) {
+            Some("C-") => Modifiers::CTRL,
+            Some("M-" | "A-") => Modifiers::ALT,
+            Some("S-") => Modifiers::SHIFT,
+            Some("D-") => Modifiers::SUPER,
+            _ => break,
+        };
+        modifiers |= modifier;
+        remain = &remain[2..];
+    }
+
+    let mut chars = remain.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(vec![KeyEvent {
+            key: KeyCode::Char(c),
+            modifiers,
+        }]);
+    }
+
+    match key_by_name(remain) {
+        Some(mut event) => {
+            event.modifiers |= modifiers;
+            Ok(vec![event])
+        }
+        None if modifiers.is_empty() => Ok(literal_text(spec).collect()),
+        None => anyhow::bail!("{spec}: {remain} is not a valid key name"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(key: KeyCode, modifiers: Modifiers) -> KeyEvent {
+        KeyEvent { key, modifiers }
+    }
+
+    fn chars(text: &str) -> Vec<KeyEvent> {
+        text.chars()
+            .map(|c| key(KeyCode::Char(c), Modifiers::NONE))
+            .collect()
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            key_by_name("Enter"),
+            Some(key(KeyCode::Enter, Modifiers::NONE))
+        );
+        assert_eq!(
+            key_by_name("ESC"),
+            Some(key(KeyCode::Escape, Modifiers::NONE))
+        );
+        assert_eq!(
+            key_by_name("pgdn"),
+            Some(key(KeyCode::PageDown, Modifiers::NONE))
+        );
+        assert_eq!(
+            key_by_name("BTab"),
+            Some(key(KeyCode::Tab, Modifiers::SHIFT))
+        );
+        assert_eq!(
+            key_by_name("F1"),
+            Some(key(KeyCode::Function(1), Modifiers::NONE))
+        );
+        assert_eq!(
+            key_by_name("f24"),
+            Some(key(KeyCode::Function(24), Modifiers::NONE))
+        );
+        assert_eq!(key_by_name("F0"), None);
+        assert_eq!(key_by_name("F25"), None);
+        assert_eq!(key_by_name("Fx"), None);
+        assert_eq!(key_by_name("nope"), None);
+    }
+
+    #[test]
+    fn single_keys() {
+        assert_eq!(
+            parse_key("a").unwrap(),
+            vec![key(KeyCode::Char('a'), Modifiers::NONE)]
+        );
+        assert_eq!(
+            parse_key("-").unwrap(),
+            vec![key(KeyCode::Char('-'), Modifiers::NONE)]
+        );
+        assert_eq!(
+            parse_key("Up").unwrap(),
+            vec![key(KeyCode::UpArrow, Modifiers::NONE)]
+        );
+        assert_eq!(
+            parse_key("F5").unwrap(),
+            vec![key(KeyCode::Function(5), Modifiers::NONE)]
+        );
+    }
+
+    #[test]
+    fn modifiers() {
+        assert_eq!(
+            parse_key("C-c").unwrap(),
+            vec![key(KeyCode::Char('c'), Modifiers::CTRL)]
+        );
+        assert_eq!(
+            parse_key("M-x").unwrap(),
+            vec![key(KeyCode::Char('x'), Modifiers::ALT)]
+        );
+        assert_eq!(
+            parse_key("A-x").unwrap(),
+            vec![key(KeyCode::Char('x'), Modifiers::ALT)]
+        );
+        assert_eq!(
+            parse_key("C-S-Left").unwrap(),
+            vec![key(KeyCode::LeftArrow, Modifiers::CTRL | Modifiers::SHIFT)]
+        );
+        assert_eq!(
+            parse_key("D-enter").unwrap(),
+            vec![key(KeyCode::Enter, Modifiers::SUPER)]
+        );
+        assert_eq!(
+            parse_key("S-BTab").unwrap(),
+            vec![key(KeyCode::Tab, Modifiers::SHIFT)]
+        );
+        assert_eq!(
+            parse_key("C--").unwrap(),
+            vec![key(KeyCode::Char('-'), Modifiers::CTRL)]
+        );
+    }
+
+    #[test]
+    fn literal_fallback() {
+        assert_eq!(parse_key("hello").unwrap(), chars("hello"));
+        assert_eq!(parse_key("C-").unwrap(), chars("C-"));
+        // The modifier prefixes are case sensitive
+        assert_eq!(parse_key("c-c").unwrap(), chars("c-c"));
+        assert_eq!(parse_key("F25").unwrap(), chars("F25"));
+        assert_eq!(literal_text("C-c").collect::<Vec<_>>(), chars("C-c"));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            parse_key("C-nope").unwrap_err().to_string(),
+            "C-nope: nope is not a valid key name"
+        );
+        assert_eq!(
+            parse_key("M-F0").unwrap_err().to_string(),
+            "M-F0: F0 is not a valid key name"
+        );
+    }
+}
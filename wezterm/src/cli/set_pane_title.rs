@@ -0,0 +1,30 @@
+use clap::Parser;
+use mux::pane::PaneId;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct SetPaneTitle {
+    /// Specify the target pane.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// The new title for the pane.
+    /// An empty title removes the override, so that the title
+    /// set by the program running in the pane is used again.
+    title: String,
+}
+
+impl SetPaneTitle {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+        client
+            .set_pane_title(codec::PaneTitleChanged {
+                pane_id,
+                title: Some(self.title),
+            })
+            .await?;
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::cli::resolve_relative_cwd;
+use crate::cli::wait_for_exit::wait_for_exit;
 use clap::{Parser, ValueHint};
 use config::keyassignment::SpawnTabDomain;
 use config::ConfigHandle;
@@ -43,6 +44,11 @@ pub struct SpawnCommand {
     #[arg(long, requires = "new_window")]
     workspace: Option<String>,
 
+    /// Wait for the spawned program to terminate, and then
+    /// exit with its exit status.
+    #[arg(long)]
+    wait: bool,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm cli spawn -- bash -l` will spawn bash
     /// as if it were a login shell.
@@ -118,6 +124,11 @@ impl SpawnCommand {
 
         log::debug!("{:?}", spawned);
         println!("{}", spawned.pane_id);
+
+        if self.wait {
+            let code = wait_for_exit(&client, spawned.pane_id).await?;
+            std::process::exit(code);
+        }
         Ok(())
     }
 }
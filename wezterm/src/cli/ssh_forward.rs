@@ -0,0 +1,32 @@
+use clap::Parser;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct SshForward {
+    /// The name of the ssh domain.
+    /// Only ssh domains with `multiplexing = "None"` are supported.
+    #[arg(long)]
+    domain: String,
+
+    /// A forward to establish, using the same syntax as the
+    /// `-L`, `-R` and `-D` options of ssh, eg: `-L 8080:localhost:80`,
+    /// `R2222:localhost:22` or `D1080`.
+    /// When omitted, the existing forwards are listed.
+    #[arg(allow_hyphen_values = true)]
+    spec: Option<String>,
+}
+
+impl SshForward {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let response = client
+            .ssh_forward(codec::SshForward {
+                domain: self.domain,
+                spec: self.spec,
+            })
+            .await?;
+        for forward in response.forwards {
+            println!("{forward}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+use crate::cli::CliOutputFormatKind;
+use clap::Parser;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone, Copy)]
+pub struct Stats {
+    /// Controls the output format.
+    /// "table" and "json" are possible formats.
+    #[arg(long = "format", default_value = "table")]
+    format: CliOutputFormatKind,
+}
+
+impl Stats {
+    pub async fn run(&self, client: Client) -> anyhow::Result<()> {
+        let stats = client.get_stats().await?.stats;
+        let mut out = std::io::stdout().lock();
+        match self.format {
+            CliOutputFormatKind::Json => {
+                serde_json::to_writer_pretty(&mut out, &stats)?;
+                println!();
+            }
+            CliOutputFormatKind::Table => {
+                stats.render(&mut out)?;
+            }
+        }
+        Ok(())
+    }
+}
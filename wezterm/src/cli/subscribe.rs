@@ -0,0 +1,51 @@
+use clap::builder::PossibleValuesParser;
+use clap::Parser;
+use codec::MuxEventKind;
+use std::io::Write;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct Subscribe {
+    /// A comma separated list of the events to report.
+    /// The default is to report all events.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = PossibleValuesParser::new(MuxEventKind::NAMES),
+    )]
+    events: Vec<String>,
+}
+
+impl Subscribe {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let events = if self.events.is_empty() {
+            MuxEventKind::NAMES.iter().map(|s| s.to_string()).collect()
+        } else {
+            self.events
+        };
+        let rx = client.subscribe_events(events).await?;
+
+        let mut stdout = std::io::stdout();
+        // The channel is closed when the connection to the mux goes away
+        while let Ok(event) = rx.recv().await {
+            writeln!(stdout, "{}", event_to_json(&event)?)?;
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Flatten the event into a single object, with its name in the
+/// `event` field, so that `{"PaneOutput":{"pane_id":1}}` becomes
+/// `{"event":"pane-output","pane_id":1}`
+fn event_to_json(event: &MuxEventKind) -> anyhow::Result<String> {
+    let mut fields = match serde_json::to_value(event)? {
+        serde_json::Value::Object(map) => match map.into_iter().next() {
+            Some((_, serde_json::Value::Object(fields))) => fields,
+            _ => serde_json::Map::new(),
+        },
+        _ => serde_json::Map::new(),
+    };
+    fields.insert("event".to_string(), event.name().into());
+    Ok(serde_json::to_string(&fields)?)
+}
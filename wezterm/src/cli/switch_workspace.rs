@@ -0,0 +1,72 @@
+use clap::Parser;
+use config::keyassignment::SpawnTabDomain;
+use config::ConfigHandle;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct SwitchWorkspace {
+    /// If the workspace has no windows, spawn the default program
+    /// into a new window in that workspace, rather than failing.
+    #[arg(long)]
+    create: bool,
+
+    /// When creating the workspace, spawn into the named multiplexer
+    /// domain, rather than the default domain.
+    #[arg(long, requires = "create")]
+    domain_name: Option<String>,
+
+    /// The name of the workspace to switch to
+    workspace: String,
+}
+
+impl SwitchWorkspace {
+    pub async fn run(self, client: Client, config: &ConfigHandle) -> anyhow::Result<()> {
+        let panes = client.list_panes().await?;
+        let exists = panes.tabs.into_iter().any(|tabroot| {
+            let mut cursor = tabroot.into_tree().cursor();
+            loop {
+                if let Some(entry) = cursor.leaf_mut() {
+                    if entry.workspace == self.workspace {
+                        return true;
+                    }
+                }
+                match cursor.preorder_next() {
+                    Ok(c) => cursor = c,
+                    Err(_) => return false,
+                }
+            }
+        });
+
+        if !exists {
+            if !self.create {
+                anyhow::bail!(
+                    "workspace {} has no windows; use --create to create it",
+                    self.workspace
+                );
+            }
+            let spawned = client
+                .spawn_v2(codec::SpawnV2 {
+                    domain: self
+                        .domain_name
+                        .clone()
+                        .map_or(SpawnTabDomain::DefaultDomain, |name| {
+                            SpawnTabDomain::DomainName(name)
+                        }),
+                    window_id: None,
+                    command: None,
+                    command_dir: None,
+                    size: config.initial_size(0, None),
+                    workspace: self.workspace.clone(),
+                })
+                .await?;
+            log::debug!("{:?}", spawned);
+        }
+
+        client
+            .set_active_workspace(codec::SetActiveWorkspace {
+                workspace: self.workspace,
+            })
+            .await?;
+        Ok(())
+    }
+}
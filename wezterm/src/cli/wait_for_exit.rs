@@ -0,0 +1,32 @@
+use clap::Parser;
+use mux::pane::PaneId;
+use wezterm_client::client::Client;
+
+#[derive(Debug, Parser, Clone)]
+pub struct WaitForExit {
+    /// Specify the target pane.
+    #[arg(long)]
+    pane_id: PaneId,
+}
+
+impl WaitForExit {
+    pub async fn run(self, client: Client) -> anyhow::Result<()> {
+        let code = wait_for_exit(&client, self.pane_id).await?;
+        std::process::exit(code);
+    }
+}
+
+/// Wait for the process running in the specified pane to terminate,
+/// and return its exit code
+pub async fn wait_for_exit(client: &Client, pane_id: PaneId) -> anyhow::Result<i32> {
+    let status = client
+        .wait_for_pane_exit(codec::WaitForPaneExit { pane_id })
+        .await?;
+    if let Some(signal) = &status.signal {
+        eprintln!("The process in pane {pane_id} was terminated: {signal}");
+    }
+    match status.exit_code {
+        Some(code) => Ok(code as i32),
+        None => anyhow::bail!("The exit status of the process in pane {pane_id} is unknown"),
+    }
+}
@@ -144,6 +144,11 @@ enum SubCommand {
     },
 }
 
+use termwiz::escape::apc::{
+    KittyAnimationState, KittyFrameCompositionMode, KittyImage, KittyImageAnimation,
+    KittyImageCompression, KittyImageData, KittyImageDelete, KittyImageFormat, KittyImageFrame,
+    KittyImagePlacement, KittyImageTransmit, KittyImageVerbosity,
+};
 use termwiz::escape::osc::{
     ITermDimension, ITermFileData, ITermProprietary, OperatingSystemCommand,
 };
@@ -163,10 +168,26 @@ struct ImgCatCommand {
     #[arg(long = "height")]
     height: Option<ITermDimension>,
     /// Do not respect the aspect ratio.  The default is to respect the aspect
-    /// ratio
+    /// ratio.  This is equivalent to `--scale stretch`.
     #[arg(long = "no-preserve-aspect-ratio")]
     no_preserve_aspect_ratio: bool,
 
+    /// How to scale the image when both `--width` and `--height`
+    /// are specified.
+    /// `fit` preserves the aspect ratio and fits the image within
+    /// the area, `stretch` distorts the image to exactly fill the
+    /// area and `fill` preserves the aspect ratio and crops the image
+    /// so that it fills the area.
+    /// When using the iterm2 protocol, `fill` crops the image before
+    /// it is sent, which will reduce any animated images to a single
+    /// frame.
+    #[arg(
+        long,
+        default_value = "fit",
+        conflicts_with = "no_preserve_aspect_ratio"
+    )]
+    scale: ImageScale,
+
     /// Set the cursor position prior to displaying the image.
     /// The default is to use the current cursor position.
     /// Coordinates are expressed in cells with 0,0 being the top left
@@ -189,6 +210,37 @@ struct ImgCatCommand {
     #[arg(long, value_parser)]
     tmux_passthru: Option<TmuxPassthru>,
 
+    /// Which image protocol to use to display the image.
+    /// When using the kitty protocol, animated GIF, WebP and APNG
+    /// images are decoded here and sent to the terminal as a
+    /// sequence of animation frames.
+    #[arg(long, default_value = "iterm2")]
+    protocol: ImageProtocol,
+
+    /// Specify the z-index of the image; negative values place the
+    /// image beneath the text.
+    /// Requires `--protocol kitty`.
+    #[arg(long, allow_negative_numbers = true)]
+    z_index: Option<i32>,
+
+    /// Specify the id to assign to the image, so that it can later
+    /// be removed using `--delete`.
+    /// The default is to choose an id at random.
+    /// Requires `--protocol kitty`.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    image_id: Option<u32>,
+
+    /// Rather than displaying an image, delete the image with the
+    /// specified id that was previously displayed using
+    /// `--protocol kitty --image-id ID`.
+    #[arg(
+        long,
+        value_name = "ID",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with = "file_name"
+    )]
+    delete: Option<u32>,
+
     /// Set the maximum number of pixels per image frame.
     /// Images will be scaled down so that they do not exceed this size,
     /// unless `--no-resample` is also used.
@@ -287,6 +339,19 @@ fn width_x_height(arg: &str) -> Result<ImageDimension, String> {
     }
 }
 
+#[derive(Copy, Clone, Debug, ValueEnum, PartialEq, Eq)]
+enum ImageProtocol {
+    Iterm2,
+    Kitty,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum, PartialEq, Eq)]
+enum ImageScale {
+    Fit,
+    Stretch,
+    Fill,
+}
+
 #[derive(Copy, Clone, Debug, ValueEnum, Default)]
 enum ResampleFilter {
     Nearest,
@@ -372,7 +437,7 @@ impl ImgCatCommand {
                 let w = h as f32 * aspect;
                 (w as usize, h)
             }
-            (Some(w), Some(_)) if !self.no_preserve_aspect_ratio => {
+            (Some(w), Some(_)) if self.scale_mode() == ImageScale::Fit => {
                 let h = w as f32 / aspect;
                 (w, h as usize)
             }
@@ -383,6 +448,40 @@ impl ImgCatCommand {
         (width / cell_pixel_width, height / cell_pixel_height)
     }
 
+    fn scale_mode(&self) -> ImageScale {
+        if self.no_preserve_aspect_ratio {
+            ImageScale::Stretch
+        } else {
+            self.scale
+        }
+    }
+
+    /// When using `--scale fill`, compute the centered region of the
+    /// image that has the same aspect ratio as the display area.
+    /// Returns (x, y, width, height) in pixels.
+    fn fill_source_rect(
+        &self,
+        info: ImageInfo,
+        term_size: ScreenSize,
+    ) -> Option<(u32, u32, u32, u32)> {
+        if self.scale_mode() != ImageScale::Fill {
+            return None;
+        }
+        let width = self
+            .width
+            .unwrap_or_default()
+            .to_pixels(term_size.xpixel, term_size.cols)?;
+        let height = self
+            .height
+            .unwrap_or_default()
+            .to_pixels(term_size.ypixel, term_size.rows)?;
+
+        let scale = (width as f32 / info.width as f32).max(height as f32 / info.height as f32);
+        let w = ((width as f32 / scale).round() as u32).clamp(1, info.width);
+        let h = ((height as f32 / scale).round() as u32).clamp(1, info.height);
+        Some(((info.width - w) / 2, (info.height - h) / 2, w, h))
+    }
+
     fn image_dimensions(data: &[u8]) -> anyhow::Result<ImageInfo> {
         let reader = image::io::Reader::new(std::io::Cursor::new(data)).with_guessed_format()?;
         let format = reader
@@ -433,11 +532,7 @@ impl ImgCatCommand {
         let mut data = vec![];
         let start = std::time::Instant::now();
 
-        let output_format = match self.resample_format {
-            ResampleImageFormat::Png => image::ImageFormat::Png,
-            ResampleImageFormat::Jpeg => image::ImageFormat::Jpeg,
-            ResampleImageFormat::Input => image_info.format,
-        };
+        let output_format = self.output_format(image_info);
         im.write_to(&mut std::io::Cursor::new(&mut data), output_format)
             .with_context(|| format!("encoding resampled image as {output_format:?}"))?;
 
@@ -458,6 +553,78 @@ impl ImgCatCommand {
         Ok((data, new_info))
     }
 
+    fn output_format(&self, image_info: ImageInfo) -> image::ImageFormat {
+        match self.resample_format {
+            ResampleImageFormat::Png => image::ImageFormat::Png,
+            ResampleImageFormat::Jpeg => image::ImageFormat::Jpeg,
+            ResampleImageFormat::Input => image_info.format,
+        }
+    }
+
+    fn crop_image(
+        &self,
+        data: &[u8],
+        (x, y, width, height): (u32, u32, u32, u32),
+        image_info: ImageInfo,
+    ) -> anyhow::Result<(Vec<u8>, ImageInfo)> {
+        let im = image::load_from_memory(data).context("loading image to crop it")?;
+        let im = im.crop_imm(x, y, width, height);
+
+        let mut data = vec![];
+        let output_format = self.output_format(image_info);
+        im.write_to(&mut std::io::Cursor::new(&mut data), output_format)
+            .with_context(|| format!("encoding cropped image as {output_format:?}"))?;
+
+        Ok((
+            data,
+            ImageInfo {
+                width,
+                height,
+                format: output_format,
+            },
+        ))
+    }
+
+    /// Decode the image into its frames, each of which covers the
+    /// full image.  Images that are not animated produce a single frame.
+    fn decode_frames(data: &[u8], image_info: ImageInfo) -> anyhow::Result<Vec<image::Frame>> {
+        use image::codecs::gif::GifDecoder;
+        use image::codecs::png::PngDecoder;
+        use image::codecs::webp::WebPDecoder;
+        use image::{AnimationDecoder, ImageFormat};
+
+        let cursor = std::io::Cursor::new(data);
+        let frames = match image_info.format {
+            ImageFormat::Gif => GifDecoder::new(cursor)?.into_frames().collect_frames()?,
+            ImageFormat::Png => {
+                let decoder = PngDecoder::new(cursor)?;
+                if decoder.is_apng()? {
+                    decoder.apng()?.into_frames().collect_frames()?
+                } else {
+                    vec![]
+                }
+            }
+            ImageFormat::WebP => {
+                let decoder = WebPDecoder::new(cursor)?;
+                if decoder.has_animation() {
+                    decoder.into_frames().collect_frames()?
+                } else {
+                    vec![]
+                }
+            }
+            _ => vec![],
+        };
+
+        if frames.is_empty() {
+            let im = image::load_from_memory(data)
+                .context("decoding image")?
+                .into_rgba8();
+            Ok(vec![image::Frame::new(im)])
+        } else {
+            Ok(frames)
+        }
+    }
+
     fn get_image_data(&self) -> anyhow::Result<(Vec<u8>, ImageInfo)> {
         let mut data = Vec::new();
         if let Some(file_name) = self.file_name.as_ref() {
@@ -494,7 +661,123 @@ impl ImgCatCommand {
         }
     }
 
+    fn kitty_escape(&self, img: KittyImage) -> String {
+        let st = Esc::Code(EscCode::StringTerminator);
+        self.tmux_passthru
+            .unwrap_or_default()
+            .encode(format!("{img}{st}"))
+    }
+
+    /// Send the image and any animation frames using the kitty
+    /// image protocol
+    fn emit_kitty(
+        &self,
+        data: &[u8],
+        image_info: ImageInfo,
+        (columns, rows): (usize, usize),
+        source_rect: Option<(u32, u32, u32, u32)>,
+    ) -> anyhow::Result<()> {
+        let image_id = self.image_id.unwrap_or_else(random_image_id);
+        let mut frames = Self::decode_frames(data, image_info)?.into_iter();
+        let first = frames
+            .next()
+            .ok_or_else(|| anyhow!("image has no frames"))?;
+        let first_gap = frame_gap_ms(&first);
+
+        let (x, y, w, h) = match source_rect {
+            Some((x, y, w, h)) => (Some(x), Some(y), Some(w), Some(h)),
+            None => (None, None, None, None),
+        };
+        let img = KittyImage::TransmitDataAndDisplay {
+            transmit: rgba_transmit(image_id, first),
+            placement: KittyImagePlacement {
+                x,
+                y,
+                w,
+                h,
+                x_offset: None,
+                y_offset: None,
+                columns: Some(columns.max(1) as u32),
+                rows: Some(rows.max(1) as u32),
+                do_not_move_cursor: self.no_move_cursor,
+                placement_id: None,
+                z_index: self.z_index,
+                virtual_placement: false,
+                parent_image_id: None,
+                parent_placement_id: None,
+                parent_x_offset: None,
+                parent_y_offset: None,
+            },
+            verbosity: KittyImageVerbosity::Quiet,
+        };
+        print!("{}", self.kitty_escape(img));
+
+        // Each decoded frame is a complete image, so it replaces
+        // the content of the frame rather than being blended with it
+        let mut animated = false;
+        for frame in frames {
+            let duration_ms = Some(frame_gap_ms(&frame));
+            let img = KittyImage::TransmitFrame {
+                transmit: rgba_transmit(image_id, frame),
+                frame: KittyImageFrame {
+                    x: None,
+                    y: None,
+                    base_frame: None,
+                    frame_number: None,
+                    duration_ms,
+                    composition_mode: KittyFrameCompositionMode::Overwrite,
+                    background_pixel: None,
+                },
+                verbosity: KittyImageVerbosity::Quiet,
+            };
+            print!("{}", self.kitty_escape(img));
+            animated = true;
+        }
+
+        if animated {
+            // The gap of the first frame can only be set once the
+            // other frames exist; then loop the animation forever
+            let img = KittyImage::AnimationControl {
+                animation: KittyImageAnimation {
+                    image_id: Some(image_id),
+                    image_number: None,
+                    state: Some(KittyAnimationState::Running),
+                    frame_number: Some(1),
+                    duration_ms: Some(first_gap),
+                    current_frame: None,
+                    loops: Some(1),
+                },
+                verbosity: KittyImageVerbosity::Quiet,
+            };
+            print!("{}", self.kitty_escape(img));
+        }
+
+        Ok(())
+    }
+
     fn run(&self) -> anyhow::Result<()> {
+        if let Some(image_id) = self.delete {
+            let img = KittyImage::Delete {
+                what: KittyImageDelete::ByImageId {
+                    image_id,
+                    placement_id: None,
+                    delete: true,
+                },
+                verbosity: KittyImageVerbosity::Quiet,
+            };
+            print!("{}", self.kitty_escape(img));
+            return Ok(());
+        }
+
+        if self.protocol != ImageProtocol::Kitty {
+            if self.z_index.is_some() {
+                anyhow::bail!("--z-index requires --protocol kitty");
+            }
+            if self.image_id.is_some() {
+                anyhow::bail!("--image-id requires --protocol kitty");
+            }
+        }
+
         let (data, image_info) = self.get_image_data()?;
 
         let caps = Capabilities::new_from_env()?;
@@ -527,6 +810,12 @@ impl ImgCatCommand {
 
         term.set_cooked_mode()?;
 
+        let fill_rect = self.fill_source_rect(image_info, term_size);
+        let (data, image_info) = match (self.protocol, fill_rect) {
+            (ImageProtocol::Iterm2, Some(rect)) => self.crop_image(&data, rect, image_info)?,
+            _ => (data, image_info),
+        };
+
         let save_cursor = Esc::Code(EscCode::DecSaveCursorPosition);
         let restore_cursor = Esc::Code(EscCode::DecRestoreCursorPosition);
 
@@ -557,23 +846,31 @@ impl ImgCatCommand {
             }])?;
         }
 
-        let osc = OperatingSystemCommand::ITermProprietary(ITermProprietary::File(Box::new(
-            ITermFileData {
-                name: None,
-                size: Some(data.len()),
-                width: self.width.unwrap_or_default(),
-                height: self.height.unwrap_or_default(),
-                preserve_aspect_ratio: !self.no_preserve_aspect_ratio,
-                inline: true,
-                do_not_move_cursor: self.no_move_cursor,
-                data,
-            },
-        )));
-        let encoded = self
-            .tmux_passthru
-            .unwrap_or_default()
-            .encode(osc.to_string());
-        println!("{encoded}");
+        match self.protocol {
+            ImageProtocol::Iterm2 => {
+                let osc = OperatingSystemCommand::ITermProprietary(ITermProprietary::File(
+                    Box::new(ITermFileData {
+                        name: None,
+                        size: Some(data.len()),
+                        width: self.width.unwrap_or_default(),
+                        height: self.height.unwrap_or_default(),
+                        preserve_aspect_ratio: self.scale_mode() != ImageScale::Stretch,
+                        inline: true,
+                        do_not_move_cursor: self.no_move_cursor,
+                        data,
+                    }),
+                ));
+                let encoded = self
+                    .tmux_passthru
+                    .unwrap_or_default()
+                    .encode(osc.to_string());
+                println!("{encoded}");
+            }
+            ImageProtocol::Kitty => {
+                self.emit_kitty(&data, image_info, image_dims, fill_rect)?;
+                println!();
+            }
+        }
 
         if let ((_cursor_x, cursor_y), true) = (image_dims, needs_force_cursor_move) {
             // tell the terminal that doesn't fully understand the image sequence
@@ -604,6 +901,35 @@ impl ImgCatCommand {
     }
 }
 
+fn rgba_transmit(image_id: u32, frame: image::Frame) -> KittyImageTransmit {
+    let buffer = frame.into_buffer();
+    KittyImageTransmit {
+        format: Some(KittyImageFormat::Rgba),
+        width: Some(buffer.width()),
+        height: Some(buffer.height()),
+        data: KittyImageData::DirectBin(buffer.into_raw()),
+        image_id: Some(image_id),
+        image_number: None,
+        compression: KittyImageCompression::None,
+        more_data_follows: false,
+    }
+}
+
+fn frame_gap_ms(frame: &image::Frame) -> u32 {
+    let (numer, denom) = frame.delay().numer_denom_ms();
+    numer.checked_div(denom).unwrap_or(0)
+}
+
+/// Choose an image id that is unlikely to collide with the
+/// ids used by other programs
+fn random_image_id() -> u32 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    (nanos ^ std::process::id().rotate_left(16)).max(1)
+}
+
 #[derive(Debug, Parser, Clone)]
 struct SetCwdCommand {
     /// The directory to specify.
@@ -637,7 +963,7 @@ impl SetCwdCommand {
         let host = host.to_str().unwrap_or("localhost");
         url.set_host(Some(host))?;
 
-        let osc = OperatingSystemCommand::CurrentWorkingDirectory(url.into());
+        let osc = OperatingSystemCommand::CurrentWorkingDirectory(url.as_str().parse()?);
         let tmux = self.tmux_passthru.unwrap_or_default();
         let encoded = tmux.encode(osc.to_string());
         print!("{encoded}");
@@ -752,7 +1078,19 @@ fn run() -> anyhow::Result<()> {
             use clap::CommandFactory;
             let mut cmd = Opt::command();
             let name = cmd.get_name().to_string();
-            generate_completion(shell, &mut cmd, name, &mut std::io::stdout());
+            let mut script = vec![];
+            generate_completion(shell.clone(), &mut cmd, name.clone(), &mut script);
+            let script = String::from_utf8(script)?;
+            // Teach the shells that support it to complete the ids of
+            // panes, tabs and windows, and the names of workspaces and
+            // domains, by asking the running wezterm
+            let script = match shell {
+                Shell::Bash => cli::add_bash_completions(script, &name),
+                Shell::Zsh => cli::add_zsh_completions(script, &name),
+                Shell::Fish => cli::add_fish_completions(script, &name),
+                Shell::Elvish | Shell::PowerShell | Shell::Fig => script,
+            };
+            print!("{script}");
             Ok(())
         }
     }
@@ -1,7 +1,8 @@
 use crate::bitmaps::{BitmapImage, Texture2d, TextureRect};
 use crate::{Point, Rect, Size};
 use anyhow::{ensure, Result as Fallible};
-use guillotiere::{SimpleAtlasAllocator, Size as AtlasSize};
+use guillotiere::{Allocation, AtlasAllocator, Rectangle, SimpleAtlasAllocator, Size as AtlasSize};
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::rc::Rc;
 use thiserror::*;
@@ -15,13 +16,76 @@ pub struct OutOfTextureSpace {
     pub current_size: usize,
 }
 
+/// The side of a page, in pixels.  Sprites are packed into pages,
+/// which are allocated from the texture as they are needed and are the
+/// unit of eviction.  Sprites that are larger than a page are given a
+/// page of their own.
+const PAGE_SIZE: usize = 256;
+
+type PageId = usize;
+
+struct Page {
+    /// The region of the texture that is occupied by the page
+    allocation: Allocation,
+    allocator: SimpleAtlasAllocator,
+    /// The frame in which a sprite from this page was last used
+    last_used: u64,
+    /// Pinned pages are never evicted
+    pinned: bool,
+    /// The number of pixels reserved by sprites in this page
+    used_pixels: usize,
+}
+
+/// A summary of how much of an atlas is in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasOccupancy {
+    /// Dimensions of the texture
+    pub side: usize,
+    /// The number of pages that are currently allocated
+    pub pages: usize,
+    /// The number of regular sized pages that fit in the texture
+    pub page_capacity: usize,
+    /// The number of pixels reserved by sprites
+    pub used_pixels: usize,
+    /// The number of pages that have been evicted to make room
+    /// for other sprites
+    pub evicted_pages: usize,
+}
+
+impl std::fmt::Display for AtlasOccupancy {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{}x{}, {} of {} pages in use, {:.0}% occupied, {} pages evicted",
+            self.side,
+            self.side,
+            self.pages,
+            self.page_capacity,
+            100. * self.used_pixels as f64 / (self.side * self.side).max(1) as f64,
+            self.evicted_pages
+        )
+    }
+}
+
 /// Atlases are bitmaps of srgba data that are sized as a power of 2.
-/// We allocate sprites out of the available space, using AtlasAllocator
-/// to manage the available rectangles.
+/// The texture is divided into pages that are allocated on demand, and
+/// sprites are allocated out of the available space in those pages.
+/// When the texture is full, the least recently used pages can be
+/// evicted to make room, rather than discarding the whole atlas.
 pub struct Atlas {
     texture: Rc<dyn Texture2d>,
 
-    allocator: SimpleAtlasAllocator,
+    /// Manages the space in the texture that is available for pages
+    allocator: AtlasAllocator,
+
+    pages: HashMap<PageId, Page>,
+    next_page_id: PageId,
+    page_size: usize,
+
+    /// Incremented for each frame that is rendered; used to
+    /// track which pages have been used recently
+    frame: u64,
+    evicted_pages: usize,
 
     /// Dimensions of the texture
     side: usize,
@@ -40,12 +104,16 @@ impl Atlas {
         let rect = Rect::new(Point::new(0, 0), Size::new(iside, iside));
         texture.write(rect, &image);
 
-        let allocator =
-            SimpleAtlasAllocator::new(AtlasSize::new(side.try_into()?, side.try_into()?));
+        let allocator = AtlasAllocator::new(AtlasSize::new(side.try_into()?, side.try_into()?));
         Ok(Self {
             texture: Rc::clone(texture),
             side,
             allocator,
+            pages: HashMap::new(),
+            next_page_id: 0,
+            page_size: PAGE_SIZE.min(side),
+            frame: 0,
+            evicted_pages: 0,
         })
     }
 
@@ -92,12 +160,10 @@ impl Atlas {
         // interpolated on to the render surface.
         let reserve_width = reserve_width + padding.unwrap_or(0) as i32 + PADDING * 2;
         let reserve_height = reserve_height + padding.unwrap_or(0) as i32 + PADDING * 2;
+        let reserve = AtlasSize::new(reserve_width, reserve_height);
 
         let start = std::time::Instant::now();
-        let res = if let Some(allocation) = self
-            .allocator
-            .allocate(AtlasSize::new(reserve_width, reserve_height))
-        {
+        let res = if let Some((page_id, allocation)) = self.allocate_in_page(reserve) {
             let left = allocation.min.x;
             let top = allocation.min.y;
             let rect = Rect::new(
@@ -111,6 +177,7 @@ impl Atlas {
             Ok(Sprite {
                 texture: Rc::clone(&self.texture),
                 coords: rect,
+                page: page_id,
             })
         } else {
             // It's not possible to satisfy that request
@@ -126,10 +193,141 @@ impl Atlas {
         res
     }
 
+    /// Find space for `reserve` in one of the existing pages, or
+    /// in a new page if none of them have room for it.
+    /// Returns the page and the region of the texture that was reserved.
+    fn allocate_in_page(&mut self, reserve: AtlasSize) -> Option<(PageId, Rectangle)> {
+        let page_size = self.page_size as i32;
+        let dedicated = reserve.width > page_size || reserve.height > page_size;
+
+        if !dedicated {
+            for (&page_id, page) in self.pages.iter_mut() {
+                if let Some(rect) = page.allocator.allocate(reserve) {
+                    page.last_used = self.frame;
+                    page.used_pixels += reserve.area() as usize;
+                    return Some((
+                        page_id,
+                        rect.translate(page.allocation.rectangle.min.to_vector()),
+                    ));
+                }
+            }
+        }
+
+        let page_dims = if dedicated {
+            reserve
+        } else {
+            AtlasSize::new(page_size, page_size)
+        };
+        let allocation = self.allocator.allocate(page_dims)?;
+        let mut allocator = SimpleAtlasAllocator::new(page_dims);
+        let rect = allocator
+            .allocate(reserve)?
+            .translate(allocation.rectangle.min.to_vector());
+
+        let page_id = self.next_page_id;
+        self.next_page_id += 1;
+        self.pages.insert(
+            page_id,
+            Page {
+                allocation,
+                allocator,
+                last_used: self.frame,
+                pinned: false,
+                used_pixels: reserve.area() as usize,
+            },
+        );
+        Some((page_id, rect))
+    }
+
     pub fn size(&self) -> usize {
         self.side
     }
 
+    /// Called at the start of each frame, so that the sprites that are
+    /// used while rendering it can be distinguished from older sprites.
+    /// This is also when the occupancy of the atlas is reported.
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+
+        let occupancy = self.occupancy();
+        metrics::gauge!("window.atlas.side").set(occupancy.side as f64);
+        metrics::gauge!("window.atlas.pages").set(occupancy.pages as f64);
+        metrics::gauge!("window.atlas.occupied.percent").set(
+            100. * occupancy.used_pixels as f64 / (occupancy.side * occupancy.side).max(1) as f64,
+        );
+    }
+
+    /// Record that `sprite` is being used in the current frame
+    pub fn touch(&mut self, sprite: &Sprite) {
+        if let Some(page) = self.pages.get_mut(&sprite.page) {
+            page.last_used = self.frame;
+        }
+    }
+
+    /// Returns true if `sprite` is still present in the atlas;
+    /// sprites are invalidated when their page is evicted
+    pub fn contains(&self, sprite: &Sprite) -> bool {
+        self.pages.contains_key(&sprite.page)
+    }
+
+    /// Prevent the pages that are currently allocated from being
+    /// evicted.  This is used for sprites that are held outside of
+    /// the caches that are informed about evictions.
+    pub fn pin_allocated_pages(&mut self) {
+        for page in self.pages.values_mut() {
+            page.pinned = true;
+        }
+    }
+
+    /// Evict the least recently used pages that were not used in the
+    /// current frame, freeing up to half of those pages.
+    /// Returns the number of pages that were evicted.
+    /// The caller is responsible for forgetting the sprites from those
+    /// pages, which can be identified using `contains`, and for
+    /// re-rendering anything that was rendered using them.
+    pub fn evict_least_recently_used(&mut self) -> usize {
+        let mut candidates: Vec<(u64, PageId)> = self
+            .pages
+            .iter()
+            .filter(|(_, page)| !page.pinned && page.last_used < self.frame)
+            .map(|(&page_id, page)| (page.last_used, page_id))
+            .collect();
+        candidates.sort_unstable();
+        candidates.truncate((candidates.len() + 1) / 2);
+
+        for (_, page_id) in &candidates {
+            if let Some(page) = self.pages.remove(page_id) {
+                // Zero out the page so that the padding around the
+                // sprites that are allocated from it later is blank
+                let rect = page.allocation.rectangle;
+                let (width, height) = (rect.width() as usize, rect.height() as usize);
+                let image = crate::Image::new(width, height);
+                self.texture.write(
+                    Rect::new(
+                        Point::new(rect.min.x as isize, rect.min.y as isize),
+                        Size::new(width as isize, height as isize),
+                    ),
+                    &image,
+                );
+                self.allocator.deallocate(page.allocation.id);
+            }
+        }
+
+        self.evicted_pages += candidates.len();
+        metrics::histogram!("window.atlas.evicted.pages").record(candidates.len() as f64);
+        candidates.len()
+    }
+
+    pub fn occupancy(&self) -> AtlasOccupancy {
+        AtlasOccupancy {
+            side: self.side,
+            pages: self.pages.len(),
+            page_capacity: (self.side / self.page_size).pow(2),
+            used_pixels: self.pages.values().map(|page| page.used_pixels).sum(),
+            evicted_pages: self.evicted_pages,
+        }
+    }
+
     /// Zero out the texture, and forget all allocated regions
     pub fn clear(&mut self) {
         let iside = self.side as isize;
@@ -137,12 +335,14 @@ impl Atlas {
         let rect = Rect::new(Point::new(0, 0), Size::new(iside, iside));
         self.texture.write(rect, &image);
         self.allocator.clear();
+        self.pages.clear();
     }
 }
 
 pub struct Sprite {
     pub texture: Rc<dyn Texture2d>,
     pub coords: Rect,
+    page: PageId,
 }
 
 impl std::fmt::Debug for Sprite {
@@ -160,6 +360,7 @@ impl Clone for Sprite {
         Self {
             texture: Rc::clone(&self.texture),
             coords: self.coords,
+            page: self.page,
         }
     }
 }
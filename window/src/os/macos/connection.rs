@@ -5,7 +5,7 @@ use super::nsstring_to_str;
 use super::window::WindowInner;
 use crate::connection::ConnectionOps;
 use crate::os::macos::app::create_app_delegate;
-use crate::screen::{ScreenInfo, Screens};
+use crate::screen::{ScreenInfo, Screens, SubpixelOrder};
 use crate::spawn::*;
 use crate::Appearance;
 use cocoa::appkit::{NSApp, NSApplication, NSApplicationActivationPolicyRegular, NSScreen};
@@ -247,6 +247,8 @@ pub fn nsscreen_to_screen_info(screen: *mut Object) -> ScreenInfo {
         scale,
         max_fps,
         effective_dpi,
+        subpixel_order: SubpixelOrder::Unknown,
+        rotated: false,
     }
 }
 
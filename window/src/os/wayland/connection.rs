@@ -9,9 +9,10 @@ use mio::unix::SourceFd;
 use mio::{Events, Interest, Poll, Token};
 use wayland_client::backend::WaylandError;
 use wayland_client::globals::registry_queue_init;
+use wayland_client::protocol::wl_output::{Subpixel, Transform};
 use wayland_client::{Connection as WConnection, EventQueue};
 
-use crate::screen::{ScreenInfo, Screens};
+use crate::screen::{ScreenInfo, Screens, SubpixelOrder};
 use crate::spawn::SPAWN_QUEUE;
 use crate::{Appearance, Connection, ConnectionOps, ScreenRect};
 
@@ -192,7 +193,27 @@ impl ConnectionOps for WaylandConnection {
         log::trace!("Getting screens for wayland connection");
 
         if let Some(output_manager) = &self.wayland_state.borrow().output_manager {
-            if let Some(screens) = output_manager.screens() {
+            if let Some(mut screens) = output_manager.screens() {
+                let output_state = &self.wayland_state.borrow().output;
+                for output in output_state.outputs() {
+                    let info = match output_state.info(&output) {
+                        Some(info) => info,
+                        None => continue,
+                    };
+                    let name = match &info.name {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    let subpixel_order = subpixel_order(info.subpixel);
+                    let update = |screen: &mut ScreenInfo| {
+                        if screen.name == *name {
+                            screen.subpixel_order = subpixel_order;
+                        }
+                    };
+                    update(&mut screens.main);
+                    update(&mut screens.active);
+                    screens.by_name.values_mut().for_each(update);
+                }
                 return Ok(screens);
             }
         }
@@ -239,6 +260,8 @@ impl ConnectionOps for WaylandConnection {
                     scale,
                     max_fps: None,
                     effective_dpi,
+                    subpixel_order: subpixel_order(info.subpixel),
+                    rotated: info.transform != Transform::Normal,
                 },
             );
         }
@@ -271,3 +294,14 @@ impl ConnectionOps for WaylandConnection {
         })
     }
 }
+
+fn subpixel_order(subpixel: Subpixel) -> SubpixelOrder {
+    match subpixel {
+        Subpixel::HorizontalRgb => SubpixelOrder::HorizontalRgb,
+        Subpixel::HorizontalBgr => SubpixelOrder::HorizontalBgr,
+        Subpixel::VerticalRgb => SubpixelOrder::VerticalRgb,
+        Subpixel::VerticalBgr => SubpixelOrder::VerticalBgr,
+        Subpixel::None => SubpixelOrder::None,
+        _ => SubpixelOrder::Unknown,
+    }
+}
@@ -1,6 +1,6 @@
 //! Dealing with Wayland outputs
 
-use crate::screen::{ScreenInfo, Screens};
+use crate::screen::{ScreenInfo, Screens, SubpixelOrder};
 use crate::ScreenRect;
 use smithay_client_toolkit::globals::GlobalData;
 use smithay_client_toolkit::reexports::protocols_wlr::output_management::v1::client::zwlr_output_head_v1::{ZwlrOutputHeadV1, self, Event as ZwlrOutputHeadEvent};
@@ -107,6 +107,11 @@ impl OutputManagerState {
                     scale,
                     max_fps: None,
                     effective_dpi,
+                    // The output management protocol doesn't describe
+                    // the subpixels; that is filled in from wl_output
+                    // by the connection
+                    subpixel_order: SubpixelOrder::Unknown,
+                    rotated: head.transform.map_or(false, |t| t != Transform::Normal),
                 },
             );
         }
@@ -1,7 +1,7 @@
 //! The connection to the GUI subsystem
 use super::{HWindow, WindowInner};
 use crate::connection::ConnectionOps;
-use crate::screen::{ScreenInfo, Screens};
+use crate::screen::{ScreenInfo, Screens, SubpixelOrder};
 use crate::spawn::*;
 use crate::{Appearance, ScreenRect};
 use anyhow::Context;
@@ -249,6 +249,8 @@ impl ScreenInfoHelper {
                 scale: 1.0,
                 max_fps,
                 effective_dpi,
+                subpixel_order: SubpixelOrder::Unknown,
+                rotated: false,
             };
 
             info.virtual_rect = info.virtual_rect.union(&screen_info.rect);
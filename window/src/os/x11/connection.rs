@@ -3,7 +3,7 @@ use crate::connection::ConnectionOps;
 use crate::os::x11::window::XWindowInner;
 use crate::os::x11::xsettings::*;
 use crate::os::Connection;
-use crate::screen::{ScreenInfo, Screens};
+use crate::screen::{ScreenInfo, Screens, SubpixelOrder};
 use crate::spawn::*;
 use crate::{Appearance, DeadKeyStatus, ScreenRect};
 use anyhow::{anyhow, bail, Context as _};
@@ -298,12 +298,24 @@ impl ConnectionOps for XConnection {
                     effective_dpi.replace(dpi);
                 }
 
+                let subpixel_order = match info.subpixel_order() {
+                    xcb::render::SubPixel::HorizontalRgb => SubpixelOrder::HorizontalRgb,
+                    xcb::render::SubPixel::HorizontalBgr => SubpixelOrder::HorizontalBgr,
+                    xcb::render::SubPixel::VerticalRgb => SubpixelOrder::VerticalRgb,
+                    xcb::render::SubPixel::VerticalBgr => SubpixelOrder::VerticalBgr,
+                    xcb::render::SubPixel::None => SubpixelOrder::None,
+                    _ => SubpixelOrder::Unknown,
+                };
+                let rotated = cinfo.rotation() != xcb::randr::Rotation::ROTATE_0;
+
                 let info = ScreenInfo {
                     name: name.clone(),
                     rect: bounds,
                     scale: 1.0,
                     max_fps,
                     effective_dpi,
+                    subpixel_order,
+                    rotated,
                 };
                 by_name.insert(name, info);
             }
@@ -16,4 +16,23 @@ pub struct ScreenInfo {
     pub scale: f64,
     pub max_fps: Option<usize>,
     pub effective_dpi: Option<f64>,
+    /// The arrangement of the subpixels of the display,
+    /// as reported by the system
+    pub subpixel_order: SubpixelOrder,
+    /// Whether the display is rotated or reflected relative
+    /// to its natural orientation
+    pub rotated: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SubpixelOrder {
+    /// The system doesn't know or doesn't report the arrangement
+    #[default]
+    Unknown,
+    /// The display has no individually addressable subpixels
+    None,
+    HorizontalRgb,
+    HorizontalBgr,
+    VerticalRgb,
+    VerticalBgr,
 }